@@ -0,0 +1,95 @@
+//! Requests per second of the oha binary against an in-process loopback server, to catch
+//! regressions of the request loop. Run with `cargo bench`.
+
+use std::time::{Duration, Instant};
+
+use axum::{routing::get, Router};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+/// Requests of each run of oha
+const REQUESTS: u64 = 20_000;
+
+fn serve(runtime: &tokio::runtime::Runtime) -> u16 {
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let app = Router::new().route("/", get(|| async { "Hello World" }));
+        tokio::spawn(async { axum::serve(listener, app).await });
+        port
+    })
+}
+
+/// The time oha takes for `REQUESTS` requests with `args`
+fn run(port: u16, args: &[&str]) -> Duration {
+    let start = Instant::now();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_oha"))
+        .args(["--no-tui", "-c", "16", "-n", &REQUESTS.to_string()])
+        .args(args)
+        .arg(format!("http://127.0.0.1:{port}/"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    start.elapsed()
+}
+
+fn loopback(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let port = serve(&runtime);
+    let raw_request = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        raw_request.path(),
+        "GET / HTTP/1.1\r\nHost: {host}\r\nAccept: */*\r\n\r\n",
+    )
+    .unwrap();
+    let raw_request = raw_request.path().to_str().unwrap();
+
+    let mut group = c.benchmark_group("loopback");
+    group.throughput(Throughput::Elements(REQUESTS));
+    group.sample_size(10);
+    group.bench_function("http1", |b| {
+        b.iter_custom(|iters| (0..iters).map(|_| run(port, &[])).sum())
+    });
+    group.bench_function("prebuilt_requests", |b| {
+        b.iter_custom(|iters| {
+            (0..iters)
+                .map(|_| run(port, &["--prebuilt-requests"]))
+                .sum()
+        })
+    });
+    group.bench_function("raw_request", |b| {
+        b.iter_custom(|iters| {
+            (0..iters)
+                .map(|_| run(port, &["--raw-request", raw_request]))
+                .sum()
+        })
+    });
+    group.bench_function("http1_no_keepalive", |b| {
+        b.iter_custom(|iters| {
+            (0..iters)
+                .map(|_| run(port, &["--disable-keepalive"]))
+                .sum()
+        })
+    });
+    // cargo bench --features uring
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    {
+        group.bench_function("uring", |b| {
+            b.iter_custom(|iters| {
+                (0..iters)
+                    .map(|_| run(port, &["--io-backend", "uring"]))
+                    .sum()
+            })
+        });
+        group.bench_function("uring_no_keepalive", |b| {
+            b.iter_custom(|iters| {
+                (0..iters)
+                    .map(|_| run(port, &["--io-backend", "uring", "--disable-keepalive"]))
+                    .sum()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, loopback);
+criterion_main!(benches);
@@ -0,0 +1,272 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
+
+use crate::client::{ClientError, RequestResult};
+
+/// The exit code when the run is aborted by `--abort-on-error-rate` or `--abort-on-first-error`.
+/// `--max-bytes` ends the run successfully.
+pub const EXIT_CODE: i32 = 3;
+
+/// The error rate isn't checked until the window has this many results
+pub const MIN_SAMPLES: usize = 20;
+
+/// `--abort-on-error-rate`
+#[derive(Clone, Copy, Debug)]
+pub struct ErrorRate {
+    /// The threshold in percent
+    pub percent: f64,
+    pub window: Duration,
+}
+
+#[derive(Debug)]
+pub enum AbortReason {
+    ErrorRate(ErrorRate),
+    /// The message of the first failed request
+    FirstError(String),
+    /// `--max-bytes` was received
+    MaxBytes,
+}
+
+impl AbortReason {
+    /// Whether the run failed, rather than reached a limit
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, Self::MaxBytes)
+    }
+}
+
+/// Stops the run when requests fail by `--abort-on-error-rate` or `--abort-on-first-error`, or
+/// when `--max-bytes` is reached.
+/// The collector checks the results and the workers stop their requests on the signal.
+pub struct Abort {
+    error_rate: Option<ErrorRate>,
+    first_error: bool,
+    /// Whether the status codes are checked by `--expect-status`
+    expect_status: bool,
+    max_bytes: Option<u64>,
+    reason: OnceLock<AbortReason>,
+    signal: tokio::sync::watch::Sender<bool>,
+}
+
+impl Abort {
+    pub fn new(
+        error_rate: Option<ErrorRate>,
+        first_error: bool,
+        expect_status: bool,
+        max_bytes: Option<u64>,
+    ) -> Self {
+        Self {
+            error_rate,
+            first_error,
+            expect_status,
+            max_bytes,
+            reason: OnceLock::new(),
+            signal: tokio::sync::watch::channel(false).0,
+        }
+    }
+
+    /// Abort the run. Only the first reason is kept.
+    pub fn abort(&self, reason: AbortReason) {
+        let _ = self.reason.set(reason);
+        self.signal.send_replace(true);
+    }
+
+    /// Why the run was aborted. None unless it was.
+    pub fn reason(&self) -> Option<&AbortReason> {
+        self.reason.get()
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        *self.signal.borrow()
+    }
+
+    /// Resolves when the run is aborted
+    pub fn aborted(&self) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let mut signal = self.signal.subscribe();
+        async move {
+            let _ = signal.wait_for(|aborted| *aborted).await;
+        }
+    }
+
+    /// The error of the requests in flight when the run is aborted.
+    /// They are cut like by `-z` when `--max-bytes` is reached.
+    pub fn error(&self) -> ClientError {
+        match self.reason() {
+            Some(AbortReason::MaxBytes) => ClientError::Deadline,
+            _ => ClientError::Aborted,
+        }
+    }
+
+    /// A checker for the collector to feed the results to
+    pub fn checker(self: &Arc<Self>) -> AbortChecker {
+        AbortChecker {
+            abort: self.clone(),
+            results: VecDeque::new(),
+            failures: 0,
+            bytes: 0,
+        }
+    }
+}
+
+/// Checks the results in the collector, keeping the failure ratio in a sliding window of time
+pub struct AbortChecker {
+    abort: Arc<Abort>,
+    /// When each result was received and whether it failed
+    results: VecDeque<(Instant, bool)>,
+    failures: usize,
+    /// Body bytes received so far, for `--max-bytes`
+    bytes: u64,
+}
+
+impl AbortChecker {
+    /// Record a result and abort the run when it should stop.
+    /// Requests cut by `-z` or by the abort itself aren't counted.
+    pub fn record(&mut self, res: &Result<RequestResult, ClientError>) {
+        if self.abort.is_aborted() {
+            return;
+        }
+        if let (Some(max_bytes), Ok(r)) = (self.abort.max_bytes, res) {
+            self.bytes = self.bytes.saturating_add(r.len_bytes);
+            if self.bytes >= max_bytes {
+                self.abort.abort(AbortReason::MaxBytes);
+                return;
+            }
+        }
+        // Errors and unexpected status codes are failures of any kind
+        let error = match res {
+            Err(ClientError::Deadline | ClientError::Aborted) => return,
+            Err(e) => Some(e.to_string()),
+            Ok(r) => r.status_error(),
+        };
+        if self.abort.first_error {
+            if let Some(error) = error {
+                self.abort.abort(AbortReason::FirstError(error));
+                return;
+            }
+        }
+        if let Some(error_rate) = self.abort.error_rate {
+            // Without `--expect-status`, 4xx and 5xx are failures for the error rate too
+            let failed = error.is_some()
+                || !self.abort.expect_status
+                    && res
+                        .as_ref()
+                        .is_ok_and(|r| r.status.is_client_error() || r.status.is_server_error());
+            if self.push(error_rate, Instant::now(), failed) {
+                self.abort.abort(AbortReason::ErrorRate(error_rate));
+            }
+        }
+    }
+
+    /// Tell whether the error rate is over the threshold with the new result
+    fn push(&mut self, error_rate: ErrorRate, now: Instant, failed: bool) -> bool {
+        self.results.push_back((now, failed));
+        self.failures += failed as usize;
+        while let Some(&(at, failed)) = self.results.front() {
+            if now.duration_since(at) <= error_rate.window {
+                break;
+            }
+            self.results.pop_front();
+            self.failures -= failed as usize;
+        }
+        self.results.len() >= MIN_SAMPLES
+            && self.failures as f64 / self.results.len() as f64 > error_rate.percent / 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::label::Labels;
+    use hyper::http;
+
+    #[test]
+    fn test_push() {
+        let error_rate = ErrorRate {
+            percent: 50.0,
+            window: Duration::from_secs(1),
+        };
+        let mut checker = Arc::new(Abort::new(Some(error_rate), false, false, None)).checker();
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+
+        // Not checked until there are enough samples
+        for i in 0..MIN_SAMPLES as u64 - 1 {
+            assert!(!checker.push(error_rate, ms(i), true));
+        }
+        // Old failures leave the window
+        for i in 0..MIN_SAMPLES as u64 {
+            assert!(!checker.push(error_rate, ms(2000 + i), i % 2 == 0));
+        }
+        assert_eq!(checker.results.len(), MIN_SAMPLES);
+        assert!(checker.push(error_rate, ms(2100), true));
+    }
+
+    #[test]
+    fn test_first_error() {
+        let abort = Arc::new(Abort::new(None, true, false, None));
+        let mut checker = abort.checker();
+        checker.record(&Err(ClientError::Deadline));
+        assert!(!abort.is_aborted());
+        checker.record(&Err(ClientError::Timeout));
+        assert!(matches!(
+            abort.reason(),
+            Some(AbortReason::FirstError(error)) if error == "timeout"
+        ));
+        // The first reason is kept
+        checker.record(&Err(ClientError::ReadTimeout));
+        assert!(matches!(
+            abort.reason(),
+            Some(AbortReason::FirstError(error)) if error == "timeout"
+        ));
+    }
+
+    fn response(len_bytes: u64) -> Result<RequestResult, ClientError> {
+        let now = Instant::now();
+        Ok(RequestResult {
+            start_latency_correction: None,
+            send_delay: None,
+            start: now,
+            connection_time: None,
+            first_byte: now,
+            end: now,
+            status: http::StatusCode::OK,
+            method: Labels::default().method(&http::Method::GET),
+            step: None,
+            url: None,
+            len_bytes,
+            decoded_len_bytes: None,
+            expect_continue: None,
+            unexpected_status: false,
+            truncated: false,
+            http2_connection: None,
+            informational: None,
+        })
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let abort = Arc::new(Abort::new(None, false, false, Some(1000)));
+        let mut checker = abort.checker();
+        checker.record(&response(600));
+        checker.record(&Err(ClientError::Timeout));
+        assert!(!abort.is_aborted());
+        checker.record(&response(400));
+        assert!(matches!(abort.reason(), Some(AbortReason::MaxBytes)));
+        assert!(!abort.reason().unwrap().is_failure());
+        assert!(matches!(abort.error(), ClientError::Deadline));
+    }
+
+    #[tokio::test]
+    async fn test_abort() {
+        let abort = Abort::new(None, true, false, None);
+        let aborted = abort.aborted();
+        assert!(!abort.is_aborted());
+        abort.abort(AbortReason::FirstError("timeout".to_string()));
+        assert!(abort.is_aborted());
+        aborted.await;
+        // Resolves right away after the abort
+        abort.aborted().await;
+    }
+}
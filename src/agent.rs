@@ -0,0 +1,790 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use base64::Engine;
+use hmac::Mac;
+use rand::RngCore;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    aggregate::{Folded, ResultAggregate},
+    opts::Opts,
+    printer::PrintConfig,
+    result_channel::{Report, ReportSender},
+};
+
+/// The version of the wire format. The agent refuses a job of another version.
+pub const VERSION: u32 = 3;
+/// Frames longer than this are refused
+const MAX_FRAME: u32 = 64 * 1024 * 1024;
+/// How long the controller waits for an agent to accept the connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Bytes of the challenge which the controller signs with its job
+const NONCE_LEN: usize = 32;
+
+type HmacSha256 = hmac::Hmac<sha2::Sha256>;
+
+/// `oha agent`: run the jobs of a controller started with `--workers`
+#[derive(clap::Parser)]
+#[command(
+    name = "oha agent",
+    about = "Wait for jobs from `oha --workers` and stream the results back to it"
+)]
+pub struct AgentOpts {
+    #[clap(
+        help = "Address to listen on for the controller. Only local controllers can connect to the default, so listen on e.g. 0.0.0.0:7070 for the other hosts.",
+        long,
+        default_value = "127.0.0.1:7070"
+    )]
+    listen: std::net::SocketAddr,
+    #[clap(
+        help = "The secret shared with the controller, which must sign its job with it to have it run. OHA_AGENT_SECRET keeps it out of the process list.",
+        long,
+        env = "OHA_AGENT_SECRET",
+        hide_env_values = true
+    )]
+    secret: Option<String>,
+    /// Run the job on stdin, for the agent which checked it
+    #[clap(long = "run-job", hide = true)]
+    run_job: bool,
+}
+
+impl AgentOpts {
+    /// Whether this is the process of a run, whose job is on stdin
+    pub fn runs_job(&self) -> bool {
+        self.run_job
+    }
+}
+
+/// The frames between the controller and an agent: a big-endian u32 length and the message in JSON
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Message {
+    /// From the agent as the connection is accepted: random bytes in base64, which the
+    /// controller signs with its job
+    Challenge { version: u32, nonce: String },
+    /// From the controller: the options of the run in JSON, and their HMAC-SHA256 after the
+    /// nonce with the shared secret in base64
+    Job {
+        version: u32,
+        job: String,
+        proof: String,
+    },
+    /// From the agent: the results of a second of the run, folded like the summary
+    Second { second: u64, folded: Box<Folded> },
+    /// From the agent: the run has finished
+    Done,
+    /// From the agent: the run couldn't start or stopped early
+    Failed { error: String },
+}
+
+pub async fn write_message<W: AsyncWrite + Unpin>(
+    w: &mut W,
+    message: &Message,
+) -> std::io::Result<()> {
+    let json = serde_json::to_vec(message)?;
+    let len = u32::try_from(json.len())
+        .ok()
+        .filter(|&len| len <= MAX_FRAME)
+        .ok_or_else(|| std::io::Error::other("the message is too long"))?;
+    w.write_all(&len.to_be_bytes()).await?;
+    w.write_all(&json).await?;
+    w.flush().await
+}
+
+/// Read a message. None when the connection is closed between messages.
+pub async fn read_message<R: AsyncRead + Unpin>(r: &mut R) -> std::io::Result<Option<Message>> {
+    let mut len = [0; 4];
+    match r.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_be_bytes(len);
+    if len > MAX_FRAME {
+        return Err(std::io::Error::other(format!(
+            "a message of {len} bytes is too long"
+        )));
+    }
+    let mut json = vec![0; len as usize];
+    r.read_exact(&mut json).await?;
+    Ok(Some(serde_json::from_slice(&json)?))
+}
+
+/// The job of the controller for its agents, and the secret it's signed with
+pub struct Job {
+    json: String,
+    secret: String,
+}
+
+/// The options for the agents, with the files of `-D` and `--header-file` read here since an
+/// agent refuses the options which read or write files on its host
+pub fn job(opts: &Opts) -> anyhow::Result<Job> {
+    let secret = opts
+        .workers_secret
+        .clone()
+        .filter(|secret| !secret.is_empty())
+        .context(
+            "--workers needs the secret of the agents, with --workers-secret or OHA_AGENT_SECRET",
+        )?;
+    let mut job = opts.clone();
+    job.workers = None;
+    if let Some(path) = job.header_file.take() {
+        let content = std::fs::read_to_string(&path).map_err(|source| crate::Error::File {
+            option: "--header-file",
+            path: path.clone(),
+            source,
+        })?;
+        let mut headers = Vec::new();
+        for (name, value) in crate::header::parse_file(&content)
+            .map_err(|e| anyhow::anyhow!("--header-file {}: {e}", path.display()))?
+        {
+            let value = value
+                .to_str()
+                .with_context(|| format!("--header-file {}: {name}", path.display()))?;
+            headers.push(format!("{name}: {value}"));
+        }
+        // -H overrides them
+        headers.append(&mut job.headers);
+        job.headers = headers;
+    }
+    if let Some(path) = job.body_path.take() {
+        if job.body_string.is_none() {
+            job.body_bytes = Some(std::fs::read(&path).map_err(|source| crate::Error::File {
+                option: "-D",
+                path,
+                source,
+            })?);
+        }
+    }
+    if let Some(option) = check_job(&job) {
+        anyhow::bail!(
+            "{option} can't be used with --workers, since the agents don't read or write files"
+        );
+    }
+    if let Some(option) = needs_results(&job) {
+        anyhow::bail!(
+            "{option} can't be used with --workers, since its statistics need every result and the agents only send the statistics of each second"
+        );
+    }
+    let json = serde_json::to_string(&job)?;
+    let len = serde_json::to_vec(&Message::Job {
+        version: VERSION,
+        job: json.clone(),
+        proof: String::new(),
+    })?
+    .len();
+    if len + 64 > MAX_FRAME as usize {
+        anyhow::bail!(
+            "the job for the agents is {len} bytes, more than the {MAX_FRAME} bytes they accept"
+        );
+    }
+    Ok(Job { json, secret })
+}
+
+/// The option of a job which would read or write files on the agent, which aren't the ones of
+/// the controller
+pub fn check_job(opts: &Opts) -> Option<&'static str> {
+    let options = [
+        ("-D", opts.body_path.is_some()),
+        ("--header-file", opts.header_file.is_some()),
+        ("--body-dir", opts.body_dir.is_some()),
+        (
+            "--body-reload-interval",
+            opts.body_reload_interval.is_some(),
+        ),
+        (
+            "--multipart with a file",
+            opts.multipart
+                .iter()
+                .any(|part| matches!(part.value, crate::multipart::PartValue::File { .. })),
+        ),
+        ("--raw-request", opts.raw_request.is_some()),
+        ("--urls-from-file", opts.urls_from_file.is_some()),
+        ("--scenario", opts.scenario.is_some()),
+        ("--har", opts.har.is_some()),
+        ("--load-profile", opts.load_profile.is_some()),
+        ("--from-curl", opts.from_curl.is_some()),
+        ("--save-responses", opts.save_responses.is_some()),
+        ("--cacert", opts.cacert.is_some()),
+        ("--cert", opts.cert.is_some()),
+        ("--key", opts.key.is_some()),
+        #[cfg(unix)]
+        ("--unix-socket", opts.unix_socket.is_some()),
+        ("--workers", opts.workers.is_some()),
+    ];
+    options
+        .into_iter()
+        .find_map(|(option, used)| used.then_some(option))
+}
+
+/// The option of a job whose statistics are made from every result, as
+/// `PrintConfig::keeps_results` tells, which the controller doesn't receive
+fn needs_results(opts: &Opts) -> Option<&'static str> {
+    let options = [
+        ("--per-method-stats", opts.per_method_stats),
+        ("--per-url-stats", opts.per_url_stats),
+        ("--qps-ramp", opts.qps_ramp.is_some()),
+        ("--target-p99", opts.target_p99.is_some()),
+        ("--on", opts.on.is_some()),
+        ("--burst-delay", opts.burst_duration.is_some()),
+        ("--ramp-up", opts.ramp_up.is_some()),
+        ("--stagger-exclude", opts.stagger_exclude),
+    ];
+    options
+        .into_iter()
+        .find_map(|(option, used)| used.then_some(option))
+}
+
+/// The HMAC of a job with the shared secret, after the nonce of the agent
+fn sign(secret: &str, nonce: &[u8], job: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC takes a key of any size");
+    mac.update(nonce);
+    mac.update(job.as_bytes());
+    mac
+}
+
+/// Accept the jobs of controllers, one run per connection
+pub async fn serve(opts: AgentOpts) -> Result<(), crate::Error> {
+    let secret: Arc<str> = opts
+        .secret
+        .filter(|secret| !secret.is_empty())
+        .ok_or_else(|| {
+            crate::Error::Config(
+                "oha agent needs the secret shared with the controller, with --secret or OHA_AGENT_SECRET"
+                    .to_string(),
+            )
+        })?
+        .into();
+    let listener = tokio::net::TcpListener::bind(opts.listen)
+        .await
+        .map_err(|source| crate::Error::Listen {
+            address: opts.listen,
+            source,
+        })?;
+    eprintln!("Listening on {}", listener.local_addr()?);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let secret = secret.clone();
+        tokio::spawn(async move {
+            eprintln!("Job from {peer}");
+            match run_job(stream, &secret).await {
+                Ok(()) => eprintln!("Job from {peer} finished"),
+                Err(e) => eprintln!("Job from {peer} failed: {e:#}"),
+            }
+        });
+    }
+}
+
+/// Tell the controller why its job isn't run, and fail with the same error
+async fn refuse(stream: &mut tokio::net::TcpStream, error: String) -> anyhow::Result<()> {
+    write_message(
+        stream,
+        &Message::Failed {
+            error: error.clone(),
+        },
+    )
+    .await?;
+    anyhow::bail!(error)
+}
+
+/// Check that the job is signed with the secret, run it in a child process and pass its results
+/// to the controller
+async fn run_job(mut stream: tokio::net::TcpStream, secret: &str) -> anyhow::Result<()> {
+    let mut nonce = [0; NONCE_LEN];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+    let challenge = Message::Challenge {
+        version: VERSION,
+        nonce: base64::engine::general_purpose::STANDARD.encode(nonce),
+    };
+    write_message(&mut stream, &challenge).await?;
+    let (job, proof) = match read_message(&mut stream).await? {
+        Some(Message::Job {
+            version,
+            job,
+            proof,
+        }) if version == VERSION => (job, proof),
+        Some(Message::Job { version, .. }) => {
+            let error = format!("the agent speaks version {VERSION}, the controller {version}");
+            return refuse(&mut stream, error).await;
+        }
+        _ => anyhow::bail!("expected a job"),
+    };
+    let signed = base64::engine::general_purpose::STANDARD
+        .decode(proof)
+        .is_ok_and(|proof| sign(secret, &nonce, &job).verify_slice(&proof).is_ok());
+    if !signed {
+        let error = "the job isn't signed with the secret of the agent".to_string();
+        return refuse(&mut stream, error).await;
+    }
+    match serde_json::from_str::<Opts>(&job) {
+        Ok(opts) => {
+            if let Some(option) = check_job(&opts) {
+                let error = format!("the agent doesn't run a job with {option}");
+                return refuse(&mut stream, error).await;
+            }
+        }
+        Err(e) => return refuse(&mut stream, format!("invalid job: {e}")).await,
+    }
+
+    let mut child = match tokio::process::Command::new(std::env::current_exe()?)
+        .args(["agent", "--run-job"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            let error = format!("failed to start the run: {e}");
+            write_message(&mut stream, &Message::Failed { error }).await?;
+            return Err(e.into());
+        }
+    };
+    // A child which fails before it reads the job tells why on stderr
+    let mut stdin = child.stdin.take().context("no stdin")?;
+    let _ = stdin.write_all(job.as_bytes()).await;
+    drop(stdin);
+    let mut stdout = child.stdout.take().context("no stdout")?;
+    let mut stderr = child.stderr.take().context("no stderr")?;
+    let stderr = tokio::spawn(async move {
+        let mut s = String::new();
+        let _ = stderr.read_to_string(&mut s).await;
+        s
+    });
+
+    // The child writes frames of the same format. A disconnected controller kills the child.
+    tokio::io::copy(&mut stdout, &mut stream).await?;
+    let status = child.wait().await?;
+    let message = if status.success() {
+        Message::Done
+    } else {
+        Message::Failed {
+            error: child_error(&stderr.await?)
+                .unwrap_or_else(|| format!("the run exited with {status}")),
+        }
+    };
+    write_message(&mut stream, &message).await?;
+    Ok(())
+}
+
+/// The error printed by a failed run on one line with its causes, without the backtrace
+fn child_error(stderr: &str) -> Option<String> {
+    let error = stderr
+        .find("Error: ")
+        .map_or(stderr, |i| &stderr[i + "Error: ".len()..]);
+    let error = error.split("Stack backtrace:").next().unwrap_or(error);
+    let lines: Vec<&str> = error
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && *line != "Caused by:")
+        // The causes are numbered when there are several
+        .map(|line| match line.split_once(": ") {
+            Some((n, cause)) if n.parse::<usize>().is_ok() => cause,
+            _ => line,
+        })
+        .collect();
+    (!lines.is_empty()).then(|| lines.join(": "))
+}
+
+/// Fold the results of each second of the run and write them to stdout as `Second` messages,
+/// for the agent which started the run
+pub async fn stream_results(
+    result_rx: flume::Receiver<Report>,
+    config: PrintConfig,
+    start: Instant,
+) -> std::io::Result<()> {
+    let mut stdout = tokio::io::stdout();
+    let mut abort_checker = config.abort.as_ref().map(|abort| abort.checker());
+    let mut aggregate = ResultAggregate::new(&config, start);
+    let mut second = 0;
+    let mut tick = tokio::time::interval_at(
+        (start + Duration::from_secs(1)).into(),
+        Duration::from_secs(1),
+    );
+    loop {
+        tokio::select! {
+            report = result_rx.recv_async() => {
+                let Ok(report) = report else { break };
+                if let Report::Result(res) = report {
+                    if let Some(checker) = &mut abort_checker {
+                        checker.record(&res);
+                    }
+                    aggregate.push(res);
+                }
+            }
+            _ = tick.tick() => {
+                let folded = Box::new(aggregate.take());
+                write_message(&mut stdout, &Message::Second { second, folded }).await?;
+                second += 1;
+            }
+        }
+    }
+    if aggregate.count > 0 {
+        let folded = Box::new(aggregate.take());
+        write_message(&mut stdout, &Message::Second { second, folded }).await?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AgentStatus {
+    Connecting,
+    Running,
+    Done,
+    Failed(String),
+}
+
+impl std::fmt::Display for AgentStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Connecting => write!(f, "connecting"),
+            Self::Running => write!(f, "running"),
+            Self::Done => write!(f, "done"),
+            Self::Failed(error) => write!(f, "failed: {error}"),
+        }
+    }
+}
+
+/// An agent of `--workers` and how its run is going
+pub struct Agent {
+    pub address: Arc<str>,
+    status: Mutex<AgentStatus>,
+    /// The seconds received so far. None before the first one.
+    folded: Mutex<Option<Folded>>,
+}
+
+impl Agent {
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.into(),
+            status: Mutex::new(AgentStatus::Connecting),
+            folded: Mutex::new(None),
+        }
+    }
+
+    pub fn status(&self) -> AgentStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn set_status(&self, status: AgentStatus) {
+        *self.status.lock().unwrap() = status;
+    }
+
+    /// The results received from the agent so far. None before the first second.
+    pub fn folded(&self) -> Option<Folded> {
+        self.folded.lock().unwrap().clone()
+    }
+
+    fn merge(&self, folded: &Folded) {
+        match &mut *self.folded.lock().unwrap() {
+            Some(all) => all.merge(folded),
+            all => *all = Some(folded.clone()),
+        }
+    }
+
+    /// Responses received from the agent so far
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn responses(&self) -> usize {
+        self.folded
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(0, Folded::responses)
+    }
+}
+
+/// Send the job to each agent and pass their seconds to `result_tx` until all of them are done or
+/// failed. A failed agent doesn't stop the others.
+pub async fn control(agents: Arc<[Agent]>, job: Job, result_tx: ReportSender) {
+    let n = agents.len();
+    futures::future::join_all(agents.iter().enumerate().map(|(i, agent)| {
+        let result_tx = result_tx.clone();
+        let job = &job;
+        // Each agent numbers its HTTP/2 connections from 0
+        let connection = move |id: usize| id * n + i;
+        async move {
+            if let Err(e) = run_agent(agent, job, result_tx, connection).await {
+                agent.set_status(AgentStatus::Failed(format!("{e:#}")));
+            }
+        }
+    }))
+    .await;
+}
+
+async fn run_agent(
+    agent: &Agent,
+    job: &Job,
+    result_tx: ReportSender,
+    connection: impl Fn(usize) -> usize,
+) -> anyhow::Result<()> {
+    let mut stream = tokio::time::timeout(
+        CONNECT_TIMEOUT,
+        tokio::net::TcpStream::connect(&*agent.address),
+    )
+    .await
+    .context("timed out connecting")?
+    .context("failed to connect")?;
+    let nonce = match read_message(&mut stream).await? {
+        Some(Message::Challenge { version, nonce }) if version == VERSION => nonce,
+        Some(Message::Challenge { version, .. }) => {
+            anyhow::bail!("the agent speaks version {version}, the controller {VERSION}")
+        }
+        Some(Message::Failed { error }) => anyhow::bail!(error),
+        _ => anyhow::bail!("expected a challenge from the agent"),
+    };
+    let nonce = base64::engine::general_purpose::STANDARD
+        .decode(nonce)
+        .context("invalid challenge")?;
+    let proof = sign(&job.secret, &nonce, &job.json).finalize().into_bytes();
+    let job = Message::Job {
+        version: VERSION,
+        job: job.json.clone(),
+        proof: base64::engine::general_purpose::STANDARD.encode(proof),
+    };
+    write_message(&mut stream, &job).await?;
+    agent.set_status(AgentStatus::Running);
+    loop {
+        match read_message(&mut stream).await? {
+            Some(Message::Second { mut folded, .. }) => {
+                folded.http2_streams = std::mem::take(&mut folded.http2_streams)
+                    .into_iter()
+                    .map(|(id, n)| (connection(id), n))
+                    .collect();
+                agent.merge(&folded);
+                if result_tx.send_folded(*folded).await.is_err() {
+                    // The run is over on the controller
+                    return Ok(());
+                }
+            }
+            Some(Message::Done) => {
+                agent.set_status(AgentStatus::Done);
+                return Ok(());
+            }
+            Some(Message::Failed { error }) => anyhow::bail!(error),
+            Some(Message::Challenge { .. } | Message::Job { .. }) => {
+                anyhow::bail!("unexpected message from the agent")
+            }
+            None => anyhow::bail!("the agent closed the connection before the end of the run"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::http;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_messages() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+        let job = Message::Job {
+            version: VERSION,
+            job: "{}".to_string(),
+            proof: "cHJvb2Y=".to_string(),
+        };
+        write_message(&mut a, &job).await.unwrap();
+        write_message(&mut a, &Message::Done).await.unwrap();
+        drop(a);
+        match read_message(&mut b).await.unwrap() {
+            Some(Message::Job {
+                version,
+                job,
+                proof,
+            }) => {
+                assert_eq!(version, VERSION);
+                assert_eq!(job, "{}");
+                assert_eq!(proof, "cHJvb2Y=");
+            }
+            m => panic!("{m:?}"),
+        }
+        assert!(matches!(
+            read_message(&mut b).await.unwrap(),
+            Some(Message::Done)
+        ));
+        assert!(read_message(&mut b).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_frame_too_long() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+        a.write_all(&(MAX_FRAME + 1).to_be_bytes()).await.unwrap();
+        assert!(read_message(&mut b).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_second() {
+        use crate::client::{ClientError, LatencyIncludes, RequestResult};
+        use crate::label::Labels;
+
+        let start = Instant::now();
+        let response = |i: u64, status| RequestResult {
+            start_latency_correction: None,
+            send_delay: None,
+            start: start + Duration::from_millis(i),
+            connection_time: None,
+            first_byte: start + Duration::from_millis(i + 1),
+            end: start + Duration::from_millis(2 * i + 1),
+            status,
+            method: Labels::default().method(&http::Method::POST),
+            step: None,
+            url: None,
+            len_bytes: 42,
+            decoded_len_bytes: None,
+            expect_continue: None,
+            unexpected_status: false,
+            truncated: false,
+            http2_connection: Some(0),
+            informational: None,
+        };
+        let aggregate =
+            || ResultAggregate::with_options(start, LatencyIncludes::All, None, false, 3);
+        let mut agent = aggregate();
+        for i in 0..100_000 {
+            agent.push(Ok(response(i % 1000, http::StatusCode::OK)));
+        }
+        agent.push(Ok(response(5, http::StatusCode::NOT_FOUND)));
+        agent.push(Err(ClientError::Timeout));
+        agent.push(Err(ClientError::Deadline));
+
+        // The frame doesn't grow with the number of results
+        let second = Message::Second {
+            second: 0,
+            folded: Box::new(agent.take()),
+        };
+        assert!(serde_json::to_vec(&second).unwrap().len() < 64 * 1024);
+        let (mut a, mut b) = tokio::io::duplex(1 << 20);
+        write_message(&mut a, &second).await.unwrap();
+        let Some(Message::Second { folded, .. }) = read_message(&mut b).await.unwrap() else {
+            panic!("expected a second");
+        };
+        assert_eq!(agent.count, 0);
+
+        let mut controller = aggregate();
+        controller.push(Ok(response(0, http::StatusCode::OK)));
+        controller.merge(&folded);
+        assert_eq!(controller.count, 100_004);
+        assert_eq!(controller.counted, 100_003);
+        assert_eq!(controller.succeeded, 100_002);
+        assert_eq!(controller.responses(), 100_002);
+        assert_eq!(controller.status[&http::StatusCode::OK], (100_001, false));
+        assert_eq!(controller.status[&http::StatusCode::NOT_FOUND], (1, false));
+        assert_eq!(controller.errors["timeout"], 1);
+        assert_eq!(controller.timeouts, 1);
+        assert_eq!(controller.bytes, 42 * 100_002);
+        assert_eq!(controller.latency.min(), 0.001);
+        assert_eq!(controller.latency.max(), 1.0);
+        let median = controller.latency.percentile(50.0);
+        assert!((median - 0.5).abs() < 0.001, "{median}");
+        assert_eq!(
+            controller.rps(Duration::from_secs(3)),
+            vec![50_002.0, 50_000.0, 0.0]
+        );
+        assert_eq!(controller.http2_streams[&0], 100_002);
+    }
+
+    fn opts(args: &[&str]) -> Opts {
+        use clap::Parser;
+        Opts::parse_from(["oha"].iter().chain(args))
+    }
+
+    #[test]
+    fn test_job() {
+        let dir = tempfile::tempdir().unwrap();
+        let body = dir.path().join("body");
+        std::fs::write(&body, b"\x00\xffbody").unwrap();
+        let headers = dir.path().join("headers");
+        std::fs::write(&headers, "X-A: file\nX-B: file\n").unwrap();
+        let args = [
+            "-n",
+            "10",
+            "-m",
+            "PUT",
+            "--workers",
+            "a:1",
+            "--workers-secret",
+            "s3cret",
+            "-D",
+            body.to_str().unwrap(),
+            "--header-file",
+            headers.to_str().unwrap(),
+            "-H",
+            "X-B: flag",
+            "http://x/",
+        ];
+        let sent = job(&opts(&args)).unwrap();
+        assert_eq!(sent.secret, "s3cret");
+        let sent: Opts = serde_json::from_str(&sent.json).unwrap();
+        assert_eq!(check_job(&sent), None);
+        assert_eq!(sent.n_requests, 10);
+        assert_eq!(sent.method, Some(http::Method::PUT));
+        assert_eq!(sent.url.as_deref(), Some("http://x/"));
+        assert_eq!(sent.body_bytes.as_deref(), Some(&b"\x00\xffbody"[..]));
+        assert_eq!(sent.headers, ["x-a: file", "x-b: file", "X-B: flag"]);
+        assert_eq!(sent.workers_secret, None);
+
+        let err = job(&opts(&["--workers", "a:1", "http://x/"]))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("--workers-secret"), "{err}");
+        let err = job(&opts(&[
+            "--workers",
+            "a:1",
+            "--workers-secret",
+            "s",
+            "--per-url-stats",
+            "http://x/",
+        ]))
+        .err()
+        .unwrap();
+        assert!(
+            err.to_string()
+                .starts_with("--per-url-stats can't be used with --workers"),
+            "{err}"
+        );
+        for option in ["--body-dir", "--raw-request", "--cacert"] {
+            let args = [
+                "--workers",
+                "a:1",
+                "--workers-secret",
+                "s",
+                option,
+                "f",
+                "http://x/",
+            ];
+            let err = job(&opts(&args)).err().unwrap();
+            assert!(err.to_string().starts_with(option), "{err}");
+        }
+    }
+
+    #[test]
+    fn test_sign() {
+        let nonce = [7; NONCE_LEN];
+        let proof = sign("secret", &nonce, "{}").finalize().into_bytes();
+        assert!(sign("secret", &nonce, "{}").verify_slice(&proof).is_ok());
+        assert!(sign("other", &nonce, "{}").verify_slice(&proof).is_err());
+        assert!(sign("secret", &[8; NONCE_LEN], "{}")
+            .verify_slice(&proof)
+            .is_err());
+        assert!(sign("secret", &nonce, "{ }").verify_slice(&proof).is_err());
+    }
+
+    #[test]
+    fn test_child_error() {
+        assert_eq!(
+            child_error("Starting\nError: failed to resolve\n\nStack backtrace:\n  0: x"),
+            Some("failed to resolve".to_string())
+        );
+        assert_eq!(
+            child_error("Error: a\n\nCaused by:\n    0: b\n    1: c\n"),
+            Some("a: b: c".to_string())
+        );
+        assert_eq!(child_error(""), None);
+    }
+}
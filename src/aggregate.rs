@@ -0,0 +1,830 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use hyper::http::StatusCode;
+
+use crate::{
+    client::{ClientError, LatencyIncludes, RequestResult},
+    histogram::LatencyHistogram,
+    printer::PrintConfig,
+    websocket::WebSocketError,
+};
+
+/// Distinct error messages kept in the distribution. The next ones are counted under
+/// `OTHER_ERRORS`, so that errors which carry a changing detail don't grow it without bound.
+pub const MAX_ERROR_KINDS: usize = 1000;
+pub const OTHER_ERRORS: &str = "other errors";
+
+/// A request is late when it waits for a worker longer than this and than the interval of the
+/// rate. Shorter delays come from the resolution of the timer.
+pub const LATE_SEND_DELAY: Duration = Duration::from_millis(2);
+
+/// The mean of values like `average::Mean`, which an agent of `--workers` can send
+#[derive(Clone, Copy, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Average {
+    mean: f64,
+    len: u64,
+}
+
+impl Average {
+    pub(crate) fn add(&mut self, v: f64) {
+        self.len += 1;
+        self.mean += (v - self.mean) / self.len as f64;
+    }
+
+    pub(crate) fn merge(&mut self, other: &Self) {
+        let len = self.len + other.len;
+        if len > 0 {
+            self.mean += (other.mean - self.mean) * other.len as f64 / len as f64;
+            self.len = len;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 0 when there's none
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+}
+
+/// The fastest, slowest and average of durations in seconds
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Extent {
+    mean: Average,
+    /// The fastest and the slowest. None when there's none.
+    range: Option<(f64, f64)>,
+    /// The values, each counted as many times as it was added for
+    count: usize,
+}
+
+impl Extent {
+    /// Add `v` for `n` values, which only weighs in the count since they are all the same
+    pub(crate) fn add(&mut self, v: f64, n: usize) {
+        self.mean.add(v);
+        self.range = Some(match self.range {
+            Some((min, max)) => (min.min(v), max.max(v)),
+            None => (v, v),
+        });
+        self.count += n;
+    }
+
+    pub(crate) fn merge(&mut self, other: &Self) {
+        self.mean.merge(&other.mean);
+        self.range = match (self.range, other.range) {
+            (Some((min, max)), Some((other_min, other_max))) => {
+                Some((min.min(other_min), max.max(other_max)))
+            }
+            (range, None) | (None, range) => range,
+        };
+        self.count += other.count;
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mean.is_empty()
+    }
+
+    /// 0 when there's none
+    pub fn average(&self) -> f64 {
+        self.mean.mean()
+    }
+
+    pub fn fastest(&self) -> f64 {
+        self.range.map_or(f64::INFINITY, |(min, _)| min)
+    }
+
+    pub fn slowest(&self) -> f64 {
+        self.range.map_or(f64::NEG_INFINITY, |(_, max)| max)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ExpectContinueCounts {
+    pub received: usize,
+    #[serde(rename = "timedOut")]
+    pub timed_out: usize,
+    #[serde(rename = "bodyNotSent")]
+    pub body_not_sent: usize,
+}
+
+/// The statistics of the summary, folded in as the results arrive so that a run of any length
+/// takes about the same memory. The results themselves are kept only when a statistic needs all
+/// of them, as `PrintConfig::keeps_results` tells.
+pub struct ResultAggregate {
+    start: Instant,
+    includes: LatencyIncludes,
+    /// A late send delay in seconds, under `-q` or `--qps-ramp`
+    late_send_delay: Option<f64>,
+    results: Option<Vec<Result<RequestResult, ClientError>>>,
+    /// The results each pushed one stands for, which is N of `--sample-rate 1/N`
+    weight: usize,
+    folded: Folded,
+}
+
+/// The statistics which `ResultAggregate` folds from the results. An agent of `--workers` sends
+/// the ones of each second of its run, which the controller merges.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Folded {
+    /// Every result, failed or not
+    pub count: usize,
+    /// The results counted in the success rate, which excludes the requests cut by `-z` or by
+    /// an abort
+    pub counted: usize,
+    /// The counted responses with an expected status
+    pub succeeded: usize,
+    /// The latency of the responses as `--latency-includes` defines it
+    pub latency: LatencyHistogram,
+    latency_mean: Average,
+    /// 2xx only
+    pub latency_successful: LatencyHistogram,
+    /// 4xx and 5xx only
+    pub latency_not_successful: LatencyHistogram,
+    /// The statuses not allowed by `--expect-status`
+    pub latency_unexpected_status: LatencyHistogram,
+    /// The latency by each definition of `--latency-includes`
+    latency_by_includes: [Extent; 3],
+    pub first_byte: Extent,
+    pub dns_dialup: Extent,
+    pub dns_lookup: Extent,
+    pub tls_handshake: Extent,
+    pub tls_full: Extent,
+    pub tls_resumed: Extent,
+    /// Whether the TLS backend tells the resumed handshakes from the full ones
+    pub tls_known: bool,
+    /// Responses on a new connection
+    pub new_connections: usize,
+    /// Responses on a kept-alive connection
+    pub reused_connections: usize,
+    /// Body bytes as received
+    #[serde(with = "bytes")]
+    pub bytes: u128,
+    /// Decoded body bytes of the responses whose decoded size is known
+    #[serde(with = "bytes")]
+    pub decoded_bytes: u128,
+    pub decoded: usize,
+    pub assertion_failures: usize,
+    pub truncated: usize,
+    /// Requests which didn't finish within `-t`
+    pub timeouts: usize,
+    /// Responses by status, and whether the status is unexpected
+    #[serde(with = "statuses")]
+    pub status: BTreeMap<StatusCode, (usize, bool)>,
+    /// Errors and unexpected statuses by their messages
+    pub errors: BTreeMap<String, usize>,
+    /// Responses by the second of the run they ended in
+    #[serde(with = "seconds")]
+    ended_per_second: Vec<usize>,
+    /// Responses by the second of the run their request started in
+    #[serde(with = "seconds")]
+    started_per_second: Vec<usize>,
+    pub send_delay: LatencyHistogram,
+    send_delay_mean: Average,
+    /// Send delays over the late threshold
+    pub late_sends: usize,
+    /// None unless `Expect: 100-continue` is sent
+    pub expect_continue: Option<ExpectContinueCounts>,
+    /// None unless `--informational-stats` is given
+    pub early_hints: Option<usize>,
+    /// Time until the first 1xx response
+    pub first_informational: Extent,
+    /// Responses on each HTTP/2 connection
+    #[serde(with = "pairs")]
+    pub http2_streams: BTreeMap<usize, usize>,
+    /// Failed requests by the HTTP/2 frame and the error code from the server
+    pub http2_errors: BTreeMap<String, usize>,
+}
+
+impl Folded {
+    /// `precision` is the one of the latency histograms
+    pub fn new(precision: u8) -> Self {
+        Self {
+            count: 0,
+            counted: 0,
+            succeeded: 0,
+            latency: LatencyHistogram::new(precision),
+            latency_mean: Default::default(),
+            latency_successful: LatencyHistogram::new(precision),
+            latency_not_successful: LatencyHistogram::new(precision),
+            latency_unexpected_status: LatencyHistogram::new(precision),
+            latency_by_includes: Default::default(),
+            first_byte: Default::default(),
+            dns_dialup: Default::default(),
+            dns_lookup: Default::default(),
+            tls_handshake: Default::default(),
+            tls_full: Default::default(),
+            tls_resumed: Default::default(),
+            tls_known: false,
+            new_connections: 0,
+            reused_connections: 0,
+            bytes: 0,
+            decoded_bytes: 0,
+            decoded: 0,
+            assertion_failures: 0,
+            timeouts: 0,
+            truncated: 0,
+            status: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            ended_per_second: Vec::new(),
+            started_per_second: Vec::new(),
+            send_delay: LatencyHistogram::new(precision),
+            send_delay_mean: Default::default(),
+            late_sends: 0,
+            expect_continue: None,
+            early_hints: None,
+            first_informational: Default::default(),
+            http2_streams: BTreeMap::new(),
+            http2_errors: BTreeMap::new(),
+        }
+    }
+
+    /// Add the statistics of other results
+    pub fn merge(&mut self, other: &Self) {
+        self.count += other.count;
+        self.counted += other.counted;
+        self.succeeded += other.succeeded;
+        self.latency.merge(&other.latency);
+        self.latency_mean.merge(&other.latency_mean);
+        self.latency_successful.merge(&other.latency_successful);
+        self.latency_not_successful
+            .merge(&other.latency_not_successful);
+        self.latency_unexpected_status
+            .merge(&other.latency_unexpected_status);
+        for (extent, other) in self
+            .latency_by_includes
+            .iter_mut()
+            .zip(&other.latency_by_includes)
+        {
+            extent.merge(other);
+        }
+        self.first_byte.merge(&other.first_byte);
+        self.dns_dialup.merge(&other.dns_dialup);
+        self.dns_lookup.merge(&other.dns_lookup);
+        self.tls_handshake.merge(&other.tls_handshake);
+        self.tls_full.merge(&other.tls_full);
+        self.tls_resumed.merge(&other.tls_resumed);
+        self.tls_known |= other.tls_known;
+        self.new_connections += other.new_connections;
+        self.reused_connections += other.reused_connections;
+        self.bytes += other.bytes;
+        self.decoded_bytes += other.decoded_bytes;
+        self.decoded += other.decoded;
+        self.assertion_failures += other.assertion_failures;
+        self.truncated += other.truncated;
+        self.timeouts += other.timeouts;
+        for (&status, &(count, unexpected)) in &other.status {
+            let entry = self.status.entry(status).or_default();
+            entry.0 += count;
+            entry.1 |= unexpected;
+        }
+        for (error, &count) in &other.errors {
+            self.push_error(error.clone(), count);
+        }
+        merge_seconds(&mut self.ended_per_second, &other.ended_per_second);
+        merge_seconds(&mut self.started_per_second, &other.started_per_second);
+        self.send_delay.merge(&other.send_delay);
+        self.send_delay_mean.merge(&other.send_delay_mean);
+        self.late_sends += other.late_sends;
+        if let Some(other) = other.expect_continue {
+            let counts = self.expect_continue.get_or_insert_with(Default::default);
+            counts.received += other.received;
+            counts.timed_out += other.timed_out;
+            counts.body_not_sent += other.body_not_sent;
+        }
+        if let Some(early_hints) = other.early_hints {
+            *self.early_hints.get_or_insert(0) += early_hints;
+        }
+        self.first_informational.merge(&other.first_informational);
+        for (&id, &count) in &other.http2_streams {
+            *self.http2_streams.entry(id).or_default() += count;
+        }
+        for (error, &count) in &other.http2_errors {
+            *self.http2_errors.entry(error.clone()).or_default() += count;
+        }
+    }
+
+    /// Count `n` times `error`
+    fn push_error(&mut self, error: String, n: usize) {
+        if let Some(count) = self.errors.get_mut(&error) {
+            *count += n;
+        } else if self.errors.len() < MAX_ERROR_KINDS {
+            self.errors.insert(error, n);
+        } else {
+            *self.errors.entry(OTHER_ERRORS.to_string()).or_default() += n;
+        }
+    }
+
+    /// Successful responses
+    pub fn responses(&self) -> usize {
+        self.latency.len() as usize
+    }
+
+    /// The average latency. NaN when there's no response.
+    pub fn latency_average(&self) -> f64 {
+        if self.latency_mean.is_empty() {
+            f64::NAN
+        } else {
+            self.latency_mean.mean()
+        }
+    }
+
+    /// The latency by the definition of `includes`
+    pub fn latency_by(&self, includes: LatencyIncludes) -> &Extent {
+        &self.latency_by_includes[match includes {
+            LatencyIncludes::All => 0,
+            LatencyIncludes::NoDns => 1,
+            LatencyIncludes::ResponseOnly => 2,
+        }]
+    }
+
+    /// The average send delay, when any is known
+    pub fn send_delay_average(&self) -> Option<f64> {
+        (!self.send_delay_mean.is_empty()).then(|| self.send_delay_mean.mean())
+    }
+
+    /// Responses by the second of the run they ended in
+    pub fn ended_per_second(&self) -> &[usize] {
+        &self.ended_per_second
+    }
+}
+
+impl std::ops::Deref for ResultAggregate {
+    type Target = Folded;
+
+    fn deref(&self) -> &Folded {
+        &self.folded
+    }
+}
+
+impl std::ops::DerefMut for ResultAggregate {
+    fn deref_mut(&mut self) -> &mut Folded {
+        &mut self.folded
+    }
+}
+
+impl ResultAggregate {
+    pub(crate) fn new(config: &PrintConfig, start: Instant) -> Self {
+        Self {
+            weight: config
+                .sampling
+                .as_ref()
+                .map_or(1, |sampling| sampling.every() as usize),
+            ..Self::with_options(
+                start,
+                config.latency_includes,
+                config.qps.or(config.qps_ramp.map(|ramp| ramp.max_rate())),
+                config.keeps_results(),
+                config.histogram_precision,
+            )
+        }
+    }
+
+    /// `rate` is the one of `-q` or `--qps-ramp`, to tell the late send delays. `precision` is
+    /// the one of the latency histograms.
+    pub fn with_options(
+        start: Instant,
+        includes: LatencyIncludes,
+        rate: Option<f64>,
+        keep_results: bool,
+        precision: u8,
+    ) -> Self {
+        Self {
+            start,
+            includes,
+            late_send_delay: rate.map(|rate| LATE_SEND_DELAY.as_secs_f64().max(1.0 / rate)),
+            results: keep_results.then(Vec::new),
+            weight: 1,
+            folded: Folded::new(precision),
+        }
+    }
+
+    /// Fold all of `results`
+    pub(crate) fn from_results(
+        config: &PrintConfig,
+        start: Instant,
+        results: Vec<Result<RequestResult, ClientError>>,
+    ) -> Self {
+        let mut aggregate = Self::new(config, start);
+        for res in results {
+            aggregate.push(res);
+        }
+        aggregate
+    }
+
+    pub fn push(&mut self, res: Result<RequestResult, ClientError>) {
+        let weight = self.weight;
+        let folded = &mut self.folded;
+        folded.count += weight;
+        if !matches!(res, Err(ClientError::Deadline | ClientError::Aborted)) {
+            folded.counted += weight;
+            if matches!(&res, Ok(r) if !r.unexpected_status) {
+                folded.succeeded += weight;
+            }
+        }
+        match &res {
+            Ok(r) => self.push_response(r),
+            Err(e) => {
+                match e {
+                    ClientError::AssertionFailed(_) => folded.assertion_failures += weight,
+                    ClientError::Timeout | ClientError::WebSocket(WebSocketError::Timeout) => {
+                        folded.timeouts += weight
+                    }
+                    ClientError::Http2StreamReset(reason) => {
+                        *folded
+                            .http2_errors
+                            .entry(format!("RST_STREAM {reason:?}"))
+                            .or_default() += weight
+                    }
+                    ClientError::Http2GoAway(reason) => {
+                        *folded
+                            .http2_errors
+                            .entry(format!("GOAWAY {reason:?}"))
+                            .or_default() += weight
+                    }
+                    _ => {}
+                }
+                folded.push_error(e.to_string(), weight);
+            }
+        }
+        if let Some(results) = &mut self.results {
+            results.push(res);
+        }
+    }
+
+    fn push_response(&mut self, r: &RequestResult) {
+        let weight = self.weight;
+        let folded = &mut self.folded;
+        let latency = r.duration(self.includes).as_secs_f64();
+        folded.latency.push_n(latency, weight as u64);
+        folded.latency_mean.add(latency);
+        if r.status.is_success() {
+            folded.latency_successful.push_n(latency, weight as u64);
+        }
+        if r.status.is_client_error() || r.status.is_server_error() {
+            folded.latency_not_successful.push_n(latency, weight as u64);
+        }
+        if r.unexpected_status {
+            folded
+                .latency_unexpected_status
+                .push_n(latency, weight as u64);
+        }
+        for (extent, includes) in folded.latency_by_includes.iter_mut().zip([
+            LatencyIncludes::All,
+            LatencyIncludes::NoDns,
+            LatencyIncludes::ResponseOnly,
+        ]) {
+            extent.add(r.duration(includes).as_secs_f64(), weight);
+        }
+        let started = r.start_latency_correction.unwrap_or(r.start);
+        folded
+            .first_byte
+            .add((r.first_byte - started).as_secs_f64(), weight);
+
+        match r.connection_time {
+            Some(c) => {
+                folded.new_connections += weight;
+                folded
+                    .dns_dialup
+                    .add((c.dialup - r.start).as_secs_f64(), weight);
+                folded
+                    .dns_lookup
+                    .add((c.dns_lookup - r.start).as_secs_f64(), weight);
+                if let Some(tls) = c.tls_handshake {
+                    let duration = tls.duration.as_secs_f64();
+                    folded.tls_handshake.add(duration, weight);
+                    match tls.resumed {
+                        Some(true) => folded.tls_resumed.add(duration, weight),
+                        Some(false) => folded.tls_full.add(duration, weight),
+                        None => {}
+                    }
+                    folded.tls_known |= tls.resumed.is_some();
+                }
+            }
+            None => folded.reused_connections += weight,
+        }
+
+        folded.bytes += u128::from(r.len_bytes) * weight as u128;
+        if let Some(decoded) = r.decoded_len_bytes {
+            folded.decoded_bytes += u128::from(decoded) * weight as u128;
+            folded.decoded += weight;
+        }
+        folded.truncated += r.truncated as usize * weight;
+        let entry = folded.status.entry(r.status).or_default();
+        entry.0 += weight;
+        entry.1 |= r.unexpected_status;
+        if let Some(error) = r.status_error() {
+            folded.push_error(error, weight);
+        }
+
+        count_second(&mut folded.ended_per_second, self.start, r.end, weight);
+        count_second(&mut folded.started_per_second, self.start, started, weight);
+
+        if let (Some(late), Some(delay)) = (self.late_send_delay, r.send_delay) {
+            let delay = delay.as_secs_f64();
+            folded.send_delay.push_n(delay, weight as u64);
+            folded.send_delay_mean.add(delay);
+            folded.late_sends += (delay > late) as usize * weight;
+        }
+        if let Some(e) = r.expect_continue {
+            let counts = folded.expect_continue.get_or_insert_with(Default::default);
+            if e.received {
+                counts.received += weight;
+            } else if e.body_sent {
+                counts.timed_out += weight;
+            } else {
+                counts.body_not_sent += weight;
+            }
+        }
+        if let Some(informational) = r.informational {
+            *folded.early_hints.get_or_insert(0) += informational.early_hints * weight;
+            if let Some(first) = informational.first {
+                folded
+                    .first_informational
+                    .add((first - started).as_secs_f64(), weight);
+            }
+        }
+        if let Some(id) = r.http2_connection {
+            *folded.http2_streams.entry(id).or_default() += weight;
+        }
+    }
+
+    /// Add the statistics folded by an agent of `--workers`
+    pub fn merge(&mut self, folded: &Folded) {
+        self.folded.merge(folded);
+    }
+
+    /// The statistics folded since the last time, which start over
+    pub fn take(&mut self) -> Folded {
+        let precision = self.folded.latency.precision();
+        std::mem::replace(&mut self.folded, Folded::new(precision))
+    }
+
+    /// Every result, when they are kept. Empty otherwise.
+    pub fn results(&self) -> &[Result<RequestResult, ClientError>] {
+        self.results.as_deref().unwrap_or_default()
+    }
+
+    /// The results, when they are kept
+    pub fn into_results(self) -> Option<Vec<Result<RequestResult, ClientError>>> {
+        self.results
+    }
+
+    /// Responses per second in each full second of a run of `duration`, or over the whole run
+    /// when it's shorter than a second. Empty without any response.
+    pub fn rps(&self, duration: Duration) -> Vec<f64> {
+        let seconds = duration.as_secs() as usize;
+        if self.ended_per_second.is_empty() {
+            return Vec::new();
+        }
+        if seconds == 0 {
+            return vec![self.responses() as f64 / duration.as_secs_f64()];
+        }
+        (0..seconds)
+            .map(|s| self.ended_per_second.get(s).copied().unwrap_or(0) as f64)
+            .collect()
+    }
+
+    /// Requests started in each full second of a run of `duration`
+    pub fn started_per_second(&self, duration: Duration) -> Vec<usize> {
+        (0..duration.as_secs() as usize)
+            .map(|s| self.started_per_second.get(s).copied().unwrap_or(0))
+            .collect()
+    }
+}
+
+fn count_second(counts: &mut Vec<usize>, start: Instant, at: Instant, n: usize) {
+    count_at(
+        counts,
+        at.saturating_duration_since(start).as_secs() as usize,
+        n,
+    );
+}
+
+fn count_at(counts: &mut Vec<usize>, second: usize, n: usize) {
+    if second >= counts.len() {
+        counts.resize(second + 1, 0);
+    }
+    counts[second] += n;
+}
+
+fn merge_seconds(counts: &mut Vec<usize>, other: &[usize]) {
+    if other.len() > counts.len() {
+        counts.resize(other.len(), 0);
+    }
+    for (count, other) in counts.iter_mut().zip(other) {
+        *count += other;
+    }
+}
+
+/// The counts by second as the seconds which have any, since an agent sends them for each second
+mod seconds {
+    use serde::{Deserialize, Serialize};
+
+    pub fn serialize<S: serde::Serializer>(
+        counts: &[usize],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let seconds: Vec<(usize, usize)> = counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &n)| n > 0)
+            .map(|(second, &n)| (second, n))
+            .collect();
+        seconds.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<usize>, D::Error> {
+        let mut counts = Vec::new();
+        for (second, n) in Vec::<(usize, usize)>::deserialize(deserializer)? {
+            super::count_at(&mut counts, second, n);
+        }
+        Ok(counts)
+    }
+}
+
+/// Bytes within a u64, which serde can't buffer in a u128 for the tagged messages of the agents
+mod bytes {
+    use serde::{Deserialize, Serialize};
+
+    pub fn serialize<S: serde::Serializer>(bytes: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        u64::try_from(*bytes)
+            .unwrap_or(u64::MAX)
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<u128, D::Error> {
+        u64::deserialize(deserializer).map(u128::from)
+    }
+}
+
+/// A map as its pairs, since serde buffers the keys of a map as strings in the tagged messages of
+/// the agents
+mod pairs {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    pub fn serialize<K: Serialize, V: Serialize, S: serde::Serializer>(
+        map: &BTreeMap<K, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map)
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<BTreeMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Ord,
+        V: Deserialize<'de>,
+        D: serde::Deserializer<'de>,
+    {
+        Ok(Vec::<(K, V)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+/// The statuses by their codes
+mod statuses {
+    use std::collections::BTreeMap;
+
+    use hyper::http::StatusCode;
+    use serde::de::Error;
+
+    pub fn serialize<S: serde::Serializer>(
+        status: &BTreeMap<StatusCode, (usize, bool)>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            status
+                .iter()
+                .map(|(status, counts)| (status.as_u16(), counts)),
+        )
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<StatusCode, (usize, bool)>, D::Error> {
+        super::pairs::deserialize::<u16, (usize, bool), D>(deserializer)?
+            .into_iter()
+            .map(|(status, counts)| {
+                Ok((
+                    StatusCode::from_u16(status).map_err(D::Error::custom)?,
+                    counts,
+                ))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{histogram::DEFAULT_PRECISION, label::Labels};
+
+    fn response(start: Instant, end: Duration, status: StatusCode) -> RequestResult {
+        RequestResult {
+            start_latency_correction: None,
+            send_delay: None,
+            start,
+            connection_time: None,
+            first_byte: start,
+            end: start + end,
+            status,
+            method: Labels::default().method(&hyper::http::Method::GET),
+            step: None,
+            url: None,
+            len_bytes: 10,
+            decoded_len_bytes: None,
+            expect_continue: None,
+            unexpected_status: false,
+            truncated: false,
+            http2_connection: None,
+            informational: None,
+        }
+    }
+
+    #[test]
+    fn test_error_kinds_are_bounded() {
+        let start = Instant::now();
+        let mut aggregate = ResultAggregate::with_options(
+            start,
+            LatencyIncludes::All,
+            None,
+            false,
+            DEFAULT_PRECISION,
+        );
+        for i in 0..MAX_ERROR_KINDS + 10 {
+            aggregate.push(Err(std::io::Error::other(i.to_string()).into()));
+            aggregate.push(Err(std::io::Error::other("again").into()));
+        }
+        assert_eq!(aggregate.count, 2 * (MAX_ERROR_KINDS + 10));
+        assert_eq!(aggregate.errors.len(), MAX_ERROR_KINDS + 1);
+        assert_eq!(aggregate.errors[OTHER_ERRORS], 11);
+        assert_eq!(aggregate.errors["again"], MAX_ERROR_KINDS + 10);
+        assert!(aggregate.results().is_empty());
+        assert!(aggregate.into_results().is_none());
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let mut aggregate = ResultAggregate::with_options(
+            Instant::now(),
+            LatencyIncludes::All,
+            None,
+            false,
+            DEFAULT_PRECISION,
+        );
+        aggregate.push(Err(ClientError::Timeout));
+        aggregate.push(Err(WebSocketError::Timeout.into()));
+        aggregate.push(Err(ClientError::ConnectTimeout));
+        aggregate.push(Err(ClientError::ReadTimeout));
+        aggregate.push(Err(ClientError::Deadline));
+        assert_eq!(aggregate.timeouts, 2);
+        assert_eq!(aggregate.errors["timeout"], 1);
+        assert_eq!(aggregate.errors["connect timeout"], 1);
+    }
+
+    #[test]
+    fn test_rps() {
+        let start = Instant::now();
+        let mut aggregate = ResultAggregate::with_options(
+            start,
+            LatencyIncludes::All,
+            None,
+            true,
+            DEFAULT_PRECISION,
+        );
+        assert!(aggregate.rps(Duration::from_secs(3)).is_empty());
+
+        for end in [100, 200, 1500, 2500, 2600, 2700, 3500] {
+            aggregate.push(Ok(response(
+                start,
+                Duration::from_millis(end),
+                StatusCode::OK,
+            )));
+        }
+        // The last second isn't full
+        assert_eq!(
+            aggregate.rps(Duration::from_millis(3600)),
+            vec![2.0, 1.0, 3.0]
+        );
+        assert_eq!(aggregate.rps(Duration::from_millis(500)), vec![14.0]);
+        assert_eq!(
+            aggregate.started_per_second(Duration::from_secs(2)),
+            vec![7, 0]
+        );
+        assert_eq!(aggregate.results().len(), 7);
+    }
+}
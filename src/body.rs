@@ -0,0 +1,392 @@
+use std::{
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::{future::BoxFuture, FutureExt};
+use hyper::body::{Frame, SizeHint};
+
+use crate::body_dir::BodyDir;
+
+/// The buffer which a file body is read through for each request without `--chunked`
+pub const FILE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The file of `-D`, opened once for the run. Every request reads it at its own offset, so
+/// that the requests in flight don't move a shared one.
+#[derive(Debug)]
+pub struct FileBody {
+    path: PathBuf,
+    file: std::fs::File,
+    /// The size at startup, which is sent as `Content-Length`
+    len: u64,
+}
+
+impl FileBody {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            len,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Read the bytes from `offset` into `buf`, up to the size at startup. A file which
+    /// shrank since is an error, since the `Content-Length` is already sent.
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        let len = (buf.len() as u64).min(self.len.saturating_sub(offset)) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+        #[cfg(unix)]
+        let n = std::os::unix::fs::FileExt::read_at(&self.file, &mut buf[..len], offset)?;
+        #[cfg(windows)]
+        let n = std::os::windows::fs::FileExt::seek_read(&self.file, &mut buf[..len], offset)?;
+        if n == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("{} shrank during the run", self.path.display()),
+            ));
+        }
+        if offset + n as u64 == self.len {
+            self.check_len()?;
+        }
+        Ok(n)
+    }
+
+    /// Fail when the size of the file isn't the one at startup anymore, since the request
+    /// would be sent with a wrong `Content-Length`. Checked once the whole body is read.
+    fn check_len(&self) -> std::io::Result<()> {
+        let len = self.file.metadata()?.len();
+        if len != self.len {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "{} changed size during the run, from {} to {len} bytes",
+                    self.path.display(),
+                    self.len
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Read up to `len` bytes from `offset` on a blocking thread, since a file which isn't in
+    /// the page cache waits for the disk. The buffer is handed off as the chunk. Empty at the
+    /// end of the file.
+    pub async fn read_chunk(self: Arc<Self>, offset: u64, len: usize) -> std::io::Result<Bytes> {
+        tokio::task::spawn_blocking(move || {
+            let mut buf = vec![0; (len as u64).min(self.len.saturating_sub(offset)) as usize];
+            let n = self.read_at(&mut buf, offset)?;
+            buf.truncate(n);
+            Ok(buf.into())
+        })
+        .await
+        .map_err(std::io::Error::other)?
+    }
+
+    /// `check_len` on a blocking thread, for a body sent without `read_chunk`
+    #[cfg(target_os = "linux")]
+    pub async fn verify_len(self: Arc<Self>) -> std::io::Result<()> {
+        tokio::task::spawn_blocking(move || self.check_len())
+            .await
+            .map_err(std::io::Error::other)?
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        std::os::fd::AsRawFd::as_raw_fd(&self.file)
+    }
+}
+
+/// Where the request body comes from.
+#[derive(Clone, Debug)]
+pub enum BodySource {
+    /// The whole body is in memory.
+    Static(Bytes),
+    /// The body is in memory and replaced by `--body-reload-interval`.
+    Reloaded(tokio::sync::watch::Receiver<Bytes>),
+    /// The body is read from the file for each request.
+    File(Arc<FileBody>),
+    /// One of the files of `--body-dir` is picked for each request.
+    Dir(Arc<BodyDir>),
+}
+
+/// A request body.
+/// A body without exact size is sent with `Transfer-Encoding: chunked` on HTTP/1.1.
+pub enum Body {
+    Full(Option<Bytes>),
+    Chunked {
+        data: Bytes,
+        chunk_size: usize,
+    },
+    /// Sent with `Content-Length` unless it's chunked
+    File {
+        file: Arc<FileBody>,
+        offset: u64,
+        chunk_size: usize,
+        chunked: bool,
+        /// The read of the next chunk in progress
+        read: Option<BoxFuture<'static, std::io::Result<Bytes>>>,
+    },
+    /// Wait for the gate before sending the inner body. The gate resolves to false to abort.
+    Gated {
+        gate: BoxFuture<'static, bool>,
+        inner: Box<Body>,
+    },
+}
+
+impl Body {
+    pub fn empty() -> Self {
+        Self::Full(None)
+    }
+
+    pub fn new(source: &BodySource, chunk_size: Option<usize>) -> std::io::Result<Self> {
+        match (source, chunk_size) {
+            (BodySource::Static(data), None) => Ok(Self::Full(Some(data.clone()))),
+            (BodySource::Static(data), Some(chunk_size)) => Ok(Self::Chunked {
+                data: data.clone(),
+                chunk_size,
+            }),
+            (BodySource::Reloaded(current), chunk_size) => {
+                Self::new(&BodySource::Static(current.borrow().clone()), chunk_size)
+            }
+            (BodySource::Dir(dir), chunk_size) => {
+                Self::new(&BodySource::Static(dir.pick()), chunk_size)
+            }
+            (BodySource::File(file), chunk_size) => Ok(Self::File {
+                file: file.clone(),
+                offset: 0,
+                chunk_size: chunk_size.unwrap_or(FILE_BUFFER_SIZE),
+                chunked: chunk_size.is_some(),
+                read: None,
+            }),
+        }
+    }
+}
+
+impl hyper::body::Body for Body {
+    type Data = Bytes;
+    type Error = std::io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        if let Body::Gated { gate, inner } = this {
+            match gate.poll_unpin(cx) {
+                Poll::Ready(true) => {
+                    *this = std::mem::replace(inner.as_mut(), Body::empty());
+                }
+                Poll::Ready(false) => {
+                    return Poll::Ready(Some(Err(std::io::Error::new(
+                        std::io::ErrorKind::Interrupted,
+                        "request body aborted",
+                    ))));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        match this {
+            Body::Full(data) => Poll::Ready(
+                data.take()
+                    .filter(|d| !d.is_empty())
+                    .map(|d| Ok(Frame::data(d))),
+            ),
+            Body::Chunked { data, chunk_size } => {
+                if data.is_empty() {
+                    return Poll::Ready(None);
+                }
+                let chunk = data.split_to((*chunk_size).min(data.len()));
+                Poll::Ready(Some(Ok(Frame::data(chunk))))
+            }
+            Body::File {
+                file,
+                offset,
+                chunk_size,
+                read,
+                ..
+            } => {
+                if *offset >= file.len() {
+                    return Poll::Ready(None);
+                }
+                let pending = read
+                    .get_or_insert_with(|| file.clone().read_chunk(*offset, *chunk_size).boxed());
+                let chunk = futures::ready!(pending.poll_unpin(cx));
+                *read = None;
+                match chunk {
+                    Ok(chunk) if chunk.is_empty() => Poll::Ready(None),
+                    Ok(chunk) => {
+                        *offset += chunk.len() as u64;
+                        Poll::Ready(Some(Ok(Frame::data(chunk))))
+                    }
+                    Err(err) => Poll::Ready(Some(Err(err))),
+                }
+            }
+            Body::Gated { .. } => unreachable!(),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self {
+            Body::Full(data) => data.as_ref().map(|d| d.is_empty()).unwrap_or(true),
+            Body::Chunked { data, .. } => data.is_empty(),
+            Body::File { file, offset, .. } => *offset >= file.len(),
+            Body::Gated { .. } => false,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self {
+            Body::Full(data) => {
+                SizeHint::with_exact(data.as_ref().map(|d| d.len()).unwrap_or(0) as u64)
+            }
+            Body::Gated { inner, .. } => inner.size_hint(),
+            Body::File {
+                file,
+                offset,
+                chunked: false,
+                ..
+            } => SizeHint::with_exact(file.len() - offset),
+            _ => SizeHint::default(),
+        }
+    }
+}
+
+/// Re-run `load` every `interval` for `--body-reload-interval` and send the body when it has
+/// changed. A failed load keeps the previous body. Returns when every receiver is gone.
+pub async fn reload(
+    interval: Duration,
+    tx: tokio::sync::watch::Sender<Bytes>,
+    load: impl Fn() -> anyhow::Result<Bytes>,
+    log: bool,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The first tick is immediate and the body was just loaded
+    ticker.tick().await;
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = tx.closed() => return,
+        }
+        match load() {
+            Ok(body) => {
+                tx.send_if_modified(|current| {
+                    if *current == body {
+                        return false;
+                    }
+                    *current = body;
+                    true
+                });
+            }
+            Err(err) if log => eprintln!("--body-reload-interval: {err:#}, keeping the body"),
+            Err(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use hyper::body::Body as _;
+
+    #[tokio::test]
+    async fn test_chunked() {
+        let source = BodySource::Static(Bytes::from_static(b"abcde"));
+        let mut body = Body::new(&source, Some(2)).unwrap();
+        let mut chunks = Vec::new();
+        while let Some(frame) = body.frame().await {
+            chunks.push(frame.unwrap().into_data().unwrap());
+        }
+        assert_eq!(chunks, ["ab", "cd", "e"]);
+        // The source is not consumed
+        let body = Body::new(&source, None).unwrap();
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "abcde");
+    }
+
+    #[tokio::test]
+    async fn test_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("body.bin");
+        std::fs::write(&path, "abcde").unwrap();
+        let file = Arc::new(FileBody::open(&path).unwrap());
+        let source = BodySource::File(file.clone());
+
+        let body = Body::new(&source, None).unwrap();
+        assert_eq!(body.size_hint().exact(), Some(5));
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "abcde");
+
+        let mut body = Body::new(&source, Some(2)).unwrap();
+        assert_eq!(body.size_hint().exact(), None);
+        let mut chunks = Vec::new();
+        while let Some(frame) = body.frame().await {
+            chunks.push(frame.unwrap().into_data().unwrap());
+        }
+        assert_eq!(chunks, ["ab", "cd", "e"]);
+
+        // A file of another size than at startup fails
+        std::fs::write(&path, "abcdefg").unwrap();
+        assert_eq!(file.clone().read_chunk(3, 1).await.unwrap(), "d");
+        let err = file.clone().read_chunk(3, 8).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err
+            .to_string()
+            .ends_with("body.bin changed size during the run, from 5 to 7 bytes"));
+        std::fs::write(&path, "ab").unwrap();
+        assert_eq!(
+            file.clone().read_chunk(3, 8).await.unwrap_err().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+        assert!(file.read_chunk(5, 8).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("body.json");
+        std::fs::write(&path, "one").unwrap();
+        let load = {
+            let path = path.clone();
+            move || Ok(Bytes::from(std::fs::read(&path)?))
+        };
+        let (tx, rx) = tokio::sync::watch::channel(load().unwrap());
+        let mut changed = rx.clone();
+        let source = BodySource::Reloaded(rx);
+        let reloader = tokio::spawn(reload(Duration::from_millis(10), tx, load, false));
+
+        let body = Body::new(&source, None).unwrap();
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "one");
+
+        std::fs::write(&path, "two").unwrap();
+        changed.changed().await.unwrap();
+        let body = Body::new(&source, None).unwrap();
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "two");
+
+        // A missing file keeps the body
+        std::fs::remove_file(&path).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let body = Body::new(&source, Some(2)).unwrap();
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "two");
+
+        drop(source);
+        drop(changed);
+        reloader.await.unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+use std::{borrow::Cow, io::Read};
+
+use hyper::http::HeaderValue;
+use regex::Regex;
+use thiserror::Error;
+
+use crate::decompress::ContentEncoding;
+
+/// Checks of the response body by `--assert-body-contains` and `--assert-body-regex`.
+pub struct BodyAssertion {
+    pub contains: Option<Vec<u8>>,
+    pub regex: Option<Regex>,
+    /// At most this many bytes of the body are buffered and checked
+    pub max_bytes: usize,
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssertionFailure {
+    #[error("body doesn't contain the expected string")]
+    NotContained,
+    #[error("body doesn't match the regex")]
+    NotMatched,
+    #[error("body is not UTF-8")]
+    NotUtf8,
+    #[error("failed to decode the body")]
+    Decode,
+}
+
+impl BodyAssertion {
+    /// Check the buffered body. `truncated` tells that the body was longer than `max_bytes`.
+    pub fn check(
+        &self,
+        content_encoding: Option<&HeaderValue>,
+        body: &[u8],
+        truncated: bool,
+    ) -> Result<(), AssertionFailure> {
+        let body = match decode(content_encoding, body, self.max_bytes) {
+            (body, None) => body,
+            // A body cut at the cap can't be decoded to the end
+            (body, Some(_)) if truncated => body,
+            (_, Some(_)) => return Err(AssertionFailure::Decode),
+        };
+        let truncated = truncated || body.len() >= self.max_bytes;
+
+        if let Some(contains) = &self.contains {
+            if !contains.is_empty() && !body.windows(contains.len()).any(|w| w == &contains[..]) {
+                return Err(AssertionFailure::NotContained);
+            }
+        }
+
+        if let Some(regex) = &self.regex {
+            let text = match std::str::from_utf8(&body) {
+                Ok(text) => text,
+                // The cap may split the last character
+                Err(e) if truncated && e.error_len().is_none() => {
+                    std::str::from_utf8(&body[..e.valid_up_to()]).unwrap()
+                }
+                Err(_) => return Err(AssertionFailure::NotUtf8),
+            };
+            if !regex.is_match(text) {
+                return Err(AssertionFailure::NotMatched);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Decode a body by its `Content-Encoding`. At most `max_len` bytes are decoded.
+/// When decoding fails, the bytes decoded until then are returned with the error.
+pub fn decode<'a>(
+    content_encoding: Option<&HeaderValue>,
+    body: &'a [u8],
+    max_len: usize,
+) -> (Cow<'a, [u8]>, Option<std::io::Error>) {
+    let decoder: Box<dyn Read + 'a> = match ContentEncoding::parse(content_encoding) {
+        Ok(Some(ContentEncoding::Gzip)) => Box::new(flate2::read::GzDecoder::new(body)),
+        Ok(Some(ContentEncoding::Deflate)) => Box::new(flate2::read::ZlibDecoder::new(body)),
+        Ok(Some(ContentEncoding::Br)) => Box::new(brotli::Decompressor::new(body, 4096)),
+        #[cfg(feature = "zstd")]
+        Ok(Some(ContentEncoding::Zstd)) => match zstd::stream::read::Decoder::with_buffer(body) {
+            Ok(decoder) => Box::new(decoder),
+            Err(err) => return (Cow::Borrowed(body), Some(err)),
+        },
+        Ok(None) | Err(()) => return (Cow::Borrowed(body), None),
+    };
+
+    let mut decoded = Vec::new();
+    let error = decoder.take(max_len as u64).read_to_end(&mut decoded).err();
+    (Cow::Owned(decoded), error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn assertion(contains: Option<&str>, regex: Option<&str>) -> BodyAssertion {
+        BodyAssertion {
+            contains: contains.map(|s| s.as_bytes().to_vec()),
+            regex: regex.map(|r| Regex::new(r).unwrap()),
+            max_bytes: 1024,
+        }
+    }
+
+    #[test]
+    fn test_contains() {
+        let a = assertion(Some("\"ok\":true"), None);
+        assert_eq!(a.check(None, br#"{"ok":true}"#, false), Ok(()));
+        assert_eq!(
+            a.check(None, br#"{"ok":false}"#, false),
+            Err(AssertionFailure::NotContained)
+        );
+        // Non UTF-8 bodies are matched on bytes
+        let a = assertion(Some("ok"), None);
+        assert_eq!(a.check(None, b"\xff\xfeok", false), Ok(()));
+    }
+
+    #[test]
+    fn test_regex() {
+        let a = assertion(None, Some(r#""id":\s*\d+"#));
+        assert_eq!(a.check(None, br#"{"id": 42}"#, false), Ok(()));
+        assert_eq!(
+            a.check(None, br#"{"id": null}"#, false),
+            Err(AssertionFailure::NotMatched)
+        );
+        assert_eq!(
+            a.check(None, b"\xff\xfe", false),
+            Err(AssertionFailure::NotUtf8)
+        );
+        // A character split by the cap is ignored
+        let a = assertion(None, Some("^a"));
+        assert_eq!(a.check(None, "aあ".as_bytes()[..2].as_ref(), true), Ok(()));
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"hello world").unwrap();
+        let gzip = encoder.finish().unwrap();
+
+        let a = assertion(Some("world"), None);
+        let encoding = HeaderValue::from_static("gzip");
+        assert_eq!(a.check(Some(&encoding), &gzip, false), Ok(()));
+        assert_eq!(
+            a.check(None, &gzip, false),
+            Err(AssertionFailure::NotContained)
+        );
+        assert_eq!(
+            a.check(Some(&encoding), b"not gzip", false),
+            Err(AssertionFailure::Decode)
+        );
+
+        #[cfg(feature = "zstd")]
+        {
+            let zstd = zstd::encode_all(&b"hello world"[..], 3).unwrap();
+            let encoding = HeaderValue::from_static("zstd");
+            assert_eq!(a.check(Some(&encoding), &zstd, false), Ok(()));
+        }
+    }
+}
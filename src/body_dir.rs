@@ -0,0 +1,138 @@
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use bytes::Bytes;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Bodies of `--body-dir` are held in memory, so their total size is limited.
+pub const MAX_TOTAL_SIZE: u64 = 1024 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
+pub enum BodyDirOrder {
+    /// Send the files in turn, sorted by their names
+    RoundRobin,
+    /// Pick a file at random for each request. Reproducible with `--rand-seed`.
+    Random,
+}
+
+/// Request bodies loaded from the files in `--body-dir`. One of them is sent with each request.
+#[derive(Debug)]
+pub struct BodyDir {
+    bodies: Vec<Bytes>,
+    order: BodyDirOrder,
+    next: AtomicUsize,
+    rng: Mutex<StdRng>,
+    /// Whether each body has been sent
+    used: Vec<AtomicBool>,
+}
+
+impl BodyDir {
+    /// Load the regular files in `dir`. Subdirectories are not followed.
+    pub fn load(dir: &Path, order: BodyDirOrder, seed: Option<u64>) -> anyhow::Result<Self> {
+        let mut paths = Vec::new();
+        let mut total = 0;
+        for entry in std::fs::read_dir(dir)
+            .map_err(|e| anyhow::anyhow!("--body-dir {}: {e}", dir.display()))?
+        {
+            let entry = entry?;
+            let metadata = std::fs::metadata(entry.path())?;
+            if metadata.is_file() {
+                total += metadata.len();
+                paths.push(entry.path());
+            }
+        }
+        if paths.is_empty() {
+            anyhow::bail!("--body-dir {}: no files in the directory", dir.display());
+        }
+        if total > MAX_TOTAL_SIZE {
+            anyhow::bail!(
+                "--body-dir {}: the files total {total} bytes, more than the {MAX_TOTAL_SIZE} bytes which can be held in memory. Use -D with --chunked to stream a large body from disk.",
+                dir.display()
+            );
+        }
+        paths.sort();
+
+        let bodies = paths
+            .iter()
+            .map(|path| -> std::io::Result<Bytes> { Ok(Bytes::from(std::fs::read(path)?)) })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        Ok(Self::new(bodies, order, seed))
+    }
+
+    fn new(bodies: Vec<Bytes>, order: BodyDirOrder, seed: Option<u64>) -> Self {
+        Self {
+            used: bodies.iter().map(|_| AtomicBool::new(false)).collect(),
+            bodies,
+            order,
+            next: AtomicUsize::new(0),
+            rng: Mutex::new(match seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            }),
+        }
+    }
+
+    /// The body of the next request
+    pub fn pick(&self) -> Bytes {
+        let index = match self.order {
+            BodyDirOrder::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed) % self.count(),
+            BodyDirOrder::Random => self.rng.lock().unwrap().gen_range(0..self.count()),
+        };
+        self.used[index].store(true, Ordering::Relaxed);
+        self.bodies[index].clone()
+    }
+
+    /// The number of files
+    pub fn count(&self) -> usize {
+        self.bodies.len()
+    }
+
+    /// The number of distinct bodies which have been sent
+    pub fn used(&self) -> usize {
+        self.used
+            .iter()
+            .filter(|used| used.load(Ordering::Relaxed))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("b.json"), "b").unwrap();
+        std::fs::write(dir.path().join("a.json"), "a").unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+
+        let bodies = BodyDir::load(dir.path(), BodyDirOrder::RoundRobin, None).unwrap();
+        assert_eq!(bodies.count(), 2);
+        assert_eq!(bodies.used(), 0);
+        assert_eq!(bodies.pick(), "a");
+        assert_eq!(bodies.used(), 1);
+        assert_eq!(bodies.pick(), "b");
+        assert_eq!(bodies.pick(), "a");
+        assert_eq!(bodies.used(), 2);
+
+        let empty = tempfile::tempdir().unwrap();
+        assert!(BodyDir::load(empty.path(), BodyDirOrder::RoundRobin, None).is_err());
+    }
+
+    #[test]
+    fn test_random_seed() {
+        let bodies = ["a", "b", "c", "d"].map(Bytes::from).to_vec();
+        let picks = |seed| {
+            let bodies = BodyDir::new(bodies.clone(), BodyDirOrder::Random, Some(seed));
+            (0..16).map(|_| bodies.pick()).collect::<Vec<_>>()
+        };
+        assert_eq!(picks(1), picks(1));
+        assert_ne!(picks(1), picks(2));
+    }
+}
@@ -0,0 +1,64 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// When the bursts of `--burst-delay` and `--burst-rate` were released
+#[derive(Default)]
+pub struct BurstReleases(Mutex<Vec<Instant>>);
+
+impl BurstReleases {
+    pub fn release(&self, at: Instant) {
+        self.0.lock().unwrap().push(at);
+    }
+
+    pub fn get(&self) -> Vec<Instant> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// The time from the release of each burst until its last response arrived.
+/// A response belongs to the last burst released before its request started.
+/// Bursts without any response are skipped.
+pub fn completion_times(
+    releases: &[Instant],
+    responses: impl Iterator<Item = (Instant, Instant)>,
+) -> Vec<Duration> {
+    let mut last_end: Vec<Option<Instant>> = vec![None; releases.len()];
+    for (start, end) in responses {
+        let Some(i) = releases.partition_point(|&t| t <= start).checked_sub(1) else {
+            continue;
+        };
+        last_end[i] = Some(last_end[i].map_or(end, |last| last.max(end)));
+    }
+    releases
+        .iter()
+        .zip(last_end)
+        .filter_map(|(&release, end)| Some(end? - release))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completion_times() {
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+        let releases = [ms(100), ms(200), ms(300)];
+        let responses = [
+            // Before the first burst
+            (ms(0), ms(10)),
+            (ms(100), ms(120)),
+            (ms(101), ms(150)),
+            // Started late by a busy worker but still in the first burst
+            (ms(199), ms(230)),
+            (ms(200), ms(210)),
+        ];
+        assert_eq!(
+            completion_times(&releases, responses.into_iter()),
+            vec![Duration::from_millis(130), Duration::from_millis(10)]
+        );
+    }
+}
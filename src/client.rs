@@ -1,68 +1,277 @@
 use futures::{future::FutureExt, Future};
-use http_body_util::Full;
 use hyper::{
-    body::{Body, Incoming},
+    body::{Body as _, Incoming},
     http,
 };
 use hyper_util::rt::{TokioExecutor, TokioIo};
 use rand::prelude::*;
-use std::{pin::Pin, sync::Arc};
+use std::{
+    borrow::Cow,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
 use thiserror::Error;
 use tokio::net::TcpStream;
 use url::{ParseError, Url};
 
+#[cfg(all(feature = "uring", target_os = "linux"))]
+use crate::uring::{self, UringStream};
 use crate::{
-    url_generator::{UrlGenerator, UrlGeneratorError},
-    ConnectToEntry,
+    abort::Abort,
+    body::{Body, BodySource},
+    body_assert::{AssertionFailure, BodyAssertion},
+    burst::BurstReleases,
+    connect_rate::{ConnectRate, StartupConnects},
+    decompress::DecodedLen,
+    duty_cycle::DutyCycle,
+    expect_status::ExpectStatus,
+    fd_limit,
+    label::{Label, Labels},
+    load_profile::LoadProfile,
+    method_mix::{self, MethodMix},
+    opts::{ConnectHost, ConnectToEntry},
+    park::{Parking, PARK_AFTER},
+    pause::Pause,
+    qps_ramp::QpsRamp,
+    ramp_up::{FinishGuard, RampUp},
+    raw_request::{self, PrebuiltBody, RawRequest, RawRequestError, RawResponse, ReadOptions},
+    result_channel::ReportSender,
+    retry_after::{self, RetryAfterCounters},
+    save_responses::ResponseSaver,
+    scenario::{Scenario, ScenarioState, Step},
+    shutdown::Shutdown,
+    target_p99::TargetP99,
+    think_time::ThinkTime,
+    tls::{self, TlsConnector},
+    url_generator::{self, Authority, UrlGenerator, UrlGeneratorError},
+    url_list::{UrlList, UrlPermit},
+    websocket::{WebSocketConnection, WebSocketCounters, WebSocketError, WebSocketOptions},
 };
 
-type SendRequestHttp1 = hyper::client::conn::http1::SendRequest<Full<&'static [u8]>>;
-type SendRequestHttp2 = hyper::client::conn::http2::SendRequest<Full<&'static [u8]>>;
+type SendRequestHttp1 = hyper::client::conn::http1::SendRequest<Body>;
+type SendRequestHttp2 = hyper::client::conn::http2::SendRequest<Body>;
+
+/// The generator of the setup in main, such as the first URL and the multipart boundary
+pub const SETUP_RNG_STREAM: u64 = u64::MAX;
+/// The generator of the offsets of `--stagger`
+pub const STAGGER_RNG_STREAM: u64 = u64::MAX - 1;
+/// The generator of the gaps of `--arrival-distribution poisson`
+const ARRIVALS_RNG_STREAM: u64 = u64::MAX - 2;
+/// The generator of `--pre-lookup`
+const PRE_LOOKUP_RNG_STREAM: u64 = u64::MAX - 3;
+/// The generators of the prewarmed connections count down from here
+const PREWARM_RNG_STREAM: u64 = u64::MAX - 4;
+
+/// The generator of a worker, numbered by where the worker is rather than by when it started,
+/// so that a run with the same seed gives each worker the same choices
+#[derive(Debug, Clone, Copy)]
+enum WorkerRng {
+    /// The requests of a worker. On HTTP/2, the workers of connection `c` are numbered from
+    /// `c * n_http2_parallel`. With `--arrival-rate`, the number of the request that set it up.
+    Requests(u64),
+    /// The setup of the HTTP/2 connection `c`
+    Http2Setup(u64),
+}
+
+impl WorkerRng {
+    /// Counting up from 0, away from the streams above
+    fn stream(self) -> u64 {
+        match self {
+            Self::Requests(i) => 2 * i,
+            Self::Http2Setup(c) => 2 * c + 1,
+        }
+    }
+}
+
+/// A random number generator derived from `--rand-seed`. Each stream gets its own sequence, so
+/// that a generator doesn't depend on how many numbers the others have drawn.
+pub fn seeded_rng(seed: u64, stream: u64) -> StdRng {
+    StdRng::seed_from_u64(seed.wrapping_add(stream))
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct ConnectionTime {
     pub dns_lookup: std::time::Instant,
     pub dialup: std::time::Instant,
+    /// The TLS handshake in the dialup. None without TLS.
+    pub tls_handshake: Option<TlsHandshake>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TlsHandshake {
+    /// The part of the dialup spent on the handshake
+    pub duration: std::time::Duration,
+    /// The session of an earlier connection was resumed. None when the TLS backend can't tell.
+    pub resumed: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Outcome of `Expect: 100-continue`
+pub struct ExpectContinue {
+    /// `100 Continue` was received
+    pub received: bool,
+    /// The body was sent, possibly after the timeout
+    pub body_sent: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
+/// 1xx responses received before the final response, recorded with `--informational-stats`
+pub struct Informational {
+    /// Number of `103 Early Hints`
+    pub early_hints: usize,
+    /// When the first 1xx response is received
+    pub first: Option<std::time::Instant>,
+}
+
+/// What the latency of a request measures, for `--latency-includes`
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum LatencyIncludes {
+    /// From the start of the request to the last byte of the response, including DNS lookup and dialup of a new connection
+    #[default]
+    All,
+    /// Same as all but without the DNS lookup of a new connection
+    NoDns,
+    /// From the first byte to the last byte of the response
+    ResponseOnly,
+}
+
+impl LatencyIncludes {
+    /// The definition printed in the summary
+    pub fn definition(self) -> &'static str {
+        match self {
+            Self::All => "request start to last byte, including DNS lookup and dialup",
+            Self::NoDns => "request start to last byte, excluding DNS lookup",
+            Self::ResponseOnly => "first byte to last byte of the response",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 /// a result for a request
 pub struct RequestResult {
     // When the query should started
     pub start_latency_correction: Option<std::time::Instant>,
+    /// How long after its scheduled time a worker picked the request up under -q
+    pub send_delay: Option<std::time::Duration>,
     /// When the query started
     pub start: std::time::Instant,
     /// DNS + dialup
     /// None when reuse connection
     pub connection_time: Option<ConnectionTime>,
+    /// When the response header is received
+    pub first_byte: std::time::Instant,
     /// When the query ends
     pub end: std::time::Instant,
     /// HTTP status
     pub status: http::StatusCode,
-    /// Length of body
-    pub len_bytes: usize,
+    /// HTTP method, named by `Labels::name`
+    pub method: Label,
+    /// Name of the scenario step
+    pub step: Option<Label>,
+    /// Path and query of the request when `--per-url-stats` is given
+    pub url: Option<Label>,
+    /// Length of body as received, before decoding Content-Encoding
+    pub len_bytes: u64,
+    /// Length of body after decoding Content-Encoding.
+    /// None unless `--decompressed-size` is given or when the encoding isn't supported.
+    pub decoded_len_bytes: Option<u64>,
+    /// None when `Expect: 100-continue` is not sent
+    pub expect_continue: Option<ExpectContinue>,
+    /// The status is not allowed by `--expect-status`
+    pub unexpected_status: bool,
+    /// Reading the body stopped at `--max-body-bytes`. `end` is when it stopped.
+    pub truncated: bool,
+    /// Id of the HTTP/2 connection the request is sent on
+    pub http2_connection: Option<usize>,
+    /// None unless `--informational-stats` is given
+    pub informational: Option<Informational>,
 }
 
 impl RequestResult {
-    /// Duration the request takes.
-    pub fn duration(&self) -> std::time::Duration {
-        self.end - self.start_latency_correction.unwrap_or(self.start)
+    /// Duration the request takes, measured as `includes` defines.
+    pub fn duration(&self, includes: LatencyIncludes) -> std::time::Duration {
+        let total = self.end - self.start_latency_correction.unwrap_or(self.start);
+        match includes {
+            LatencyIncludes::All => total,
+            LatencyIncludes::NoDns => match self.connection_time {
+                Some(connection_time) => {
+                    total.saturating_sub(connection_time.dns_lookup - self.start)
+                }
+                None => total,
+            },
+            LatencyIncludes::ResponseOnly => self.end - self.first_byte,
+        }
+    }
+
+    /// A response with an unexpected status is counted as an error under this name.
+    pub fn status_error(&self) -> Option<String> {
+        self.unexpected_status
+            .then(|| format!("unexpected status {}", self.status))
     }
 }
 
+/// Addresses of a host to connect to
+#[derive(Clone, Copy, Debug)]
+pub struct Addrs {
+    /// The address tried first, of the family preferred by `--ip-version`
+    pub primary: std::net::IpAddr,
+    /// An address of the other family, raced against `primary` after `HAPPY_EYEBALLS_DELAY`
+    pub fallback: Option<std::net::IpAddr>,
+    pub port: u16,
+}
+
+/// How long the fallback family waits for the preferred one (Connection Attempt Delay of RFC 8305)
+const HAPPY_EYEBALLS_DELAY: std::time::Duration = std::time::Duration::from_millis(250);
+
 pub struct Dns {
     pub connect_to: Vec<ConnectToEntry>,
+    /// Connect here instead of the URL of the run when no entry of `connect_to` applies to it
+    pub connect_host: Option<(Authority, ConnectHost)>,
     pub resolver:
         hickory_resolver::AsyncResolver<hickory_resolver::name_server::TokioConnectionProvider>,
+    /// Retry a failed lookup this many times
+    pub retries: usize,
+    /// Wait before the first retry. Doubled for each retry.
+    pub retry_interval: std::time::Duration,
+    /// The lookup of each host, shared by the workers so that they don't send the same query
+    /// at once. The addresses are kept for the run, and a failed lookup is forgotten.
+    pub lookups: std::sync::Mutex<std::collections::HashMap<String, SharedLookup>>,
+    /// Try IPv4 first when a host has addresses of both families, rather than IPv6
+    pub prefer_ipv4: bool,
+}
+
+/// Whether the machine has a route to the IPv6 internet, for `--ip-version auto`. Connecting
+/// a UDP socket only looks up the route and sends nothing.
+pub fn has_ipv6_route() -> bool {
+    std::net::UdpSocket::bind((std::net::Ipv6Addr::UNSPECIFIED, 0))
+        .and_then(|socket| socket.connect("[2001:4860:4860::8888]:53"))
+        .is_ok()
 }
 
+type SharedLookup = futures::future::Shared<
+    futures::future::BoxFuture<
+        'static,
+        Result<Arc<[std::net::IpAddr]>, hickory_resolver::error::ResolveError>,
+    >,
+>;
+
 impl Dns {
-    /// Perform a DNS lookup for a given url and returns (ip_addr, port)
-    async fn lookup<R: Rng>(
-        &self,
-        url: &Url,
-        rng: &mut R,
-    ) -> Result<(std::net::IpAddr, u16), ClientError> {
+    /// Perform a DNS lookup for a given url and returns the addresses to connect to
+    async fn lookup<R: Rng>(&self, url: &Url, rng: &mut R) -> Result<Addrs, ClientError> {
         let host = url.host_str().ok_or(ClientError::HostNotFound)?;
         let port = url
             .port_or_known_default()
@@ -75,6 +284,12 @@ impl Dns {
             .find(|entry| entry.requested_port == port && entry.requested_host == host)
         {
             (entry.target_host.as_str(), entry.target_port)
+        } else if let Some((_, target)) = self
+            .connect_host
+            .as_ref()
+            .filter(|(origin, _)| origin.matches(url))
+        {
+            (target.host.as_str(), target.port.unwrap_or(port))
         } else {
             (host, port)
         };
@@ -87,19 +302,95 @@ impl Dns {
             host
         };
 
+        // An IP address needs no resolution, so `--ip-version` doesn't apply to it
+        if let Ok(addr) = host.parse::<std::net::IpAddr>() {
+            return Ok(Addrs {
+                primary: addr,
+                fallback: None,
+                port,
+            });
+        }
+
         // Perform actual DNS lookup, either on the original (host, port), or
         // on the (host, port) specified with `--connect-to`.
-        let addrs = self
-            .resolver
-            .lookup_ip(host)
-            .await
-            .map_err(Box::new)?
-            .iter()
-            .collect::<Vec<_>>();
+        let addrs = self.lookup_ip(host).await?;
+
+        let (ipv6, ipv4): (Vec<_>, Vec<_>) = addrs.iter().copied().partition(|addr| addr.is_ipv6());
+        let (preferred, other) = if self.prefer_ipv4 {
+            (ipv4, ipv6)
+        } else {
+            (ipv6, ipv4)
+        };
+        let (primary, fallback) = match (preferred.choose(rng), other.choose(rng)) {
+            (Some(&preferred), other) => (preferred, other.copied()),
+            (None, Some(&other)) => (other, None),
+            (None, None) => return Err(ClientError::DNSNoRecord),
+        };
+
+        Ok(Addrs {
+            primary,
+            fallback,
+            port,
+        })
+    }
 
-        let addr = *addrs.choose(rng).ok_or(ClientError::DNSNoRecord)?;
+    /// Resolve `host`, or wait for the lookup of another worker
+    async fn lookup_ip(&self, host: &str) -> Result<Arc<[std::net::IpAddr]>, ClientError> {
+        let lookup = {
+            let mut lookups = self.lookups.lock().unwrap();
+            match lookups.get(host) {
+                Some(lookup) => lookup.clone(),
+                None => {
+                    let lookup = Self::resolve(
+                        self.resolver.clone(),
+                        host.to_string(),
+                        self.retries,
+                        self.retry_interval,
+                    )
+                    .boxed()
+                    .shared();
+                    lookups.insert(host.to_string(), lookup.clone());
+                    lookup
+                }
+            }
+        };
+        match lookup.clone().await {
+            Ok(addrs) => Ok(addrs),
+            Err(err) => {
+                // The next lookup tries again
+                let mut lookups = self.lookups.lock().unwrap();
+                if lookups.get(host).is_some_and(|l| l.ptr_eq(&lookup)) {
+                    lookups.remove(host);
+                }
+                Err(Box::new(err).into())
+            }
+        }
+    }
 
-        Ok((addr, port))
+    /// Resolve `host` with exponential backoff between retries. After the last failure,
+    /// it waits once more so that the worker doesn't fail its requests in a busy loop.
+    async fn resolve(
+        resolver: hickory_resolver::AsyncResolver<
+            hickory_resolver::name_server::TokioConnectionProvider,
+        >,
+        host: String,
+        retries: usize,
+        mut interval: std::time::Duration,
+    ) -> Result<Arc<[std::net::IpAddr]>, hickory_resolver::error::ResolveError> {
+        let mut retried = 0;
+        loop {
+            match resolver.lookup_ip(host.as_str()).await {
+                Ok(lookup) => return Ok(lookup.iter().collect()),
+                Err(err) => {
+                    tokio::time::sleep(interval).await;
+                    if retried >= retries {
+                        return Err(err);
+                    }
+                    retried += 1;
+                    interval *= 2;
+                }
+            }
+        }
     }
 }
 
@@ -113,7 +404,7 @@ pub enum ClientError {
     DNSNoRecord,
     #[error("Redirection limit has reached")]
     TooManyRedirect,
-    #[error(transparent)]
+    #[error("DNS lookup failed: {0}")]
     // Use Box here because ResolveError is big.
     ResolveError(#[from] Box<hickory_resolver::error::ResolveError>),
 
@@ -125,6 +416,10 @@ pub enum ClientError {
     #[error(transparent)]
     RustlsError(#[from] rustls::Error),
 
+    #[cfg(feature = "rustls")]
+    #[error(transparent)]
+    RustlsVerifierError(#[from] rustls::client::VerifierBuilderError),
+
     #[cfg(feature = "rustls")]
     #[error(transparent)]
     InvalidDnsName(#[from] rustls_pki_types::InvalidDnsNameError),
@@ -145,74 +440,742 @@ pub enum ClientError {
     HeaderToStrError(#[from] http::header::ToStrError),
     #[error(transparent)]
     InvalidUri(#[from] http::uri::InvalidUri),
+    /// `-t` expired
     #[error("timeout")]
     Timeout,
+    #[error("connect timeout")]
+    ConnectTimeout,
+    /// EMFILE or ENFILE while connecting
+    #[error("{0}, raise the limit with ulimit -n or lower -c")]
+    TooManyOpenFiles(std::io::Error),
+    #[error("read timeout")]
+    ReadTimeout,
     #[error("aborted due to deadline")]
     Deadline,
+    #[error("aborted due to failed requests")]
+    Aborted,
     #[error(transparent)]
     UrlGeneratorError(#[from] UrlGeneratorError),
     #[error(transparent)]
     UrlParseError(#[from] ParseError),
+    #[error("scenario step {0} failed: {1}")]
+    ScenarioStep(Arc<str>, String),
+    #[error("assertion failed: {0}")]
+    AssertionFailed(#[from] AssertionFailure),
+    #[error("HTTP/2 stream reset by the server: {0:?}")]
+    Http2StreamReset(h2::Reason),
+    #[error("HTTP/2 GOAWAY from the server: {0:?}")]
+    Http2GoAway(h2::Reason),
+    #[error("truncated response: received {received} of {expected} bytes")]
+    TruncatedResponse { expected: u64, received: usize },
+    #[error("truncated response: connection closed in the chunked body")]
+    TruncatedChunkedResponse,
+    #[error(transparent)]
+    WebSocket(#[from] WebSocketError),
+    #[error(transparent)]
+    RawRequest(#[from] RawRequestError),
+}
+
+impl ClientError {
+    /// An I/O error of connecting, told apart when it's out of file descriptors
+    fn connect(err: std::io::Error) -> Self {
+        if fd_limit::is_exhausted(&err) {
+            Self::TooManyOpenFiles(err)
+        } else {
+            Self::IoError(err)
+        }
+    }
+}
+
+/// A response body read to the end or up to `--max-body-bytes`
+struct ResponseBody {
+    len: usize,
+    /// Reading stopped at `--max-body-bytes`
+    truncated: bool,
+    /// The beginning of the body kept for scenario extraction, body assertions and
+    /// `--save-responses`. The buffer of the worker, which `recycle` gives back.
+    buffer: bytes::BytesMut,
+    /// Response headers, moved here by the caller once it's done with them
+    headers: http::HeaderMap,
+    /// Size after decoding Content-Encoding, when it's measured and known
+    decoded_len: Option<usize>,
+}
+
+/// Reading a response body failed after `received` bytes
+struct BodyError {
+    /// None when no bytes arrived within `--read-timeout`
+    source: Option<hyper::Error>,
+    received: usize,
+}
+
+impl From<BodyError> for ClientError {
+    fn from(err: BodyError) -> Self {
+        match err.source {
+            Some(source) => source.into(),
+            None => ClientError::ReadTimeout,
+        }
+    }
+}
+
+impl BodyError {
+    /// Tell a body cut by the server closing the HTTP/1.1 connection from other errors.
+    fn into_http1_error(self, headers: &http::HeaderMap) -> ClientError {
+        let Some(source) = self.source else {
+            return ClientError::ReadTimeout;
+        };
+        let expected = headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        match expected {
+            Some(expected) if (self.received as u64) < expected => ClientError::TruncatedResponse {
+                expected,
+                received: self.received,
+            },
+            None if is_unexpected_eof(&source) => ClientError::TruncatedChunkedResponse,
+            _ => source.into(),
+        }
+    }
+}
+
+/// Whether the server keeps a HTTP/1 connection open after the response.
+/// `Connection` header overrides the default of the version, which is close for HTTP/1.0.
+fn is_keep_alive(method: &http::Method, parts: &http::response::Parts) -> bool {
+    let has_token = |token: &str| {
+        parts
+            .headers
+            .get_all(http::header::CONNECTION)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    };
+    if has_token("close") || (parts.version < http::Version::HTTP_11 && !has_token("keep-alive")) {
+        return false;
+    }
+
+    // Otherwise the body is delimited by closing the connection
+    *method == http::Method::HEAD
+        || parts.status == http::StatusCode::NO_CONTENT
+        || parts.status == http::StatusCode::NOT_MODIFIED
+        || parts.headers.contains_key(http::header::CONTENT_LENGTH)
+        || parts.headers.contains_key(http::header::TRANSFER_ENCODING)
+}
+
+/// The connection was closed in the middle of a message
+fn is_unexpected_eof(err: &hyper::Error) -> bool {
+    if err.is_incomplete_message() {
+        return true;
+    }
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            return io.kind() == std::io::ErrorKind::UnexpectedEof;
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// The connection was closed or reset by the server while the request was sent
+fn is_closed_connection(err: &hyper::Error) -> bool {
+    if err.is_incomplete_message() {
+        return true;
+    }
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            return raw_request::is_connection_reset(io);
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// The error of a raw response, with the same errors for a truncated response as the
+/// responses read by hyper
+fn raw_response_error(err: RawRequestError) -> ClientError {
+    match err {
+        RawRequestError::ReadTimeout => ClientError::ReadTimeout,
+        RawRequestError::Truncated { expected, received } => ClientError::TruncatedResponse {
+            expected: expected as u64,
+            received,
+        },
+        RawRequestError::TruncatedChunked => ClientError::TruncatedChunkedResponse,
+        err => err.into(),
+    }
+}
+
+impl ResponseBody {
+    /// Read the body and keep up to `limit` bytes of it in `buffer`.
+    /// Reading stops once `max_len` bytes are received and the rest of the body is dropped.
+    /// The decoded size is measured too when `decode` is true.
+    /// Fails when no frame arrives within `read_timeout`.
+    async fn read(
+        headers: &http::HeaderMap,
+        mut stream: Incoming,
+        mut buffer: bytes::BytesMut,
+        limit: usize,
+        max_len: Option<usize>,
+        decode: bool,
+        read_timeout: Option<std::time::Duration>,
+    ) -> Result<Self, BodyError> {
+        let mut len = 0;
+        let mut truncated = false;
+        buffer.clear();
+        let mut decoded_len =
+            decode.then(|| DecodedLen::new(headers.get(http::header::CONTENT_ENCODING)));
+        loop {
+            let frame =
+                futures::future::poll_fn(|cx| Incoming::poll_frame(Pin::new(&mut stream), cx));
+            let frame = match read_timeout {
+                Some(read_timeout) => {
+                    tokio::time::timeout(read_timeout, frame)
+                        .await
+                        .map_err(|_| BodyError {
+                            source: None,
+                            received: len,
+                        })?
+                }
+                None => frame.await,
+            };
+            let Some(chunk) = frame else {
+                break;
+            };
+            let chunk = chunk.map_err(|source| BodyError {
+                source: Some(source),
+                received: len,
+            })?;
+            if let Some(data) = chunk.data_ref() {
+                len += data.len();
+                let keep = data.len().min(limit - buffer.len());
+                buffer.extend_from_slice(&data[..keep]);
+                if let Some(decoded_len) = &mut decoded_len {
+                    decoded_len.update(data);
+                }
+            }
+            if max_len.is_some_and(|max_len| len >= max_len) && !stream.is_end_stream() {
+                truncated = true;
+                break;
+            }
+        }
+        Ok(Self {
+            len,
+            truncated,
+            buffer,
+            headers: Default::default(),
+            // A body without content such as a response to HEAD can't be decoded
+            decoded_len: decoded_len.and_then(|d| if len == 0 { Some(0) } else { d.finish() }),
+        })
+    }
+
+    /// `buffer` doesn't hold the whole body
+    fn buffer_truncated(&self) -> bool {
+        self.truncated || self.len > self.buffer.len()
+    }
+
+    /// The buffer, to read the next body into
+    fn recycle(self) -> bytes::BytesMut {
+        self.buffer
+    }
+}
+
+/// Records 1xx responses of a request. hyper skips them and passes only the final response.
+#[derive(Default)]
+struct InformationalTracker {
+    early_hints: AtomicUsize,
+    first: std::sync::OnceLock<std::time::Instant>,
+}
+
+impl InformationalTracker {
+    fn record(&self, status: http::StatusCode) {
+        self.first.get_or_init(std::time::Instant::now);
+        // 103 Early Hints has no constant in http
+        if status.as_u16() == 103 {
+            self.early_hints.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn finish(&self) -> Informational {
+        Informational {
+            early_hints: self.early_hints.load(Ordering::Relaxed),
+            first: self.first.get().copied(),
+        }
+    }
+}
+
+/// Held while a request with `Expect: 100-continue` is in flight.
+struct ExpectContinueGate {
+    tx: flume::Sender<bool>,
+    received: Arc<AtomicBool>,
+    body_sent: Arc<AtomicBool>,
+}
+
+impl ExpectContinueGate {
+    /// Abort the body if it is still waiting, since the final response has been received.
+    fn finish(self) -> ExpectContinue {
+        let _ = self.tx.try_send(false);
+        ExpectContinue {
+            received: self.received.load(Ordering::Relaxed),
+            body_sent: self.body_sent.load(Ordering::Relaxed),
+        }
+    }
 }
 
 pub struct Client {
     pub http_version: http::Version,
     pub url_generator: UrlGenerator,
+    /// Name of the query parameter appended with a unique value to each request
+    pub cache_bust: Option<String>,
+    /// `--urls-from-file`, requested instead of the URL
+    pub url_list: Option<Arc<UrlList>>,
     pub method: http::Method,
+    /// Pick the method of each request from this instead of `method`
+    pub method_mix: Option<MethodMix>,
     pub headers: http::header::HeaderMap,
-    pub body: Option<&'static [u8]>,
+    /// `headers` as the requests send them. Built on the first use.
+    pub request_headers: std::sync::OnceLock<RequestHeaders>,
+    pub body: Option<BodySource>,
+    /// Send the body with `Transfer-Encoding: chunked` in chunks of this size
+    pub chunk_size: Option<usize>,
+    /// Record 1xx responses on HTTP/1.1
+    pub informational_stats: bool,
+    /// Send `Expect: 100-continue` and wait this long for `100 Continue` before sending the body
+    pub expect_continue_timeout: Option<std::time::Duration>,
     pub dns: Dns,
     pub timeout: Option<std::time::Duration>,
+    /// Fail a request when no bytes of the response arrive for this long
+    pub read_timeout: Option<std::time::Duration>,
     pub redirect_limit: usize,
     pub disable_keepalive: bool,
-    pub insecure: bool,
+    /// Send the whole URL as the request target on HTTP/1.x for `--request-target absolute`
+    pub absolute_form: bool,
+    /// The request target of the first URL, which is the only one without a URL generator
+    pub uri_cache: std::sync::OnceLock<(Url, http::Uri)>,
+    /// Reconnect instead of reusing a connection idle for this long
+    pub keepalive_idle_timeout: Option<std::time::Duration>,
+    /// Hand the permits of the rate to the worker which became idle last, for
+    /// `--idle-workers park`. HTTP/1.x only.
+    pub park_idle_workers: bool,
+    /// Reconnect after this many requests on a connection. None is unlimited.
+    pub requests_per_connection: Option<usize>,
+    pub keepalive_counters: Arc<KeepAliveCounters>,
+    /// Limits how fast new connections are established
+    pub connect_rate: Option<ConnectRate>,
+    /// Counts the connections established at the start. None with `--prewarm-connections`,
+    /// which makes them before the run.
+    pub startup_connects: Option<Arc<StartupConnects>>,
+    pub tls: tls::Connector,
     #[cfg(unix)]
     pub unix_socket: Option<std::path::PathBuf>,
+    /// Run HTTP/1.x on this many threads with an io_uring each, for `--io-backend uring`
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    pub uring_threads: Option<usize>,
+    /// Each worker runs the steps in a loop instead of requesting the URL
+    pub scenario: Option<Scenario>,
+    /// Record the URL of each request for `--per-url-stats`
+    pub record_url: bool,
+    /// The names of the methods, steps and URLs of the results
+    pub labels: Arc<Labels>,
+    /// `--rand-seed`, or a random one. Every random choice of the run derives from it.
+    pub seed: u64,
+    /// Fail requests whose response body doesn't pass the check
+    pub body_assertion: Option<BodyAssertion>,
+    /// Count responses with other status codes as errors
+    pub expect_status: Option<ExpectStatus>,
+    /// Stop reading the response body after this many bytes
+    pub max_body_bytes: Option<usize>,
+    /// Measure the size of bodies after decoding Content-Encoding
+    pub decompressed_size: bool,
+    /// Capture the first responses into files
+    pub save_responses: Option<ResponseSaver>,
+    /// Enable SO_KEEPALIVE on TCP sockets with this idle time
+    pub tcp_keepalive: Option<std::time::Duration>,
+    /// Connections established by `prewarm` and not taken by workers yet
+    pub prewarmed: std::sync::Mutex<Vec<PrewarmedConnection>>,
+    pub http2_settings: Http2Settings,
+    /// How many times a HTTP/2 request is retried after REFUSED_STREAM or GOAWAY
+    pub http2_max_retries: usize,
+    /// Number of HTTP/2 connections made, used as their ids
+    pub http2_connections: AtomicUsize,
+    pub http2_counters: Arc<Http2Counters>,
+    /// TCP connections by the address family
+    pub ip_families: Arc<IpFamilies>,
+    /// Some in the WebSocket mode
+    pub websocket: Option<WebSocketOptions>,
+    pub websocket_counters: Arc<WebSocketCounters>,
+    /// Send this template instead of building requests for `--raw-request`
+    pub raw_request: Option<RawRequest>,
+    /// Encode the request once when every request is the same, for `--prebuilt-requests`
+    pub prebuilt_requests: bool,
+    /// The request encoded once when every request is the same. Built on the first use.
+    pub prebuilt: std::sync::OnceLock<Option<RawRequest>>,
+    /// Pause a worker for the Retry-After of 429 and 503 responses
+    pub respect_retry_after: bool,
+    pub retry_after_counters: Arc<RetryAfterCounters>,
+    /// When the bursts of `--burst-delay` were released
+    pub burst_releases: Arc<BurstReleases>,
+    /// Start the workers gradually
+    pub ramp_up: Option<Arc<RampUp>>,
+    pub arrival_counters: Arc<ArrivalCounters>,
+    /// Pause each worker between its requests
+    pub think_time: Option<Arc<ThinkTime>>,
+    /// Rest between the periods of load
+    pub duty_cycle: Option<Arc<DutyCycle>>,
+    /// How the requests of `-q` and `--arrival-rate` are spread in time
+    pub arrival_distribution: ArrivalDistribution,
+    /// Release the requests of `-q` in a burst at the start of each second
+    pub qps_burst: bool,
+    /// Let the requests in flight at the deadline finish instead of cutting them
+    pub wait_ongoing_requests_after_deadline: bool,
+    /// Adjusts the rate of `-q` for `--target-p99`
+    pub target_p99: Option<Arc<TargetP99>>,
+    /// Traffic paused by SIGUSR1. None on Windows.
+    pub pause: Option<Arc<Pause>>,
+    /// Stop the requests when the collector aborts the run
+    pub abort: Option<Arc<Abort>>,
+    /// Start no new request once ctrl-c is pressed
+    pub shutdown: Arc<Shutdown>,
+}
+
+/// TCP connections by the address family which won Happy Eyeballs
+#[derive(Default)]
+pub struct IpFamilies {
+    pub ipv4: AtomicUsize,
+    pub ipv6: AtomicUsize,
+    /// IPv4 is tried first, as `Dns::prefer_ipv4`
+    pub prefer_ipv4: bool,
+}
+
+/// Reconnections of kept-alive HTTP/1 connections which aren't recorded as errors
+#[derive(Default)]
+pub struct KeepAliveCounters {
+    /// Connections closed by `--keepalive-idle-timeout` before the next request
+    pub idle_closes: AtomicUsize,
+    /// Requests sent again on a new connection since the kept-alive one was closed
+    /// by the server before the request was written
+    pub stale_retries: AtomicUsize,
+}
+
+/// Requests of `--arrival-rate` which couldn't start on schedule
+#[derive(Default)]
+pub struct ArrivalCounters {
+    /// Requests started late because `--max-in-flight` requests were in flight
+    pub late: AtomicUsize,
+}
+
+/// Events on HTTP/2 connections which aren't recorded in the results
+#[derive(Default)]
+pub struct Http2Counters {
+    /// Connections closed by the server with GOAWAY
+    pub goaways: AtomicUsize,
+    /// Requests retried after REFUSED_STREAM
+    pub refused_stream_retries: AtomicUsize,
+    /// Requests resent on a new connection since the server closed the connection before
+    /// processing them
+    pub goaway_resends: AtomicUsize,
+}
+
+/// SETTINGS sent on HTTP/2 connections. None leaves the default of hyper.
+#[derive(Default)]
+pub struct Http2Settings {
+    pub initial_window_size: Option<u32>,
+    pub max_frame_size: Option<u32>,
+}
+
+/// A connection established before the measurement starts
+pub enum PrewarmedConnection {
+    Http1(SendRequestHttp1),
+    Http2(Arc<Http2Connection>),
+}
+
+/// The headers of the requests, with what depends on the body already applied. hyper takes
+/// the headers of a request by value, so a request clones one of these as it is.
+pub struct RequestHeaders {
+    /// For a request with the body, or when there's no body at all
+    body: http::HeaderMap,
+    /// For a request without the body when there's one, such as a bodyless method of
+    /// `--method-mix`
+    bodyless: http::HeaderMap,
+}
+
+/// A HTTP/2 connection shared by the workers multiplexed on it.
+/// The connection is closed when all of them drop this.
+pub struct Http2Connection {
+    id: usize,
+    send_request: SendRequestHttp2,
 }
 
 struct ClientStateHttp1 {
     rng: StdRng,
+    /// The values of `--cache-bust`
+    cache_bust: Option<url_generator::CacheBust>,
     send_request: Option<SendRequestHttp1>,
+    /// When the last request finished, for `--keepalive-idle-timeout`
+    idle_since: std::time::Instant,
+    scenario: ScenarioState,
+    websocket: Option<WebSocketConnection>,
+    /// The kept-alive connection of `--raw-request`
+    raw_stream: Option<Stream>,
+    /// The request and the response of `--raw-request`, reused by the next ones
+    raw_buffers: (Vec<u8>, bytes::BytesMut),
+    /// The response body kept by hyper requests, reused by the next ones
+    body_buffer: bytes::BytesMut,
+    /// Don't send the next request until then for `--respect-retry-after`
+    retry_after: Option<std::time::Instant>,
+    /// Don't send the next request until then for `--think-time`
+    think_until: Option<std::time::Instant>,
+    /// Requests sent on the current connection, for `--requests-per-connection`
+    requests_on_connection: usize,
+    /// Send the prebuilt request. A worker which took a prewarmed connection uses hyper.
+    prebuilt: bool,
 }
 
-impl Default for ClientStateHttp1 {
-    fn default() -> Self {
+impl ClientStateHttp1 {
+    fn new(client: &Client, mut rng: StdRng) -> Self {
+        let send_request = client.take_prewarmed_http1();
         Self {
-            rng: StdRng::from_entropy(),
-            send_request: None,
+            cache_bust: client.cache_bust(&mut rng),
+            rng,
+            prebuilt: send_request.is_none() && client.prebuilt().is_some(),
+            send_request,
+            idle_since: std::time::Instant::now(),
+            scenario: Default::default(),
+            websocket: None,
+            raw_stream: None,
+            raw_buffers: Default::default(),
+            body_buffer: Default::default(),
+            retry_after: None,
+            think_until: None,
+            requests_on_connection: 0,
+        }
+    }
+
+    /// Count a request sent on the connection for `--requests-per-connection`
+    fn count_request(&mut self, new_connection: bool) {
+        self.requests_on_connection = if new_connection {
+            1
+        } else {
+            self.requests_on_connection + 1
+        };
+    }
+
+    /// Close the WebSocket connection when the worker finishes.
+    /// Close the connection, with a close frame on WebSocket
+    async fn close(&mut self) {
+        if let Some(websocket) = self.websocket.take() {
+            websocket.close().await;
         }
+        self.send_request = None;
+        self.raw_stream = None;
     }
 }
 
 struct ClientStateHttp2 {
     rng: StdRng,
-    send_request: SendRequestHttp2,
+    /// The values of `--cache-bust`
+    cache_bust: Option<url_generator::CacheBust>,
+    connection: Arc<Http2Connection>,
+    /// The connection of all forks. It's replaced when the server closes it with GOAWAY.
+    shared_connection: Arc<tokio::sync::Mutex<Arc<Http2Connection>>>,
+    scenario: ScenarioState,
+    /// The response body kept for the checks, reused by the next requests
+    body_buffer: bytes::BytesMut,
+    /// Don't send the next request until then for `--respect-retry-after`
+    retry_after: Option<std::time::Instant>,
+    /// Don't send the next request until then for `--think-time`
+    think_until: Option<std::time::Instant>,
 }
 
-impl Clone for ClientStateHttp2 {
-    fn clone(&self) -> Self {
+impl ClientStateHttp2 {
+    /// Another state on the same connection, for a worker with `rng`
+    fn fork(&self, mut rng: StdRng) -> Self {
         Self {
-            rng: StdRng::from_entropy(),
-            send_request: self.send_request.clone(),
+            cache_bust: self
+                .cache_bust
+                .as_ref()
+                .map(|_| url_generator::CacheBust::new(&mut rng)),
+            rng,
+            connection: self.connection.clone(),
+            shared_connection: self.shared_connection.clone(),
+            scenario: Default::default(),
+            body_buffer: Default::default(),
+            retry_after: None,
+            think_until: None,
         }
     }
+
+    /// Switch to a new connection after the server closed the current one.
+    /// Only the first fork to notice connects, and the others take its connection.
+    async fn reconnect(&mut self, client: &Client) -> Result<Option<ConnectionTime>, ClientError> {
+        let mut shared_connection = self.shared_connection.lock().await;
+        let connection_time = if shared_connection.id == self.connection.id {
+            let url = client.url_generator.generate(&mut self.rng)?;
+            let (connection_time, connection) = client.connect_http2(&url, &mut self.rng).await?;
+            *shared_connection = connection;
+            Some(connection_time)
+        } else {
+            None
+        };
+        self.connection = shared_connection.clone();
+        Ok(connection_time)
+    }
 }
 
 pub enum QueryLimit {
-    Qps(usize),
+    Qps(Rate),
     Burst(std::time::Duration, usize),
+    /// `--load-profile`, which always runs with a dead line
+    Profile(Arc<LoadProfile>),
+}
+
+/// A rate in queries per second
+#[derive(Clone, Copy, Debug)]
+pub enum Rate {
+    Fixed(f64),
+    /// `--qps-ramp`
+    Ramp(QpsRamp),
+}
+
+impl Rate {
+    /// When the `i`th request is sent. Computed from `start` rather than the previous request
+    /// so that rounding errors don't accumulate.
+    fn schedule(&self, start: std::time::Instant, i: usize) -> std::time::Instant {
+        match self {
+            // Rounded to the nanosecond so that e.g. the 100th request of `-q 100/h` is due exactly
+            // at the end of the hour
+            Rate::Fixed(qps) => {
+                start + std::time::Duration::from_nanos((i as f64 * 1e9 / qps).round() as u64)
+            }
+            Rate::Ramp(ramp) => start + ramp.offset(i),
+        }
+    }
+
+    fn max(&self) -> f64 {
+        match self {
+            Rate::Fixed(qps) => *qps,
+            Rate::Ramp(ramp) => ramp.max_rate(),
+        }
+    }
+}
+
+/// How the requests of `-q` and `--arrival-rate` are spread in time
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArrivalDistribution {
+    /// At a constant interval
+    #[default]
+    Uniform,
+    /// At exponentially distributed intervals, like requests from many independent clients
+    Poisson,
+}
+
+impl ArrivalDistribution {
+    /// The name printed in the summary
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Uniform => "uniform",
+            Self::Poisson => "poisson",
+        }
+    }
+}
+
+/// The times to send the requests at a rate, one after another
+struct Arrivals {
+    rate: Rate,
+    start: std::time::Instant,
+    /// The index of the next request
+    i: usize,
+    /// Set for `--arrival-distribution poisson`
+    poisson: Option<StdRng>,
+    /// Send the requests due in each second together at its start for `--qps-burst`
+    tick: bool,
+    /// The schedule moves by the time paused
+    pause: Option<Arc<Pause>>,
+    /// The rate of `--target-p99`, which changes as the run goes
+    target_p99: Option<Arc<TargetP99>>,
+    /// Seconds from `start` to the last Poisson arrival, or to the last arrival at the rate of
+    /// `--target-p99`
+    offset: f64,
+}
+
+impl Arrivals {
+    /// Sleep until `at`, then while the traffic is paused but not past `dead_line`. Returns when
+    /// the request is due, moved by the time paused along with the rest of the schedule so that
+    /// the requests due in the pause aren't sent in a burst on resume.
+    async fn wait(
+        &mut self,
+        at: std::time::Instant,
+        dead_line: Option<std::time::Instant>,
+    ) -> std::time::Instant {
+        tokio::time::sleep_until(at.into()).await;
+        match &self.pause {
+            Some(pause) if pause.is_paused() => {
+                pause.wait_resumed(dead_line).await;
+                let paused = at.elapsed();
+                self.start += paused;
+                at + paused
+            }
+            _ => at,
+        }
+    }
+
+    fn next(&mut self) -> std::time::Instant {
+        if let Some(target_p99) = &self.target_p99 {
+            // Each interval is at the rate of the moment, since it can't be computed from the start
+            let qps = target_p99.qps();
+            let at = match &mut self.poisson {
+                Some(rng) => {
+                    self.offset += -(1.0 - rng.gen::<f64>()).ln() / qps;
+                    self.offset
+                }
+                None => {
+                    let at = self.offset;
+                    self.offset += 1.0 / qps;
+                    at
+                }
+            };
+            return self.start + std::time::Duration::from_secs_f64(at);
+        }
+        match &mut self.poisson {
+            Some(rng) => {
+                // Inverse transform sampling of the exponential distribution with mean 1 / rate
+                self.offset += -(1.0 - rng.gen::<f64>()).ln() / self.rate.max();
+                self.start + std::time::Duration::from_secs_f64(self.offset)
+            }
+            None => {
+                let at = self.rate.schedule(self.start, self.i);
+                self.i += 1;
+                if self.tick {
+                    self.start + std::time::Duration::from_secs((at - self.start).as_secs())
+                } else {
+                    at
+                }
+            }
+        }
+    }
 }
 
 // To avoid dynamic dispatch
 // I'm not sure how much this is effective
+#[allow(clippy::large_enum_variant)]
 enum Stream {
     Tcp(TcpStream),
-    #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
-    Tls(tokio_native_tls::TlsStream<TcpStream>),
-    #[cfg(feature = "rustls")]
-    Tls(tokio_rustls::client::TlsStream<TcpStream>),
+    Tls(<tls::Connector as TlsConnector>::Stream),
     #[cfg(unix)]
     Unix(tokio::net::UnixStream),
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    Uring(UringStream),
 }
 
 impl Stream {
@@ -221,44 +1184,92 @@ impl Stream {
             Stream::Tcp(stream) => {
                 let (send_request, conn) =
                     hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
-                tokio::spawn(conn);
+                // Upgrades are for the WebSocket handshake
+                tokio::spawn(conn.with_upgrades());
                 Ok(send_request)
             }
             Stream::Tls(stream) => {
                 let (send_request, conn) =
                     hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
-                tokio::spawn(conn);
+                // Upgrades are for the WebSocket handshake
+                tokio::spawn(conn.with_upgrades());
                 Ok(send_request)
             }
             #[cfg(unix)]
             Stream::Unix(stream) => {
                 let (send_request, conn) =
                     hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
-                tokio::spawn(conn);
+                // Upgrades are for the WebSocket handshake
+                tokio::spawn(conn.with_upgrades());
+                Ok(send_request)
+            }
+            #[cfg(all(feature = "uring", target_os = "linux"))]
+            Stream::Uring(stream) => {
+                let (send_request, conn) =
+                    hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+                // Upgrades are for the WebSocket handshake
+                tokio::spawn(conn.with_upgrades());
                 Ok(send_request)
             }
         }
     }
-    async fn handshake_http2(self) -> Result<SendRequestHttp2, ClientError> {
-        let builder = hyper::client::conn::http2::Builder::new(TokioExecutor::new());
+    async fn handshake_http2(self, client: &Client) -> Result<Arc<Http2Connection>, ClientError> {
+        let mut builder = hyper::client::conn::http2::Builder::new(TokioExecutor::new());
+        builder
+            .initial_stream_window_size(client.http2_settings.initial_window_size)
+            .max_frame_size(client.http2_settings.max_frame_size);
 
         match self {
             Stream::Tcp(stream) => {
                 let (send_request, conn) = builder.handshake(TokioIo::new(stream)).await?;
-                tokio::spawn(conn);
-                Ok(send_request)
+                Ok(client.spawn_http2(send_request, conn))
             }
             Stream::Tls(stream) => {
                 let (send_request, conn) = builder.handshake(TokioIo::new(stream)).await?;
-                tokio::spawn(conn);
-                Ok(send_request)
+                Ok(client.spawn_http2(send_request, conn))
             }
             #[cfg(unix)]
             Stream::Unix(stream) => {
                 let (send_request, conn) = builder.handshake(TokioIo::new(stream)).await?;
-                tokio::spawn(conn);
-                Ok(send_request)
+                Ok(client.spawn_http2(send_request, conn))
             }
+            #[cfg(all(feature = "uring", target_os = "linux"))]
+            Stream::Uring(stream) => {
+                let (send_request, conn) = builder.handshake(TokioIo::new(stream)).await?;
+                Ok(client.spawn_http2(send_request, conn))
+            }
+        }
+    }
+
+    /// Whether the server closed the kept-alive connection, see `raw_request::is_closed`
+    fn is_closed(&mut self, buf: &mut bytes::BytesMut) -> bool {
+        match self {
+            Stream::Tcp(stream) => raw_request::is_closed(stream, buf),
+            Stream::Tls(stream) => raw_request::is_closed(stream, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => raw_request::is_closed(stream, buf),
+            #[cfg(all(feature = "uring", target_os = "linux"))]
+            Stream::Uring(stream) => stream.is_closed(),
+        }
+    }
+
+    async fn send_raw(
+        &mut self,
+        raw_request: &RawRequest,
+        request: &[u8],
+        buf: &mut bytes::BytesMut,
+        options: ReadOptions,
+    ) -> Result<RawResponse, RawRequestError> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Stream::Tcp(stream) => raw_request.send_tcp(stream, request, buf, options).await,
+            #[cfg(not(target_os = "linux"))]
+            Stream::Tcp(stream) => raw_request.send(stream, request, buf, options).await,
+            Stream::Tls(stream) => raw_request.send(stream, request, buf, options).await,
+            #[cfg(unix)]
+            Stream::Unix(stream) => raw_request.send(stream, request, buf, options).await,
+            #[cfg(all(feature = "uring", target_os = "linux"))]
+            Stream::Uring(stream) => raw_request.send(stream, request, buf, options).await,
         }
     }
 }
@@ -268,478 +1279,1963 @@ impl Client {
         self.http_version == http::Version::HTTP_2
     }
 
-    /// Perform a DNS lookup to cache it
-    /// This is useful to avoid DNS lookup latency at the first concurrent requests
-    pub async fn pre_lookup(&self) -> Result<(), ClientError> {
-        let mut rng = StdRng::from_entropy();
-        let url = self.url_generator.generate(&mut rng)?;
+    /// Wait for the turn of the `worker`th worker of `--ramp-up`. Returns false when it shouldn't start.
+    async fn ramp_up_wait(&self, worker: usize, dead_line: Option<std::time::Instant>) -> bool {
+        match &self.ramp_up {
+            Some(ramp_up) => self
+                .unless_stopped(ramp_up.wait(worker, dead_line))
+                .await
+                .unwrap_or(false),
+            None => true,
+        }
+    }
 
-        // It automatically caches the result
-        self.dns.lookup(&url, &mut rng).await?;
-        Ok(())
+    /// Held by a started worker so that the waiting ones don't start once it's done
+    fn ramp_up_finish(&self) -> Option<FinishGuard<'_>> {
+        self.ramp_up.as_ref().map(|ramp_up| ramp_up.finish_guard())
     }
 
-    async fn client(
-        &self,
-        addr: (std::net::IpAddr, u16),
-        url: &Url,
-    ) -> Result<Stream, ClientError> {
-        // TODO: Allow the connect timeout to be configured
-        let timeout_duration = tokio::time::Duration::from_secs(5);
+    /// The `--cache-bust` values of a worker with `rng`
+    fn cache_bust<R: Rng>(&self, rng: &mut R) -> Option<url_generator::CacheBust> {
+        self.cache_bust
+            .as_ref()
+            .map(|_| url_generator::CacheBust::new(rng))
+    }
 
-        if url.scheme() == "https" {
-            // If we do not put a timeout here then the connections attempts will
-            // linger long past the configured timeout
-            let stream = tokio::time::timeout(timeout_duration, self.tls_client(addr, url)).await;
-            return match stream {
-                Ok(Ok(stream)) => Ok(stream),
-                Ok(Err(err)) => Err(err),
-                Err(_) => Err(ClientError::Timeout),
-            };
+    /// Generate the URL of the next request.
+    fn generate_url<R: Rng>(
+        &self,
+        rng: &mut R,
+        cache_bust: &mut Option<url_generator::CacheBust>,
+    ) -> Result<Cow<'_, Url>, ClientError> {
+        let url = self.url_generator.generate(rng)?;
+        match (&self.cache_bust, cache_bust) {
+            (Some(name), Some(cache_bust)) => {
+                let mut url = url.into_owned();
+                cache_bust.append(&mut url, name);
+                Ok(Cow::Owned(url))
+            }
+            _ => Ok(url),
         }
-        #[cfg(unix)]
-        if let Some(socket_path) = &self.unix_socket {
-            let stream = tokio::time::timeout(
+    }
+
+    /// The URL of the next request, picked from `--urls-from-file` when it's given. The permit
+    /// holds a slot of the URL's `max_concurrent` until it's dropped.
+    async fn next_url<R: Rng>(
+        &self,
+        rng: &mut R,
+        cache_bust: &mut Option<url_generator::CacheBust>,
+    ) -> Result<(Cow<'_, Url>, Option<UrlPermit<'_>>), ClientError> {
+        let Some(url_list) = &self.url_list else {
+            return Ok((self.generate_url(rng, cache_bust)?, None));
+        };
+        let permit = url_list.acquire(rng).await;
+        let url = match (&self.cache_bust, cache_bust) {
+            (Some(name), Some(cache_bust)) => {
+                let mut url = permit.url().clone();
+                cache_bust.append(&mut url, name);
+                Cow::Owned(url)
+            }
+            _ => Cow::Borrowed(permit.url()),
+        };
+        Ok((url, Some(permit)))
+    }
+
+    /// Establish `n` connections before the measurement starts, sending an untimed request
+    /// with `method` on each of them if it's given. Workers take them instead of connecting.
+    /// Returns the number of connections established.
+    pub async fn prewarm(&self, n: usize, method: Option<&http::Method>) -> usize {
+        let connections =
+            futures::future::join_all((0..n).map(|i| self.prewarm_connection(i as u64, method)))
+                .await;
+        let mut prewarmed = self.prewarmed.lock().unwrap();
+        prewarmed.extend(connections.into_iter().flatten());
+        prewarmed.len()
+    }
+
+    async fn prewarm_connection(
+        &self,
+        stream: u64,
+        method: Option<&http::Method>,
+    ) -> Result<PrewarmedConnection, ClientError> {
+        // Don't use `rng()` not to shift the generators of the workers
+        let mut rng = seeded_rng(self.seed, PREWARM_RNG_STREAM - stream);
+        let url = self.url_generator.generate(&mut rng)?;
+        if self.is_http2() {
+            let (_, connection) = self.connect_http2(&url, &mut rng).await?;
+            if let Some(method) = method {
+                let res = connection
+                    .send_request
+                    .clone()
+                    .send_request(self.prewarm_request(&url, method)?)
+                    .await?;
+                let (parts, stream) = res.into_parts();
+                // h2 resets the stream of a HEAD response with Content-Length,
+                // but the connection is still usable
+                let _ = ResponseBody::read(
+                    &parts.headers,
+                    stream,
+                    Default::default(),
+                    0,
+                    None,
+                    false,
+                    None,
+                )
+                .await;
+            }
+            Ok(PrewarmedConnection::Http2(connection))
+        } else {
+            let addr = self.dns.lookup(&url, &mut rng).await?;
+            let (mut send_request, _) = self.client_http1(addr, &url).await?;
+            if let Some(method) = method {
+                let res = send_request
+                    .send_request(self.prewarm_request(&url, method)?)
+                    .await?;
+                let (parts, stream) = res.into_parts();
+                ResponseBody::read(
+                    &parts.headers,
+                    stream,
+                    Default::default(),
+                    0,
+                    None,
+                    false,
+                    None,
+                )
+                .await?;
+                if !is_keep_alive(method, &parts) {
+                    send_request = self.client_http1(addr, &url).await?.0;
+                }
+            }
+            Ok(PrewarmedConnection::Http1(send_request))
+        }
+    }
+
+    /// A request without the body for `prewarm`
+    fn prewarm_request(
+        &self,
+        url: &Url,
+        method: &http::Method,
+    ) -> Result<http::Request<Body>, ClientError> {
+        let mut builder = http::Request::builder()
+            .uri(self.request_uri(url))
+            .method(method.clone())
+            .version(self.http_version);
+
+        let headers = builder
+            .headers_mut()
+            .ok_or(ClientError::GetHeaderFromBuilderError)?;
+        *headers = self.request_headers().bodyless.clone();
+
+        Ok(builder.body(Body::empty())?)
+    }
+
+    fn take_prewarmed_http1(&self) -> Option<SendRequestHttp1> {
+        let mut prewarmed = self.prewarmed.lock().unwrap();
+        match prewarmed.pop()? {
+            PrewarmedConnection::Http1(send_request) => Some(send_request),
+            connection => {
+                prewarmed.push(connection);
+                None
+            }
+        }
+    }
+
+    fn take_prewarmed_http2(&self) -> Option<Arc<Http2Connection>> {
+        let mut prewarmed = self.prewarmed.lock().unwrap();
+        match prewarmed.pop()? {
+            PrewarmedConnection::Http2(connection) => Some(connection),
+            connection => {
+                prewarmed.push(connection);
+                None
+            }
+        }
+    }
+
+    /// The times to send the requests at `rate` from `start`
+    fn arrivals(&self, rate: Rate, start: std::time::Instant) -> Arrivals {
+        Arrivals {
+            rate,
+            start,
+            i: 0,
+            poisson: (self.arrival_distribution == ArrivalDistribution::Poisson)
+                .then(|| seeded_rng(self.seed, ARRIVALS_RNG_STREAM)),
+            tick: self.qps_burst,
+            pause: self.pause.clone(),
+            target_p99: self.target_p99.clone(),
+            offset: 0.0,
+        }
+    }
+
+    /// The random number generator of a worker
+    fn rng(&self, worker: WorkerRng) -> StdRng {
+        seeded_rng(self.seed, worker.stream())
+    }
+
+    /// Perform a DNS lookup to cache it
+    /// This is useful to avoid DNS lookup latency at the first concurrent requests
+    pub async fn pre_lookup(&self) -> Result<(), ClientError> {
+        let mut rng = seeded_rng(self.seed, PRE_LOOKUP_RNG_STREAM);
+        let url = self.url_generator.generate(&mut rng)?;
+
+        // It automatically caches the result
+        self.dns.lookup(&url, &mut rng).await?;
+        Ok(())
+    }
+
+    /// Connect to the server after taking a token of `--connect-rate`. Returns the stream and
+    /// how long its TLS handshake took.
+    async fn client(
+        &self,
+        addr: Addrs,
+        url: &Url,
+    ) -> Result<(Stream, Option<TlsHandshake>), ClientError> {
+        if let Some(connect_rate) = &self.connect_rate {
+            connect_rate.acquire().await;
+        }
+        let res = self.connect(addr, url).await;
+        if let (Ok(_), Some(startup_connects)) = (&res, &self.startup_connects) {
+            startup_connects.established();
+        }
+        res
+    }
+
+    async fn connect(
+        &self,
+        addr: Addrs,
+        url: &Url,
+    ) -> Result<(Stream, Option<TlsHandshake>), ClientError> {
+        // TODO: Allow the connect timeout to be configured
+        let timeout_duration = tokio::time::Duration::from_secs(5);
+
+        if matches!(url.scheme(), "https" | "wss") {
+            // Only the first URL is checked before the run
+            #[cfg(all(feature = "uring", target_os = "linux"))]
+            if self.uring_threads.is_some() {
+                return Err(ClientError::IoError(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "--io-backend uring doesn't support TLS",
+                )));
+            }
+            // If we do not put a timeout here then the connections attempts will
+            // linger long past the configured timeout
+            let stream = tokio::time::timeout(timeout_duration, async {
+                let stream = self.connect_tcp(addr).await.map_err(ClientError::connect)?;
+                self.configure_tcp(&stream)?;
+                let tls_start = std::time::Instant::now();
+                let (stream, resumed) = self.tls.connect(stream, url).await?;
+                let tls_handshake = TlsHandshake {
+                    duration: tls_start.elapsed(),
+                    resumed,
+                };
+                Ok::<_, ClientError>((Stream::Tls(stream), Some(tls_handshake)))
+            })
+            .await;
+            return match stream {
+                Ok(Ok(stream)) => Ok(stream),
+                Ok(Err(err)) => Err(err),
+                Err(_) => Err(ClientError::ConnectTimeout),
+            };
+        }
+        #[cfg(unix)]
+        if let Some(socket_path) = &self.unix_socket {
+            let stream = tokio::time::timeout(
                 timeout_duration,
                 tokio::net::UnixStream::connect(socket_path),
             )
             .await;
             return match stream {
-                Ok(Ok(stream)) => Ok(Stream::Unix(stream)),
-                Ok(Err(err)) => Err(ClientError::IoError(err)),
-                Err(_) => Err(ClientError::Timeout),
+                Ok(Ok(stream)) => Ok((Stream::Unix(stream), None)),
+                Ok(Err(err)) => Err(ClientError::connect(err)),
+                Err(_) => Err(ClientError::ConnectTimeout),
             };
         }
-        let stream =
-            tokio::time::timeout(timeout_duration, tokio::net::TcpStream::connect(addr)).await;
+        #[cfg(all(feature = "uring", target_os = "linux"))]
+        if self.uring_threads.is_some() {
+            let stream = tokio::time::timeout(timeout_duration, self.connect_uring(addr)).await;
+            return match stream {
+                Ok(Ok(stream)) => Ok((Stream::Uring(stream), None)),
+                Ok(Err(err)) => Err(ClientError::connect(err)),
+                Err(_) => Err(ClientError::ConnectTimeout),
+            };
+        }
+        let stream = tokio::time::timeout(timeout_duration, self.connect_tcp(addr)).await;
         match stream {
             Ok(Ok(stream)) => {
-                stream.set_nodelay(true)?;
-                Ok(Stream::Tcp(stream))
+                self.configure_tcp(&stream)?;
+                Ok((Stream::Tcp(stream), None))
             }
-            Ok(Err(err)) => Err(ClientError::IoError(err)),
-            Err(_) => Err(ClientError::Timeout),
+            Ok(Err(err)) => Err(ClientError::connect(err)),
+            Err(_) => Err(ClientError::ConnectTimeout),
         }
     }
 
-    #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
-    async fn tls_client(
+    /// Connect to the primary address, and race the fallback address against it when it
+    /// doesn't connect in `HAPPY_EYEBALLS_DELAY` (Happy Eyeballs, RFC 8305).
+    /// The first connection established wins and the other attempt is dropped.
+    async fn connect_tcp(&self, addr: Addrs) -> std::io::Result<TcpStream> {
+        let primary = TcpStream::connect((addr.primary, addr.port));
+        let stream = match addr.fallback {
+            None => primary.await?,
+            Some(fallback) => {
+                tokio::pin!(primary);
+                let early = tokio::select! {
+                    result = &mut primary => Some(result),
+                    _ = tokio::time::sleep(HAPPY_EYEBALLS_DELAY) => None,
+                };
+                match early {
+                    Some(Ok(stream)) => stream,
+                    // Don't wait for the delay when the primary address fails fast
+                    Some(Err(_)) => TcpStream::connect((fallback, addr.port)).await?,
+                    None => {
+                        let fallback = TcpStream::connect((fallback, addr.port));
+                        tokio::pin!(fallback);
+                        let (result, other) = tokio::select! {
+                            result = &mut primary => (result, fallback),
+                            result = &mut fallback => (result, primary),
+                        };
+                        match result {
+                            Ok(stream) => stream,
+                            Err(_) => other.await?,
+                        }
+                    }
+                }
+            }
+        };
+
+        let counter = if stream.peer_addr()?.is_ipv6() {
+            &self.ip_families.ipv6
+        } else {
+            &self.ip_families.ipv4
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        Ok(stream)
+    }
+
+    /// Connect to the primary address through the io_uring of this thread, without Happy
+    /// Eyeballs
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    async fn connect_uring(&self, addr: Addrs) -> std::io::Result<UringStream> {
+        let stream = UringStream::connect((addr.primary, addr.port).into()).await?;
+        if let Some(idle) = self.tcp_keepalive {
+            stream.socket().set_tcp_keepalive(&tcp_keepalive(idle))?;
+        }
+        let counter = if addr.primary.is_ipv6() {
+            &self.ip_families.ipv6
+        } else {
+            &self.ip_families.ipv4
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        Ok(stream)
+    }
+
+    /// Set the options of a TCP socket the client creates.
+    fn configure_tcp(&self, stream: &TcpStream) -> std::io::Result<()> {
+        stream.set_nodelay(true)?;
+        if let Some(idle) = self.tcp_keepalive {
+            socket2::SockRef::from(stream).set_tcp_keepalive(&tcp_keepalive(idle))?;
+        }
+        Ok(())
+    }
+
+    async fn client_http1(
         &self,
-        addr: (std::net::IpAddr, u16),
+        addr: Addrs,
         url: &Url,
-    ) -> Result<Stream, ClientError> {
-        let stream = tokio::net::TcpStream::connect(addr).await?;
-        stream.set_nodelay(true)?;
+    ) -> Result<(SendRequestHttp1, Option<TlsHandshake>), ClientError> {
+        let (stream, tls_handshake) = self.client(addr, url).await?;
+        Ok((stream.handshake_http1().await?, tls_handshake))
+    }
 
-        let mut connector_builder = native_tls::TlsConnector::builder();
-        if self.insecure {
-            connector_builder
-                .danger_accept_invalid_certs(true)
-                .danger_accept_invalid_hostnames(true);
+    /// Pick the method of the next request.
+    fn choose_method<R: Rng>(&self, rng: &mut R) -> http::Method {
+        match &self.method_mix {
+            Some(method_mix) => method_mix.sample(rng).clone(),
+            None => self.method.clone(),
         }
+    }
 
-        if self.is_http2() {
-            connector_builder.request_alpns(&["h2"]);
+    /// The body to send with `method`. Bodyless methods in `--method-mix` are sent without it.
+    fn body_for(&self, method: &http::Method) -> Option<&BodySource> {
+        if self.method_mix.is_some() && method_mix::is_bodyless(method) {
+            None
+        } else {
+            self.body.as_ref()
         }
+    }
 
-        let connector = tokio_native_tls::TlsConnector::from(connector_builder.build()?);
-        let stream = connector
-            .connect(url.host_str().ok_or(ClientError::HostNotFound)?, stream)
-            .await?;
+    fn request_uri<'a>(&self, url: &'a Url) -> &'a str {
+        if self.is_http2() || self.absolute_form {
+            &url[..]
+        } else {
+            &url[url::Position::BeforePath..]
+        }
+    }
 
-        Ok(Stream::Tls(stream))
+    /// The request target of `url`. The one of the first URL is parsed only once.
+    fn uri(&self, url: &Url) -> Result<http::Uri, ClientError> {
+        match self.uri_cache.get() {
+            Some((cached, uri)) if cached == url => Ok(uri.clone()),
+            Some(_) => Ok(self.request_uri(url).parse()?),
+            None => {
+                let uri: http::Uri = self.request_uri(url).parse()?;
+                let _ = self.uri_cache.set((url.clone(), uri.clone()));
+                Ok(uri)
+            }
+        }
     }
 
-    #[cfg(feature = "rustls")]
-    async fn tls_client(
+    /// The request which is the same each time, encoded once and sent like `--raw-request`
+    /// with `--prebuilt-requests`. None when anything varies between the requests, or when
+    /// hyper has to read the response since more than its status, size and Location is needed.
+    fn prebuilt(&self) -> Option<&RawRequest> {
+        self.prebuilt
+            .get_or_init(|| {
+                if !self.prebuilt_requests {
+                    return None;
+                }
+                let UrlGenerator::Static(url) = &self.url_generator else {
+                    return None;
+                };
+                let body = self.prebuilt_body().ok()?;
+                let varies = self.cache_bust.is_some()
+                    || self.url_list.is_some()
+                    || self.method_mix.is_some()
+                    || self.scenario.is_some()
+                    || self.raw_request.is_some()
+                    || self.websocket.is_some();
+                let needs_hyper = self.http_version != http::Version::HTTP_11
+                    || self.chunk_size.is_some()
+                    || self.expect_continue_timeout.is_some()
+                    || self.informational_stats
+                    || self.body_assertion.is_some()
+                    || self.save_responses.is_some()
+                    || self.respect_retry_after
+                    || self.keepalive_idle_timeout.is_some();
+                (!varies && !needs_hyper).then(|| {
+                    RawRequest::prebuilt(
+                        self.method.clone(),
+                        self.request_uri(url),
+                        &self.headers,
+                        body,
+                    )
+                })
+            })
+            .as_ref()
+    }
+
+    /// The body of a prebuilt request. Err when it isn't the same each time.
+    fn prebuilt_body(&self) -> Result<Option<PrebuiltBody>, ()> {
+        match &self.body {
+            Some(BodySource::Static(body)) => Ok(Some(PrebuiltBody::Bytes(body.clone()))),
+            Some(BodySource::File(file)) => Ok(Some(PrebuiltBody::File(file.clone()))),
+            Some(_) => Err(()),
+            None => Ok(None),
+        }
+    }
+
+    fn read_options(&self) -> ReadOptions {
+        ReadOptions {
+            read_timeout: self.read_timeout,
+            max_len: self.max_body_bytes,
+            decode: self.decompressed_size,
+        }
+    }
+
+    /// The request to `url`, built without `http::request::Builder` since it's sent for every
+    /// request
+    fn request(
         &self,
-        addr: (std::net::IpAddr, u16),
         url: &Url,
-    ) -> Result<Stream, ClientError> {
-        let stream = tokio::net::TcpStream::connect(addr).await?;
-        stream.set_nodelay(true)?;
+        method: &http::Method,
+    ) -> Result<http::Request<Body>, ClientError> {
+        let body = self.body_for(method);
+        let mut request = http::Request::new(match body {
+            Some(body) => Body::new(body, self.chunk_size)?,
+            None => Body::empty(),
+        });
+        *request.method_mut() = method.clone();
+        *request.uri_mut() = self.uri(url)?;
+        *request.version_mut() = self.http_version;
+
+        let headers = self.request_headers();
+        *request.headers_mut() = if body.is_none() && self.body.is_some() {
+            headers.bodyless.clone()
+        } else {
+            headers.body.clone()
+        };
+        Ok(request)
+    }
+
+    fn request_headers(&self) -> &RequestHeaders {
+        self.request_headers.get_or_init(|| {
+            let mut body = self.headers.clone();
+            if self.body.is_some()
+                && self.chunk_size.is_some()
+                && self.http_version == http::Version::HTTP_11
+            {
+                // hyper assumes no body for GET without this
+                body.append(
+                    http::header::TRANSFER_ENCODING,
+                    http::HeaderValue::from_static("chunked"),
+                );
+            }
+            let mut bodyless = self.headers.clone();
+            bodyless.remove(http::header::CONTENT_TYPE);
+            bodyless.remove(http::header::CONTENT_ENCODING);
+            RequestHeaders { body, bodyless }
+        })
+    }
+
+    /// The URL of the current scenario step
+    fn step_url<R: Rng>(
+        &self,
+        step: &Step,
+        state: &ScenarioState,
+        rng: &mut R,
+        cache_bust: &mut Option<url_generator::CacheBust>,
+    ) -> Result<Url, ClientError> {
+        let mut url = self
+            .generate_url(rng, cache_bust)?
+            .join(&step.path.render(&state.vars))?;
+        url_generator::normalize(&mut url);
+        Ok(url)
+    }
 
-        let mut root_cert_store = rustls::RootCertStore::empty();
-        for cert in rustls_native_certs::load_native_certs()? {
-            root_cert_store.add(cert).ok(); // ignore error
+    fn step_request(
+        &self,
+        step: &Step,
+        state: &ScenarioState,
+        url: &Url,
+    ) -> Result<http::Request<Body>, ClientError> {
+        let mut builder = http::Request::builder()
+            .uri(self.request_uri(url))
+            .method(step.method.clone())
+            .version(self.http_version);
+
+        let headers = builder
+            .headers_mut()
+            .ok_or(ClientError::GetHeaderFromBuilderError)?;
+        *headers = self.headers.clone();
+        for (name, value) in &step.headers {
+            headers.insert(
+                name.clone(),
+                http::HeaderValue::from_str(&value.render(&state.vars))?,
+            );
         }
-        let mut config = rustls::ClientConfig::builder()
-            .with_root_certificates(root_cert_store)
-            .with_no_client_auth();
-        if self.insecure {
-            config
-                .dangerous()
-                .set_certificate_verifier(Arc::new(AcceptAnyServerCert));
+
+        let body = step
+            .body
+            .as_ref()
+            .map(|body| bytes::Bytes::from(body.render(&state.vars)));
+        Ok(builder.body(Body::Full(body))?)
+    }
+
+    /// The label of the step of a strict `--scenario`
+    fn step_label(&self, step: Option<&Step>) -> Option<Label> {
+        step.filter(|_| self.scenario.as_ref().is_some_and(|s| s.strict))
+            .map(|step| self.labels.intern(&step.name))
+    }
+
+    /// The label of the path and query of `url` for `--per-url-stats`
+    fn url_label(&self, url: &Url) -> Option<Label> {
+        self.record_url
+            .then(|| self.labels.intern(&url[url::Position::BeforePath..]))
+    }
+
+    fn is_unexpected_status(&self, status: http::StatusCode) -> bool {
+        self.expect_status
+            .as_ref()
+            .is_some_and(|expect| !expect.matches(status))
+    }
+
+    /// How many bytes of the response body are kept for the scenario step, `body_assertion`
+    /// and `save_responses`.
+    fn body_buffer_limit(&self, step: Option<&Step>) -> usize {
+        if step.is_some_and(|s| !s.extract.is_empty())
+            || self
+                .save_responses
+                .as_ref()
+                .is_some_and(|s| s.is_capturing())
+        {
+            usize::MAX
+        } else {
+            self.body_assertion.as_ref().map_or(0, |a| a.max_bytes)
         }
-        if self.is_http2() {
-            config.alpn_protocols = vec![b"h2".to_vec()];
+    }
+
+    fn check_body(&self, body: &ResponseBody) -> Result<(), ClientError> {
+        match &self.body_assertion {
+            Some(assertion) => Ok(assertion.check(
+                body.headers.get(http::header::CONTENT_ENCODING),
+                &body.buffer,
+                body.buffer_truncated(),
+            )?),
+            None => Ok(()),
         }
-        let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
-        let domain = rustls_pki_types::ServerName::try_from(
-            url.host_str().ok_or(ClientError::HostNotFound)?,
-        )?;
-        let stream = connector.connect(domain.to_owned(), stream).await?;
+    }
 
-        Ok(Stream::Tls(stream))
+    /// Pass the response to `save_responses` with the result of `check_body`.
+    fn save_response(
+        &self,
+        status: http::StatusCode,
+        body: &ResponseBody,
+        checked: &Result<(), ClientError>,
+    ) {
+        if let Some(saver) = &self.save_responses {
+            if saver.wants(status, checked.is_err()) {
+                saver.save(
+                    status,
+                    &body.headers,
+                    &body.buffer,
+                    body.buffer_truncated(),
+                    checked.as_ref().err().map(|e| e.to_string()),
+                );
+            }
+        }
     }
 
-    async fn client_http1(
+    /// Check the response of the current scenario step and go to the next step.
+    fn finish_step(
+        &self,
+        step: &Step,
+        state: &mut ScenarioState,
+        status: http::StatusCode,
+        body: &[u8],
+    ) -> Result<(), ClientError> {
+        let strict = self.scenario.as_ref().is_some_and(|s| s.strict);
+        let result = if strict && (status.is_client_error() || status.is_server_error()) {
+            Err(format!("status {status}"))
+        } else {
+            step.extract(body, &mut state.vars)
+        };
+
+        match (result, &self.scenario) {
+            (Ok(()), Some(scenario)) => {
+                state.advance(scenario);
+                Ok(())
+            }
+            (Ok(()), None) => Ok(()),
+            (Err(reason), _) => {
+                state.abort();
+                Err(ClientError::ScenarioStep(step.name.clone(), reason))
+            }
+        }
+    }
+
+    /// Record 1xx responses of a request into `tracker`.
+    fn track_informational(request: &mut http::Request<Body>, tracker: &Arc<InformationalTracker>) {
+        let tracker = tracker.clone();
+        hyper::ext::on_informational(request, move |res| tracker.record(res.status()));
+    }
+
+    /// Build a HTTP/1.1 request which waits for `100 Continue` before sending the body.
+    /// 1xx responses are recorded into `tracker` too, since a request has only one callback.
+    fn request_expect_continue(
         &self,
-        addr: (std::net::IpAddr, u16),
         url: &Url,
-    ) -> Result<SendRequestHttp1, ClientError> {
-        let stream = self.client(addr, url).await?;
-        stream.handshake_http1().await
+        method: &http::Method,
+        tracker: Option<&Arc<InformationalTracker>>,
+    ) -> Result<(http::Request<Body>, Option<ExpectContinueGate>), ClientError> {
+        let mut request = self.request(url, method)?;
+        let (Some(timeout), Some(_)) = (self.expect_continue_timeout, self.body_for(method)) else {
+            if let Some(tracker) = tracker {
+                Self::track_informational(&mut request, tracker);
+            }
+            return Ok((request, None));
+        };
+
+        request.headers_mut().insert(
+            http::header::EXPECT,
+            http::HeaderValue::from_static("100-continue"),
+        );
+
+        let (tx, rx) = flume::bounded(1);
+        let received = Arc::new(AtomicBool::new(false));
+        let body_sent = Arc::new(AtomicBool::new(false));
+
+        let on_continue = tx.clone();
+        let on_continue_received = received.clone();
+        let tracker = tracker.cloned();
+        hyper::ext::on_informational(&mut request, move |res| {
+            if let Some(tracker) = &tracker {
+                tracker.record(res.status());
+            }
+            if res.status() == http::StatusCode::CONTINUE {
+                on_continue_received.store(true, Ordering::Relaxed);
+                let _ = on_continue.try_send(true);
+            }
+        });
+
+        let gate_body_sent = body_sent.clone();
+        let gate = async move {
+            // Send the body anyway on timeout as curl does
+            let send = tokio::time::timeout(timeout, rx.recv_async())
+                .await
+                .unwrap_or(Ok(true))
+                .unwrap_or(false);
+            gate_body_sent.store(send, Ordering::Relaxed);
+            send
+        }
+        .boxed();
+
+        let request = request.map(|inner| Body::Gated {
+            gate,
+            inner: Box::new(inner),
+        });
+        Ok((
+            request,
+            Some(ExpectContinueGate {
+                tx,
+                received,
+                body_sent,
+            }),
+        ))
     }
 
-    fn request(&self, url: &Url) -> Result<http::Request<Full<&'static [u8]>>, ClientError> {
-        let mut builder = http::Request::builder()
-            .uri(if self.is_http2() {
-                &url[..]
+    /// Wait for the Retry-After of the previous response, but not past `dead_line`
+    async fn pause_for_retry_after(
+        &self,
+        retry_after: &mut Option<std::time::Instant>,
+        dead_line: Option<std::time::Instant>,
+    ) {
+        if let Some(until) = retry_after.take() {
+            retry_after::pause(&self.retry_after_counters, until, dead_line).await;
+        }
+    }
+
+    /// Run `f` unless the run is aborted first, so that a pausing worker stops right away
+    async fn unless_aborted<T>(&self, f: impl Future<Output = T>) -> Result<T, ClientError> {
+        match &self.abort {
+            Some(abort) => tokio::select! {
+                biased;
+                _ = abort.aborted() => Err(abort.error()),
+                t = f => Ok(t),
+            },
+            None => Ok(f.await),
+        }
+    }
+
+    /// Whether ctrl-c stopped the run, so that no new request starts
+    fn is_stopped(&self) -> bool {
+        self.shutdown.is_stopped()
+    }
+
+    /// Run `f` unless ctrl-c stops the run first, so that a worker waiting for its next request
+    /// stops right away. None once it's stopped.
+    async fn unless_stopped<T>(&self, f: impl Future<Output = T>) -> Option<T> {
+        tokio::select! {
+            biased;
+            _ = self.shutdown.stopped() => None,
+            t = f => Some(t),
+        }
+    }
+
+    /// Wait for the `--think-time` after the previous response, but not past `dead_line`
+    async fn pause_for_think_time(
+        &self,
+        think_until: &mut Option<std::time::Instant>,
+        dead_line: Option<std::time::Instant>,
+    ) {
+        if let (Some(think_time), Some(until)) = (&self.think_time, think_until.take()) {
+            think_time.pause(until, dead_line).await;
+        }
+    }
+
+    /// Wait for the load to resume during an `--off` period, but not past `dead_line`
+    async fn pause_for_duty_cycle(&self, dead_line: Option<std::time::Instant>) {
+        if let Some(duty_cycle) = &self.duty_cycle {
+            duty_cycle.wait_on(dead_line).await;
+        }
+    }
+
+    /// Whether no new request should start because `dead_line` has passed. Without
+    /// `--wait-ongoing-requests-after-deadline` the request is cut by the deadline right away instead.
+    fn is_past_dead_line(&self, dead_line: Option<std::time::Instant>) -> bool {
+        self.wait_ongoing_requests_after_deadline
+            && dead_line.is_some_and(|dead_line| std::time::Instant::now() >= dead_line)
+    }
+
+    /// Wait while the traffic is paused by SIGUSR1, but not past `dead_line`
+    async fn pause_for_signal(&self, dead_line: Option<std::time::Instant>) {
+        if let Some(pause) = &self.pause {
+            pause.wait_resumed(dead_line).await;
+        }
+    }
+
+    /// When the worker may send the next request after `--think-time`
+    fn think_time_until(&self, rng: &mut StdRng) -> Option<std::time::Instant> {
+        let think_time = self.think_time.as_ref()?;
+        Some(std::time::Instant::now() + think_time.sample(rng))
+    }
+
+    /// When the worker may send the next request after a response with Retry-After
+    fn retry_after_until(
+        &self,
+        status: http::StatusCode,
+        headers: &http::HeaderMap,
+    ) -> Option<std::time::Instant> {
+        if !self.respect_retry_after {
+            return None;
+        }
+        let wait = retry_after::retry_after(status, headers, std::time::SystemTime::now())?;
+        self.retry_after_counters
+            .responses
+            .fetch_add(1, Ordering::Relaxed);
+        Some(std::time::Instant::now() + wait)
+    }
+
+    /// Wait for the response with `--read-timeout`
+    async fn read_timeout<T>(&self, response: impl Future<Output = T>) -> Result<T, ClientError> {
+        match self.read_timeout {
+            Some(read_timeout) => tokio::time::timeout(read_timeout, response)
+                .await
+                .map_err(|_| ClientError::ReadTimeout),
+            None => Ok(response.await),
+        }
+    }
+
+    /// `connections_for` with the workers of this client on each connection
+    fn connections_for(
+        &self,
+        n_tasks: u64,
+        n_connections: usize,
+        n_http2_parallel: usize,
+    ) -> usize {
+        connections_for(
+            n_tasks,
+            n_connections,
+            if self.is_http2() { n_http2_parallel } else { 1 },
+        )
+    }
+
+    /// The error of the request at `--timeout` or at the deadline, whichever comes first, or
+    /// when the run is aborted. Nothing is boxed, and the error is made only when one comes.
+    fn timeout_future(
+        &self,
+        dead_line: Option<std::time::Instant>,
+    ) -> impl Future<Output = ClientError> + Send + '_ {
+        let dead_line = dead_line
+            .filter(|_| !self.wait_ongoing_requests_after_deadline)
+            .map(tokio::time::Instant::from);
+        // From now rather than from the first poll, so that the whole request is timed
+        let timeout_at = self
+            .timeout
+            .map(|timeout| tokio::time::Instant::now() + timeout);
+        let expiry: Option<(_, fn() -> ClientError)> = match (dead_line, timeout_at) {
+            (Some(dead_line), Some(timeout_at)) if timeout_at > dead_line => {
+                Some((dead_line, || ClientError::Deadline))
+            }
+            (_, Some(timeout_at)) => Some((timeout_at, || ClientError::Timeout)),
+            (Some(dead_line), None) => Some((dead_line, || ClientError::Deadline)),
+            (None, None) => None,
+        };
+        let timeout = async move {
+            match expiry {
+                Some((at, error)) => {
+                    tokio::time::sleep_until(at).await;
+                    error()
+                }
+                None => std::future::pending().await,
+            }
+        };
+        async move {
+            match &self.abort {
+                Some(abort) => tokio::select! {
+                    biased;
+                    _ = abort.aborted() => abort.error(),
+                    err = timeout => err,
+                },
+                None => timeout.await,
+            }
+        }
+    }
+
+    async fn work_http1(
+        &self,
+        client_state: &mut ClientStateHttp1,
+        dead_line: Option<std::time::Instant>,
+    ) -> Result<RequestResult, ClientError> {
+        self.unless_aborted(async {
+            self.pause_for_retry_after(&mut client_state.retry_after, dead_line)
+                .await;
+            self.pause_for_think_time(&mut client_state.think_until, dead_line)
+                .await;
+            self.pause_for_duty_cycle(dead_line).await;
+            self.pause_for_signal(dead_line).await;
+        })
+        .await?;
+        if self.is_past_dead_line(dead_line) {
+            return Err(ClientError::Deadline);
+        }
+        let res = self.send_http1(client_state, dead_line).await;
+        client_state.think_until = self.think_time_until(&mut client_state.rng);
+        res
+    }
+
+    async fn send_http1(
+        &self,
+        client_state: &mut ClientStateHttp1,
+        dead_line: Option<std::time::Instant>,
+    ) -> Result<RequestResult, ClientError> {
+        if let Some(websocket) = &self.websocket {
+            return self
+                .work_websocket(websocket, client_state, dead_line)
+                .await;
+        }
+        if self
+            .requests_per_connection
+            .is_some_and(|n| client_state.requests_on_connection >= n)
+        {
+            client_state.send_request = None;
+            client_state.raw_stream = None;
+        }
+        if let Some(raw_request) = &self.raw_request {
+            return self.work_raw(raw_request, client_state, dead_line).await;
+        }
+        if let Some(prebuilt) = self.prebuilt().filter(|_| client_state.prebuilt) {
+            return self.work_raw(prebuilt, client_state, dead_line).await;
+        }
+        let timeout = self.timeout_future(dead_line);
+
+        if self
+            .keepalive_idle_timeout
+            .is_some_and(|idle_timeout| client_state.idle_since.elapsed() >= idle_timeout)
+            && client_state.send_request.take().is_some()
+        {
+            self.keepalive_counters
+                .idle_closes
+                .fetch_add(1, Ordering::Relaxed);
+        }
+
+        let step = self
+            .scenario
+            .as_ref()
+            .map(|scenario| scenario.step(&client_state.scenario));
+
+        let do_req = async {
+            // The slot of the URL of --urls-from-file is held until the request is done
+            let (url, method, _url_permit) = match step {
+                Some(step) => (
+                    Cow::Owned(self.step_url(
+                        step,
+                        &client_state.scenario,
+                        &mut client_state.rng,
+                        &mut client_state.cache_bust,
+                    )?),
+                    step.method.clone(),
+                    None,
+                ),
+                None => {
+                    let (url, url_permit) = self
+                        .next_url(&mut client_state.rng, &mut client_state.cache_bust)
+                        .await?;
+                    (url, self.choose_method(&mut client_state.rng), url_permit)
+                }
+            };
+            let mut start = std::time::Instant::now();
+            let mut connection_time: Option<ConnectionTime> = None;
+
+            let mut send_request = if let Some(send_request) = client_state.send_request.take() {
+                send_request
             } else {
-                &url[url::Position::BeforePath..]
-            })
-            .method(self.method.clone())
-            .version(self.http_version);
+                let (new_connection_time, send_request) =
+                    self.connect_http1(&url, &mut client_state.rng).await?;
+                connection_time = Some(new_connection_time);
+                send_request
+            };
+            while futures::future::poll_fn(|ctx| send_request.poll_ready(ctx))
+                .await
+                .is_err()
+            {
+                // This gets hit when the connection for HTTP/1.1 faults
+                // This re-connects
+                if connection_time.is_none() {
+                    // The server closed the kept-alive connection while it was idle
+                    self.keepalive_counters
+                        .stale_retries
+                        .fetch_add(1, Ordering::Relaxed);
+                }
+                start = std::time::Instant::now();
+                let new_connection_time;
+                (new_connection_time, send_request) =
+                    self.connect_http1(&url, &mut client_state.rng).await?;
+                connection_time = Some(new_connection_time);
+            }
+            let tracker = self
+                .informational_stats
+                .then(|| Arc::new(InformationalTracker::default()));
+            let build_request = || match step {
+                Some(step) => {
+                    let mut request = self.step_request(step, &client_state.scenario, &url)?;
+                    if let Some(tracker) = &tracker {
+                        Self::track_informational(&mut request, tracker);
+                    }
+                    Ok((request, None))
+                }
+                None => self.request_expect_continue(&url, &method, tracker.as_ref()),
+            };
+            let (request, mut expect_continue_gate) = build_request()?;
+            // Returning on a read timeout drops the connection
+            let res = match self
+                .read_timeout(send_request.try_send_request(request))
+                .await?
+            {
+                // A kept-alive connection turned out to be dead before the request was written,
+                // or, for an idempotent method, was closed by the server before any response
+                // (RFC 9112 section 9.3.1). Retry once on a new connection.
+                Err(mut err) if connection_time.is_none() => {
+                    let request = match err.take_message() {
+                        Some(request) => Ok(request),
+                        None => match err.into_error() {
+                            err if method.is_idempotent() && is_closed_connection(&err) => {
+                                let request;
+                                (request, expect_continue_gate) = build_request()?;
+                                Ok(request)
+                            }
+                            err => Err(err),
+                        },
+                    };
+                    match request {
+                        Ok(request) => {
+                            self.keepalive_counters
+                                .stale_retries
+                                .fetch_add(1, Ordering::Relaxed);
+                            start = std::time::Instant::now();
+                            let new_connection_time;
+                            (new_connection_time, send_request) =
+                                self.connect_http1(&url, &mut client_state.rng).await?;
+                            connection_time = Some(new_connection_time);
+                            self.read_timeout(send_request.send_request(request))
+                                .await?
+                        }
+                        Err(err) => Err(err),
+                    }
+                }
+                res => res.map_err(|err| err.into_error()),
+            };
+            client_state.count_request(connection_time.is_some());
+            match res {
+                Ok(res) => {
+                    let first_byte = std::time::Instant::now();
+                    let (parts, stream) = res.into_parts();
+                    let mut status = parts.status;
+                    client_state.retry_after = self.retry_after_until(status, &parts.headers);
+
+                    let buffer_limit = self.body_buffer_limit(step);
+                    let mut body = ResponseBody::read(
+                        &parts.headers,
+                        stream,
+                        std::mem::take(&mut client_state.body_buffer),
+                        buffer_limit,
+                        self.max_body_bytes,
+                        self.decompressed_size,
+                        self.read_timeout,
+                    )
+                    .await
+                    .map_err(|err| err.into_http1_error(&parts.headers))?;
+
+                    let expect_continue = expect_continue_gate.map(ExpectContinueGate::finish);
+                    // The connection can't be reused when the body was aborted
+                    // in the middle of the request.
+                    let body_aborted = expect_continue.is_some_and(|e| !e.body_sent);
+                    // The rest of a truncated body is left on the connection
+                    let mut send_request =
+                        (!body_aborted && !body.truncated && is_keep_alive(&method, &parts))
+                            .then_some(send_request);
+                    let location = parts
+                        .headers
+                        .get("Location")
+                        .filter(|_| self.redirect_limit != 0 && !body_aborted && !body.truncated)
+                        .cloned();
+                    body.headers = parts.headers;
+
+                    if let Some(location) = location {
+                        let (send_request_redirect, new_status, new_body) = self
+                            .redirect(
+                                send_request,
+                                &url,
+                                &method,
+                                &location,
+                                self.redirect_limit,
+                                buffer_limit,
+                                body.recycle(),
+                                &mut client_state.rng,
+                            )
+                            .await?;
+
+                        send_request = send_request_redirect;
+                        status = new_status;
+                        body = new_body;
+                    }
+
+                    let end = std::time::Instant::now();
+
+                    let result = RequestResult {
+                        start_latency_correction: None,
+                        send_delay: None,
+                        start,
+                        first_byte,
+                        end,
+                        status,
+                        method: self.labels.method(&method),
+                        step: self.step_label(step),
+                        url: self.url_label(&url),
+                        len_bytes: body.len as u64,
+                        decoded_len_bytes: body.decoded_len.map(|len| len as u64),
+                        connection_time,
+                        expect_continue,
+                        unexpected_status: self.is_unexpected_status(status),
+                        truncated: body.truncated,
+                        http2_connection: None,
+                        informational: tracker.map(|tracker| tracker.finish()),
+                    };
+
+                    if !self.disable_keepalive {
+                        client_state.send_request = send_request;
+                    }
+
+                    let checked = self.check_body(&body);
+                    self.save_response(status, &body, &checked);
+                    let finished = checked.and_then(|()| match step {
+                        Some(step) => {
+                            self.finish_step(step, &mut client_state.scenario, status, &body.buffer)
+                        }
+                        None => Ok(()),
+                    });
+                    client_state.body_buffer = body.recycle();
+                    finished?;
+
+                    Ok::<_, ClientError>(result)
+                }
+                Err(e) => {
+                    if !self.disable_keepalive {
+                        client_state.send_request = Some(send_request);
+                    }
+                    Err(e.into())
+                }
+            }
+        };
 
-        *builder
-            .headers_mut()
-            .ok_or(ClientError::GetHeaderFromBuilderError)? = self.headers.clone();
+        let res = tokio::select! {
+            res = do_req => {
+                res
+            }
+            client_error = timeout => {
+                Err(client_error)
+            }
+        };
+        client_state.idle_since = std::time::Instant::now();
 
-        if let Some(body) = self.body {
-            Ok(builder.body(Full::new(body))?)
-        } else {
-            Ok(builder.body(Full::default())?)
+        if res.is_err() {
+            // A failed step aborts the iteration of the scenario
+            client_state.scenario.abort();
         }
+        res
     }
-
-    fn timeout_future(
+    /// Send a message on the WebSocket connection and wait for the response message.
+    /// A new connection is made first if there is none.
+    async fn work_websocket(
         &self,
+        websocket: &WebSocketOptions,
+        client_state: &mut ClientStateHttp1,
         dead_line: Option<std::time::Instant>,
-    ) -> Pin<Box<dyn Future<Output = ClientError> + Send>> {
-        match (dead_line, self.timeout) {
-            (Some(dead_line), Some(timeout)) => {
-                if tokio::time::Instant::now() + timeout > dead_line.into() {
-                    async move {
-                        tokio::time::sleep_until(dead_line.into()).await;
-                        ClientError::Deadline
-                    }
-                    .boxed()
-                } else {
-                    async move {
-                        tokio::time::sleep(timeout).await;
-                        ClientError::Timeout
-                    }
-                    .boxed()
+    ) -> Result<RequestResult, ClientError> {
+        let timeout = self.timeout_future(dead_line);
+        let connected = AtomicBool::new(false);
+
+        let do_req = async {
+            let url = self.generate_url(&mut client_state.rng, &mut client_state.cache_bust)?;
+            let start = std::time::Instant::now();
+            let mut connection_time: Option<ConnectionTime> = None;
+
+            let connection = match &mut client_state.websocket {
+                Some(connection) => connection,
+                None => {
+                    let addr = self.dns.lookup(&url, &mut client_state.rng).await?;
+                    let dns_lookup = std::time::Instant::now();
+                    let (connection, tls_handshake) =
+                        self.websocket_handshake(websocket, addr, &url).await?;
+                    let dialup = std::time::Instant::now();
+                    connection_time = Some(ConnectionTime {
+                        dns_lookup,
+                        dialup,
+                        tls_handshake,
+                    });
+                    client_state.websocket.insert(connection)
+                }
+            };
+            connected.store(true, Ordering::Relaxed);
+
+            let result = connection.round_trip(&websocket.payload).await;
+            let messages = connection.messages;
+            let (end, len) = match result {
+                Ok(received) => received,
+                Err(err) => {
+                    client_state.websocket = None;
+                    return Err(err.into());
+                }
+            };
+            if websocket
+                .messages_per_connection
+                .is_some_and(|max| messages >= max)
+            {
+                if let Some(connection) = client_state.websocket.take() {
+                    connection.close().await;
                 }
             }
-            (Some(dead_line), None) => async move {
-                tokio::time::sleep_until(dead_line.into()).await;
-                ClientError::Deadline
-            }
-            .boxed(),
-            (None, Some(timeout)) => async move {
-                tokio::time::sleep(timeout).await;
-                ClientError::Timeout
+
+            Ok(RequestResult {
+                start_latency_correction: None,
+                send_delay: None,
+                start,
+                first_byte: end,
+                end,
+                status: http::StatusCode::SWITCHING_PROTOCOLS,
+                method: self.labels.method(&http::Method::GET),
+                step: None,
+                url: self.url_label(&url),
+                len_bytes: len as u64,
+                decoded_len_bytes: None,
+                connection_time,
+                expect_continue: None,
+                unexpected_status: false,
+                truncated: false,
+                http2_connection: None,
+                informational: None,
+            })
+        };
+
+        let res = tokio::select! {
+            res = do_req => res,
+            client_error = timeout => Err(client_error),
+        };
+        match res {
+            Err(ClientError::Timeout) => {
+                // A late response would be taken as the response to the next message
+                client_state.websocket = None;
+                Err(if connected.load(Ordering::Relaxed) {
+                    WebSocketError::Timeout
+                } else {
+                    WebSocketError::Handshake("timeout".to_string())
+                }
+                .into())
             }
-            .boxed(),
-            (None, None) => std::future::pending().boxed(),
+            res => res,
         }
     }
 
-    async fn work_http1(
+    /// Send the `--raw-request` template on the kept-alive connection or a new one
+    async fn work_raw(
         &self,
+        raw_request: &RawRequest,
         client_state: &mut ClientStateHttp1,
         dead_line: Option<std::time::Instant>,
     ) -> Result<RequestResult, ClientError> {
         let timeout = self.timeout_future(dead_line);
 
         let do_req = async {
-            let url = self.url_generator.generate(&mut client_state.rng)?;
+            let url = self.generate_url(&mut client_state.rng, &mut client_state.cache_bust)?;
+            if client_state
+                .raw_stream
+                .as_mut()
+                .is_some_and(|stream| stream.is_closed(&mut client_state.raw_buffers.1))
+            {
+                // The server closed the kept-alive connection while it was idle
+                self.keepalive_counters
+                    .stale_retries
+                    .fetch_add(1, Ordering::Relaxed);
+                client_state.raw_stream = None;
+            }
+            let host = if raw_request.has_host() {
+                url_generator::host_header(&url).unwrap_or_default()
+            } else {
+                String::new()
+            };
+            let (request_buf, response_buf) = &mut client_state.raw_buffers;
+            let request = raw_request.render(&host, request_buf);
             let mut start = std::time::Instant::now();
             let mut connection_time: Option<ConnectionTime> = None;
 
-            let mut send_request = if let Some(send_request) = client_state.send_request.take() {
-                send_request
-            } else {
-                let addr = self.dns.lookup(&url, &mut client_state.rng).await?;
-                let dns_lookup = std::time::Instant::now();
-                let send_request = self.client_http1(addr, &url).await?;
-                let dialup = std::time::Instant::now();
-
-                connection_time = Some(ConnectionTime { dns_lookup, dialup });
-                send_request
-            };
-            while futures::future::poll_fn(|ctx| send_request.poll_ready(ctx))
-                .await
-                .is_err()
-            {
-                // This gets hit when the connection for HTTP/1.1 faults
-                // This re-connects
-                start = std::time::Instant::now();
-                let addr = self.dns.lookup(&url, &mut client_state.rng).await?;
-                let dns_lookup = std::time::Instant::now();
-                send_request = self.client_http1(addr, &url).await?;
-                let dialup = std::time::Instant::now();
-                connection_time = Some(ConnectionTime { dns_lookup, dialup });
-            }
-            let request = self.request(&url)?;
-            match send_request.send_request(request).await {
-                Ok(res) => {
-                    let (parts, mut stream) = res.into_parts();
-                    let mut status = parts.status;
+            let response = loop {
+                let stream = match &mut client_state.raw_stream {
+                    Some(stream) => stream,
+                    None => {
+                        let addr = self.dns.lookup(&url, &mut client_state.rng).await?;
+                        let dns_lookup = std::time::Instant::now();
+                        let (stream, tls_handshake) = self.client(addr, &url).await?;
+                        let dialup = std::time::Instant::now();
+                        connection_time = Some(ConnectionTime {
+                            dns_lookup,
+                            dialup,
+                            tls_handshake,
+                        });
+                        client_state.raw_stream.insert(stream)
+                    }
+                };
 
-                    let mut len_sum = 0;
-                    while let Some(chunk) = futures::future::poll_fn(|cx| {
-                        Incoming::poll_frame(Pin::new(&mut stream), cx)
-                    })
+                match stream
+                    .send_raw(raw_request, request, response_buf, self.read_options())
                     .await
+                {
+                    Ok(response) => break response,
+                    // The server closed the kept-alive connection as the request was sent.
+                    // An idempotent request is sent again once on a new connection
+                    // (RFC 9112 section 9.3.1).
+                    Err(err)
+                        if connection_time.is_none()
+                            && raw_request.method().is_idempotent()
+                            && err.is_closed_connection() =>
                     {
-                        len_sum += chunk?.data_ref().map(|d| d.len()).unwrap_or_default();
+                        self.keepalive_counters
+                            .stale_retries
+                            .fetch_add(1, Ordering::Relaxed);
+                        client_state.raw_stream = None;
+                        start = std::time::Instant::now();
                     }
-
-                    if self.redirect_limit != 0 {
-                        if let Some(location) = parts.headers.get("Location") {
-                            let (send_request_redirect, new_status, len) = self
-                                .redirect(
-                                    send_request,
-                                    &url,
-                                    location,
-                                    self.redirect_limit,
-                                    &mut client_state.rng,
-                                )
-                                .await?;
-
-                            send_request = send_request_redirect;
-                            status = new_status;
-                            len_sum = len;
-                        }
+                    Err(err) => {
+                        client_state.raw_stream = None;
+                        return Err(raw_response_error(err));
                     }
+                }
+            };
+            client_state.count_request(connection_time.is_some());
+            if !response.keep_alive || self.disable_keepalive {
+                client_state.raw_stream = None;
+            }
+            let first_byte = response.first_byte;
+            // A prebuilt request follows redirects as the others do. `--raw-request` doesn't.
+            let response = match response
+                .location
+                .clone()
+                .filter(|_| self.raw_request.is_none() && self.redirect_limit != 0)
+                .filter(|_| !response.truncated)
+            {
+                Some(location) => {
+                    self.redirect_raw(client_state, &url, raw_request.method(), location)
+                        .await?
+                }
+                None => response,
+            };
+            let status = response.status;
+
+            Ok(RequestResult {
+                start_latency_correction: None,
+                send_delay: None,
+                start,
+                first_byte,
+                end: std::time::Instant::now(),
+                status,
+                method: self.labels.method(raw_request.method()),
+                step: None,
+                url: self.url_label(&url),
+                len_bytes: response.len as u64,
+                decoded_len_bytes: response.decoded_len.map(|len| len as u64),
+                connection_time,
+                expect_continue: None,
+                unexpected_status: self.is_unexpected_status(status),
+                truncated: response.truncated,
+                http2_connection: None,
+                informational: None,
+            })
+        };
 
-                    let end = std::time::Instant::now();
-
-                    let result = RequestResult {
-                        start_latency_correction: None,
-                        start,
-                        end,
-                        status,
-                        len_bytes: len_sum,
-                        connection_time,
-                    };
+        let res = tokio::select! {
+            res = do_req => res,
+            client_error = timeout => Err(client_error),
+        };
+        if res.is_err() {
+            // A late response would be taken as the response to the next request
+            client_state.raw_stream = None;
+        }
+        res
+    }
 
-                    if !self.disable_keepalive {
-                        client_state.send_request = Some(send_request);
-                    }
+    /// Follow the redirects of a prebuilt request as `redirect` does with hyper. A location
+    /// with the authority of `url` is sent on the kept-alive connection of the worker, and
+    /// a connection to another one is closed after the redirects.
+    async fn redirect_raw(
+        &self,
+        client_state: &mut ClientStateHttp1,
+        url: &Url,
+        method: &http::Method,
+        mut location: http::HeaderValue,
+    ) -> Result<RawResponse, ClientError> {
+        let mut base_url = url.clone();
+        // The connection to another authority than the one of `url`
+        let mut other_stream: Option<Stream> = None;
+        let mut other_authority = String::new();
+        for _ in 0..self.redirect_limit {
+            let mut next_url = match Url::parse(location.to_str()?) {
+                Ok(url) => url,
+                Err(ParseError::RelativeUrlWithoutBase) => Url::options()
+                    .base_url(Some(&base_url))
+                    .parse(location.to_str()?)?,
+                Err(err) => Err(err)?,
+            };
+            // Credentials aren't sent to the redirected location
+            url_generator::take_userinfo(&mut next_url);
+            url_generator::normalize(&mut next_url);
 
-                    Ok::<_, ClientError>(result)
+            let same_authority = next_url.authority() == url.authority();
+            let headers = if same_authority {
+                Cow::Borrowed(&self.headers)
+            } else {
+                let mut headers = self.headers.clone();
+                headers.insert(
+                    http::header::HOST,
+                    http::HeaderValue::from_str(
+                        &url_generator::host_header(&next_url).unwrap_or_default(),
+                    )?,
+                );
+                Cow::Owned(headers)
+            };
+            let request = RawRequest::prebuilt(
+                method.clone(),
+                self.request_uri(&next_url),
+                &headers,
+                // The body of a prebuilt request is the same each time
+                self.prebuilt_body().unwrap_or_default(),
+            );
+            let (request_buf, response_buf) = &mut client_state.raw_buffers;
+            let request_bytes = request.render("", request_buf);
+
+            if !same_authority && other_authority != next_url.authority() {
+                other_stream = None;
+                other_authority = next_url.authority().to_string();
+            }
+            let slot = if same_authority {
+                &mut client_state.raw_stream
+            } else {
+                &mut other_stream
+            };
+            let stream = match slot {
+                Some(stream) => stream,
+                None => {
+                    let addr = self.dns.lookup(&next_url, &mut client_state.rng).await?;
+                    slot.insert(self.client(addr, &next_url).await?.0)
                 }
-                Err(e) => {
-                    client_state.send_request = Some(send_request);
-                    Err(e.into())
+            };
+            let response = match stream
+                .send_raw(&request, request_bytes, response_buf, self.read_options())
+                .await
+            {
+                Ok(response) => response,
+                Err(err) => {
+                    *slot = None;
+                    return Err(raw_response_error(err));
                 }
+            };
+            if !response.keep_alive || self.disable_keepalive {
+                *slot = None;
             }
-        };
-
-        tokio::select! {
-            res = do_req => {
-                res
-            }
-            client_error = timeout => {
-                Err(client_error)
+            match response.location.clone().filter(|_| !response.truncated) {
+                Some(next_location) => {
+                    location = next_location;
+                    base_url = next_url;
+                }
+                None => return Ok(response),
             }
         }
+        Err(ClientError::TooManyRedirect)
+    }
+
+    async fn websocket_handshake(
+        &self,
+        websocket: &WebSocketOptions,
+        addr: Addrs,
+        url: &Url,
+    ) -> Result<(WebSocketConnection, Option<TlsHandshake>), ClientError> {
+        let (mut send_request, tls_handshake) = self.client_http1(addr, url).await?;
+
+        let key = tokio_tungstenite::tungstenite::handshake::client::generate_key();
+        let mut request = http::Request::builder()
+            .uri(self.request_uri(url))
+            .method(http::Method::GET)
+            .version(http::Version::HTTP_11)
+            .body(Body::empty())?;
+        let headers = request.headers_mut();
+        *headers = self.request_headers().bodyless.clone();
+        headers.insert(
+            http::header::CONNECTION,
+            http::HeaderValue::from_static("Upgrade"),
+        );
+        headers.insert(
+            http::header::UPGRADE,
+            http::HeaderValue::from_static("websocket"),
+        );
+        headers.insert(
+            http::header::SEC_WEBSOCKET_VERSION,
+            http::HeaderValue::from_static("13"),
+        );
+        headers.insert(
+            http::header::SEC_WEBSOCKET_KEY,
+            http::HeaderValue::from_str(&key)?,
+        );
+
+        let res = send_request.send_request(request).await?;
+        if res.status() != http::StatusCode::SWITCHING_PROTOCOLS {
+            return Err(
+                WebSocketError::Handshake(format!("unexpected status {}", res.status())).into(),
+            );
+        }
+        let accept = tokio_tungstenite::tungstenite::handshake::derive_accept_key(key.as_bytes());
+        if res
+            .headers()
+            .get(http::header::SEC_WEBSOCKET_ACCEPT)
+            .map(|v| v.as_bytes())
+            != Some(accept.as_bytes())
+        {
+            return Err(
+                WebSocketError::Handshake("invalid Sec-WebSocket-Accept".to_string()).into(),
+            );
+        }
+        let upgraded = hyper::upgrade::on(res).await?;
+
+        Ok((
+            WebSocketConnection::new(upgraded, websocket, &self.websocket_counters).await,
+            tls_handshake,
+        ))
+    }
+
+    async fn connect_http1<R: Rng>(
+        &self,
+        url: &Url,
+        rng: &mut R,
+    ) -> Result<(ConnectionTime, SendRequestHttp1), ClientError> {
+        let addr = self.dns.lookup(url, rng).await?;
+        let dns_lookup = std::time::Instant::now();
+        let (send_request, tls_handshake) = self.client_http1(addr, url).await?;
+        let dialup = std::time::Instant::now();
+        Ok((
+            ConnectionTime {
+                dns_lookup,
+                dialup,
+                tls_handshake,
+            },
+            send_request,
+        ))
     }
+
     async fn connect_http2<R: Rng>(
         &self,
         url: &Url,
         rng: &mut R,
-    ) -> Result<(ConnectionTime, SendRequestHttp2), ClientError> {
+    ) -> Result<(ConnectionTime, Arc<Http2Connection>), ClientError> {
         let addr = self.dns.lookup(url, rng).await?;
         let dns_lookup = std::time::Instant::now();
-        let stream = self.client(addr, url).await?;
-        let send_request = stream.handshake_http2().await?;
+        let (stream, tls_handshake) = self.client(addr, url).await?;
+        let connection = stream.handshake_http2(self).await?;
         let dialup = std::time::Instant::now();
-        Ok((ConnectionTime { dns_lookup, dialup }, send_request))
+        Ok((
+            ConnectionTime {
+                dns_lookup,
+                dialup,
+                tls_handshake,
+            },
+            connection,
+        ))
+    }
+
+    /// Run a HTTP/2 connection and count it in `http2_goaways` when the server closes it.
+    fn spawn_http2<C>(&self, send_request: SendRequestHttp2, conn: C) -> Arc<Http2Connection>
+    where
+        C: Future<Output = Result<(), hyper::Error>> + Send + 'static,
+    {
+        let connection = Arc::new(Http2Connection {
+            id: self.http2_connections.fetch_add(1, Ordering::Relaxed),
+            send_request,
+        });
+        let weak = Arc::downgrade(&connection);
+        let counters = self.http2_counters.clone();
+        tokio::spawn(async move {
+            let closed_by_server = match conn.await {
+                // A GOAWAY without an error closes the connection while workers still hold it
+                Ok(()) => weak.strong_count() > 0,
+                Err(err) => h2_error(&err).is_some_and(|err| err.is_go_away() && err.is_remote()),
+            };
+            if closed_by_server {
+                counters.goaways.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        connection
     }
 
     async fn work_http2(
         &self,
         client_state: &mut ClientStateHttp2,
         dead_line: Option<std::time::Instant>,
+    ) -> Result<RequestResult, ClientError> {
+        self.unless_aborted(async {
+            self.pause_for_retry_after(&mut client_state.retry_after, dead_line)
+                .await;
+            self.pause_for_think_time(&mut client_state.think_until, dead_line)
+                .await;
+            self.pause_for_duty_cycle(dead_line).await;
+            self.pause_for_signal(dead_line).await;
+        })
+        .await?;
+        if self.is_past_dead_line(dead_line) {
+            return Err(ClientError::Deadline);
+        }
+        let res = self.send_http2(client_state, dead_line).await;
+        client_state.think_until = self.think_time_until(&mut client_state.rng);
+        res
+    }
+
+    async fn send_http2(
+        &self,
+        client_state: &mut ClientStateHttp2,
+        dead_line: Option<std::time::Instant>,
     ) -> Result<RequestResult, ClientError> {
         let timeout = self.timeout_future(dead_line);
 
+        let step = self
+            .scenario
+            .as_ref()
+            .map(|scenario| scenario.step(&client_state.scenario));
+
         let do_req = async {
-            let url = self.url_generator.generate(&mut client_state.rng)?;
+            // The slot of the URL of --urls-from-file is held until the request is done
+            let (url, method, _url_permit) = match step {
+                Some(step) => (
+                    Cow::Owned(self.step_url(
+                        step,
+                        &client_state.scenario,
+                        &mut client_state.rng,
+                        &mut client_state.cache_bust,
+                    )?),
+                    step.method.clone(),
+                    None,
+                ),
+                None => {
+                    let (url, url_permit) = self
+                        .next_url(&mut client_state.rng, &mut client_state.cache_bust)
+                        .await?;
+                    (url, self.choose_method(&mut client_state.rng), url_permit)
+                }
+            };
             let start = std::time::Instant::now();
-            let connection_time: Option<ConnectionTime> = None;
+            let mut connection_time: Option<ConnectionTime> = None;
 
-            let request = self.request(&url)?;
-            match client_state.send_request.send_request(request).await {
+            let mut retries = 0;
+            let res = loop {
+                let request = match step {
+                    Some(step) => self.step_request(step, &client_state.scenario, &url)?,
+                    None => self.request(&url, &method)?,
+                };
+                let response = client_state
+                    .connection
+                    .send_request
+                    .clone()
+                    .send_request(request);
+                let err = match self.read_timeout(response).await {
+                    Ok(Ok(res)) => break Ok(res),
+                    Ok(Err(err)) => http2_failure(err),
+                    Err(err) => {
+                        // Dropping the response resets the stream. The connection which
+                        // stalled isn't used for the next requests.
+                        client_state.reconnect(self).await?;
+                        return Err(err);
+                    }
+                };
+                if retries >= self.http2_max_retries {
+                    break Err(err);
+                }
+                retries += 1;
+                match err {
+                    Http2Failure::RefusedStream => {
+                        self.http2_counters
+                            .refused_stream_retries
+                            .fetch_add(1, Ordering::Relaxed);
+                    }
+                    Http2Failure::Unprocessed(_) => {
+                        self.http2_counters
+                            .goaway_resends
+                            .fetch_add(1, Ordering::Relaxed);
+                        if let Some(new_connection_time) = client_state.reconnect(self).await? {
+                            connection_time = Some(new_connection_time);
+                        }
+                    }
+                    Http2Failure::Other(err) => break Err(Http2Failure::Other(err)),
+                }
+            };
+            match res {
                 Ok(res) => {
-                    let (parts, mut stream) = res.into_parts();
+                    let first_byte = std::time::Instant::now();
+                    let (parts, stream) = res.into_parts();
                     let status = parts.status;
-
-                    let mut len_sum = 0;
-                    while let Some(chunk) = futures::future::poll_fn(|cx| {
-                        Incoming::poll_frame(Pin::new(&mut stream), cx)
-                    })
+                    client_state.retry_after = self.retry_after_until(status, &parts.headers);
+
+                    // Dropping the rest of a truncated body resets the stream
+                    let mut body = match ResponseBody::read(
+                        &parts.headers,
+                        stream,
+                        std::mem::take(&mut client_state.body_buffer),
+                        self.body_buffer_limit(step),
+                        self.max_body_bytes,
+                        self.decompressed_size,
+                        self.read_timeout,
+                    )
                     .await
                     {
-                        len_sum += chunk?.data_ref().map(|d| d.len()).unwrap_or_default();
-                    }
+                        Ok(body) => body,
+                        Err(BodyError {
+                            source: Some(err), ..
+                        }) => return Err(http2_failure(err).into()),
+                        Err(BodyError { source: None, .. }) => {
+                            client_state.reconnect(self).await?;
+                            return Err(ClientError::ReadTimeout);
+                        }
+                    };
 
                     let end = std::time::Instant::now();
 
                     let result = RequestResult {
                         start_latency_correction: None,
+                        send_delay: None,
                         start,
+                        first_byte,
                         end,
                         status,
-                        len_bytes: len_sum,
+                        method: self.labels.method(&method),
+                        step: self.step_label(step),
+                        url: self.url_label(&url),
+                        len_bytes: body.len as u64,
+                        decoded_len_bytes: body.decoded_len.map(|len| len as u64),
                         connection_time,
+                        expect_continue: None,
+                        unexpected_status: self.is_unexpected_status(status),
+                        truncated: body.truncated,
+                        http2_connection: Some(client_state.connection.id),
+                        informational: None,
                     };
 
+                    body.headers = parts.headers;
+                    let checked = self.check_body(&body);
+                    self.save_response(status, &body, &checked);
+                    let finished = checked.and_then(|()| match step {
+                        Some(step) => {
+                            self.finish_step(step, &mut client_state.scenario, status, &body.buffer)
+                        }
+                        None => Ok(()),
+                    });
+                    client_state.body_buffer = body.recycle();
+                    finished?;
+
                     Ok::<_, ClientError>(result)
                 }
                 Err(e) => Err(e.into()),
             }
         };
 
-        tokio::select! {
+        let res = tokio::select! {
             res = do_req => {
                 res
             }
             client_error = timeout => {
                 Err(client_error)
             }
+        };
+
+        if res.is_err() {
+            // A failed step aborts the iteration of the scenario
+            client_state.scenario.abort();
         }
+        res
     }
 
-    #[allow(clippy::type_complexity)]
+    #[allow(clippy::type_complexity, clippy::too_many_arguments)]
+    /// Follow a redirect. `send_request` is the connection of the previous response, or None
+    /// when it can't be reused. The connection for `base_url` is returned in the same way.
     fn redirect<'a, R: Rng + Send>(
         &'a self,
-        send_request: SendRequestHttp1,
+        send_request: Option<SendRequestHttp1>,
         base_url: &'a Url,
+        method: &'a http::Method,
         location: &'a http::header::HeaderValue,
         limit: usize,
+        buffer_limit: usize,
+        buffer: bytes::BytesMut,
         rng: &'a mut R,
     ) -> futures::future::BoxFuture<
         'a,
-        Result<(SendRequestHttp1, http::StatusCode, usize), ClientError>,
+        Result<(Option<SendRequestHttp1>, http::StatusCode, ResponseBody), ClientError>,
     > {
         async move {
             if limit == 0 {
                 return Err(ClientError::TooManyRedirect);
             }
-            let url = match Url::parse(location.to_str()?) {
+            let mut url = match Url::parse(location.to_str()?) {
                 Ok(url) => url,
                 Err(ParseError::RelativeUrlWithoutBase) => Url::options()
                     .base_url(Some(base_url))
                     .parse(location.to_str()?)?,
                 Err(err) => Err(err)?,
             };
-
-            let (mut send_request, send_request_base) =
-                if base_url.authority() == url.authority() && !self.disable_keepalive {
+            // Credentials aren't sent to the redirected location
+            url_generator::take_userinfo(&mut url);
+            url_generator::normalize(&mut url);
+
+            let (mut send_request, send_request_base) = match send_request {
+                Some(send_request)
+                    if base_url.authority() == url.authority() && !self.disable_keepalive =>
+                {
                     // reuse connection
                     (send_request, None)
-                } else {
+                }
+                send_request => {
                     let addr = self.dns.lookup(&url, rng).await?;
-                    (self.client_http1(addr, &url).await?, Some(send_request))
-                };
+                    (self.client_http1(addr, &url).await?.0, Some(send_request))
+                }
+            };
 
             while futures::future::poll_fn(|ctx| send_request.poll_ready(ctx))
                 .await
                 .is_err()
             {
                 let addr = self.dns.lookup(&url, rng).await?;
-                send_request = self.client_http1(addr, &url).await?;
+                send_request = self.client_http1(addr, &url).await?.0;
             }
 
-            let mut request = self.request(&url)?;
+            let mut request = self.request(&url, method)?;
             if url.authority() != base_url.authority() {
                 request.headers_mut().insert(
                     http::header::HOST,
-                    http::HeaderValue::from_str(url.authority())?,
+                    http::HeaderValue::from_str(
+                        &url_generator::host_header(&url).unwrap_or_default(),
+                    )?,
                 );
             }
-            let res = send_request.send_request(request).await?;
-            let (parts, mut stream) = res.into_parts();
+            let res = self
+                .read_timeout(send_request.send_request(request))
+                .await??;
+            let (parts, stream) = res.into_parts();
             let mut status = parts.status;
 
-            let mut len_sum = 0;
-            while let Some(chunk) =
-                futures::future::poll_fn(|cx| Incoming::poll_frame(Pin::new(&mut stream), cx)).await
-            {
-                len_sum += chunk?.data_ref().map(|d| d.len()).unwrap_or_default();
-            }
-
-            if let Some(location) = parts.headers.get("Location") {
-                let (send_request_redirect, new_status, len) = self
-                    .redirect(send_request, &url, location, limit - 1, rng)
+            let mut body = ResponseBody::read(
+                &parts.headers,
+                stream,
+                buffer,
+                buffer_limit,
+                self.max_body_bytes,
+                self.decompressed_size,
+                self.read_timeout,
+            )
+            .await
+            .map_err(|err| err.into_http1_error(&parts.headers))?;
+
+            let mut send_request =
+                (!body.truncated && is_keep_alive(method, &parts)).then_some(send_request);
+            let location = parts
+                .headers
+                .get("Location")
+                .filter(|_| !body.truncated)
+                .cloned();
+            body.headers = parts.headers;
+
+            if let Some(location) = location {
+                let (send_request_redirect, new_status, new_body) = self
+                    .redirect(
+                        send_request,
+                        &url,
+                        method,
+                        &location,
+                        limit - 1,
+                        buffer_limit,
+                        body.recycle(),
+                        rng,
+                    )
                     .await?;
                 send_request = send_request_redirect;
                 status = new_status;
-                len_sum = len;
+                body = new_body;
             }
 
             if let Some(send_request_base) = send_request_base {
-                Ok((send_request_base, status, len_sum))
+                Ok((send_request_base, status, body))
             } else {
-                Ok((send_request, status, len_sum))
+                Ok((send_request, status, body))
             }
         }
         .boxed()
     }
 }
 
-/// A server certificate verifier that accepts any certificate.
-#[cfg(feature = "rustls")]
-#[derive(Debug)]
-struct AcceptAnyServerCert;
+/// SO_KEEPALIVE with the idle time. Where the platform supports them, probes are
+/// sent every third of the idle time and the connection is dropped after 3 of them fail.
+fn tcp_keepalive(idle: std::time::Duration) -> socket2::TcpKeepalive {
+    let keepalive = socket2::TcpKeepalive::new().with_time(idle);
+    #[cfg(any(
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "ios",
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "netbsd",
+        target_os = "windows",
+    ))]
+    let keepalive = keepalive.with_interval((idle / 3).max(std::time::Duration::from_secs(1)));
+    #[cfg(any(
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "ios",
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "netbsd",
+    ))]
+    let keepalive = keepalive.with_retries(3);
+    keepalive
+}
 
-#[cfg(feature = "rustls")]
-impl rustls::client::danger::ServerCertVerifier for AcceptAnyServerCert {
-    fn verify_server_cert(
-        &self,
-        _end_entity: &rustls_pki_types::CertificateDer<'_>,
-        _intermediates: &[rustls_pki_types::CertificateDer<'_>],
-        _server_name: &rustls_pki_types::ServerName<'_>,
-        _ocsp_response: &[u8],
-        _now: rustls_pki_types::UnixTime,
-    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
-        Ok(rustls::client::danger::ServerCertVerified::assertion())
-    }
+fn h2_error(err: &hyper::Error) -> Option<&h2::Error> {
+    std::error::Error::source(err).and_then(|source| source.downcast_ref::<h2::Error>())
+}
 
-    fn verify_tls12_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls_pki_types::CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
-    }
+/// A failed HTTP/2 request
+enum Http2Failure {
+    /// The server refused the stream before processing it, so it's safe to retry
+    RefusedStream,
+    /// The connection was closed before the server processed the request
+    Unprocessed(ClientError),
+    Other(ClientError),
+}
 
-    fn verify_tls13_signature(
-        &self,
-        _message: &[u8],
-        _cert: &rustls_pki_types::CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+impl From<Http2Failure> for ClientError {
+    fn from(failure: Http2Failure) -> Self {
+        match failure {
+            Http2Failure::RefusedStream => {
+                ClientError::Http2StreamReset(h2::Reason::REFUSED_STREAM)
+            }
+            Http2Failure::Unprocessed(err) | Http2Failure::Other(err) => err,
+        }
     }
+}
 
-    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-        rustls::crypto::ring::default_provider()
-            .signature_verification_algorithms
-            .supported_schemes()
+/// Keep the error code of RST_STREAM and GOAWAY from the server.
+fn http2_failure(err: hyper::Error) -> Http2Failure {
+    match h2_error(&err) {
+        Some(h2) if h2.is_remote() && h2.is_reset() => match h2.reason() {
+            Some(h2::Reason::REFUSED_STREAM) => Http2Failure::RefusedStream,
+            Some(reason) => Http2Failure::Other(ClientError::Http2StreamReset(reason)),
+            None => Http2Failure::Other(err.into()),
+        },
+        Some(h2) if h2.is_remote() && h2.is_go_away() => match h2.reason() {
+            // A graceful GOAWAY resets only the streams the server won't process
+            Some(h2::Reason::NO_ERROR) => {
+                Http2Failure::Unprocessed(ClientError::Http2GoAway(h2::Reason::NO_ERROR))
+            }
+            Some(reason) => Http2Failure::Other(ClientError::Http2GoAway(reason)),
+            None => Http2Failure::Other(err.into()),
+        },
+        // The request wasn't sent because the connection is already gone
+        _ if err.is_canceled() => Http2Failure::Unprocessed(err.into()),
+        _ => Http2Failure::Other(err.into()),
     }
 }
 
 /// Check error and decide whether to cancel the connection
 fn is_cancel_error(res: &Result<RequestResult, ClientError>) -> bool {
-    matches!(res, Err(ClientError::Deadline)) || is_too_many_open_files(res)
+    matches!(res, Err(ClientError::Deadline | ClientError::Aborted)) || is_too_many_open_files(res)
 }
 
 /// Check error was "Too many open file"
@@ -747,7 +3243,8 @@ fn is_too_many_open_files(res: &Result<RequestResult, ClientError>) -> bool {
     res.as_ref()
         .err()
         .map(|err| match err {
-            ClientError::IoError(io_error) => io_error.raw_os_error() == Some(libc::EMFILE),
+            ClientError::TooManyOpenFiles(_) => true,
+            ClientError::IoError(io_error) => fd_limit::is_exhausted(io_error),
             _ => false,
         })
         .unwrap_or(false)
@@ -762,815 +3259,732 @@ fn is_hyper_error(res: &Result<RequestResult, ClientError>) -> bool {
             // should also cause a stop of HTTP2 requests
             ClientError::IoError(_) => true,
             ClientError::HyperError(_) => true,
+            ClientError::Http2GoAway(_) => true,
             _ => false,
         })
         .unwrap_or(false)
 }
 
-async fn setup_http2(client: &Client) -> Result<(ConnectionTime, ClientStateHttp2), ClientError> {
-    let mut rng = StdRng::from_entropy();
-    let url = client.url_generator.generate(&mut rng)?;
-    let (connection_time, send_request) = client.connect_http2(&url, &mut rng).await?;
+/// Connect the HTTP/2 connection `c`. The connection time is None for a prewarmed connection.
+async fn setup_http2(
+    client: &Client,
+    c: u64,
+) -> Result<(Option<ConnectionTime>, ClientStateHttp2), ClientError> {
+    let mut rng = client.rng(WorkerRng::Http2Setup(c));
+    let (connection_time, connection) = match client.take_prewarmed_http2() {
+        Some(connection) => (None, connection),
+        None => {
+            let url = client.url_generator.generate(&mut rng)?;
+            let (connection_time, connection) = client.connect_http2(&url, &mut rng).await?;
+            (Some(connection_time), connection)
+        }
+    };
 
-    let client_state = ClientStateHttp2 { rng, send_request };
+    let client_state = ClientStateHttp2 {
+        cache_bust: client.cache_bust(&mut rng),
+        rng,
+        shared_connection: Arc::new(tokio::sync::Mutex::new(connection.clone())),
+        connection,
+        scenario: Default::default(),
+        body_buffer: Default::default(),
+        retry_after: None,
+        think_until: None,
+    };
 
     Ok((connection_time, client_state))
 }
 
-fn set_connection_time<E>(res: &mut Result<RequestResult, E>, connection_time: ConnectionTime) {
-    if let Ok(res) = res {
-        res.connection_time = Some(connection_time);
+/// Set the connection time unless the request made its own connection.
+fn set_connection_time<E>(
+    res: &mut Result<RequestResult, E>,
+    connection_time: Option<ConnectionTime>,
+) {
+    if let (Ok(res), Some(connection_time)) = (res, connection_time) {
+        res.connection_time.get_or_insert(connection_time);
+    }
+}
+
+fn set_start_latency_correction<E>(
+    res: &mut Result<RequestResult, E>,
+    start_latency_correction: std::time::Instant,
+) {
+    if let Ok(res) = res {
+        res.start_latency_correction = Some(start_latency_correction);
+    }
+}
+
+/// Set how long the request waited for a worker after its scheduled time
+fn set_send_delay<E>(res: &mut Result<RequestResult, E>, send_delay: std::time::Duration) {
+    if let Ok(res) = res {
+        res.send_delay = Some(send_delay);
+    }
+}
+
+/// The connections of which at least one worker gets one of `n_tasks` requests, with
+/// `n_http2_parallel` workers on each connection. The other workers would only connect for
+/// nothing.
+pub fn connections_for(n_tasks: u64, n_connections: usize, n_http2_parallel: usize) -> usize {
+    let needed = n_tasks.div_ceil(n_http2_parallel.max(1) as u64);
+    usize::try_from(needed).map_or(n_connections, |needed| n_connections.min(needed))
+}
+
+/// The permits of a rate for the HTTP/1.x workers
+#[derive(Clone)]
+enum Permits<T> {
+    /// Taken by the workers in the order they became idle
+    Shared(flume::Receiver<T>),
+    /// `--idle-workers park`
+    Parked(Arc<Parking<T>>),
+}
+
+impl<T: Send + 'static> Permits<T> {
+    fn new(client: &Client, rx: flume::Receiver<T>) -> Self {
+        if client.park_idle_workers {
+            Self::Parked(Parking::spawn(rx))
+        } else {
+            Self::Shared(rx)
+        }
+    }
+
+    /// The next permit, or None when there are no more. A parked worker closes its connection
+    /// when no permit comes for `PARK_AFTER`.
+    async fn next(&self, client_state: Option<&mut ClientStateHttp1>) -> Option<T> {
+        match self {
+            Self::Shared(rx) => rx.recv_async().await.ok(),
+            Self::Parked(parking) => {
+                let mut next = std::pin::pin!(parking.next());
+                match tokio::time::timeout(PARK_AFTER, &mut next).await {
+                    Ok(permit) => permit,
+                    Err(_) => {
+                        if let Some(client_state) = client_state {
+                            client_state.close().await;
+                        }
+                        next.await
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// How much a run sends, and on how many connections
+#[derive(Debug, Clone, Copy)]
+pub struct Workload {
+    /// The number of requests, `u64::MAX` for no limit
+    pub n_tasks: u64,
+    /// Stop sending at this time
+    pub dead_line: Option<std::time::Instant>,
+    pub n_connections: usize,
+    pub n_http2_parallel: usize,
+}
+
+/// When a request is due
+#[derive(Clone, Copy)]
+enum Due {
+    /// As soon as a worker is free
+    Now,
+    /// At `scheduled` by the rate. The send delay is the wait for a free worker, and with
+    /// `--latency-correction` the latency counts from then.
+    At {
+        scheduled: std::time::Instant,
+        latency_correction: bool,
+    },
+    /// At `scheduled` by `--arrival-rate`, which the latency always counts from
+    Arrival(std::time::Instant),
+}
+
+/// Where the workers take their next request from
+#[derive(Clone)]
+enum Schedule {
+    /// `n_tasks` requests in total, each sent as soon as a worker is free
+    Free { taken: Arc<AtomicU64>, n_tasks: u64 },
+    /// The requests released by a scheduler at their due time
+    Timed {
+        permits: Permits<std::time::Instant>,
+        latency_correction: bool,
+    },
+}
+
+impl Schedule {
+    fn free(n_tasks: u64) -> Self {
+        Self::Free {
+            taken: Default::default(),
+            n_tasks,
+        }
+    }
+
+    /// The HTTP/2 workers share the released requests, and the HTTP/1.x ones may park
+    fn timed(
+        client: &Client,
+        rx: flume::Receiver<std::time::Instant>,
+        latency_correction: bool,
+    ) -> Self {
+        Self::Timed {
+            permits: if client.is_http2() {
+                Permits::Shared(rx)
+            } else {
+                Permits::new(client, rx)
+            },
+            latency_correction,
+        }
+    }
+
+    /// The next request of a worker, or None when there are no more or the run is stopped.
+    /// `client_state` is the connection that a parked HTTP/1.x worker closes.
+    async fn next(
+        &self,
+        client: &Client,
+        client_state: Option<&mut ClientStateHttp1>,
+    ) -> Option<Due> {
+        match self {
+            Self::Free { taken, n_tasks } => (!client.is_stopped()
+                && taken.fetch_add(1, Ordering::Relaxed) < *n_tasks)
+                .then_some(Due::Now),
+            Self::Timed {
+                permits,
+                latency_correction,
+            } => client
+                .unless_stopped(permits.next(client_state))
+                .await
+                .flatten()
+                .map(|scheduled| Due::At {
+                    scheduled,
+                    latency_correction: *latency_correction,
+                }),
+        }
+    }
+}
+
+/// The connection that a worker sends its request on
+enum WorkerState<'a> {
+    Http1(&'a mut ClientStateHttp1),
+    Http2(&'a mut ClientStateHttp2),
+}
+
+/// What a worker does after a request
+enum NextStep {
+    Next,
+    /// The HTTP/2 connection is gone
+    Reconnect,
+    /// The deadline, an abort or no more file descriptors
+    Stop,
+}
+
+impl Client {
+    /// Send a request on the connection of a worker, stamp it with when it was due and report it
+    async fn execute_and_report(
+        &self,
+        client_state: WorkerState<'_>,
+        due: Due,
+        connection_time: Option<ConnectionTime>,
+        dead_line: Option<std::time::Instant>,
+        report_tx: &ReportSender,
+    ) -> NextStep {
+        let picked_up = std::time::Instant::now();
+        let work = async {
+            match client_state {
+                WorkerState::Http1(client_state) => {
+                    (self.work_http1(client_state, dead_line).await, false)
+                }
+                WorkerState::Http2(client_state) => {
+                    (self.work_http2(client_state, dead_line).await, true)
+                }
+            }
+        };
+        // A request still in flight when the grace period after ctrl-c is over is dropped
+        let (mut res, http2) = tokio::select! {
+            res = work => res,
+            _ = self.shutdown.grace_over() => return NextStep::Stop,
+        };
+        set_connection_time(&mut res, connection_time);
+        match due {
+            Due::Now => {}
+            Due::At {
+                scheduled,
+                latency_correction,
+            } => {
+                if latency_correction {
+                    set_start_latency_correction(&mut res, scheduled);
+                }
+                set_send_delay(&mut res, picked_up.saturating_duration_since(scheduled));
+            }
+            Due::Arrival(scheduled) => set_start_latency_correction(&mut res, scheduled),
+        }
+        let step = if is_cancel_error(&res) {
+            NextStep::Stop
+        } else if http2 && is_hyper_error(&res) {
+            NextStep::Reconnect
+        } else {
+            NextStep::Next
+        };
+        report_tx.send(res).await.unwrap();
+        step
     }
 }
 
-fn set_start_latency_correction<E>(
-    res: &mut Result<RequestResult, E>,
-    start_latency_correction: std::time::Instant,
+/// A HTTP/1.x worker, which sends the requests of `schedule` one after the other
+async fn http1_worker(
+    client: Arc<Client>,
+    mut client_state: ClientStateHttp1,
+    schedule: Schedule,
+    worker: usize,
+    dead_line: Option<std::time::Instant>,
+    report_tx: ReportSender,
 ) {
-    if let Ok(res) = res {
-        res.start_latency_correction = Some(start_latency_correction);
+    if !client.ramp_up_wait(worker, dead_line).await {
+        return;
+    }
+    let _finish = client.ramp_up_finish();
+    while let Some(due) = schedule.next(&client, Some(&mut client_state)).await {
+        let step = client
+            .execute_and_report(
+                WorkerState::Http1(&mut client_state),
+                due,
+                None,
+                dead_line,
+                &report_tx,
+            )
+            .await;
+        if let NextStep::Stop = step {
+            break;
+        }
     }
+    client_state.close().await;
 }
 
-/// Run n tasks by m workers
-pub async fn work(
-    client: Client,
-    report_tx: flume::Sender<Result<RequestResult, ClientError>>,
-    n_tasks: usize,
-    n_connections: usize,
+/// A HTTP/2 connection with `n_http2_parallel` workers on it, which sends the requests of
+/// `schedule`. It connects again when the server closes the connection.
+async fn http2_connection(
+    client: Arc<Client>,
+    schedule: Schedule,
+    worker: usize,
     n_http2_parallel: usize,
+    dead_line: Option<std::time::Instant>,
+    report_tx: ReportSender,
 ) {
-    use std::sync::atomic::{AtomicUsize, Ordering};
-    let counter = Arc::new(AtomicUsize::new(0));
-
-    let client = Arc::new(client);
-
-    if client.is_http2() {
-        let futures = (0..n_connections)
-            .map(|_| {
-                let report_tx = report_tx.clone();
-                let counter = counter.clone();
-                let client = client.clone();
-                tokio::spawn(async move {
-                    loop {
-                        match setup_http2(&client).await {
-                            Ok((connection_time, client_state)) => {
-                                let futures = (0..n_http2_parallel)
-                                    .map(|_| {
-                                        let report_tx = report_tx.clone();
-                                        let counter = counter.clone();
-                                        let client = client.clone();
-
-                                        let mut client_state = client_state.clone();
-                                        tokio::spawn(async move {
-                                            while counter.fetch_add(1, Ordering::Relaxed) < n_tasks
-                                            {
-                                                let mut res = client
-                                                    .work_http2(&mut client_state, None)
-                                                    .await;
-                                                let is_cancel = is_cancel_error(&res);
-                                                let is_reconnect = is_hyper_error(&res);
-                                                set_connection_time(&mut res, connection_time);
-                                                report_tx.send_async(res).await.unwrap();
-                                                if is_cancel || is_reconnect {
-                                                    return is_cancel;
-                                                }
-                                            }
-
-                                            true
-                                        })
-                                    })
-                                    .collect::<Vec<_>>();
-
-                                let mut connection_gone = false;
-                                for f in futures {
-                                    match f.await {
-                                        Ok(true) => {
-                                            // All works done
-                                            connection_gone = true;
-                                        }
-                                        Err(_) => {
-                                            // Unexpected
-                                            connection_gone = true;
-                                        }
-                                        _ => {}
-                                    }
-                                }
-
-                                if connection_gone {
-                                    return;
-                                }
-                            }
-                            Err(err) => {
-                                if counter.fetch_add(1, Ordering::Relaxed) < n_tasks {
-                                    report_tx.send_async(Err(err)).await.unwrap();
-                                } else {
-                                    return;
+    if !client.ramp_up_wait(worker, dead_line).await {
+        return;
+    }
+    let _finish = client.ramp_up_finish();
+    // The workers keep their generators over the reconnections
+    let mut rngs = (0..n_http2_parallel)
+        .map(|i| client.rng(WorkerRng::Requests((worker * n_http2_parallel + i) as u64)))
+        .collect::<Vec<_>>();
+    loop {
+        match setup_http2(&client, worker as u64).await {
+            Ok((connection_time, client_state)) => {
+                let futures = rngs
+                    .drain(..)
+                    .map(|rng| {
+                        let client = client.clone();
+                        let schedule = schedule.clone();
+                        let report_tx = report_tx.clone();
+                        let mut client_state = client_state.fork(rng);
+                        // Whether the connection is done with, rather than to reconnect
+                        tokio::spawn(async move {
+                            while let Some(due) = schedule.next(&client, None).await {
+                                let step = client
+                                    .execute_and_report(
+                                        WorkerState::Http2(&mut client_state),
+                                        due,
+                                        connection_time,
+                                        dead_line,
+                                        &report_tx,
+                                    )
+                                    .await;
+                                match step {
+                                    NextStep::Next => {}
+                                    NextStep::Reconnect => return (false, client_state.rng),
+                                    NextStep::Stop => return (true, client_state.rng),
                                 }
                             }
-                        }
-                    }
-                })
-            })
-            .collect::<Vec<_>>();
-        for f in futures {
-            let _ = f.await;
-        }
-    } else {
-        let futures = (0..n_connections)
-            .map(|_| {
-                let report_tx = report_tx.clone();
-                let counter = counter.clone();
-                let client = client.clone();
-                tokio::spawn(async move {
-                    let mut client_state = ClientStateHttp1::default();
-                    while counter.fetch_add(1, Ordering::Relaxed) < n_tasks {
-                        let res = client.work_http1(&mut client_state, None).await;
-                        let is_cancel = is_cancel_error(&res);
-                        report_tx.send_async(res).await.unwrap();
-                        if is_cancel {
-                            break;
-                        }
+                            // All works done
+                            (true, client_state.rng)
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                let mut connection_gone = false;
+                for f in futures {
+                    match f.await {
+                        Ok((false, rng)) => rngs.push(rng),
+                        // A panic is unexpected
+                        Ok((true, _)) | Err(_) => connection_gone = true,
                     }
-                })
-            })
-            .collect::<Vec<_>>();
-        for f in futures {
-            let _ = f.await;
-        }
-    };
-}
-
-/// n tasks by m workers limit to qps works in a second
-pub async fn work_with_qps(
-    client: Client,
-    report_tx: flume::Sender<Result<RequestResult, ClientError>>,
-    query_limit: QueryLimit,
-    n_tasks: usize,
-    n_connections: usize,
-    n_http2_parallel: usize,
-) {
-    let (tx, rx) = flume::unbounded();
-
-    match query_limit {
-        QueryLimit::Qps(qps) => {
-            tokio::spawn(async move {
-                let start = std::time::Instant::now();
-                for i in 0..n_tasks {
-                    tokio::time::sleep_until(
-                        (start + i as u32 * std::time::Duration::from_secs(1) / qps as u32).into(),
-                    )
-                    .await;
-                    tx.send_async(()).await.unwrap();
                 }
-                // tx gone
-            });
-        }
-        QueryLimit::Burst(duration, rate) => {
-            tokio::spawn(async move {
-                let mut n = 0;
-                // Handle via rate till n_tasks out of bound
-                while n + rate < n_tasks {
-                    tokio::time::sleep(duration).await;
-                    for _ in 0..rate {
-                        tx.send_async(()).await.unwrap();
-                    }
-                    n += rate;
+                if connection_gone {
+                    return;
                 }
-                // Handle the remaining tasks
-                if n_tasks > n {
-                    tokio::time::sleep(duration).await;
-                    for _ in 0..n_tasks - n {
-                        tx.send_async(()).await.unwrap();
-                    }
+            }
+            Err(err) => {
+                // The failure takes the place of a request
+                if schedule.next(&client, None).await.is_none() {
+                    return;
                 }
-                // tx gone
-            });
+                report_tx.send(Err(err)).await.unwrap();
+                if dead_line.is_some_and(|dead_line| std::time::Instant::now() >= dead_line) {
+                    return;
+                }
+            }
         }
     }
-
-    let client = Arc::new(client);
-
-    if client.is_http2() {
-        let futures = (0..n_connections)
-            .map(|_| {
-                let report_tx = report_tx.clone();
-                let rx = rx.clone();
-                let client = client.clone();
-                tokio::spawn(async move {
-                    loop {
-                        match setup_http2(&client).await {
-                            Ok((connection_time, client_state)) => {
-                                let futures = (0..n_http2_parallel)
-                                    .map(|_| {
-                                        let report_tx = report_tx.clone();
-                                        let rx = rx.clone();
-                                        let client = client.clone();
-                                        let mut client_state = client_state.clone();
-                                        tokio::spawn(async move {
-                                            while let Ok(()) = rx.recv_async().await {
-                                                let mut res = client
-                                                    .work_http2(&mut client_state, None)
-                                                    .await;
-                                                let is_cancel = is_cancel_error(&res);
-                                                let is_reconnect = is_hyper_error(&res);
-                                                set_connection_time(&mut res, connection_time);
-                                                report_tx.send_async(res).await.unwrap();
-                                                if is_cancel || is_reconnect {
-                                                    return is_cancel;
-                                                }
-                                            }
-                                            true
-                                        })
-                                    })
-                                    .collect::<Vec<_>>();
-                                let mut connection_gone = false;
-                                for f in futures {
-                                    match f.await {
-                                        Ok(true) => {
-                                            // All works done
-                                            connection_gone = true;
-                                        }
-                                        Err(_) => {
-                                            // Unexpected
-                                            connection_gone = true;
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                if connection_gone {
-                                    return;
-                                }
-                            }
-                            Err(err) => {
-                                // Consume a task
-                                if let Ok(()) = rx.recv_async().await {
-                                    report_tx.send_async(Err(err)).await.unwrap();
-                                } else {
-                                    return;
-                                }
-                            }
-                        }
-                    }
-                })
-            })
-            .collect::<Vec<_>>();
-        for f in futures {
-            let _ = f.await;
-        }
-    } else {
-        let futures = (0..n_connections)
-            .map(|_| {
-                let report_tx = report_tx.clone();
-                let rx = rx.clone();
-                let client = client.clone();
-                tokio::spawn(async move {
-                    let mut client_state = ClientStateHttp1::default();
-                    while let Ok(()) = rx.recv_async().await {
-                        let res = client.work_http1(&mut client_state, None).await;
-                        let is_cancel = is_cancel_error(&res);
-                        report_tx.send_async(res).await.unwrap();
-                        if is_cancel {
-                            break;
-                        }
-                    }
-                })
-            })
-            .collect::<Vec<_>>();
-        for f in futures {
-            let _ = f.await;
-        }
-    };
 }
 
-/// n tasks by m workers limit to qps works in a second with latency correction
-pub async fn work_with_qps_latency_correction(
-    client: Client,
-    report_tx: flume::Sender<Result<RequestResult, ClientError>>,
-    query_limit: QueryLimit,
-    n_tasks: usize,
+/// Run the workers of `schedule` on the connections of `workload`
+async fn run_workers(
+    client: Arc<Client>,
+    report_tx: ReportSender,
+    schedule: Schedule,
+    workload: Workload,
     n_connections: usize,
-    n_http2_parallel: usize,
 ) {
-    let (tx, rx) = flume::unbounded();
-
-    match query_limit {
-        QueryLimit::Qps(qps) => {
-            tokio::spawn(async move {
-                let start = std::time::Instant::now();
-                for i in 0..n_tasks {
-                    tokio::time::sleep_until(
-                        (start + i as u32 * std::time::Duration::from_secs(1) / qps as u32).into(),
-                    )
-                    .await;
-                    tx.send_async(std::time::Instant::now()).await.unwrap();
-                }
-                // tx gone
-            });
-        }
-        QueryLimit::Burst(duration, rate) => {
-            tokio::spawn(async move {
-                let mut n = 0;
-                // Handle via rate till n_tasks out of bound
-                while n + rate < n_tasks {
-                    tokio::time::sleep(duration).await;
-                    let now = std::time::Instant::now();
-                    for _ in 0..rate {
-                        tx.send_async(now).await.unwrap();
-                    }
-                    n += rate;
-                }
-                // Handle the remaining tasks
-                if n_tasks > n {
-                    tokio::time::sleep(duration).await;
-                    let now = std::time::Instant::now();
-                    for _ in 0..n_tasks - n {
-                        tx.send_async(now).await.unwrap();
-                    }
-                }
-                // tx gone
-            });
-        }
+    let futures = (0..n_connections)
+        .map(|worker| {
+            let client = client.clone();
+            let schedule = schedule.clone();
+            let report_tx = report_tx.clone();
+            if client.is_http2() {
+                tokio::spawn(http2_connection(
+                    client,
+                    schedule,
+                    worker,
+                    workload.n_http2_parallel,
+                    workload.dead_line,
+                    report_tx,
+                ))
+            } else {
+                let client_state =
+                    ClientStateHttp1::new(&client, client.rng(WorkerRng::Requests(worker as u64)));
+                tokio::spawn(http1_worker(
+                    client,
+                    client_state,
+                    schedule,
+                    worker,
+                    workload.dead_line,
+                    report_tx,
+                ))
+            }
+        })
+        .collect::<Vec<_>>();
+    for f in futures {
+        let _ = f.await;
     }
+}
 
-    let client = Arc::new(client);
+/// Send the requests of `workload` as fast as the workers go
+pub async fn work(client: Client, report_tx: ReportSender, workload: Workload) {
+    let n_connections = client.connections_for(
+        workload.n_tasks,
+        workload.n_connections,
+        workload.n_http2_parallel,
+    );
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    if let Some(threads) = client.uring_threads {
+        return work_uring(client, report_tx, workload, n_connections, threads).await;
+    }
 
-    if client.is_http2() {
-        let futures = (0..n_connections)
-            .map(|_| {
-                let report_tx = report_tx.clone();
-                let rx = rx.clone();
-                let client = client.clone();
-                tokio::spawn(async move {
-                    loop {
-                        match setup_http2(&client).await {
-                            Ok((connection_time, client_state)) => {
-                                let futures = (0..n_http2_parallel)
-                                    .map(|_| {
-                                        let report_tx = report_tx.clone();
-                                        let rx = rx.clone();
-                                        let client = client.clone();
-                                        let mut client_state = client_state.clone();
-                                        tokio::spawn(async move {
-                                            while let Ok(start) = rx.recv_async().await {
-                                                let mut res = client
-                                                    .work_http2(&mut client_state, None)
-                                                    .await;
-                                                let is_cancel = is_cancel_error(&res);
-                                                let is_reconnect = is_hyper_error(&res);
-                                                set_connection_time(&mut res, connection_time);
-                                                set_start_latency_correction(&mut res, start);
-                                                report_tx.send_async(res).await.unwrap();
-                                                if is_cancel || is_reconnect {
-                                                    return is_cancel;
-                                                }
-                                            }
-                                            true
-                                        })
-                                    })
-                                    .collect::<Vec<_>>();
-                                let mut connection_gone = false;
-                                for f in futures {
-                                    match f.await {
-                                        Ok(true) => {
-                                            // All works done
-                                            connection_gone = true;
-                                        }
-                                        Err(_) => {
-                                            // Unexpected
-                                            connection_gone = true;
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                if connection_gone {
-                                    return;
-                                }
-                            }
-                            Err(err) => {
-                                // Consume a task
-                                if rx.recv_async().await.is_ok() {
-                                    report_tx.send_async(Err(err)).await.unwrap();
-                                } else {
-                                    return;
-                                }
-                            }
-                        }
-                    }
-                })
-            })
-            .collect::<Vec<_>>();
-        for f in futures {
-            let _ = f.await;
-        }
-    } else {
-        let futures = (0..n_connections)
-            .map(|_| {
-                let client = client.clone();
-                let mut client_state = ClientStateHttp1::default();
-                let report_tx = report_tx.clone();
-                let rx = rx.clone();
-                tokio::spawn(async move {
-                    while let Ok(start) = rx.recv_async().await {
-                        let mut res = client.work_http1(&mut client_state, None).await;
-                        set_start_latency_correction(&mut res, start);
-                        let is_cancel = is_cancel_error(&res);
-                        report_tx.send_async(res).await.unwrap();
-                        if is_cancel {
-                            break;
-                        }
-                    }
-                })
-            })
-            .collect::<Vec<_>>();
-        for f in futures {
-            let _ = f.await;
-        }
-    };
+    run_workers(
+        Arc::new(client),
+        report_tx,
+        Schedule::free(workload.n_tasks),
+        workload,
+        n_connections,
+    )
+    .await;
 }
 
-/// Run until dead_line by n workers
-pub async fn work_until(
+/// `work` of HTTP/1.x with `--io-backend uring`. The workers are spread over `threads` threads,
+/// each with a current-thread runtime and an io_uring of its own.
+#[cfg(all(feature = "uring", target_os = "linux"))]
+async fn work_uring(
     client: Client,
-    report_tx: flume::Sender<Result<RequestResult, ClientError>>,
-    dead_line: std::time::Instant,
+    report_tx: ReportSender,
+    workload: Workload,
     n_connections: usize,
-    n_http2_parallel: usize,
+    threads: usize,
 ) {
+    let schedule = Schedule::free(workload.n_tasks);
     let client = Arc::new(client);
-    if client.is_http2() {
-        let futures = (0..n_connections)
-            .map(|_| {
-                let client = client.clone();
-                let report_tx = report_tx.clone();
-                tokio::spawn(async move {
-                    // Keep trying to establish or re-establish connections up to the deadline
-                    loop {
-                        match setup_http2(&client).await {
-                            Ok((connection_time, client_state)) => {
-                                // Setup the parallel workers for each HTTP2 connection
-                                let futures = (0..n_http2_parallel)
-                                    .map(|_| {
-                                        let client = client.clone();
-                                        let report_tx = report_tx.clone();
-                                        let mut client_state = client_state.clone();
-                                        tokio::spawn(async move {
-                                            // This is where HTTP2 loops to make all the requests for a given client and worker
-                                            loop {
-                                                let mut res = client
-                                                    .work_http2(&mut client_state, Some(dead_line))
-                                                    .await;
-                                                let is_cancel = is_cancel_error(&res);
-                                                let is_reconnect = is_hyper_error(&res);
-                                                set_connection_time(&mut res, connection_time);
-                                                report_tx.send_async(res).await.unwrap();
-                                                if is_cancel || is_reconnect {
-                                                    break is_cancel;
-                                                }
-                                            }
-                                        })
-                                    })
-                                    .collect::<Vec<_>>();
-
-                                let (is_cancel, _, rest) =
-                                    futures::future::select_all(futures).await;
-                                for f in rest {
-                                    let _ = f.await;
-                                }
 
-                                if matches!(is_cancel, Ok(true)) {
-                                    break;
-                                }
-                            }
-
-                            Err(err) => {
-                                report_tx.send_async(Err(err)).await.unwrap();
-                                if tokio::time::Instant::now() >= dead_line.into() {
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                })
-            })
-            .collect::<Vec<_>>();
-        for f in futures {
-            let _ = f.await;
-        }
-    } else {
-        let futures = (0..n_connections)
-            .map(|_| {
-                let client = client.clone();
-                let report_tx = report_tx.clone();
-                let mut client_state = ClientStateHttp1::default();
-                tokio::spawn(async move {
-                    loop {
-                        // This is where HTTP1 loops to make all the requests for a given client
-                        let res = client.work_http1(&mut client_state, Some(dead_line)).await;
-                        let is_cancel = is_cancel_error(&res);
-                        report_tx.send_async(res).await.unwrap();
-                        if is_cancel {
-                            break;
+    let handles = (0..threads.min(n_connections))
+        .filter_map(|thread| {
+            let report_tx = report_tx.clone();
+            let schedule = schedule.clone();
+            let client = client.clone();
+            let spawned = std::thread::Builder::new()
+                .name(format!("oha-uring-{thread}"))
+                .spawn(move || {
+                    uring::block_on(async move {
+                        let futures = (thread..n_connections)
+                            .step_by(threads)
+                            .map(|worker| {
+                                let client_state = ClientStateHttp1::new(
+                                    &client,
+                                    client.rng(WorkerRng::Requests(worker as u64)),
+                                );
+                                tokio::spawn(http1_worker(
+                                    client.clone(),
+                                    client_state,
+                                    schedule.clone(),
+                                    worker,
+                                    workload.dead_line,
+                                    report_tx.clone(),
+                                ))
+                            })
+                            .collect::<Vec<_>>();
+                        for f in futures {
+                            let _ = f.await;
                         }
-                    }
-                })
-            })
-            .collect::<Vec<_>>();
-        for f in futures {
-            let _ = f.await;
+                    })
+                });
+            match spawned {
+                Ok(handle) => Some(handle),
+                Err(err) => {
+                    eprintln!("failed to start an io_uring thread: {err}");
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let _ = tokio::task::spawn_blocking(move || {
+        for handle in handles {
+            match handle.join() {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => eprintln!("failed to set up an io_uring: {err}"),
+                // The panic was printed by the thread
+                Err(_) => {}
+            }
         }
-    };
+    })
+    .await;
 }
 
-/// Run until dead_line by n workers limit to qps works in a second
-pub async fn work_until_with_qps(
+/// Send the requests of `workload` at the times of `query_limit` from `start`. With
+/// `latency_correction`, the latency of each request counts from its scheduled time.
+pub async fn work_with_qps(
     client: Client,
-    report_tx: flume::Sender<Result<RequestResult, ClientError>>,
+    report_tx: ReportSender,
+    workload: Workload,
     query_limit: QueryLimit,
     start: std::time::Instant,
-    dead_line: std::time::Instant,
-    n_connections: usize,
-    n_http2_parallel: usize,
+    latency_correction: bool,
 ) {
-    let rx = match query_limit {
+    let n_connections = client.connections_for(
+        workload.n_tasks,
+        workload.n_connections,
+        workload.n_http2_parallel,
+    );
+    let rx = spawn_scheduler(&client, query_limit, start, latency_correction, workload);
+    let schedule = Schedule::timed(&client, rx, latency_correction);
+    run_workers(
+        Arc::new(client),
+        report_tx,
+        schedule,
+        workload,
+        n_connections,
+    )
+    .await;
+}
+
+/// Release the requests of `query_limit` to the workers at their scheduled time, until
+/// `workload` is sent
+fn spawn_scheduler(
+    client: &Client,
+    query_limit: QueryLimit,
+    start: std::time::Instant,
+    latency_correction: bool,
+    workload: Workload,
+) -> flume::Receiver<std::time::Instant> {
+    let Workload {
+        n_tasks, dead_line, ..
+    } = workload;
+    match query_limit {
         QueryLimit::Qps(qps) => {
-            let (tx, rx) = flume::bounded(qps);
+            // Without latency correction, a run until the dead line has at most a second of
+            // requests waiting for a worker
+            let (tx, rx) = match dead_line {
+                Some(_) if !latency_correction => {
+                    flume::bounded((qps.max().ceil() as usize).max(1))
+                }
+                _ => flume::unbounded(),
+            };
+            let mut arrivals = client.arrivals(qps, start);
             tokio::spawn(async move {
-                for i in 0.. {
-                    if std::time::Instant::now() > dead_line {
+                let past = |at| dead_line.is_some_and(|dead_line| at >= dead_line);
+                for _ in 0..n_tasks {
+                    let scheduled = arrivals.next();
+                    if past(scheduled) {
                         break;
                     }
-                    tokio::time::sleep_until(
-                        (start + i as u32 * std::time::Duration::from_secs(1) / qps as u32).into(),
-                    )
-                    .await;
-                    if tx.send_async(()).await.is_err() {
+                    let scheduled = arrivals.wait(scheduled, dead_line).await;
+                    if past(scheduled) || tx.send_async(scheduled).await.is_err() {
                         break;
                     }
                 }
+                // The run lasts until the dead line even when the last request is due before it
+                if let Some(dead_line) = dead_line {
+                    tokio::time::sleep_until(dead_line.into()).await;
+                }
                 // tx gone
             });
             rx
         }
         QueryLimit::Burst(duration, rate) => {
             let (tx, rx) = flume::unbounded();
+            let releases = client.burst_releases.clone();
             tokio::spawn(async move {
-                // Handle via rate till deadline is reached
-                for _ in 0.. {
-                    if std::time::Instant::now() > dead_line {
+                let mut n = 0;
+                while n < n_tasks {
+                    tokio::time::sleep(duration).await;
+                    let now = std::time::Instant::now();
+                    if dead_line.is_some_and(|dead_line| now > dead_line) {
                         break;
                     }
-
-                    tokio::time::sleep(duration).await;
-                    for _ in 0..rate {
-                        tx.send_async(()).await.unwrap();
+                    releases.release(now);
+                    // The last burst has the remaining tasks
+                    let burst = (rate as u64).min(n_tasks - n);
+                    for _ in 0..burst {
+                        if tx.send_async(now).await.is_err() {
+                            return;
+                        }
                     }
+                    n += burst;
                 }
                 // tx gone
             });
             rx
         }
-    };
-
-    let client = Arc::new(client);
-
-    if client.is_http2() {
-        let futures = (0..n_connections)
-            .map(|_| {
-                let client = client.clone();
-                let report_tx = report_tx.clone();
-                let rx = rx.clone();
-                tokio::spawn(async move {
-                    loop {
-                        match setup_http2(&client).await {
-                            Ok((connection_time, client_state)) => {
-                                let futures = (0..n_http2_parallel)
-                                    .map(|_| {
-                                        let client = client.clone();
-                                        let report_tx = report_tx.clone();
-                                        let rx = rx.clone();
-                                        let mut client_state = client_state.clone();
-                                        tokio::spawn(async move {
-                                            while let Ok(()) = rx.recv_async().await {
-                                                let mut res = client
-                                                    .work_http2(&mut client_state, Some(dead_line))
-                                                    .await;
-                                                let is_cancel = is_cancel_error(&res);
-                                                let is_reconnect = is_hyper_error(&res);
-                                                set_connection_time(&mut res, connection_time);
-                                                report_tx.send_async(res).await.unwrap();
-                                                if is_cancel || is_reconnect {
-                                                    return is_cancel;
-                                                }
-                                            }
-                                            true
-                                        })
-                                    })
-                                    .collect::<Vec<_>>();
-                                let mut connection_gone = false;
-                                for f in futures {
-                                    match f.await {
-                                        Ok(true) => {
-                                            // All works done
-                                            connection_gone = true;
-                                        }
-                                        Err(_) => {
-                                            // Unexpected
-                                            connection_gone = true;
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                if connection_gone {
-                                    return;
-                                }
-                            }
-                            Err(err) => {
-                                // Consume a task
-                                if rx.recv_async().await.is_ok() {
-                                    report_tx.send_async(Err(err)).await.unwrap();
-                                } else {
-                                    return;
-                                }
-                            }
-                        }
-                    }
-                })
-            })
-            .collect::<Vec<_>>();
-
-        for f in futures {
-            let _ = f.await;
-        }
-    } else {
-        let futures = (0..n_connections)
-            .map(|_| {
-                let client = client.clone();
-                let mut client_state = ClientStateHttp1::default();
-                let report_tx = report_tx.clone();
-                let rx = rx.clone();
-                tokio::spawn(async move {
-                    while let Ok(()) = rx.recv_async().await {
-                        let res = client.work_http1(&mut client_state, Some(dead_line)).await;
-                        let is_cancel = is_cancel_error(&res);
-                        report_tx.send_async(res).await.unwrap();
-                        if is_cancel {
-                            break;
-                        }
-                    }
-                })
-            })
-            .collect::<Vec<_>>();
-
-        for f in futures {
-            let _ = f.await;
+        QueryLimit::Profile(profile) => {
+            // Enough to keep all the workers busy on `max` steps
+            let (tx, rx) = flume::bounded(workload.n_connections * workload.n_http2_parallel);
+            tokio::spawn(async move { profile.release(start, tx, std::time::Instant::now).await });
+            rx
         }
     }
 }
 
-/// Run until dead_line by n workers limit to qps works in a second with latency correction
-pub async fn work_until_with_qps_latency_correction(
+/// Start requests at `rate` per second on a fixed timeline no matter how many are in flight, up
+/// to `max_in_flight`. Runs until the tasks of `workload` are started or its dead line.
+/// HTTP/1 connections are opened as needed and reused when idle, and HTTP/2 requests are spread
+/// over `n_connections` connections.
+pub async fn work_with_arrival_rate(
     client: Client,
-    report_tx: flume::Sender<Result<RequestResult, ClientError>>,
-    query_limit: QueryLimit,
-    start: std::time::Instant,
-    dead_line: std::time::Instant,
-    n_connections: usize,
-    n_http2_parallel: usize,
+    report_tx: ReportSender,
+    workload: Workload,
+    rate: f64,
+    max_in_flight: usize,
 ) {
-    let (tx, rx) = flume::unbounded();
-    match query_limit {
-        QueryLimit::Qps(qps) => {
-            tokio::spawn(async move {
-                for i in 0.. {
-                    tokio::time::sleep_until(
-                        (start + i as u32 * std::time::Duration::from_secs(1) / qps as u32).into(),
-                    )
-                    .await;
-                    let now = std::time::Instant::now();
-                    if now > dead_line {
-                        break;
-                    }
-                    if tx.send_async(now).await.is_err() {
-                        break;
-                    }
-                }
-                // tx gone
-            });
+    let Workload {
+        n_tasks, dead_line, ..
+    } = workload;
+    let client = Arc::new(client);
+    let in_flight = Arc::new(tokio::sync::Semaphore::new(max_in_flight));
+    let idle_http1: Arc<std::sync::Mutex<Vec<ClientStateHttp1>>> = Default::default();
+    // Connected by the first request on each
+    let http2 = Arc::new(
+        (0..workload.n_connections)
+            .map(|_| tokio::sync::OnceCell::new())
+            .collect::<Vec<_>>(),
+    );
+
+    let mut arrivals = client.arrivals(Rate::Fixed(rate), std::time::Instant::now());
+    for i in 0..n_tasks {
+        let scheduled = arrivals.next();
+        if dead_line.is_some_and(|dead_line| scheduled >= dead_line) {
+            break;
         }
-        QueryLimit::Burst(duration, rate) => {
-            tokio::spawn(async move {
-                // Handle via rate till deadline is reached
-                loop {
-                    tokio::time::sleep(duration).await;
-                    let now = std::time::Instant::now();
-                    if now > dead_line {
-                        break;
-                    }
-
-                    for _ in 0..rate {
-                        tx.send_async(now).await.unwrap();
-                    }
-                }
-                // tx gone
-            });
+        let Some(scheduled) = client
+            .unless_stopped(arrivals.wait(scheduled, dead_line))
+            .await
+        else {
+            break;
+        };
+        if dead_line.is_some_and(|dead_line| scheduled >= dead_line) {
+            break;
         }
-    };
-
-    let client = Arc::new(client);
-
-    if client.is_http2() {
-        let futures = (0..n_connections)
-            .map(|_| {
-                let client = client.clone();
-                let report_tx = report_tx.clone();
-                let rx = rx.clone();
-                tokio::spawn(async move {
-                    loop {
-                        match setup_http2(&client).await {
-                            Ok((connection_time, client_state)) => {
-                                let futures = (0..n_http2_parallel)
-                                    .map(|_| {
-                                        let client = client.clone();
-                                        let report_tx = report_tx.clone();
-                                        let rx = rx.clone();
-                                        let mut client_state = client_state.clone();
-                                        tokio::spawn(async move {
-                                            while let Ok(start) = rx.recv_async().await {
-                                                let mut res = client
-                                                    .work_http2(&mut client_state, Some(dead_line))
-                                                    .await;
-                                                set_start_latency_correction(&mut res, start);
-                                                set_connection_time(&mut res, connection_time);
-                                                let is_cancel = is_cancel_error(&res);
-                                                let is_reconnect = is_hyper_error(&res);
-                                                report_tx.send_async(res).await.unwrap();
-                                                if is_cancel || is_reconnect {
-                                                    return is_cancel;
-                                                }
-                                            }
-                                            true
-                                        })
-                                    })
-                                    .collect::<Vec<_>>();
-                                let mut connection_gone = false;
-                                for f in futures {
-                                    match f.await {
-                                        Ok(true) => {
-                                            // All works done
-                                            connection_gone = true;
-                                        }
-                                        Err(_) => {
-                                            // Unexpected
-                                            connection_gone = true;
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                if connection_gone {
-                                    return;
-                                }
-                            }
-
-                            Err(err) => {
-                                if rx.recv_async().await.is_ok() {
-                                    report_tx.send_async(Err(err)).await.unwrap();
-                                } else {
-                                    return;
-                                }
-                            }
+        if client
+            .abort
+            .as_ref()
+            .is_some_and(|abort| abort.is_aborted())
+        {
+            break;
+        }
+        let permit = match in_flight.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                client.arrival_counters.late.fetch_add(1, Ordering::Relaxed);
+                tokio::select! {
+                    permit = in_flight.clone().acquire_owned() => permit.unwrap(),
+                    _ = async {
+                        match dead_line {
+                            Some(dead_line) => tokio::time::sleep_until(dead_line.into()).await,
+                            None => std::future::pending().await,
                         }
-                    }
-                })
-            })
-            .collect::<Vec<_>>();
-
-        for f in futures {
-            let _ = f.await;
-        }
-    } else {
-        let futures = (0..n_connections)
-            .map(|_| {
-                let client = client.clone();
-                let mut client_state = ClientStateHttp1::default();
-                let report_tx = report_tx.clone();
-                let rx = rx.clone();
-                tokio::spawn(async move {
-                    while let Ok(start) = rx.recv_async().await {
-                        let mut res = client.work_http1(&mut client_state, Some(dead_line)).await;
-                        set_start_latency_correction(&mut res, start);
-                        let is_cancel = is_cancel_error(&res);
-                        report_tx.send_async(res).await.unwrap();
-                        if is_cancel {
-                            break;
+                    } => break,
+                }
+            }
+        };
+
+        let client = client.clone();
+        let report_tx = report_tx.clone();
+        let idle_http1 = idle_http1.clone();
+        let http2 = http2.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            // The time waiting for a slot under the cap counts as latency
+            let due = Due::Arrival(scheduled);
+            if client.is_http2() {
+                let c = i % http2.len() as u64;
+                match http2[c as usize]
+                    .get_or_try_init(|| setup_http2(&client, c))
+                    .await
+                {
+                    Ok((connection_time, client_state)) => {
+                        let mut client_state =
+                            client_state.fork(client.rng(WorkerRng::Requests(i)));
+                        // The connection may have been replaced since it was set up
+                        client_state.connection =
+                            client_state.shared_connection.lock().await.clone();
+                        let step = client
+                            .execute_and_report(
+                                WorkerState::Http2(&mut client_state),
+                                due,
+                                *connection_time,
+                                dead_line,
+                                &report_tx,
+                            )
+                            .await;
+                        if let NextStep::Reconnect = step {
+                            // The next request finds the new connection, or tries again
+                            let _ = client_state.reconnect(&client).await;
                         }
                     }
-                })
-            })
-            .collect::<Vec<_>>();
+                    Err(err) => report_tx.send(Err(err)).await.unwrap(),
+                }
+            } else {
+                let client_state = idle_http1.lock().unwrap().pop();
+                let mut client_state = client_state.unwrap_or_else(|| {
+                    ClientStateHttp1::new(&client, client.rng(WorkerRng::Requests(i)))
+                });
+                client
+                    .execute_and_report(
+                        WorkerState::Http1(&mut client_state),
+                        due,
+                        None,
+                        dead_line,
+                        &report_tx,
+                    )
+                    .await;
+                idle_http1.lock().unwrap().push(client_state);
+            }
+        });
+    }
 
-        for f in futures {
-            let _ = f.await;
-        }
+    // Wait for the requests in flight
+    let _ = in_flight.acquire_many(max_in_flight as u32).await;
+    let idle_http1 = std::mem::take(&mut *idle_http1.lock().unwrap());
+    for mut client_state in idle_http1 {
+        client_state.close().await;
     }
 }
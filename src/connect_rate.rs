@@ -0,0 +1,103 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// The token bucket of `--connect-rate`, which every new connection takes a token from.
+/// The bucket holds a single token, so that the connections are spread evenly instead of
+/// starting in a burst.
+pub struct ConnectRate {
+    interval: Duration,
+    /// When the next token is available
+    next: Mutex<Instant>,
+}
+
+impl ConnectRate {
+    pub fn new(per_second: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / per_second),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait for a token
+    pub async fn acquire(&self) {
+        tokio::time::sleep_until(self.take(Instant::now()).into()).await;
+    }
+
+    /// When the token taken at `now` is available
+    fn take(&self, now: Instant) -> Instant {
+        let mut next = self.next.lock().unwrap();
+        let at = (*next).max(now);
+        *next = at + self.interval;
+        at
+    }
+}
+
+/// When the first connections of the run were established, one for each worker
+/// unless some of them reconnected meanwhile
+pub struct StartupConnects {
+    /// `-c`
+    target: usize,
+    established: AtomicUsize,
+    done: OnceLock<Instant>,
+}
+
+impl StartupConnects {
+    pub fn new(target: usize) -> Self {
+        Self {
+            target,
+            established: AtomicUsize::new(0),
+            done: OnceLock::new(),
+        }
+    }
+
+    pub fn target(&self) -> usize {
+        self.target
+    }
+
+    /// Count an established connection
+    pub fn established(&self) {
+        if self.established.fetch_add(1, Ordering::Relaxed) + 1 == self.target {
+            let _ = self.done.set(Instant::now());
+        }
+    }
+
+    /// When the `target`th connection was established. None until then.
+    pub fn done(&self) -> Option<Instant> {
+        self.done.get().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect_rate() {
+        let rate = ConnectRate::new(10.0);
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+        // A token at once, then one every 100ms
+        assert_eq!(rate.take(t0), t0);
+        assert_eq!(rate.take(t0), ms(100));
+        assert_eq!(rate.take(ms(50)), ms(200));
+        // Tokens don't pile up while idle
+        assert_eq!(rate.take(ms(1000)), ms(1000));
+        assert_eq!(rate.take(ms(1000)), ms(1100));
+    }
+
+    #[test]
+    fn test_startup_connects() {
+        let startup = StartupConnects::new(2);
+        startup.established();
+        assert!(startup.done().is_none());
+        startup.established();
+        let done = startup.done().unwrap();
+        startup.established();
+        assert_eq!(startup.done(), Some(done));
+    }
+}
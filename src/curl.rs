@@ -0,0 +1,339 @@
+use std::{path::PathBuf, str::FromStr};
+
+use thiserror::Error;
+
+/// A request specified by a curl command line for `--from-curl`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CurlCommand {
+    pub url: Option<String>,
+    pub method: Option<String>,
+    pub headers: Vec<String>,
+    pub data: Vec<Data>,
+    pub user: Option<String>,
+    pub insecure: bool,
+}
+
+/// A value of `-d`, `--data-binary` or `--data-raw`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Data {
+    Text(String),
+    /// `-d @file`. curl removes CR and LF from the content.
+    File(PathBuf),
+    /// `--data-binary @file`
+    BinaryFile(PathBuf),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CurlError {
+    #[error("unterminated quote in the curl command")]
+    UnterminatedQuote,
+    #[error("the curl command doesn't start with curl")]
+    NotCurl,
+    #[error("{0} requires a value")]
+    MissingValue(String),
+    #[error("more than one URL in the curl command: {0}, {1}")]
+    MultipleUrls(String, String),
+    #[error("unsupported curl options: {}", .0.join(", "))]
+    Unsupported(Vec<String>),
+}
+
+/// Split a command line in the same way as a POSIX shell does, including `$'...'` which
+/// browsers use in "Copy as cURL".
+pub fn tokenize(s: &str) -> Result<Vec<String>, CurlError> {
+    let mut tokens = Vec::new();
+    let mut current: Option<String> = None;
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(token) = current.take() {
+                    tokens.push(token);
+                }
+            }
+            '\\' => match chars.next() {
+                // Line continuation
+                Some('\n') => {}
+                Some(c) => current.get_or_insert_with(String::new).push(c),
+                None => current.get_or_insert_with(String::new).push('\\'),
+            },
+            '\'' => {
+                let token = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => token.push(c),
+                        None => return Err(CurlError::UnterminatedQuote),
+                    }
+                }
+            }
+            '"' => {
+                let token = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => token.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                token.push('\\');
+                                token.push(c);
+                            }
+                            None => return Err(CurlError::UnterminatedQuote),
+                        },
+                        Some(c) => token.push(c),
+                        None => return Err(CurlError::UnterminatedQuote),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                let token = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => token.push('\n'),
+                            Some('r') => token.push('\r'),
+                            Some('t') => token.push('\t'),
+                            Some('x') => {
+                                let hex: String = (0..2)
+                                    .filter_map(|_| chars.next_if(|c| c.is_ascii_hexdigit()))
+                                    .collect();
+                                match u8::from_str_radix(&hex, 16) {
+                                    Ok(b) => token.push(b as char),
+                                    Err(_) => token.push_str("\\x"),
+                                }
+                            }
+                            Some('u') => {
+                                let hex: String = (0..4)
+                                    .filter_map(|_| chars.next_if(|c| c.is_ascii_hexdigit()))
+                                    .collect();
+                                match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                                    Some(c) => token.push(c),
+                                    None => token.push_str("\\u"),
+                                }
+                            }
+                            Some(c) => token.push(c),
+                            None => return Err(CurlError::UnterminatedQuote),
+                        },
+                        Some(c) => token.push(c),
+                        None => return Err(CurlError::UnterminatedQuote),
+                    }
+                }
+            }
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(token) = current {
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// Options which don't change the request.
+const IGNORED: &[&str] = &[
+    "-s",
+    "--silent",
+    "-S",
+    "--show-error",
+    "-v",
+    "--verbose",
+    "-i",
+    "--include",
+    "-L",
+    "--location",
+    "--compressed",
+];
+
+impl FromStr for CurlCommand {
+    type Err = CurlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut tokens = tokens.into_iter();
+        if tokens.next().as_deref() != Some("curl") {
+            return Err(CurlError::NotCurl);
+        }
+
+        let mut command = CurlCommand::default();
+        let mut urls = Vec::new();
+        let mut unsupported = Vec::new();
+
+        while let Some(token) = tokens.next() {
+            // Support both `-XPOST` and `--request=POST`
+            let (flag, inline) = if let Some((flag, value)) =
+                token.split_once('=').filter(|_| token.starts_with("--"))
+            {
+                (flag.to_string(), Some(value.to_string()))
+            } else if token.len() > 2
+                && token.starts_with('-')
+                && !token.starts_with("--")
+                && ["-X", "-H", "-d", "-u"].contains(&&token[..2])
+            {
+                (token[..2].to_string(), Some(token[2..].to_string()))
+            } else {
+                (token.clone(), None)
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| tokens.next())
+                    .ok_or_else(|| CurlError::MissingValue(flag.clone()))
+            };
+
+            match flag.as_str() {
+                "-X" | "--request" => command.method = Some(value()?),
+                "-H" | "--header" => command.headers.push(value()?),
+                "-d" | "--data" | "--data-ascii" => {
+                    let value = value()?;
+                    command.data.push(match value.strip_prefix('@') {
+                        Some(path) => Data::File(path.into()),
+                        None => Data::Text(value),
+                    });
+                }
+                "--data-binary" => {
+                    let value = value()?;
+                    command.data.push(match value.strip_prefix('@') {
+                        Some(path) => Data::BinaryFile(path.into()),
+                        None => Data::Text(value),
+                    });
+                }
+                "--data-raw" => command.data.push(Data::Text(value()?)),
+                "-u" | "--user" => command.user = Some(value()?),
+                "-k" | "--insecure" => command.insecure = true,
+                "-I" | "--head" => command.method = Some("HEAD".to_string()),
+                "--url" => urls.push(value()?),
+                flag if IGNORED.contains(&flag) => {}
+                flag if flag.starts_with('-') && flag.len() > 1 => {
+                    unsupported.push(flag.to_string())
+                }
+                _ => urls.push(token),
+            }
+        }
+
+        // Values of unsupported options may be taken as URLs, so check them first
+        if !unsupported.is_empty() {
+            return Err(CurlError::Unsupported(unsupported));
+        }
+        let mut urls = urls.into_iter();
+        command.url = urls.next();
+        if let Some(url) = urls.next() {
+            return Err(CurlError::MultipleUrls(
+                command.url.unwrap_or_default(),
+                url,
+            ));
+        }
+
+        Ok(command)
+    }
+}
+
+impl CurlCommand {
+    /// Build the request body in the same way as curl. Multiple values are joined with `&`.
+    pub fn body(&self) -> std::io::Result<Option<Vec<u8>>> {
+        if self.data.is_empty() {
+            return Ok(None);
+        }
+
+        let mut body = Vec::new();
+        for (i, data) in self.data.iter().enumerate() {
+            if i > 0 {
+                body.push(b'&');
+            }
+            match data {
+                Data::Text(text) => body.extend_from_slice(text.as_bytes()),
+                Data::File(path) => body.extend(
+                    std::fs::read(path)?
+                        .into_iter()
+                        .filter(|&b| b != b'\r' && b != b'\n'),
+                ),
+                Data::BinaryFile(path) => body.extend(std::fs::read(path)?),
+            }
+        }
+        Ok(Some(body))
+    }
+
+    /// Whether a header is given by `-H`.
+    pub fn has_header(&self, name: &str) -> bool {
+        self.headers.iter().any(|h| {
+            h.split_once(':')
+                .is_some_and(|(n, _)| n.trim().eq_ignore_ascii_case(name))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize(r#"curl 'a b' "c \"d\" \$e" f\ g h\'i"#).unwrap(),
+            vec!["curl", "a b", r#"c "d" $e"#, "f g", "h'i"]
+        );
+        assert_eq!(
+            tokenize("curl \\\n  -k\t''").unwrap(),
+            vec!["curl", "-k", ""]
+        );
+        assert_eq!(
+            tokenize(r"curl $'a\nb\x41\'c' x'y'z").unwrap(),
+            vec!["curl", "a\nbA'c", "xyz"]
+        );
+        assert_eq!(
+            tokenize("curl 'abc").unwrap_err(),
+            CurlError::UnterminatedQuote
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let command: CurlCommand = r#"curl -XPUT 'http://localhost:3000/a?b=c' -H 'Content-Type: application/json' --data-raw '{"a": 1}' -u user:pass -k --compressed -s"#
+            .parse()
+            .unwrap();
+        assert_eq!(
+            command,
+            CurlCommand {
+                url: Some("http://localhost:3000/a?b=c".to_string()),
+                method: Some("PUT".to_string()),
+                headers: vec!["Content-Type: application/json".to_string()],
+                data: vec![Data::Text(r#"{"a": 1}"#.to_string())],
+                user: Some("user:pass".to_string()),
+                insecure: true,
+            }
+        );
+        assert!(command.has_header("content-type"));
+        assert_eq!(command.body().unwrap().unwrap(), br#"{"a": 1}"#);
+
+        let command: CurlCommand = "curl --url=http://a -d a=1 -d b=2 --data-binary @x"
+            .parse()
+            .unwrap();
+        assert_eq!(command.url.as_deref(), Some("http://a"));
+        assert_eq!(
+            command.data,
+            vec![
+                Data::Text("a=1".to_string()),
+                Data::Text("b=2".to_string()),
+                Data::BinaryFile("x".into())
+            ]
+        );
+
+        assert_eq!(
+            "curl -o out --retry 3 http://a".parse::<CurlCommand>(),
+            Err(CurlError::Unsupported(vec![
+                "-o".to_string(),
+                "--retry".to_string()
+            ]))
+        );
+        assert_eq!(
+            "wget http://a".parse::<CurlCommand>(),
+            Err(CurlError::NotCurl)
+        );
+        assert_eq!(
+            "curl -H".parse::<CurlCommand>(),
+            Err(CurlError::MissingValue("-H".to_string()))
+        );
+    }
+}
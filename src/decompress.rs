@@ -0,0 +1,220 @@
+use std::io::Write;
+
+use hyper::http::HeaderValue;
+
+/// A `Content-Encoding` which oha decodes, for `--decompressed-size` and the body assertions.
+/// `Accept-Encoding` advertises exactly these, so that every size oha reports can be decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Gzip,
+    /// "deflate" in HTTP means zlib format
+    Deflate,
+    Br,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl ContentEncoding {
+    /// In the order of preference of `Accept-Encoding`
+    pub const SUPPORTED: &'static [Self] = &[
+        Self::Gzip,
+        Self::Deflate,
+        Self::Br,
+        #[cfg(feature = "zstd")]
+        Self::Zstd,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+            Self::Br => "br",
+            #[cfg(feature = "zstd")]
+            Self::Zstd => "zstd",
+        }
+    }
+
+    /// The encoding of a `Content-Encoding` header. Ok(None) is no encoding and Err is one
+    /// which oha can't decode.
+    pub fn parse(content_encoding: Option<&HeaderValue>) -> Result<Option<Self>, ()> {
+        let Some(encoding) = content_encoding else {
+            return Ok(None);
+        };
+        let encoding = encoding.to_str().map_err(|_| ())?.trim();
+        if encoding.eq_ignore_ascii_case("identity") {
+            return Ok(None);
+        }
+        if encoding.eq_ignore_ascii_case("x-gzip") {
+            return Ok(Some(Self::Gzip));
+        }
+        Self::SUPPORTED
+            .iter()
+            .find(|e| encoding.eq_ignore_ascii_case(e.name()))
+            .copied()
+            .map(Some)
+            .ok_or(())
+    }
+}
+
+/// The `Accept-Encoding` header sent unless `--disable-compression`
+pub fn accept_encoding() -> HeaderValue {
+    let names: Vec<&str> = ContentEncoding::SUPPORTED
+        .iter()
+        .map(|e| e.name())
+        .collect();
+    // The unwrap is fine because the names are ASCII tokens.
+    HeaderValue::from_str(&names.join(", ")).unwrap()
+}
+
+/// Counts the bytes written into it
+#[derive(Default)]
+pub struct Counter(usize);
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Measures the size of a body after decoding its `Content-Encoding` while the body is
+/// received, for `--decompressed-size`. The decoded bytes are counted and dropped.
+pub enum DecodedLen {
+    Identity(usize),
+    Gzip(flate2::write::GzDecoder<Counter>),
+    Deflate(flate2::write::ZlibDecoder<Counter>),
+    Br(Box<brotli::DecompressorWriter<Counter>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Decoder<'static, Counter>),
+    /// The encoding is not supported or decoding failed
+    Unknown,
+}
+
+impl DecodedLen {
+    pub fn new(content_encoding: Option<&HeaderValue>) -> Self {
+        match ContentEncoding::parse(content_encoding) {
+            Ok(None) => Self::Identity(0),
+            Ok(Some(ContentEncoding::Gzip)) => {
+                Self::Gzip(flate2::write::GzDecoder::new(Counter::default()))
+            }
+            Ok(Some(ContentEncoding::Deflate)) => {
+                Self::Deflate(flate2::write::ZlibDecoder::new(Counter::default()))
+            }
+            Ok(Some(ContentEncoding::Br)) => Self::Br(Box::new(brotli::DecompressorWriter::new(
+                Counter::default(),
+                4096,
+            ))),
+            #[cfg(feature = "zstd")]
+            Ok(Some(ContentEncoding::Zstd)) => {
+                match zstd::stream::write::Decoder::new(Counter::default()) {
+                    Ok(decoder) => Self::Zstd(decoder),
+                    Err(_) => Self::Unknown,
+                }
+            }
+            Err(()) => Self::Unknown,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let result = match self {
+            Self::Identity(len) => {
+                *len += data.len();
+                Ok(())
+            }
+            Self::Gzip(decoder) => decoder.write_all(data),
+            Self::Deflate(decoder) => decoder.write_all(data),
+            Self::Br(decoder) => decoder.write_all(data),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(decoder) => decoder.write_all(data),
+            Self::Unknown => Ok(()),
+        };
+        if result.is_err() {
+            *self = Self::Unknown;
+        }
+    }
+
+    /// The decoded size. None when it's unknown.
+    pub fn finish(self) -> Option<usize> {
+        match self {
+            Self::Identity(len) => Some(len),
+            Self::Gzip(decoder) => decoder.finish().ok().map(|counter| counter.0),
+            Self::Deflate(decoder) => decoder.finish().ok().map(|counter| counter.0),
+            Self::Br(decoder) => decoder.into_inner().ok().map(|counter| counter.0),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(mut decoder) => decoder.flush().ok().map(|_| decoder.into_inner().0),
+            Self::Unknown => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded_len(encoding: Option<&'static str>, body: &[u8]) -> Option<usize> {
+        let encoding = encoding.map(HeaderValue::from_static);
+        let mut decoded_len = DecodedLen::new(encoding.as_ref());
+        // Split into chunks as they arrive from the network
+        for chunk in body.chunks(7) {
+            decoded_len.update(chunk);
+        }
+        decoded_len.finish()
+    }
+
+    #[test]
+    fn test_decoded_len() {
+        let text = "hello world ".repeat(100);
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(text.as_bytes()).unwrap();
+        let gzip = gzip.finish().unwrap();
+        assert!(gzip.len() < text.len());
+        assert_eq!(decoded_len(Some("gzip"), &gzip), Some(text.len()));
+
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(text.as_bytes()).unwrap();
+        let zlib = zlib.finish().unwrap();
+        assert_eq!(decoded_len(Some("deflate"), &zlib), Some(text.len()));
+
+        let mut br = Vec::new();
+        {
+            let mut writer = brotli::CompressorWriter::new(&mut br, 4096, 5, 22);
+            writer.write_all(text.as_bytes()).unwrap();
+        }
+        assert_eq!(decoded_len(Some("br"), &br), Some(text.len()));
+
+        #[cfg(feature = "zstd")]
+        {
+            let zstd = zstd::encode_all(text.as_bytes(), 3).unwrap();
+            assert_eq!(decoded_len(Some("zstd"), &zstd), Some(text.len()));
+        }
+
+        assert_eq!(decoded_len(None, b"plain"), Some(5));
+        assert_eq!(decoded_len(Some("Identity"), b"plain"), Some(5));
+        assert_eq!(decoded_len(Some("X-Gzip"), &gzip), Some(text.len()));
+        assert_eq!(decoded_len(Some("compress"), b"plain"), None);
+        assert_eq!(decoded_len(Some("gzip"), b"not gzip at all"), None);
+    }
+
+    #[test]
+    fn test_accept_encoding() {
+        let accept_encoding = accept_encoding();
+        #[cfg(not(feature = "zstd"))]
+        assert_eq!(accept_encoding, "gzip, deflate, br");
+        #[cfg(feature = "zstd")]
+        assert_eq!(accept_encoding, "gzip, deflate, br, zstd");
+        // Every advertised encoding is decoded
+        for name in accept_encoding.to_str().unwrap().split(", ") {
+            let value = HeaderValue::from_str(name).unwrap();
+            assert!(matches!(ContentEncoding::parse(Some(&value)), Ok(Some(_))));
+        }
+        assert_eq!(
+            ContentEncoding::parse(Some(&HeaderValue::from_static("compress"))),
+            Err(())
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::{ops::Deref, str::FromStr};
+
+use thiserror::Error;
+
+/// The units from the largest, with their length in milliseconds
+const UNITS: [(&str, u64); 4] = [("h", 3_600_000), ("m", 60_000), ("s", 1_000), ("ms", 1)];
+
+/// A duration of the command line such as `-z` and `-t`: `300ms`, `10s`, `5m`, `2h`, units
+/// combined from the largest like `1h30m`, or a bare number of seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Duration(std::time::Duration);
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("invalid duration {0:?}, expected a number with a unit of ms, s, m or h e.g. 300ms, 10s, 5m or 2h, units from the largest e.g. 1h30m, or a number of seconds e.g. 10")]
+pub struct DurationError(String);
+
+/// Parse a duration, and tell whether it was a bare number of seconds
+pub fn parse(s: &str) -> Result<(std::time::Duration, bool), DurationError> {
+    let error = || DurationError(s.to_string());
+    let s = s.trim();
+    if s.is_empty() {
+        return Err(error());
+    }
+    if s.bytes().all(|b| b.is_ascii_digit()) {
+        let secs = s.parse().map_err(|_| error())?;
+        return Ok((std::time::Duration::from_secs(secs), true));
+    }
+
+    let mut millis: u64 = 0;
+    let mut rest = s;
+    // The units left for the next part, so that each unit is smaller than the one before
+    let mut units = &UNITS[..];
+    while !rest.is_empty() {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return Err(error());
+        }
+        let n: u64 = rest[..digits].parse().map_err(|_| error())?;
+        rest = &rest[digits..];
+        let letters = rest.bytes().take_while(u8::is_ascii_alphabetic).count();
+        let unit = &rest[..letters];
+        rest = &rest[letters..];
+        let position = units
+            .iter()
+            .position(|&(name, _)| name == unit)
+            .ok_or_else(error)?;
+        millis = n
+            .checked_mul(units[position].1)
+            .and_then(|part| millis.checked_add(part))
+            .ok_or_else(error)?;
+        units = &units[position + 1..];
+    }
+    Ok((std::time::Duration::from_millis(millis), false))
+}
+
+impl FromStr for Duration {
+    type Err = DurationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (duration, bare) = parse(s)?;
+        if bare {
+            eprintln!("note: the duration {s} without a unit is read as {s}s");
+        }
+        Ok(Self(duration))
+    }
+}
+
+impl Deref for Duration {
+    type Target = std::time::Duration;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<std::time::Duration> for Duration {
+    fn from(duration: std::time::Duration) -> Self {
+        Self(duration)
+    }
+}
+
+impl From<Duration> for std::time::Duration {
+    fn from(duration: Duration) -> Self {
+        duration.0
+    }
+}
+
+impl std::fmt::Display for Duration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        humantime::Duration::from(self.0).fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let ms = std::time::Duration::from_millis;
+        for (s, expected) in [
+            ("300ms", Some((ms(300), false))),
+            ("10s", Some((ms(10_000), false))),
+            ("5m", Some((ms(300_000), false))),
+            ("2h", Some((ms(7_200_000), false))),
+            ("1h30m", Some((ms(5_400_000), false))),
+            ("1m30s500ms", Some((ms(90_500), false))),
+            ("0s", Some((ms(0), false))),
+            (" 10s ", Some((ms(10_000), false))),
+            ("10", Some((ms(10_000), true))),
+            ("0", Some((ms(0), true))),
+            ("", None),
+            ("s", None),
+            ("10sec", None),
+            ("10 s", None),
+            ("1h 30m", None),
+            ("30m1h", None),
+            ("1s1s", None),
+            ("1.5s", None),
+            ("-1s", None),
+            ("1d", None),
+            ("10us", None),
+            ("1h30", None),
+            ("99999999999999999999h", None),
+        ] {
+            assert_eq!(parse(s).ok(), expected, "{s:?}");
+        }
+        assert!(parse("10sec")
+            .unwrap_err()
+            .to_string()
+            .starts_with("invalid duration \"10sec\", expected"));
+    }
+
+    #[test]
+    fn test_display() {
+        let duration: Duration = "1h30m".parse().unwrap();
+        assert_eq!(duration.to_string(), "1h 30m");
+        assert_eq!(duration.as_secs(), 5400);
+    }
+}
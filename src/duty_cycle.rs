@@ -0,0 +1,136 @@
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+/// Requests in this long after the load resumes are reported separately
+pub const RESUME: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// The workers send requests
+    On,
+    /// No new request is sent. Requests in flight complete.
+    Off,
+}
+
+/// Periods of load and rest one after another, given by `--on` and `--off`
+pub struct DutyCycle {
+    on: Duration,
+    off: Duration,
+    /// When the first worker started
+    start: OnceLock<Instant>,
+}
+
+impl DutyCycle {
+    pub fn new(on: Duration, off: Duration) -> Self {
+        Self {
+            on,
+            off,
+            start: OnceLock::new(),
+        }
+    }
+
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn on(&self) -> Duration {
+        self.on
+    }
+
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn off(&self) -> Duration {
+        self.off
+    }
+
+    /// When the first cycle started
+    pub fn start(&self) -> Instant {
+        *self.start.get_or_init(Instant::now)
+    }
+
+    /// The index of the cycle `elapsed` after the start and the time into it
+    fn split(&self, elapsed: Duration) -> (usize, Duration) {
+        let period = (self.on + self.off).as_nanos();
+        let nanos = elapsed.as_nanos();
+        (
+            (nanos / period) as usize,
+            Duration::from_nanos((nanos % period) as u64),
+        )
+    }
+
+    /// The phase `elapsed` after the start, the index of its cycle and the time left in the phase
+    pub fn phase_at(&self, elapsed: Duration) -> (Phase, usize, Duration) {
+        let (cycle, into) = self.split(elapsed);
+        if into < self.on {
+            (Phase::On, cycle, self.on - into)
+        } else {
+            (Phase::Off, cycle, self.on + self.off - into)
+        }
+    }
+
+    /// Whether a request started `elapsed` after the start is in the first second after an off
+    /// period
+    pub fn is_resume(&self, elapsed: Duration) -> bool {
+        let (cycle, into) = self.split(elapsed);
+        cycle > 0 && into < RESUME.min(self.on)
+    }
+
+    /// The time spent in on periods in the first `elapsed` of the run, the denominator of the
+    /// throughput
+    pub fn on_time(&self, elapsed: Duration) -> Duration {
+        let (cycle, into) = self.split(elapsed);
+        self.on * cycle as u32 + into.min(self.on)
+    }
+
+    /// Wait for the next on period, but not past `dead_line`
+    pub async fn wait_on(&self, dead_line: Option<Instant>) {
+        let now = Instant::now();
+        if let (Phase::Off, _, left) = self.phase_at(now.saturating_duration_since(self.start())) {
+            let until = now + left;
+            let until = dead_line.map_or(until, |dead_line| until.min(dead_line));
+            tokio::time::sleep_until(until.into()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_at() {
+        let duty_cycle = DutyCycle::new(Duration::from_secs(30), Duration::from_secs(10));
+        let secs = Duration::from_secs;
+        assert_eq!(duty_cycle.phase_at(secs(0)), (Phase::On, 0, secs(30)));
+        assert_eq!(duty_cycle.phase_at(secs(35)), (Phase::Off, 0, secs(5)));
+        assert_eq!(duty_cycle.phase_at(secs(40)), (Phase::On, 1, secs(30)));
+        assert_eq!(duty_cycle.phase_at(secs(75)), (Phase::Off, 1, secs(5)));
+
+        assert!(!duty_cycle.is_resume(Duration::from_millis(500)));
+        assert!(duty_cycle.is_resume(Duration::from_millis(40500)));
+        assert!(!duty_cycle.is_resume(secs(42)));
+
+        assert_eq!(duty_cycle.on_time(secs(20)), secs(20));
+        assert_eq!(duty_cycle.on_time(secs(35)), secs(30));
+        assert_eq!(duty_cycle.on_time(secs(100)), secs(80));
+    }
+
+    #[tokio::test]
+    async fn test_wait_on() {
+        let duty_cycle = DutyCycle::new(Duration::from_millis(50), Duration::from_millis(100));
+        let start = duty_cycle.start();
+        duty_cycle.wait_on(None).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+
+        tokio::time::sleep_until((start + Duration::from_millis(60)).into()).await;
+        duty_cycle.wait_on(None).await;
+        assert!(start.elapsed() >= Duration::from_millis(150));
+
+        // Cut by the dead line
+        tokio::time::sleep_until((start + Duration::from_millis(210)).into()).await;
+        duty_cycle
+            .wait_on(Some(start + Duration::from_millis(230)))
+            .await;
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(230), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(290), "{elapsed:?}");
+    }
+}
@@ -0,0 +1,90 @@
+use std::str::FromStr;
+
+use hyper::http::StatusCode;
+
+/// Allowed status codes specified by `--expect-status` e.g. `200,204,3xx`.
+#[derive(Clone, Debug)]
+pub struct ExpectStatus {
+    patterns: Vec<StatusPattern>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StatusPattern {
+    Code(u16),
+    /// `2xx` is stored as `Class(2)`
+    Class(u16),
+}
+
+impl ExpectStatus {
+    pub fn matches(&self, status: StatusCode) -> bool {
+        let status = status.as_u16();
+        self.patterns.iter().any(|pattern| match *pattern {
+            StatusPattern::Code(code) => code == status,
+            StatusPattern::Class(class) => status / 100 == class,
+        })
+    }
+}
+
+impl FromStr for ExpectStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let patterns = s
+            .split(',')
+            .map(|pattern| {
+                let pattern = pattern.trim();
+                let lower = pattern.to_ascii_lowercase();
+                if let Some(class) = lower.strip_suffix("xx") {
+                    match class.parse::<u16>() {
+                        Ok(n @ 1..=5) if class.len() == 1 => Ok(StatusPattern::Class(n)),
+                        _ => Err(format!("invalid status class {pattern:?}")),
+                    }
+                } else {
+                    StatusCode::from_str(pattern)
+                        .map(|status| StatusPattern::Code(status.as_u16()))
+                        .map_err(|_| format!("invalid status code {pattern:?}"))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { patterns })
+    }
+}
+
+/// The syntax of `--expect-status`, which parses back to the same patterns
+impl std::fmt::Display for ExpectStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, pattern) in self.patterns.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            match pattern {
+                StatusPattern::Code(code) => write!(f, "{code}")?,
+                StatusPattern::Class(class) => write!(f, "{class}xx")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_expect_status() {
+        let expect: ExpectStatus = "200, 3XX,404".parse().unwrap();
+        assert!(expect.matches(StatusCode::OK));
+        assert!(expect.matches(StatusCode::FOUND));
+        assert!(expect.matches(StatusCode::NOT_FOUND));
+        assert!(!expect.matches(StatusCode::CREATED));
+        assert!(!expect.matches(StatusCode::INTERNAL_SERVER_ERROR));
+        assert_eq!(expect.to_string(), "200,3xx,404");
+
+        assert!("".parse::<ExpectStatus>().is_err());
+        assert!("2x".parse::<ExpectStatus>().is_err());
+        assert!("6xx".parse::<ExpectStatus>().is_err());
+        assert!("20xx".parse::<ExpectStatus>().is_err());
+        assert!("99".parse::<ExpectStatus>().is_err());
+    }
+}
@@ -0,0 +1,72 @@
+/// Files oha keeps open besides its connections: the standard streams, the runtime, the DNS
+/// resolver and the output files
+pub const OVERHEAD: u64 = 64;
+
+/// The soft limit of open files, None when it's unlimited or unknown
+#[cfg(unix)]
+pub fn soft_limit() -> Option<u64> {
+    rlimit::getrlimit(rlimit::Resource::NOFILE)
+        .ok()
+        .map(|(soft, _)| soft)
+        .filter(|&soft| soft != rlimit::INFINITY)
+}
+
+#[cfg(not(unix))]
+pub fn soft_limit() -> Option<u64> {
+    None
+}
+
+/// Raise the soft limit of open files as far as the hard limit allows, best-effort
+#[cfg(unix)]
+pub fn raise() {
+    let _ = rlimit::increase_nofile_limit(u64::MAX);
+}
+
+#[cfg(not(unix))]
+pub fn raise() {}
+
+/// The error of `connections` which can't all be open at once under `limit`
+pub fn check(connections: usize, limit: Option<u64>) -> Result<(), String> {
+    let needed = connections as u64 + OVERHEAD;
+    match limit {
+        Some(limit) if needed > limit => Err(format!(
+            "-c {connections} needs about {needed} open files, but the limit is {limit}. Raise it with ulimit -n {needed}, or lower -c"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The process or the system ran out of file descriptors
+pub fn is_exhausted(err: &std::io::Error) -> bool {
+    matches!(err.raw_os_error(), Some(libc::EMFILE | libc::ENFILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        assert!(check(50, None).is_ok());
+        assert!(check(50, Some(1024)).is_ok());
+        assert!(check(960, Some(1024)).is_ok());
+        assert_eq!(
+            check(1000, Some(1024)).unwrap_err(),
+            "-c 1000 needs about 1064 open files, but the limit is 1024. Raise it with ulimit -n 1064, or lower -c"
+        );
+    }
+
+    #[test]
+    fn test_is_exhausted() {
+        assert!(is_exhausted(&std::io::Error::from_raw_os_error(
+            libc::EMFILE
+        )));
+        assert!(is_exhausted(&std::io::Error::from_raw_os_error(
+            libc::ENFILE
+        )));
+        assert!(!is_exhausted(&std::io::Error::from_raw_os_error(
+            libc::ECONNREFUSED
+        )));
+        assert!(!is_exhausted(&std::io::Error::other("too many open files")));
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::atomic::AtomicUsize,
+};
+
+use hyper::http::{header, HeaderName, HeaderValue, Method};
+use serde::Deserialize;
+use thiserror::Error;
+use url::Url;
+
+use crate::scenario::{Scenario, Step, Template};
+
+#[derive(Deserialize, Debug)]
+struct Har {
+    log: Log,
+}
+
+#[derive(Deserialize, Debug)]
+struct Log {
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Entry {
+    request: Request,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Request {
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: Vec<NameValue>,
+    post_data: Option<PostData>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PostData {
+    text: Option<String>,
+    #[serde(default)]
+    params: Vec<NameValue>,
+}
+
+#[derive(Deserialize, Debug)]
+struct NameValue {
+    name: String,
+    value: String,
+}
+
+#[derive(Error, Debug)]
+pub enum HarError {
+    #[error("failed to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("failed to parse {0}: {1}")]
+    Json(PathBuf, serde_json::Error),
+    #[error("HAR has no entry")]
+    Empty,
+    #[error("entry {0}: invalid URL {1:?}: {2}")]
+    Url(usize, String, url::ParseError),
+    #[error("entry {0}: invalid method {1:?}")]
+    Method(usize, String),
+    #[error("HAR has entries for {} other than {target}. Use --har-any-origin to send them to {target}", .others.join(", "))]
+    OtherOrigins { target: String, others: Vec<String> },
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum, serde::Serialize, Deserialize)]
+pub enum HarOrder {
+    /// Each worker replays the entries in the recorded order
+    OriginalOrder,
+    /// Workers take the entries in turn, so each entry is sent equally often
+    RoundRobin,
+}
+
+/// Options of `--har`.
+pub struct HarOptions {
+    pub order: HarOrder,
+    pub strip_cookies: bool,
+    pub any_origin: bool,
+}
+
+/// Headers which are not forwarded from the HAR.
+/// Host and Content-Length are set by oha and the others are hop-by-hop.
+fn is_skipped_header(name: &HeaderName) -> bool {
+    [
+        header::HOST,
+        header::CONTENT_LENGTH,
+        header::CONNECTION,
+        header::TRANSFER_ENCODING,
+        header::TE,
+        header::TRAILER,
+        header::UPGRADE,
+        header::PROXY_AUTHORIZATION,
+        header::PROXY_AUTHENTICATE,
+    ]
+    .contains(name)
+        || name == "keep-alive"
+        || name == "proxy-connection"
+}
+
+fn origin(url: &Url) -> String {
+    url.origin().ascii_serialization()
+}
+
+/// Load the requests of a HAR file as a scenario without extraction.
+pub fn load(path: &Path, target: &Url, options: &HarOptions) -> Result<Scenario, HarError> {
+    let content = std::fs::read(path).map_err(|e| HarError::Io(path.into(), e))?;
+    let har: Har = serde_json::from_slice(&content).map_err(|e| HarError::Json(path.into(), e))?;
+    build(har, target, options)
+}
+
+fn build(har: Har, target: &Url, options: &HarOptions) -> Result<Scenario, HarError> {
+    if har.log.entries.is_empty() {
+        return Err(HarError::Empty);
+    }
+
+    let target_origin = origin(target);
+    let mut other_origins = BTreeSet::new();
+    let mut steps = Vec::new();
+
+    for (i, entry) in har.log.entries.into_iter().enumerate() {
+        let request = entry.request;
+        let url = Url::parse(&request.url).map_err(|e| HarError::Url(i, request.url.clone(), e))?;
+        if origin(&url) != target_origin {
+            other_origins.insert(origin(&url));
+        }
+        let method = Method::from_bytes(request.method.as_bytes())
+            .map_err(|_| HarError::Method(i, request.method.clone()))?;
+
+        let headers = request
+            .headers
+            .iter()
+            // HTTP/2 pseudo headers such as :authority are not valid names and skipped here
+            .filter_map(|h| {
+                let name = HeaderName::from_bytes(h.name.as_bytes()).ok()?;
+                HeaderValue::from_str(&h.value).ok()?;
+                Some((name, Template::literal(&h.value)))
+            })
+            .filter(|(name, _)| !is_skipped_header(name))
+            .filter(|(name, _)| {
+                !(options.strip_cookies
+                    && (name == header::COOKIE || name == header::AUTHORIZATION))
+            })
+            .collect();
+
+        let body = request.post_data.map(|post_data| match post_data.text {
+            Some(text) => Template::literal(text),
+            None => Template::literal(
+                url::form_urlencoded::Serializer::new(String::new())
+                    .extend_pairs(post_data.params.iter().map(|p| (&p.name, &p.value)))
+                    .finish(),
+            ),
+        });
+
+        let path = &url[url::Position::BeforePath..];
+        steps.push(Step {
+            name: format!("{method} {path}").into(),
+            method,
+            path: Template::literal(path),
+            headers,
+            body,
+            extract: Vec::new(),
+        });
+    }
+
+    if !other_origins.is_empty() && !options.any_origin {
+        return Err(HarError::OtherOrigins {
+            target: target_origin,
+            others: other_origins.into_iter().collect(),
+        });
+    }
+
+    Ok(Scenario {
+        steps,
+        strict: false,
+        round_robin: match options.order {
+            HarOrder::OriginalOrder => None,
+            HarOrder::RoundRobin => Some(AtomicUsize::new(0)),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HAR: &str = r#"{
+        "log": {
+            "entries": [
+                {
+                    "request": {
+                        "method": "GET",
+                        "url": "https://example.com/a?x=1",
+                        "headers": [
+                            {"name": ":authority", "value": "example.com"},
+                            {"name": "Host", "value": "example.com"},
+                            {"name": "Connection", "value": "keep-alive"},
+                            {"name": "Cookie", "value": "session=1"},
+                            {"name": "Accept", "value": "text/html"}
+                        ]
+                    }
+                },
+                {
+                    "request": {
+                        "method": "POST",
+                        "url": "https://example.com/b",
+                        "headers": [],
+                        "postData": {"mimeType": "application/x-www-form-urlencoded", "params": [{"name": "k", "value": "v w"}]}
+                    }
+                }
+            ]
+        }
+    }"#;
+
+    fn options(strip_cookies: bool, any_origin: bool) -> HarOptions {
+        HarOptions {
+            order: HarOrder::OriginalOrder,
+            strip_cookies,
+            any_origin,
+        }
+    }
+
+    #[test]
+    fn test_build() {
+        let target = Url::parse("https://example.com/").unwrap();
+        let scenario = build(
+            serde_json::from_str(HAR).unwrap(),
+            &target,
+            &options(false, false),
+        )
+        .unwrap();
+        assert_eq!(scenario.steps.len(), 2);
+        assert_eq!(&*scenario.steps[0].name, "GET /a?x=1");
+        let names: Vec<&str> = scenario.steps[0]
+            .headers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names, vec!["cookie", "accept"]);
+        assert_eq!(
+            scenario.steps[1]
+                .body
+                .as_ref()
+                .unwrap()
+                .render(&Default::default()),
+            "k=v+w"
+        );
+
+        let scenario = build(
+            serde_json::from_str(HAR).unwrap(),
+            &target,
+            &options(true, false),
+        )
+        .unwrap();
+        assert_eq!(scenario.steps[0].headers.len(), 1);
+    }
+
+    #[test]
+    fn test_other_origins() {
+        let target = Url::parse("http://127.0.0.1:3000/").unwrap();
+        assert!(matches!(
+            build(
+                serde_json::from_str(HAR).unwrap(),
+                &target,
+                &options(false, false)
+            ),
+            Err(HarError::OtherOrigins { .. })
+        ));
+        assert!(build(
+            serde_json::from_str(HAR).unwrap(),
+            &target,
+            &options(false, true)
+        )
+        .is_ok());
+    }
+}
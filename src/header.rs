@@ -0,0 +1,166 @@
+use hyper::http::header::{HeaderName, HeaderValue};
+use thiserror::Error;
+
+/// Why a header of `-H` or `--header-file` can't be sent. The bytes count from the start of the
+/// argument or the line as given.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum HeaderError {
+    #[error("missing ':' between the name and the value")]
+    MissingColon,
+    #[error("empty header name")]
+    EmptyName,
+    #[error("invalid character {ch:?} in the header name at byte {byte}")]
+    InvalidName { ch: char, byte: usize },
+    #[error("invalid character {ch:?} in the header value at byte {byte}")]
+    InvalidValue { ch: char, byte: usize },
+}
+
+/// `-a` without a colon, which curl would prompt the password for
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("expected user:password, e.g. alice:secret, or user: for an empty password")]
+pub struct MissingPassword;
+
+/// The `Authorization` of `-a user:password`, encoded as curl does: the user is up to the
+/// first colon and the password is the rest, each possibly empty, so that `user:` and
+/// `:password` are sent with their colon and a password keeps its own colons
+pub fn basic_auth(credentials: &str) -> Result<HeaderValue, MissingPassword> {
+    use base64::Engine;
+
+    if !credentials.contains(':') {
+        return Err(MissingPassword);
+    }
+    let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+    // Base64 is always a valid value
+    Ok(HeaderValue::from_str(&format!("Basic {encoded}")).unwrap())
+}
+
+/// A header like `"X-Foo: bar"`, with the whitespace around the name and the value trimmed
+pub fn parse(header: &str) -> Result<(HeaderName, HeaderValue), HeaderError> {
+    let (name, value) = header.split_once(':').ok_or(HeaderError::MissingColon)?;
+
+    let trimmed_name = name.trim_matches(is_whitespace);
+    if trimmed_name.is_empty() {
+        return Err(HeaderError::EmptyName);
+    }
+    let name_start = name.len() - name.trim_start_matches(is_whitespace).len();
+    if let Some((i, ch)) = trimmed_name.char_indices().find(|&(_, ch)| !is_token(ch)) {
+        return Err(HeaderError::InvalidName {
+            ch,
+            byte: name_start + i,
+        });
+    }
+
+    let trimmed_value = value.trim_matches(is_whitespace);
+    let value_start = name.len() + 1 + value.len() - value.trim_start_matches(is_whitespace).len();
+    if let Some((i, ch)) = trimmed_value
+        .char_indices()
+        .find(|&(_, ch)| !(ch == '\t' || (' '..='~').contains(&ch)))
+    {
+        return Err(HeaderError::InvalidValue {
+            ch,
+            byte: value_start + i,
+        });
+    }
+
+    // Both are checked above
+    Ok((
+        HeaderName::from_bytes(trimmed_name.as_bytes()).unwrap(),
+        HeaderValue::from_str(trimmed_value).unwrap(),
+    ))
+}
+
+/// The headers of a `--header-file`: one per line, skipping blank lines and `#` comments. The
+/// errors tell the line.
+pub fn parse_file(content: &str) -> Result<Vec<(HeaderName, HeaderValue)>, String> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
+        .map(|(i, line)| parse(line).map_err(|e| format!("line {} {line:?}: {e}", i + 1)))
+        .collect()
+}
+
+fn is_whitespace(ch: char) -> bool {
+    ch == ' ' || ch == '\t'
+}
+
+/// A character of a token of RFC 9110
+fn is_token(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(ch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let (name, value) = parse(" X-Foo : bar ").unwrap();
+        assert_eq!(name, "x-foo");
+        assert_eq!(value, "bar");
+        let (_, value) = parse("x-empty:").unwrap();
+        assert_eq!(value, "");
+        // Only the first colon separates them
+        let (_, value) = parse("Referer: http://example.com/").unwrap();
+        assert_eq!(value, "http://example.com/");
+
+        assert_eq!(parse("X-Foo"), Err(HeaderError::MissingColon));
+        assert_eq!(parse("  : bar"), Err(HeaderError::EmptyName));
+        assert_eq!(
+            parse(" X Foo: bar"),
+            Err(HeaderError::InvalidName { ch: ' ', byte: 2 })
+        );
+        assert_eq!(
+            parse("X-Foo:  b\u{e9}r"),
+            Err(HeaderError::InvalidValue {
+                ch: '\u{e9}',
+                byte: 9
+            })
+        );
+        assert_eq!(
+            parse("X-Foo: a\nb").unwrap_err().to_string(),
+            "invalid character '\\n' in the header value at byte 8"
+        );
+    }
+
+    #[test]
+    fn test_basic_auth() {
+        let decode = |credentials: &str| {
+            use base64::Engine;
+            let value = basic_auth(credentials).unwrap();
+            let encoded = value.to_str().unwrap().strip_prefix("Basic ").unwrap();
+            let decoded = base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .unwrap();
+            String::from_utf8(decoded).unwrap()
+        };
+        assert_eq!(
+            basic_auth("Aladdin:open sesame").unwrap(),
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+        assert_eq!(decode("user:pa:ss:word"), "user:pa:ss:word");
+        assert_eq!(decode("user:"), "user:");
+        assert_eq!(decode(":password"), ":password");
+        assert_eq!(decode(":"), ":");
+        assert_eq!(decode("üser:pässwörd"), "üser:pässwörd");
+
+        assert_eq!(basic_auth("user"), Err(MissingPassword));
+        assert_eq!(basic_auth(""), Err(MissingPassword));
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let headers = parse_file("# comment\n\nX-Foo: bar\r\n  X-Baz : 1\n").unwrap();
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[1].0, "x-baz");
+        assert_eq!(headers[1].1, "1");
+
+        assert_eq!(
+            parse_file("X-Foo: bar\n\n: nameless\n").unwrap_err(),
+            "line 3 \": nameless\": empty header name"
+        );
+    }
+}
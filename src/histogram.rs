@@ -1,13 +1,32 @@
-pub fn histogram(values: &[f64], bins: usize) -> Vec<(f64, usize)> {
+#[cfg(test)]
+fn histogram(values: &[f64], bins: usize) -> Vec<(f64, usize)> {
+    let values: Vec<(f64, usize)> = values.iter().map(|&v| (v, 1)).collect();
+    weighted_histogram(&values, bins)
+}
+
+/// Like `histogram` on values given with how many times each occurs
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+pub fn weighted_histogram(values: &[(f64, usize)], bins: usize) -> Vec<(f64, usize)> {
+    let min = values.iter().map(|v| v.0).collect::<average::Min>().min();
+    let max = values.iter().map(|v| v.0).collect::<average::Max>().max();
+    linear(values.iter().copied(), min, max, bins)
+}
+
+/// Count the values, given with how many times each occurs, in `bins` bins of the same width
+/// from `min` to `max`
+fn linear(
+    values: impl Iterator<Item = (f64, usize)>,
+    min: f64,
+    max: f64,
+    bins: usize,
+) -> Vec<(f64, usize)> {
     assert!(bins >= 2);
     let mut bucket: Vec<usize> = vec![0; bins];
-    let min = values.iter().collect::<average::Min>().min();
-    let max = values.iter().collect::<average::Max>().max();
     let step = (max - min) / (bins - 1) as f64;
 
-    for &v in values {
+    for (v, n) in values {
         let i = std::cmp::min(((v - min) / step).ceil() as usize, bins - 1);
-        bucket[i] += 1;
+        bucket[i] += n;
     }
 
     bucket
@@ -17,6 +36,178 @@ pub fn histogram(values: &[f64], bins: usize) -> Vec<(f64, usize)> {
         .collect()
 }
 
+/// `--histogram-precision`: significant figures of the values in `LatencyHistogram`
+pub const DEFAULT_PRECISION: u8 = 3;
+/// The unit of the values in `LatencyHistogram`, in seconds. Smaller differences are rounded.
+pub const RESOLUTION: f64 = 1e-6;
+
+/// Durations in seconds, counted in an HDR histogram of microseconds so that the percentiles of
+/// any number of values take a fixed amount of memory. A value is reported within a relative
+/// error of 10^-precision, plus the rounding to a microsecond, and within the smallest and the
+/// largest value, which are exact.
+#[derive(Clone, Debug)]
+pub struct LatencyHistogram {
+    hdr: hdrhistogram::Histogram<u64>,
+    min: average::Min,
+    max: average::Max,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new(DEFAULT_PRECISION)
+    }
+}
+
+impl LatencyHistogram {
+    /// `precision` is the number of significant figures, from 1 to 5
+    pub fn new(precision: u8) -> Self {
+        Self {
+            hdr: hdrhistogram::Histogram::new(precision).expect("precision from 1 to 5"),
+            min: average::Min::new(),
+            max: average::Max::new(),
+        }
+    }
+
+    /// The middle of the bucket of a recorded value, in the range of the values
+    fn value(&self, recorded: u64) -> f64 {
+        (self.hdr.median_equivalent(recorded) as f64 * RESOLUTION).clamp(self.min(), self.max())
+    }
+
+    pub fn push(&mut self, v: f64) {
+        self.push_n(v, 1);
+    }
+
+    /// Push `v` `n` times
+    pub fn push_n(&mut self, v: f64, n: u64) {
+        // record grows the histogram to fit any u64, where saturating_record would clamp
+        self.hdr
+            .record_n((v / RESOLUTION).round() as u64, n)
+            .expect("auto-resized histogram");
+        average::Estimate::add(&mut self.min, v);
+        average::Estimate::add(&mut self.max, v);
+    }
+
+    /// Add the values of `other`
+    pub fn merge(&mut self, other: &Self) {
+        self.hdr.add(&other.hdr).expect("auto-resized histogram");
+        average::Merge::merge(&mut self.min, &other.min);
+        average::Merge::merge(&mut self.max, &other.max);
+    }
+
+    /// The significant figures of the values
+    pub fn precision(&self) -> u8 {
+        self.hdr.sigfig()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.hdr.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hdr.is_empty()
+    }
+
+    pub fn min(&self) -> f64 {
+        self.min.min()
+    }
+
+    pub fn max(&self) -> f64 {
+        self.max.max()
+    }
+
+    /// The value at `p` percent of the sorted values, like the one at index `p * len / 100`.
+    /// NaN when there is none.
+    pub fn percentile(&self, p: f64) -> f64 {
+        let i = (p / 100.0 * self.len() as f64) as u64;
+        if i >= self.len() {
+            return f64::NAN;
+        }
+        // The ends are known exactly
+        if i == 0 {
+            return self.min();
+        }
+        if i == self.len() - 1 {
+            return self.max();
+        }
+        let mut seen = 0;
+        for bucket in self.hdr.iter_recorded() {
+            seen += bucket.count_since_last_iteration();
+            if seen > i {
+                return self.value(bucket.value_iterated_to());
+            }
+        }
+        self.max()
+    }
+
+    /// The values by their buckets, with how many times each occurs
+    pub fn buckets(&self) -> impl Iterator<Item = (f64, usize)> + '_ {
+        let buckets = self.hdr.iter_recorded().count();
+        self.hdr
+            .iter_recorded()
+            .enumerate()
+            .map(move |(i, bucket)| {
+                // The first bucket holds the smallest value and the last one the largest
+                let v = if i == 0 {
+                    self.min()
+                } else if i == buckets - 1 {
+                    self.max()
+                } else {
+                    self.value(bucket.value_iterated_to())
+                };
+                (v, bucket.count_since_last_iteration() as usize)
+            })
+    }
+
+    /// Like `histogram` on the values. A value near the edge of a bin may be counted in the next
+    /// one.
+    pub fn linear(&self, bins: usize) -> Vec<(f64, usize)> {
+        linear(self.buckets(), self.min(), self.max(), bins)
+    }
+}
+
+/// The buckets of a `LatencyHistogram` as they are sent by an agent of `--workers`, which are
+/// only the recorded ones
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Recorded {
+    precision: u8,
+    /// The smallest and the largest value. None when there's none.
+    range: Option<(f64, f64)>,
+    /// A value of each recorded bucket and its count
+    counts: Vec<(u64, u64)>,
+}
+
+impl serde::Serialize for LatencyHistogram {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Recorded {
+            precision: self.precision(),
+            range: (!self.is_empty()).then(|| (self.min(), self.max())),
+            counts: self
+                .hdr
+                .iter_recorded()
+                .map(|bucket| (bucket.value_iterated_to(), bucket.count_at_value()))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for LatencyHistogram {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let recorded = Recorded::deserialize(deserializer)?;
+        let mut hdr = hdrhistogram::Histogram::new(recorded.precision).map_err(D::Error::custom)?;
+        for (value, count) in recorded.counts {
+            hdr.record_n(value, count).map_err(D::Error::custom)?;
+        }
+        let (min, max) = match recorded.range {
+            Some((min, max)) => (average::Min::from_value(min), average::Max::from_value(max)),
+            None => (average::Min::new(), average::Max::new()),
+        };
+        Ok(Self { hdr, min, max })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -84,4 +275,111 @@ mod tests {
         );
         assert_eq!(histogram(&values2, 2), vec![(1.0, 5), (10.0, 5)]);
     }
+
+    #[test]
+    fn test_latency_histogram() {
+        let mut h = LatencyHistogram::default();
+        assert!(h.percentile(50.0).is_nan());
+        // 1ms to 1000ms
+        let values: Vec<f64> = (1..=1000).map(|i| i as f64 / 1000.0).collect();
+        for &v in values.iter().rev() {
+            h.push(v);
+        }
+        assert_eq!(h.len(), 1000);
+        assert_eq!(h.percentile(0.0), 0.001);
+        assert_eq!(h.percentile(99.99), 1.0);
+        for p in [10.0, 25.0, 50.0, 75.0, 90.0, 95.0, 99.0, 99.9] {
+            let exact = values[(p / 100.0 * 1000.0) as usize];
+            let v = h.percentile(p);
+            assert!((v - exact).abs() <= exact * 0.001, "p{p}: {v} vs {exact}");
+        }
+        let bins = h.linear(10);
+        let exact = histogram(&values, 10);
+        assert_eq!(bins.iter().map(|b| b.1).sum::<usize>(), 1000);
+        for (bin, exact) in bins.iter().zip(&exact) {
+            assert_eq!(bin.0, exact.0);
+            assert!(bin.1.abs_diff(exact.1) <= 1, "{bins:?} vs {exact:?}");
+        }
+
+        let mut zeros = LatencyHistogram::default();
+        zeros.push(0.0);
+        zeros.push(0.0);
+        assert_eq!(zeros.percentile(50.0), 0.0);
+        assert_eq!(zeros.linear(2), histogram(&[0.0, 0.0], 2));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = LatencyHistogram::default();
+        let mut b = LatencyHistogram::default();
+        for i in 1..=1000 {
+            if i % 3 == 0 { &mut a } else { &mut b }.push(i as f64 / 1000.0);
+        }
+        let json = serde_json::to_string(&b).unwrap();
+        let b: LatencyHistogram = serde_json::from_str(&json).unwrap();
+        a.merge(&b);
+        assert_eq!(a.len(), 1000);
+        assert_eq!(a.min(), 0.001);
+        assert_eq!(a.max(), 1.0);
+        let median = a.percentile(50.0);
+        assert!((median - 0.501).abs() <= 0.501 * 0.001, "{median}");
+
+        let empty: LatencyHistogram =
+            serde_json::from_str(&serde_json::to_string(&LatencyHistogram::new(2)).unwrap())
+                .unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.precision(), 2);
+        a.merge(&empty);
+        assert_eq!((a.len(), a.min(), a.max()), (1000, 0.001, 1.0));
+    }
+
+    #[test]
+    fn test_precision() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let distributions: [(&str, Vec<f64>); 3] = [
+            (
+                "uniform",
+                (0..100_000).map(|_| rng.gen_range(0.001..0.1)).collect(),
+            ),
+            (
+                "log-uniform",
+                (0..100_000)
+                    .map(|_| 10f64.powf(rng.gen_range(-5.0..1.0)))
+                    .collect(),
+            ),
+            (
+                "bimodal",
+                (0..100_000)
+                    .map(|i| {
+                        let v = rng.gen_range(0.0..0.001);
+                        if i % 10 == 0 {
+                            2.0 + v
+                        } else {
+                            0.005 + v
+                        }
+                    })
+                    .collect(),
+            ),
+        ];
+        for (name, mut values) in distributions {
+            float_ord::sort(&mut values);
+            for precision in 1..=5 {
+                let mut h = LatencyHistogram::new(precision);
+                for &v in &values {
+                    h.push(v);
+                }
+                let error = 10f64.powi(-(precision as i32));
+                for p in [1.0, 10.0, 25.0, 50.0, 75.0, 90.0, 99.0, 99.9, 99.99] {
+                    let exact = values[(p / 100.0 * values.len() as f64) as usize];
+                    let v = h.percentile(p);
+                    assert!(
+                        (v - exact).abs() <= exact * error + RESOLUTION,
+                        "{name} with {precision} figures, p{p}: {v} vs {exact}"
+                    );
+                }
+            }
+        }
+    }
 }
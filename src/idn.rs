@@ -0,0 +1,68 @@
+//! Internationalized domain names. Hosts are sent over the wire as A-labels (punycode)
+//! and shown to the user in their Unicode form.
+
+/// Convert a host to its ASCII form, as used for DNS, TLS SNI and the `Host` header.
+/// ASCII names are returned unchanged except for lowercasing, and bracketed IPv6 addresses
+/// are normalized in the same way as in a URL so that they can be compared with it.
+pub fn to_ascii(host: &str) -> Result<String, String> {
+    if host.starts_with('[') && host.ends_with(']') {
+        if host.contains('%') {
+            return Err(format!("IPv6 zone identifiers are not supported: {host}"));
+        }
+        return url::Host::parse(host)
+            .map(|host| host.to_string())
+            .map_err(|_| format!("invalid IPv6 address {host:?}"));
+    }
+    idna::domain_to_ascii(host).map_err(|_| format!("invalid host name {host:?}"))
+}
+
+/// `bücher.example (xn--bcher-kva.example)` for an internationalized host given in either
+/// form. None for a plain ASCII host.
+pub fn display(host: &str) -> Option<String> {
+    let ascii = to_ascii(host).ok()?;
+    let (unicode, result) = idna::domain_to_unicode(&ascii);
+    (result.is_ok() && unicode != ascii).then(|| format!("{unicode} ({ascii})"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_ascii() {
+        assert_eq!(to_ascii("bücher.example").unwrap(), "xn--bcher-kva.example");
+        // Mixed-script labels
+        assert_eq!(
+            to_ascii("bücher-книги.example").unwrap(),
+            "xn--bcher--3ya307djbbt0b.example"
+        );
+        assert_eq!(to_ascii("例え.テスト").unwrap(), "xn--r8jz45g.xn--zckzah");
+        // Already punycode
+        assert_eq!(
+            to_ascii("xn--bcher-kva.example").unwrap(),
+            "xn--bcher-kva.example"
+        );
+        assert_eq!(to_ascii("Example.COM").unwrap(), "example.com");
+        assert_eq!(to_ascii("127.0.0.1").unwrap(), "127.0.0.1");
+        assert_eq!(to_ascii("[::1]").unwrap(), "[::1]");
+        assert_eq!(to_ascii("[2001:DB8:0:0::1]").unwrap(), "[2001:db8::1]");
+        assert!(to_ascii("[fe80::1%eth0]").is_err());
+        assert!(to_ascii("[::g]").is_err());
+        assert!(to_ascii("a\u{fffd}.example").is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            display("bücher.example").as_deref(),
+            Some("bücher.example (xn--bcher-kva.example)")
+        );
+        assert_eq!(
+            display("xn--bcher-kva.example").as_deref(),
+            Some("bücher.example (xn--bcher-kva.example)")
+        );
+        assert_eq!(display("example.com"), None);
+        assert_eq!(display("127.0.0.1"), None);
+        assert_eq!(display("[::1]"), None);
+    }
+}
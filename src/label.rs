@@ -0,0 +1,101 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use hyper::http::Method;
+
+/// A method, a scenario step or a URL of a result, looked up in `Labels`. The results hold
+/// these rather than the strings so that they are Copy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Label(u32);
+
+/// The standard methods, which have the first labels so that they're found without the lock
+const METHODS: [Method; 9] = [
+    Method::GET,
+    Method::POST,
+    Method::PUT,
+    Method::DELETE,
+    Method::HEAD,
+    Method::OPTIONS,
+    Method::CONNECT,
+    Method::PATCH,
+    Method::TRACE,
+];
+
+/// The names of the labels of a run, shared by the workers and the printer
+pub struct Labels {
+    names: RwLock<Names>,
+}
+
+#[derive(Default)]
+struct Names {
+    labels: HashMap<Arc<str>, Label>,
+    /// Indexed by the labels
+    names: Vec<Arc<str>>,
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        let labels = Self {
+            names: Default::default(),
+        };
+        for method in &METHODS {
+            labels.intern(method.as_str());
+        }
+        labels
+    }
+}
+
+impl Labels {
+    /// The label of `method`
+    pub fn method(&self, method: &Method) -> Label {
+        match METHODS.iter().position(|m| m == method) {
+            Some(i) => Label(i as u32),
+            None => self.intern(method.as_str()),
+        }
+    }
+
+    /// The label of `name`, added when it's new
+    pub fn intern(&self, name: &str) -> Label {
+        if let Some(&label) = self.names.read().unwrap().labels.get(name) {
+            return label;
+        }
+        let mut names = self.names.write().unwrap();
+        if let Some(&label) = names.labels.get(name) {
+            return label;
+        }
+        let label = Label(names.names.len() as u32);
+        let name: Arc<str> = name.into();
+        names.names.push(name.clone());
+        names.labels.insert(name, label);
+        label
+    }
+
+    /// The name of `label`
+    pub fn name(&self, label: Label) -> Arc<str> {
+        self.names.read().unwrap().names[label.0 as usize].clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels() {
+        let labels = Labels::default();
+        assert_eq!(labels.method(&Method::GET), labels.intern("GET"));
+        assert_eq!(&*labels.name(labels.method(&Method::PATCH)), "PATCH");
+
+        let purge = Method::from_bytes(b"PURGE").unwrap();
+        let label = labels.method(&purge);
+        assert_eq!(labels.method(&purge), label);
+        assert_eq!(&*labels.name(label), "PURGE");
+
+        let login = labels.intern("login");
+        assert_ne!(login, label);
+        assert_eq!(labels.intern("login"), login);
+        assert_eq!(&*labels.name(login), "login");
+    }
+}
@@ -0,0 +1,156 @@
+//! oha's load engine, to run a load from code and render the results yourself. The `oha`
+//! binary is a thin wrapper around it.
+//!
+//! ```
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use http_body_util::Full;
+//! use hyper::{body::Bytes, server::conn::http1, service::service_fn, Response};
+//! use hyper_util::rt::TokioIo;
+//!
+//! // A server to send the load to
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+//! let port = listener.local_addr()?.port();
+//! tokio::spawn(async move {
+//!     loop {
+//!         let (tcp, _) = listener.accept().await.unwrap();
+//!         tokio::spawn(http1::Builder::new().serve_connection(
+//!             TokioIo::new(tcp),
+//!             service_fn(|_| async {
+//!                 Ok::<_, std::convert::Infallible>(Response::new(Full::new(Bytes::from("Hello"))))
+//!             }),
+//!         ));
+//!     }
+//! });
+//!
+//! let config = oha::LoadConfig::new(&format!("http://127.0.0.1:{port}/"))
+//!     .requests(100)
+//!     .connections(4);
+//! let report = oha::run(config).await?;
+//! let aggregate = report.aggregate();
+//! assert_eq!(aggregate.count, 100);
+//! assert_eq!(aggregate.status[&hyper::StatusCode::OK].0, 100);
+//! println!("p99: {:.4} secs", aggregate.latency.percentile(99.0));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Any option of the command line can be given with `LoadConfig::from_args`, and
+//! `RunReport::print` prints the summary like oha does. The library leaves the process to the
+//! program: `LoadConfig::shutdown` stops the run on ctrl-c or whatever the program chooses, and
+//! `LoadConfig::partial_summary` has the results so far for a panic hook.
+
+mod abort;
+mod agent;
+mod aggregate;
+mod body;
+mod body_assert;
+mod body_dir;
+mod burst;
+mod client;
+mod connect_rate;
+mod curl;
+mod decompress;
+mod duration;
+mod duty_cycle;
+mod expect_status;
+mod fd_limit;
+mod har;
+mod header;
+mod histogram;
+mod idn;
+mod label;
+mod load_profile;
+mod method_mix;
+#[cfg(feature = "tui")]
+mod monitor;
+mod multipart;
+mod opts;
+mod park;
+mod pause;
+mod printer;
+mod qps_ramp;
+mod ramp_up;
+mod raw_request;
+mod result_channel;
+mod retry_after;
+mod run;
+mod sampling;
+mod save_responses;
+mod scenario;
+mod self_monitor;
+mod shutdown;
+mod start_at;
+mod style;
+mod target_p99;
+mod think_time;
+#[cfg(feature = "tui")]
+mod timescale;
+mod tls;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+mod uring;
+mod url_generator;
+mod url_list;
+mod websocket;
+
+pub use agent::AgentOpts;
+pub use aggregate::{ExpectContinueCounts, Extent, ResultAggregate};
+pub use body_assert::AssertionFailure;
+pub use client::{
+    ClientError, ConnectionTime, ExpectContinue, Informational, LatencyIncludes, RequestResult,
+    TlsHandshake,
+};
+pub use histogram::LatencyHistogram;
+pub use label::{Label, Labels};
+pub use opts::Opts;
+pub use printer::PartialSummary;
+pub use raw_request::RawRequestError;
+pub use run::{run, LoadConfig, RunReport};
+pub use shutdown::{Shutdown, FORCED_EXIT_CODE};
+pub use url_generator::UrlGeneratorError;
+pub use websocket::WebSocketError;
+
+/// The errors of the library
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The arguments of `LoadConfig::from_args` can't be parsed, or ask for the help or the
+    /// version. `clap::Error::exit` prints it as oha does.
+    #[error(transparent)]
+    Args(#[from] clap::Error),
+    /// The options are invalid or can't be used together
+    #[error("{0}")]
+    Config(String),
+    /// A file named by an option can't be read
+    #[error("{option} {}", path.display())]
+    File {
+        option: &'static str,
+        path: std::path::PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The certificates or the key of `--cacert`, `--cert` and `--key` can't be used
+    #[error("{0}")]
+    Tls(String),
+    /// The host of the URL can't be resolved before the run, with `--pre-lookup`
+    #[error("failed to resolve the host before the run")]
+    Resolve(#[source] ClientError),
+    /// `oha agent` can't listen on its address
+    #[error("failed to listen on {address}")]
+    Listen {
+        address: std::net::SocketAddr,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The summary or the saved responses couldn't be written
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// The task which collects the results failed
+    #[error("the collector of the results failed: {0}")]
+    Collector(String),
+}
+
+/// Serve the controller of `--workers`, like `oha agent`
+pub async fn serve_agent(opts: AgentOpts) -> Result<(), Error> {
+    agent::serve(opts).await
+}
@@ -0,0 +1,242 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::duration::{self, DurationError};
+
+/// A step of `--load-profile`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoadStep {
+    pub duration: Duration,
+    /// None for `max`, which sends as fast as the workers can
+    pub qps: Option<f64>,
+}
+
+impl std::fmt::Display for LoadStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let duration = humantime::Duration::from(self.duration);
+        match self.qps {
+            Some(qps) => write!(f, "{duration} at {qps} QPS"),
+            None => write!(f, "{duration} at max"),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum LoadProfileError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("line {0}: expected `<duration> <qps>` or `<duration> max`")]
+    Format(usize),
+    #[error("line {0}: {1}")]
+    Duration(usize, DurationError),
+    #[error("line {0}: the duration must be longer than 0")]
+    ZeroDuration(usize),
+    #[error("line {0}: invalid rate {1:?}, which must be a positive number or max")]
+    Qps(usize, String),
+    #[error("no steps")]
+    Empty,
+}
+
+/// Steps of load run one after another, given by `--load-profile`.
+///
+/// ```text
+/// # warm up
+/// 30s 100
+/// 1m 1000
+/// 10s max
+/// ```
+#[derive(Debug)]
+pub struct LoadProfile {
+    steps: Vec<LoadStep>,
+}
+
+impl LoadProfile {
+    pub fn load(path: &Path) -> Result<Self, LoadProfileError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// One step per line. Empty lines and lines starting with `#` are skipped.
+    fn parse(s: &str) -> Result<Self, LoadProfileError> {
+        let mut steps = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let (Some(duration), Some(qps), None) = (words.next(), words.next(), words.next())
+            else {
+                return Err(LoadProfileError::Format(line_number));
+            };
+            let (duration, _) = duration::parse(duration)
+                .map_err(|e| LoadProfileError::Duration(line_number, e))?;
+            if duration.is_zero() {
+                return Err(LoadProfileError::ZeroDuration(line_number));
+            }
+            let qps = if qps == "max" {
+                None
+            } else {
+                match qps.parse::<f64>() {
+                    Ok(qps) if qps > 0.0 && qps.is_finite() => Some(qps),
+                    _ => return Err(LoadProfileError::Qps(line_number, qps.to_string())),
+                }
+            };
+            steps.push(LoadStep { duration, qps });
+        }
+        if steps.is_empty() {
+            return Err(LoadProfileError::Empty);
+        }
+        Ok(Self { steps })
+    }
+
+    /// The total duration, which replaces `-z`
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().map(|step| step.duration).sum()
+    }
+
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn steps(&self) -> &[LoadStep] {
+        &self.steps
+    }
+
+    /// The index of the step running `elapsed` after the start and the time left in it.
+    /// None after the last step.
+    pub fn step_at(&self, elapsed: Duration) -> Option<(usize, Duration)> {
+        let mut end = Duration::ZERO;
+        for (i, step) in self.steps.iter().enumerate() {
+            end += step.duration;
+            if elapsed < end {
+                return Some((i, end - elapsed));
+            }
+        }
+        None
+    }
+
+    /// The name of the `i`th step in the report, padded so that the steps sort in order
+    pub fn step_name(&self, i: usize) -> String {
+        let width = self.steps.len().to_string().len();
+        format!("#{:0width$} {}", i + 1, self.steps[i])
+    }
+
+    /// Send a token to `tx` for each request until the last step ends.
+    /// The channel should be bounded so that `max` steps don't queue up an unbounded amount of
+    /// requests.
+    pub async fn release<T>(&self, start: Instant, tx: flume::Sender<T>, token: impl Fn() -> T) {
+        let mut step_start = start;
+        for step in &self.steps {
+            let step_end = step_start + step.duration;
+            for i in 0.. {
+                if let Some(qps) = step.qps {
+                    // Computed from the start of the step so that rounding errors don't accumulate
+                    let at = step_start + Duration::from_secs_f64(i as f64 / qps);
+                    if at >= step_end {
+                        break;
+                    }
+                    tokio::time::sleep_until(at.into()).await;
+                }
+                tokio::select! {
+                    biased;
+                    _ = tokio::time::sleep_until(step_end.into()) => break,
+                    sent = tx.send_async(token()) => {
+                        if sent.is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+            // The last request of a throttled step is sent before its end
+            tokio::time::sleep_until(step_end.into()).await;
+            step_start = step_end;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let profile = LoadProfile::parse("# warm up\n30s 100\n\n  1m 0.5  \n10s max\n").unwrap();
+        assert_eq!(
+            profile.steps(),
+            &[
+                LoadStep {
+                    duration: Duration::from_secs(30),
+                    qps: Some(100.0)
+                },
+                LoadStep {
+                    duration: Duration::from_secs(60),
+                    qps: Some(0.5)
+                },
+                LoadStep {
+                    duration: Duration::from_secs(10),
+                    qps: None
+                },
+            ]
+        );
+        assert_eq!(profile.duration(), Duration::from_secs(100));
+        assert_eq!(profile.step_name(1), "#2 1m at 0.5 QPS");
+        assert_eq!(profile.step_name(2), "#3 10s at max");
+
+        assert_eq!(
+            profile.step_at(Duration::from_secs(0)),
+            Some((0, Duration::from_secs(30)))
+        );
+        assert_eq!(
+            profile.step_at(Duration::from_secs(40)),
+            Some((1, Duration::from_secs(50)))
+        );
+        assert_eq!(profile.step_at(Duration::from_secs(100)), None);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let error = |s| LoadProfile::parse(s).unwrap_err().to_string();
+        assert_eq!(
+            error("10s 1\n10s\n"),
+            "line 2: expected `<duration> <qps>` or `<duration> max`"
+        );
+        assert_eq!(
+            error("10s 1 2"),
+            "line 1: expected `<duration> <qps>` or `<duration> max`"
+        );
+        assert!(error("# comment\n10x 1").starts_with("line 2: invalid duration \"10x\""));
+        assert_eq!(error("0s 1"), "line 1: the duration must be longer than 0");
+        assert_eq!(
+            error("10s -1"),
+            "line 1: invalid rate \"-1\", which must be a positive number or max"
+        );
+        assert_eq!(error("# nothing\n"), "no steps");
+    }
+
+    #[tokio::test]
+    async fn test_release() {
+        let profile = LoadProfile::parse("100ms 50\n100ms max").unwrap();
+        let (tx, rx) = flume::bounded(1);
+        let start = Instant::now();
+        let release = tokio::spawn(async move { profile.release(start, tx, || ()).await });
+
+        let mut throttled = 0;
+        let mut max = 0;
+        while rx.recv_async().await.is_ok() {
+            if start.elapsed() < Duration::from_millis(100) {
+                throttled += 1;
+            } else {
+                max += 1;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        release.await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        // 5 requests at 50 QPS in 100ms
+        assert!((4..=6).contains(&throttled), "{throttled}");
+        // Limited by the receiver rather than the profile
+        assert!(max > 20, "{max}");
+    }
+}
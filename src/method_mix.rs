@@ -0,0 +1,118 @@
+use std::str::FromStr;
+
+use hyper::http::Method;
+use rand::{distributions::WeightedIndex, prelude::*};
+
+/// Weighted methods specified by `--method-mix` e.g. `GET:90,POST:10`.
+#[derive(Clone, Debug)]
+pub struct MethodMix {
+    methods: Vec<(Method, u32)>,
+    index: WeightedIndex<u32>,
+}
+
+impl MethodMix {
+    pub fn methods(&self) -> impl Iterator<Item = &Method> {
+        self.methods.iter().map(|(method, _)| method)
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> &Method {
+        &self.methods[self.index.sample(rng)].0
+    }
+}
+
+impl FromStr for MethodMix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut methods: Vec<(Method, u32)> = Vec::new();
+        for entry in s.split(',') {
+            let (method, weight) = entry
+                .split_once(':')
+                .ok_or("syntax for --method-mix is METHOD:WEIGHT[,METHOD:WEIGHT...]")?;
+            let method = Method::from_str(method.trim().to_ascii_uppercase().as_str())
+                .map_err(|_| format!("invalid method {method:?}"))?;
+            let weight: u32 = weight
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid weight {weight:?} for {method}"))?;
+            if methods.iter().any(|(m, _)| m == method) {
+                return Err(format!("{method} is given more than once"));
+            }
+            methods.push((method, weight));
+        }
+
+        if methods
+            .iter()
+            .try_fold(0u32, |sum, (_, w)| sum.checked_add(*w))
+            .is_none()
+        {
+            return Err("sum of weights is too large".to_string());
+        }
+
+        let index = WeightedIndex::new(methods.iter().map(|(_, w)| *w))
+            .map_err(|_| "at least one weight must be positive".to_string())?;
+
+        Ok(Self { methods, index })
+    }
+}
+
+/// The syntax of `--method-mix`, which parses back to the same mix
+impl std::fmt::Display for MethodMix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (method, weight)) in self.methods.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{method}:{weight}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Methods which are sent without the request body in `--method-mix`.
+pub fn is_bodyless(method: &Method) -> bool {
+    [
+        Method::GET,
+        Method::HEAD,
+        Method::OPTIONS,
+        Method::TRACE,
+        Method::CONNECT,
+    ]
+    .contains(method)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_method_mix() {
+        let mix: MethodMix = "GET:90, post:10".parse().unwrap();
+        assert_eq!(
+            mix.methods().collect::<Vec<_>>(),
+            vec![&Method::GET, &Method::POST]
+        );
+        assert_eq!(mix.to_string(), "GET:90,POST:10");
+
+        assert!("GET".parse::<MethodMix>().is_err());
+        assert!("GET:x".parse::<MethodMix>().is_err());
+        assert!("GET:0,POST:0".parse::<MethodMix>().is_err());
+        assert!("GET:1,GET:2".parse::<MethodMix>().is_err());
+        assert!("GET:4294967295,POST:1".parse::<MethodMix>().is_err());
+    }
+
+    #[test]
+    fn test_sample_method_mix() {
+        let mix: MethodMix = "GET:3,POST:1,PUT:0".parse().unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut posts = 0;
+        for _ in 0..4000 {
+            let method = mix.sample(&mut rng);
+            assert_ne!(method, Method::PUT);
+            if method == Method::POST {
+                posts += 1;
+            }
+        }
+        assert!((800..1200).contains(&posts), "{posts}");
+    }
+}
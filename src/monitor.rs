@@ -3,30 +3,44 @@ use crossterm::{
     event::{Event, KeyCode, KeyEvent, KeyModifiers},
     ExecutableCommand,
 };
-use flume::TryRecvError;
 use hyper::http;
 use ratatui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout},
     style::{Color, Style},
     text::{Line, Span},
-    widgets::{BarChart, Block, Borders, Gauge, Paragraph},
+    widgets::{BarChart, Block, Borders, Gauge, Paragraph, Sparkline},
     Terminal,
 };
-use std::{collections::BTreeMap, io};
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use crate::{
-    client::{ClientError, RequestResult},
-    printer::PrintMode,
+    abort::Abort,
+    aggregate::{Folded, ResultAggregate},
+    duty_cycle::Phase,
+    printer::{format_rate, PartialSummary, PrintConfig},
+    result_channel::Report,
+    shutdown::Shutdown,
+    target_p99,
     timescale::{TimeLabel, TimeScale},
 };
 
+/// The resolution of the bar chart of the requests
+const SLOT: Duration = Duration::from_millis(100);
+
 /// When the monitor ends
 pub enum EndLine {
     /// After a duration
     Duration(std::time::Duration),
     /// After n query done
-    NumQuery(usize),
+    NumQuery(u64),
+    /// When interrupted by `--forever` or `-n 0`
+    Infinite,
 }
 
 struct ColorScheme {
@@ -51,21 +65,114 @@ impl ColorScheme {
     }
 }
 
+/// The results so far, shared by the ingestion and the frames
+struct Collected {
+    aggregate: ResultAggregate,
+    /// The end, latency and size of the responses within the timescale, and how many responses
+    /// each stands for, for the stats and the response time histogram
+    recent: VecDeque<(Instant, f64, u64, usize)>,
+    /// Responses by the 100ms of the run they ended in, for the bar chart
+    ended: Vec<u64>,
+    /// All workers are done and every result is in
+    done: bool,
+}
+
+impl Collected {
+    fn push(&mut self, report: Report, config: &PrintConfig, start: Instant) {
+        let report = match report {
+            Report::Result(report) => report,
+            Report::Folded(folded) => return self.merge(&folded),
+        };
+        if let Ok(r) = report.as_ref() {
+            self.recent.push_back((
+                r.end,
+                r.duration(config.latency_includes).as_secs_f64(),
+                r.len_bytes,
+                1,
+            ));
+            let slot =
+                (r.end.saturating_duration_since(start).as_millis() / SLOT.as_millis()) as usize;
+            self.count_slot(slot, 1);
+        }
+        self.aggregate.push(report);
+    }
+
+    /// A second of an agent of `--workers`, whose responses are shown as they arrive by the
+    /// buckets of their latency, and at the start of their second in the bar chart
+    fn merge(&mut self, folded: &Folded) {
+        let now = Instant::now();
+        let bytes = u64::try_from(folded.bytes).unwrap_or(u64::MAX);
+        for (i, (latency, n)) in folded.latency.buckets().enumerate() {
+            self.recent
+                .push_back((now, latency, if i == 0 { bytes } else { 0 }, n));
+        }
+        let slots_per_second = (Duration::from_secs(1).as_millis() / SLOT.as_millis()) as usize;
+        for (second, &n) in folded.ended_per_second().iter().enumerate() {
+            self.count_slot(second * slots_per_second, n as u64);
+        }
+        self.aggregate.merge(folded);
+    }
+
+    fn count_slot(&mut self, slot: usize, n: u64) {
+        if slot >= self.ended.len() {
+            self.ended.resize(slot + 1, 0);
+        }
+        self.ended[slot] += n;
+    }
+}
+
+/// Take the results as they arrive, apart from the frames, so that the channel of the results
+/// doesn't fill up while a frame is drawn or waited for
+async fn ingest(
+    report_receiver: flume::Receiver<Report>,
+    collected: Arc<Mutex<Collected>>,
+    config: PrintConfig,
+    start: Instant,
+) {
+    let mut abort_checker = config.abort.as_ref().map(Abort::checker);
+    while let Ok(report) = report_receiver.recv_async().await {
+        let mut collected = collected.lock().unwrap();
+        // The ones which came meanwhile are taken under the same lock
+        for report in std::iter::once(report).chain(report_receiver.drain()) {
+            if let Report::Result(report) = &report {
+                if let Some(checker) = &mut abort_checker {
+                    checker.record(report);
+                }
+                if let Some(target_p99) = &config.target_p99 {
+                    target_p99.record(report);
+                }
+            }
+            collected.push(report, &config, start);
+        }
+    }
+    collected.lock().unwrap().done = true;
+}
+
+/// The results so far, for the summary
+fn take(collected: &Mutex<Collected>, config: &PrintConfig, start: Instant) -> ResultAggregate {
+    std::mem::replace(
+        &mut collected.lock().unwrap().aggregate,
+        ResultAggregate::new(config, start),
+    )
+}
+
 pub struct Monitor {
-    pub print_mode: PrintMode,
+    pub print_config: PrintConfig,
     pub end_line: EndLine,
     /// All workers sends each result to this channel
-    pub report_receiver: flume::Receiver<Result<RequestResult, ClientError>>,
+    pub report_receiver: flume::Receiver<Report>,
     // When started
     pub start: std::time::Instant,
     // Frame per scond of TUI
     pub fps: usize,
-    pub disable_color: bool,
-    pub stats_success_breakdown: bool,
+    /// Set by q or ctrl-c
+    pub shutdown: std::sync::Arc<Shutdown>,
+    /// Printed by the panic hook
+    pub partial_summary: Arc<PartialSummary>,
 }
 
 impl Monitor {
-    pub async fn monitor(self) -> Result<Vec<Result<RequestResult, ClientError>>, std::io::Error> {
+    pub async fn monitor(self) -> Result<ResultAggregate, std::io::Error> {
         crossterm::terminal::enable_raw_mode()?;
         io::stdout().execute(crossterm::terminal::EnterAlternateScreen)?;
         io::stdout().execute(crossterm::cursor::Hide)?;
@@ -75,81 +182,131 @@ impl Monitor {
             Terminal::new(backend)?
         };
 
-        // Return this when ends to application print summary
-        // We must not read all data from this due to computational cost.
-        let mut all: Vec<Result<RequestResult, ClientError>> = Vec::new();
-        // stats for HTTP status
-        let mut status_dist: BTreeMap<http::StatusCode, usize> = Default::default();
-        // stats for Error
-        let mut error_dist: BTreeMap<String, usize> = Default::default();
-
-        #[cfg(unix)]
-        // Limit for number open files. eg. ulimit -n
-        let nofile_limit = rlimit::getrlimit(rlimit::Resource::NOFILE);
+        // The results are taken in as they arrive, so that the workers never wait for a frame
+        let collected = Arc::new(Mutex::new(Collected {
+            aggregate: ResultAggregate::new(&self.print_config, self.start),
+            recent: VecDeque::new(),
+            ended: Vec::new(),
+            done: false,
+        }));
+        let panic_collected = collected.clone();
+        let (config, start) = (self.print_config.clone(), self.start);
+        self.partial_summary
+            .set(self.print_config.clone(), self.start, move || {
+                let mut collected = panic_collected.try_lock().ok()?;
+                Some(std::mem::replace(
+                    &mut collected.aggregate,
+                    ResultAggregate::new(&config, start),
+                ))
+            });
+        tokio::spawn(ingest(
+            self.report_receiver.clone(),
+            collected.clone(),
+            self.print_config.clone(),
+            self.start,
+        ));
 
         // None means auto timescale which depends on how long it takes
         let mut timescale_auto = None;
 
         let mut colors = ColorScheme::new();
-        if !self.disable_color {
+        if !self.print_config.disable_color {
             colors.set_colors();
         }
 
         'outer: loop {
             let frame_start = std::time::Instant::now();
-            loop {
-                match self.report_receiver.try_recv() {
-                    Ok(report) => {
-                        match report.as_ref() {
-                            Ok(report) => *status_dist.entry(report.status).or_default() += 1,
-                            Err(e) => *error_dist.entry(e.to_string()).or_default() += 1,
-                        }
-                        all.push(report);
-                    }
-                    Err(TryRecvError::Empty) => {
-                        break;
-                    }
-                    Err(TryRecvError::Disconnected) => {
-                        // Application ends.
-                        break 'outer;
-                    }
-                }
-            }
-
             let now = std::time::Instant::now();
-            let progress = match &self.end_line {
-                EndLine::Duration(d) => {
-                    ((now - self.start).as_secs_f64() / d.as_secs_f64()).clamp(0.0, 1.0)
-                }
-                EndLine::NumQuery(n) => (all.len() as f64 / *n as f64).clamp(0.0, 1.0),
-            };
-
             let count = 32;
 
             let timescale = if let Some(timescale) = timescale_auto {
                 timescale
+            } else if self.print_config.burst_releases.is_some() {
+                // Coarser bins would smooth the bursts away
+                TimeScale::HundredMillis
             } else {
                 TimeScale::from_elapsed(self.start.elapsed())
             };
 
-            let bin = timescale.as_secs_f64();
+            // Copy what the frame shows, to let the ingestion go on while it's drawn
+            let (num_results, bytes, status_v, error_v, last_1_timescale, bar_num_req, percentiles) = {
+                let mut collected = collected.lock().unwrap();
+                if collected.done {
+                    // Application ends.
+                    break 'outer;
+                }
+                let Collected {
+                    aggregate,
+                    recent,
+                    ended,
+                    ..
+                } = &mut *collected;
+
+                while recent.front().is_some_and(|&(end, _, _, _)| {
+                    (now - end).as_secs_f64() > timescale.as_secs_f64()
+                }) {
+                    recent.pop_front();
+                }
+                let last_1_timescale: Vec<(f64, u64, usize)> = recent
+                    .iter()
+                    .filter(|(end, _, _, _)| (now - *end).as_secs_f64() <= timescale.as_secs_f64())
+                    .map(|&(_, latency, len_bytes, n)| (latency, len_bytes, n))
+                    .collect();
 
-            let mut bar_num_req = vec![0u64; count];
-            let short_bin = (now - self.start).as_secs_f64() % bin;
-            for r in all.iter().rev() {
-                if let Ok(r) = r.as_ref() {
-                    let past = (now - r.end).as_secs_f64();
-                    let i = if past <= short_bin {
-                        0
-                    } else {
-                        1 + ((past - short_bin) / bin) as usize
-                    };
-                    if i >= bar_num_req.len() {
-                        break;
+                // The bins end at now and are aligned on the start like the slots
+                let slots_per_bin = (timescale.as_secs_f64() / SLOT.as_secs_f64()).round() as usize;
+                let current_bin = (now.saturating_duration_since(self.start).as_millis()
+                    / SLOT.as_millis()) as usize
+                    / slots_per_bin;
+                let first_slot = (current_bin + 1).saturating_sub(count) * slots_per_bin;
+                let mut bar_num_req = vec![0u64; count];
+                for (slot, n) in ended.iter().enumerate().skip(first_slot) {
+                    if let Some(bar) = current_bin
+                        .checked_sub(slot / slots_per_bin)
+                        .and_then(|i| bar_num_req.get_mut(i))
+                    {
+                        *bar += n;
                     }
-                    bar_num_req[i] += 1;
                 }
-            }
+
+                let mut status_v: Vec<(http::StatusCode, usize)> = aggregate
+                    .status
+                    .iter()
+                    .map(|(&status, &(count, _))| (status, count))
+                    .collect();
+                status_v.sort_by_key(|t| std::cmp::Reverse(t.1));
+                let mut error_v: Vec<(String, usize)> =
+                    aggregate.errors.clone().into_iter().collect();
+                error_v.sort_by_key(|t| std::cmp::Reverse(t.1));
+
+                (
+                    aggregate.count,
+                    // Body bytes received for `--max-bytes`
+                    u64::try_from(aggregate.bytes).unwrap_or(u64::MAX),
+                    status_v,
+                    error_v,
+                    last_1_timescale,
+                    bar_num_req,
+                    // Of the whole run, from the histogram of the summary
+                    (
+                        aggregate.latency.percentile(50.0),
+                        aggregate.latency.percentile(99.0),
+                    ),
+                )
+            };
+
+            let progress = match &self.end_line {
+                EndLine::Duration(d) => {
+                    ((now - self.start).as_secs_f64() / d.as_secs_f64()).clamp(0.0, 1.0)
+                }
+                EndLine::NumQuery(n) => (num_results as f64 / *n as f64).clamp(0.0, 1.0),
+                EndLine::Infinite => 0.0,
+            };
+            // Whichever limit is closer
+            let progress = match &self.print_config.run_limits {
+                Some(limits) => progress.max((bytes as f64 / limits.max_bytes as f64).min(1.0)),
+                None => progress,
+            };
 
             let cols = bar_num_req
                 .iter()
@@ -179,8 +336,11 @@ impl Monitor {
             let bar_num_req_str: Vec<(&str, u64)> =
                 bar_num_req.iter().map(|(a, b)| (a.as_str(), *b)).collect();
 
-            #[cfg(unix)]
-            let nofile = std::fs::read_dir("/dev/fd").map(|dir| dir.count());
+            // Sampled once per second, not for each frame
+            let usage =
+                self.print_config.self_monitor.as_ref().map(|self_monitor| {
+                    (self_monitor.samples().last, self_monitor.open_files_limit())
+                });
 
             terminal.draw(|f| {
                 let row4 = Layout::default()
@@ -188,9 +348,24 @@ impl Monitor {
                     .constraints(
                         [
                             Constraint::Length(3),
-                            Constraint::Length(8),
-                            Constraint::Length(error_dist.len() as u16 + 2),
+                            Constraint::Length(
+                                9 + self.print_config.retry_after_counters.is_some() as u16
+                                    + self.print_config.ramp_up.is_some() as u16
+                                    + self
+                                        .print_config
+                                        .agents
+                                        .as_ref()
+                                        .map_or(0, |agents| agents.len() as u16),
+                            ),
+                            Constraint::Length(error_v.len() as u16 + 2),
                             Constraint::Percentage(40),
+                            Constraint::Length(
+                                if self.print_config.target_p99.is_some() {
+                                    6
+                                } else {
+                                    0
+                                },
+                            ),
                         ]
                         .as_ref(),
                     )
@@ -214,45 +389,132 @@ impl Monitor {
                         )),
                         humantime::Duration::from(*d)
                     ),
-                    EndLine::NumQuery(n) => format!("{} / {}", all.len(), n),
+                    EndLine::NumQuery(n) => format!("{} / {}", num_results, n),
+                    EndLine::Infinite => format!(
+                        "{} elapsed, press q or ctrl-c to stop",
+                        humantime::Duration::from(std::time::Duration::from_secs(
+                            (now - self.start).as_secs()
+                        ))
+                    ),
+                };
+                let gauge_label = match &self.print_config.run_limits {
+                    Some(limits) => format!(
+                        "{gauge_label}, {:.2} / {:.2}",
+                        Byte::from_u64(bytes).get_appropriate_unit(byte_unit::UnitType::Binary),
+                        Byte::from_u64(limits.max_bytes)
+                            .get_appropriate_unit(byte_unit::UnitType::Binary)
+                    ),
+                    None => gauge_label,
+                };
+                let gauge_title =
+                    match (&self.print_config.qps_ramp, &self.print_config.load_profile) {
+                        (Some(ramp), _) => {
+                            format!("Progress (target {:.2} QPS)", ramp.rate(now - self.start))
+                        }
+                        (None, Some(load_profile)) => {
+                            match load_profile.step_at(now - self.start) {
+                                Some((i, left)) => format!(
+                                    "Progress (step {} / {}: {}, {} left)",
+                                    i + 1,
+                                    load_profile.steps().len(),
+                                    load_profile.steps()[i],
+                                    humantime::Duration::from(std::time::Duration::from_secs(
+                                        left.as_secs()
+                                    )),
+                                ),
+                                None => "Progress".to_string(),
+                            }
+                        }
+                        (None, None) => match self.print_config.qps {
+                            Some(qps) => format!("Progress (target {})", format_rate(qps)),
+                            None => "Progress".to_string(),
+                        },
+                    };
+                let gauge_title = match &self.print_config.target_p99 {
+                    Some(target_p99) => format!(
+                        "Progress (target p99 {}, now {})",
+                        humantime::Duration::from(target_p99.target()),
+                        format_rate(target_p99.qps())
+                    ),
+                    None => gauge_title,
+                };
+                let mut gauge_color = colors.light_blue.unwrap_or(Color::White);
+                let gauge_title = match &self.print_config.duty_cycle {
+                    Some(duty_cycle) => {
+                        let (phase, cycle, left) =
+                            duty_cycle.phase_at(now.saturating_duration_since(duty_cycle.start()));
+                        let (phase, length) = match phase {
+                            Phase::On => ("ON", duty_cycle.on()),
+                            Phase::Off => {
+                                gauge_color = colors.yellow.unwrap_or(Color::White);
+                                ("OFF", duty_cycle.off())
+                            }
+                        };
+                        format!(
+                            "Progress (cycle {}, load {phase}: {} left of {})",
+                            cycle + 1,
+                            humantime::Duration::from(std::time::Duration::from_secs(
+                                left.as_secs()
+                            )),
+                            humantime::Duration::from(length),
+                        )
+                    }
+                    None => gauge_title,
+                };
+                let gauge_title = match &self.print_config.pause {
+                    Some(pause) if pause.is_paused() => {
+                        gauge_color = colors.yellow.unwrap_or(Color::White);
+                        "Paused (send SIGUSR1 or SIGUSR2 to resume)".to_string()
+                    }
+                    _ => gauge_title,
+                };
+                let gauge_title = match &self.print_config.start_at {
+                    Some(start_at) if now < self.start => format!(
+                        "Starting at {} in {} secs",
+                        humantime::format_rfc3339_millis(start_at.requested),
+                        (self.start - now).as_secs_f64().ceil()
+                    ),
+                    _ => gauge_title,
+                };
+                // The results keep coming until the requests in flight at the deadline finish
+                let gauge_title = match self.print_config.test_duration {
+                    Some(test_duration) if now - self.start >= test_duration => {
+                        gauge_color = colors.yellow.unwrap_or(Color::White);
+                        "Waiting for the requests in flight (--wait-ongoing-requests-after-deadline)"
+                            .to_string()
+                    }
+                    _ => gauge_title,
+                };
+                let gauge_title = if self.shutdown.is_stopped() {
+                    gauge_color = colors.yellow.unwrap_or(Color::White);
+                    "Stopping: waiting for the requests in flight (press q or ctrl-c again to quit)"
+                        .to_string()
+                } else {
+                    gauge_title
                 };
                 let gauge = Gauge::default()
-                    .block(Block::default().title("Progress").borders(Borders::ALL))
-                    .gauge_style(Style::default().fg(colors.light_blue.unwrap_or(Color::White)))
+                    .block(Block::default().title(gauge_title).borders(Borders::ALL))
+                    .gauge_style(Style::default().fg(gauge_color))
                     .label(Span::raw(gauge_label))
                     .ratio(progress);
                 f.render_widget(gauge, row4[0]);
 
-                let last_1_timescale = all
-                    .iter()
-                    .rev()
-                    .filter_map(|r| r.as_ref().ok())
-                    .take_while(|r| (now - r.end).as_secs_f64() <= timescale.as_secs_f64())
-                    .collect::<Vec<_>>();
+                let latencies = || last_1_timescale.iter().map(|&(latency, _, _)| latency);
+                let requests: usize = last_1_timescale.iter().map(|&(_, _, n)| n).sum();
 
-                let stats_text = vec![
-                    Line::from(format!("Requests : {}", last_1_timescale.len())),
+                let mut stats_text = vec![
+                    Line::from(format!("Requests : {requests}")),
                     Line::from(vec![Span::styled(
                         format!(
                             "Slowest: {:.4} secs",
-                            last_1_timescale
-                                .iter()
-                                .map(|r| r.duration())
-                                .max()
-                                .map(|d| d.as_secs_f64())
-                                .unwrap_or(std::f64::NAN)
+                            latencies().reduce(f64::max).unwrap_or(f64::NAN)
                         ),
                         Style::default().fg(colors.yellow.unwrap_or(Color::Reset)),
                     )]),
                     Line::from(vec![Span::styled(
                         format!(
                             "Fastest: {:.4} secs",
-                            last_1_timescale
-                                .iter()
-                                .map(|r| r.duration())
-                                .min()
-                                .map(|d| d.as_secs_f64())
-                                .unwrap_or(std::f64::NAN)
+                            latencies().reduce(f64::min).unwrap_or(f64::NAN)
                         ),
                         Style::default().fg(colors.green.unwrap_or(Color::Reset)),
                     )]),
@@ -261,36 +523,71 @@ impl Monitor {
                             "Average: {:.4} secs",
                             last_1_timescale
                                 .iter()
-                                .map(|r| r.duration())
-                                .sum::<std::time::Duration>()
-                                .as_secs_f64()
-                                / last_1_timescale.len() as f64
+                                .map(|&(latency, _, n)| latency * n as f64)
+                                .sum::<f64>()
+                                / requests as f64
                         ),
                         Style::default().fg(colors.light_blue.unwrap_or(Color::Reset)),
                     )]),
+                    Line::from(format!(
+                        "p50 / p99 of the run: {:.4} / {:.4} secs",
+                        percentiles.0, percentiles.1
+                    )),
                     Line::from(format!(
                         "Data: {:.2}",
                         Byte::from_u64(
                             last_1_timescale
                                 .iter()
-                                .map(|r| r.len_bytes as u64)
-                                .sum::<u64>()
+                                .map(|&(_, len_bytes, _)| len_bytes)
+                                .fold(0, u64::saturating_add)
                         )
                         .get_appropriate_unit(byte_unit::UnitType::Binary)
                     )),
-                    #[cfg(unix)]
-                    // Note: Windows can open 255 * 255 * 255 files. So not showing on windows is OK.
-                    Line::from(format!(
-                        "Number of open files: {} / {}",
-                        nofile
-                            .map(|c| c.to_string())
-                            .unwrap_or_else(|_| "Error".to_string()),
-                        nofile_limit
-                            .as_ref()
-                            .map(|(s, _h)| s.to_string())
-                            .unwrap_or_else(|_| "Unknown".to_string())
-                    )),
                 ];
+                if let Some((usage, open_files_limit)) = usage {
+                    let mut line = vec![format!(
+                        "oha: CPU {} of {} threads",
+                        usage
+                            .cpu
+                            .map_or_else(|| "-".to_string(), |cpu| format!("{cpu:.0}%")),
+                        self.print_config.runtime_threads
+                    )];
+                    if let Some(rss) = usage.rss {
+                        line.push(format!(
+                            "RSS {:.2}",
+                            Byte::from_u64(rss).get_appropriate_unit(byte_unit::UnitType::Binary)
+                        ));
+                    }
+                    // Note: Windows can open 255 * 255 * 255 files. So not showing on windows is OK.
+                    if let Some(open_files) = usage.open_files {
+                        line.push(match open_files_limit {
+                            Some(limit) => format!("{open_files} / {limit} open files"),
+                            None => format!("{open_files} open files"),
+                        });
+                    }
+                    stats_text.push(Line::from(line.join(", ")));
+                }
+                if let Some(counters) = &self.print_config.retry_after_counters {
+                    stats_text.push(Line::from(format!(
+                        "Workers in Retry-After backoff: {}",
+                        counters.in_backoff.load(Ordering::Relaxed)
+                    )));
+                }
+                if let Some(ramp_up) = &self.print_config.ramp_up {
+                    stats_text.push(Line::from(format!(
+                        "Active workers: {} / {}",
+                        ramp_up.active.load(Ordering::Relaxed),
+                        ramp_up.workers()
+                    )));
+                }
+                for agent in self.print_config.agents.iter().flat_map(|agents| agents.iter()) {
+                    stats_text.push(Line::from(format!(
+                        "Agent {}: {}, {} responses",
+                        agent.address,
+                        agent.status(),
+                        agent.responses()
+                    )));
+                }
                 let stats_title = format!("stats for last {timescale}");
                 let stats = Paragraph::new(stats_text).block(
                     Block::default()
@@ -299,12 +596,8 @@ impl Monitor {
                 );
                 f.render_widget(stats, mid[0]);
 
-                let mut status_v: Vec<(http::StatusCode, usize)> =
-                    status_dist.clone().into_iter().collect();
-                status_v.sort_by_key(|t| std::cmp::Reverse(t.1));
-
                 let stats2_text = status_v
-                    .into_iter()
+                    .iter()
                     .map(|(status, count)| {
                         Line::from(format!("[{}] {} responses", status.as_str(), count))
                     })
@@ -316,10 +609,8 @@ impl Monitor {
                 );
                 f.render_widget(stats2, mid[1]);
 
-                let mut error_v: Vec<(String, usize)> = error_dist.clone().into_iter().collect();
-                error_v.sort_by_key(|t| std::cmp::Reverse(t.1));
                 let errors_text = error_v
-                    .into_iter()
+                    .iter()
                     .map(|(e, count)| Line::from(format!("[{count}] {e}")))
                     .collect::<Vec<_>>();
                 let errors = Paragraph::new(errors_text).block(
@@ -369,15 +660,12 @@ impl Monitor {
                         (bottom[1].width as usize - 2) / (resp_histo_width + 1)
                     }
                     .max(2);
-                    let values = all
+                    let values = last_1_timescale
                         .iter()
-                        .rev()
-                        .filter_map(|r| r.as_ref().ok())
-                        .take_while(|r| (now - r.end).as_secs_f64() < timescale.as_secs_f64())
-                        .map(|r| r.duration().as_secs_f64())
+                        .map(|&(latency, _, n)| (latency, n))
                         .collect::<Vec<_>>();
 
-                    let histo = crate::histogram::histogram(&values, bins);
+                    let histo = crate::histogram::weighted_histogram(&values, bins);
                     histo
                         .into_iter()
                         .map(|(label, v)| (format!("{label:.4}"), v as u64))
@@ -403,6 +691,32 @@ impl Monitor {
                     .data(resp_histo_data_str.as_slice())
                     .bar_width(resp_histo_width as u16);
                 f.render_widget(resp_histo, bottom[1]);
+
+                if let Some(target_p99) = &self.print_config.target_p99 {
+                    let adjustments = target_p99.adjustments();
+                    // The setpoint of each window and the current one, the latest on the right
+                    let setpoints: Vec<u64> = adjustments
+                        .iter()
+                        .map(|a| a.setpoint)
+                        .chain(std::iter::once(target_p99.qps()))
+                        .map(|qps| qps.round() as u64)
+                        .collect();
+                    let width = row4[4].width.saturating_sub(2) as usize;
+                    let title = format!(
+                        "QPS setpoint of --target-p99, every {}: {}{}",
+                        humantime::Duration::from(target_p99::INTERVAL),
+                        format_rate(target_p99.qps()),
+                        match adjustments.last() {
+                            Some(last) => format!(", last p99 {:.4} secs", last.p99),
+                            None => String::new(),
+                        }
+                    );
+                    let sparkline = Sparkline::default()
+                        .block(Block::default().title(title).borders(Borders::ALL))
+                        .style(Style::default().fg(colors.light_blue.unwrap_or(Color::Reset)))
+                        .data(&setpoints[setpoints.len().saturating_sub(width)..]);
+                    f.render_widget(sparkline, row4[4]);
+                }
             })?;
 
             while crossterm::event::poll(std::time::Duration::from_secs(0))? {
@@ -425,7 +739,8 @@ impl Monitor {
                             timescale_auto = Some(timescale)
                         }
                     }
-                    // User pressed q or ctrl-c
+                    // User pressed q or ctrl-c. The workers stop and the summary waits for the
+                    // requests in flight, unless it's the second time.
                     Event::Key(KeyEvent {
                         code: KeyCode::Char('q'),
                         ..
@@ -435,19 +750,7 @@ impl Monitor {
                         modifiers: KeyModifiers::CONTROL,
                         ..
                     }) => {
-                        std::io::stdout().execute(crossterm::terminal::LeaveAlternateScreen)?;
-                        crossterm::terminal::disable_raw_mode()?;
-                        std::io::stdout().execute(crossterm::cursor::Show)?;
-                        let _ = crate::printer::print_result(
-                            &mut std::io::stdout(),
-                            self.print_mode,
-                            self.start,
-                            &all,
-                            now - self.start,
-                            self.disable_color,
-                            self.stats_success_breakdown,
-                        );
-                        std::process::exit(libc::EXIT_SUCCESS);
+                        self.shutdown.interrupt();
                     }
                     _ => (),
                 }
@@ -463,6 +766,6 @@ impl Monitor {
         std::io::stdout().execute(crossterm::terminal::LeaveAlternateScreen)?;
         crossterm::terminal::disable_raw_mode()?;
         std::io::stdout().execute(crossterm::cursor::Show)?;
-        Ok(all)
+        Ok(take(&collected, &self.print_config, self.start))
     }
 }
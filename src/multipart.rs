@@ -0,0 +1,242 @@
+use std::{path::PathBuf, str::FromStr};
+
+use rand::prelude::*;
+use thiserror::Error;
+
+/// Files larger than this are rejected because the whole body is built in memory once.
+pub const MAX_FILE_SIZE: u64 = 512 * 1024 * 1024;
+
+/// A part specified by `--multipart`.
+/// `name=value` is a text part and `name=@path[;type=mime]` is a file part.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Part {
+    pub name: String,
+    pub value: PartValue,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum PartValue {
+    Text(String),
+    File {
+        path: PathBuf,
+        content_type: Option<String>,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum MultipartError {
+    #[error("failed to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("{path} is {size} bytes, larger than the {MAX_FILE_SIZE} bytes limit for --multipart")]
+    TooLarge { path: PathBuf, size: u64 },
+    #[error("boundary {0:?} appears in the content of part {1:?}")]
+    BoundaryCollision(String, String),
+}
+
+impl FromStr for Part {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or("syntax for --multipart is name=value or name=@path[;type=mime]")?;
+
+        let value = if let Some(file) = value.strip_prefix('@') {
+            let (path, content_type) = match file.rsplit_once(";type=") {
+                Some((path, content_type)) => (path, Some(content_type.to_string())),
+                None => (file, None),
+            };
+            if path.is_empty() {
+                return Err(format!("file path is empty for --multipart {name}"));
+            }
+            PartValue::File {
+                path: path.into(),
+                content_type,
+            }
+        } else {
+            PartValue::Text(value.to_string())
+        };
+
+        Ok(Part {
+            name: name.to_string(),
+            value,
+        })
+    }
+}
+
+/// Generate a boundary which is unlikely to appear in the content.
+pub fn generate_boundary<R: Rng>(rng: &mut R) -> String {
+    let suffix: String = rng
+        .sample_iter(rand::distributions::Alphanumeric)
+        .take(24)
+        .map(char::from)
+        .collect();
+    format!("----oha-boundary-{suffix}")
+}
+
+/// Guess Content-Type from the file extension.
+pub fn guess_content_type(path: &std::path::Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match ext.as_deref() {
+        Some("txt") => "text/plain",
+        Some("html" | "htm") => "text/html",
+        Some("css") => "text/css",
+        Some("csv") => "text/csv",
+        Some("js") => "text/javascript",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        Some("wasm") => "application/wasm",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        Some("mp3") => "audio/mpeg",
+        Some("mp4") => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Escape a name or filename for a quoted-string in Content-Disposition
+/// in the same way as browsers do.
+fn escape(s: &str) -> String {
+    s.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Build a multipart/form-data body. Files are read at once.
+pub fn build(parts: &[Part], boundary: &str) -> Result<Vec<u8>, MultipartError> {
+    let mut body = Vec::new();
+
+    for part in parts {
+        let content = match &part.value {
+            PartValue::Text(text) => {
+                body.extend_from_slice(
+                    format!(
+                        "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n",
+                        escape(&part.name)
+                    )
+                    .as_bytes(),
+                );
+                text.as_bytes().to_vec()
+            }
+            PartValue::File { path, content_type } => {
+                let size = std::fs::metadata(path)
+                    .map_err(|e| MultipartError::Io(path.clone(), e))?
+                    .len();
+                if size > MAX_FILE_SIZE {
+                    return Err(MultipartError::TooLarge {
+                        path: path.clone(),
+                        size,
+                    });
+                }
+                let filename = path
+                    .file_name()
+                    .map(|f| f.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let content_type = content_type
+                    .as_deref()
+                    .unwrap_or_else(|| guess_content_type(path));
+                body.extend_from_slice(
+                    format!(
+                        "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {content_type}\r\n\r\n",
+                        escape(&part.name),
+                        escape(&filename)
+                    )
+                    .as_bytes(),
+                );
+                std::fs::read(path).map_err(|e| MultipartError::Io(path.clone(), e))?
+            }
+        };
+
+        if content
+            .windows(boundary.len())
+            .any(|w| w == boundary.as_bytes())
+        {
+            return Err(MultipartError::BoundaryCollision(
+                boundary.to_string(),
+                part.name.clone(),
+            ));
+        }
+        body.extend_from_slice(&content);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_part() {
+        assert_eq!(
+            "a=b".parse::<Part>().unwrap(),
+            Part {
+                name: "a".into(),
+                value: PartValue::Text("b".into())
+            }
+        );
+        assert_eq!(
+            "a=b=c".parse::<Part>().unwrap().value,
+            PartValue::Text("b=c".into())
+        );
+        assert_eq!(
+            "f=@dir/x.png".parse::<Part>().unwrap().value,
+            PartValue::File {
+                path: "dir/x.png".into(),
+                content_type: None
+            }
+        );
+        assert_eq!(
+            "f=@x.bin;type=image/png".parse::<Part>().unwrap().value,
+            PartValue::File {
+                path: "x.bin".into(),
+                content_type: Some("image/png".into())
+            }
+        );
+        assert!("novalue".parse::<Part>().is_err());
+        assert!("f=@".parse::<Part>().is_err());
+    }
+
+    #[test]
+    fn test_guess_content_type() {
+        assert_eq!(guess_content_type("a.PNG".as_ref()), "image/png");
+        assert_eq!(guess_content_type("a.json".as_ref()), "application/json");
+        assert_eq!(
+            guess_content_type("noext".as_ref()),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn test_build() {
+        let parts = vec![Part {
+            name: "a\"b".into(),
+            value: PartValue::Text("value".into()),
+        }];
+        let body = build(&parts, "XYZ").unwrap();
+        assert_eq!(
+            body,
+            b"--XYZ\r\nContent-Disposition: form-data; name=\"a%22b\"\r\n\r\nvalue\r\n--XYZ--\r\n"
+        );
+
+        let parts = vec![Part {
+            name: "a".into(),
+            value: PartValue::Text("--XYZ".into()),
+        }];
+        assert!(matches!(
+            build(&parts, "XYZ"),
+            Err(MultipartError::BoundaryCollision(_, _))
+        ));
+    }
+}
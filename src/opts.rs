@@ -0,0 +1,1178 @@
+use anyhow::Context;
+use clap::Parser;
+use hyper::http::{self, header::HeaderValue};
+use std::str::FromStr;
+
+use crate::{
+    body_dir, client, curl, duration::Duration, expect_status, har, idn, method_mix, multipart,
+    start_at,
+};
+
+/// The options of a run. They are also the job which the controller of `--workers` sends to
+/// its agents, with the files read on the controller.
+#[derive(Parser, Clone, serde::Serialize, serde::Deserialize)]
+#[clap(author, about, version, override_usage = "oha [FLAGS] [OPTIONS] <url>")]
+#[command(arg_required_else_help(true))]
+pub struct Opts {
+    #[clap(
+        help = "Target URL. Without a scheme, http:// is assumed, or https:// for port 443, e.g. localhost:8080/health.",
+        required_unless_present = "from_curl"
+    )]
+    pub(crate) url: Option<String>,
+    #[structopt(
+        help = "Number of requests to run. 0 runs until interrupted, the same as --forever. Underscores and the suffixes k, M, G and T are accepted e.g. 10_000 or 5M.",
+        short = 'n',
+        default_value = "200",
+        value_parser = parse_count
+    )]
+    pub(crate) n_requests: u64,
+    #[clap(
+        help = "Number of connections to run concurrently. You may should increase limit to number of open files for larger `-c`, and oha refuses to run when they don't fit in the limit. Lowered to the number of requests of -n when there are fewer.",
+        short = 'c',
+        default_value = "50"
+    )]
+    pub(crate) n_connections: usize,
+    #[clap(
+        help = "Number of parallel requests to send on HTTP/2. `oha` will run c * p concurrent workers in total.",
+        short = 'p',
+        long = "http2-streams-per-connection",
+        default_value = "1"
+    )]
+    pub(crate) n_http2_parallel: usize,
+    #[clap(
+        help = "Number of HTTP/2 connections. Overrides `-c`, so that `oha` multiplexes --http2-streams-per-connection workers on each of them.",
+        long = "http2-connections"
+    )]
+    pub(crate) http2_connections: Option<usize>,
+    #[clap(
+        help = "Initial stream window size sent in the HTTP/2 SETTINGS e.g. 1MiB. Max is 2^31-1.",
+        long = "http2-initial-window-size",
+        value_parser = parse_bytes
+    )]
+    pub(crate) http2_initial_window_size: Option<u64>,
+    #[clap(
+        help = "Max frame size sent in the HTTP/2 SETTINGS e.g. 64KiB. It must be between 16KiB and 16MiB-1.",
+        long = "http2-max-frame-size",
+        value_parser = parse_bytes
+    )]
+    pub(crate) http2_max_frame_size: Option<u64>,
+    #[clap(
+        help = "Max number of times a HTTP/2 request is retried when the server refuses it with REFUSED_STREAM or closes the connection with GOAWAY before processing it.",
+        long = "http2-max-retries",
+        default_value = "3"
+    )]
+    pub(crate) http2_max_retries: usize,
+    #[clap(
+        help = "WebSocket mode: each request is a round trip of a message from -d or -D and the first message the server sends back. Implied by ws:// and wss:// URLs.",
+        long = "ws"
+    )]
+    pub(crate) ws: bool,
+    #[clap(
+        help = "Number of messages sent on a WebSocket connection before reconnecting. Unlimited by default.",
+        long = "ws-messages-per-connection"
+    )]
+    pub(crate) ws_messages_per_connection: Option<usize>,
+    #[clap(
+        help = "Send a ping frame at this interval while a WebSocket connection is idle. Examples: --ws-ping-interval 30s",
+        long = "ws-ping-interval"
+    )]
+    pub(crate) ws_ping_interval: Option<Duration>,
+    #[clap(
+        help = "Duration of application to send requests. If duration is specified, n is ignored.
+When the duration is reached, ongoing requests are aborted and counted as \"aborted due to deadline\"
+Examples: -z 10s -z 3m.",
+        short = 'z'
+    )]
+    pub(crate) duration: Option<Duration>,
+    #[clap(
+        help = "When -z or --load-profile ends, start no new requests but let the ongoing ones finish, bounded by -t, and include them in the stats instead of counting them as \"aborted due to deadline\". The summary shows the drain separately from the test duration.",
+        long = "wait-ongoing-requests-after-deadline"
+    )]
+    pub(crate) wait_ongoing_requests_after_deadline: bool,
+    #[clap(
+        help = "On ctrl-c, start no new requests and wait this long for the ongoing ones to finish, so that they are included in the summary. Press ctrl-c again to quit right away without them, with exit code 130.",
+        long = "shutdown-grace",
+        default_value = "1s"
+    )]
+    pub(crate) shutdown_grace: Duration,
+    #[clap(
+        help = "Run until interrupted with ctrl-c, or q in the TUI, which prints the summary as usual.",
+        long = "forever",
+        conflicts_with_all = ["n_requests", "duration", "load_profile"]
+    )]
+    pub(crate) forever: bool,
+    #[clap(
+        help = "Stop the run after receiving this many bytes of response bodies e.g. 10GB or 1GiB, or when -n or -z is reached first. Combine with --forever to stop on the bytes alone. The summary shows which limit ended the run.",
+        long = "max-bytes",
+        value_parser = parse_bytes
+    )]
+    pub(crate) max_bytes: Option<u64>,
+    #[clap(
+        help = "Rate limit for all, in queries per second (QPS). Fractions are allowed e.g. 0.5 for a request every 2 seconds. Append /m or /h for a rate per minute or hour e.g. 30/m or 100/h.",
+        short = 'q',
+        value_parser = parse_qps
+    )]
+    pub(crate) query_per_second: Option<f64>,
+    #[arg(
+        help = "Introduce delay between a predefined number of requests.
+Note: If qps is specified, burst will be ignored",
+        long = "burst-delay"
+    )]
+    pub(crate) burst_duration: Option<Duration>,
+    #[arg(
+        help = "Rates of requests for burst. Default is 1
+Note: If qps is specified, burst will be ignored",
+        long = "burst-rate"
+    )]
+    pub(crate) burst_requests: Option<usize>,
+    #[clap(
+        help = "Start the workers gradually, from 1 to -c evenly over this duration, before holding steady. Requests which start in the ramp-up are reported separately and excluded from the other statistics. Examples: --ramp-up 30s",
+        long = "ramp-up"
+    )]
+    pub(crate) ramp_up: Option<Duration>,
+    #[clap(
+        help = "Start each worker at a uniformly random time within this duration instead of all at once, so that their connects and requests don't run in lockstep. Reproducible with --rand-seed. Examples: --stagger 2s",
+        long = "stagger",
+        conflicts_with_all = ["ramp_up", "arrival_rate"]
+    )]
+    pub(crate) stagger: Option<Duration>,
+    #[clap(
+        help = "Report the requests which start in the --stagger window separately and exclude them from the other statistics, like --ramp-up.",
+        long = "stagger-exclude",
+        requires = "stagger",
+        conflicts_with = "on"
+    )]
+    pub(crate) stagger_exclude: bool,
+    #[clap(
+        help = "Change the rate limit linearly from one QPS to another over the -z duration e.g. --qps-ramp 10:1000. The summary shows the achieved rate and the p99 latency for each second.",
+        long = "qps-ramp",
+        value_parser = parse_qps_ramp,
+        requires = "duration",
+        conflicts_with = "query_per_second"
+    )]
+    pub(crate) qps_ramp: Option<(f64, f64)>,
+    #[clap(
+        help = "Run steps of load one after another, read from a file with a `<duration> <qps>` or `<duration> max` step per line e.g. `30s 100`. The total of the steps overrides -z. The summary shows the statistics of each step.",
+        long = "load-profile",
+        conflicts_with_all = ["query_per_second", "qps_ramp", "burst_duration"]
+    )]
+    pub(crate) load_profile: Option<std::path::PathBuf>,
+    #[clap(
+        help = "Start requests at this rate on a fixed timeline however many are in flight, so that queueing at the server shows up as latency rather than lower throughput. HTTP/1 connections are opened as needed, and -c is the number of HTTP/2 connections. Fractions are allowed.",
+        long = "arrival-rate",
+        value_parser = parse_qps,
+        conflicts_with_all = ["query_per_second", "qps_ramp", "load_profile", "burst_duration", "ramp_up", "latency_correction"]
+    )]
+    pub(crate) arrival_rate: Option<f64>,
+    #[clap(
+        help = "The cap on requests in flight for --arrival-rate. Requests which are due while it's reached start late and are counted in the summary.",
+        long = "max-in-flight",
+        default_value = "1000",
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "arrival_rate"
+    )]
+    pub(crate) max_in_flight: u32,
+    #[clap(
+        help = "How the requests of -q or --arrival-rate are spread in time. uniform sends them at a constant interval and poisson at exponentially distributed intervals with the same mean, like requests from many independent clients. The summary shows the spread of the requests started per second. Reproducible with --rand-seed.",
+        long = "arrival-distribution",
+        value_enum,
+        conflicts_with_all = ["qps_ramp", "load_profile", "burst_duration"]
+    )]
+    pub(crate) arrival_distribution: Option<client::ArrivalDistribution>,
+    #[clap(
+        help = "Send the requests of -q or --qps-ramp due in each second together at the start of the second instead of spacing them evenly, for servers which should be tested with ticks of load.",
+        long = "qps-burst",
+        conflicts_with_all = ["arrival_rate", "arrival_distribution", "load_profile", "burst_duration"]
+    )]
+    pub(crate) qps_burst: bool,
+    #[clap(
+        help = "Adjust the rate of -q every 2 seconds to find the highest rate which keeps the p99 latency under this e.g. --target-p99 100ms. The rate goes up by a tenth of the starting rate while the p99 of the last 2 seconds is under the target, and down by 30% when it's over. Starts from -q, or 10 QPS without it. The summary reports the stable rate and its latency.",
+        long = "target-p99",
+        conflicts_with_all = ["qps_ramp", "load_profile", "arrival_rate", "burst_duration", "qps_burst"]
+    )]
+    pub(crate) target_p99: Option<Duration>,
+    #[clap(
+        help = "Pause each worker for this long between a response and its next request, to emulate human-paced clients. The pauses are excluded from the latency and their total is shown in the summary. With -q, the slower of the two wins. Examples: --think-time 500ms",
+        long = "think-time",
+        conflicts_with_all = ["arrival_rate", "latency_correction"]
+    )]
+    pub(crate) think_time: Option<Duration>,
+    #[clap(
+        help = "Vary --think-time by a uniform random factor within this percentage e.g. 20 for 80% to 120% of it. Reproducible with --rand-seed.",
+        long = "think-time-jitter",
+        default_value = "0",
+        value_parser = parse_percent,
+        requires = "think_time"
+    )]
+    pub(crate) think_time_jitter: f64,
+    #[clap(
+        help = "Send requests for this long, then rest for --off, repeated until -n or -z is reached. Requests in flight complete during the rest. Requests/sec only counts the time under load, and the summary shows the statistics by phase including the first second after each resume. Examples: --on 30s --off 30s",
+        long = "on",
+        requires = "off",
+        conflicts_with_all = ["query_per_second", "qps_ramp", "load_profile", "burst_duration", "arrival_rate", "ramp_up"]
+    )]
+    pub(crate) on: Option<Duration>,
+    #[clap(
+        help = "Rest without sending new requests for this long between the --on periods.",
+        long = "off",
+        requires = "on"
+    )]
+    pub(crate) off: Option<Duration>,
+
+    #[clap(
+        help = "Generate URL by rand_regex crate but dot is disabled for each query e.g. http://127.0.0.1/[a-z][a-z][0-9]. The scheme, host and port must be literal so that connections can be reused. See https://docs.rs/rand_regex/latest/rand_regex/struct.Regex.html for details of syntax.",
+        default_value = "false",
+        long
+    )]
+    pub(crate) rand_regex_url: bool,
+    #[clap(
+        help = "A parameter for the '--rand-regex-url'. The max_repeat parameter gives the maximum extra repeat counts the x*, x+ and x{n,} operators will become.",
+        default_value = "4",
+        long
+    )]
+    pub(crate) max_repeat: u32,
+    #[clap(
+        help = "Seed for every random choice of the run, such as the URLs of --rand-regex-url, the bodies of --body-dir, --think-time-jitter, --stagger and --arrival-distribution poisson. Each worker derives its own generator from it. When it's not given, a seed is generated and shown in the summary so that the run can be repeated. With -c 1 the same sequence of requests is sent on each run.",
+        long = "rand-seed"
+    )]
+    pub(crate) rand_seed: Option<u64>,
+    #[clap(
+        help = "Append a query parameter with a unique value to each request to bypass caches e.g. --cache-bust=nocache. A fixed --rand-seed gives the same values on each run. [default: _oha]",
+        long = "cache-bust",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "_oha"
+    )]
+    pub(crate) cache_bust: Option<String>,
+    #[clap(
+        help = "Request the URLs listed in the file, one per line and relative to the URL, instead of the URL. Each line may be followed by weight=N, the relative chance of the URL to be picked, and max_concurrent=N, the most requests in flight to it at once. A request which picks a full URL goes to another URL instead.",
+        long = "urls-from-file",
+        conflicts_with_all = ["scenario", "har", "rand_regex_url", "raw_request", "ws"]
+    )]
+    pub(crate) urls_from_file: Option<std::path::PathBuf>,
+    #[clap(
+        help = "Run the steps of a TOML scenario file in a loop on each worker instead of requesting the URL. Paths of the steps are relative to the URL.",
+        long = "scenario",
+        conflicts_with_all = ["method", "method_mix", "body_string", "body_path", "form", "multipart", "rand_regex_url", "cache_bust"]
+    )]
+    pub(crate) scenario: Option<std::path::PathBuf>,
+    #[clap(
+        help = "Replay the requests recorded in a HAR file. Their origin must be the same as the URL.",
+        long = "har",
+        conflicts_with_all = ["scenario", "method", "method_mix", "body_string", "body_path", "form", "multipart", "rand_regex_url", "cache_bust"]
+    )]
+    pub(crate) har: Option<std::path::PathBuf>,
+    #[clap(
+        help = "Order to send the requests of --har.",
+        long = "har-order",
+        value_enum,
+        default_value = "original-order"
+    )]
+    pub(crate) har_order: har::HarOrder,
+    #[clap(
+        help = "Don't send the Cookie and Authorization headers recorded in --har.",
+        long = "har-strip-cookies"
+    )]
+    pub(crate) har_strip_cookies: bool,
+    #[clap(
+        help = "Accept --har entries for any origin and send them to the URL.",
+        long = "har-any-origin"
+    )]
+    pub(crate) har_any_origin: bool,
+    #[clap(
+        help = "Take the URL, method, headers, body, basic authentication and --insecure from a curl command line. Use - to read it from stdin. Other options override them.",
+        long = "from-curl"
+    )]
+    pub(crate) from_curl: Option<String>,
+    #[clap(
+        help = "Correct latency to avoid coordinated omission problem. It's ignored if -q is not set.",
+        long = "latency-correction"
+    )]
+    pub(crate) latency_correction: bool,
+    #[clap(
+        help = "What the latency statistics measure. all is from the start of the request to the last byte of the response, no-dns excludes the DNS lookup of new connections and response-only is from the first byte to the last byte of the response. The phase timings are reported regardless.",
+        long = "latency-includes",
+        value_enum,
+        default_value = "all"
+    )]
+    pub(crate) latency_includes: client::LatencyIncludes,
+    #[clap(
+        help = "Significant figures of the latency histograms, from 1 to 5. The percentiles are within 10^-N of the exact ones, plus a microsecond. Each figure takes about 10 times the memory.",
+        long = "histogram-precision",
+        default_value = "3",
+        value_parser = clap::value_parser!(u8).range(1..=5)
+    )]
+    pub(crate) histogram_precision: u8,
+    #[clap(
+        help = "Only collect 1 in N results e.g. 1/100, for rates which the collection of every result would limit. The totals of requests, errors and bytes stay exact, the other counts are estimated from the sample.",
+        long = "sample-rate",
+        value_parser = parse_sample_rate,
+        conflicts_with_all = ["workers", "max_bytes", "abort_on_first_error", "wait_ongoing_requests_after_deadline"]
+    )]
+    pub(crate) sample_rate: Option<u64>,
+    #[clap(help = "No realtime tui", long = "no-tui")]
+    pub(crate) no_tui: bool,
+    #[clap(help = "Print results as JSON", short, long)]
+    pub(crate) json: bool,
+    #[clap(help = "Frame per second for tui. [default: 16]", long = "fps")]
+    pub(crate) fps: Option<usize>,
+    #[clap(
+        help = "HTTP method. Default to GET, or POST when -F or --multipart is given.",
+        short = 'm',
+        long = "method"
+    )]
+    #[serde(with = "as_str")]
+    pub(crate) method: Option<http::Method>,
+    #[clap(
+        help = "Pick the method of each request by weight e.g. \"GET:90,POST:10\". GET, HEAD, OPTIONS, TRACE and CONNECT are sent without the request body.",
+        long = "method-mix",
+        conflicts_with = "method"
+    )]
+    #[serde(with = "as_str")]
+    pub(crate) method_mix: Option<method_mix::MethodMix>,
+    #[clap(help = "Custom HTTP header. Examples: -H \"foo: bar\"", short = 'H')]
+    pub(crate) headers: Vec<String>,
+    #[clap(
+        help = "Send the headers of the file, one \"name: value\" per line. Blank lines and lines starting with # are skipped. -H overrides them.",
+        long = "header-file"
+    )]
+    pub(crate) header_file: Option<std::path::PathBuf>,
+    #[clap(
+        help = "Timeout for each request, from when it starts until the last byte of the response body, including the DNS lookup and the connection when a new one is made. Such requests count as timeouts and their connection isn't reused. Default to infinite.",
+        short = 't'
+    )]
+    pub(crate) timeout: Option<Duration>,
+    #[clap(
+        help = "Fail a request when no bytes of the response arrive for this long, while waiting for the response header after the request is sent and between the chunks of the body. The connection is closed and not reused. Examples: --read-timeout 2s",
+        long = "read-timeout"
+    )]
+    pub(crate) read_timeout: Option<Duration>,
+    #[clap(
+        help = "When a 429 or 503 response has Retry-After, pause the worker which received it for that long before its next request. Requests/sec is still based on the total time.",
+        long = "respect-retry-after"
+    )]
+    pub(crate) respect_retry_after: bool,
+    #[clap(help = "HTTP Accept Header.", short = 'A')]
+    pub(crate) accept_header: Option<String>,
+    #[clap(help = "HTTP request body.", short = 'd')]
+    pub(crate) body_string: Option<String>,
+    #[clap(
+        help = "HTTP request body from file. A file from --body-stream-threshold is streamed from disk for each request instead of being held in memory.",
+        short = 'D'
+    )]
+    pub(crate) body_path: Option<std::path::PathBuf>,
+    /// The file of -D, read by the controller of `--workers` for its agents
+    #[clap(skip)]
+    #[serde(default, with = "base64_bytes")]
+    pub(crate) body_bytes: Option<Vec<u8>>,
+    #[clap(
+        help = "Stream the file of -D from disk for each request when it's at least this large e.g. 64MiB, and load a smaller one in memory. 0 always streams it.",
+        long = "body-stream-threshold",
+        default_value = "1MiB",
+        value_parser = parse_bytes
+    )]
+    pub(crate) body_stream_threshold: u64,
+    #[clap(
+        help = "Re-read the file of -D on this interval and send the new body from then on, e.g. 30s. A failed read keeps the previous body.",
+        long = "body-reload-interval",
+        requires = "body_path",
+        conflicts_with = "body_string"
+    )]
+    pub(crate) body_reload_interval: Option<Duration>,
+    #[clap(
+        help = "Form field for an application/x-www-form-urlencoded body. Can be repeated. Prefix the value with @ to read it from a file. Examples: -F \"name=value\" -F \"data=@file.txt\"",
+        short = 'F',
+        conflicts_with_all = ["body_string", "body_path"]
+    )]
+    pub(crate) form: Vec<String>,
+    #[clap(
+        help = "Part for a multipart/form-data body. Can be repeated. Use name=value for a text part, name=@path for a file part and name=@path;type=mime to override the guessed Content-Type.",
+        long = "multipart",
+        conflicts_with_all = ["body_string", "body_path", "form"]
+    )]
+    pub(crate) multipart: Vec<multipart::Part>,
+    #[clap(
+        help = "Send one of the files in the directory as the body of each request. The files are loaded at startup.",
+        long = "body-dir",
+        conflicts_with_all = ["body_string", "body_path", "form", "multipart", "body_encoding", "scenario", "har"]
+    )]
+    pub(crate) body_dir: Option<std::path::PathBuf>,
+    #[clap(
+        help = "How to pick the file of --body-dir for each request.",
+        long = "body-dir-order",
+        value_enum,
+        default_value = "round-robin",
+        requires = "body_dir"
+    )]
+    pub(crate) body_dir_order: body_dir::BodyDirOrder,
+    #[clap(
+        help = "Send the request body with Transfer-Encoding: chunked in chunks of the given size e.g. --chunked=16KiB, at least 1 byte. The file of -D is streamed from disk. On HTTP/2, the body is sent in DATA frames of the given size. [default: 8KiB]",
+        long = "chunked",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "8KiB",
+        value_parser = parse_nonzero_bytes
+    )]
+    pub(crate) chunked: Option<u64>,
+    #[clap(
+        help = "Compress the request body once at startup and send it with Content-Encoding.",
+        long = "body-encoding",
+        value_enum
+    )]
+    pub(crate) body_encoding: Option<BodyEncoding>,
+    #[clap(
+        help = "Send Expect: 100-continue and wait for 100 Continue before sending the request body. HTTP/1.1 only.",
+        long = "expect-continue"
+    )]
+    pub(crate) expect_continue: bool,
+    #[clap(
+        help = "Time to wait for 100 Continue before sending the request body anyway.",
+        long = "expect-continue-timeout",
+        default_value = "1s"
+    )]
+    pub(crate) expect_continue_timeout: Duration,
+    #[clap(
+        help = "Count 1xx informational responses such as 103 Early Hints received before the final response, and report when the first of them arrived. HTTP/1.1 only.",
+        long = "informational-stats"
+    )]
+    pub(crate) informational_stats: bool,
+    #[clap(
+        help = "Stop reading the response body after this many bytes e.g. --max-body-bytes 64KiB. The connection is closed, or the stream is reset on HTTP/2, and the response time is measured until then.",
+        long = "max-body-bytes",
+        value_parser = parse_bytes
+    )]
+    pub(crate) max_body_bytes: Option<u64>,
+    #[clap(
+        help = "Save the first --save-count responses into numbered .body and .headers files in the directory. Bodies are decoded by Content-Encoding.",
+        long = "save-responses"
+    )]
+    pub(crate) save_responses: Option<std::path::PathBuf>,
+    #[clap(
+        help = "Number of responses to save with --save-responses.",
+        long = "save-count",
+        default_value = "10",
+        requires = "save_responses"
+    )]
+    pub(crate) save_count: usize,
+    #[clap(
+        help = "Save only responses which aren't 2xx or fail --assert-body-contains or --assert-body-regex.",
+        long = "save-errors-only",
+        requires = "save_responses"
+    )]
+    pub(crate) save_errors_only: bool,
+    #[clap(
+        help = "Count responses with other status codes as errors e.g. --expect-status 200,3xx. They are still shown in the status code distribution.",
+        long = "expect-status"
+    )]
+    #[serde(with = "as_str")]
+    pub(crate) expect_status: Option<expect_status::ExpectStatus>,
+    #[clap(
+        help = "Stop the run when more than this percentage of the requests in --abort-window fail, once there are at least 20 of them. Errors are failures, and so are 4xx and 5xx responses or the ones not matching --expect-status. The summary is marked as aborted and oha exits with code 3.",
+        long = "abort-on-error-rate",
+        value_parser = parse_percent
+    )]
+    pub(crate) abort_on_error_rate: Option<f64>,
+    #[clap(
+        help = "The sliding window of time for --abort-on-error-rate.",
+        long = "abort-window",
+        default_value = "10s",
+        requires = "abort_on_error_rate"
+    )]
+    pub(crate) abort_window: Duration,
+    #[clap(
+        help = "Stop the run on the first failed request, which is an error like a timeout or, with --expect-status, an unexpected status code. The requests in flight are cancelled, the error is shown at the top of the summary and oha exits with code 3.",
+        long = "abort-on-first-error"
+    )]
+    pub(crate) abort_on_first_error: bool,
+    #[clap(
+        help = "Count a response as an assertion failure unless its body contains the string. Compressed bodies are decoded.",
+        long = "assert-body-contains"
+    )]
+    pub(crate) assert_body_contains: Option<String>,
+    #[clap(
+        help = "Count a response as an assertion failure unless its body matches the regex. Bodies which are not UTF-8 fail.",
+        long = "assert-body-regex"
+    )]
+    #[serde(with = "as_str")]
+    pub(crate) assert_body_regex: Option<regex::Regex>,
+    #[clap(
+        help = "Maximum size of the response body checked by --assert-body-contains and --assert-body-regex. The rest is ignored.",
+        long = "max-assert-body-bytes",
+        default_value = "1MiB",
+        value_parser = parse_bytes
+    )]
+    pub(crate) max_assert_body_bytes: u64,
+    #[clap(help = "Content-Type.", short = 'T')]
+    pub(crate) content_type: Option<String>,
+    #[clap(
+        help = "Basic authentication, username:password. The password is everything after the first colon and either part may be empty, as with curl -u.",
+        short = 'a'
+    )]
+    pub(crate) basic_auth: Option<String>,
+    /*
+    #[structopt(help = "HTTP proxy", short = "x")]
+    pub(crate) proxy: Option<String>,
+    */
+    #[clap(
+        help = "HTTP version. Available values 0.9, 1.0, 1.1.",
+        long = "http-version"
+    )]
+    pub(crate) http_version: Option<String>,
+    #[clap(help = "Use HTTP/2. Shorthand for --http-version=2", long = "http2")]
+    pub(crate) http2: bool,
+    #[clap(
+        help = "HTTP Host header. Only the header changes: where oha connects and the TLS name stay the host of the URL. See --connect-host and --sni.",
+        long = "host"
+    )]
+    pub(crate) host: Option<String>,
+    #[clap(
+        help = "Form of the request target in the request line. absolute sends the whole URL as proxies expect, e.g. with --connect-to pointing at a forward proxy. HTTP/1.x only.",
+        long = "request-target",
+        value_enum,
+        default_value = "origin",
+        conflicts_with = "host"
+    )]
+    pub(crate) request_target: RequestTarget,
+    #[clap(
+        help = "Send the bytes of the file as each request instead of building it, with line endings of the request line and the headers normalized to CRLF. {host} and {seq} in them are replaced with the Host of the URL and a sequence number. The URL only tells where to connect. Redirects aren't followed. HTTP/1.x only.",
+        long = "raw-request",
+        conflicts_with_all = ["ws", "scenario", "har", "from_curl", "method", "method_mix", "headers", "header_file", "accept_header", "body_string", "body_path", "form", "multipart", "body_dir", "chunked", "body_encoding", "expect_continue", "informational_stats", "max_body_bytes", "save_responses", "assert_body_contains", "assert_body_regex", "content_type", "basic_auth", "host", "request_target", "decompressed_size", "prewarm_connections", "rand_regex_url", "cache_bust", "respect_retry_after"]
+    )]
+    pub(crate) raw_request: Option<std::path::PathBuf>,
+    #[clap(
+        help = "Encode an HTTP/1.1 request which is the same each time once, write it with a single vectored write and read the responses without hyper. Requests which vary and responses needing more than their status, size and Location are still sent with hyper.",
+        long = "prebuilt-requests",
+        conflicts_with = "raw_request"
+    )]
+    pub(crate) prebuilt_requests: bool,
+    #[clap(
+        help = "Don't send Accept-Encoding, so that the responses aren't compressed.",
+        long = "disable-compression"
+    )]
+    pub(crate) disable_compression: bool,
+    #[clap(
+        help = "Also measure the size of response bodies after decoding the Content-Encoding sent in Accept-Encoding, gzip, deflate, br and zstd with the zstd feature. Sizes are wire bytes otherwise.",
+        long = "decompressed-size"
+    )]
+    pub(crate) decompressed_size: bool,
+    #[clap(
+        help = "Limit for number of Redirect. Set 0 for no redirection. Redirection isn't supported for HTTP/2.",
+        default_value = "10",
+        short = 'r',
+        long = "redirect"
+    )]
+    pub(crate) redirect: usize,
+    #[clap(
+        help = "Disable keep-alive, so that each request is sent on a new connection. Not supported on HTTP/2.",
+        long = "disable-keepalive"
+    )]
+    pub(crate) disable_keepalive: bool,
+    #[clap(
+        help = "Close a kept-alive connection which has been idle for this long and open a new one for the next request e.g. --keepalive-idle-timeout 4s. Set it below the idle timeout of the server, so that requests aren't sent on connections the server is dropping. HTTP/1.x only.",
+        long = "keepalive-idle-timeout",
+        conflicts_with = "disable_keepalive"
+    )]
+    pub(crate) keepalive_idle_timeout: Option<Duration>,
+    #[clap(
+        help = "What the workers do when -q, --burst-delay or --load-profile leaves them without a request. keep takes the requests in turn, so that every worker keeps a connection open. park gives each request to the worker which became idle last, so that the rate is sent by as few workers as it needs, and the others close their connection after an idle second. HTTP/1.x only.",
+        long = "idle-workers",
+        value_enum,
+        default_value = "keep"
+    )]
+    pub(crate) idle_workers: IdleWorkers,
+    #[clap(
+        help = "Close the connection of each worker after this many requests and open a new one for the next, to emulate clients which churn connections. 1 is the same as --disable-keepalive, and 0 reuses connections without limit as by default. HTTP/1.x only.",
+        long = "requests-per-connection",
+        conflicts_with = "disable_keepalive"
+    )]
+    pub(crate) requests_per_connection: Option<usize>,
+    #[clap(
+        help = "Enable TCP keepalive with the given idle time e.g. --tcp-keepalive 30s, so that idle connections aren't silently dropped by firewalls.",
+        long = "tcp-keepalive"
+    )]
+    pub(crate) tcp_keepalive: Option<Duration>,
+    #[clap(
+        help = "Establish the connections of all workers before the measurement starts, so that the first requests don't pay for connection and TLS setup. Ignored with --disable-keepalive.",
+        long = "prewarm-connections"
+    )]
+    pub(crate) prewarm_connections: bool,
+    #[clap(
+        help = "Send an untimed request with this method e.g. HEAD on each prewarmed connection.",
+        long = "prewarm-method",
+        requires = "prewarm_connections"
+    )]
+    #[serde(with = "as_str")]
+    pub(crate) prewarm_method: Option<http::Method>,
+    #[clap(
+        help = "Complete the setup, including --prewarm-connections, then wait until this wall-clock time to start the run, so that oha on several hosts starts at once e.g. --start-at 2024-05-01T12:00:00Z. A countdown is shown meanwhile. Keeping the clocks of the hosts in sync is up to you.",
+        long = "start-at",
+        value_parser = start_at::parse_rfc3339,
+        conflicts_with = "start_in"
+    )]
+    pub(crate) start_at: Option<std::time::SystemTime>,
+    #[clap(
+        help = "Like --start-at, but this long from now e.g. --start-in 30s.",
+        long = "start-in"
+    )]
+    pub(crate) start_in: Option<Duration>,
+    #[clap(
+        help = "Spread the load over the agents at these addresses, started with `oha agent --listen`, and merge their results e.g. --workers 10.0.0.1:7070,10.0.0.2:7070. Each agent runs the same options, so -n, -c and -q apply to each of them. An agent which fails is reported without stopping the others.",
+        long = "workers",
+        value_delimiter = ',',
+        conflicts_with_all = ["start_at", "start_in", "target_p99", "save_responses"]
+    )]
+    pub(crate) workers: Option<Vec<String>>,
+    #[clap(
+        help = "The secret of the agents of --workers, given to `oha agent` with --secret. A job is only run by an agent with the same secret.",
+        long = "workers-secret",
+        env = "OHA_AGENT_SECRET",
+        hide_env_values = true
+    )]
+    #[serde(skip)]
+    pub(crate) workers_secret: Option<String>,
+    /// Stream the results to stdout for `oha agent` instead of printing them
+    #[clap(skip)]
+    #[serde(skip)]
+    pub(crate) agent_output: bool,
+    #[clap(
+        help = "Perform a DNS lookup at beginning to cache it",
+        long = "pre-lookup",
+        default_value = "true"
+    )]
+    pub(crate) pre_lookup: bool,
+    #[clap(
+        help = "Retry a failed DNS lookup this many times before failing the request.",
+        long = "dns-retries",
+        default_value = "2"
+    )]
+    pub(crate) dns_retries: usize,
+    #[clap(
+        help = "Wait this long before the first retry of a failed DNS lookup. The wait is doubled for each retry, and a worker waits once more after the last one.",
+        long = "dns-retry-interval",
+        default_value = "100ms"
+    )]
+    pub(crate) dns_retry_interval: Duration,
+    #[clap(
+        help = "Limit how fast new connections (TCP and TLS) are established, at startup and during the run, in connections per second e.g. 100. Append /m or /h for a rate per minute or hour.",
+        long = "connect-rate",
+        value_parser = parse_qps
+    )]
+    pub(crate) connect_rate: Option<f64>,
+    #[clap(
+        help = "Number of threads of the async runtime. 1 runs everything on the main thread. [default: the number of CPUs]",
+        long = "runtime-threads",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub(crate) runtime_threads: Option<u32>,
+    #[clap(
+        help = "Don't raise the soft limit of open files to the hard limit at startup. oha refuses to run when -c doesn't fit in the limit either way.",
+        long = "no-rlimit-bump"
+    )]
+    pub(crate) no_rlimit_bump: bool,
+    #[clap(
+        help = "How the requests do their I/O. uring sends plain HTTP/1.1 through an io_uring on each of --runtime-threads threads, for -n and -z without a rate limit. It needs Linux and oha built with the uring feature.",
+        long = "io-backend",
+        value_enum,
+        default_value = "tokio"
+    )]
+    pub(crate) io_backend: IoBackend,
+    #[clap(
+        help = "The address families to connect over. 4 or 6 looks up only that family. dual-prefer-4 and dual-prefer-6 look up both and try the preferred family first, starting the other one if it isn't connected within 250ms. auto is dual-prefer-6 when the machine has an IPv6 route to the internet, and dual-prefer-4 otherwise. [default: auto]",
+        long = "ip-version",
+        value_enum
+    )]
+    pub(crate) ip_version: Option<IpVersion>,
+    #[clap(
+        help = "Same as --ip-version 6. With --ipv4, the same as --ip-version dual-prefer-6.",
+        long = "ipv6"
+    )]
+    pub(crate) ipv6: bool,
+    #[clap(
+        help = "Same as --ip-version 4. With --ipv6, the same as --ip-version dual-prefer-6.",
+        long = "ipv4"
+    )]
+    pub(crate) ipv4: bool,
+    #[clap(help = "Accept invalid certs.", long = "insecure")]
+    pub(crate) insecure: bool,
+    #[clap(
+        help = "Verify the server certificate with the CA certificates in this PEM file instead of the platform's roots.",
+        long = "cacert"
+    )]
+    pub(crate) cacert: Option<std::path::PathBuf>,
+    #[clap(
+        help = "Send the client certificate chain in this PEM file when the server asks for one.",
+        long = "cert",
+        requires = "key"
+    )]
+    pub(crate) cert: Option<std::path::PathBuf>,
+    #[clap(
+        help = "The PKCS#8 private key of --cert in a PEM file.",
+        long = "key",
+        requires = "cert"
+    )]
+    pub(crate) key: Option<std::path::PathBuf>,
+    #[cfg(feature = "rustls")]
+    #[clap(
+        help = "Let new connections resume the TLS sessions of earlier connections with session tickets or ids. Use off to measure full handshakes, e.g. with --disable-keepalive.",
+        long = "tls-session-resumption",
+        default_value = "on"
+    )]
+    pub(crate) tls_session_resumption: TlsSessionResumption,
+    #[clap(
+        help = "Override DNS resolution and default port numbers with strings like 'example.org:443:localhost:8443'",
+        long = "connect-to"
+    )]
+    pub(crate) connect_to: Vec<ConnectToEntry>,
+    #[clap(
+        help = "Connect to this host or IP address, with an optional port, instead of the host of the URL, e.g. 10.0.0.5:8080 or [::1]. The URL still gives the Host header and the TLS name, see --sni. Only the connections to the host and port of the URL go there, not redirects to other hosts. An entry of --connect-to that matches the URL takes precedence.",
+        long = "connect-host"
+    )]
+    pub(crate) connect_host: Option<ConnectHost>,
+    #[clap(
+        help = "The name sent in TLS SNI and verified in the server certificate instead of the host of the URL, e.g. to test a virtual host through an IP address. Redirects to other hosts use their own name.",
+        long = "sni",
+        value_parser = parse_sni
+    )]
+    pub(crate) sni: Option<String>,
+    #[clap(help = "Disable the color scheme.", long = "disable-color")]
+    pub(crate) disable_color: bool,
+    #[cfg(unix)]
+    #[clap(
+        help = "Connect to a unix socket instead of the domain in the URL. Only for non-HTTPS URLs.",
+        long = "unix-socket"
+    )]
+    pub(crate) unix_socket: Option<std::path::PathBuf>,
+    #[clap(
+        help = "Include a response status code successful or not successful breakdown for the time histogram and distribution statistics",
+        long = "stats-success-breakdown"
+    )]
+    pub(crate) stats_success_breakdown: bool,
+    #[clap(
+        help = "Include status code distribution and response time statistics for each method",
+        long = "per-method-stats"
+    )]
+    pub(crate) per_method_stats: bool,
+    #[clap(
+        help = "Include status code distribution and response time statistics for each path and query",
+        long = "per-url-stats"
+    )]
+    pub(crate) per_url_stats: bool,
+}
+
+/// An entry specified by `connect-to` to override DNS resolution and default
+/// port numbers. For example, `example.org:80:localhost:5000` will connect to
+/// `localhost:5000` whenever `http://example.org` is requested.
+/// Internationalized host names are stored in their ASCII form.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ConnectToEntry {
+    pub requested_host: String,
+    pub requested_port: u16,
+    pub target_host: String,
+    pub target_port: u16,
+}
+
+impl FromStr for ConnectToEntry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expected_syntax: &str = "syntax for --connect-to is host:port:target_host:target_port";
+
+        let (s, target_port) = s.rsplit_once(':').ok_or(expected_syntax)?;
+        let (s, target_host) = if s.ends_with(']') {
+            // ipv6
+            let i = s.rfind(":[").ok_or(expected_syntax)?;
+            (&s[..i], &s[i + 1..])
+        } else {
+            s.rsplit_once(':').ok_or(expected_syntax)?
+        };
+        let (requested_host, requested_port) = s.rsplit_once(':').ok_or(expected_syntax)?;
+
+        Ok(ConnectToEntry {
+            requested_host: idn::to_ascii(requested_host)?,
+            requested_port: requested_port.parse().map_err(|err| {
+                format!("requested port must be an u16, but got {requested_port}: {err}")
+            })?,
+            target_host: idn::to_ascii(target_host)?,
+            target_port: target_port.parse().map_err(|err| {
+                format!("target port must be an u16, but got {target_port}: {err}")
+            })?,
+        })
+    }
+}
+
+/// The target of `--connect-host`, where every connection goes regardless of the URL.
+/// IPv6 addresses are kept in brackets like the host of a URL, and the port defaults to
+/// the one of the URL.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ConnectHost {
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl FromStr for ConnectHost {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_port = |port: &str| {
+            port.parse()
+                .map_err(|err| format!("port must be an u16, but got {port}: {err}"))
+        };
+
+        if let Ok(ip) = s.parse::<std::net::Ipv6Addr>() {
+            return Ok(ConnectHost {
+                host: format!("[{ip}]"),
+                port: None,
+            });
+        }
+        let (host, port) = if let Some(rest) = s.strip_prefix('[') {
+            let (ip, port) = rest
+                .split_once(']')
+                .ok_or("an IPv6 address must be like [::1] or [::1]:8080")?;
+            let ip = ip
+                .parse::<std::net::Ipv6Addr>()
+                .map_err(|err| format!("invalid IPv6 address {ip}: {err}"))?;
+            let port = match port {
+                "" => None,
+                port => Some(parse_port(
+                    port.strip_prefix(':')
+                        .ok_or("expected :port after the IPv6 address")?,
+                )?),
+            };
+            (format!("[{ip}]"), port)
+        } else {
+            match s.rsplit_once(':') {
+                Some((host, port)) => (idn::to_ascii(host)?, Some(parse_port(port)?)),
+                None => (idn::to_ascii(s)?, None),
+            }
+        };
+        if host.is_empty() {
+            return Err("the host must not be empty".to_string());
+        }
+
+        Ok(ConnectHost { host, port })
+    }
+}
+
+/// Parse the name of `--sni`: a host name in its ASCII form, or an IP address, IPv6 without
+/// brackets
+fn parse_sni(s: &str) -> Result<String, String> {
+    let ip = s
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .unwrap_or(s);
+    if let Ok(ip) = ip.parse::<std::net::IpAddr>() {
+        return Ok(ip.to_string());
+    }
+    match idn::to_ascii(s)? {
+        name if name.is_empty() => Err("the name must not be empty".to_string()),
+        name => Ok(name),
+    }
+}
+
+/// Parse a positive rate like `10` or `0.5`.
+/// Parse a rate in requests per second, or per minute or hour like `30/m` or `100/h`.
+fn parse_qps(s: &str) -> Result<f64, String> {
+    let (count, per) = match s.split_once('/') {
+        Some((count, "s")) => (count, 1.0),
+        Some((count, "m")) => (count, 60.0),
+        Some((count, "h")) => (count, 3600.0),
+        Some(_) => return Err("the unit must be /s, /m or /h e.g. 30/m".to_string()),
+        None => (s, 1.0),
+    };
+    match count.parse::<f64>() {
+        Ok(count) if count > 0.0 && count.is_finite() => Ok(count / per),
+        Ok(_) => Err("must be a positive number".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse the N of `--sample-rate 1/N`.
+fn parse_sample_rate(s: &str) -> Result<u64, String> {
+    match s.split_once('/').map(|(one, n)| (one, n.parse::<u64>())) {
+        Some(("1", Ok(n))) if n > 0 => Ok(n),
+        _ => Err("must be 1/N with a positive N e.g. 1/100".to_string()),
+    }
+}
+
+/// Parse the rates of `--qps-ramp` like `10:1000`.
+fn parse_qps_ramp(s: &str) -> Result<(f64, f64), String> {
+    let (from, to) = s
+        .split_once(':')
+        .ok_or("must be two rates separated by a colon e.g. 10:1000")?;
+    Ok((parse_qps(from)?, parse_qps(to)?))
+}
+
+/// Parse a percentage from 0 to 100.
+fn parse_percent(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(percent),
+        Ok(_) => Err("must be from 0 to 100".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse a count like `10000`, `10_000` or `10k`. The suffixes k, M, G and T are powers of 1000.
+fn parse_count(s: &str) -> Result<u64, String> {
+    let examples = "Examples: 10000, 10_000, 10k, 5M";
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1_000),
+        Some((i, 'M')) => (&s[..i], 1_000_000),
+        Some((i, 'G')) => (&s[..i], 1_000_000_000),
+        Some((i, 'T')) => (&s[..i], 1_000_000_000_000),
+        _ => (s, 1),
+    };
+    if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(format!("must start with a digit. {examples}"));
+    }
+    digits
+        .replace('_', "")
+        .parse::<u64>()
+        .map_err(|e| format!("{e}. {examples}"))?
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("must be at most {}", u64::MAX))
+}
+
+/// Parse a size like `8192`, `8KB` or `8KiB`.
+fn parse_bytes(s: &str) -> Result<u64, String> {
+    byte_unit::Byte::parse_str(s, true)
+        .map(|b| b.as_u64())
+        .map_err(|e| format!("{e}. Examples: 8192, 8KB, 8KiB"))
+}
+
+/// Parse a size like `parse_bytes` that can't be 0, e.g. a chunk that must make progress
+fn parse_nonzero_bytes(s: &str) -> Result<u64, String> {
+    match parse_bytes(s)? {
+        0 => Err("must be at least 1 byte".to_string()),
+        bytes => Ok(bytes),
+    }
+}
+
+/// Serde of the options parsed from a string, as the string which parses back to them
+mod as_str {
+    use std::{fmt::Display, str::FromStr};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.as_ref().map(T::to_string).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| s.parse().map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// Serde of bytes as base64, which is shorter in JSON than an array of numbers
+mod base64_bytes {
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value
+            .as_ref()
+            .map(|bytes| base64::engine::general_purpose::STANDARD.encode(bytes))
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| {
+                base64::engine::general_purpose::STANDARD
+                    .decode(s)
+                    .map_err(serde::de::Error::custom)
+            })
+            .transpose()
+    }
+}
+
+/// The form of the request target for `--request-target` (RFC 9112 section 3.2)
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize,
+)]
+pub enum RequestTarget {
+    /// `GET /path HTTP/1.1`
+    Origin,
+    /// `GET http://example.com/path HTTP/1.1`
+    Absolute,
+}
+
+/// Whether TLS sessions are resumed, for `--tls-session-resumption`
+#[cfg(feature = "rustls")]
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize,
+)]
+pub enum TlsSessionResumption {
+    On,
+    Off,
+}
+
+/// What the workers without a request of the rate do, for `--idle-workers`
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize,
+)]
+pub enum IdleWorkers {
+    Keep,
+    Park,
+}
+
+/// The address families of `--ip-version`
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize,
+)]
+pub enum IpVersion {
+    #[value(name = "4")]
+    V4,
+    #[value(name = "6")]
+    V6,
+    #[value(name = "dual-prefer-4")]
+    DualPrefer4,
+    #[value(name = "dual-prefer-6")]
+    DualPrefer6,
+    Auto,
+}
+
+/// The I/O of the requests, for `--io-backend`
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize,
+)]
+pub enum IoBackend {
+    Tokio,
+    Uring,
+}
+
+/// An encoding for `--body-encoding`.
+#[derive(Clone, Copy, Debug, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
+pub enum BodyEncoding {
+    Gzip,
+    Deflate,
+    Br,
+}
+
+impl BodyEncoding {
+    pub fn header_value(self) -> HeaderValue {
+        match self {
+            BodyEncoding::Gzip => HeaderValue::from_static("gzip"),
+            BodyEncoding::Deflate => HeaderValue::from_static("deflate"),
+            BodyEncoding::Br => HeaderValue::from_static("br"),
+        }
+    }
+
+    pub fn compress(self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        use std::io::Write;
+        match self {
+            BodyEncoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            // "deflate" in HTTP means zlib format
+            BodyEncoding::Deflate => {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            BodyEncoding::Br => {
+                let mut buf = Vec::new();
+                {
+                    let mut encoder = brotli::CompressorWriter::new(&mut buf, 4096, 11, 22);
+                    encoder.write_all(body)?;
+                }
+                Ok(buf)
+            }
+        }
+    }
+}
+
+/// Build an `application/x-www-form-urlencoded` body from `-F name=value` fields.
+/// A value starting with `@` is read from the file at the rest of the path like curl.
+pub fn form_body(fields: &[String]) -> anyhow::Result<String> {
+    let mut body = String::new();
+    for field in fields {
+        let (name, value) = field
+            .split_once('=')
+            .with_context(|| format!("Parse form field {field:?}, expected name=value"))?;
+        // The bytes of a file are encoded as they are, so it needn't be UTF-8
+        let value = match value.strip_prefix('@') {
+            Some(path) => std::borrow::Cow::Owned(
+                std::fs::read(path)
+                    .with_context(|| format!("Read form field {name:?} from {path:?}"))?,
+            ),
+            None => std::borrow::Cow::Borrowed(value.as_bytes()),
+        };
+        if !body.is_empty() {
+            body.push('&');
+        }
+        body.extend(url::form_urlencoded::byte_serialize(name.as_bytes()));
+        body.push('=');
+        body.extend(url::form_urlencoded::byte_serialize(&value));
+    }
+    Ok(body)
+}
+
+/// Fill the options which are not given explicitly from `--from-curl`.
+pub fn apply_curl(opts: &mut Opts, curl: &str) -> anyhow::Result<()> {
+    let curl = if curl == "-" {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        curl.to_string()
+    };
+    let curl: curl::CurlCommand = curl.parse().context("--from-curl")?;
+
+    if opts.url.is_none() {
+        opts.url = curl.url.clone();
+    }
+    opts.headers = curl
+        .headers
+        .iter()
+        .cloned()
+        .chain(std::mem::take(&mut opts.headers))
+        .collect();
+    opts.basic_auth = opts.basic_auth.take().or(curl.user.clone());
+    opts.insecure |= curl.insecure;
+
+    let has_body = opts.body_string.is_some()
+        || opts.body_path.is_some()
+        || !opts.form.is_empty()
+        || !opts.multipart.is_empty()
+        || opts.body_dir.is_some();
+    if !has_body && !curl.data.is_empty() {
+        if let [curl::Data::BinaryFile(path)] = curl.data.as_slice() {
+            opts.body_path = Some(path.clone());
+        } else {
+            let body = curl.body().context("--from-curl")?.unwrap_or_default();
+            opts.body_string = Some(String::from_utf8(body).context(
+                "the body of --from-curl must be UTF-8 unless it is a single --data-binary @file",
+            )?);
+        }
+        if opts.content_type.is_none() && !curl.has_header("content-type") {
+            opts.content_type = Some("application/x-www-form-urlencoded".to_string());
+        }
+    }
+
+    if opts.method.is_none() {
+        opts.method = match &curl.method {
+            Some(method) => Some(method.parse().context("--from-curl")?),
+            None if !curl.data.is_empty() => Some(http::Method::POST),
+            None => None,
+        };
+    }
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use std::{sync::Mutex, time::Duration};
+
+use tokio::sync::{oneshot, Notify};
+
+/// How long a worker of `--idle-workers park` waits for a permit before it closes its connection
+pub const PARK_AFTER: Duration = Duration::from_secs(1);
+
+/// Hands the permits of a rate to the workers of `--idle-workers park`, each to the worker which
+/// became idle last. The rate is then carried by as few workers as it needs, and the others
+/// wait without a request and close their connection.
+pub struct Parking<T> {
+    idle: Mutex<Idle<T>>,
+    /// A worker became idle
+    notify: Notify,
+}
+
+struct Idle<T> {
+    /// The workers waiting for a permit, the last to become idle at the end
+    workers: Vec<oneshot::Sender<T>>,
+    /// No permit is left
+    closed: bool,
+}
+
+impl<T: Send + 'static> Parking<T> {
+    /// Hand out the permits of `rx` until it's closed
+    pub fn spawn(rx: flume::Receiver<T>) -> std::sync::Arc<Self> {
+        let parking = std::sync::Arc::new(Self {
+            idle: Mutex::new(Idle {
+                workers: Vec::new(),
+                closed: false,
+            }),
+            notify: Notify::new(),
+        });
+        tokio::spawn(parking.clone().run(rx));
+        parking
+    }
+
+    async fn run(self: std::sync::Arc<Self>, rx: flume::Receiver<T>) {
+        while let Ok(mut permit) = rx.recv_async().await {
+            loop {
+                let notified = self.notify.notified();
+                let worker = self.idle.lock().unwrap().workers.pop();
+                match worker {
+                    // A worker which stopped waiting gives the permit back
+                    Some(worker) => match worker.send(permit) {
+                        Ok(()) => break,
+                        Err(returned) => permit = returned,
+                    },
+                    None => notified.await,
+                }
+            }
+        }
+        let mut idle = self.idle.lock().unwrap();
+        idle.closed = true;
+        idle.workers.clear();
+    }
+
+    /// Wait for the next permit. None when there are no more.
+    pub async fn next(&self) -> Option<T> {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut idle = self.idle.lock().unwrap();
+            if idle.closed {
+                return None;
+            }
+            idle.workers.push(tx);
+        }
+        self.notify.notify_one();
+        rx.await.ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_last_idle_first() {
+        let (tx, rx) = flume::unbounded();
+        let parking = Parking::spawn(rx);
+
+        let first = tokio::spawn({
+            let parking = parking.clone();
+            async move { parking.next().await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let second = tokio::spawn({
+            let parking = parking.clone();
+            async move { parking.next().await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        tx.send(1).unwrap();
+        assert_eq!(second.await.unwrap(), Some(1));
+        assert!(!first.is_finished());
+
+        // A worker which stopped waiting doesn't take a permit
+        first.abort();
+        let _ = first.await;
+        tx.send(2).unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(parking.next().await, Some(2));
+
+        drop(tx);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(parking.next().await, None);
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Traffic paused from outside the run, by SIGUSR1 on unix
+pub struct Pause {
+    paused: tokio::sync::watch::Sender<bool>,
+    /// When each pause started and ended. The last one is open while paused.
+    intervals: Mutex<Vec<(Instant, Option<Instant>)>>,
+}
+
+impl Default for Pause {
+    fn default() -> Self {
+        Self {
+            paused: tokio::sync::watch::channel(false).0,
+            intervals: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Pause {
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Pause the traffic. Returns false if it's already paused.
+    pub fn pause(&self) -> bool {
+        let mut intervals = self.intervals.lock().unwrap();
+        if self.is_paused() {
+            return false;
+        }
+        intervals.push((Instant::now(), None));
+        self.paused.send_replace(true);
+        true
+    }
+
+    /// Resume the traffic. Returns false if it isn't paused.
+    pub fn resume(&self) -> bool {
+        let mut intervals = self.intervals.lock().unwrap();
+        if !self.is_paused() {
+            return false;
+        }
+        if let Some((_, end)) = intervals.last_mut() {
+            *end = Some(Instant::now());
+        }
+        self.paused.send_replace(false);
+        true
+    }
+
+    /// Pause or resume the traffic. Returns whether it's paused now.
+    pub fn toggle(&self) -> bool {
+        if self.is_paused() {
+            self.resume();
+            false
+        } else {
+            self.pause();
+            true
+        }
+    }
+
+    /// Wait while the traffic is paused, but not past `dead_line`
+    pub async fn wait_resumed(&self, dead_line: Option<Instant>) {
+        let mut paused = self.paused.subscribe();
+        let resumed = paused.wait_for(|paused| !*paused);
+        match dead_line {
+            Some(dead_line) => {
+                let _ = tokio::time::timeout_at(dead_line.into(), resumed).await;
+            }
+            None => {
+                let _ = resumed.await;
+            }
+        }
+    }
+
+    /// The pauses so far. A pause which hasn't ended ends at `now`.
+    pub fn intervals(&self, now: Instant) -> Vec<(Instant, Instant)> {
+        self.intervals
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|&(start, end)| (start, end.unwrap_or(now).max(start)))
+            .collect()
+    }
+
+    /// The total time paused until `now`
+    pub fn paused_time(&self, now: Instant) -> Duration {
+        self.intervals(now)
+            .iter()
+            .map(|(start, end)| *end - *start)
+            .sum()
+    }
+}
+
+/// Pause the traffic on SIGUSR1 and resume it on the next SIGUSR1 or on SIGUSR2, logging each
+/// change to stderr when `log` is set.
+#[cfg(unix)]
+pub async fn listen(
+    pause: std::sync::Arc<Pause>,
+    start: Instant,
+    log: bool,
+) -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut usr1 = signal(SignalKind::user_defined1())?;
+    let mut usr2 = signal(SignalKind::user_defined2())?;
+    loop {
+        let (changed, signal) = tokio::select! {
+            Some(()) = usr1.recv() => {
+                pause.toggle();
+                (true, "SIGUSR1")
+            }
+            Some(()) = usr2.recv() => (pause.resume(), "SIGUSR2"),
+            else => return Ok(()),
+        };
+        if changed && log {
+            eprintln!(
+                "{} {} by {signal} at {:.3} secs",
+                humantime::format_rfc3339_millis(std::time::SystemTime::now()),
+                if pause.is_paused() {
+                    "Paused"
+                } else {
+                    "Resumed"
+                },
+                start.elapsed().as_secs_f64()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intervals() {
+        let pause = Pause::default();
+        assert!(!pause.resume());
+        assert!(pause.pause());
+        assert!(!pause.pause());
+        assert!(pause.is_paused());
+        assert!(pause.resume());
+        assert!(pause.toggle());
+
+        let now = Instant::now();
+        let intervals = pause.intervals(now);
+        assert_eq!(intervals.len(), 2);
+        // The open pause ends now
+        assert_eq!(intervals[1].1, now);
+        assert!(pause.paused_time(now) < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_wait_resumed() {
+        let pause = std::sync::Arc::new(Pause::default());
+        // Not paused
+        pause.wait_resumed(None).await;
+
+        pause.pause();
+        let start = Instant::now();
+        tokio::spawn({
+            let pause = pause.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                pause.resume();
+            }
+        });
+        pause.wait_resumed(None).await;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(pause.paused_time(Instant::now()) >= Duration::from_millis(50));
+
+        // Cut by the dead line
+        pause.pause();
+        let dead_line = Instant::now() + Duration::from_millis(20);
+        pause.wait_resumed(Some(dead_line)).await;
+        assert!(Instant::now() >= dead_line);
+        assert!(pause.is_paused());
+    }
+}
@@ -1,15 +1,42 @@
 use crate::{
-    client::{ClientError, ConnectionTime, RequestResult},
-    histogram::histogram,
+    abort::{Abort, AbortReason},
+    agent::{Agent, AgentStatus},
+    aggregate::{ExpectContinueCounts, Extent, ResultAggregate},
+    body_dir::BodyDir,
+    burst::{self, BurstReleases},
+    client::{
+        ArrivalCounters, ArrivalDistribution, Http2Counters, IpFamilies, KeepAliveCounters,
+        LatencyIncludes, RequestResult,
+    },
+    connect_rate::StartupConnects,
+    duty_cycle::{DutyCycle, Phase},
+    histogram::LatencyHistogram,
+    label::{Label, Labels},
+    load_profile::LoadProfile,
+    pause::Pause,
+    qps_ramp::QpsRamp,
+    ramp_up::RampUp,
+    retry_after::RetryAfterCounters,
+    sampling::Sampling,
+    self_monitor::SelfMonitor,
+    shutdown::Shutdown,
+    style::{StyledContent, Stylize},
+    target_p99::{self, Adjustment, TargetP99},
+    think_time::ThinkTime,
+    url_list::UrlList,
+    websocket::WebSocketCounters,
 };
 use average::{Max, Variance};
 use byte_unit::Byte;
-use crossterm::style::{StyledContent, Stylize};
 use hyper::http::{self, StatusCode};
 use std::{
     collections::BTreeMap,
     io::Write,
-    time::{Duration, Instant},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
 #[derive(Clone, Copy)]
@@ -36,6 +63,20 @@ impl StyleScheme {
             self.no_color(text).bold()
         }
     }
+    fn aborted(self, text: &str) -> StyledContent<&str> {
+        if self.color_enabled {
+            text.red().bold()
+        } else {
+            self.no_color(text).bold()
+        }
+    }
+    fn warning(self, text: &str) -> StyledContent<&str> {
+        if self.color_enabled {
+            text.yellow().bold()
+        } else {
+            self.no_color(text).bold()
+        }
+    }
     fn fastest(self, text: &str) -> StyledContent<&str> {
         if self.color_enabled {
             text.green()
@@ -95,24 +136,388 @@ pub enum PrintMode {
     Json,
 }
 
+#[derive(Clone)]
+pub struct PrintConfig {
+    pub mode: PrintMode,
+    pub disable_color: bool,
+    pub stats_success_breakdown: bool,
+    pub per_method_stats: bool,
+    pub per_url_stats: bool,
+    /// `--assert-body-contains` or `--assert-body-regex` is given
+    pub assert_body: bool,
+    /// `--expect-status` is given
+    pub expect_status: bool,
+    /// `--max-body-bytes` is given
+    pub max_body_bytes: bool,
+    /// `-t` is given
+    pub timeout: bool,
+    /// `--decompressed-size` is given
+    pub decompressed_size: bool,
+    /// Connections established by `--prewarm-connections`
+    pub prewarm: Option<Prewarm>,
+    /// The first connections of the run. None with `--prewarm-connections`.
+    pub startup_connects: Option<Arc<StartupConnects>>,
+    /// `--connect-rate`
+    pub connect_rate: Option<f64>,
+    /// `--start-at` or `--start-in`
+    pub start_at: Option<StartAt>,
+    /// `--tcp-keepalive`
+    pub tcp_keepalive: Option<Duration>,
+    /// Events on HTTP/2 connections. None unless HTTP/2 is used.
+    pub http2_counters: Option<Arc<Http2Counters>>,
+    /// The requested host in Unicode with its punycode, if it's an internationalized name
+    pub idn_host: Option<String>,
+    /// TCP connections by the address family
+    pub ip_families: Option<Arc<IpFamilies>>,
+    /// Events on WebSocket connections. None unless WebSocket is used.
+    pub websocket_counters: Option<Arc<WebSocketCounters>>,
+    /// The bodies of `--body-dir`
+    pub body_dir: Option<Arc<BodyDir>>,
+    /// Reconnections of kept-alive connections. None on HTTP/2.
+    pub keepalive_counters: Option<Arc<KeepAliveCounters>>,
+    /// What the latency statistics measure
+    pub latency_includes: LatencyIncludes,
+    /// Pauses of the workers. None unless `--respect-retry-after` is given.
+    pub retry_after_counters: Option<Arc<RetryAfterCounters>>,
+    /// The rate limit of `-q` or `--arrival-rate`
+    pub qps: Option<f64>,
+    /// Releases of the bursts. None unless `--burst-delay` is used.
+    pub burst_releases: Option<Arc<BurstReleases>>,
+    /// `--ramp-up`
+    pub ramp_up: Option<Arc<RampUp>>,
+    /// `--qps-ramp`
+    pub qps_ramp: Option<QpsRamp>,
+    /// `--load-profile`
+    pub load_profile: Option<Arc<LoadProfile>>,
+    /// Late requests of `--arrival-rate`
+    pub arrival_counters: Option<Arc<ArrivalCounters>>,
+    /// None unless `--arrival-distribution` is given
+    pub arrival_distribution: Option<ArrivalDistribution>,
+    /// `--think-time`
+    pub think_time: Option<Arc<ThinkTime>>,
+    /// `--on` and `--off`
+    pub duty_cycle: Option<Arc<DutyCycle>>,
+    /// Pauses by SIGUSR1. None on Windows.
+    pub pause: Option<Arc<Pause>>,
+    /// `--abort-on-error-rate`, `--abort-on-first-error` and `--max-bytes`
+    pub abort: Option<Arc<Abort>>,
+    /// None unless `--max-bytes` is given
+    pub run_limits: Option<RunLimits>,
+    /// `--rand-seed`, or the one generated for the run
+    pub seed: u64,
+    /// `--runtime-threads`, or the number of CPUs
+    pub runtime_threads: usize,
+    /// `--target-p99`
+    pub target_p99: Option<Arc<TargetP99>>,
+    /// `-z`. None unless `--wait-ongoing-requests-after-deadline` is given.
+    pub test_duration: Option<Duration>,
+    /// The agents of `--workers`
+    pub agents: Option<Arc<[Agent]>>,
+    /// `--urls-from-file`
+    pub url_list: Option<Arc<UrlList>>,
+    /// The steps of a strict `--scenario` are recorded
+    pub per_step_stats: bool,
+    /// The names of the methods, steps and URLs of the results
+    pub labels: Arc<Labels>,
+    /// Results which found the channel to the collector full
+    pub backpressure: Arc<AtomicU64>,
+    /// `--histogram-precision`
+    pub histogram_precision: u8,
+    /// `--sample-rate`
+    pub sampling: Option<Arc<Sampling>>,
+    /// oha's own usage. None with `--workers`.
+    pub self_monitor: Option<Arc<SelfMonitor>>,
+    /// Tells whether a second ctrl-c dropped the requests in flight
+    pub shutdown: Arc<Shutdown>,
+}
+
+impl PrintConfig {
+    /// Whether a statistic needs every result rather than the aggregate of them. The results
+    /// are kept for the whole run then.
+    pub fn keeps_results(&self) -> bool {
+        self.per_method_stats
+            || self.per_url_stats
+            || self.per_step_stats
+            || self.load_profile.is_some()
+            || self.duty_cycle.is_some()
+            || self.qps_ramp.is_some()
+            || self.target_p99.is_some()
+            || self.burst_releases.is_some()
+            || self
+                .ramp_up
+                .as_ref()
+                .is_some_and(|ramp_up| ramp_up.is_excluded())
+    }
+}
+
+/// The limits of the run, to tell which one ended it when `--max-bytes` is combined with the others
+#[derive(Clone, Copy, Debug)]
+pub struct RunLimits {
+    /// `-n`. None with `-z` or `--forever`.
+    pub n_requests: Option<u64>,
+    /// `-z`
+    pub duration: Option<Duration>,
+    /// `--max-bytes`
+    pub max_bytes: u64,
+}
+
+/// What ended the run, shown with `--max-bytes`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+enum StoppedBy {
+    MaxBytes,
+    Duration,
+    Requests,
+    Aborted,
+    Interrupted,
+}
+
+/// `--sample-rate 1/N`
+#[derive(Debug, serde::Serialize)]
+struct SamplingStats {
+    every: u64,
+    /// The results collected, which the other counts are estimated from
+    #[serde(rename = "sampledResults")]
+    sampled_results: u64,
+    /// The statistics of the summary which are exact rather than estimated
+    exact: &'static [&'static str],
+}
+
+fn calculate_sampling(config: &PrintConfig) -> Option<SamplingStats> {
+    config.sampling.as_ref().map(|sampling| SamplingStats {
+        every: sampling.every(),
+        sampled_results: sampling.sampled(),
+        exact: &["successRate", "requestsPerSec", "totalData", "sizePerSec"],
+    })
+}
+
+/// The time from the start until the first `-c` connections were established
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+struct StartupConnectsStats {
+    connections: usize,
+    #[serde(serialize_with = "serialize_secs")]
+    duration: Duration,
+    #[serde(rename = "connectRate", skip_serializing_if = "Option::is_none")]
+    connect_rate: Option<f64>,
+}
+
+/// None until all the first connections were established
+fn calculate_startup_connects(
+    config: &PrintConfig,
+    start: Instant,
+) -> Option<StartupConnectsStats> {
+    let startup_connects = config.startup_connects.as_ref()?;
+    Some(StartupConnectsStats {
+        connections: startup_connects.target(),
+        duration: startup_connects.done()?.saturating_duration_since(start),
+        connect_rate: config.connect_rate,
+    })
+}
+
+/// The min, mean and max of the samples of oha's own usage
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+struct UsageStats {
+    min: f64,
+    mean: f64,
+    max: f64,
+}
+
+impl UsageStats {
+    fn new(extent: &Extent) -> Option<Self> {
+        (!extent.is_empty()).then(|| Self {
+            min: extent.fastest(),
+            mean: extent.average(),
+            max: extent.slowest(),
+        })
+    }
+}
+
+/// oha's own usage during the run, sampled once per second
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+struct GeneratorStats {
+    /// Percent of the runtime threads
+    cpu: Option<UsageStats>,
+    /// Resident set size in bytes
+    rss: Option<UsageStats>,
+    #[serde(rename = "openFiles")]
+    open_files: Option<UsageStats>,
+    #[serde(rename = "openFilesLimit")]
+    open_files_limit: Option<u64>,
+    /// The CPU reached 90% of the runtime threads
+    #[serde(rename = "cpuBound")]
+    cpu_bound: bool,
+    /// The open files reached 90% of the limit
+    #[serde(rename = "nearOpenFilesLimit")]
+    near_open_files_limit: bool,
+}
+
+/// None when nothing was sampled, e.g. with `--workers`
+fn calculate_generator(config: &PrintConfig) -> Option<GeneratorStats> {
+    let self_monitor = config.self_monitor.as_ref()?;
+    let samples = self_monitor.samples();
+    let stats = GeneratorStats {
+        cpu: UsageStats::new(&samples.cpu),
+        rss: UsageStats::new(&samples.rss),
+        open_files: UsageStats::new(&samples.open_files),
+        open_files_limit: self_monitor.open_files_limit(),
+        cpu_bound: self_monitor.cpu_bound(&samples),
+        near_open_files_limit: self_monitor.near_open_files_limit(&samples),
+    };
+    (stats.cpu.is_some() || stats.rss.is_some() || stats.open_files.is_some()).then_some(stats)
+}
+
+/// Connections established before the measurement. Not included in the total duration.
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+pub struct Prewarm {
+    pub connections: usize,
+    #[serde(serialize_with = "serialize_secs")]
+    pub duration: Duration,
+}
+
+/// The wall-clock start of `--start-at` or `--start-in`
+#[derive(Clone, Debug)]
+pub struct StartAt {
+    pub requested: SystemTime,
+    /// When the wait was over. Unset when interrupted before.
+    pub actual: Arc<OnceLock<SystemTime>>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct StartAtStats {
+    /// RFC 3339 in UTC
+    requested: String,
+    actual: Option<String>,
+    /// Seconds from the requested start to the actual one
+    late: Option<f64>,
+}
+
+fn calculate_start_at(config: &PrintConfig) -> Option<StartAtStats> {
+    let start_at = config.start_at.as_ref()?;
+    let actual = start_at.actual.get();
+    Some(StartAtStats {
+        requested: humantime::format_rfc3339_micros(start_at.requested).to_string(),
+        actual: actual.map(|actual| humantime::format_rfc3339_micros(*actual).to_string()),
+        late: actual.map(|actual| match actual.duration_since(start_at.requested) {
+            Ok(late) => late.as_secs_f64(),
+            Err(early) => -early.duration().as_secs_f64(),
+        }),
+    })
+}
+
+fn serialize_secs<S: serde::Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(duration.as_secs_f64())
+}
+
+/// Requests which started during `--ramp-up`, or `--stagger` with `--stagger-exclude`.
+/// They aren't included in the other statistics.
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+pub struct RampUpStats {
+    #[serde(skip)]
+    stagger: bool,
+    #[serde(serialize_with = "serialize_secs")]
+    duration: Duration,
+    requests: usize,
+    /// The average latency
+    average: f64,
+}
+
+/// The results collected so far, which a panic hook prints so that a crash late in a long
+/// run doesn't lose them, see `LoadConfig::partial_summary`
+#[derive(Default)]
+pub struct PartialSummary(std::sync::Mutex<Option<PartialSource>>);
+
+struct PartialSource {
+    config: PrintConfig,
+    start: Instant,
+    /// The aggregate of the collector. None when it's locked, e.g. by the panicking thread.
+    take: Box<dyn Fn() -> Option<ResultAggregate> + Send>,
+}
+
+impl PartialSummary {
+    /// Take the results with `take` on a panic until `finish`
+    pub(crate) fn set(
+        &self,
+        config: PrintConfig,
+        start: Instant,
+        take: impl Fn() -> Option<ResultAggregate> + Send + 'static,
+    ) {
+        if let Ok(mut source) = self.0.lock() {
+            *source = Some(PartialSource {
+                config,
+                start,
+                take: Box::new(take),
+            });
+        }
+    }
+
+    /// The collector has returned the results, which are printed as usual
+    pub(crate) fn finish(&self) {
+        if let Ok(mut source) = self.0.lock() {
+            *source = None;
+        }
+    }
+
+    /// Print the summary of the results so far, marked as partial. Nothing is printed when
+    /// there are none or they can't be taken.
+    pub fn print<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        let Ok(mut source) = self.0.try_lock() else {
+            return Ok(());
+        };
+        let Some(source) = source.take() else {
+            return Ok(());
+        };
+        let Some(aggregate) = (source.take)() else {
+            return Ok(());
+        };
+        writeln!(w, "Partial summary, of the results until oha panicked:")?;
+        print_result(
+            w,
+            source.config,
+            source.start,
+            aggregate,
+            source.start.elapsed(),
+        )
+    }
+}
+
 pub fn print_result<W: Write>(
     w: &mut W,
-    mode: PrintMode,
+    config: PrintConfig,
     start: Instant,
-    res: &[Result<RequestResult, ClientError>],
-    total_duration: Duration,
-    disable_color: bool,
-    stats_success_breakdown: bool,
-) -> anyhow::Result<()> {
-    match mode {
-        PrintMode::Text => print_summary(
-            w,
-            res,
-            total_duration,
-            disable_color,
-            stats_success_breakdown,
-        )?,
-        PrintMode::Json => print_json(w, start, res, total_duration, stats_success_breakdown)?,
+    mut aggregate: ResultAggregate,
+    mut total_duration: Duration,
+) -> std::io::Result<()> {
+    if let Some(sampling) = &config.sampling {
+        sampling.correct(&mut aggregate);
+    }
+    let mut ramp_up = None;
+    // Nothing is excluded when the run ends in the ramp-up
+    if let Some((duration, stagger)) = config
+        .ramp_up
+        .as_ref()
+        .filter(|ramp_up| ramp_up.is_excluded())
+        .map(|ramp_up| (ramp_up.duration(), ramp_up.is_stagger()))
+        .filter(|&(duration, _)| duration < total_duration)
+    {
+        let ramp_up_end = start + duration;
+        // The results are kept for it
+        let (ramp_up_res, steady): (Vec<_>, Vec<_>) = aggregate
+            .into_results()
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|r| r.as_ref().is_ok_and(|r| r.start < ramp_up_end));
+        ramp_up = Some(RampUpStats {
+            stagger,
+            duration,
+            requests: ramp_up_res.len(),
+            average: calculate_average_request(&ramp_up_res, config.latency_includes),
+        });
+        aggregate = ResultAggregate::from_results(&config, ramp_up_end, steady);
+        total_duration -= duration;
+    }
+    match config.mode {
+        PrintMode::Text => print_summary(w, config, ramp_up, start, &aggregate, total_duration)?,
+        PrintMode::Json => print_json(w, config, ramp_up, start, &aggregate, total_duration)?,
     }
     Ok(())
 }
@@ -120,28 +525,107 @@ pub fn print_result<W: Write>(
 /// Print all summary as JSON
 fn print_json<W: Write>(
     w: &mut W,
+    config: PrintConfig,
+    ramp_up: Option<RampUpStats>,
     start: Instant,
-    res: &[Result<RequestResult, ClientError>],
+    aggregate: &ResultAggregate,
     total_duration: Duration,
-    stats_success_breakdown: bool,
 ) -> serde_json::Result<()> {
     use serde::Serialize;
     #[derive(Serialize)]
     struct Summary {
         #[serde(rename = "successRate")]
         success_rate: f64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        aborted: Option<&'static str>,
+        #[serde(rename = "firstError", skip_serializing_if = "Option::is_none")]
+        first_error: Option<String>,
+        #[serde(rename = "stoppedBy", skip_serializing_if = "Option::is_none")]
+        stopped_by: Option<StoppedBy>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sampling: Option<SamplingStats>,
         total: f64,
+        #[serde(rename = "testDuration", skip_serializing_if = "Option::is_none")]
+        test_duration: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        drain: Option<f64>,
+        #[serde(rename = "randSeed")]
+        rand_seed: u64,
+        #[serde(rename = "runtimeThreads")]
+        runtime_threads: usize,
+        #[serde(rename = "latencyIncludes")]
+        latency_includes: LatencyIncludes,
         slowest: f64,
         fastest: f64,
         average: f64,
         #[serde(rename = "requestsPerSec")]
         requests_per_sec: f64,
+        #[serde(rename = "loadTime", skip_serializing_if = "Option::is_none")]
+        load_time: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pauses: Option<Vec<PauseInterval>>,
+        #[serde(rename = "requestedQps", skip_serializing_if = "Option::is_none")]
+        requested_qps: Option<f64>,
+        #[serde(rename = "lateArrivals", skip_serializing_if = "Option::is_none")]
+        late_arrivals: Option<usize>,
+        #[serde(rename = "sendDelay", skip_serializing_if = "Option::is_none")]
+        send_delay: Option<SendDelayStats>,
+        #[serde(rename = "arrivalsPerSecond", skip_serializing_if = "Option::is_none")]
+        arrivals_per_second: Option<ArrivalStats>,
+        /// Results which waited for the collector
+        backpressure: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        generator: Option<GeneratorStats>,
         #[serde(rename = "totalData")]
         total_data: u64,
         #[serde(rename = "sizePerRequest")]
         size_per_request: Option<u64>,
         #[serde(rename = "sizePerSec")]
         size_per_sec: f64,
+        #[serde(
+            rename = "totalDecompressedData",
+            skip_serializing_if = "Option::is_none"
+        )]
+        total_decompressed_data: Option<u64>,
+        #[serde(
+            rename = "decompressedSizePerRequest",
+            skip_serializing_if = "Option::is_none"
+        )]
+        decompressed_size_per_request: Option<Option<u64>>,
+        #[serde(rename = "assertionFailures", skip_serializing_if = "Option::is_none")]
+        assertion_failures: Option<usize>,
+        #[serde(rename = "truncatedBodies", skip_serializing_if = "Option::is_none")]
+        truncated_bodies: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        timeouts: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        prewarm: Option<Prewarm>,
+        #[serde(rename = "startupConnects", skip_serializing_if = "Option::is_none")]
+        startup_connects: Option<StartupConnectsStats>,
+        #[serde(rename = "startAt", skip_serializing_if = "Option::is_none")]
+        start_at: Option<StartAtStats>,
+        #[serde(rename = "rampUp", skip_serializing_if = "Option::is_none")]
+        ramp_up: Option<RampUpStats>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        stagger: Option<RampUpStats>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        host: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bodies: Option<BodiesUsed>,
+        #[serde(rename = "keepAlive", skip_serializing_if = "Option::is_none")]
+        keep_alive: Option<KeepAliveStats>,
+        #[serde(rename = "retryAfter", skip_serializing_if = "Option::is_none")]
+        retry_after: Option<RetryAfterStats>,
+        #[serde(rename = "thinkTime", skip_serializing_if = "Option::is_none")]
+        think_time: Option<ThinkTimeStats>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bursts: Option<BurstStats>,
+    }
+
+    #[derive(Serialize)]
+    struct BodiesUsed {
+        used: usize,
+        total: usize,
     }
 
     #[derive(Serialize)]
@@ -151,12 +635,51 @@ fn print_json<W: Write>(
         slowest: f64,
     }
 
+    #[derive(Serialize)]
+    struct Latencies {
+        all: Triple,
+        #[serde(rename = "noDns")]
+        no_dns: Triple,
+        #[serde(rename = "responseOnly")]
+        response_only: Triple,
+    }
+
+    let triple = |extent: &Extent| Triple {
+        average: extent.average(),
+        fastest: extent.fastest(),
+        slowest: extent.slowest(),
+    };
+    let latency = |includes| {
+        let extent = aggregate.latency_by(includes);
+        Triple {
+            // NaN without any response
+            average: if extent.is_empty() {
+                f64::NAN
+            } else {
+                extent.average()
+            },
+            ..triple(extent)
+        }
+    };
+
     #[derive(Serialize)]
     struct Details {
         #[serde(rename = "DNSDialup")]
         dns_dialup: Triple,
         #[serde(rename = "DNSLookup")]
         dns_lookup: Triple,
+        #[serde(rename = "firstByte")]
+        first_byte: Triple,
+        /// Latency by each definition of `--latency-includes`
+        latency: Latencies,
+        #[serde(rename = "tcpKeepalive", skip_serializing_if = "Option::is_none")]
+        tcp_keepalive: Option<f64>,
+        #[serde(rename = "ipFamilies", skip_serializing_if = "Option::is_none")]
+        ip_families: Option<IpFamilyStats>,
+        #[serde(rename = "TLSHandshake", skip_serializing_if = "Option::is_none")]
+        tls_handshake: Option<TlsHandshakeStats>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        connections: Option<ConnectionReuse>,
     }
 
     #[derive(Serialize)]
@@ -197,94 +720,138 @@ fn print_json<W: Write>(
         #[serde(rename = "rps")]
         rps: Rps,
         details: Details,
+        #[serde(
+            rename = "latencyPercentilesUnexpectedStatus",
+            skip_serializing_if = "Option::is_none"
+        )]
+        latency_percentiles_unexpected_status: Option<BTreeMap<String, f64>>,
         #[serde(rename = "statusCodeDistribution")]
         status_code_distribution: BTreeMap<String, usize>,
         #[serde(rename = "errorDistribution")]
         error_distribution: BTreeMap<String, usize>,
+        #[serde(rename = "expectContinue", skip_serializing_if = "Option::is_none")]
+        expect_continue: Option<ExpectContinueCounts>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        informational: Option<InformationalStats>,
+        #[serde(rename = "perMethod", skip_serializing_if = "Option::is_none")]
+        per_method: Option<BTreeMap<String, GroupStats>>,
+        #[serde(rename = "perStep", skip_serializing_if = "BTreeMap::is_empty")]
+        per_step: BTreeMap<String, GroupStats>,
+        #[serde(rename = "perLoadStep", skip_serializing_if = "Option::is_none")]
+        per_load_step: Option<BTreeMap<String, GroupStats>>,
+        #[serde(rename = "perDutyCyclePhase", skip_serializing_if = "Option::is_none")]
+        per_duty_cycle_phase: Option<BTreeMap<String, GroupStats>>,
+        #[serde(rename = "perUrl", skip_serializing_if = "Option::is_none")]
+        per_url: Option<BTreeMap<String, GroupStats>>,
+        #[serde(rename = "urlList", skip_serializing_if = "Option::is_none")]
+        url_list: Option<Vec<UrlListStats>>,
+        #[serde(rename = "perAgent", skip_serializing_if = "Option::is_none")]
+        per_agent: Option<BTreeMap<String, AgentStats>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        http2: Option<Http2Stats>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        websocket: Option<WebSocketStats>,
+        #[serde(rename = "qpsRamp", skip_serializing_if = "Option::is_none")]
+        qps_ramp: Option<Vec<QpsRampSecond>>,
+        #[serde(rename = "targetP99", skip_serializing_if = "Option::is_none")]
+        target_p99: Option<TargetP99Stats>,
     }
 
+    // The statistics which need every result. They're kept when any of them is shown.
+    let res = aggregate.results();
+    let load_duration = calculate_load_duration(&config, start, total_duration);
+    let pauses = calculate_pauses(&config, start, total_duration);
+    let drain = calculate_drain(&config, total_duration);
     let summary = Summary {
-        success_rate: calculate_success_rate(res),
+        success_rate: calculate_success_rate(aggregate),
+        aborted: match abort_reason(&config) {
+            Some(AbortReason::ErrorRate(_)) => Some("error rate exceeded"),
+            Some(AbortReason::FirstError(_)) => Some("first error"),
+            _ if config.shutdown.is_forced() => Some("forced quit"),
+            Some(AbortReason::MaxBytes) | None => None,
+        },
+        first_error: match abort_reason(&config) {
+            Some(AbortReason::FirstError(error)) => Some(error.clone()),
+            _ => None,
+        },
+        stopped_by: stopped_by(&config, aggregate, total_duration),
+        sampling: calculate_sampling(&config),
         total: total_duration.as_secs_f64(),
-        slowest: calculate_slowest_request(res),
-        fastest: calculate_fastest_request(res),
-        average: calculate_average_request(res),
-        requests_per_sec: calculate_requests_per_sec(res, total_duration),
-        total_data: calculate_total_data(res),
-        size_per_request: calculate_size_per_request(res),
-        size_per_sec: (calculate_size_per_sec(res, total_duration)),
+        test_duration: drain.map(|(test_duration, _)| test_duration.as_secs_f64()),
+        drain: drain.map(|(_, drain)| drain.as_secs_f64()),
+        rand_seed: config.seed,
+        runtime_threads: config.runtime_threads,
+        latency_includes: config.latency_includes,
+        slowest: aggregate.latency.max(),
+        fastest: aggregate.latency.min(),
+        average: aggregate.latency_average(),
+        requests_per_sec: calculate_requests_per_sec(aggregate, load_duration),
+        load_time: (config.duty_cycle.is_some() || pauses.is_some())
+            .then_some(load_duration.as_secs_f64()),
+        pauses,
+        requested_qps: config.qps,
+        late_arrivals: config
+            .arrival_counters
+            .as_ref()
+            .map(|counters| counters.late.load(Ordering::Relaxed)),
+        send_delay: calculate_send_delay(aggregate),
+        arrivals_per_second: calculate_arrivals(aggregate, &config, total_duration),
+        backpressure: config.backpressure.load(Ordering::Relaxed),
+        generator: calculate_generator(&config),
+        total_data: calculate_total_data(aggregate),
+        size_per_request: calculate_size_per_request(aggregate),
+        size_per_sec: (calculate_size_per_sec(aggregate, load_duration)),
+        total_decompressed_data: config
+            .decompressed_size
+            .then(|| calculate_total_decompressed_data(aggregate)),
+        decompressed_size_per_request: config
+            .decompressed_size
+            .then(|| calculate_decompressed_size_per_request(aggregate)),
+        assertion_failures: config.assert_body.then_some(aggregate.assertion_failures),
+        truncated_bodies: config.max_body_bytes.then_some(aggregate.truncated),
+        timeouts: config.timeout.then_some(aggregate.timeouts),
+        prewarm: config.prewarm,
+        startup_connects: calculate_startup_connects(&config, start),
+        start_at: calculate_start_at(&config),
+        ramp_up: ramp_up.filter(|ramp_up| !ramp_up.stagger),
+        stagger: ramp_up.filter(|ramp_up| ramp_up.stagger),
+        host: config.idn_host.clone(),
+        bodies: config.body_dir.as_ref().map(|dir| BodiesUsed {
+            used: dir.used(),
+            total: dir.count(),
+        }),
+        keep_alive: calculate_keepalive(&config),
+        retry_after: calculate_retry_after(&config),
+        think_time: calculate_think_time(&config),
+        bursts: calculate_bursts(res, &config),
     };
 
-    let mut durations = get_durations_all(res);
-
-    let response_time_histogram = histogram(&durations, 11)
-        .into_iter()
-        .map(|(k, v)| (k.to_string(), v))
-        .collect();
+    let response_time_histogram = histogram_labels(&aggregate.latency);
 
-    let latency_percentiles = percentiles(&mut durations);
+    let latency_percentiles = histogram_percentiles(&aggregate.latency);
 
     let mut response_time_histogram_successful: Option<BTreeMap<String, usize>> = None;
     let mut latency_percentiles_successful: Option<BTreeMap<String, f64>> = None;
     let mut response_time_histogram_not_successful: Option<BTreeMap<String, usize>> = None;
     let mut latency_percentiles_not_successful: Option<BTreeMap<String, f64>> = None;
 
-    if stats_success_breakdown {
-        let mut durations_successful = get_durations_successful(res);
-
-        response_time_histogram_successful = Some(
-            histogram(&durations_successful, 11)
-                .into_iter()
-                .map(|(k, v)| (k.to_string(), v))
-                .collect(),
-        );
-
-        latency_percentiles_successful = Some(percentiles(&mut durations_successful));
+    if config.stats_success_breakdown {
+        response_time_histogram_successful = Some(histogram_labels(&aggregate.latency_successful));
 
-        let mut durations_not_successful = get_durations_not_successful(res);
+        latency_percentiles_successful = Some(histogram_percentiles(&aggregate.latency_successful));
 
-        response_time_histogram_not_successful = Some(
-            histogram(&durations_not_successful, 11)
-                .into_iter()
-                .map(|(k, v)| (k.to_string(), v))
-                .collect(),
-        );
+        response_time_histogram_not_successful =
+            Some(histogram_labels(&aggregate.latency_not_successful));
 
-        latency_percentiles_not_successful = Some(percentiles(&mut durations_not_successful));
+        latency_percentiles_not_successful =
+            Some(histogram_percentiles(&aggregate.latency_not_successful));
     }
 
-    let mut ends = res
-        .iter()
-        .filter_map(|r| r.as_ref().ok())
-        .map(|r| (r.end - start).as_secs_f64())
-        .collect::<Vec<_>>();
-    ends.push(0.0);
-    float_ord::sort(&mut ends);
-
-    let mut rps: Vec<f64> = Vec::new();
-    // 10ms
-    const INTERVAL: f64 = 0.01;
-    let mut r = 0;
-    loop {
-        let prev_r = r;
-
-        // increment at least 1
-        if r + 1 < ends.len() {
-            r += 1;
-        }
-
-        while r + 1 < ends.len() && ends[prev_r] + INTERVAL > ends[r + 1] {
-            r += 1;
-        }
-
-        if r == prev_r {
-            break;
-        }
+    let latency_percentiles_unexpected_status = config
+        .expect_status
+        .then(|| histogram_percentiles(&aggregate.latency_unexpected_status));
 
-        let n = r - prev_r;
-        let t = ends[r] - ends[prev_r];
-        rps.push(n as f64 / t);
-    }
+    let mut rps = aggregate.rps(total_duration);
 
     let rps_percentiles = percentiles(&mut rps);
 
@@ -296,30 +863,19 @@ fn print_json<W: Write>(
         percentiles: rps_percentiles,
     };
 
-    let mut status_code_distribution: BTreeMap<http::StatusCode, usize> = Default::default();
-
-    for s in res.iter().filter_map(|r| r.as_ref().ok()).map(|r| r.status) {
-        *status_code_distribution.entry(s).or_default() += 1;
-    }
-
-    let mut error_distribution: BTreeMap<String, usize> = Default::default();
-    for e in res.iter().filter_map(|r| r.as_ref().err()) {
-        *error_distribution.entry(e.to_string()).or_default() += 1;
-    }
-
-    let connection_times: Vec<(std::time::Instant, ConnectionTime)> =
-        calculate_connection_times_base(res);
     let details = Details {
-        dns_dialup: Triple {
-            average: calculate_connection_times_dns_dialup_average(&connection_times),
-            fastest: calculate_connection_times_dns_dialup_fastest(&connection_times),
-            slowest: calculate_connection_times_dns_dialup_slowest(&connection_times),
-        },
-        dns_lookup: Triple {
-            average: calculate_connection_times_dns_lookup_average(&connection_times),
-            fastest: calculate_connection_times_dns_lookup_fastest(&connection_times),
-            slowest: calculate_connection_times_dns_lookup_slowest(&connection_times),
+        dns_dialup: triple(&aggregate.dns_dialup),
+        dns_lookup: triple(&aggregate.dns_lookup),
+        first_byte: triple(&aggregate.first_byte),
+        latency: Latencies {
+            all: latency(LatencyIncludes::All),
+            no_dns: latency(LatencyIncludes::NoDns),
+            response_only: latency(LatencyIncludes::ResponseOnly),
         },
+        tcp_keepalive: config.tcp_keepalive.map(|d| d.as_secs_f64()),
+        ip_families: calculate_ip_families(&config),
+        tls_handshake: calculate_tls_handshake(aggregate),
+        connections: calculate_connection_reuse(aggregate, &config),
     };
 
     serde_json::to_writer_pretty(
@@ -332,13 +888,32 @@ fn print_json<W: Write>(
             latency_percentiles_successful,
             response_time_histogram_not_successful,
             latency_percentiles_not_successful,
+            latency_percentiles_unexpected_status,
             rps,
             details,
-            status_code_distribution: status_code_distribution
-                .into_iter()
-                .map(|(k, v)| (k.as_u16().to_string(), v))
+            status_code_distribution: aggregate
+                .status
+                .iter()
+                .map(|(k, &(v, _))| (k.as_u16().to_string(), v))
                 .collect(),
-            error_distribution,
+            error_distribution: aggregate.errors.clone(),
+            expect_continue: aggregate.expect_continue,
+            informational: calculate_informational(aggregate),
+            per_method: config
+                .per_method_stats
+                .then(|| calculate_per_method(res, config.latency_includes, &config.labels)),
+            per_step: calculate_per_step(res, config.latency_includes, &config.labels),
+            per_load_step: calculate_per_load_step(res, &config, start),
+            per_duty_cycle_phase: calculate_per_duty_cycle_phase(res, &config),
+            per_url: config
+                .per_url_stats
+                .then(|| calculate_per_url(res, config.latency_includes, &config.labels)),
+            url_list: calculate_url_list(&config, load_duration),
+            per_agent: calculate_per_agent(&config),
+            http2: calculate_http2(aggregate, &config),
+            websocket: calculate_websocket(&config, total_duration),
+            qps_ramp: calculate_qps_ramp(res, &config, start),
+            target_p99: calculate_target_p99(res, &config, start),
         },
     )
 }
@@ -346,16 +921,23 @@ fn print_json<W: Write>(
 /// Print all summary as Text
 fn print_summary<W: Write>(
     w: &mut W,
-    res: &[Result<RequestResult, ClientError>],
+    config: PrintConfig,
+    ramp_up: Option<RampUpStats>,
+    start: Instant,
+    aggregate: &ResultAggregate,
     total_duration: Duration,
-    disable_color: bool,
-    stats_success_breakdown: bool,
 ) -> std::io::Result<()> {
     let style = StyleScheme {
-        color_enabled: !disable_color,
+        color_enabled: !config.disable_color,
     };
+    // The statistics which need every result. They're kept when any of them is shown.
+    let res = aggregate.results();
+    let load_duration = calculate_load_duration(&config, start, total_duration);
     writeln!(w, "{}", style.heading("Summary:"))?;
-    let success_rate = 100.0 * calculate_success_rate(res);
+    if let Some(host) = &config.idn_host {
+        writeln!(w, "  Host:\t{host}")?;
+    }
+    let success_rate = 100.0 * calculate_success_rate(aggregate);
     writeln!(
         w,
         "{}",
@@ -364,78 +946,396 @@ fn print_summary<W: Write>(
             success_rate
         )
     )?;
+    if let Some(sampling) = calculate_sampling(&config) {
+        writeln!(
+            w,
+            "  Sampled:\t1 in {} results ({} collected). The success rate, requests/sec and data are exact, the other counts are estimated as {} times the sample.",
+            sampling.every, sampling.sampled_results, sampling.every
+        )?;
+    }
+    match abort_reason(&config) {
+        Some(AbortReason::ErrorRate(error_rate)) => writeln!(
+            w,
+            "{}",
+            style.aborted(&format!(
+                "  Aborted:\terror rate exceeded {}% in {}",
+                error_rate.percent,
+                humantime::Duration::from(error_rate.window)
+            ))
+        )?,
+        Some(AbortReason::FirstError(error)) => writeln!(
+            w,
+            "{}",
+            style.aborted(&format!("  Aborted:\tfirst error: {error}"))
+        )?,
+        _ if config.shutdown.is_forced() => writeln!(
+            w,
+            "{}",
+            style.aborted(
+                "  Aborted:\tforced quit by a second ctrl-c, the requests in flight were dropped"
+            )
+        )?,
+        Some(AbortReason::MaxBytes) | None => {}
+    }
+    if let (Some(stopped_by), Some(limits)) = (
+        stopped_by(&config, aggregate, total_duration),
+        config.run_limits,
+    ) {
+        match stopped_by {
+            StoppedBy::MaxBytes => writeln!(
+                w,
+                "  Stopped by:\t--max-bytes {:.2} ({:.2} received)",
+                Byte::from_u64(limits.max_bytes).get_appropriate_unit(byte_unit::UnitType::Binary),
+                Byte::from_u64(calculate_total_data(aggregate))
+                    .get_appropriate_unit(byte_unit::UnitType::Binary)
+            )?,
+            StoppedBy::Duration => writeln!(
+                w,
+                "  Stopped by:\t-z {}",
+                humantime::Duration::from(limits.duration.unwrap_or_default())
+            )?,
+            StoppedBy::Requests => writeln!(
+                w,
+                "  Stopped by:\t-n {}",
+                limits.n_requests.unwrap_or_default()
+            )?,
+            StoppedBy::Aborted => writeln!(w, "  Stopped by:\tabort")?,
+            StoppedBy::Interrupted => writeln!(w, "  Stopped by:\tinterrupt")?,
+        }
+    }
     writeln!(w, "  Total:\t{:.4} secs", total_duration.as_secs_f64())?;
+    if let Some((test_duration, drain)) = calculate_drain(&config, total_duration) {
+        writeln!(
+            w,
+            "  Test duration:\t{:.4} secs",
+            test_duration.as_secs_f64()
+        )?;
+        writeln!(
+            w,
+            "  Drain:\t{:.4} secs (waiting for the requests in flight at the deadline)",
+            drain.as_secs_f64()
+        )?;
+    }
+    writeln!(w, "  Random seed:\t{} (--rand-seed)", config.seed)?;
+    writeln!(
+        w,
+        "  Runtime threads:\t{} (--runtime-threads)",
+        config.runtime_threads
+    )?;
+    if let Some(start_at) = calculate_start_at(&config) {
+        match (start_at.actual, start_at.late) {
+            (Some(actual), Some(late)) => writeln!(
+                w,
+                "  Start:\t{actual}, {:.4} secs after the requested {}",
+                late, start_at.requested
+            )?,
+            _ => writeln!(
+                w,
+                "  Start:\tinterrupted before the requested {}",
+                start_at.requested
+            )?,
+        }
+    }
+    if let Some(prewarm) = config.prewarm {
+        writeln!(
+            w,
+            "  Prewarm:\t{:.4} secs for {} connections (not included in Total)",
+            prewarm.duration.as_secs_f64(),
+            prewarm.connections
+        )?;
+    }
+    if let Some(startup_connects) = calculate_startup_connects(&config, start) {
+        writeln!(
+            w,
+            "  Startup connects:\t{:.4} secs for {} connections{} (included in the latency of the first requests)",
+            startup_connects.duration.as_secs_f64(),
+            startup_connects.connections,
+            match startup_connects.connect_rate {
+                Some(rate) => format!(", --connect-rate {rate}/sec"),
+                None => String::new(),
+            }
+        )?;
+    }
+    if let Some(ramp_up) = ramp_up {
+        writeln!(
+            w,
+            "  {}:\t{:.4} secs, {} requests, {:.4} secs average (not included in the others)",
+            if ramp_up.stagger {
+                "Stagger"
+            } else {
+                "Ramp-up"
+            },
+            ramp_up.duration.as_secs_f64(),
+            ramp_up.requests,
+            ramp_up.average
+        )?;
+    }
+    writeln!(w, "  Latency:\t{}", config.latency_includes.definition())?;
     writeln!(
         w,
         "{}",
-        style.slowest(&format!(
-            "  Slowest:\t{:.4} secs",
-            calculate_slowest_request(res)
-        ))
+        style.slowest(&format!("  Slowest:\t{:.4} secs", aggregate.latency.max()))
     )?;
     writeln!(
         w,
         "{}",
-        style.fastest(&format!(
-            "  Fastest:\t{:.4} secs",
-            calculate_fastest_request(res)
-        ))
+        style.fastest(&format!("  Fastest:\t{:.4} secs", aggregate.latency.min()))
     )?;
     writeln!(
         w,
         "{}",
         style.average(&format!(
             "  Average:\t{:.4} secs",
-            calculate_average_request(res)
+            aggregate.latency_average()
         ))
     )?;
     writeln!(
         w,
         "  Requests/sec:\t{:.4}",
-        calculate_requests_per_sec(res, total_duration)
+        calculate_requests_per_sec(aggregate, load_duration)
     )?;
+    let pauses = calculate_pauses(&config, start, total_duration);
+    let excluded = match (config.duty_cycle.is_some(), pauses.is_some()) {
+        (true, true) => Some("--off periods and pauses are"),
+        (true, false) => Some("--off periods are"),
+        (false, true) => Some("pauses are"),
+        (false, false) => None,
+    };
+    if let Some(excluded) = excluded {
+        writeln!(
+            w,
+            "  Time under load:\t{:.4} secs ({excluded} excluded from Requests/sec)",
+            load_duration.as_secs_f64()
+        )?;
+    }
+    if let Some(pauses) = &pauses {
+        writeln!(
+            w,
+            "  Pauses:\t{}, {:.4} secs in total",
+            pauses.len(),
+            pauses.iter().map(|p| p.end - p.start).sum::<f64>()
+        )?;
+        for pause in pauses {
+            writeln!(w, "    {:.4} secs to {:.4} secs", pause.start, pause.end)?;
+        }
+    }
+    if let Some(qps) = config.qps {
+        let achieved = calculate_requests_per_sec(aggregate, load_duration) / qps * 100.0;
+        if qps < 1.0 {
+            writeln!(
+                w,
+                "  Requested QPS:\t{:.4} ({}, {:.1}% achieved)",
+                qps,
+                format_rate(qps),
+                achieved
+            )?;
+        } else {
+            writeln!(w, "  Requested QPS:\t{qps:.4} ({achieved:.1}% achieved)")?;
+        }
+    }
+    if let Some(send_delay) = calculate_send_delay(aggregate) {
+        writeln!(
+            w,
+            "  Send delay:\t{:.4} secs average, {:.4} secs p99, {:.4} secs max (from the scheduled time until a worker picked the request up)",
+            send_delay.average, send_delay.p99, send_delay.max
+        )?;
+        if send_delay.behind {
+            writeln!(
+                w,
+                "{}",
+                style.warning(&format!(
+                    "  Warning:\t{} requests started more than one interval late. oha couldn't sustain the requested rate, raise -c or check the CPU of this machine.",
+                    send_delay.late
+                ))
+            )?;
+        }
+    }
+    if let Some(arrivals) = calculate_arrivals(aggregate, &config, total_duration) {
+        writeln!(
+            w,
+            "  Arrivals/sec:\t{:.2} mean, {:.2} stddev, {} min, {} max (dispersion {:.2}, {})",
+            arrivals.mean,
+            arrivals.stddev,
+            arrivals.min,
+            arrivals.max,
+            arrivals.dispersion,
+            arrivals.distribution.name()
+        )?;
+    }
+    if let Some(counters) = &config.arrival_counters {
+        writeln!(
+            w,
+            "  Late arrivals:\t{} (started late because of --max-in-flight)",
+            counters.late.load(Ordering::Relaxed)
+        )?;
+    }
+    let backpressure = config.backpressure.load(Ordering::Relaxed);
+    if backpressure > 0 {
+        writeln!(
+            w,
+            "{}",
+            style.warning(&format!(
+                "  Warning:\t{backpressure} results waited for the collector of the results. oha was collector-bound, so the rates may be lower than the server allows."
+            ))
+        )?;
+    }
+    if let Some(generator) = calculate_generator(&config) {
+        let threads = config.runtime_threads;
+        let mut usage = Vec::new();
+        if let Some(cpu) = generator.cpu {
+            usage.push(format!(
+                "CPU {:.1}% / {:.1}% / {:.1}% of {threads} runtime threads",
+                cpu.min, cpu.mean, cpu.max
+            ));
+        }
+        if let Some(rss) = generator.rss {
+            let bytes =
+                |v: f64| Byte::from_u64(v as u64).get_appropriate_unit(byte_unit::UnitType::Binary);
+            usage.push(format!(
+                "RSS {:.2} / {:.2} / {:.2}",
+                bytes(rss.min),
+                bytes(rss.mean),
+                bytes(rss.max)
+            ));
+        }
+        if let Some(open_files) = generator.open_files {
+            let limit = match generator.open_files_limit {
+                Some(limit) => format!(" of {limit}"),
+                None => String::new(),
+            };
+            usage.push(format!(
+                "open files {:.0} / {:.0} / {:.0}{limit}",
+                open_files.min, open_files.mean, open_files.max
+            ));
+        }
+        writeln!(
+            w,
+            "  Generator:\t{} (min / mean / max of oha itself)",
+            usage.join(", ")
+        )?;
+        if let Some(cpu) = generator.cpu.filter(|_| generator.cpu_bound) {
+            writeln!(
+                w,
+                "{}",
+                style.warning(&format!(
+                    "  Warning:\toha used up to {:.1}% of its {threads} runtime threads. The generator may have been the bottleneck, raise --runtime-threads or spread the load with --workers.",
+                    cpu.max
+                ))
+            )?;
+        }
+        if let Some((open_files, limit)) = generator
+            .open_files
+            .zip(generator.open_files_limit)
+            .filter(|_| generator.near_open_files_limit)
+        {
+            writeln!(
+                w,
+                "{}",
+                style.warning(&format!(
+                    "  Warning:\toha had up to {:.0} open files of its limit of {limit}. Raise it with ulimit -n before the connections fail with too many open files.",
+                    open_files.max
+                ))
+            )?;
+        }
+    }
+    if config.assert_body {
+        writeln!(w, "  Assertion failures:\t{}", aggregate.assertion_failures)?;
+    }
+    if config.max_body_bytes {
+        writeln!(w, "  Truncated bodies:\t{}", aggregate.truncated)?;
+    }
+    if config.timeout {
+        writeln!(w, "  Timeouts:\t{}", aggregate.timeouts)?;
+    }
+    if let Some(keep_alive) = calculate_keepalive(&config) {
+        writeln!(
+            w,
+            "  Keep-alive:\t{} idle closes, {} stale connection retries",
+            keep_alive.idle_closes, keep_alive.stale_retries
+        )?;
+    }
+    if let Some(retry_after) = calculate_retry_after(&config) {
+        writeln!(
+            w,
+            "  Retry-After:\t{} responses, {:.4} secs paused",
+            retry_after.responses, retry_after.paused
+        )?;
+    }
+    if let Some(think_time) = calculate_think_time(&config) {
+        writeln!(
+            w,
+            "  Think time:\t{} pauses, {:.4} secs in total (not included in the latency)",
+            think_time.pauses, think_time.paused
+        )?;
+    }
+    if let Some(bursts) = calculate_bursts(res, &config) {
+        writeln!(
+            w,
+            "  Burst completion:\t{:.4} secs, {:.4} secs, {:.4} secs over {} bursts",
+            bursts.average, bursts.fastest, bursts.slowest, bursts.count
+        )?;
+    }
+    if let Some(dir) = &config.body_dir {
+        writeln!(w, "  Bodies used:\t{} of {} files", dir.used(), dir.count())?;
+    }
     writeln!(w)?;
     writeln!(
         w,
-        "  Total data:\t{:.2}",
-        Byte::from_u64(calculate_total_data(res)).get_appropriate_unit(byte_unit::UnitType::Binary)
+        "  Total data:\t{:.2} (wire bytes)",
+        Byte::from_u64(calculate_total_data(aggregate))
+            .get_appropriate_unit(byte_unit::UnitType::Binary)
     )?;
     writeln!(
         w,
-        "  Size/request:\t{:.2}",
-        (calculate_size_per_request(res))
-            .map(|n| Byte::from_u64(n)
-                .get_appropriate_unit(byte_unit::UnitType::Binary)
-                .to_string())
+        "  Size/request:\t{} (wire bytes)",
+        (calculate_size_per_request(aggregate))
+            .map(|n| format!(
+                "{:.2}",
+                Byte::from_u64(n).get_appropriate_unit(byte_unit::UnitType::Binary)
+            ))
             .unwrap_or_else(|| "NaN".to_string())
     )?;
     writeln!(
         w,
-        "  Size/sec:\t{:.2}",
-        Byte::from_u64((calculate_size_per_sec(res, total_duration)) as u64)
+        "  Size/sec:\t{:.2} (wire bytes)",
+        Byte::from_u64((calculate_size_per_sec(aggregate, load_duration)) as u64)
             .get_appropriate_unit(byte_unit::UnitType::Binary)
     )?;
+    if config.decompressed_size {
+        writeln!(
+            w,
+            "  Total data:\t{:.2} (decompressed)",
+            Byte::from_u64(calculate_total_decompressed_data(aggregate))
+                .get_appropriate_unit(byte_unit::UnitType::Binary)
+        )?;
+        writeln!(
+            w,
+            "  Size/request:\t{} (decompressed)",
+            calculate_decompressed_size_per_request(aggregate)
+                .map(|n| format!(
+                    "{:.2}",
+                    Byte::from_u64(n).get_appropriate_unit(byte_unit::UnitType::Binary)
+                ))
+                .unwrap_or_else(|| "NaN".to_string())
+        )?;
+    }
     writeln!(w)?;
 
-    let mut durations = get_durations_all(res);
-
     writeln!(w, "{}", style.heading("Response time histogram:"))?;
-    print_histogram(w, &durations, style)?;
+    print_histogram(w, &aggregate.latency, style)?;
     writeln!(w)?;
 
     writeln!(w, "{}", style.heading("Response time distribution:"))?;
-    print_distribution(w, &mut durations, style)?;
+    print_distribution(w, histogram_percentile_iter(&aggregate.latency), style)?;
     writeln!(w)?;
 
-    if stats_success_breakdown {
-        let mut durations_successful = get_durations_successful(res);
-
+    if config.stats_success_breakdown {
         writeln!(
             w,
             "{}",
             style.heading("Response time histogram (2xx only):")
         )?;
-        print_histogram(w, &durations_successful, style)?;
+        print_histogram(w, &aggregate.latency_successful, style)?;
         writeln!(w)?;
 
         writeln!(
@@ -443,17 +1343,19 @@ fn print_summary<W: Write>(
             "{}",
             style.heading("Response time distribution (2xx only):")
         )?;
-        print_distribution(w, &mut durations_successful, style)?;
+        print_distribution(
+            w,
+            histogram_percentile_iter(&aggregate.latency_successful),
+            style,
+        )?;
         writeln!(w)?;
 
-        let mut durations_not_successful = get_durations_not_successful(res);
-
         writeln!(
             w,
             "{}",
             style.heading("Response time histogram (4xx + 5xx only):")
         )?;
-        print_histogram(w, &durations_not_successful, style)?;
+        print_histogram(w, &aggregate.latency_not_successful, style)?;
         writeln!(w)?;
 
         writeln!(
@@ -461,13 +1363,28 @@ fn print_summary<W: Write>(
             "{}",
             style.heading("Response time distribution (4xx + 5xx only):")
         )?;
-        print_distribution(w, &mut durations_not_successful, style)?;
+        print_distribution(
+            w,
+            histogram_percentile_iter(&aggregate.latency_not_successful),
+            style,
+        )?;
+        writeln!(w)?;
+    }
+    if config.expect_status {
+        writeln!(
+            w,
+            "{}",
+            style.heading("Response time distribution (unexpected status only):")
+        )?;
+        print_distribution(
+            w,
+            histogram_percentile_iter(&aggregate.latency_unexpected_status),
+            style,
+        )?;
         writeln!(w)?;
     }
     writeln!(w)?;
 
-    let connection_times: Vec<(std::time::Instant, ConnectionTime)> =
-        calculate_connection_times_base(res);
     writeln!(
         w,
         "{}",
@@ -477,47 +1394,287 @@ fn print_summary<W: Write>(
     writeln!(
         w,
         "  DNS+dialup:\t{:.4} secs, {:.4} secs, {:.4} secs",
-        calculate_connection_times_dns_dialup_average(&connection_times),
-        calculate_connection_times_dns_dialup_fastest(&connection_times),
-        calculate_connection_times_dns_dialup_slowest(&connection_times),
+        aggregate.dns_dialup.average(),
+        aggregate.dns_dialup.fastest(),
+        aggregate.dns_dialup.slowest(),
     )?;
     writeln!(
         w,
         "  DNS-lookup:\t{:.4} secs, {:.4} secs, {:.4} secs",
-        calculate_connection_times_dns_lookup_average(&connection_times),
-        calculate_connection_times_dns_lookup_fastest(&connection_times),
-        calculate_connection_times_dns_lookup_slowest(&connection_times),
+        aggregate.dns_lookup.average(),
+        aggregate.dns_lookup.fastest(),
+        aggregate.dns_lookup.slowest(),
     )?;
-    writeln!(w)?;
-
-    let mut status_dist: BTreeMap<http::StatusCode, usize> = Default::default();
-
-    for s in res.iter().filter_map(|r| r.as_ref().ok()).map(|r| r.status) {
-        *status_dist.entry(s).or_default() += 1;
-    }
-
-    let mut status_v: Vec<(http::StatusCode, usize)> = status_dist.into_iter().collect();
-    status_v.sort_by_key(|t| std::cmp::Reverse(t.1));
-
+    if let Some(tls) = calculate_tls_handshake(aggregate) {
+        writeln!(
+            w,
+            "  TLS handshake:\t{:.4} secs, {:.4} secs, {:.4} secs ({})",
+            tls.average, tls.fastest, tls.slowest, tls.backend,
+        )?;
+        if let (Some(full), Some(resumed)) = (tls.full, tls.resumed) {
+            writeln!(
+                w,
+                "  TLS full:\t{} handshakes, {:.4} secs average",
+                full.count, full.average,
+            )?;
+            writeln!(
+                w,
+                "  TLS resumed:\t{} handshakes, {:.4} secs average",
+                resumed.count, resumed.average,
+            )?;
+        }
+    }
+    writeln!(
+        w,
+        "  First byte:\t{:.4} secs, {:.4} secs, {:.4} secs",
+        aggregate.first_byte.average(),
+        aggregate.first_byte.fastest(),
+        aggregate.first_byte.slowest(),
+    )?;
+    if let Some(tcp_keepalive) = config.tcp_keepalive {
+        writeln!(
+            w,
+            "  TCP keepalive:\t{:.4} secs idle",
+            tcp_keepalive.as_secs_f64()
+        )?;
+    }
+    if let Some(families) = calculate_ip_families(&config) {
+        writeln!(
+            w,
+            "  IP families:\t{} IPv6, {} IPv4 connections, {} first",
+            families.ipv6,
+            families.ipv4,
+            if families.preferred == "ipv4" {
+                "IPv4"
+            } else {
+                "IPv6"
+            }
+        )?;
+    }
+    if let Some(reuse) = calculate_connection_reuse(aggregate, &config) {
+        writeln!(
+            w,
+            "  Connections:\t{} new, {} reused ({:.2}% reused)",
+            reuse.new,
+            reuse.reused,
+            reuse.ratio * 100.0
+        )?;
+    }
+    writeln!(w)?;
+
+    if let Some(http2) = calculate_http2(aggregate, &config) {
+        writeln!(w, "{}", style.heading("HTTP/2 connections:"))?;
+        writeln!(w, "  Connections:\t{}", http2.connections)?;
+        writeln!(
+            w,
+            "  Streams/connection:\t{:.2} average, {} min, {} max",
+            http2.streams_per_connection.average,
+            http2.streams_per_connection.min,
+            http2.streams_per_connection.max
+        )?;
+        writeln!(w, "  GOAWAY received:\t{}", http2.goaways)?;
+        writeln!(
+            w,
+            "  Retried after REFUSED_STREAM:\t{}",
+            http2.refused_stream_retries
+        )?;
+        writeln!(w, "  Resent after GOAWAY:\t{}", http2.goaway_resends)?;
+        if !http2.errors.is_empty() {
+            writeln!(w, "  Errors:")?;
+            for (code, count) in &http2.errors {
+                writeln!(w, "    [{count}] {code}")?;
+            }
+        }
+        writeln!(w)?;
+    }
+
+    if let Some(websocket) = calculate_websocket(&config, total_duration) {
+        writeln!(w, "{}", style.heading("WebSocket:"))?;
+        writeln!(
+            w,
+            "  Handshakes:\t{} ({:.4} per sec)",
+            websocket.handshakes, websocket.handshakes_per_sec
+        )?;
+        writeln!(w, "  Pings sent:\t{}", websocket.pings)?;
+        writeln!(w)?;
+    }
+
+    let mut status_v: Vec<(http::StatusCode, (usize, bool))> =
+        aggregate.status.clone().into_iter().collect();
+    status_v.sort_by_key(|t| std::cmp::Reverse(t.1 .0));
+
     writeln!(w, "{}", style.heading("Status code distribution:"))?;
 
-    for (status, count) in status_v {
+    for (status, (count, unexpected)) in status_v {
         writeln!(
             w,
             "{}",
             style.status_distribution(
-                &format!("  [{}] {} responses", status.as_str(), count),
+                &format!(
+                    "  [{}] {} responses{}",
+                    status.as_str(),
+                    count,
+                    if unexpected { " (unexpected)" } else { "" }
+                ),
                 status
             )
         )?;
     }
 
-    let mut error_dist: BTreeMap<String, usize> = Default::default();
-    for e in res.iter().filter_map(|r| r.as_ref().err()) {
-        *error_dist.entry(e.to_string()).or_default() += 1;
+    if config.per_method_stats {
+        writeln!(w)?;
+        writeln!(w, "{}", style.heading("Per method:"))?;
+        print_grouped(
+            w,
+            calculate_per_method(res, config.latency_includes, &config.labels),
+        )?;
+    }
+
+    let per_step = calculate_per_step(res, config.latency_includes, &config.labels);
+    if !per_step.is_empty() {
+        writeln!(w)?;
+        writeln!(w, "{}", style.heading("Per step:"))?;
+        print_grouped(w, per_step)?;
+    }
+
+    if let Some(per_load_step) = calculate_per_load_step(res, &config, start) {
+        writeln!(w)?;
+        writeln!(w, "{}", style.heading("Per load profile step:"))?;
+        print_grouped(w, per_load_step)?;
+    }
+
+    if let Some(per_phase) = calculate_per_duty_cycle_phase(res, &config) {
+        writeln!(w)?;
+        writeln!(w, "{}", style.heading("Per duty cycle phase:"))?;
+        print_grouped(w, per_phase)?;
     }
 
-    let mut error_v: Vec<(String, usize)> = error_dist.into_iter().collect();
+    if config.per_url_stats {
+        writeln!(w)?;
+        writeln!(w, "{}", style.heading("Per URL:"))?;
+        print_grouped(
+            w,
+            calculate_per_url(res, config.latency_includes, &config.labels),
+        )?;
+    }
+
+    if let Some(urls) = calculate_url_list(&config, load_duration) {
+        writeln!(w)?;
+        writeln!(w, "{}", style.heading("URL list:"))?;
+        for url in urls {
+            write!(
+                w,
+                "  {}:\t{} requests, {:.2} average and {} peak concurrency",
+                url.url, url.requests, url.average_concurrency, url.peak_concurrency
+            )?;
+            match url.max_concurrent {
+                Some(max_concurrent) => {
+                    writeln!(w, " of {max_concurrent} max, full {} times", url.blocked)?
+                }
+                None => writeln!(w)?,
+            }
+        }
+    }
+
+    if let Some(per_agent) = calculate_per_agent(&config) {
+        writeln!(w)?;
+        writeln!(w, "{}", style.heading("Per agent:"))?;
+        for (address, agent) in per_agent {
+            match agent.stats {
+                Some(stats) => print_grouped(w, BTreeMap::from([(address, stats)]))?,
+                None => writeln!(w, "  {address}: 0 responses")?,
+            }
+            if agent.errors > 0 {
+                writeln!(w, "    Errors:\t{}", agent.errors)?;
+            }
+            match agent.error {
+                Some(error) => writeln!(w, "    Status:\t{} ({error})", agent.status)?,
+                None => writeln!(w, "    Status:\t{}", agent.status)?,
+            }
+        }
+    }
+
+    if let Some(seconds) = calculate_qps_ramp(res, &config, start) {
+        writeln!(w)?;
+        writeln!(w, "{}", style.heading("QPS ramp (target, achieved, p99):"))?;
+        for s in seconds {
+            writeln!(
+                w,
+                "  {}s:\t{:.2} QPS, {} QPS, {:.4} secs",
+                s.second, s.target, s.achieved, s.p99
+            )?;
+        }
+    }
+
+    if let Some(mut stats) = calculate_target_p99(res, &config, start) {
+        writeln!(w)?;
+        writeln!(
+            w,
+            "{}",
+            style.heading(&format!(
+                "Target p99 ({}):",
+                humantime::Duration::from(config.target_p99.as_ref().unwrap().target())
+            ))
+        )?;
+        match stats.stable_rate {
+            Some(rate) => {
+                writeln!(
+                    w,
+                    "  Stable rate:\t{} (the windows under the target in the second half of the run)",
+                    format_rate(rate)
+                )?;
+                writeln!(w, "  Latency at the stable rate:")?;
+                print_distribution(w, percentile_iter(&mut stats.stable_latencies), style)?;
+            }
+            None => writeln!(
+                w,
+                "  Stable rate:\tnone, no window in the second half of the run was under the target"
+            )?,
+        }
+        writeln!(w, "  Windows (setpoint, achieved, p99):")?;
+        for window in &stats.windows {
+            writeln!(
+                w,
+                "    {:.0}s to {:.0}s:\t{:.2} QPS, {:.2} QPS, {:.4} secs, {}",
+                window.start,
+                window.end,
+                window.setpoint,
+                window.achieved,
+                window.p99,
+                match window.action {
+                    target_p99::Action::Increase => "increase",
+                    target_p99::Action::Decrease => "decrease",
+                    target_p99::Action::Hold => "hold, the workers can't keep up",
+                }
+            )?;
+        }
+    }
+
+    if let Some(counts) = aggregate.expect_continue {
+        writeln!(w)?;
+        writeln!(w, "{}", style.heading("Expect 100-continue:"))?;
+        writeln!(w, "  [{}] 100 Continue received", counts.received)?;
+        writeln!(w, "  [{}] Timed out, body sent anyway", counts.timed_out)?;
+        writeln!(
+            w,
+            "  [{}] Final response first, body not sent",
+            counts.body_not_sent
+        )?;
+    }
+
+    if let Some(stats) = calculate_informational(aggregate) {
+        writeln!(w)?;
+        writeln!(w, "{}", style.heading("Informational responses:"))?;
+        writeln!(w, "  [{}] Requests with 1xx responses", stats.requests)?;
+        writeln!(w, "  [{}] 103 Early Hints", stats.early_hints)?;
+        writeln!(
+            w,
+            "  First 1xx:\t{:.4} secs, {:.4} secs, {:.4} secs (average, fastest, slowest)",
+            stats.first.average, stats.first.fastest, stats.first.slowest
+        )?;
+    }
+
+    let mut error_v: Vec<(String, usize)> = aggregate.errors.clone().into_iter().collect();
     error_v.sort_by_key(|t| std::cmp::Reverse(t.1));
 
     if !error_v.is_empty() {
@@ -531,16 +1688,38 @@ fn print_summary<W: Write>(
     Ok(())
 }
 
+fn print_grouped<W: Write>(w: &mut W, groups: BTreeMap<String, GroupStats>) -> std::io::Result<()> {
+    for (key, stats) in groups {
+        writeln!(w, "  {key}: {} responses", stats.count)?;
+        writeln!(
+            w,
+            "    Average/Fastest/Slowest:\t{:.4} secs, {:.4} secs, {:.4} secs",
+            stats.average, stats.fastest, stats.slowest
+        )?;
+        let mut status_v: Vec<(String, usize)> =
+            stats.status_code_distribution.into_iter().collect();
+        status_v.sort_by_key(|t| std::cmp::Reverse(t.1));
+        for (status, count) in status_v {
+            writeln!(w, "    [{status}] {count} responses")?;
+        }
+    }
+    Ok(())
+}
+
 /// Print histogram of series of f64 data.
 /// This is used to print histogram of response time.
-fn print_histogram<W: Write>(w: &mut W, values: &[f64], style: StyleScheme) -> std::io::Result<()> {
+fn print_histogram<W: Write>(
+    w: &mut W,
+    histogram: &LatencyHistogram,
+    style: StyleScheme,
+) -> std::io::Result<()> {
     // TODO: Use better algorithm.
     // Is there any common and good algorithm?
-    if values.is_empty() {
+    if histogram.is_empty() {
         return Ok(());
     }
     let lines = 11;
-    let data = crate::histogram::histogram(values, lines);
+    let data = histogram.linear(lines);
 
     let max_bar = data.iter().map(|t| t.1).max().unwrap();
     let str_len_max = max_bar.to_string().len();
@@ -583,24 +1762,33 @@ fn bar<W: Write>(w: &mut W, ratio: f64, style: StyleScheme, label: f64) -> std::
     Ok(())
 }
 
+const PERCENTILES: [f64; 9] = [10.0, 25.0, 50.0, 75.0, 90.0, 95.0, 99.0, 99.9, 99.99];
+
 fn percentile_iter(values: &mut [f64]) -> impl Iterator<Item = (f64, f64)> + '_ {
     float_ord::sort(values);
 
-    [10.0, 25.0, 50.0, 75.0, 90.0, 95.0, 99.0, 99.9, 99.99]
+    PERCENTILES.iter().map(move |&p| {
+        let i = (p / 100.0 * values.len() as f64) as usize;
+        (p, *values.get(i).unwrap_or(&f64::NAN))
+    })
+}
+
+/// Like `percentile_iter`, within the resolution of the histogram
+fn histogram_percentile_iter(
+    histogram: &LatencyHistogram,
+) -> impl Iterator<Item = (f64, f64)> + '_ {
+    PERCENTILES
         .iter()
-        .map(move |&p| {
-            let i = (p / 100.0 * values.len() as f64) as usize;
-            (p, *values.get(i).unwrap_or(&std::f64::NAN))
-        })
+        .map(move |&p| (p, histogram.percentile(p)))
 }
 
 /// Print distribution of collection of f64
 fn print_distribution<W: Write>(
     w: &mut W,
-    values: &mut [f64],
+    percentiles: impl Iterator<Item = (f64, f64)>,
     style: StyleScheme,
 ) -> std::io::Result<()> {
-    for (p, v) in percentile_iter(values) {
+    for (p, v) in percentiles {
         writeln!(
             w,
             "{}",
@@ -617,39 +1805,60 @@ fn percentiles(values: &mut [f64]) -> BTreeMap<String, f64> {
         .collect()
 }
 
-fn calculate_success_rate(res: &[Result<RequestResult, ClientError>]) -> f64 {
-    // We ignore deadline errors which are because of `-z` option, not because of the server
-    let iter = res
-        .iter()
-        .filter(|r| !matches!(r, Err(ClientError::Deadline)));
+fn histogram_percentiles(histogram: &LatencyHistogram) -> BTreeMap<String, f64> {
+    histogram_percentile_iter(histogram)
+        .map(|(p, v)| (format!("p{p}"), v))
+        .collect()
+}
 
-    let denominator = iter.clone().count();
-    let numerator = iter.filter(|r| r.is_ok()).count();
+/// The bins of the response time histogram by their labels
+fn histogram_labels(histogram: &LatencyHistogram) -> BTreeMap<String, usize> {
+    histogram
+        .linear(11)
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect()
+}
 
-    numerator as f64 / denominator as f64
+/// Why the run was aborted by `--abort-on-error-rate` or `--abort-on-first-error`
+fn abort_reason(config: &PrintConfig) -> Option<&AbortReason> {
+    config.abort.as_ref()?.reason()
 }
 
-fn calculate_slowest_request<E>(res: &[Result<RequestResult, E>]) -> f64 {
-    res.iter()
-        .filter_map(|r| r.as_ref().ok())
-        .map(|r| r.duration().as_secs_f64())
-        .collect::<average::Max>()
-        .max()
+/// Which limit ended the run. None unless `--max-bytes` is given.
+fn stopped_by(
+    config: &PrintConfig,
+    aggregate: &ResultAggregate,
+    total_duration: Duration,
+) -> Option<StoppedBy> {
+    let limits = config.run_limits?;
+    Some(match abort_reason(config) {
+        Some(AbortReason::MaxBytes) => StoppedBy::MaxBytes,
+        Some(_) => StoppedBy::Aborted,
+        None if limits.duration.is_some_and(|d| total_duration >= d) => StoppedBy::Duration,
+        None if limits
+            .n_requests
+            .is_some_and(|n| aggregate.count as u64 >= n) =>
+        {
+            StoppedBy::Requests
+        }
+        // ctrl-c prints the summary before the limits are reached
+        None => StoppedBy::Interrupted,
+    })
 }
 
-fn calculate_fastest_request<E>(res: &[Result<RequestResult, E>]) -> f64 {
-    res.iter()
-        .filter_map(|r| r.as_ref().ok())
-        .map(|r| r.duration().as_secs_f64())
-        .collect::<average::Min>()
-        .min()
+fn calculate_success_rate(aggregate: &ResultAggregate) -> f64 {
+    aggregate.succeeded as f64 / aggregate.counted as f64
 }
 
-fn calculate_average_request<E>(res: &[Result<RequestResult, E>]) -> f64 {
+fn calculate_average_request<E>(
+    res: &[Result<RequestResult, E>],
+    includes: LatencyIncludes,
+) -> f64 {
     let mean = res
         .iter()
         .filter_map(|r| r.as_ref().ok())
-        .map(|r| r.duration().as_secs_f64())
+        .map(|r| r.duration(includes).as_secs_f64())
         .collect::<average::Mean>();
     if mean.is_empty() {
         f64::NAN
@@ -658,144 +1867,755 @@ fn calculate_average_request<E>(res: &[Result<RequestResult, E>]) -> f64 {
     }
 }
 
-fn calculate_requests_per_sec<E>(
-    res: &[Result<RequestResult, E>],
+fn calculate_requests_per_sec(aggregate: &ResultAggregate, total_duration: Duration) -> f64 {
+    aggregate.count as f64 / total_duration.as_secs_f64()
+}
+
+/// The total, or u64::MAX when it doesn't fit
+fn calculate_total_data(aggregate: &ResultAggregate) -> u64 {
+    u64::try_from(aggregate.bytes).unwrap_or(u64::MAX)
+}
+
+fn calculate_size_per_request(aggregate: &ResultAggregate) -> Option<u64> {
+    // The average of u64 sizes fits in u64
+    aggregate
+        .bytes
+        .checked_div(aggregate.responses() as u128)
+        .map(|size| size as u64)
+}
+
+/// Requests whose decoded size is unknown are not included. u64::MAX when the total doesn't fit.
+fn calculate_total_decompressed_data(aggregate: &ResultAggregate) -> u64 {
+    u64::try_from(aggregate.decoded_bytes).unwrap_or(u64::MAX)
+}
+
+fn calculate_decompressed_size_per_request(aggregate: &ResultAggregate) -> Option<u64> {
+    aggregate
+        .decoded_bytes
+        .checked_div(aggregate.decoded as u128)
+        .map(|size| size as u64)
+}
+
+fn calculate_size_per_sec(aggregate: &ResultAggregate, total_duration: Duration) -> f64 {
+    aggregate.bytes as f64 / total_duration.as_secs_f64()
+}
+
+#[derive(Debug, PartialEq, serde::Serialize)]
+struct InformationalStats {
+    /// Requests which received at least one 1xx response
+    requests: usize,
+    #[serde(rename = "earlyHints")]
+    early_hints: usize,
+    /// Time until the first 1xx response
+    first: FirstInformational,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize)]
+struct FirstInformational {
+    average: f64,
+    fastest: f64,
+    slowest: f64,
+}
+
+/// None when `--informational-stats` is not used.
+fn calculate_informational(aggregate: &ResultAggregate) -> Option<InformationalStats> {
+    let first = &aggregate.first_informational;
+    Some(InformationalStats {
+        requests: first.len(),
+        early_hints: aggregate.early_hints?,
+        first: FirstInformational {
+            average: first.average(),
+            fastest: first.fastest(),
+            slowest: first.slowest(),
+        },
+    })
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Http2Stats {
+    connections: usize,
+    #[serde(rename = "streamsPerConnection")]
+    streams_per_connection: StreamsPerConnection,
+    goaways: usize,
+    #[serde(rename = "refusedStreamRetries")]
+    refused_stream_retries: usize,
+    #[serde(rename = "goawayResends")]
+    goaway_resends: usize,
+    /// Failed requests by the frame and the error code from the server
+    errors: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct StreamsPerConnection {
+    average: f64,
+    min: usize,
+    max: usize,
+}
+
+/// Requests sent on each HTTP/2 connection. None when HTTP/2 is not used.
+fn calculate_http2(aggregate: &ResultAggregate, config: &PrintConfig) -> Option<Http2Stats> {
+    let counters = config.http2_counters.as_ref()?;
+    let streams = &aggregate.http2_streams;
+
+    Some(Http2Stats {
+        connections: streams.len(),
+        streams_per_connection: StreamsPerConnection {
+            average: if streams.is_empty() {
+                0.0
+            } else {
+                streams.values().sum::<usize>() as f64 / streams.len() as f64
+            },
+            min: streams.values().copied().min().unwrap_or(0),
+            max: streams.values().copied().max().unwrap_or(0),
+        },
+        goaways: counters.goaways.load(Ordering::Relaxed),
+        refused_stream_retries: counters.refused_stream_retries.load(Ordering::Relaxed),
+        goaway_resends: counters.goaway_resends.load(Ordering::Relaxed),
+        errors: aggregate.http2_errors.clone(),
+    })
+}
+
+#[derive(Debug, serde::Serialize)]
+struct WebSocketStats {
+    handshakes: usize,
+    #[serde(rename = "handshakesPerSec")]
+    handshakes_per_sec: f64,
+    pings: usize,
+}
+
+/// WebSocket handshakes and pings. None when WebSocket is not used.
+fn calculate_websocket(config: &PrintConfig, total_duration: Duration) -> Option<WebSocketStats> {
+    let counters = config.websocket_counters.as_ref()?;
+    let handshakes = counters.handshakes.load(Ordering::Relaxed);
+    Some(WebSocketStats {
+        handshakes,
+        handshakes_per_sec: handshakes as f64 / total_duration.as_secs_f64(),
+        pings: counters.pings.load(Ordering::Relaxed),
+    })
+}
+
+#[derive(Debug, serde::Serialize)]
+struct IpFamilyStats {
+    ipv6: usize,
+    ipv4: usize,
+    /// The family tried first, `ipv6` or `ipv4`
+    preferred: &'static str,
+}
+
+/// Connections by the address family. None when nothing was connected over TCP.
+fn calculate_ip_families(config: &PrintConfig) -> Option<IpFamilyStats> {
+    let families = config.ip_families.as_ref()?;
+    let stats = IpFamilyStats {
+        ipv6: families.ipv6.load(Ordering::Relaxed),
+        ipv4: families.ipv4.load(Ordering::Relaxed),
+        preferred: if families.prefer_ipv4 { "ipv4" } else { "ipv6" },
+    };
+    (stats.ipv6 + stats.ipv4 > 0).then_some(stats)
+}
+
+#[derive(Debug, serde::Serialize)]
+struct KeepAliveStats {
+    #[serde(rename = "idleCloses")]
+    idle_closes: usize,
+    #[serde(rename = "staleRetries")]
+    stale_retries: usize,
+}
+
+/// Reconnections of kept-alive connections. None when there were none.
+fn calculate_keepalive(config: &PrintConfig) -> Option<KeepAliveStats> {
+    let counters = config.keepalive_counters.as_ref()?;
+    let stats = KeepAliveStats {
+        idle_closes: counters.idle_closes.load(Ordering::Relaxed),
+        stale_retries: counters.stale_retries.load(Ordering::Relaxed),
+    };
+    (stats.idle_closes + stats.stale_retries > 0).then_some(stats)
+}
+
+#[derive(Debug, serde::Serialize)]
+struct RetryAfterStats {
+    responses: usize,
+    /// Total seconds the workers paused
+    paused: f64,
+}
+
+/// Pauses by `--respect-retry-after`. None without the option.
+fn calculate_retry_after(config: &PrintConfig) -> Option<RetryAfterStats> {
+    let counters = config.retry_after_counters.as_ref()?;
+    Some(RetryAfterStats {
+        responses: counters.responses.load(Ordering::Relaxed),
+        paused: Duration::from_nanos(counters.paused_nanos.load(Ordering::Relaxed)).as_secs_f64(),
+    })
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ThinkTimeStats {
+    pauses: usize,
+    /// Total seconds the workers paused
+    paused: f64,
+}
+
+/// Pauses by `--think-time`. None without the option.
+fn calculate_think_time(config: &PrintConfig) -> Option<ThinkTimeStats> {
+    let think_time = config.think_time.as_ref()?;
+    Some(ThinkTimeStats {
+        pauses: think_time.pauses(),
+        paused: think_time.paused().as_secs_f64(),
+    })
+}
+
+#[derive(Debug, serde::Serialize)]
+struct QpsRampSecond {
+    second: usize,
+    /// The target rate in the middle of the second
+    target: f64,
+    /// Requests started in the second
+    achieved: usize,
+    /// NaN when no request started in the second
+    p99: f64,
+}
+
+/// A rate in the largest unit in which it's at least 1, e.g. 30.00/m for 0.5 QPS
+pub fn format_rate(qps: f64) -> String {
+    if qps >= 1.0 {
+        format!("{qps:.2} QPS")
+    } else if qps * 60.0 >= 1.0 {
+        format!("{:.2}/m", qps * 60.0)
+    } else {
+        format!("{:.2}/h", qps * 3600.0)
+    }
+}
+
+/// The ratio of late requests over which oha is behind schedule
+const BEHIND_RATIO: f64 = 0.01;
+/// The number of late requests that oha is behind schedule from, so that a hiccup of the
+/// scheduler in a short run isn't over the ratio
+const BEHIND_MIN_LATE: usize = 5;
+
+#[derive(Debug, serde::Serialize)]
+struct SendDelayStats {
+    /// Seconds from the scheduled time of a request until a worker picked it up
+    average: f64,
+    p99: f64,
+    max: f64,
+    late: usize,
+    /// Whether too many requests were late to sustain the rate
+    behind: bool,
+}
+
+/// How far behind the schedule of `-q` or `--qps-ramp` the workers picked the requests up.
+/// None without them. Failed requests have no timing and aren't counted.
+fn calculate_send_delay(aggregate: &ResultAggregate) -> Option<SendDelayStats> {
+    let delays = &aggregate.send_delay;
+    Some(SendDelayStats {
+        average: aggregate.send_delay_average()?,
+        p99: delays.percentile(99.0),
+        max: delays.max(),
+        late: aggregate.late_sends,
+        behind: aggregate.late_sends >= BEHIND_MIN_LATE
+            && aggregate.late_sends as f64 > delays.len() as f64 * BEHIND_RATIO,
+    })
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ArrivalStats {
+    distribution: ArrivalDistribution,
+    mean: f64,
+    stddev: f64,
+    min: usize,
+    max: usize,
+    /// The variance over the mean, near 0 for uniform arrivals and near 1 for Poisson ones
+    dispersion: f64,
+}
+
+/// The number of requests started in each full second of the run, to check the shape of
+/// `--arrival-distribution`. None without the option or when the run is shorter than a second.
+/// Failed requests have no timing and aren't counted.
+fn calculate_arrivals(
+    aggregate: &ResultAggregate,
+    config: &PrintConfig,
     total_duration: Duration,
-) -> f64 {
-    res.len() as f64 / total_duration.as_secs_f64()
+) -> Option<ArrivalStats> {
+    let distribution = config.arrival_distribution?;
+    let counts = aggregate.started_per_second(total_duration);
+    if counts.is_empty() {
+        return None;
+    }
+    let variance = counts.iter().map(|&c| c as f64).collect::<Variance>();
+    Some(ArrivalStats {
+        distribution,
+        mean: variance.mean(),
+        stddev: variance.sample_variance().sqrt(),
+        min: counts.iter().copied().min().unwrap_or_default(),
+        max: counts.iter().copied().max().unwrap_or_default(),
+        dispersion: variance.sample_variance() / variance.mean(),
+    })
 }
 
-fn calculate_total_data<E>(res: &[Result<RequestResult, E>]) -> u64 {
-    res.iter()
-        .filter_map(|r| r.as_ref().ok())
-        .map(|r| r.len_bytes as u64)
-        .sum::<u64>()
+/// The target and achieved rates and the p99 latency in each second of `--qps-ramp`.
+/// A request belongs to the second it started in. Failed requests have no timing and aren't counted.
+fn calculate_qps_ramp<E>(
+    res: &[Result<RequestResult, E>],
+    config: &PrintConfig,
+    start: Instant,
+) -> Option<Vec<QpsRampSecond>> {
+    let ramp = config.qps_ramp?;
+    let mut durations = vec![
+        LatencyHistogram::new(config.histogram_precision);
+        ramp.duration.as_secs_f64().ceil() as usize
+    ];
+    for r in res.iter().filter_map(|r| r.as_ref().ok()) {
+        let started = r.start_latency_correction.unwrap_or(r.start);
+        let second = started.saturating_duration_since(start).as_secs() as usize;
+        if let Some(durations) = durations.get_mut(second) {
+            durations.push(r.duration(config.latency_includes).as_secs_f64());
+        }
+    }
+    Some(
+        durations
+            .into_iter()
+            .enumerate()
+            .map(|(second, durations)| QpsRampSecond {
+                second,
+                target: ramp.rate(Duration::from_secs_f64(second as f64 + 0.5)),
+                achieved: durations.len() as usize,
+                p99: durations.percentile(99.0),
+            })
+            .collect(),
+    )
 }
 
-fn calculate_size_per_request<E>(res: &[Result<RequestResult, E>]) -> Option<u64> {
-    res.iter()
-        .filter_map(|r| r.as_ref().ok())
-        .map(|r| r.len_bytes as u64)
-        .sum::<u64>()
-        .checked_div(res.iter().filter(|r| r.is_ok()).count() as u64)
+#[derive(Debug, serde::Serialize)]
+struct TargetP99Stats {
+    /// Seconds
+    target: f64,
+    #[serde(rename = "stableRate")]
+    stable_rate: Option<f64>,
+    /// The latency of the responses received in the windows of the stable rate
+    #[serde(rename = "stableLatencyPercentiles")]
+    stable_latency_percentiles: BTreeMap<String, f64>,
+    #[serde(skip)]
+    stable_latencies: Vec<f64>,
+    windows: Vec<Adjustment>,
 }
 
-fn calculate_size_per_sec<E>(res: &[Result<RequestResult, E>], total_duration: Duration) -> f64 {
-    res.iter()
+/// The windows of `--target-p99` and the rate it converged on. None without it.
+fn calculate_target_p99<E>(
+    res: &[Result<RequestResult, E>],
+    config: &PrintConfig,
+    start: Instant,
+) -> Option<TargetP99Stats> {
+    let target = config.target_p99.as_ref()?;
+    let windows = target.adjustments();
+    let stable = target_p99::stable_windows(&windows);
+    let mut stable_latencies: Vec<f64> = res
+        .iter()
         .filter_map(|r| r.as_ref().ok())
-        .map(|r| r.len_bytes as u128)
-        .sum::<u128>() as f64
-        / total_duration.as_secs_f64()
+        .filter(|r| {
+            let end = r.end.saturating_duration_since(start).as_secs_f64();
+            stable.iter().any(|w| (w.start..w.end).contains(&end))
+        })
+        .map(|r| r.duration(config.latency_includes).as_secs_f64())
+        .collect();
+    Some(TargetP99Stats {
+        target: target.target().as_secs_f64(),
+        stable_rate: target_p99::stable_rate(&windows),
+        stable_latency_percentiles: percentiles(&mut stable_latencies),
+        stable_latencies,
+        windows,
+    })
 }
 
-fn calculate_connection_times_base<E>(
+#[derive(Debug, serde::Serialize)]
+struct BurstStats {
+    count: usize,
+    /// Seconds from the release of a burst until its last response arrived
+    average: f64,
+    fastest: f64,
+    slowest: f64,
+}
+
+/// Completion times of the bursts which got any response. None without `--burst-delay` or
+/// when no burst got a response.
+fn calculate_bursts<E>(
     res: &[Result<RequestResult, E>],
-) -> Vec<(Instant, ConnectionTime)> {
-    res.iter()
-        .filter_map(|r| r.as_ref().ok())
-        .filter_map(|r| r.connection_time.map(|c| (r.start, c)))
-        .collect()
+    config: &PrintConfig,
+) -> Option<BurstStats> {
+    let releases = config.burst_releases.as_ref()?.get();
+    let times: Vec<f64> = burst::completion_times(
+        &releases,
+        res.iter()
+            .filter_map(|r| r.as_ref().ok())
+            .map(|r| (r.start_latency_correction.unwrap_or(r.start), r.end)),
+    )
+    .iter()
+    .map(Duration::as_secs_f64)
+    .collect();
+    (!times.is_empty()).then(|| BurstStats {
+        count: times.len(),
+        average: times.iter().collect::<average::Mean>().mean(),
+        fastest: times.iter().collect::<average::Min>().min(),
+        slowest: times.iter().collect::<average::Max>().max(),
+    })
 }
 
-fn calculate_connection_times_dns_dialup_average(
-    connection_times: &[(Instant, ConnectionTime)],
-) -> f64 {
-    connection_times
-        .iter()
-        .map(|(s, c)| (c.dialup - *s).as_secs_f64())
-        .collect::<average::Mean>()
-        .mean()
+#[derive(Debug, serde::Serialize)]
+struct ConnectionReuse {
+    new: usize,
+    reused: usize,
+    /// The fraction of the requests sent on a kept-alive connection. 0 when there's none.
+    ratio: f64,
 }
 
-fn calculate_connection_times_dns_dialup_fastest(
-    connection_times: &[(Instant, ConnectionTime)],
-) -> f64 {
-    connection_times
-        .iter()
-        .map(|(s, c)| (c.dialup - *s).as_secs_f64())
-        .collect::<average::Min>()
-        .min()
+/// Successful requests on a new connection and on a kept-alive one.
+/// None on HTTP/2, whose requests share the connections of the workers.
+fn calculate_connection_reuse(
+    aggregate: &ResultAggregate,
+    config: &PrintConfig,
+) -> Option<ConnectionReuse> {
+    if config.http2_counters.is_some() {
+        return None;
+    }
+    let (new, reused) = (aggregate.new_connections, aggregate.reused_connections);
+    let total = new + reused;
+    Some(ConnectionReuse {
+        new,
+        reused,
+        ratio: if total == 0 {
+            0.0
+        } else {
+            reused as f64 / total as f64
+        },
+    })
 }
 
-fn calculate_connection_times_dns_dialup_slowest(
-    connection_times: &[(Instant, ConnectionTime)],
-) -> f64 {
-    connection_times
-        .iter()
-        .map(|(s, c)| (c.dialup - *s).as_secs_f64())
-        .collect::<average::Max>()
-        .max()
+#[derive(Debug, serde::Serialize)]
+struct TlsHandshakeStats {
+    /// The TLS backend oha was built with
+    backend: &'static str,
+    average: f64,
+    fastest: f64,
+    slowest: f64,
+    /// Handshakes which verified the server certificate. None when the TLS backend can't tell.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    full: Option<HandshakeKindStats>,
+    /// Handshakes which resumed the session of an earlier connection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resumed: Option<HandshakeKindStats>,
 }
 
-fn calculate_connection_times_dns_lookup_average(
-    connection_times: &[(Instant, ConnectionTime)],
-) -> f64 {
-    connection_times
-        .iter()
-        .map(|(s, c)| (c.dns_lookup - *s).as_secs_f64())
-        .collect::<average::Mean>()
-        .mean()
+#[derive(Debug, serde::Serialize)]
+struct HandshakeKindStats {
+    count: usize,
+    /// 0 when there's none
+    average: f64,
 }
 
-fn calculate_connection_times_dns_lookup_fastest(
-    connection_times: &[(Instant, ConnectionTime)],
-) -> f64 {
-    connection_times
-        .iter()
-        .map(|(s, c)| (c.dns_lookup - *s).as_secs_f64())
-        .collect::<average::Min>()
-        .min()
+/// The TLS handshake part of the dialup. None when no connection used TLS.
+fn calculate_tls_handshake(aggregate: &ResultAggregate) -> Option<TlsHandshakeStats> {
+    let handshakes = &aggregate.tls_handshake;
+    let kind = |extent: &Extent| HandshakeKindStats {
+        count: extent.len(),
+        average: extent.average(),
+    };
+    let known = aggregate.tls_known;
+    (!handshakes.is_empty()).then(|| TlsHandshakeStats {
+        backend: crate::tls::BACKEND,
+        average: handshakes.average(),
+        fastest: handshakes.fastest(),
+        slowest: handshakes.slowest(),
+        full: known.then(|| kind(&aggregate.tls_full)),
+        resumed: known.then(|| kind(&aggregate.tls_resumed)),
+    })
 }
 
-fn calculate_connection_times_dns_lookup_slowest(
-    connection_times: &[(Instant, ConnectionTime)],
-) -> f64 {
-    connection_times
-        .iter()
-        .map(|(s, c)| (c.dns_lookup - *s).as_secs_f64())
-        .collect::<average::Max>()
-        .max()
+#[derive(Debug, serde::Serialize)]
+struct GroupStats {
+    count: usize,
+    average: f64,
+    fastest: f64,
+    slowest: f64,
+    #[serde(rename = "latencyPercentiles")]
+    latency_percentiles: BTreeMap<String, f64>,
+    #[serde(rename = "statusCodeDistribution")]
+    status_code_distribution: BTreeMap<String, usize>,
+}
+
+/// Statistics of responses grouped by method. Errors are not included since they have no method.
+fn calculate_per_method<E>(
+    res: &[Result<RequestResult, E>],
+    includes: LatencyIncludes,
+    labels: &Labels,
+) -> BTreeMap<String, GroupStats> {
+    calculate_labeled(res, includes, labels, |r| Some(r.method))
+}
+
+/// Statistics of responses grouped by scenario step. Empty when `--scenario` is not used.
+fn calculate_per_step<E>(
+    res: &[Result<RequestResult, E>],
+    includes: LatencyIncludes,
+    labels: &Labels,
+) -> BTreeMap<String, GroupStats> {
+    calculate_labeled(res, includes, labels, |r| r.step)
+}
+
+/// Statistics of responses grouped by the `--load-profile` step their requests started in.
+/// Steps without any response are skipped, and None when no step got any.
+fn calculate_per_load_step<E>(
+    res: &[Result<RequestResult, E>],
+    config: &PrintConfig,
+    start: Instant,
+) -> Option<BTreeMap<String, GroupStats>> {
+    let load_profile = config.load_profile.as_ref()?;
+    let groups = calculate_grouped(res, config.latency_includes, |r| {
+        let started = r.start_latency_correction.unwrap_or(r.start);
+        load_profile
+            .step_at(started.saturating_duration_since(start))
+            .map(|(i, _)| load_profile.step_name(i))
+    });
+    (!groups.is_empty()).then_some(groups)
+}
+
+/// Statistics of responses grouped by the phase of `--on` and `--off`. Requests which finish
+/// during an off period are counted as `off`, and the ones which start in the first second after
+/// it as `on, first second after resume`. None without `--on`.
+fn calculate_per_duty_cycle_phase<E>(
+    res: &[Result<RequestResult, E>],
+    config: &PrintConfig,
+) -> Option<BTreeMap<String, GroupStats>> {
+    let duty_cycle = config.duty_cycle.as_ref()?;
+    let start = duty_cycle.start();
+    Some(calculate_grouped(res, config.latency_includes, |r| {
+        let key =
+            if let (Phase::Off, ..) = duty_cycle.phase_at(r.end.saturating_duration_since(start)) {
+                "off"
+            } else if duty_cycle.is_resume(r.start.saturating_duration_since(start)) {
+                "on, first second after resume"
+            } else {
+                "on"
+            };
+        Some(key.to_string())
+    }))
+}
+
+/// The denominator of the throughput, which excludes the `--off` periods and the pauses
+fn calculate_load_duration(
+    config: &PrintConfig,
+    start: Instant,
+    total_duration: Duration,
+) -> Duration {
+    let load_duration = match &config.duty_cycle {
+        Some(duty_cycle) => duty_cycle.on_time(total_duration),
+        None => total_duration,
+    };
+    match &config.pause {
+        Some(pause) => load_duration.saturating_sub(pause.paused_time(start + total_duration)),
+        None => load_duration,
+    }
+}
+
+/// The test duration and the time the requests in flight at its end took to finish after it, by
+/// `--wait-ongoing-requests-after-deadline`
+fn calculate_drain(config: &PrintConfig, total_duration: Duration) -> Option<(Duration, Duration)> {
+    let test_duration = config.test_duration?.min(total_duration);
+    Some((test_duration, total_duration - test_duration))
+}
+
+#[derive(Debug, serde::Serialize)]
+struct PauseInterval {
+    /// Seconds from the start of the run
+    start: f64,
+    end: f64,
+}
+
+/// The pauses by SIGUSR1. None when the traffic wasn't paused.
+fn calculate_pauses(
+    config: &PrintConfig,
+    start: Instant,
+    total_duration: Duration,
+) -> Option<Vec<PauseInterval>> {
+    let intervals = config.pause.as_ref()?.intervals(start + total_duration);
+    (!intervals.is_empty()).then(|| {
+        intervals
+            .into_iter()
+            .map(|(from, to)| PauseInterval {
+                start: from.saturating_duration_since(start).as_secs_f64(),
+                end: to.saturating_duration_since(start).as_secs_f64(),
+            })
+            .collect()
+    })
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UrlListStats {
+    /// Path and query, since the origin is the one of the URL
+    url: String,
+    weight: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_concurrent: Option<usize>,
+    requests: u64,
+    average_concurrency: f64,
+    peak_concurrency: usize,
+    /// Times the URL was picked while it was full, and another URL was requested instead
+    blocked: u64,
+}
+
+/// How each URL of `--urls-from-file` was requested. None without it.
+fn calculate_url_list(config: &PrintConfig, load_duration: Duration) -> Option<Vec<UrlListStats>> {
+    let url_list = config.url_list.as_ref()?;
+    Some(
+        url_list
+            .entries()
+            .iter()
+            .map(|entry| UrlListStats {
+                url: entry.url[url::Position::BeforePath..].to_string(),
+                weight: entry.weight,
+                max_concurrent: entry.max_concurrent,
+                requests: entry.requests(),
+                average_concurrency: entry.average_concurrency(load_duration),
+                peak_concurrency: entry.peak_concurrency(),
+                blocked: entry.blocked(),
+            })
+            .collect(),
+    )
 }
 
-fn get_durations_all<E>(res: &[Result<RequestResult, E>]) -> Vec<f64> {
-    res.iter()
-        .filter_map(|r: &Result<RequestResult, E>| r.as_ref().ok())
-        .map(|r| r.duration().as_secs_f64())
-        .collect::<Vec<_>>()
+#[derive(Debug, serde::Serialize)]
+struct AgentStats {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// Failed requests, which aren't in the statistics
+    errors: usize,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    stats: Option<GroupStats>,
 }
 
-fn get_durations_successful<E>(res: &[Result<RequestResult, E>]) -> Vec<f64> {
-    res.iter()
-        .filter_map(|r: &Result<RequestResult, E>| r.as_ref().ok())
-        .filter(|r| r.status.is_success())
-        .map(|r| r.duration().as_secs_f64())
-        .collect::<Vec<_>>()
+/// Statistics of responses grouped by the agent of `--workers`, with how each agent's run ended.
+/// None without `--workers`.
+fn calculate_per_agent(config: &PrintConfig) -> Option<BTreeMap<String, AgentStats>> {
+    let agents = config.agents.as_ref()?;
+    Some(
+        agents
+            .iter()
+            .map(|agent| {
+                let (status, error) = match agent.status() {
+                    AgentStatus::Connecting => ("connecting", None),
+                    AgentStatus::Running => ("running", None),
+                    AgentStatus::Done => ("done", None),
+                    AgentStatus::Failed(error) => ("failed", Some(error)),
+                };
+                let folded = agent.folded();
+                let stats = AgentStats {
+                    status,
+                    error,
+                    errors: folded
+                        .as_ref()
+                        .map_or(0, |folded| folded.count - folded.responses()),
+                    stats: folded
+                        .filter(|folded| folded.responses() > 0)
+                        .map(|folded| GroupStats {
+                            count: folded.responses(),
+                            average: folded.latency_average(),
+                            fastest: folded.latency.min(),
+                            slowest: folded.latency.max(),
+                            latency_percentiles: histogram_percentiles(&folded.latency),
+                            status_code_distribution: folded
+                                .status
+                                .iter()
+                                .map(|(status, &(count, _))| (status.as_str().to_string(), count))
+                                .collect(),
+                        }),
+                };
+                (agent.address.to_string(), stats)
+            })
+            .collect(),
+    )
+}
+
+/// Statistics of responses grouped by path and query.
+fn calculate_per_url<E>(
+    res: &[Result<RequestResult, E>],
+    includes: LatencyIncludes,
+    labels: &Labels,
+) -> BTreeMap<String, GroupStats> {
+    calculate_labeled(res, includes, labels, |r| r.url)
 }
 
-fn get_durations_not_successful<E>(res: &[Result<RequestResult, E>]) -> Vec<f64> {
-    res.iter()
-        .filter_map(|r: &Result<RequestResult, E>| r.as_ref().ok())
-        .filter(|r| r.status.is_client_error() || r.status.is_server_error())
-        .map(|r| r.duration().as_secs_f64())
-        .collect::<Vec<_>>()
+/// `calculate_grouped` by a label, with the names of the labels as the keys
+fn calculate_labeled<E>(
+    res: &[Result<RequestResult, E>],
+    includes: LatencyIncludes,
+    labels: &Labels,
+    key: impl Fn(&RequestResult) -> Option<Label>,
+) -> BTreeMap<String, GroupStats> {
+    calculate_grouped(res, includes, key)
+        .into_iter()
+        .map(|(label, stats)| (labels.name(label).to_string(), stats))
+        .collect()
+}
+
+fn calculate_grouped<E, K: Ord>(
+    res: &[Result<RequestResult, E>],
+    includes: LatencyIncludes,
+    key: impl Fn(&RequestResult) -> Option<K>,
+) -> BTreeMap<K, GroupStats> {
+    let mut groups: BTreeMap<K, Vec<&RequestResult>> = Default::default();
+    for r in res.iter().filter_map(|r| r.as_ref().ok()) {
+        if let Some(key) = key(r) {
+            groups.entry(key).or_default().push(r);
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(key, results)| {
+            let mut durations: Vec<f64> = results
+                .iter()
+                .map(|r| r.duration(includes).as_secs_f64())
+                .collect();
+            let mut status_code_distribution: BTreeMap<String, usize> = Default::default();
+            for r in &results {
+                *status_code_distribution
+                    .entry(r.status.as_str().to_string())
+                    .or_default() += 1;
+            }
+            let stats = GroupStats {
+                count: results.len(),
+                average: durations.iter().collect::<average::Mean>().mean(),
+                fastest: durations.iter().collect::<average::Min>().min(),
+                slowest: durations.iter().collect::<Max>().max(),
+                latency_percentiles: percentiles(&mut durations),
+                status_code_distribution,
+            };
+            (key, stats)
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::client::{ClientError, RequestResult};
+    use crate::{
+        client::{
+            ClientError, ConnectionTime, ExpectContinue, Informational, RequestResult, TlsHandshake,
+        },
+        histogram::{DEFAULT_PRECISION, RESOLUTION},
+    };
     use std::time::Duration;
 
     fn build_mock_request_result(
         status: StatusCode,
         request_time: u64,
+        first_byte_time: u64,
         connection_time_dns_lookup: u64,
         connection_time_dialup: u64,
-        size: usize,
+        size: u64,
     ) -> Result<RequestResult, ClientError> {
         let now = Instant::now();
         Ok(RequestResult {
             start_latency_correction: None,
+            send_delay: None,
             start: now,
             connection_time: Some(ConnectionTime {
                 dns_lookup: Instant::now()
@@ -804,20 +2624,40 @@ mod tests {
                 dialup: Instant::now()
                     .checked_add(Duration::from_millis(connection_time_dialup))
                     .unwrap(),
+                tls_handshake: None,
             }),
+            first_byte: now
+                .checked_add(Duration::from_millis(first_byte_time))
+                .unwrap(),
             end: Instant::now()
                 .checked_add(Duration::from_millis(request_time))
                 .unwrap(),
             status,
+            method: Labels::default().method(&http::Method::GET),
+            step: None,
+            url: None,
             len_bytes: size,
+            decoded_len_bytes: None,
+            expect_continue: None,
+            unexpected_status: false,
+            truncated: false,
+            http2_connection: None,
+            informational: None,
         })
     }
 
     fn build_mock_request_result_vec() -> Vec<Result<RequestResult, ClientError>> {
         vec![
-            build_mock_request_result(StatusCode::OK, 1000, 200, 50, 100),
-            build_mock_request_result(StatusCode::BAD_REQUEST, 100000, 250, 100, 200),
-            build_mock_request_result(StatusCode::INTERNAL_SERVER_ERROR, 1000000, 300, 150, 300),
+            build_mock_request_result(StatusCode::OK, 1000, 500, 200, 50, 100),
+            build_mock_request_result(StatusCode::BAD_REQUEST, 100000, 1000, 250, 100, 200),
+            build_mock_request_result(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                1000000,
+                1500,
+                300,
+                150,
+                300,
+            ),
         ]
     }
 
@@ -827,6 +2667,495 @@ mod tests {
         (value * multiplier).round() / multiplier
     }
 
+    // The statistics computed from every result, as they were before `ResultAggregate`. They
+    // are the reference the aggregate is checked against.
+
+    fn calculate_success_rate(res: &[Result<RequestResult, ClientError>]) -> f64 {
+        // We ignore deadline errors which are because of `-z` option, not because of the server,
+        // and the requests cut by `--abort-on-error-rate`
+        let iter = res
+            .iter()
+            .filter(|r| !matches!(r, Err(ClientError::Deadline | ClientError::Aborted)));
+
+        let denominator = iter.clone().count();
+        let numerator = iter
+            .filter(|r| matches!(r, Ok(r) if !r.unexpected_status))
+            .count();
+
+        numerator as f64 / denominator as f64
+    }
+
+    /// Errors and unexpected status codes by their messages
+    fn calculate_error_distribution(
+        res: &[Result<RequestResult, ClientError>],
+    ) -> BTreeMap<String, usize> {
+        let mut error_dist: BTreeMap<String, usize> = Default::default();
+        for r in res {
+            let error = match r {
+                Ok(r) => r.status_error(),
+                Err(e) => Some(e.to_string()),
+            };
+            if let Some(error) = error {
+                *error_dist.entry(error).or_default() += 1;
+            }
+        }
+        error_dist
+    }
+
+    fn calculate_assertion_failures(res: &[Result<RequestResult, ClientError>]) -> usize {
+        res.iter()
+            .filter(|r| matches!(r, Err(ClientError::AssertionFailed(_))))
+            .count()
+    }
+
+    fn calculate_truncated_bodies<E>(res: &[Result<RequestResult, E>]) -> usize {
+        res.iter()
+            .filter_map(|r| r.as_ref().ok())
+            .filter(|r| r.truncated)
+            .count()
+    }
+
+    fn calculate_slowest_request<E>(
+        res: &[Result<RequestResult, E>],
+        includes: LatencyIncludes,
+    ) -> f64 {
+        res.iter()
+            .filter_map(|r| r.as_ref().ok())
+            .map(|r| r.duration(includes).as_secs_f64())
+            .collect::<average::Max>()
+            .max()
+    }
+
+    fn calculate_fastest_request<E>(
+        res: &[Result<RequestResult, E>],
+        includes: LatencyIncludes,
+    ) -> f64 {
+        res.iter()
+            .filter_map(|r| r.as_ref().ok())
+            .map(|r| r.duration(includes).as_secs_f64())
+            .collect::<average::Min>()
+            .min()
+    }
+
+    fn calculate_requests_per_sec<E>(
+        res: &[Result<RequestResult, E>],
+        total_duration: Duration,
+    ) -> f64 {
+        res.len() as f64 / total_duration.as_secs_f64()
+    }
+
+    /// Sum the sizes in 128 bits, so that the total of any number of responses fits
+    fn sum_bytes(sizes: impl Iterator<Item = u64>) -> u128 {
+        sizes.map(u128::from).sum()
+    }
+
+    /// The total, or u64::MAX when it doesn't fit
+    fn calculate_total_data<E>(res: &[Result<RequestResult, E>]) -> u64 {
+        let total = sum_bytes(
+            res.iter()
+                .filter_map(|r| r.as_ref().ok())
+                .map(|r| r.len_bytes),
+        );
+        u64::try_from(total).unwrap_or(u64::MAX)
+    }
+
+    fn calculate_size_per_request<E>(res: &[Result<RequestResult, E>]) -> Option<u64> {
+        let total = sum_bytes(
+            res.iter()
+                .filter_map(|r| r.as_ref().ok())
+                .map(|r| r.len_bytes),
+        );
+        let count = res.iter().filter(|r| r.is_ok()).count() as u128;
+        // The average of u64 sizes fits in u64
+        total.checked_div(count).map(|size| size as u64)
+    }
+
+    /// Requests whose decoded size is unknown are not included. u64::MAX when the total doesn't fit.
+    fn calculate_total_decompressed_data<E>(res: &[Result<RequestResult, E>]) -> u64 {
+        let total = sum_bytes(
+            res.iter()
+                .filter_map(|r| r.as_ref().ok())
+                .filter_map(|r| r.decoded_len_bytes),
+        );
+        u64::try_from(total).unwrap_or(u64::MAX)
+    }
+
+    fn calculate_decompressed_size_per_request<E>(res: &[Result<RequestResult, E>]) -> Option<u64> {
+        let sizes: Vec<u64> = res
+            .iter()
+            .filter_map(|r| r.as_ref().ok())
+            .filter_map(|r| r.decoded_len_bytes)
+            .collect();
+        sum_bytes(sizes.iter().copied())
+            .checked_div(sizes.len() as u128)
+            .map(|size| size as u64)
+    }
+
+    fn calculate_size_per_sec<E>(
+        res: &[Result<RequestResult, E>],
+        total_duration: Duration,
+    ) -> f64 {
+        sum_bytes(
+            res.iter()
+                .filter_map(|r| r.as_ref().ok())
+                .map(|r| r.len_bytes),
+        ) as f64
+            / total_duration.as_secs_f64()
+    }
+
+    fn calculate_connection_times_base<E>(
+        res: &[Result<RequestResult, E>],
+    ) -> Vec<(Instant, ConnectionTime)> {
+        res.iter()
+            .filter_map(|r| r.as_ref().ok())
+            .filter_map(|r| r.connection_time.map(|c| (r.start, c)))
+            .collect()
+    }
+
+    fn calculate_connection_times_dns_dialup_average(
+        connection_times: &[(Instant, ConnectionTime)],
+    ) -> f64 {
+        connection_times
+            .iter()
+            .map(|(s, c)| (c.dialup - *s).as_secs_f64())
+            .collect::<average::Mean>()
+            .mean()
+    }
+
+    fn calculate_connection_times_dns_dialup_fastest(
+        connection_times: &[(Instant, ConnectionTime)],
+    ) -> f64 {
+        connection_times
+            .iter()
+            .map(|(s, c)| (c.dialup - *s).as_secs_f64())
+            .collect::<average::Min>()
+            .min()
+    }
+
+    fn calculate_connection_times_dns_dialup_slowest(
+        connection_times: &[(Instant, ConnectionTime)],
+    ) -> f64 {
+        connection_times
+            .iter()
+            .map(|(s, c)| (c.dialup - *s).as_secs_f64())
+            .collect::<average::Max>()
+            .max()
+    }
+
+    fn calculate_connection_times_dns_lookup_average(
+        connection_times: &[(Instant, ConnectionTime)],
+    ) -> f64 {
+        connection_times
+            .iter()
+            .map(|(s, c)| (c.dns_lookup - *s).as_secs_f64())
+            .collect::<average::Mean>()
+            .mean()
+    }
+
+    fn calculate_connection_times_dns_lookup_fastest(
+        connection_times: &[(Instant, ConnectionTime)],
+    ) -> f64 {
+        connection_times
+            .iter()
+            .map(|(s, c)| (c.dns_lookup - *s).as_secs_f64())
+            .collect::<average::Min>()
+            .min()
+    }
+
+    fn calculate_connection_times_dns_lookup_slowest(
+        connection_times: &[(Instant, ConnectionTime)],
+    ) -> f64 {
+        connection_times
+            .iter()
+            .map(|(s, c)| (c.dns_lookup - *s).as_secs_f64())
+            .collect::<average::Max>()
+            .max()
+    }
+
+    fn first_byte_durations<E>(res: &[Result<RequestResult, E>]) -> impl Iterator<Item = f64> + '_ {
+        res.iter()
+            .filter_map(|r| r.as_ref().ok())
+            .map(|r| (r.first_byte - r.start_latency_correction.unwrap_or(r.start)).as_secs_f64())
+    }
+
+    fn calculate_first_byte_average<E>(res: &[Result<RequestResult, E>]) -> f64 {
+        first_byte_durations(res).collect::<average::Mean>().mean()
+    }
+
+    fn calculate_first_byte_fastest<E>(res: &[Result<RequestResult, E>]) -> f64 {
+        first_byte_durations(res).collect::<average::Min>().min()
+    }
+
+    fn calculate_first_byte_slowest<E>(res: &[Result<RequestResult, E>]) -> f64 {
+        first_byte_durations(res).collect::<average::Max>().max()
+    }
+
+    /// None when `--expect-continue` is not used.
+    fn calculate_expect_continue<E>(
+        res: &[Result<RequestResult, E>],
+    ) -> Option<ExpectContinueCounts> {
+        let mut counts: Option<ExpectContinueCounts> = None;
+        for e in res
+            .iter()
+            .filter_map(|r| r.as_ref().ok())
+            .filter_map(|r| r.expect_continue)
+        {
+            let counts = counts.get_or_insert_with(Default::default);
+            if e.received {
+                counts.received += 1;
+            } else if e.body_sent {
+                counts.timed_out += 1;
+            } else {
+                counts.body_not_sent += 1;
+            }
+        }
+        counts
+    }
+
+    /// None when `--informational-stats` is not used.
+    fn calculate_informational<E>(res: &[Result<RequestResult, E>]) -> Option<InformationalStats> {
+        let mut early_hints = None;
+        let mut firsts = Vec::new();
+        for r in res.iter().filter_map(|r| r.as_ref().ok()) {
+            if let Some(informational) = r.informational {
+                *early_hints.get_or_insert(0) += informational.early_hints;
+                if let Some(first) = informational.first {
+                    firsts.push(
+                        (first - r.start_latency_correction.unwrap_or(r.start)).as_secs_f64(),
+                    );
+                }
+            }
+        }
+
+        Some(InformationalStats {
+            requests: firsts.len(),
+            early_hints: early_hints?,
+            first: FirstInformational {
+                average: firsts.iter().collect::<average::Mean>().mean(),
+                fastest: firsts.iter().collect::<average::Min>().min(),
+                slowest: firsts.iter().collect::<average::Max>().max(),
+            },
+        })
+    }
+
+    fn get_durations_all<E>(
+        res: &[Result<RequestResult, E>],
+        includes: LatencyIncludes,
+    ) -> Vec<f64> {
+        res.iter()
+            .filter_map(|r: &Result<RequestResult, E>| r.as_ref().ok())
+            .map(|r| r.duration(includes).as_secs_f64())
+            .collect::<Vec<_>>()
+    }
+
+    fn get_durations_successful<E>(
+        res: &[Result<RequestResult, E>],
+        includes: LatencyIncludes,
+    ) -> Vec<f64> {
+        res.iter()
+            .filter_map(|r: &Result<RequestResult, E>| r.as_ref().ok())
+            .filter(|r| r.status.is_success())
+            .map(|r| r.duration(includes).as_secs_f64())
+            .collect::<Vec<_>>()
+    }
+
+    fn get_durations_not_successful<E>(
+        res: &[Result<RequestResult, E>],
+        includes: LatencyIncludes,
+    ) -> Vec<f64> {
+        res.iter()
+            .filter_map(|r: &Result<RequestResult, E>| r.as_ref().ok())
+            .filter(|r| r.status.is_client_error() || r.status.is_server_error())
+            .map(|r| r.duration(includes).as_secs_f64())
+            .collect::<Vec<_>>()
+    }
+
+    fn get_durations_unexpected_status<E>(
+        res: &[Result<RequestResult, E>],
+        includes: LatencyIncludes,
+    ) -> Vec<f64> {
+        res.iter()
+            .filter_map(|r: &Result<RequestResult, E>| r.as_ref().ok())
+            .filter(|r| r.unexpected_status)
+            .map(|r| r.duration(includes).as_secs_f64())
+            .collect::<Vec<_>>()
+    }
+
+    /// Results of every kind, with latencies spread over several orders of magnitude
+    fn build_random_results(n: usize) -> Vec<Result<RequestResult, ClientError>> {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(0);
+        let start = Instant::now();
+        let ms = |ms: f64| Duration::from_secs_f64(ms / 1000.0);
+        (0..n)
+            .map(|i| {
+                if i % 50 == 0 {
+                    return Err(ClientError::Timeout);
+                }
+                if i % 77 == 0 {
+                    return Err(ClientError::Deadline);
+                }
+                let start = start + ms(rng.gen_range(0.0..3000.0));
+                let latency = ms(10f64.powf(rng.gen_range(-1.0..3.0)));
+                let connection_time = (i % 3 == 0).then(|| ConnectionTime {
+                    dns_lookup: start + ms(rng.gen_range(0.0..1.0)),
+                    dialup: start + ms(rng.gen_range(1.0..5.0)),
+                    tls_handshake: Some(TlsHandshake {
+                        duration: ms(rng.gen_range(0.5..1.0)),
+                        resumed: Some(i % 2 == 0),
+                    }),
+                });
+                Ok(RequestResult {
+                    start_latency_correction: None,
+                    send_delay: None,
+                    start,
+                    connection_time,
+                    first_byte: start + latency / 2,
+                    end: start + latency,
+                    status: [
+                        StatusCode::OK,
+                        StatusCode::NOT_FOUND,
+                        StatusCode::BAD_GATEWAY,
+                    ][rng.gen_range(0..3)],
+                    method: Labels::default().method(&http::Method::GET),
+                    step: None,
+                    url: None,
+                    len_bytes: rng.gen_range(0..100_000),
+                    decoded_len_bytes: (i % 4 == 0).then(|| rng.gen_range(0..400_000)),
+                    expect_continue: Some(ExpectContinue {
+                        received: i % 5 != 0,
+                        body_sent: i % 2 == 0,
+                    }),
+                    unexpected_status: i % 7 == 0,
+                    truncated: i % 11 == 0,
+                    http2_connection: None,
+                    informational: Some(Informational {
+                        early_hints: i % 2,
+                        first: (i % 2 == 0).then(|| start + latency / 4),
+                    }),
+                })
+            })
+            .collect()
+    }
+
+    fn assert_close(aggregate: f64, reference: f64) {
+        assert!(
+            (aggregate - reference).abs() <= reference.abs() * 1e-9,
+            "{aggregate} != {reference}"
+        );
+    }
+
+    #[test]
+    fn test_aggregate_matches_results() {
+        let mut aggregate = ResultAggregate::with_options(
+            Instant::now(),
+            LatencyIncludes::All,
+            None,
+            true,
+            DEFAULT_PRECISION,
+        );
+        for r in build_random_results(10_000) {
+            aggregate.push(r);
+        }
+        let res = aggregate.results();
+
+        assert_eq!(
+            super::calculate_success_rate(&aggregate),
+            calculate_success_rate(res)
+        );
+        assert_eq!(aggregate.errors, calculate_error_distribution(res));
+        assert_eq!(aggregate.truncated, calculate_truncated_bodies(res));
+        assert_eq!(
+            super::calculate_total_data(&aggregate),
+            calculate_total_data(res)
+        );
+        assert_eq!(
+            super::calculate_size_per_request(&aggregate),
+            calculate_size_per_request(res)
+        );
+        assert_eq!(
+            super::calculate_total_decompressed_data(&aggregate),
+            calculate_total_decompressed_data(res)
+        );
+        assert_eq!(
+            super::calculate_decompressed_size_per_request(&aggregate),
+            calculate_decompressed_size_per_request(res)
+        );
+        assert_eq!(
+            super::calculate_requests_per_sec(&aggregate, Duration::from_secs(3)),
+            calculate_requests_per_sec(res, Duration::from_secs(3))
+        );
+        assert_eq!(aggregate.expect_continue, calculate_expect_continue(res));
+        assert_eq!(
+            super::calculate_informational(&aggregate),
+            calculate_informational(res)
+        );
+
+        for includes in [
+            LatencyIncludes::All,
+            LatencyIncludes::NoDns,
+            LatencyIncludes::ResponseOnly,
+        ] {
+            let latency = aggregate.latency_by(includes);
+            assert_eq!(latency.fastest(), calculate_fastest_request(res, includes));
+            assert_eq!(latency.slowest(), calculate_slowest_request(res, includes));
+            assert_close(latency.average(), calculate_average_request(res, includes));
+        }
+        assert_close(
+            aggregate.latency_average(),
+            calculate_average_request(res, LatencyIncludes::All),
+        );
+
+        let connection_times = calculate_connection_times_base(res);
+        assert_close(
+            aggregate.dns_dialup.average(),
+            calculate_connection_times_dns_dialup_average(&connection_times),
+        );
+        assert_eq!(
+            aggregate.dns_lookup.fastest(),
+            calculate_connection_times_dns_lookup_fastest(&connection_times)
+        );
+        assert_eq!(
+            aggregate.dns_dialup.slowest(),
+            calculate_connection_times_dns_dialup_slowest(&connection_times)
+        );
+        assert_close(
+            aggregate.first_byte.average(),
+            calculate_first_byte_average(res),
+        );
+        assert_eq!(aggregate.new_connections, connection_times.len());
+
+        // The percentiles are within the resolution of the histogram
+        for (histogram, mut durations) in [
+            (
+                &aggregate.latency,
+                get_durations_all(res, LatencyIncludes::All),
+            ),
+            (
+                &aggregate.latency_successful,
+                get_durations_successful(res, LatencyIncludes::All),
+            ),
+            (
+                &aggregate.latency_not_successful,
+                get_durations_not_successful(res, LatencyIncludes::All),
+            ),
+            (
+                &aggregate.latency_unexpected_status,
+                get_durations_unexpected_status(res, LatencyIncludes::All),
+            ),
+        ] {
+            assert_eq!(histogram.len() as usize, durations.len());
+            for ((p, v), (_, expected)) in
+                histogram_percentile_iter(histogram).zip(percentile_iter(&mut durations))
+            {
+                assert!(
+                    (v - expected).abs() <= expected * 0.001 + RESOLUTION,
+                    "p{p}: {v} != {expected}"
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_percentile_iter() {
         let mut values: [f64; 40] = [
@@ -852,12 +3181,48 @@ mod tests {
         assert_eq!(calculate_success_rate(&res), 1.0);
     }
 
+    #[test]
+    fn test_calculate_assertion_failures() {
+        let mut res = build_mock_request_result_vec();
+        assert_eq!(calculate_assertion_failures(&res), 0);
+        res.push(Err(ClientError::AssertionFailed(
+            crate::body_assert::AssertionFailure::NotContained,
+        )));
+        res.push(Err(ClientError::Timeout));
+        assert_eq!(calculate_assertion_failures(&res), 1);
+        assert_eq!(calculate_success_rate(&res), 0.6);
+    }
+
+    #[test]
+    fn test_calculate_truncated_bodies() {
+        let mut res = build_mock_request_result_vec();
+        assert_eq!(calculate_truncated_bodies(&res), 0);
+        res[0].as_mut().unwrap().truncated = true;
+        assert_eq!(calculate_truncated_bodies(&res), 1);
+    }
+
+    #[test]
+    fn test_calculate_unexpected_status() {
+        let mut res = build_mock_request_result_vec();
+        for r in res.iter_mut().skip(1) {
+            r.as_mut().unwrap().unexpected_status = true;
+        }
+        assert_eq!(fp_round(calculate_success_rate(&res), 4.0), 0.3333);
+        assert_eq!(
+            get_durations_unexpected_status(&res, LatencyIncludes::All).len(),
+            2
+        );
+        let errors = calculate_error_distribution(&res);
+        assert_eq!(errors["unexpected status 400 Bad Request"], 1);
+        assert_eq!(errors["unexpected status 500 Internal Server Error"], 1);
+    }
+
     #[test]
     fn test_calculate_slowest_request() {
         assert_eq!(
             // Round the calculation to 4 decimal places to remove imprecision
             fp_round(
-                calculate_slowest_request(&build_mock_request_result_vec()),
+                calculate_slowest_request(&build_mock_request_result_vec(), LatencyIncludes::All),
                 4.0
             ),
             1000_f64
@@ -869,7 +3234,7 @@ mod tests {
         assert_eq!(
             // Round the calculation to 4 decimal places to remove imprecision
             fp_round(
-                calculate_fastest_request(&build_mock_request_result_vec()),
+                calculate_fastest_request(&build_mock_request_result_vec(), LatencyIncludes::All),
                 4.0
             ),
             1_f64
@@ -881,7 +3246,7 @@ mod tests {
         assert_eq!(
             // Round the calculation to 4 decimal places to remove imprecision
             fp_round(
-                calculate_average_request(&build_mock_request_result_vec()),
+                calculate_average_request(&build_mock_request_result_vec(), LatencyIncludes::All),
                 4.0
             ),
             367_f64
@@ -909,6 +3274,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_calculate_data_at_extreme_sizes() {
+        let huge = u64::MAX / 2 + 1;
+        let res = vec![
+            build_mock_request_result(StatusCode::OK, 1000, 500, 200, 50, huge),
+            build_mock_request_result(StatusCode::OK, 1000, 500, 200, 50, huge),
+            build_mock_request_result(StatusCode::OK, 1000, 500, 200, 50, u64::MAX),
+        ];
+        // The total doesn't fit in u64, but the average does
+        assert_eq!(calculate_total_data(&res), u64::MAX);
+        assert_eq!(
+            calculate_size_per_request(&res),
+            Some(((huge as u128 * 2 + u64::MAX as u128) / 3) as u64)
+        );
+        let size_per_sec = calculate_size_per_sec(&res, Duration::from_secs(1));
+        assert!(size_per_sec > u64::MAX as f64, "{size_per_sec}");
+        assert_eq!(calculate_size_per_request::<ClientError>(&[]), None);
+    }
+
     #[test]
     fn test_calculate_size_per_sec() {
         assert_eq!(
@@ -1009,9 +3393,106 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_calculate_first_byte() {
+        let res = build_mock_request_result_vec();
+        // Round the calculation to 4 decimal places to remove imprecision
+        assert_eq!(fp_round(calculate_first_byte_average(&res), 4.0), 1.0);
+        assert_eq!(fp_round(calculate_first_byte_fastest(&res), 4.0), 0.5);
+        assert_eq!(fp_round(calculate_first_byte_slowest(&res), 4.0), 1.5);
+    }
+
+    #[test]
+    fn test_latency_includes() {
+        let res = build_mock_request_result_vec();
+        let fastest = |includes| fp_round(calculate_fastest_request(&res, includes), 4.0);
+        let slowest = |includes| fp_round(calculate_slowest_request(&res, includes), 4.0);
+        assert_eq!(fastest(LatencyIncludes::All), 1.0);
+        assert_eq!(fastest(LatencyIncludes::NoDns), 0.8);
+        assert_eq!(fastest(LatencyIncludes::ResponseOnly), 0.5);
+        assert_eq!(slowest(LatencyIncludes::All), 1000.0);
+        assert_eq!(slowest(LatencyIncludes::NoDns), 999.7);
+        assert_eq!(slowest(LatencyIncludes::ResponseOnly), 998.5);
+    }
+
+    #[test]
+    fn test_calculate_expect_continue() {
+        let mut res = build_mock_request_result_vec();
+        assert_eq!(calculate_expect_continue(&res), None);
+
+        let outcomes = [(true, true), (false, true), (false, false)];
+        for (r, (received, body_sent)) in res.iter_mut().zip(outcomes) {
+            r.as_mut().unwrap().expect_continue = Some(ExpectContinue {
+                received,
+                body_sent,
+            });
+        }
+        assert_eq!(
+            calculate_expect_continue(&res),
+            Some(ExpectContinueCounts {
+                received: 1,
+                timed_out: 1,
+                body_not_sent: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_calculate_informational() {
+        let mut res = build_mock_request_result_vec();
+        assert_eq!(calculate_informational(&res), None);
+
+        for (i, r) in res.iter_mut().enumerate() {
+            let r = r.as_mut().unwrap();
+            r.informational = Some(Informational {
+                early_hints: i,
+                first: (i > 0).then(|| r.start + Duration::from_millis(100 * i as u64)),
+            });
+        }
+        let stats = calculate_informational(&res).unwrap();
+        assert_eq!(stats.requests, res.len() - 1);
+        assert_eq!(stats.early_hints, (0..res.len()).sum::<usize>());
+        assert!((stats.first.fastest - 0.1).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_calculate_per_method() {
+        let labels = Labels::default();
+        let mut res = build_mock_request_result_vec();
+        res[2].as_mut().unwrap().method = labels.method(&http::Method::POST);
+
+        let per_method = calculate_per_method(&res, LatencyIncludes::All, &labels);
+        assert_eq!(per_method.keys().collect::<Vec<_>>(), vec!["GET", "POST"]);
+
+        let get = &per_method["GET"];
+        assert_eq!(get.count, 2);
+        assert_eq!(fp_round(get.fastest, 4.0), 1.0);
+        assert_eq!(fp_round(get.slowest, 4.0), 100.0);
+        assert_eq!(get.status_code_distribution["200"], 1);
+        assert_eq!(get.status_code_distribution["400"], 1);
+
+        let post = &per_method["POST"];
+        assert_eq!(post.count, 1);
+        assert_eq!(fp_round(post.average, 4.0), 1000.0);
+        assert_eq!(post.status_code_distribution["500"], 1);
+    }
+
+    #[test]
+    fn test_calculate_per_step() {
+        let labels = Labels::default();
+        let mut res = build_mock_request_result_vec();
+        assert!(calculate_per_step(&res, LatencyIncludes::All, &labels).is_empty());
+
+        res[0].as_mut().unwrap().step = Some(labels.intern("login"));
+        res[1].as_mut().unwrap().step = Some(labels.intern("login"));
+        let per_step = calculate_per_step(&res, LatencyIncludes::All, &labels);
+        assert_eq!(per_step.len(), 1);
+        assert_eq!(per_step["login"].count, 2);
+    }
+
     #[test]
     fn test_get_durations_all() {
-        let durations = get_durations_all(&build_mock_request_result_vec());
+        let durations = get_durations_all(&build_mock_request_result_vec(), LatencyIncludes::All);
         // Round the calculations to 4 decimal places to remove imprecision
         assert_eq!(fp_round(durations[0], 4.0), 1.0);
         assert_eq!(fp_round(durations[1], 4.0), 100.0);
@@ -1020,7 +3501,8 @@ mod tests {
 
     #[test]
     fn test_get_durations_successful() {
-        let durations = get_durations_successful(&build_mock_request_result_vec());
+        let durations =
+            get_durations_successful(&build_mock_request_result_vec(), LatencyIncludes::All);
         // Round the calculations to 4 decimal places to remove imprecision
         assert_eq!(fp_round(durations[0], 4.0), 1.0);
         assert_eq!(durations.get(1), None);
@@ -1028,7 +3510,8 @@ mod tests {
 
     #[test]
     fn test_get_durations_not_successful() {
-        let durations = get_durations_not_successful(&build_mock_request_result_vec());
+        let durations =
+            get_durations_not_successful(&build_mock_request_result_vec(), LatencyIncludes::All);
         // Round the calculations to 4 decimal places to remove imprecision
         assert_eq!(fp_round(durations[0], 4.0), 100.0);
         assert_eq!(fp_round(durations[1], 4.0), 1000.0);
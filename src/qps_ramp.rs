@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+/// The rate of `--qps-ramp`, which changes linearly from `from` to `to` over the `-z` duration
+#[derive(Clone, Copy, Debug)]
+pub struct QpsRamp {
+    pub from: f64,
+    pub to: f64,
+    pub duration: Duration,
+}
+
+impl QpsRamp {
+    /// The target rate `elapsed` after the start
+    pub fn rate(&self, elapsed: Duration) -> f64 {
+        let progress = (elapsed.as_secs_f64() / self.duration.as_secs_f64()).clamp(0.0, 1.0);
+        self.from + (self.to - self.from) * progress
+    }
+
+    /// When the `i`th request is sent, relative to the start.
+    /// Solves `from * t + (to - from) / duration * t^2 / 2 = i`, the number of requests sent by `t`.
+    pub fn offset(&self, i: usize) -> Duration {
+        let i = i as f64;
+        let slope = (self.to - self.from) / self.duration.as_secs_f64();
+        // The root in this form is stable when the slope is near 0
+        let t = 2.0 * i / (self.from + (self.from * self.from + 2.0 * slope * i).max(0.0).sqrt());
+        Duration::from_secs_f64(t)
+    }
+
+    pub fn max_rate(&self) -> f64 {
+        self.from.max(self.to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset() {
+        let ramp = QpsRamp {
+            from: 10.0,
+            to: 30.0,
+            duration: Duration::from_secs(10),
+        };
+        assert_eq!(ramp.offset(0), Duration::ZERO);
+        // 24 requests in the first 2 seconds at 12 QPS in average
+        assert!((ramp.offset(24).as_secs_f64() - 2.0).abs() < 1e-9);
+        // 200 requests in total at 20 QPS in average
+        assert!((ramp.offset(200).as_secs_f64() - 10.0).abs() < 1e-9);
+        assert_eq!(ramp.rate(Duration::from_secs(5)), 20.0);
+        assert_eq!(ramp.rate(Duration::from_secs(20)), 30.0);
+
+        let down = QpsRamp {
+            from: 30.0,
+            to: 10.0,
+            duration: Duration::from_secs(10),
+        };
+        assert!((down.offset(200).as_secs_f64() - 10.0).abs() < 1e-9);
+
+        let flat = QpsRamp {
+            from: 4.0,
+            to: 4.0,
+            duration: Duration::from_secs(10),
+        };
+        assert_eq!(flat.offset(2), Duration::from_millis(500));
+    }
+}
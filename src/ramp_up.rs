@@ -0,0 +1,179 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+
+/// Starts the workers one by one over `--ramp-up`, from 1 to all of them, or at random times
+/// over `--stagger`
+pub struct RampUp {
+    duration: Duration,
+    /// When each worker starts, relative to the first one
+    offsets: Vec<Duration>,
+    /// `--stagger` rather than `--ramp-up`
+    stagger: bool,
+    /// Whether the requests which start in the window are excluded from the statistics
+    exclude: bool,
+    /// When the first worker started
+    start: OnceLock<Instant>,
+    /// Workers which have started, shown on the TUI
+    pub active: AtomicUsize,
+    /// Set when a worker runs out of work, so that the waiting workers don't start
+    done: tokio::sync::watch::Sender<bool>,
+}
+
+impl RampUp {
+    /// `--ramp-up`, which starts the workers evenly over `duration`
+    pub fn new(duration: Duration, workers: usize) -> Self {
+        let offsets = (0..workers)
+            .map(|worker| {
+                if workers <= 1 {
+                    Duration::ZERO
+                } else {
+                    duration.mul_f64(worker as f64 / (workers - 1) as f64)
+                }
+            })
+            .collect();
+        Self::with_offsets(duration, offsets, false, true)
+    }
+
+    /// `--stagger`, which starts each worker at a uniformly random time in `duration`
+    pub fn stagger<R: Rng>(duration: Duration, workers: usize, exclude: bool, rng: &mut R) -> Self {
+        let offsets = (0..workers)
+            .map(|_| duration.mul_f64(rng.gen_range(0.0..=1.0)))
+            .collect();
+        Self::with_offsets(duration, offsets, true, exclude)
+    }
+
+    fn with_offsets(
+        duration: Duration,
+        offsets: Vec<Duration>,
+        stagger: bool,
+        exclude: bool,
+    ) -> Self {
+        Self {
+            duration,
+            offsets,
+            stagger,
+            exclude,
+            start: OnceLock::new(),
+            active: AtomicUsize::new(0),
+            done: tokio::sync::watch::channel(false).0,
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn workers(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_stagger(&self) -> bool {
+        self.stagger
+    }
+
+    /// Whether the requests which start in the window are reported separately
+    pub fn is_excluded(&self) -> bool {
+        self.exclude
+    }
+
+    /// When the `worker`th worker starts, relative to the first one
+    fn offset(&self, worker: usize) -> Duration {
+        self.offsets.get(worker).copied().unwrap_or(self.duration)
+    }
+
+    /// Wait for the turn of the `worker`th worker.
+    /// Returns false when the run ended first and the worker shouldn't start.
+    pub async fn wait(&self, worker: usize, dead_line: Option<Instant>) -> bool {
+        let start = *self.start.get_or_init(Instant::now);
+        let at = start + self.offset(worker);
+        let mut done = self.done.subscribe();
+        let started = tokio::select! {
+            _ = tokio::time::sleep_until(at.into()) => true,
+            _ = done.wait_for(|done| *done) => false,
+            _ = async {
+                match dead_line {
+                    Some(dead_line) => tokio::time::sleep_until(dead_line.into()).await,
+                    None => std::future::pending().await,
+                }
+            } => false,
+        };
+        if started {
+            self.active.fetch_add(1, Ordering::Relaxed);
+        }
+        started
+    }
+
+    /// Tells the waiting workers to not start when dropped, which is when a worker is done
+    pub fn finish_guard(&self) -> FinishGuard<'_> {
+        FinishGuard(self)
+    }
+}
+
+pub struct FinishGuard<'a>(&'a RampUp);
+
+impl Drop for FinishGuard<'_> {
+    fn drop(&mut self) {
+        self.0.done.send_replace(true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait() {
+        let ramp_up = RampUp::new(Duration::from_millis(200), 3);
+        assert_eq!(ramp_up.offset(1), Duration::from_millis(100));
+        assert_eq!(ramp_up.offset(2), Duration::from_millis(200));
+
+        let start = Instant::now();
+        assert!(ramp_up.wait(0, None).await);
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert!(ramp_up.wait(1, None).await);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(ramp_up.active.load(Ordering::Relaxed), 2);
+
+        drop(ramp_up.finish_guard());
+        assert!(!ramp_up.wait(2, None).await);
+        assert_eq!(ramp_up.active.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_stagger() {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let stagger = RampUp::stagger(Duration::from_secs(1), 100, false, &mut rng);
+        assert_eq!(stagger.workers(), 100);
+        assert!(stagger.is_stagger());
+        assert!(!stagger.is_excluded());
+        let offsets: Vec<Duration> = (0..100).map(|worker| stagger.offset(worker)).collect();
+        assert!(offsets
+            .iter()
+            .all(|&offset| offset <= Duration::from_secs(1)));
+        // Spread over the window rather than in order
+        assert!(offsets
+            .iter()
+            .any(|&offset| offset < Duration::from_millis(200)));
+        assert!(offsets
+            .iter()
+            .any(|&offset| offset > Duration::from_millis(800)));
+        assert!(offsets.windows(2).any(|w| w[0] > w[1]));
+    }
+
+    #[tokio::test]
+    async fn test_dead_line() {
+        let ramp_up = RampUp::new(Duration::from_secs(10), 2);
+        assert!(ramp_up.wait(0, None).await);
+        let dead_line = Instant::now() + Duration::from_millis(50);
+        assert!(!ramp_up.wait(1, Some(dead_line)).await);
+    }
+}
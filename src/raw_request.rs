@@ -0,0 +1,919 @@
+use std::{
+    io::{IoSlice, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use futures::FutureExt;
+use hyper::http::{self, StatusCode};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    body::{FileBody, FILE_BUFFER_SIZE},
+    decompress::DecodedLen,
+};
+
+/// A request template of `--raw-request`, sent on the wire as is.
+/// `{host}` and `{seq}` in the request line and the headers are substituted for each request.
+/// A request which is the same each time is encoded into one by `prebuilt`.
+pub struct RawRequest {
+    /// The request with the placeholders between the constant parts
+    segments: Vec<Segment>,
+    /// The body of a prebuilt request, written after the segments without copying it.
+    /// The body of a template is in the segments.
+    body: Bytes,
+    /// The body of a prebuilt request which is streamed from the file instead
+    file: Option<Arc<FileBody>>,
+    /// The method of the request line, which tells whether the response has a body
+    method: http::Method,
+    /// The template has `Connection: close`
+    close: bool,
+    seq: AtomicUsize,
+}
+
+/// The body of a prebuilt request
+pub enum PrebuiltBody {
+    Bytes(Bytes),
+    File(Arc<FileBody>),
+}
+
+enum Segment {
+    Bytes(Vec<u8>),
+    Host,
+    Seq,
+}
+
+/// Bytes read from the connection at least at once
+const READ_SIZE: usize = 8192;
+
+#[derive(Error, Debug)]
+pub enum RawRequestError {
+    #[error("invalid response: {0}")]
+    Parse(#[from] httparse::Error),
+    #[error("invalid chunk size in the response")]
+    InvalidChunk,
+    #[error("connection closed before the response was complete")]
+    Closed,
+    #[error("connection closed before the response")]
+    NoResponse,
+    #[error("truncated response: received {received} of {expected} bytes")]
+    Truncated { expected: usize, received: usize },
+    #[error("truncated response: connection closed in the chunked body")]
+    TruncatedChunked,
+    #[error("read timeout")]
+    ReadTimeout,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl RawRequestError {
+    /// The server closed or reset the connection before any byte of the response, as it does
+    /// to a kept-alive connection it closes while the request is sent
+    pub fn is_closed_connection(&self) -> bool {
+        match self {
+            RawRequestError::NoResponse => true,
+            RawRequestError::Io(err) => is_connection_reset(err),
+            _ => false,
+        }
+    }
+}
+
+/// The peer reset or closed the connection under a write or a read
+pub fn is_connection_reset(err: &std::io::Error) -> bool {
+    matches!(
+        err.kind(),
+        std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::BrokenPipe
+    )
+}
+
+/// How a response is read, as hyper reads the others
+#[derive(Clone, Copy, Default)]
+pub struct ReadOptions {
+    /// Fail when no bytes arrive within it
+    pub read_timeout: Option<Duration>,
+    /// Stop reading the body once this many bytes are received, for `--max-body-bytes`
+    pub max_len: Option<usize>,
+    /// Measure the size of the body after decoding its `Content-Encoding`
+    pub decode: bool,
+}
+
+/// The response to a raw request. The body is counted and dropped.
+pub struct RawResponse {
+    pub status: StatusCode,
+    /// When the response header is received
+    pub first_byte: std::time::Instant,
+    /// The size of the body on the wire, up to where reading stopped
+    pub len: usize,
+    /// Reading stopped at `ReadOptions::max_len`
+    pub truncated: bool,
+    /// The size after decoding `Content-Encoding`, when it's measured and known
+    pub decoded_len: Option<usize>,
+    /// The connection can be used for the next request
+    pub keep_alive: bool,
+    /// The Location header, which oha follows like a redirect whatever the status
+    pub location: Option<http::HeaderValue>,
+}
+
+impl RawRequest {
+    /// Parse a template. Bare `\n` in the request line and the headers are normalized to `\r\n`,
+    /// and the body after the empty line is sent byte for byte.
+    pub fn parse(template: &[u8]) -> Result<Self, String> {
+        let template = String::from_utf8_lossy(template);
+        let (head, body) = template
+            .split_once("\r\n\r\n")
+            .or_else(|| template.split_once("\n\n"))
+            .filter(|(head, _)| !head.is_empty())
+            .ok_or("the headers must end with an empty line")?;
+        let head = head.replace("\r\n", "\n").replace('\n', "\r\n") + "\r\n\r\n";
+
+        let method = head
+            .split(' ')
+            .next()
+            .filter(|method| !method.contains("\r\n"))
+            .and_then(|method| http::Method::from_bytes(method.as_bytes()).ok())
+            .ok_or("the first line must be a request line like GET / HTTP/1.1")?;
+        let close = head.lines().skip(1).any(|line| {
+            line.split_once(':').is_some_and(|(name, value)| {
+                name.trim().eq_ignore_ascii_case("connection")
+                    && value.trim().eq_ignore_ascii_case("close")
+            })
+        });
+
+        let mut segments = Vec::new();
+        let mut rest = head.as_str();
+        while let Some(start) = rest.find('{') {
+            let (segment, len) = if rest[start..].starts_with("{host}") {
+                (Segment::Host, "{host}".len())
+            } else if rest[start..].starts_with("{seq}") {
+                (Segment::Seq, "{seq}".len())
+            } else {
+                push_bytes(&mut segments, &rest.as_bytes()[..=start]);
+                rest = &rest[start + 1..];
+                continue;
+            };
+            push_bytes(&mut segments, &rest.as_bytes()[..start]);
+            segments.push(segment);
+            rest = &rest[start + len..];
+        }
+        push_bytes(&mut segments, rest.as_bytes());
+        push_bytes(&mut segments, body.as_bytes());
+
+        Ok(Self {
+            segments,
+            body: Bytes::new(),
+            file: None,
+            method,
+            close,
+            seq: AtomicUsize::new(0),
+        })
+    }
+
+    /// Encode an HTTP/1.1 request the way hyper does, with `Content-Length` for a body which
+    /// isn't empty.
+    pub fn prebuilt(
+        method: http::Method,
+        target: &str,
+        headers: &http::HeaderMap,
+        body: Option<PrebuiltBody>,
+    ) -> Self {
+        let (body, file) = match body {
+            Some(PrebuiltBody::Bytes(body)) => (body, None),
+            Some(PrebuiltBody::File(file)) => (Bytes::new(), Some(file)),
+            None => (Bytes::new(), None),
+        };
+        let len = match &file {
+            Some(file) => file.len(),
+            None => body.len() as u64,
+        };
+        let mut head = format!("{method} {target} HTTP/1.1\r\n").into_bytes();
+        for (name, value) in headers {
+            head.extend_from_slice(name.as_str().as_bytes());
+            head.extend_from_slice(b": ");
+            head.extend_from_slice(value.as_bytes());
+            head.extend_from_slice(b"\r\n");
+        }
+        if len > 0 && !headers.contains_key(http::header::CONTENT_LENGTH) {
+            // The unwrap is fine because Vec::write* is infallible.
+            write!(head, "content-length: {len}\r\n").unwrap();
+        }
+        head.extend_from_slice(b"\r\n");
+        let close = headers
+            .get_all(http::header::CONNECTION)
+            .iter()
+            .any(|v| contains_ignore_case(v.as_bytes(), b"close"));
+
+        Self {
+            segments: vec![Segment::Bytes(head)],
+            body,
+            file,
+            method,
+            close,
+            seq: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn method(&self) -> &http::Method {
+        &self.method
+    }
+
+    /// Whether `{host}` is in the template
+    pub fn has_host(&self) -> bool {
+        self.segments.iter().any(|s| matches!(s, Segment::Host))
+    }
+
+    /// The bytes of the next request, written in `buf` unless the template is constant.
+    /// `{seq}` counts from 0 across all workers.
+    pub fn render<'a>(&'a self, host: &str, buf: &'a mut Vec<u8>) -> &'a [u8] {
+        if let [Segment::Bytes(request)] = self.segments.as_slice() {
+            return request;
+        }
+        buf.clear();
+        for segment in &self.segments {
+            match segment {
+                Segment::Bytes(bytes) => buf.extend_from_slice(bytes),
+                Segment::Host => buf.extend_from_slice(host.as_bytes()),
+                // The unwrap is fine because Vec::write* is infallible.
+                Segment::Seq => {
+                    write!(buf, "{}", self.seq.fetch_add(1, Ordering::Relaxed)).unwrap()
+                }
+            }
+        }
+        buf
+    }
+
+    /// Send `request` and read the response with `buf`, which is kept for the next one.
+    pub async fn send<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        stream: &mut S,
+        request: &[u8],
+        buf: &mut BytesMut,
+        options: ReadOptions,
+    ) -> Result<RawResponse, RawRequestError> {
+        write_all_vectored(
+            stream,
+            &mut [IoSlice::new(request), IoSlice::new(&self.body)],
+        )
+        .await?;
+        if let Some(file) = &self.file {
+            copy_file(stream, file).await?;
+        }
+        stream.flush().await?;
+        self.read_response(stream, buf, options).await
+    }
+
+    /// `send` on a TCP connection, which hands a file body to the kernel with sendfile(2)
+    /// instead of copying it through oha
+    #[cfg(target_os = "linux")]
+    pub async fn send_tcp(
+        &self,
+        stream: &mut tokio::net::TcpStream,
+        request: &[u8],
+        buf: &mut BytesMut,
+        options: ReadOptions,
+    ) -> Result<RawResponse, RawRequestError> {
+        let Some(file) = &self.file else {
+            return self.send(stream, request, buf, options).await;
+        };
+        write_all_vectored(stream, &mut [IoSlice::new(request)]).await?;
+        send_file(stream, file).await?;
+        self.read_response(stream, buf, options).await
+    }
+
+    /// Read the response to the request just sent
+    async fn read_response<S: AsyncRead + Unpin>(
+        &self,
+        stream: &mut S,
+        buf: &mut BytesMut,
+        options: ReadOptions,
+    ) -> Result<RawResponse, RawRequestError> {
+        buf.clear();
+        let mut reader = Reader {
+            stream,
+            buf,
+            read_timeout: options.read_timeout,
+            len: 0,
+            max_len: options.max_len,
+            decoded_len: None,
+        };
+        let mut informational = false;
+        let (status, head_len, framing, keep_alive, location, decoded_len) = loop {
+            let mut headers = [httparse::EMPTY_HEADER; 64];
+            let mut res = httparse::Response::new(&mut headers);
+            let httparse::Status::Complete(head_len) = res.parse(reader.buf)? else {
+                match reader.fill().await {
+                    Err(RawRequestError::Closed) if !informational && reader.buf.is_empty() => {
+                        return Err(RawRequestError::NoResponse)
+                    }
+                    res => res?,
+                }
+                continue;
+            };
+            let status = StatusCode::from_u16(res.code.unwrap_or_default())
+                .map_err(|_| httparse::Error::Status)?;
+            if status.is_informational() && status != StatusCode::SWITCHING_PROTOCOLS {
+                informational = true;
+                reader.buf.advance(head_len);
+                continue;
+            }
+            let (framing, keep_alive) = self.framing(status, &res);
+            let location = res
+                .headers
+                .iter()
+                .find(|h| h.name.eq_ignore_ascii_case("location"))
+                .and_then(|h| http::HeaderValue::from_bytes(h.value).ok());
+            let decoded_len = options.decode.then(|| {
+                let encoding = res
+                    .headers
+                    .iter()
+                    .find(|h| h.name.eq_ignore_ascii_case("content-encoding"))
+                    .and_then(|h| http::HeaderValue::from_bytes(h.value).ok());
+                DecodedLen::new(encoding.as_ref())
+            });
+            break (status, head_len, framing, keep_alive, location, decoded_len);
+        };
+        let first_byte = std::time::Instant::now();
+        reader.buf.advance(head_len);
+        reader.decoded_len = decoded_len;
+
+        let truncated = match framing {
+            Framing::Length(len) => {
+                let mut remaining = len;
+                match reader.data(&mut remaining).await {
+                    Err(RawRequestError::Closed) => {
+                        return Err(RawRequestError::Truncated {
+                            expected: len,
+                            received: len - remaining,
+                        })
+                    }
+                    res => res?,
+                }
+            }
+            Framing::Chunked => match reader.chunked().await {
+                Err(RawRequestError::Closed) => return Err(RawRequestError::TruncatedChunked),
+                res => res?,
+            },
+            Framing::Close => reader.until_close().await?,
+        };
+
+        let len = reader.len;
+        Ok(RawResponse {
+            status,
+            first_byte,
+            len,
+            truncated,
+            // A body without content such as a response to HEAD can't be decoded
+            decoded_len: reader
+                .decoded_len
+                .and_then(|d| if len == 0 { Some(0) } else { d.finish() }),
+            // The rest of a truncated body is left on the connection
+            keep_alive: keep_alive && !self.close && framing != Framing::Close && !truncated,
+            location,
+        })
+    }
+
+    /// How the body of the response ends (RFC 9112 section 6.3) and whether the connection is kept alive
+    fn framing(&self, status: StatusCode, res: &httparse::Response) -> (Framing, bool) {
+        let header = |name: &'static str| {
+            res.headers
+                .iter()
+                .filter(move |h| h.name.eq_ignore_ascii_case(name))
+                .map(|h| h.value)
+        };
+        let keep_alive = if res.version == Some(0) {
+            header("connection").any(|v| contains_ignore_case(v, b"keep-alive"))
+        } else {
+            !header("connection").any(|v| contains_ignore_case(v, b"close"))
+        };
+
+        let framing = if self.method == http::Method::HEAD
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+        {
+            Framing::Length(0)
+        } else if header("transfer-encoding").any(|v| {
+            let v = v.trim_ascii_end();
+            v.len() >= 7 && v[v.len() - 7..].eq_ignore_ascii_case(b"chunked")
+        }) {
+            Framing::Chunked
+        } else if let Some(len) =
+            header("content-length").find_map(|v| std::str::from_utf8(v).ok()?.trim().parse().ok())
+        {
+            Framing::Length(len)
+        } else {
+            Framing::Close
+        };
+        (framing, keep_alive)
+    }
+}
+
+/// Whether the server has closed the kept-alive `stream`, which is told without waiting by
+/// a read that doesn't block. Bytes the server sent without a request also make it unusable.
+pub fn is_closed<S: AsyncRead + Unpin>(stream: &mut S, buf: &mut BytesMut) -> bool {
+    buf.clear();
+    buf.reserve(READ_SIZE);
+    stream.read_buf(buf).now_or_never().is_some()
+}
+
+/// Write all of `bufs` with as few writes as the stream allows
+async fn write_all_vectored<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    mut bufs: &mut [IoSlice<'_>],
+) -> std::io::Result<()> {
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        let n = stream.write_vectored(bufs).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::WriteZero.into());
+        }
+        IoSlice::advance_slices(&mut bufs, n);
+    }
+    Ok(())
+}
+
+/// Write the whole `file` in chunks read on a blocking thread
+async fn copy_file<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    file: &Arc<FileBody>,
+) -> std::io::Result<()> {
+    let mut offset = 0;
+    while offset < file.len() {
+        let chunk = file.clone().read_chunk(offset, FILE_BUFFER_SIZE).await?;
+        stream.write_all(&chunk).await?;
+        offset += chunk.len() as u64;
+    }
+    Ok(())
+}
+
+/// Write the whole `file` with sendfile(2), which reads it at its own offset like `read_chunk`.
+/// The part to send is first read into the page cache with readahead(2) on a blocking thread,
+/// so that sendfile doesn't wait for the disk on the runtime.
+#[cfg(target_os = "linux")]
+async fn send_file(stream: &tokio::net::TcpStream, file: &Arc<FileBody>) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let mut offset: libc::off_t = 0;
+    while (offset as u64) < file.len() {
+        let count = (file.len() - offset as u64).min(FILE_BUFFER_SIZE as u64 * 16) as usize;
+        let ahead = file.clone();
+        tokio::task::spawn_blocking(move || {
+            // SAFETY: the descriptor is open as long as `ahead`
+            if unsafe { libc::readahead(ahead.as_raw_fd(), offset, count) } < 0 {
+                Err(std::io::Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+        let end = offset + count as libc::off_t;
+        while offset < end {
+            stream.writable().await?;
+            let sent = stream.try_io(tokio::io::Interest::WRITABLE, || {
+                let count = (end - offset) as usize;
+                // SAFETY: both descriptors are open for the call and `offset` is a valid pointer
+                let n = unsafe {
+                    libc::sendfile(stream.as_raw_fd(), file.as_raw_fd(), &mut offset, count)
+                };
+                if n < 0 {
+                    Err(std::io::Error::last_os_error())
+                } else {
+                    Ok(n)
+                }
+            });
+            match sent {
+                Ok(0) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        format!("{} shrank during the run", file.path().display()),
+                    ))
+                }
+                Ok(_) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    file.clone().verify_len().await
+}
+
+/// Append to the last constant part
+fn push_bytes(segments: &mut Vec<Segment>, bytes: &[u8]) {
+    match segments.last_mut() {
+        Some(Segment::Bytes(last)) => last.extend_from_slice(bytes),
+        _ if bytes.is_empty() => {}
+        _ => segments.push(Segment::Bytes(bytes.to_vec())),
+    }
+}
+
+fn contains_ignore_case(value: &[u8], token: &[u8]) -> bool {
+    value
+        .windows(token.len())
+        .any(|w| w.eq_ignore_ascii_case(token))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    Length(usize),
+    Chunked,
+    Close,
+}
+
+/// Reads the response with the bytes received but not consumed yet in `buf`.
+struct Reader<'a, S> {
+    stream: &'a mut S,
+    buf: &'a mut BytesMut,
+    read_timeout: Option<Duration>,
+    /// The bytes of the body consumed so far
+    len: usize,
+    max_len: Option<usize>,
+    decoded_len: Option<DecodedLen>,
+}
+
+impl<S: AsyncRead + Unpin> Reader<'_, S> {
+    /// Read more bytes at the end of `buf`
+    async fn read(&mut self) -> Result<usize, RawRequestError> {
+        self.buf.reserve(READ_SIZE);
+        let read = self.stream.read_buf(self.buf);
+        Ok(match self.read_timeout {
+            Some(read_timeout) => tokio::time::timeout(read_timeout, read)
+                .await
+                .map_err(|_| RawRequestError::ReadTimeout)??,
+            None => read.await?,
+        })
+    }
+
+    /// Read more bytes. Fails at EOF.
+    async fn fill(&mut self) -> Result<(), RawRequestError> {
+        if self.read().await? == 0 {
+            return Err(RawRequestError::Closed);
+        }
+        Ok(())
+    }
+
+    /// Consume `n` bytes of the body
+    fn consume(&mut self, n: usize) {
+        if let Some(decoded_len) = &mut self.decoded_len {
+            decoded_len.update(&self.buf[..n]);
+        }
+        self.buf.advance(n);
+        self.len += n;
+    }
+
+    /// The body reached `max_len`
+    fn full(&self) -> bool {
+        self.max_len.is_some_and(|max_len| self.len >= max_len)
+    }
+
+    /// Consume `len` bytes of the body, counting them down. Returns true when reading stopped
+    /// at `max_len` before them.
+    async fn data(&mut self, len: &mut usize) -> Result<bool, RawRequestError> {
+        loop {
+            let n = (*len).min(self.buf.len());
+            self.consume(n);
+            *len -= n;
+            if *len == 0 {
+                return Ok(false);
+            }
+            if self.full() {
+                return Ok(true);
+            }
+            self.fill().await?;
+        }
+    }
+
+    /// Consume `len` bytes which aren't data, counting them down
+    async fn skip(&mut self, len: &mut usize) -> Result<(), RawRequestError> {
+        loop {
+            let n = (*len).min(self.buf.len());
+            self.buf.advance(n);
+            *len -= n;
+            if *len == 0 {
+                return Ok(());
+            }
+            self.fill().await?;
+        }
+    }
+
+    /// Consume a line and return what `f` makes of it without CRLF
+    async fn line<T>(&mut self, f: impl Fn(&[u8]) -> T) -> Result<T, RawRequestError> {
+        loop {
+            if let Some(end) = self.buf.windows(2).position(|w| w == b"\r\n") {
+                let line = f(&self.buf[..end]);
+                self.buf.advance(end + 2);
+                return Ok(line);
+            }
+            self.fill().await?;
+        }
+    }
+
+    /// Consume a chunked body. Returns true when reading stopped at `max_len`.
+    async fn chunked(&mut self) -> Result<bool, RawRequestError> {
+        loop {
+            let size = self
+                .line(|line| {
+                    let size = line.split(|&b| b == b';').next().unwrap_or_default();
+                    let size = std::str::from_utf8(size).ok()?.trim();
+                    usize::from_str_radix(size, 16).ok()
+                })
+                .await?
+                .ok_or(RawRequestError::InvalidChunk)?;
+            if size == 0 {
+                // Trailers end with an empty line
+                while !self.line(<[u8]>::is_empty).await? {}
+                return Ok(false);
+            }
+            let mut remaining = size;
+            if self.full() || self.data(&mut remaining).await? {
+                return Ok(true);
+            }
+            self.skip(&mut 2).await?;
+        }
+    }
+
+    /// Consume the body until the server closes the connection. Returns true when reading
+    /// stopped at `max_len`.
+    async fn until_close(&mut self) -> Result<bool, RawRequestError> {
+        loop {
+            self.consume(self.buf.len());
+            if self.full() {
+                return Ok(true);
+            }
+            if self.read().await? == 0 {
+                return Ok(false);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let request =
+            RawRequest::parse(b"POST /{seq} HTTP/1.1\nHost: {host}\n\nbody\n{seq}").unwrap();
+        let mut buf = Vec::new();
+        assert!(request.has_host());
+        assert_eq!(
+            request.render("example.com", &mut buf),
+            b"POST /0 HTTP/1.1\r\nHost: example.com\r\n\r\nbody\n{seq}"
+        );
+        assert_eq!(
+            request.render("example.com", &mut buf),
+            b"POST /1 HTTP/1.1\r\nHost: example.com\r\n\r\nbody\n{seq}"
+        );
+        assert!(!request.close);
+
+        let request = RawRequest::parse(b"HEAD / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+        assert_eq!(request.method, http::Method::HEAD);
+        assert!(request.close);
+        // A constant request isn't copied
+        assert!(!request.has_host());
+        let mut unused = Vec::new();
+        assert_eq!(
+            request.render("example.com", &mut unused),
+            b"HEAD / HTTP/1.1\r\nConnection: close\r\n\r\n"
+        );
+        assert!(unused.is_empty());
+
+        let request = RawRequest::parse(b"GET /{a}{ HTTP/1.1\nHost: {host}{seq}\n\n").unwrap();
+        assert_eq!(
+            request.render("a", &mut buf),
+            b"GET /{a}{ HTTP/1.1\r\nHost: a0\r\n\r\n"
+        );
+
+        assert!(RawRequest::parse(b"GET / HTTP/1.1\nHost: a\n").is_err());
+        assert!(RawRequest::parse(b"\n\n").is_err());
+        assert!(RawRequest::parse(b"GET\nHost: a\n\n").is_err());
+    }
+
+    #[test]
+    fn test_prebuilt() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(http::header::HOST, "example.com".parse().unwrap());
+        headers.append("x-a", "1".parse().unwrap());
+        headers.append("x-a", "2".parse().unwrap());
+        let request = RawRequest::prebuilt(
+            http::Method::POST,
+            "/a?b=1",
+            &headers,
+            Some(PrebuiltBody::Bytes(Bytes::from_static(b"hello"))),
+        );
+        assert_eq!(
+            request.render("unused", &mut Vec::new()),
+            b"POST /a?b=1 HTTP/1.1\r\nhost: example.com\r\nx-a: 1\r\nx-a: 2\r\ncontent-length: 5\r\n\r\n"
+        );
+        assert_eq!(request.body, "hello");
+        assert!(!request.close);
+
+        headers.insert(http::header::CONNECTION, "close".parse().unwrap());
+        let request = RawRequest::prebuilt(http::Method::GET, "/", &headers, None);
+        assert_eq!(
+            request.render("unused", &mut Vec::new()),
+            b"GET / HTTP/1.1\r\nhost: example.com\r\nx-a: 1\r\nx-a: 2\r\nconnection: close\r\n\r\n"
+        );
+        assert!(request.close);
+    }
+
+    #[tokio::test]
+    async fn test_prebuilt_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("body.bin");
+        let payload: Vec<u8> = (0..FILE_BUFFER_SIZE * 2 + 10).map(|i| i as u8).collect();
+        std::fs::write(&path, &payload).unwrap();
+        let file = Arc::new(FileBody::open(&path).unwrap());
+        let request = RawRequest::prebuilt(
+            http::Method::POST,
+            "/",
+            &http::HeaderMap::new(),
+            Some(PrebuiltBody::File(file)),
+        );
+        let head = request.render("unused", &mut Vec::new()).to_vec();
+        assert_eq!(
+            head,
+            format!(
+                "POST / HTTP/1.1\r\ncontent-length: {}\r\n\r\n",
+                payload.len()
+            )
+            .as_bytes()
+        );
+
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let len = head.len() + payload.len();
+        let received = tokio::spawn(async move {
+            let mut received = vec![0; len];
+            server.read_exact(&mut received).await.unwrap();
+            server
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            received
+        });
+        let res = request
+            .send(&mut client, &head, &mut BytesMut::new(), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(res.status, StatusCode::OK);
+        assert_eq!(received.await.unwrap()[head.len()..], payload);
+    }
+
+    async fn send(request: &[u8], response: &[u8]) -> Result<RawResponse, RawRequestError> {
+        send_with(request, response, Default::default()).await
+    }
+
+    async fn send_with(
+        request: &[u8],
+        response: &[u8],
+        options: ReadOptions,
+    ) -> Result<RawResponse, RawRequestError> {
+        let request = RawRequest::parse(request).unwrap();
+        let bytes = request.render("a", &mut Vec::new()).to_vec();
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let response = response.to_vec();
+        let len = bytes.len();
+        tokio::spawn(async move {
+            server.read_exact(&mut vec![0; len]).await.unwrap();
+            // The client stops reading a truncated body
+            let _ = server.write_all(&response).await;
+        });
+        request
+            .send(&mut client, &bytes, &mut BytesMut::new(), options)
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_send() {
+        let get = b"GET / HTTP/1.1\n\n";
+        let res = send(get, b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello")
+            .await
+            .unwrap();
+        assert_eq!(res.status, StatusCode::OK);
+        assert_eq!(res.len, 5);
+        assert!(res.keep_alive);
+
+        let res = send(
+            get,
+            b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 201 Created\r\nTransfer-Encoding: chunked\r\n\r\n3;x=y\r\nabc\r\n2\r\nde\r\n0\r\nTrailer: 1\r\n\r\n",
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.status, StatusCode::CREATED);
+        assert_eq!(res.len, 5);
+
+        let res = send(get, b"HTTP/1.0 200 OK\r\n\r\nuntil close")
+            .await
+            .unwrap();
+        assert_eq!(res.len, 11);
+        assert!(!res.keep_alive);
+
+        let res = send(
+            b"HEAD / HTTP/1.1\n\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n",
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.len, 0);
+
+        assert!(matches!(
+            send(get, b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhel").await,
+            Err(RawRequestError::Truncated {
+                expected: 5,
+                received: 3
+            })
+        ));
+        assert!(matches!(
+            send(
+                get,
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhe"
+            )
+            .await,
+            Err(RawRequestError::TruncatedChunked)
+        ));
+        assert!(matches!(
+            send(get, b"HTTP/1.1 200 OK\r\n").await,
+            Err(RawRequestError::Closed)
+        ));
+        assert!(matches!(
+            send(get, b"").await,
+            Err(RawRequestError::NoResponse)
+        ));
+        assert!(matches!(
+            send(get, b"HTTP/1.1 100 Continue\r\n\r\n").await,
+            Err(RawRequestError::Closed)
+        ));
+        assert!(matches!(
+            send(
+                get,
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n"
+            )
+            .await,
+            Err(RawRequestError::InvalidChunk)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_send_options() {
+        let get = b"GET / HTTP/1.1\n\n";
+        let max_len = ReadOptions {
+            max_len: Some(100),
+            ..Default::default()
+        };
+        // The duplex stream delivers 1024 bytes at most at once
+        let mut response = b"HTTP/1.1 200 OK\r\nContent-Length: 5000\r\n\r\n".to_vec();
+        response.extend_from_slice(&[b'a'; 5000]);
+        let res = send_with(get, &response, max_len).await.unwrap();
+        assert!(res.truncated);
+        assert!(res.len >= 100 && res.len < 5000);
+        assert!(!res.keep_alive);
+
+        let mut response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+        for _ in 0..50 {
+            response.extend_from_slice(b"64\r\n");
+            response.extend_from_slice(&[b'a'; 100]);
+            response.extend_from_slice(b"\r\n");
+        }
+        response.extend_from_slice(b"0\r\n\r\n");
+        let res = send_with(get, &response, max_len).await.unwrap();
+        assert!(res.truncated);
+        assert_eq!(res.len, 100);
+
+        let res = send_with(
+            get,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello",
+            max_len,
+        )
+        .await
+        .unwrap();
+        assert!(!res.truncated);
+        assert!(res.keep_alive);
+
+        let text = "hello world ".repeat(100);
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(text.as_bytes()).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            gzip.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(&gzip);
+        let decode = ReadOptions {
+            decode: true,
+            ..Default::default()
+        };
+        let res = send_with(get, &response, decode).await.unwrap();
+        assert_eq!(res.len, gzip.len());
+        assert_eq!(res.decoded_len, Some(text.len()));
+
+        let res = send(get, &response).await.unwrap();
+        assert_eq!(res.decoded_len, None);
+    }
+}
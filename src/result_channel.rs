@@ -0,0 +1,139 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use crate::{
+    aggregate::Folded,
+    client::{ClientError, RequestResult},
+    sampling::Sampling,
+};
+
+/// What the collector takes in
+#[derive(Debug)]
+// The results are sent for each request, and aren't boxed
+#[allow(clippy::large_enum_variant)]
+pub enum Report {
+    /// The result of a request of a worker
+    Result(Result<RequestResult, ClientError>),
+    /// A second of the run of an agent of `--workers`, which the agent folded
+    Folded(Box<Folded>),
+}
+
+/// Results the channel holds for each worker. The collector takes them as they come, so the
+/// channel only fills up when it can't keep up.
+const PER_WORKER: usize = 64;
+/// Yields to the collector before a full channel makes the worker wait
+const SPINS: usize = 8;
+
+/// How many results the channel holds for `workers` workers
+pub fn capacity(workers: usize) -> usize {
+    workers.saturating_mul(PER_WORKER).max(PER_WORKER)
+}
+
+/// A bounded channel of results for `workers` workers, which only passes the sampled ones with
+/// `sampling`. The counter tells how many results found it full.
+pub fn channel(
+    workers: usize,
+    sampling: Option<Arc<Sampling>>,
+) -> (ReportSender, flume::Receiver<Report>, Arc<AtomicU64>) {
+    let (tx, rx) = flume::bounded(capacity(workers));
+    let full = Arc::new(AtomicU64::new(0));
+    (
+        ReportSender {
+            tx,
+            full: full.clone(),
+            sampling,
+        },
+        rx,
+        full,
+    )
+}
+
+/// Sends the results of the workers to the collector
+#[derive(Clone)]
+pub struct ReportSender {
+    tx: flume::Sender<Report>,
+    full: Arc<AtomicU64>,
+    sampling: Option<Arc<Sampling>>,
+}
+
+impl ReportSender {
+    /// Send right away, or wait for the collector when the channel is full. A result left out of
+    /// the sample is only counted. Fails when the collector is gone.
+    pub async fn send(
+        &self,
+        res: Result<RequestResult, ClientError>,
+    ) -> Result<(), flume::SendError<Report>> {
+        if let Some(sampling) = &self.sampling {
+            if !sampling.count(&res) {
+                return Ok(());
+            }
+        }
+        let mut report = match self.tx.try_send(Report::Result(res)) {
+            Ok(()) => return Ok(()),
+            Err(flume::TrySendError::Disconnected(report)) => return Err(flume::SendError(report)),
+            Err(flume::TrySendError::Full(report)) => report,
+        };
+        self.full.fetch_add(1, Ordering::Relaxed);
+        for _ in 0..SPINS {
+            tokio::task::yield_now().await;
+            report = match self.tx.try_send(report) {
+                Ok(()) => return Ok(()),
+                Err(flume::TrySendError::Disconnected(report)) => {
+                    return Err(flume::SendError(report))
+                }
+                Err(flume::TrySendError::Full(report)) => report,
+            };
+        }
+        self.tx.send_async(report).await
+    }
+
+    /// Send a second of an agent, which the sampling doesn't apply to
+    pub async fn send_folded(&self, folded: Folded) -> Result<(), flume::SendError<Report>> {
+        self.tx.send_async(Report::Folded(Box::new(folded))).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_backpressure() {
+        let (tx, rx, full) = channel(1, None);
+        for _ in 0..capacity(1) {
+            tx.send(Err(ClientError::Timeout)).await.unwrap();
+        }
+        assert_eq!(full.load(Ordering::Relaxed), 0);
+
+        // The next one waits for the collector
+        let send = tokio::spawn({
+            let tx = tx.clone();
+            async move { tx.send(Err(ClientError::Timeout)).await.is_ok() }
+        });
+        tokio::task::yield_now().await;
+        assert!(!send.is_finished());
+        assert!(matches!(
+            rx.recv_async().await.unwrap(),
+            Report::Result(Err(ClientError::Timeout))
+        ));
+        assert!(send.await.unwrap());
+        assert_eq!(full.load(Ordering::Relaxed), 1);
+        assert_eq!(rx.len(), capacity(1));
+
+        drop(rx);
+        assert!(tx.send(Err(ClientError::Timeout)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sampling() {
+        let sampling = Arc::new(Sampling::new(4));
+        let (tx, rx, _) = channel(1, Some(sampling.clone()));
+        for _ in 0..10 {
+            tx.send(Err(ClientError::Timeout)).await.unwrap();
+        }
+        assert_eq!(rx.len(), 3);
+        assert_eq!(sampling.sampled(), 3);
+    }
+}
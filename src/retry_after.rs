@@ -0,0 +1,125 @@
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant, SystemTime},
+};
+
+use hyper::http::{self, HeaderMap, StatusCode};
+
+/// Pauses of the workers by `--respect-retry-after`
+#[derive(Default)]
+pub struct RetryAfterCounters {
+    /// 429 and 503 responses with a valid Retry-After
+    pub responses: AtomicUsize,
+    /// Total time the workers have paused
+    pub paused_nanos: AtomicU64,
+    /// Workers pausing now, shown on the TUI
+    pub in_backoff: AtomicUsize,
+}
+
+/// How long the server asks to wait by the Retry-After of a 429 or 503 response.
+/// A date in the past means no wait.
+pub fn retry_after(status: StatusCode, headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    if status != StatusCode::TOO_MANY_REQUESTS && status != StatusCode::SERVICE_UNAVAILABLE {
+        return None;
+    }
+    let value = headers
+        .get(http::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            Some(date.duration_since(now).unwrap_or_default())
+        }
+    }
+}
+
+/// Decrements `in_backoff` and adds the paused time even if the worker is dropped in the pause
+struct Backoff<'a> {
+    counters: &'a RetryAfterCounters,
+    start: Instant,
+}
+
+impl Drop for Backoff<'_> {
+    fn drop(&mut self) {
+        self.counters.in_backoff.fetch_sub(1, Ordering::Relaxed);
+        self.counters
+            .paused_nanos
+            .fetch_add(self.start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Sleep until `until`, or `dead_line` if it comes first
+pub async fn pause(counters: &RetryAfterCounters, until: Instant, dead_line: Option<Instant>) {
+    let until = dead_line.map_or(until, |dead_line| until.min(dead_line));
+    if until <= Instant::now() {
+        return;
+    }
+    counters.in_backoff.fetch_add(1, Ordering::Relaxed);
+    let _backoff = Backoff {
+        counters,
+        start: Instant::now(),
+    };
+    tokio::time::sleep_until(until.into()).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_after() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        let headers = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(http::header::RETRY_AFTER, value.parse().unwrap());
+            headers
+        };
+        assert_eq!(
+            retry_after(StatusCode::TOO_MANY_REQUESTS, &headers("120"), now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            retry_after(
+                StatusCode::SERVICE_UNAVAILABLE,
+                &headers("Wed, 21 Oct 2015 07:28:30 GMT"),
+                now
+            ),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            retry_after(
+                StatusCode::SERVICE_UNAVAILABLE,
+                &headers("Wed, 21 Oct 2015 07:27:00 GMT"),
+                now
+            ),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after(StatusCode::OK, &headers("120"), now), None);
+        assert_eq!(
+            retry_after(StatusCode::TOO_MANY_REQUESTS, &headers("soon"), now),
+            None
+        );
+        assert_eq!(
+            retry_after(StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new(), now),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pause() {
+        let counters = RetryAfterCounters::default();
+        let start = Instant::now();
+        pause(
+            &counters,
+            start + Duration::from_secs(10),
+            Some(start + Duration::from_millis(50)),
+        )
+        .await;
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(counters.in_backoff.load(Ordering::Relaxed), 0);
+        assert!(counters.paused_nanos.load(Ordering::Relaxed) >= 50_000_000);
+    }
+}
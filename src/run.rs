@@ -0,0 +1,1378 @@
+use anyhow::Context;
+use bytes::Bytes;
+use futures::prelude::*;
+use hyper::http::{self, header::HeaderValue};
+use rand::prelude::*;
+use rand_regex::Regex;
+use std::io::Read;
+use url::Url;
+
+#[cfg(feature = "tui")]
+use crate::monitor;
+use crate::{
+    abort, agent, aggregate,
+    body::{self, BodySource},
+    body_assert, body_dir,
+    client::{self, ClientError},
+    connect_rate, decompress, duty_cycle, fd_limit, har, header, idn, label, load_profile,
+    method_mix, multipart,
+    opts::{apply_curl, form_body, IdleWorkers, IoBackend, IpVersion, Opts, RequestTarget},
+    pause,
+    printer::{self, PrintMode},
+    qps_ramp, ramp_up, raw_request, result_channel, sampling, save_responses, scenario,
+    self_monitor, shutdown, start_at, target_p99, think_time,
+    tls::{self, TlsConnector},
+    url_generator::{self, UrlGenerator},
+    url_list, websocket, Error,
+};
+
+/// Workers a runtime thread can drive before the requests wait for it
+const WORKERS_PER_THREAD: usize = 1000;
+
+/// What to run: the options of the command line, which can also be given from code
+pub struct LoadConfig {
+    opts: Opts,
+    runtime_threads: usize,
+    shutdown: std::sync::Arc<shutdown::Shutdown>,
+    partial_summary: std::sync::Arc<printer::PartialSummary>,
+}
+
+impl From<Opts> for LoadConfig {
+    fn from(opts: Opts) -> Self {
+        let runtime_threads = opts.runtime_threads.map_or_else(
+            || std::thread::available_parallelism().map_or(1, |n| n.get()),
+            |threads| threads as usize,
+        );
+        Self {
+            runtime_threads,
+            shutdown: std::sync::Arc::new(shutdown::Shutdown::new(opts.shutdown_grace.into())),
+            partial_summary: Default::default(),
+            opts,
+        }
+    }
+}
+
+impl LoadConfig {
+    /// 200 requests to `url` on 50 connections, like `oha --no-tui <url>`
+    pub fn new(url: &str) -> Self {
+        Self::from_args(["--no-tui", url]).expect("a URL is a valid argument")
+    }
+
+    /// The options of the command line, without the program name
+    ///
+    /// ```
+    /// let config = oha::LoadConfig::from_args(["-n", "10", "--json", "http://localhost:3000/"])?;
+    /// assert!(matches!(
+    ///     oha::LoadConfig::from_args(["--no-such-option"]),
+    ///     Err(oha::Error::Args(_))
+    /// ));
+    /// # Ok::<(), oha::Error>(())
+    /// ```
+    pub fn from_args<I, T>(args: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        let args = std::iter::once("oha".into()).chain(args.into_iter().map(Into::into));
+        Ok(<Opts as clap::Parser>::try_parse_from(args)?.into())
+    }
+
+    /// The job of a controller of `--workers`, which `oha agent` passes to the process of the
+    /// run on stdin
+    #[doc(hidden)]
+    pub fn from_agent_job(job: impl std::io::Read) -> Result<Self, Error> {
+        let mut opts: Opts =
+            serde_json::from_reader(job).map_err(|e| Error::Config(format!("invalid job: {e}")))?;
+        if let Some(option) = agent::check_job(&opts) {
+            return Err(Error::Config(format!(
+                "the agent doesn't run a job with {option}"
+            )));
+        }
+        opts.agent_output = true;
+        Ok(opts.into())
+    }
+
+    /// The threads of the runtime, as `--runtime-threads` sets or one for each CPU
+    pub fn runtime_threads(&self) -> usize {
+        self.runtime_threads
+    }
+
+    /// Stops the run as ctrl-c does in oha: the first `Shutdown::interrupt` stops sending
+    /// requests and lets the ones in flight finish within `--shutdown-grace`, the second drops
+    /// them. None for the job of an agent, whose run is stopped by the agent.
+    pub fn shutdown(&self) -> Option<std::sync::Arc<shutdown::Shutdown>> {
+        (!self.opts.agent_output).then(|| self.shutdown.clone())
+    }
+
+    /// The results collected so far, for a panic hook to print
+    pub fn partial_summary(&self) -> std::sync::Arc<printer::PartialSummary> {
+        self.partial_summary.clone()
+    }
+
+    /// `-n`
+    pub fn requests(mut self, n: u64) -> Self {
+        self.opts.n_requests = n;
+        self
+    }
+
+    /// `-c`
+    pub fn connections(mut self, n: usize) -> Self {
+        self.opts.n_connections = n;
+        self
+    }
+
+    /// `-z`
+    pub fn duration(mut self, duration: std::time::Duration) -> Self {
+        self.opts.duration = Some(duration.into());
+        self
+    }
+
+    /// `-q`
+    pub fn qps(mut self, qps: f64) -> Self {
+        self.opts.query_per_second = Some(qps);
+        self
+    }
+
+    /// `-m`
+    pub fn method(mut self, method: http::Method) -> Self {
+        self.opts.method = Some(method);
+        self
+    }
+
+    /// `-H` e.g. `"accept: text/html"`
+    pub fn header(mut self, header: &str) -> Self {
+        self.opts.headers.push(header.to_string());
+        self
+    }
+
+    /// `-d`
+    pub fn body(mut self, body: &str) -> Self {
+        self.opts.body_string = Some(body.to_string());
+        self
+    }
+
+    /// `-t`
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.opts.timeout = Some(timeout.into());
+        self
+    }
+
+    /// `--http2`
+    pub fn http2(mut self) -> Self {
+        self.opts.http2 = true;
+        self
+    }
+}
+
+/// The results of a run, with what the summary needs to print them
+pub struct RunReport {
+    print_config: printer::PrintConfig,
+    start: std::time::Instant,
+    aggregate: aggregate::ResultAggregate,
+    duration: std::time::Duration,
+    abort: Option<std::sync::Arc<abort::Abort>>,
+    /// The job of an agent streams the results to the controller instead
+    agent_output: bool,
+}
+
+impl RunReport {
+    /// The statistics of the results
+    pub fn aggregate(&self) -> &aggregate::ResultAggregate {
+        &self.aggregate
+    }
+
+    /// The names of the methods, steps and URLs of the results of `aggregate`
+    pub fn labels(&self) -> &label::Labels {
+        &self.print_config.labels
+    }
+
+    /// How long the requests were sent for
+    pub fn duration(&self) -> std::time::Duration {
+        self.duration
+    }
+
+    /// The exit code of a run aborted by `--abort-on-error-rate` or `--abort-on-first-error`,
+    /// or `FORCED_EXIT_CODE` when a second interrupt dropped the requests in flight
+    pub fn exit_code(&self) -> Option<i32> {
+        if self.print_config.shutdown.is_forced() {
+            return Some(shutdown::FORCED_EXIT_CODE);
+        }
+        self.abort
+            .as_ref()
+            .and_then(|abort| abort.reason())
+            .is_some_and(abort::AbortReason::is_failure)
+            .then_some(abort::EXIT_CODE)
+    }
+
+    /// Print the summary as text or as JSON with `-j`, like oha does
+    pub fn print<W: std::io::Write>(self, w: &mut W) -> Result<(), Error> {
+        if self.agent_output {
+            return Ok(());
+        }
+        printer::print_result(
+            w,
+            self.print_config,
+            self.start,
+            self.aggregate,
+            self.duration,
+        )?;
+        Ok(())
+    }
+}
+
+/// Send the requests of `config` and collect their results. The summary isn't printed, see
+/// `RunReport::print`.
+pub async fn run(config: LoadConfig) -> Result<RunReport, Error> {
+    // The other errors are the invalid options
+    run_load(config).await.map_err(|e| {
+        e.downcast::<Error>()
+            .unwrap_or_else(|e| Error::Config(format!("{e:#}")))
+    })
+}
+
+/// The run behind `run`, with the errors of the setup as they are
+async fn run_load(config: LoadConfig) -> anyhow::Result<RunReport> {
+    let LoadConfig {
+        mut opts,
+        runtime_threads,
+        shutdown,
+        partial_summary,
+    } = config;
+    if let Some(curl) = opts.from_curl.take() {
+        apply_curl(&mut opts, &curl)?;
+    }
+    // The agents get the options as they are now, with the files read here
+    let job = opts
+        .workers
+        .is_some()
+        .then(|| agent::job(&opts))
+        .transpose()?;
+    let mut url_arg = opts.url.take().context("URL is required")?;
+    if let Some(with_scheme) = url_generator::add_default_scheme(&url_arg) {
+        url_arg = with_scheme;
+        eprintln!(
+            "note: the URL has no scheme, so {} is assumed",
+            &url_arg[..url_arg.find("://").unwrap() + 3]
+        );
+    }
+
+    let http_version: http::Version = match (opts.http2, opts.http_version.as_deref()) {
+        (true, Some(_)) => anyhow::bail!("--http2 and --http-version are exclusive"),
+        (true, None) => http::Version::HTTP_2,
+        (false, Some(http_version)) => match http_version.trim() {
+            "0.9" => http::Version::HTTP_09,
+            "1.0" => http::Version::HTTP_10,
+            "1.1" => http::Version::HTTP_11,
+            "2.0" | "2" => http::Version::HTTP_2,
+            "3.0" | "3" => anyhow::bail!("HTTP/3 is not supported yet."),
+            _ => anyhow::bail!("Unknown HTTP version. Valid versions are 0.9, 1.0, 1.1, 2."),
+        },
+        (false, None) => http::Version::HTTP_11,
+    };
+
+    if opts.expect_continue && http_version != http::Version::HTTP_11 {
+        anyhow::bail!("--expect-continue is only supported on HTTP/1.1");
+    }
+    if opts.keepalive_idle_timeout.is_some() && http_version == http::Version::HTTP_2 {
+        anyhow::bail!("--keepalive-idle-timeout is only supported on HTTP/1.x");
+    }
+    if opts.idle_workers == IdleWorkers::Park && http_version == http::Version::HTTP_2 {
+        anyhow::bail!("--idle-workers park is only supported on HTTP/1.x");
+    }
+    if opts.request_target == RequestTarget::Absolute && http_version == http::Version::HTTP_2 {
+        anyhow::bail!("--request-target absolute is only supported on HTTP/1.x. HTTP/2 sends the whole URL in pseudo-headers");
+    }
+    if opts.requests_per_connection.is_some_and(|n| n > 0) && http_version == http::Version::HTTP_2
+    {
+        anyhow::bail!("--requests-per-connection is only supported on HTTP/1.x. Use -c to set the number of connections instead");
+    }
+    if opts.arrival_distribution.is_some()
+        && opts.query_per_second.is_none()
+        && opts.arrival_rate.is_none()
+    {
+        anyhow::bail!("--arrival-distribution requires -q or --arrival-rate");
+    }
+    if opts.qps_burst && opts.query_per_second.is_none() && opts.qps_ramp.is_none() {
+        anyhow::bail!("--qps-burst requires -q or --qps-ramp");
+    }
+    if opts.wait_ongoing_requests_after_deadline
+        && opts.duration.is_none()
+        && opts.load_profile.is_none()
+    {
+        anyhow::bail!("--wait-ongoing-requests-after-deadline requires -z or --load-profile");
+    }
+    let requested_start = opts.start_at.or(opts
+        .start_in
+        .map(|d| std::time::SystemTime::now() + d.into()));
+    if let Some(requested_start) = requested_start {
+        start_at::instant(requested_start).context("--start-at")?;
+    }
+    // A new connection for each request
+    if opts.requests_per_connection == Some(1) {
+        opts.disable_keepalive = true;
+    }
+    if opts.disable_keepalive && http_version == http::Version::HTTP_2 {
+        anyhow::bail!("--disable-keepalive is not supported on HTTP/2. Use -c to set the number of connections instead");
+    }
+    if opts.raw_request.is_some() && http_version == http::Version::HTTP_2 {
+        anyhow::bail!("--raw-request is only supported on HTTP/1.x");
+    }
+
+    if http_version != http::Version::HTTP_2
+        && (opts.http2_connections.is_some()
+            || opts.http2_initial_window_size.is_some()
+            || opts.http2_max_frame_size.is_some())
+    {
+        anyhow::bail!(
+            "--http2-connections, --http2-initial-window-size and --http2-max-frame-size are only supported on HTTP/2"
+        );
+    }
+    if let Some(n) = opts.http2_connections {
+        opts.n_connections = n;
+    }
+    let http2_settings = client::Http2Settings {
+        initial_window_size: opts
+            .http2_initial_window_size
+            .map(|size| {
+                u32::try_from(size)
+                    .ok()
+                    .filter(|&size| size < 1 << 31)
+                    .context("--http2-initial-window-size must be at most 2^31-1")
+            })
+            .transpose()?,
+        max_frame_size: opts
+            .http2_max_frame_size
+            .map(|size| {
+                u32::try_from(size)
+                    .ok()
+                    .filter(|size| (16_384..=16_777_215).contains(size))
+                    .context("--http2-max-frame-size must be between 16384 and 16777215")
+            })
+            .transpose()?,
+    };
+
+    let seed = opts.rand_seed.unwrap_or_else(random);
+    let mut rng = client::seeded_rng(seed, client::SETUP_RNG_STREAM);
+
+    let (url_generator, url_userinfo) = if opts.rand_regex_url {
+        UrlGenerator::check_fixed_authority(&url_arg)?;
+        // Almost URL has dot in domain, so disable dot in regex for convenience.
+        let dot_disabled: String = url_arg
+            .chars()
+            .map(|c| {
+                if c == '.' {
+                    regex_syntax::escape(".")
+                } else {
+                    c.to_string()
+                }
+            })
+            .collect();
+        let regex = Regex::compile(&dot_disabled, opts.max_repeat).with_context(|| {
+            format!(
+                "invalid --rand-regex-url pattern {:?}",
+                url_generator::redact_userinfo(&url_arg)
+            )
+        })?;
+        // The authority is fixed, so any generated URL has the same userinfo
+        let userinfo = rng
+            .sample::<Result<String, _>, _>(&regex)
+            .ok()
+            .and_then(|sample| Url::parse(&sample).ok())
+            .and_then(|mut sample| url_generator::take_userinfo(&mut sample));
+        (UrlGenerator::new_dynamic(regex), userinfo)
+    } else {
+        let mut url = Url::parse(&url_arg).map_err(|e| match e {
+            url::ParseError::InvalidIpv6Address if url_arg.contains('%') => {
+                anyhow::anyhow!("IPv6 zone identifiers are not supported: {url_arg}")
+            }
+            _ if url_arg.is_empty() => {
+                anyhow::anyhow!("the URL is empty, expected one like http://localhost:8080/")
+            }
+            _ if url_arg.starts_with('/') => anyhow::anyhow!(
+                "the URL {url_arg:?} has no host, expected one like http://localhost:8080{url_arg}"
+            ),
+            url::ParseError::EmptyHost => anyhow::anyhow!(
+                "the URL {:?} has no host, expected one like http://localhost:8080/",
+                url_generator::redact_userinfo(&url_arg)
+            ),
+            e => anyhow::anyhow!(
+                "invalid URL {:?}: {e}",
+                url_generator::redact_userinfo(&url_arg)
+            ),
+        })?;
+        if !matches!(url.scheme(), "http" | "https" | "ws" | "wss") {
+            anyhow::bail!(
+                "unsupported scheme {:?} in the URL, expected http, https, ws or wss",
+                url.scheme()
+            );
+        }
+        let userinfo = url_generator::take_userinfo(&mut url);
+        url_generator::normalize(&mut url);
+        (UrlGenerator::new_static(url), userinfo)
+    };
+    // An explicit `-a` wins over the credentials in the URL
+    opts.basic_auth = opts.basic_auth.take().or(url_userinfo);
+
+    let url = url_generator.generate(&mut rng)?;
+    // --connect-host and --sni apply to it
+    let origin = url_generator::Authority::of(&url);
+    if opts.sni.is_some() && !matches!(url.scheme(), "https" | "wss") {
+        anyhow::bail!("--sni requires an https or wss URL");
+    }
+    if opts.io_backend == IoBackend::Uring {
+        check_uring(&opts, &url, http_version)?;
+    }
+    let url_list = opts
+        .urls_from_file
+        .as_deref()
+        .map(|path| url_list::UrlList::load(path, &url).map(std::sync::Arc::new))
+        .transpose()
+        .context("--urls-from-file")?;
+
+    let multipart_body = if opts.multipart.is_empty() {
+        None
+    } else {
+        let boundary = multipart::generate_boundary(&mut rng);
+        let body = multipart::build(&opts.multipart, &boundary)?;
+        Some((boundary, body))
+    };
+
+    let headers = {
+        let mut headers: http::header::HeaderMap = Default::default();
+
+        // Accept all
+        headers.insert(
+            http::header::ACCEPT,
+            http::header::HeaderValue::from_static("*/*"),
+        );
+
+        // https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Accept-Encoding
+        // Only the encodings which `--decompressed-size` can decode
+        if !opts.disable_compression {
+            headers.insert(http::header::ACCEPT_ENCODING, decompress::accept_encoding());
+        }
+
+        // User agent
+        headers
+            .entry(http::header::USER_AGENT)
+            .or_insert(HeaderValue::from_static(concat!(
+                "oha/",
+                env!("CARGO_PKG_VERSION")
+            )));
+
+        if let Some(auth) = opts.basic_auth {
+            headers.insert(
+                http::header::AUTHORIZATION,
+                header::basic_auth(&auth).context("basic auth")?,
+            );
+        }
+
+        if let Some(encoding) = opts.body_encoding {
+            headers.insert(http::header::CONTENT_ENCODING, encoding.header_value());
+        }
+
+        if opts.disable_keepalive && http_version == http::Version::HTTP_11 {
+            headers.insert(http::header::CONNECTION, HeaderValue::from_static("close"));
+        }
+
+        if let Some(path) = &opts.header_file {
+            let content = std::fs::read_to_string(path).map_err(|source| Error::File {
+                option: "--header-file",
+                path: path.clone(),
+                source,
+            })?;
+            for (k, v) in header::parse_file(&content)
+                .map_err(|e| anyhow::anyhow!("--header-file {}: {e}", path.display()))?
+            {
+                headers.insert(k, v);
+            }
+        }
+        for (i, s) in opts.headers.iter().enumerate() {
+            let (k, v) = header::parse(s).with_context(|| format!("-H #{} {s:?}", i + 1))?;
+            headers.insert(k, v);
+        }
+
+        // -A, -T and --host take precedence over -H, which takes precedence over the defaults
+        // from the body and the URL
+        if let Some(h) = opts.accept_header {
+            headers.insert(http::header::ACCEPT, HeaderValue::from_bytes(h.as_bytes())?);
+        }
+
+        if let Some(h) = opts.content_type {
+            headers.insert(
+                http::header::CONTENT_TYPE,
+                HeaderValue::from_bytes(h.as_bytes())?,
+            );
+        } else if !opts.form.is_empty() {
+            headers
+                .entry(http::header::CONTENT_TYPE)
+                .or_insert(HeaderValue::from_static(
+                    "application/x-www-form-urlencoded",
+                ));
+        } else if let Some((boundary, _)) = &multipart_body {
+            if !headers.contains_key(http::header::CONTENT_TYPE) {
+                headers.insert(
+                    http::header::CONTENT_TYPE,
+                    HeaderValue::from_str(&format!("multipart/form-data; boundary={boundary}"))?,
+                );
+            }
+        }
+
+        if let Some(h) = opts.host {
+            // Send an internationalized name as its A-label
+            let h = match h.rsplit_once(':') {
+                Some((host, port)) if !port.contains(']') => {
+                    format!(
+                        "{}:{port}",
+                        idn::to_ascii(host).map_err(anyhow::Error::msg)?
+                    )
+                }
+                _ => idn::to_ascii(&h).map_err(anyhow::Error::msg)?,
+            };
+            headers.insert(http::header::HOST, HeaderValue::from_bytes(h.as_bytes())?);
+        } else if http_version != http::Version::HTTP_2 && !headers.contains_key(http::header::HOST)
+        {
+            if let Some(host) = url_generator::host_header(&url) {
+                headers.insert(
+                    http::header::HOST,
+                    http::header::HeaderValue::from_str(&host)?,
+                );
+            }
+        }
+
+        headers
+    };
+
+    // A build without the tui feature always prints the summary only
+    let no_tui = !cfg!(feature = "tui")
+        || opts.no_tui
+        || opts.agent_output
+        || !std::io::IsTerminal::is_terminal(&std::io::stdout());
+    if !cfg!(feature = "tui") && opts.fps.is_some() {
+        eprintln!("warning: --fps is ignored, as oha was built without the tui feature");
+    }
+
+    // A large file is streamed for each request instead of being loaded
+    let file_body = match &opts.body_path {
+        Some(path)
+            if opts.body_string.is_none()
+                && opts.body_encoding.is_none()
+                && opts.body_reload_interval.is_none() =>
+        {
+            let file =
+                body::FileBody::open(path).with_context(|| format!("open {}", path.display()))?;
+            let stream = opts.chunked.is_some() || file.len() >= opts.body_stream_threshold;
+            if stream {
+                eprintln!(
+                    "Request body: {}, {} bytes streamed from the file",
+                    path.display(),
+                    file.len()
+                );
+            }
+            stream.then_some(file)
+        }
+        _ => None,
+    };
+    let body: Option<Bytes> = match (opts.body_string, &opts.body_path) {
+        (Some(body), _) => Some(body.into()),
+        _ if opts.body_bytes.is_some() => opts.body_bytes.map(Into::into),
+        (_, Some(_)) if file_body.is_some() => None,
+        (_, Some(path)) => {
+            let mut buf = Vec::new();
+            std::fs::File::open(path)
+                .and_then(|mut file| file.read_to_end(&mut buf))
+                .map_err(|source| Error::File {
+                    option: "-D",
+                    path: path.clone(),
+                    source,
+                })?;
+            Some(buf.into())
+        }
+        _ if !opts.form.is_empty() => Some(form_body(&opts.form)?.into()),
+        _ => multipart_body.map(|(_, body)| body.into()),
+    };
+
+    let body: Option<Bytes> = match (opts.body_encoding, body) {
+        (Some(encoding), Some(body)) => {
+            let compressed = encoding.compress(&body)?;
+            eprintln!(
+                "Request body: {} bytes, {} bytes with {:?}",
+                body.len(),
+                compressed.len(),
+                encoding
+            );
+            Some(compressed.into())
+        }
+        (Some(_), None) => anyhow::bail!("--body-encoding requires a request body"),
+        (None, body) => body,
+    };
+
+    let body = match (body, opts.body_path) {
+        (Some(body), Some(path)) if opts.body_reload_interval.is_some() => {
+            let encoding = opts.body_encoding;
+            let load = move || -> anyhow::Result<Bytes> {
+                let body =
+                    std::fs::read(&path).with_context(|| format!("read {}", path.display()))?;
+                Ok(match encoding {
+                    Some(encoding) => encoding.compress(&body)?,
+                    None => body,
+                }
+                .into())
+            };
+            let (tx, rx) = tokio::sync::watch::channel(body);
+            let interval = opts.body_reload_interval.unwrap().into();
+            tokio::spawn(body::reload(interval, tx, load, no_tui));
+            Some(BodySource::Reloaded(rx))
+        }
+        (Some(body), _) => Some(BodySource::Static(body)),
+        (None, Some(_)) => file_body.map(|file| BodySource::File(std::sync::Arc::new(file))),
+        (None, None) => match &opts.body_dir {
+            Some(dir) => Some(BodySource::Dir(std::sync::Arc::new(
+                body_dir::BodyDir::load(dir, opts.body_dir_order, Some(seed))?,
+            ))),
+            None => None,
+        },
+    };
+    let body_dir = match &body {
+        Some(BodySource::Dir(dir)) => Some(dir.clone()),
+        _ => None,
+    };
+
+    let websocket = if opts.ws || matches!(url.scheme(), "ws" | "wss") {
+        if http_version != http::Version::HTTP_11 {
+            anyhow::bail!("WebSocket is only supported on HTTP/1.1");
+        }
+        if opts.scenario.is_some() || opts.har.is_some() {
+            anyhow::bail!("WebSocket can't be used with --scenario or --har");
+        }
+        Some(websocket::WebSocketOptions {
+            payload: match &body {
+                Some(BodySource::Static(body)) => body.clone(),
+                Some(BodySource::File(file)) => std::fs::read(file.path())?.into(),
+                Some(BodySource::Dir(_)) => {
+                    anyhow::bail!("--body-dir can't be used with WebSocket")
+                }
+                Some(BodySource::Reloaded(_)) => {
+                    anyhow::bail!("--body-reload-interval can't be used with WebSocket")
+                }
+                None => Bytes::new(),
+            },
+            messages_per_connection: opts.ws_messages_per_connection,
+            ping_interval: opts.ws_ping_interval.map(|d| d.into()),
+        })
+    } else {
+        if opts.ws_messages_per_connection.is_some() || opts.ws_ping_interval.is_some() {
+            anyhow::bail!(
+                "--ws-messages-per-connection and --ws-ping-interval are only supported with WebSocket"
+            );
+        }
+        None
+    };
+
+    let raw_request = match &opts.raw_request {
+        Some(path) => {
+            if websocket.is_some() {
+                anyhow::bail!("--raw-request can't be used with WebSocket");
+            }
+            let template = std::fs::read(path).map_err(|source| Error::File {
+                option: "--raw-request",
+                path: path.clone(),
+                source,
+            })?;
+            Some(
+                raw_request::RawRequest::parse(&template)
+                    .map_err(|e| anyhow::anyhow!("--raw-request {}: {e}", path.display()))?,
+            )
+        }
+        None => None,
+    };
+
+    let method = opts
+        .method
+        .unwrap_or(if opts.form.is_empty() && opts.multipart.is_empty() {
+            http::Method::GET
+        } else {
+            http::Method::POST
+        });
+
+    if let Some(method_mix) = &opts.method_mix {
+        if body.is_some() && method_mix.methods().all(method_mix::is_bodyless) {
+            anyhow::bail!(
+                "the request body is never sent because all methods of --method-mix are sent without a body"
+            );
+        }
+    }
+
+    let scenario = match (&opts.scenario, &opts.har) {
+        (Some(path), _) => Some(scenario::Scenario::load(path)?),
+        (None, Some(path)) => Some(har::load(
+            path,
+            &url,
+            &har::HarOptions {
+                order: opts.har_order,
+                strip_cookies: opts.har_strip_cookies,
+                any_origin: opts.har_any_origin,
+            },
+        )?),
+        (None, None) => None,
+    };
+
+    let body_assertion = (opts.assert_body_contains.is_some() || opts.assert_body_regex.is_some())
+        .then(|| body_assert::BodyAssertion {
+            contains: opts.assert_body_contains.map(String::into_bytes),
+            regex: opts.assert_body_regex,
+            max_bytes: opts.max_assert_body_bytes as usize,
+        });
+
+    let load_profile = match &opts.load_profile {
+        Some(path) => {
+            let load_profile = load_profile::LoadProfile::load(path)
+                .with_context(|| format!("--load-profile {}", path.display()))?;
+            opts.duration = Some(load_profile.duration().into());
+            Some(std::sync::Arc::new(load_profile))
+        }
+        None => None,
+    };
+    // The rate of -q is where --target-p99 starts from
+    let target_p99 = opts.target_p99.map(|target| {
+        let qps = *opts.query_per_second.get_or_insert(target_p99::DEFAULT_QPS);
+        std::sync::Arc::new(target_p99::TargetP99::new(
+            target.into(),
+            qps,
+            opts.latency_includes,
+        ))
+    });
+    // -n is ignored with -z
+    let forever = opts.duration.is_none() && (opts.forever || opts.n_requests == 0);
+    if forever {
+        opts.n_requests = u64::MAX;
+    }
+    // The agents of --workers lower their own -c
+    if opts.duration.is_none() && opts.workers.is_none() {
+        let n_connections = client::connections_for(
+            opts.n_requests,
+            opts.n_connections,
+            if http_version == http::Version::HTTP_2 {
+                opts.n_http2_parallel
+            } else {
+                1
+            },
+        );
+        if n_connections < opts.n_connections {
+            eprintln!(
+                "note: -c {} is lowered to {n_connections}, as there are only {} requests",
+                opts.n_connections, opts.n_requests
+            );
+            opts.n_connections = n_connections;
+        }
+    }
+    // The agents of --workers check their own limit
+    if opts.workers.is_none() {
+        if !opts.no_rlimit_bump {
+            fd_limit::raise();
+        }
+        if let Err(e) = fd_limit::check(opts.n_connections, fd_limit::soft_limit()) {
+            anyhow::bail!(e);
+        }
+    }
+    let qps_ramp = opts
+        .qps_ramp
+        .zip(opts.duration)
+        .map(|((from, to), duration)| qps_ramp::QpsRamp {
+            from,
+            to,
+            duration: duration.into(),
+        });
+    let abort = (opts.abort_on_error_rate.is_some()
+        || opts.abort_on_first_error
+        || opts.max_bytes.is_some())
+    .then(|| {
+        std::sync::Arc::new(abort::Abort::new(
+            opts.abort_on_error_rate.map(|percent| abort::ErrorRate {
+                percent,
+                window: opts.abort_window.into(),
+            }),
+            opts.abort_on_first_error,
+            opts.expect_status.is_some(),
+            opts.max_bytes,
+        ))
+    });
+    let workers = opts.n_connections * opts.n_http2_parallel;
+    let sampling = opts
+        .sample_rate
+        .map(|every| std::sync::Arc::new(sampling::Sampling::new(every)));
+    let (result_tx, result_rx, backpressure) = result_channel::channel(workers, sampling.clone());
+    if opts.workers.is_none() && workers > runtime_threads * WORKERS_PER_THREAD {
+        eprintln!(
+            "warning: {workers} workers are more than {WORKERS_PER_THREAD} for each runtime thread (--runtime-threads {runtime_threads}), so requests may be sent late"
+        );
+    }
+    // Prewarmed connections are reported on their own
+    let startup_connects = (opts.workers.is_none()
+        && (!opts.prewarm_connections || opts.disable_keepalive))
+        .then(|| std::sync::Arc::new(connect_rate::StartupConnects::new(opts.n_connections)));
+    let labels = std::sync::Arc::new(label::Labels::default());
+    let mut print_config = printer::PrintConfig {
+        mode: if opts.json {
+            PrintMode::Json
+        } else {
+            PrintMode::Text
+        },
+        disable_color: opts.disable_color,
+        stats_success_breakdown: opts.stats_success_breakdown,
+        per_method_stats: opts.per_method_stats,
+        per_url_stats: opts.per_url_stats,
+        assert_body: body_assertion.is_some(),
+        expect_status: opts.expect_status.is_some(),
+        max_body_bytes: opts.max_body_bytes.is_some(),
+        timeout: opts.timeout.is_some(),
+        decompressed_size: opts.decompressed_size,
+        prewarm: None,
+        startup_connects: startup_connects.clone(),
+        connect_rate: opts.connect_rate,
+        start_at: requested_start.map(|requested| printer::StartAt {
+            requested,
+            actual: Default::default(),
+        }),
+        tcp_keepalive: opts.tcp_keepalive.map(|d| d.into()),
+        http2_counters: None,
+        idn_host: url.host_str().and_then(idn::display),
+        ip_families: None,
+        websocket_counters: None,
+        body_dir,
+        keepalive_counters: None,
+        latency_includes: opts.latency_includes,
+        retry_after_counters: None,
+        // --target-p99 changes the rate
+        qps: opts
+            .query_per_second
+            .or(opts.arrival_rate)
+            .filter(|_| target_p99.is_none()),
+        qps_ramp,
+        load_profile: load_profile.clone(),
+        burst_releases: None,
+        ramp_up: None,
+        arrival_counters: None,
+        arrival_distribution: opts.arrival_distribution,
+        think_time: None,
+        duty_cycle: None,
+        pause: None,
+        abort: abort.clone(),
+        run_limits: opts.max_bytes.map(|max_bytes| printer::RunLimits {
+            n_requests: (opts.duration.is_none() && !forever).then_some(opts.n_requests),
+            duration: opts.duration.map(Into::into),
+            max_bytes,
+        }),
+        seed,
+        runtime_threads,
+        target_p99: target_p99.clone(),
+        test_duration: opts
+            .duration
+            .filter(|_| opts.wait_ongoing_requests_after_deadline)
+            .map(Into::into),
+        agents: opts
+            .workers
+            .as_ref()
+            .map(|workers| workers.iter().map(|w| agent::Agent::new(w)).collect()),
+        url_list: url_list.clone(),
+        per_step_stats: scenario.as_ref().is_some_and(|scenario| scenario.strict),
+        labels,
+        backpressure,
+        histogram_precision: opts.histogram_precision,
+        sampling,
+        self_monitor: opts
+            .workers
+            .is_none()
+            .then(|| std::sync::Arc::new(self_monitor::SelfMonitor::new(runtime_threads))),
+        shutdown: shutdown.clone(),
+    };
+
+    let result_rx = if opts.wait_ongoing_requests_after_deadline {
+        // The workers report a deadline error for the request they don't start after the deadline
+        let (tx, rx) = flume::bounded(result_channel::capacity(workers));
+        let abort = abort.clone();
+        tokio::spawn(async move {
+            while let Ok(res) = result_rx.recv_async().await {
+                let not_started = matches!(
+                    res,
+                    result_channel::Report::Result(Err(ClientError::Deadline))
+                ) && !abort.as_ref().is_some_and(|abort| abort.is_aborted());
+                if !not_started && tx.send_async(res).await.is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    } else {
+        result_rx
+    };
+
+    let ip_version = match (opts.ip_version, opts.ipv4, opts.ipv6) {
+        (Some(_), true, _) | (Some(_), _, true) => anyhow::bail!(
+            "--ipv4 and --ipv6 can't be used with --ip-version, which replaces them. Use --ip-version alone, with 4, 6, dual-prefer-4, dual-prefer-6 or auto"
+        ),
+        (Some(ip_version), false, false) => ip_version,
+        (None, true, false) => IpVersion::V4,
+        (None, false, true) => IpVersion::V6,
+        // Both families, as before --ip-version
+        (None, true, true) => IpVersion::DualPrefer6,
+        (None, false, false) => IpVersion::Auto,
+    };
+    // Both families are looked up for Happy Eyeballs unless one is forced
+    let ip_strategy = match ip_version {
+        IpVersion::V4 => hickory_resolver::config::LookupIpStrategy::Ipv4Only,
+        IpVersion::V6 => hickory_resolver::config::LookupIpStrategy::Ipv6Only,
+        _ => hickory_resolver::config::LookupIpStrategy::Ipv4AndIpv6,
+    };
+    let prefer_ipv4 = match ip_version {
+        IpVersion::V4 | IpVersion::DualPrefer4 => true,
+        IpVersion::V6 | IpVersion::DualPrefer6 => false,
+        IpVersion::Auto => !client::has_ipv6_route(),
+    };
+    let (config, _) = hickory_resolver::system_conf::read_system_conf()
+        .context("DNS: failed to load /etc/resolv.conf")?;
+    let mut resolver_opts = hickory_resolver::config::ResolverOpts::default();
+    resolver_opts.ip_strategy = ip_strategy;
+    let resolver = hickory_resolver::AsyncResolver::tokio(config, resolver_opts);
+
+    let (save_responses, save_writer) = match opts.save_responses {
+        Some(dir) => {
+            let (saver, writer) = save_responses::ResponseSaver::new(
+                dir.clone(),
+                opts.save_count,
+                opts.save_errors_only,
+            )
+            .with_context(|| format!("--save-responses: failed to create {}", dir.display()))?;
+            (Some(saver), Some(writer))
+        }
+        None => (None, None),
+    };
+
+    let read_pem = |option: &'static str, path: &std::path::Path| {
+        std::fs::read(path).map_err(|source| Error::File {
+            option,
+            path: path.to_path_buf(),
+            source,
+        })
+    };
+    let tls = tls::Connector::new(tls::TlsOptions {
+        insecure: opts.insecure,
+        http2: http_version == http::Version::HTTP_2,
+        ca_certs: opts
+            .cacert
+            .as_deref()
+            .map(|path| read_pem("--cacert", path))
+            .transpose()?,
+        client_cert: match (&opts.cert, &opts.key) {
+            (Some(cert), Some(key)) => Some((read_pem("--cert", cert)?, read_pem("--key", key)?)),
+            _ => None,
+        },
+        sni: origin
+            .clone()
+            .zip(opts.sni.clone())
+            .map(|(origin, name)| tls::Sni { origin, name }),
+        #[cfg(feature = "rustls")]
+        session_resumption: opts.tls_session_resumption == crate::opts::TlsSessionResumption::On,
+    })
+    .map_err(|e| Error::Tls(format!("{e:#}")))?;
+
+    // client_builder builds client for each workers
+    let client = client::Client {
+        http_version,
+        url_generator,
+        cache_bust: opts.cache_bust,
+        url_list,
+        method,
+        method_mix: opts.method_mix,
+        scenario,
+        record_url: opts.per_url_stats,
+        labels: print_config.labels.clone(),
+        headers,
+        request_headers: Default::default(),
+        body,
+        chunk_size: opts.chunked.map(|size| size as usize),
+        informational_stats: opts.informational_stats,
+        expect_continue_timeout: opts
+            .expect_continue
+            .then(|| opts.expect_continue_timeout.into()),
+        dns: client::Dns {
+            resolver,
+            connect_to: opts.connect_to,
+            connect_host: origin.clone().zip(opts.connect_host),
+            retries: opts.dns_retries,
+            retry_interval: opts.dns_retry_interval.into(),
+            lookups: Default::default(),
+            prefer_ipv4,
+        },
+        connect_rate: opts.connect_rate.map(connect_rate::ConnectRate::new),
+        startup_connects,
+        timeout: opts.timeout.map(|d| d.into()),
+        read_timeout: opts.read_timeout.map(|d| d.into()),
+        redirect_limit: opts.redirect,
+        disable_keepalive: opts.disable_keepalive,
+        absolute_form: opts.request_target == RequestTarget::Absolute,
+        uri_cache: Default::default(),
+        keepalive_idle_timeout: opts.keepalive_idle_timeout.map(|d| d.into()),
+        park_idle_workers: opts.idle_workers == IdleWorkers::Park,
+        // 1 is --disable-keepalive and 0 is unlimited
+        requests_per_connection: opts.requests_per_connection.filter(|&n| n > 1),
+        keepalive_counters: Default::default(),
+        tls,
+        #[cfg(unix)]
+        unix_socket: opts.unix_socket,
+        #[cfg(all(feature = "uring", target_os = "linux"))]
+        uring_threads: (opts.io_backend == IoBackend::Uring).then_some(runtime_threads),
+        seed,
+        body_assertion,
+        expect_status: opts.expect_status,
+        max_body_bytes: opts.max_body_bytes.map(|size| size as usize),
+        decompressed_size: opts.decompressed_size,
+        save_responses,
+        tcp_keepalive: opts.tcp_keepalive.map(|d| d.into()),
+        prewarmed: Default::default(),
+        http2_settings,
+        http2_max_retries: opts.http2_max_retries,
+        http2_connections: Default::default(),
+        http2_counters: Default::default(),
+        ip_families: std::sync::Arc::new(client::IpFamilies {
+            prefer_ipv4,
+            ..Default::default()
+        }),
+        websocket,
+        websocket_counters: Default::default(),
+        raw_request,
+        prebuilt_requests: opts.prebuilt_requests,
+        prebuilt: Default::default(),
+        respect_retry_after: opts.respect_retry_after,
+        retry_after_counters: Default::default(),
+        burst_releases: Default::default(),
+        ramp_up: match (opts.ramp_up, opts.stagger) {
+            (Some(duration), _) => Some(std::sync::Arc::new(ramp_up::RampUp::new(
+                duration.into(),
+                opts.n_connections,
+            ))),
+            (None, Some(duration)) => Some(std::sync::Arc::new(ramp_up::RampUp::stagger(
+                duration.into(),
+                opts.n_connections,
+                opts.stagger_exclude,
+                &mut client::seeded_rng(seed, client::STAGGER_RNG_STREAM),
+            ))),
+            (None, None) => None,
+        },
+        arrival_counters: Default::default(),
+        think_time: opts.think_time.map(|duration| {
+            std::sync::Arc::new(think_time::ThinkTime::new(
+                duration.into(),
+                opts.think_time_jitter,
+            ))
+        }),
+        duty_cycle: opts.on.zip(opts.off).map(|(on, off)| {
+            std::sync::Arc::new(duty_cycle::DutyCycle::new(on.into(), off.into()))
+        }),
+        arrival_distribution: opts.arrival_distribution.unwrap_or_default(),
+        qps_burst: opts.qps_burst,
+        wait_ongoing_requests_after_deadline: opts.wait_ongoing_requests_after_deadline,
+        target_p99: target_p99.clone(),
+        pause: cfg!(unix).then(Default::default),
+        abort: abort.clone(),
+        shutdown: shutdown.clone(),
+    };
+
+    if http_version == http::Version::HTTP_2 {
+        print_config.http2_counters = Some(client.http2_counters.clone());
+    } else {
+        print_config.keepalive_counters = Some(client.keepalive_counters.clone());
+    }
+    print_config.ip_families = Some(client.ip_families.clone());
+    if client.websocket.is_some() {
+        print_config.websocket_counters = Some(client.websocket_counters.clone());
+    }
+    if client.respect_retry_after {
+        print_config.retry_after_counters = Some(client.retry_after_counters.clone());
+    }
+    // -q takes precedence over the bursts
+    if opts.burst_duration.is_some() && opts.query_per_second.is_none() {
+        print_config.burst_releases = Some(client.burst_releases.clone());
+    }
+    print_config.ramp_up = client.ramp_up.clone();
+    print_config.think_time = client.think_time.clone();
+    print_config.duty_cycle = client.duty_cycle.clone();
+    print_config.pause = client.pause.clone();
+    if opts.arrival_rate.is_some() {
+        print_config.arrival_counters = Some(client.arrival_counters.clone());
+    }
+    if opts.sample_rate.is_some() && print_config.keeps_results() {
+        anyhow::bail!("--sample-rate can't be combined with the statistics which need every result: --per-method-stats, --per-url-stats, a strict scenario, --load-profile, --on and --off, --qps-ramp, --target-p99, --burst-delay or an excluded ramp-up");
+    }
+
+    // The agents do the setup themselves. The threads of io_uring use the lookups made here.
+    if (opts.pre_lookup || opts.io_backend == IoBackend::Uring) && opts.workers.is_none() {
+        // A host which can't be resolved at all fails the run once instead of every request
+        client.pre_lookup().await.map_err(Error::Resolve)?;
+    }
+
+    if opts.prewarm_connections && opts.workers.is_none() {
+        if opts.disable_keepalive {
+            eprintln!("--prewarm-connections is ignored because of --disable-keepalive");
+        } else {
+            let prewarm_start = std::time::Instant::now();
+            let connections = client
+                .prewarm(opts.n_connections, opts.prewarm_method.as_ref())
+                .await;
+            print_config.prewarm = Some(printer::Prewarm {
+                connections,
+                duration: prewarm_start.elapsed(),
+            });
+        }
+    }
+
+    // The setup is over. The run starts at --start-at.
+    let start = match &print_config.start_at {
+        Some(start_at) => {
+            start_at::instant(start_at.requested).context("the setup finished after --start-at")?
+        }
+        None => std::time::Instant::now(),
+    };
+
+    #[cfg(unix)]
+    if let Some(pause) = print_config.pause.clone() {
+        // The TUI shows the pauses instead of the log
+        tokio::spawn(pause::listen(pause, start, no_tui));
+    }
+    if let Some(target_p99) = target_p99 {
+        tokio::spawn(target_p99.run(start));
+    }
+
+    let data_collector = if opts.agent_output {
+        let aggregate = aggregate::ResultAggregate::new(&print_config, start);
+        tokio::spawn(
+            agent::stream_results(result_rx, print_config.clone(), start)
+                .map(|res| res.map(|()| aggregate)),
+        )
+        .boxed()
+    } else if no_tui {
+        // When `--no-tui` is enabled, just fold the results into the summary.
+        let print_config = print_config.clone();
+        // Shared with the panic hook
+        let all = std::sync::Arc::new(std::sync::Mutex::new(aggregate::ResultAggregate::new(
+            &print_config,
+            start,
+        )));
+        let panic_all = all.clone();
+        let panic_config = print_config.clone();
+        partial_summary.set(print_config.clone(), start, move || {
+            let mut all = panic_all.try_lock().ok()?;
+            Some(std::mem::replace(
+                &mut *all,
+                aggregate::ResultAggregate::new(&panic_config, start),
+            ))
+        });
+        let take = move |all: &std::sync::Mutex<aggregate::ResultAggregate>,
+                         print_config: &printer::PrintConfig| {
+            std::mem::replace(
+                &mut *all.lock().unwrap(),
+                aggregate::ResultAggregate::new(print_config, start),
+            )
+        };
+        tokio::spawn(
+            async move {
+                let mut abort_checker = print_config.abort.as_ref().map(abort::Abort::checker);
+                while let Ok(report) = result_rx.recv_async().await {
+                    let mut all = all.lock().unwrap();
+                    // The ones which came meanwhile are taken under the same lock
+                    for report in std::iter::once(report).chain(result_rx.drain()) {
+                        let report = match report {
+                            result_channel::Report::Result(report) => report,
+                            // The agents check their own limits
+                            result_channel::Report::Folded(folded) => {
+                                all.merge(&folded);
+                                continue;
+                            }
+                        };
+                        if let Some(checker) = &mut abort_checker {
+                            checker.record(&report);
+                        }
+                        if let Some(target_p99) = &print_config.target_p99 {
+                            target_p99.record(&report);
+                        }
+                        all.push(report);
+                    }
+                }
+                take(&all, &print_config)
+            }
+            .map(Ok),
+        )
+        .boxed()
+    } else {
+        #[cfg(not(feature = "tui"))]
+        unreachable!("--no-tui is implied without the tui feature");
+        // Spawn monitor future which draws realtime tui
+        #[cfg(feature = "tui")]
+        tokio::spawn(
+            monitor::Monitor {
+                print_config: print_config.clone(),
+                end_line: match opts.duration {
+                    Some(d) => monitor::EndLine::Duration(d.into()),
+                    None if forever => monitor::EndLine::Infinite,
+                    // Each agent sends -n requests
+                    None => monitor::EndLine::NumQuery(
+                        opts.n_requests.saturating_mul(
+                            print_config
+                                .agents
+                                .as_ref()
+                                .map_or(1, |agents| agents.len() as u64),
+                        ),
+                    ),
+                },
+                report_receiver: result_rx,
+                start,
+                fps: opts.fps.unwrap_or(16),
+                shutdown: shutdown.clone(),
+                partial_summary: partial_summary.clone(),
+            }
+            .monitor(),
+        )
+        .boxed()
+    };
+    if let Some(start_at) = &print_config.start_at {
+        let wait = async {
+            if no_tui {
+                start_at::countdown(start, start_at.requested).await;
+            }
+            tokio::time::sleep_until(start.into()).await;
+        };
+        // Stopped before the start, the workers send nothing
+        tokio::select! {
+            _ = wait => {
+                let _ = start_at.actual.set(std::time::SystemTime::now());
+            }
+            _ = shutdown.stopped() => {}
+        }
+    }
+    if let Some(self_monitor) = &print_config.self_monitor {
+        self_monitor.spawn();
+    }
+
+    if let (Some(agents), Some(job)) = (print_config.agents.clone(), job) {
+        // The agents end their runs when the controller is gone
+        tokio::select! {
+            _ = agent::control(agents, job, result_tx) => {}
+            _ = shutdown.grace_over() => {}
+        }
+    } else {
+        let dead_line = match &load_profile {
+            Some(load_profile) => Some(start + load_profile.duration()),
+            None => opts.duration.take().map(|duration| start + duration.into()),
+        };
+        let workload = client::Workload {
+            // -n is ignored with -z
+            n_tasks: if dead_line.is_some() {
+                u64::MAX
+            } else {
+                opts.n_requests
+            },
+            dead_line,
+            n_connections: opts.n_connections,
+            n_http2_parallel: opts.n_http2_parallel,
+        };
+        let rate = match qps_ramp {
+            Some(qps_ramp) if dead_line.is_some() => Some(client::Rate::Ramp(qps_ramp)),
+            _ => opts.query_per_second.map(client::Rate::Fixed),
+        };
+        let query_limit = if let Some(load_profile) = load_profile {
+            Some(client::QueryLimit::Profile(load_profile))
+        } else if let Some(rate) = rate {
+            Some(client::QueryLimit::Qps(rate))
+        } else {
+            opts.burst_duration.map(|burst_duration| {
+                client::QueryLimit::Burst(burst_duration.into(), opts.burst_requests.unwrap_or(1))
+            })
+        };
+
+        if let Some(arrival_rate) = opts.arrival_rate {
+            client::work_with_arrival_rate(
+                client,
+                result_tx,
+                workload,
+                arrival_rate,
+                opts.max_in_flight as usize,
+            )
+            .await
+        } else if let Some(query_limit) = query_limit {
+            client::work_with_qps(
+                client,
+                result_tx,
+                workload,
+                query_limit,
+                start,
+                opts.latency_correction,
+            )
+            .await
+        } else {
+            client::work(client, result_tx, workload).await
+        }
+    }
+
+    let duration = start.elapsed();
+    if let Some(self_monitor) = &print_config.self_monitor {
+        self_monitor.stop();
+    }
+
+    let aggregate = data_collector
+        .await
+        .map_err(|e| Error::Collector(e.to_string()))?
+        .map_err(|e| Error::Collector(e.to_string()))?;
+    partial_summary.finish();
+
+    if let Some(save_writer) = save_writer {
+        save_writer
+            .await
+            .map_err(std::io::Error::other)?
+            .context("--save-responses")
+            .map_err(|e| Error::Io(std::io::Error::other(format!("{e:#}"))))?;
+    }
+
+    Ok(RunReport {
+        print_config,
+        start,
+        aggregate,
+        duration,
+        abort,
+        agent_output: opts.agent_output,
+    })
+}
+
+/// `--io-backend uring` only runs plain HTTP/1.x as fast as it goes, with `-n` or `-z`
+fn check_uring(opts: &Opts, url: &Url, http_version: http::Version) -> anyhow::Result<()> {
+    if !cfg!(all(feature = "uring", target_os = "linux")) {
+        anyhow::bail!("--io-backend uring needs Linux and oha built with the uring feature");
+    }
+    if http_version == http::Version::HTTP_2 {
+        anyhow::bail!("--io-backend uring doesn't support HTTP/2");
+    }
+    if matches!(url.scheme(), "https" | "wss") {
+        anyhow::bail!("--io-backend uring doesn't support TLS. Use --io-backend tokio for HTTPS");
+    }
+    if opts.ws || url.scheme() == "ws" {
+        anyhow::bail!("--io-backend uring doesn't support WebSocket");
+    }
+    #[cfg(unix)]
+    if opts.unix_socket.is_some() {
+        anyhow::bail!("--io-backend uring doesn't support --unix-socket");
+    }
+    if opts.query_per_second.is_some()
+        || opts.burst_duration.is_some()
+        || opts.arrival_rate.is_some()
+        || opts.load_profile.is_some()
+        || opts.qps_ramp.is_some()
+        || opts.target_p99.is_some()
+    {
+        anyhow::bail!("--io-backend uring doesn't support -q, --burst-delay, --arrival-rate, --load-profile, --qps-ramp or --target-p99");
+    }
+    if opts.workers.is_some() || opts.prewarm_connections {
+        anyhow::bail!("--io-backend uring doesn't support --workers or --prewarm-connections");
+    }
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    crate::uring::probe().context("io_uring isn't available")?;
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+    aggregate::ResultAggregate,
+    client::{ClientError, RequestResult},
+};
+
+/// `--sample-rate 1/N`: only every Nth result goes to the collector, which weighs it as N results,
+/// while the totals of requests, errors and bytes are counted exactly for all of them. Which
+/// results are sent depends on a counter, so that there's no random number for each request.
+pub struct Sampling {
+    every: u64,
+    /// Every result, failed or not
+    requests: AtomicU64,
+    /// Like `ResultAggregate::counted`
+    counted: AtomicU64,
+    /// Like `ResultAggregate::succeeded`
+    succeeded: AtomicU64,
+    /// Body bytes as received
+    bytes: AtomicU64,
+}
+
+impl Sampling {
+    pub fn new(every: u64) -> Self {
+        Self {
+            every,
+            requests: AtomicU64::new(0),
+            counted: AtomicU64::new(0),
+            succeeded: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// N of 1/N
+    pub fn every(&self) -> u64 {
+        self.every
+    }
+
+    /// Count `report` in the totals. True when it's the one of its N to send to the collector.
+    pub fn count(&self, report: &Result<RequestResult, ClientError>) -> bool {
+        let seq = self.requests.fetch_add(1, Ordering::Relaxed);
+        if !matches!(report, Err(ClientError::Deadline | ClientError::Aborted)) {
+            self.counted.fetch_add(1, Ordering::Relaxed);
+            if matches!(report, Ok(r) if !r.unexpected_status) {
+                self.succeeded.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        if let Ok(r) = report {
+            self.bytes.fetch_add(r.len_bytes, Ordering::Relaxed);
+        }
+        seq.is_multiple_of(self.every)
+    }
+
+    /// The results sent to the collector so far
+    pub fn sampled(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed).div_ceil(self.every)
+    }
+
+    /// Replace the totals which `aggregate` estimated from the sample with the exact ones
+    pub fn correct(&self, aggregate: &mut ResultAggregate) {
+        aggregate.count = self.requests.load(Ordering::Relaxed) as usize;
+        aggregate.counted = self.counted.load(Ordering::Relaxed) as usize;
+        aggregate.succeeded = self.succeeded.load(Ordering::Relaxed) as usize;
+        aggregate.bytes = u128::from(self.bytes.load(Ordering::Relaxed));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling() {
+        let sampling = Sampling::new(3);
+        let sent: Vec<bool> = (0..7)
+            .map(|_| sampling.count(&Err(ClientError::Timeout)))
+            .collect();
+        assert_eq!(sent, [true, false, false, true, false, false, true]);
+        assert_eq!(sampling.sampled(), 3);
+        assert!(!sampling.count(&Err(ClientError::Deadline)));
+
+        let mut aggregate = ResultAggregate::with_options(
+            std::time::Instant::now(),
+            crate::client::LatencyIncludes::All,
+            None,
+            false,
+            crate::histogram::DEFAULT_PRECISION,
+        );
+        sampling.correct(&mut aggregate);
+        assert_eq!(aggregate.count, 8);
+        assert_eq!(aggregate.counted, 7);
+        assert_eq!(aggregate.succeeded, 0);
+        assert_eq!(aggregate.bytes, 0);
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use hyper::http::{HeaderMap, StatusCode};
+
+use crate::body_assert;
+
+/// A response captured by `--save-responses`
+pub struct SavedResponse {
+    index: usize,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    truncated: bool,
+    /// Why the request failed, if it did
+    error: Option<String>,
+}
+
+/// Captures the first responses and passes them to a writer task, so that
+/// the files are written off the hot path.
+pub struct ResponseSaver {
+    tx: flume::Sender<SavedResponse>,
+    count: usize,
+    next: AtomicUsize,
+    errors_only: bool,
+}
+
+impl ResponseSaver {
+    /// Create a saver and the writer task which writes the responses into `dir`.
+    pub fn new(
+        dir: PathBuf,
+        count: usize,
+        errors_only: bool,
+    ) -> std::io::Result<(Self, tokio::task::JoinHandle<std::io::Result<()>>)> {
+        std::fs::create_dir_all(&dir)?;
+        let (tx, rx) = flume::unbounded::<SavedResponse>();
+        let writer = tokio::task::spawn_blocking(move || {
+            for response in rx.iter() {
+                response.write(&dir)?;
+            }
+            Ok(())
+        });
+        Ok((
+            Self {
+                tx,
+                count,
+                next: AtomicUsize::new(0),
+                errors_only,
+            },
+            writer,
+        ))
+    }
+
+    /// Whether more responses may be captured, so their bodies should be buffered.
+    pub fn is_capturing(&self) -> bool {
+        self.next.load(Ordering::Relaxed) < self.count
+    }
+
+    /// Whether a response is a target of `--save-errors-only`.
+    pub fn wants(&self, status: StatusCode, failed: bool) -> bool {
+        !self.errors_only || failed || !status.is_success()
+    }
+
+    /// Capture a response if the count isn't reached yet.
+    pub fn save(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &[u8],
+        truncated: bool,
+        error: Option<String>,
+    ) {
+        let index = self.next.fetch_add(1, Ordering::Relaxed);
+        if index >= self.count {
+            return;
+        }
+        let _ = self.tx.send(SavedResponse {
+            index,
+            status,
+            headers: headers.clone(),
+            body: body.to_vec(),
+            truncated,
+            error,
+        });
+    }
+}
+
+impl SavedResponse {
+    /// Write `<n>.headers` and `<n>.body`. The body is decoded by its `Content-Encoding`.
+    fn write(&self, dir: &Path) -> std::io::Result<()> {
+        let name = format!("{:06}", self.index + 1);
+        let (body, decode_error) = body_assert::decode(
+            self.headers.get(hyper::http::header::CONTENT_ENCODING),
+            &self.body,
+            usize::MAX,
+        );
+
+        let mut head = std::fs::File::create(dir.join(format!("{name}.headers")))?;
+        writeln!(head, "{}", self.status)?;
+        for (name, value) in &self.headers {
+            writeln!(
+                head,
+                "{}: {}",
+                name,
+                String::from_utf8_lossy(value.as_bytes())
+            )?;
+        }
+        if self.truncated {
+            writeln!(head, "# The body is truncated")?;
+        }
+        if let Some(error) = decode_error {
+            writeln!(head, "# Failed to decode the body: {error}")?;
+        }
+        if let Some(error) = &self.error {
+            writeln!(head, "# {error}")?;
+        }
+
+        std::fs::write(dir.join(format!("{name}.body")), body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save_responses() {
+        let dir = tempfile::tempdir().unwrap();
+        let (saver, writer) = ResponseSaver::new(dir.path().into(), 2, true).unwrap();
+
+        assert!(!saver.wants(StatusCode::OK, false));
+        assert!(saver.wants(StatusCode::OK, true));
+        assert!(saver.wants(StatusCode::NOT_FOUND, false));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", "text/plain".parse().unwrap());
+        for i in 0..3 {
+            assert_eq!(saver.is_capturing(), i < 2);
+            saver.save(
+                StatusCode::NOT_FOUND,
+                &headers,
+                format!("body {i}").as_bytes(),
+                false,
+                Some("assertion failed".to_string()),
+            );
+        }
+        drop(saver);
+        writer.await.unwrap().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("000002.body")).unwrap(),
+            "body 1"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("000001.headers")).unwrap(),
+            "404 Not Found\ncontent-type: text/plain\n# assertion failed\n"
+        );
+        assert!(!dir.path().join("000003.body").exists());
+    }
+}
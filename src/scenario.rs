@@ -0,0 +1,393 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use hyper::http::{HeaderName, HeaderValue, Method};
+use serde::Deserialize;
+use thiserror::Error;
+
+/// A scenario file given by `--scenario`.
+///
+/// ```toml
+/// [[step]]
+/// name = "login"
+/// method = "POST"
+/// path = "/login"
+/// headers = { content-type = "application/json" }
+/// body = '{"user": "oha"}'
+/// extract = { token = { json = "/token" } }
+///
+/// [[step]]
+/// path = "/dashboard"
+/// headers = { authorization = "Bearer {{token}}" }
+/// ```
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    #[serde(rename = "step")]
+    steps: Vec<StepFile>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct StepFile {
+    name: Option<String>,
+    method: Option<String>,
+    path: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    body: Option<String>,
+    #[serde(default)]
+    extract: BTreeMap<String, ExtractFile>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields, rename_all = "lowercase")]
+enum ExtractFile {
+    /// JSON pointer e.g. `/data/0/id`
+    Json(String),
+    /// Regex whose first capture group is extracted
+    Regex(String),
+}
+
+#[derive(Error, Debug)]
+pub enum ScenarioError {
+    #[error("failed to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("failed to parse {0}: {1}")]
+    Toml(PathBuf, toml::de::Error),
+    #[error("scenario has no step")]
+    Empty,
+    #[error("step {0}: invalid method {1:?}")]
+    Method(Arc<str>, String),
+    #[error("step {0}: invalid header {1:?}")]
+    Header(Arc<str>, String),
+    #[error("step {0}: invalid regex for {1}: {2}")]
+    Regex(Arc<str>, String, regex::Error),
+    #[error("step {0}: regex for {1} has no capture group")]
+    NoCaptureGroup(Arc<str>, String),
+    #[error("step {0}: {{{{{1}}}}} is not extracted by an earlier step")]
+    UndefinedVariable(Arc<str>, String),
+    #[error("step {0}: unclosed {{{{ in {1:?}")]
+    Unclosed(Arc<str>, String),
+}
+
+/// A string with `{{var}}` placeholders.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(Vec<Segment>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Var(String),
+}
+
+impl Template {
+    /// A template without placeholders.
+    pub fn literal(s: impl Into<String>) -> Self {
+        Self(vec![Segment::Literal(s.into())])
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        let mut segments = Vec::new();
+        let mut rest = s;
+        while let Some(open) = rest.find("{{") {
+            if open > 0 {
+                segments.push(Segment::Literal(rest[..open].to_string()));
+            }
+            let close = rest[open + 2..].find("}}")?;
+            segments.push(Segment::Var(
+                rest[open + 2..open + 2 + close].trim().to_string(),
+            ));
+            rest = &rest[open + 2 + close + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+        Some(Self(segments))
+    }
+
+    fn vars(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().filter_map(|s| match s {
+            Segment::Var(var) => Some(var.as_str()),
+            Segment::Literal(_) => None,
+        })
+    }
+
+    /// Variables are checked when the scenario is loaded, so missing ones are rendered empty.
+    pub fn render(&self, vars: &HashMap<String, String>) -> String {
+        let mut out = String::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Literal(s) => out.push_str(s),
+                Segment::Var(var) => out.push_str(vars.get(var).map(String::as_str).unwrap_or("")),
+            }
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Extract {
+    Json(String),
+    Regex(regex::bytes::Regex),
+}
+
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub name: Arc<str>,
+    pub method: Method,
+    pub path: Template,
+    pub headers: Vec<(HeaderName, Template)>,
+    pub body: Option<Template>,
+    pub extract: Vec<(String, Extract)>,
+}
+
+impl Step {
+    /// Extract variables from the response body.
+    pub fn extract(&self, body: &[u8], vars: &mut HashMap<String, String>) -> Result<(), String> {
+        let mut json: Option<serde_json::Value> = None;
+        for (var, extract) in &self.extract {
+            let value = match extract {
+                Extract::Json(pointer) => {
+                    if json.is_none() {
+                        json = Some(
+                            serde_json::from_slice(body)
+                                .map_err(|e| format!("response is not JSON: {e}"))?,
+                        );
+                    }
+                    match json.as_ref().and_then(|j| j.pointer(pointer)) {
+                        Some(serde_json::Value::String(s)) => s.clone(),
+                        Some(value) => value.to_string(),
+                        None => return Err(format!("{pointer} is not found for {var}")),
+                    }
+                }
+                Extract::Regex(regex) => {
+                    let captures = regex
+                        .captures(body)
+                        .and_then(|c| c.get(1))
+                        .ok_or_else(|| format!("regex for {var} does not match"))?;
+                    String::from_utf8_lossy(captures.as_bytes()).into_owned()
+                }
+            };
+            vars.insert(var.clone(), value);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Scenario {
+    pub steps: Vec<Step>,
+    /// Fail a step on 4xx and 5xx, and report statistics for each step
+    pub strict: bool,
+    /// Share the position among workers instead of each worker running the steps in order
+    pub round_robin: Option<AtomicUsize>,
+}
+
+/// Progress of a worker in the scenario.
+#[derive(Debug, Default)]
+pub struct ScenarioState {
+    pub step: usize,
+    pub vars: HashMap<String, String>,
+}
+
+impl ScenarioState {
+    /// Go to the next step, or the first step of the next iteration.
+    pub fn advance(&mut self, scenario: &Scenario) {
+        if scenario.round_robin.is_some() {
+            return;
+        }
+        self.step += 1;
+        if self.step == scenario.steps.len() {
+            self.abort();
+        }
+    }
+
+    /// Start a new iteration.
+    pub fn abort(&mut self) {
+        self.step = 0;
+        self.vars.clear();
+    }
+}
+
+impl Scenario {
+    /// The step to run next on a worker.
+    pub fn step(&self, state: &ScenarioState) -> &Step {
+        match &self.round_robin {
+            Some(next) => &self.steps[next.fetch_add(1, Ordering::Relaxed) % self.steps.len()],
+            None => &self.steps[state.step],
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ScenarioError> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| ScenarioError::Io(path.into(), e))?;
+        let file: ScenarioFile =
+            toml::from_str(&content).map_err(|e| ScenarioError::Toml(path.into(), e))?;
+        Self::compile(file)
+    }
+
+    fn compile(file: ScenarioFile) -> Result<Self, ScenarioError> {
+        if file.steps.is_empty() {
+            return Err(ScenarioError::Empty);
+        }
+
+        let mut defined: HashSet<String> = HashSet::new();
+        let mut steps = Vec::new();
+        for (i, step) in file.steps.into_iter().enumerate() {
+            let method = step.method.as_deref().unwrap_or("GET").to_ascii_uppercase();
+            let name: Arc<str> = step
+                .name
+                .unwrap_or_else(|| format!("#{} {} {}", i + 1, method, step.path))
+                .into();
+            let method = Method::from_bytes(method.as_bytes())
+                .map_err(|_| ScenarioError::Method(name.clone(), method.clone()))?;
+
+            let template = |s: &str| {
+                let template = Template::parse(s)
+                    .ok_or_else(|| ScenarioError::Unclosed(name.clone(), s.to_string()))?;
+                if let Some(var) = template.vars().find(|v| !defined.contains(*v)) {
+                    return Err(ScenarioError::UndefinedVariable(
+                        name.clone(),
+                        var.to_string(),
+                    ));
+                }
+                Ok(template)
+            };
+
+            let path = template(&step.path)?;
+            let headers = step
+                .headers
+                .iter()
+                .map(|(k, v)| {
+                    let header = HeaderName::from_bytes(k.as_bytes())
+                        .map_err(|_| ScenarioError::Header(name.clone(), k.clone()))?;
+                    // Check the literal parts as early as possible
+                    HeaderValue::from_str(&v.replace("{{", "").replace("}}", ""))
+                        .map_err(|_| ScenarioError::Header(name.clone(), k.clone()))?;
+                    Ok((header, template(v)?))
+                })
+                .collect::<Result<Vec<_>, ScenarioError>>()?;
+            let body = step.body.as_deref().map(template).transpose()?;
+
+            let extract = step
+                .extract
+                .into_iter()
+                .map(|(var, extract)| {
+                    let extract = match extract {
+                        ExtractFile::Json(pointer) => Extract::Json(pointer),
+                        ExtractFile::Regex(regex) => {
+                            let regex = regex::bytes::Regex::new(&regex)
+                                .map_err(|e| ScenarioError::Regex(name.clone(), var.clone(), e))?;
+                            if regex.captures_len() < 2 {
+                                return Err(ScenarioError::NoCaptureGroup(name.clone(), var));
+                            }
+                            Extract::Regex(regex)
+                        }
+                    };
+                    Ok((var, extract))
+                })
+                .collect::<Result<Vec<_>, ScenarioError>>()?;
+            defined.extend(extract.iter().map(|(var, _)| var.clone()));
+
+            steps.push(Step {
+                name,
+                method,
+                path,
+                headers,
+                body,
+                extract,
+            });
+        }
+
+        Ok(Self {
+            steps,
+            strict: true,
+            round_robin: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(s: &str) -> Result<Scenario, ScenarioError> {
+        Scenario::compile(toml::from_str(s).unwrap())
+    }
+
+    #[test]
+    fn test_template() {
+        let template = Template::parse("/items/{{id}}?t={{ token }}").unwrap();
+        assert_eq!(template.vars().collect::<Vec<_>>(), vec!["id", "token"]);
+        let vars = HashMap::from([
+            ("id".to_string(), "42".to_string()),
+            ("token".to_string(), "abc".to_string()),
+        ]);
+        assert_eq!(template.render(&vars), "/items/42?t=abc");
+        assert!(Template::parse("/{{id").is_none());
+    }
+
+    #[test]
+    fn test_compile() {
+        let scenario = compile(
+            r#"
+            [[step]]
+            name = "login"
+            method = "post"
+            path = "/login"
+            body = '{"user": "oha"}'
+            extract = { token = { json = "/token" }, id = { regex = 'id=(\d+)' } }
+
+            [[step]]
+            path = "/items/{{id}}"
+            headers = { authorization = "Bearer {{token}}" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(scenario.steps.len(), 2);
+        assert_eq!(&*scenario.steps[0].name, "login");
+        assert_eq!(scenario.steps[0].method, Method::POST);
+        assert_eq!(&*scenario.steps[1].name, "#2 GET /items/{{id}}");
+
+        assert!(matches!(
+            compile("[[step]]\npath = \"/{{token}}\""),
+            Err(ScenarioError::UndefinedVariable(_, _))
+        ));
+        assert!(matches!(
+            compile("[[step]]\npath = \"/\"\nextract = { a = { regex = 'x' } }"),
+            Err(ScenarioError::NoCaptureGroup(_, _))
+        ));
+        assert!(matches!(compile("step = []"), Err(ScenarioError::Empty)));
+    }
+
+    #[test]
+    fn test_extract() {
+        let scenario = compile(
+            r#"
+            [[step]]
+            path = "/"
+            extract = { token = { json = "/data/token" }, n = { json = "/data/n" }, id = { regex = 'id=(\d+)' } }
+            "#,
+        )
+        .unwrap();
+        let mut vars = HashMap::new();
+        scenario.steps[0]
+            .extract(
+                br#"{"data": {"token": "abc", "n": 1, "s": "id=7"}}"#,
+                &mut vars,
+            )
+            .unwrap();
+        assert_eq!(vars["token"], "abc");
+        assert_eq!(vars["n"], "1");
+        assert_eq!(vars["id"], "7");
+
+        assert!(scenario.steps[0].extract(b"not json", &mut vars).is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{aggregate::Extent, fd_limit};
+
+/// How often oha samples itself
+const INTERVAL: Duration = Duration::from_secs(1);
+/// CPU usage, in percent of the runtime threads, from which oha may be the bottleneck
+const CPU_WARNING: f64 = 90.0;
+/// The share of the open files limit from which new connections may fail
+const OPEN_FILES_WARNING: f64 = 0.9;
+
+/// oha's own usage at one time. Each is None where it can't be measured.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Usage {
+    /// Percent of the runtime threads over the last interval. None for the first sample.
+    pub cpu: Option<f64>,
+    /// Resident set size in bytes. Only on Linux.
+    pub rss: Option<u64>,
+    /// Only on Unix
+    pub open_files: Option<u64>,
+}
+
+/// The samples of the run so far
+#[derive(Clone, Debug, Default)]
+pub struct Samples {
+    pub last: Usage,
+    pub cpu: Extent,
+    pub rss: Extent,
+    pub open_files: Extent,
+}
+
+/// Samples the CPU, memory and open files of oha itself once per second, to tell when the
+/// generator rather than the server was the bottleneck. It runs on a thread of its own with a
+/// syscall and two reads of procfs for each sample, so that it doesn't take from the runtime.
+pub struct SelfMonitor {
+    /// The CPU usage is relative to them
+    threads: usize,
+    /// The soft limit
+    open_files_limit: Option<u64>,
+    samples: Mutex<Samples>,
+    stopped: AtomicBool,
+}
+
+impl SelfMonitor {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads,
+            open_files_limit: fd_limit::soft_limit(),
+            samples: Default::default(),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    /// Start sampling, best-effort: nothing is sampled when the thread can't be spawned
+    pub fn spawn(self: &Arc<Self>) {
+        let this = self.clone();
+        let _ = std::thread::Builder::new()
+            .name("oha-self-monitor".to_string())
+            .spawn(move || this.run());
+    }
+
+    fn run(&self) {
+        let mut last = (Instant::now(), cpu_time());
+        self.record(Usage {
+            cpu: None,
+            rss: rss(),
+            open_files: open_files(),
+        });
+        loop {
+            std::thread::sleep(INTERVAL);
+            if self.stopped.load(Ordering::Relaxed) {
+                break;
+            }
+            let now = (Instant::now(), cpu_time());
+            let cpu = last.1.zip(now.1).map(|(before, after)| {
+                after.saturating_sub(before).as_secs_f64()
+                    / now.0.duration_since(last.0).as_secs_f64()
+                    / self.threads as f64
+                    * 100.0
+            });
+            last = now;
+            self.record(Usage {
+                cpu,
+                rss: rss(),
+                open_files: open_files(),
+            });
+        }
+    }
+
+    fn record(&self, usage: Usage) {
+        let mut samples = self.samples.lock().unwrap();
+        if let Some(cpu) = usage.cpu {
+            samples.cpu.add(cpu, 1);
+        }
+        if let Some(rss) = usage.rss {
+            samples.rss.add(rss as f64, 1);
+        }
+        if let Some(open_files) = usage.open_files {
+            samples.open_files.add(open_files as f64, 1);
+        }
+        samples.last = usage;
+    }
+
+    /// Stop sampling at the end of the run, so that the idle time after it isn't counted
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn samples(&self) -> Samples {
+        self.samples.lock().unwrap().clone()
+    }
+
+    pub fn open_files_limit(&self) -> Option<u64> {
+        self.open_files_limit
+    }
+
+    /// oha used nearly all of its runtime threads at some point
+    pub fn cpu_bound(&self, samples: &Samples) -> bool {
+        !samples.cpu.is_empty() && samples.cpu.slowest() >= CPU_WARNING
+    }
+
+    /// The open files came close to the limit at some point
+    pub fn near_open_files_limit(&self, samples: &Samples) -> bool {
+        match self.open_files_limit {
+            Some(limit) => {
+                !samples.open_files.is_empty()
+                    && samples.open_files.slowest() >= limit as f64 * OPEN_FILES_WARNING
+            }
+            None => false,
+        }
+    }
+}
+
+/// User and system time of the whole process
+#[cfg(unix)]
+fn cpu_time() -> Option<Duration> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: getrusage only writes the struct it's given
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: initialized by the successful call
+    let usage = unsafe { usage.assume_init() };
+    let time = |t: libc::timeval| {
+        Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64)
+    };
+    Some(time(usage.ru_utime) + time(usage.ru_stime))
+}
+
+#[cfg(not(unix))]
+fn cpu_time() -> Option<Duration> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn rss() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    // SAFETY: sysconf has no preconditions
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * u64::try_from(page_size).ok()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rss() -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn open_files() -> Option<u64> {
+    // Without the descriptor of the listing itself
+    let dir = std::fs::read_dir("/dev/fd").ok()?;
+    Some((dir.count() as u64).saturating_sub(1))
+}
+
+#[cfg(not(unix))]
+fn open_files() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warnings() {
+        let monitor = SelfMonitor {
+            threads: 2,
+            open_files_limit: Some(100),
+            samples: Default::default(),
+            stopped: AtomicBool::new(false),
+        };
+        assert!(!monitor.cpu_bound(&monitor.samples()));
+        assert!(!monitor.near_open_files_limit(&monitor.samples()));
+
+        monitor.record(Usage {
+            cpu: Some(50.0),
+            rss: Some(1024),
+            open_files: Some(10),
+        });
+        monitor.record(Usage {
+            cpu: Some(95.0),
+            rss: None,
+            open_files: Some(95),
+        });
+        let samples = monitor.samples();
+        assert_eq!(samples.cpu.len(), 2);
+        assert_eq!(samples.cpu.average(), 72.5);
+        assert_eq!(samples.rss.len(), 1);
+        assert_eq!(samples.last.open_files, Some(95));
+        assert!(monitor.cpu_bound(&samples));
+        assert!(monitor.near_open_files_limit(&samples));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_usage() {
+        assert!(cpu_time().is_some());
+        assert!(rss().is_some_and(|rss| rss > 0));
+        assert!(open_files().is_some_and(|n| n >= 3));
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+/// The exit code when a second ctrl-c quits without waiting for the requests in flight,
+/// 128 + SIGINT as shells report a process killed by ctrl-c
+pub const FORCED_EXIT_CODE: i32 = 130;
+
+/// Stops the run on ctrl-c, see `LoadConfig::shutdown`. The workers start no new request once
+/// it's stopped, and the requests in flight have `--shutdown-grace` to finish and be counted in
+/// the summary. A second ctrl-c ends the grace period right away and drops them.
+pub struct Shutdown {
+    grace: Duration,
+    /// When the grace period ends. None until the run is stopped.
+    signal: tokio::sync::watch::Sender<Option<Instant>>,
+    /// Set by the second ctrl-c
+    forced: AtomicBool,
+}
+
+impl Shutdown {
+    pub(crate) fn new(grace: Duration) -> Self {
+        Self {
+            grace,
+            signal: tokio::sync::watch::channel(None).0,
+            forced: AtomicBool::new(false),
+        }
+    }
+
+    /// Handle a ctrl-c. The first one stops the run and starts the grace period.
+    /// The next ones end it now and return true.
+    pub fn interrupt(&self) -> bool {
+        let mut forced = false;
+        self.signal.send_modify(|deadline| {
+            let now = Instant::now();
+            match deadline {
+                None => *deadline = Some(now + self.grace),
+                Some(deadline) => {
+                    forced = true;
+                    *deadline = now.min(*deadline);
+                }
+            }
+        });
+        if forced {
+            self.forced.store(true, Ordering::Relaxed);
+        }
+        forced
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.signal.borrow().is_some()
+    }
+
+    /// Whether a second ctrl-c quit without waiting for the requests in flight
+    pub fn is_forced(&self) -> bool {
+        self.forced.load(Ordering::Relaxed)
+    }
+
+    /// Resolves when the run is stopped
+    pub(crate) fn stopped(&self) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let mut signal = self.signal.subscribe();
+        async move {
+            let _ = signal.wait_for(Option::is_some).await;
+        }
+    }
+
+    /// Resolves when the grace period after the first ctrl-c is over, or is cut by the second
+    pub(crate) fn grace_over(&self) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let mut signal = self.signal.subscribe();
+        async move {
+            loop {
+                let deadline = match signal.wait_for(Option::is_some).await {
+                    Ok(deadline) => *deadline,
+                    Err(_) => None,
+                };
+                let Some(deadline) = deadline else {
+                    return std::future::pending().await;
+                };
+                tokio::select! {
+                    _ = tokio::time::sleep_until(deadline.into()) => return,
+                    changed = signal.changed() => {
+                        if changed.is_err() {
+                            return std::future::pending().await;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_interrupt() {
+        let shutdown = Shutdown::new(Duration::from_millis(50));
+        assert!(!shutdown.is_stopped());
+        let stopped = tokio::spawn(shutdown.stopped());
+        let grace_over = tokio::spawn(shutdown.grace_over());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!stopped.is_finished());
+
+        let t0 = Instant::now();
+        // The first ctrl-c starts the grace period, the next one ends it now
+        assert!(!shutdown.interrupt());
+        assert!(shutdown.is_stopped());
+        stopped.await.unwrap();
+        assert!(!shutdown.is_forced());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!grace_over.is_finished());
+        assert!(shutdown.interrupt());
+        assert!(shutdown.is_forced());
+        grace_over.await.unwrap();
+        assert!(t0.elapsed() < Duration::from_millis(50));
+
+        // The grace period doesn't restart
+        let t1 = Instant::now();
+        shutdown.grace_over().await;
+        assert!(t1.elapsed() < Duration::from_millis(50));
+
+        // Without the second ctrl-c, the grace period runs out
+        let shutdown = Shutdown::new(Duration::from_millis(50));
+        let t0 = Instant::now();
+        shutdown.interrupt();
+        shutdown.grace_over().await;
+        assert!(t0.elapsed() >= Duration::from_millis(50));
+        assert!(!shutdown.is_forced());
+    }
+}
@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant, SystemTime};
+
+/// Parse an RFC 3339 timestamp for `--start-at` e.g. 2024-05-01T12:00:00Z or
+/// 2024-05-01T21:00:00+09:00. A timestamp without an offset is in UTC.
+pub fn parse_rfc3339(s: &str) -> Result<SystemTime, String> {
+    let example = "expected e.g. 2024-05-01T12:00:00Z or 2024-05-01T21:00:00+09:00";
+    let split = s
+        .len()
+        .checked_sub(6)
+        .and_then(|i| Some((s.get(..i)?, s.get(i..)?)));
+    let (time, offset) = match split {
+        Some((time, offset)) if offset.starts_with(['+', '-']) && offset.as_bytes()[3] == b':' => {
+            let (hours, minutes) = match (offset[1..3].parse::<u64>(), offset[4..].parse::<u64>()) {
+                (Ok(hours), Ok(minutes)) if hours < 24 && minutes < 60 => (hours, minutes),
+                _ => return Err(format!("invalid offset {offset}, {example}")),
+            };
+            let ahead = offset.starts_with('+');
+            (
+                time,
+                Some((Duration::from_secs(hours * 3600 + minutes * 60), ahead)),
+            )
+        }
+        _ => (s.strip_suffix(['Z', 'z']).unwrap_or(s), None),
+    };
+    let time = humantime::parse_rfc3339_weak(time).map_err(|e| format!("{e}, {example}"))?;
+    Ok(match offset {
+        // The local time is ahead of UTC by a positive offset
+        Some((offset, true)) => time - offset,
+        Some((offset, false)) => time + offset,
+        None => time,
+    })
+}
+
+/// The instant the run starts at `at` by the wall clock. Errors when it has passed.
+pub fn instant(at: SystemTime) -> anyhow::Result<Instant> {
+    let now = SystemTime::now();
+    match at.duration_since(now) {
+        Ok(wait) => Ok(Instant::now() + wait),
+        Err(e) => anyhow::bail!(
+            "the start time {} is {:.3} secs in the past",
+            humantime::format_rfc3339_millis(at),
+            e.duration().as_secs_f64()
+        ),
+    }
+}
+
+/// Print a countdown to `start` on stderr once a second, for `--no-tui`
+pub async fn countdown(start: Instant, at: SystemTime) {
+    loop {
+        let left = start.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return;
+        }
+        eprintln!(
+            "Starting at {} in {} secs",
+            humantime::format_rfc3339_millis(at),
+            left.as_secs_f64().ceil()
+        );
+        // Tick on whole seconds before the start
+        let next = left.as_nanos() % 1_000_000_000;
+        let next = if next == 0 { 1_000_000_000 } else { next };
+        tokio::time::sleep(Duration::from_nanos(next as u64)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rfc3339() {
+        let utc = humantime::parse_rfc3339("2024-05-01T12:00:00Z").unwrap();
+        assert_eq!(parse_rfc3339("2024-05-01T12:00:00Z"), Ok(utc));
+        assert_eq!(parse_rfc3339("2024-05-01T12:00:00"), Ok(utc));
+        assert_eq!(parse_rfc3339("2024-05-01T21:00:00+09:00"), Ok(utc));
+        assert_eq!(parse_rfc3339("2024-05-01T07:30:00-04:30"), Ok(utc));
+        assert_eq!(
+            parse_rfc3339("2024-05-01T12:00:00.250Z"),
+            Ok(utc + Duration::from_millis(250))
+        );
+        assert!(parse_rfc3339("2024-05-01T21:00:00+9:000").is_err());
+        assert!(parse_rfc3339("2024-05-01T21:00:00+25:00").is_err());
+        assert!(parse_rfc3339("tomorrow").is_err());
+        assert!(parse_rfc3339("2024-05-01").is_err());
+    }
+
+    #[test]
+    fn test_instant() {
+        let at = SystemTime::now() + Duration::from_secs(10);
+        let start = instant(at).unwrap();
+        let wait = start - Instant::now();
+        assert!(wait > Duration::from_secs(9) && wait <= Duration::from_secs(10));
+
+        let past = SystemTime::now() - Duration::from_secs(5);
+        assert!(instant(past)
+            .unwrap_err()
+            .to_string()
+            .contains("secs in the past"));
+    }
+}
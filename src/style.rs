@@ -0,0 +1,179 @@
+use std::fmt::{self, Display, Formatter};
+
+/// SGR attributes, in the order they are written
+const RESET: u8 = 1 << 0;
+const BOLD: u8 = 1 << 1;
+const UNDERLINED: u8 = 1 << 2;
+const ATTRIBUTES: [(u8, &str); 3] = [(RESET, "0"), (BOLD, "1"), (UNDERLINED, "4")];
+
+/// Text with the colors of the summary. It writes the same escape codes as crossterm, so that
+/// the summary doesn't depend on it in a build without the TUI.
+#[derive(Clone, Copy, Debug)]
+pub struct StyledContent<D> {
+    content: D,
+    /// A color of the 256-color palette
+    foreground: Option<u8>,
+    attributes: u8,
+}
+
+impl<D> StyledContent<D> {
+    fn color(mut self, color: u8) -> Self {
+        self.foreground = Some(color);
+        self
+    }
+
+    fn attribute(mut self, attribute: u8) -> Self {
+        self.attributes |= attribute;
+        self
+    }
+}
+
+impl<D: Display> Display for StyledContent<D> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(color) = self.foreground {
+            write!(f, "\x1b[38;5;{color}m")?;
+        }
+        for (attribute, sgr) in ATTRIBUTES {
+            if self.attributes & attribute != 0 {
+                write!(f, "\x1b[{sgr}m")?;
+            }
+        }
+        write!(f, "{}", self.content)?;
+        if self.attributes != 0 {
+            f.write_str("\x1b[0m")
+        } else if self.foreground.is_some() {
+            f.write_str("\x1b[39m")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The colors and attributes of the summary
+pub trait Stylize: Sized {
+    type Styled;
+
+    fn stylize(self) -> Self::Styled;
+
+    fn reset(self) -> Self::Styled;
+    fn bold(self) -> Self::Styled;
+    fn underlined(self) -> Self::Styled;
+    fn red(self) -> Self::Styled;
+    fn green(self) -> Self::Styled;
+    fn yellow(self) -> Self::Styled;
+    fn cyan(self) -> Self::Styled;
+    fn white(self) -> Self::Styled;
+}
+
+impl<D> Stylize for StyledContent<D> {
+    type Styled = Self;
+
+    fn stylize(self) -> Self {
+        self
+    }
+
+    fn reset(self) -> Self {
+        self.attribute(RESET)
+    }
+    fn bold(self) -> Self {
+        self.attribute(BOLD)
+    }
+    fn underlined(self) -> Self {
+        self.attribute(UNDERLINED)
+    }
+    fn red(self) -> Self {
+        self.color(9)
+    }
+    fn green(self) -> Self {
+        self.color(10)
+    }
+    fn yellow(self) -> Self {
+        self.color(11)
+    }
+    fn cyan(self) -> Self {
+        self.color(14)
+    }
+    fn white(self) -> Self {
+        self.color(15)
+    }
+}
+
+impl<'a> Stylize for &'a str {
+    type Styled = StyledContent<&'a str>;
+
+    fn stylize(self) -> Self::Styled {
+        StyledContent {
+            content: self,
+            foreground: None,
+            attributes: 0,
+        }
+    }
+
+    fn reset(self) -> Self::Styled {
+        self.stylize().reset()
+    }
+    fn bold(self) -> Self::Styled {
+        self.stylize().bold()
+    }
+    fn underlined(self) -> Self::Styled {
+        self.stylize().underlined()
+    }
+    fn red(self) -> Self::Styled {
+        self.stylize().red()
+    }
+    fn green(self) -> Self::Styled {
+        self.stylize().green()
+    }
+    fn yellow(self) -> Self::Styled {
+        self.stylize().yellow()
+    }
+    fn cyan(self) -> Self::Styled {
+        self.stylize().cyan()
+    }
+    fn white(self) -> Self::Styled {
+        self.stylize().white()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_codes() {
+        assert_eq!("a".green().to_string(), "\x1b[38;5;10ma\x1b[39m");
+        assert_eq!("a".red().bold().to_string(), "\x1b[38;5;9m\x1b[1ma\x1b[0m");
+        assert_eq!(
+            "a".bold().underlined().to_string(),
+            "\x1b[1m\x1b[4ma\x1b[0m"
+        );
+        assert_eq!("a".reset().bold().to_string(), "\x1b[0m\x1b[1ma\x1b[0m");
+        assert_eq!("a".stylize().to_string(), "a");
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_same_as_crossterm() {
+        let ours = [
+            "a".reset(),
+            "a".underlined().bold(),
+            "a".green().bold(),
+            "a".yellow(),
+            "a".cyan(),
+            "a".white(),
+            "a".reset().bold(),
+        ];
+        let crossterm = [
+            crossterm::style::Stylize::reset("a"),
+            crossterm::style::Stylize::bold(crossterm::style::Stylize::underlined("a")),
+            crossterm::style::Stylize::bold(crossterm::style::Stylize::green("a")),
+            crossterm::style::Stylize::yellow("a"),
+            crossterm::style::Stylize::cyan("a"),
+            crossterm::style::Stylize::white("a"),
+            crossterm::style::Stylize::bold(crossterm::style::Stylize::reset("a")),
+        ];
+        for (ours, crossterm) in ours.iter().zip(crossterm) {
+            assert_eq!(ours.to_string(), crossterm.to_string());
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::client::{ClientError, LatencyIncludes, RequestResult};
+
+/// How often the rate is adjusted, and the window of the p99 it's adjusted by
+pub const INTERVAL: Duration = Duration::from_secs(2);
+/// The starting rate without `-q`
+pub const DEFAULT_QPS: f64 = 10.0;
+/// The rate never goes below this
+pub const MIN_QPS: f64 = 1.0;
+/// The rate is multiplied by this when the p99 is over the target
+pub const DECREASE: f64 = 0.7;
+/// The rate isn't increased while less than this ratio of it is achieved, since more workers
+/// are needed rather than a higher rate
+pub const ACHIEVED_RATIO: f64 = 0.9;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Increase,
+    Decrease,
+    /// Under the target, but the workers can't keep up with the rate
+    Hold,
+}
+
+/// The rate of a window and what the controller made of it
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Adjustment {
+    /// Seconds from the start of the run to the start and the end of the window
+    pub start: f64,
+    pub end: f64,
+    /// The rate during the window
+    pub setpoint: f64,
+    /// Responses received in the window per second
+    pub achieved: f64,
+    /// The p99 latency of the responses in the window. Infinite when failures are in the top
+    /// 1%, NaN when there was no response.
+    pub p99: f64,
+    pub action: Action,
+}
+
+impl Adjustment {
+    /// Whether the p99 of the window was under the target
+    pub fn met(&self) -> bool {
+        self.action != Action::Decrease
+    }
+}
+
+/// Adjusts the rate of `-q` for `--target-p99` by additive increase and multiplicative decrease.
+/// The collector feeds the results and `run` adjusts the rate at each interval.
+pub struct TargetP99 {
+    target: Duration,
+    latency_includes: LatencyIncludes,
+    /// Added to the rate while the p99 is under the target
+    increase: f64,
+    /// The current rate, as the bits of an f64
+    qps: AtomicU64,
+    /// Latencies in seconds of the results since the last adjustment. Failures are infinite.
+    window: Mutex<Vec<f64>>,
+    adjustments: Mutex<Vec<Adjustment>>,
+}
+
+impl TargetP99 {
+    pub fn new(target: Duration, qps: f64, latency_includes: LatencyIncludes) -> Self {
+        let qps = qps.max(MIN_QPS);
+        Self {
+            target,
+            latency_includes,
+            increase: (qps / 10.0).max(MIN_QPS),
+            qps: AtomicU64::new(qps.to_bits()),
+            window: Mutex::new(Vec::new()),
+            adjustments: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn target(&self) -> Duration {
+        self.target
+    }
+
+    /// The current rate
+    pub fn qps(&self) -> f64 {
+        f64::from_bits(self.qps.load(Ordering::Relaxed))
+    }
+
+    /// Record a result in the window. Requests cut by `-z` or by an abort aren't counted.
+    pub fn record(&self, res: &Result<RequestResult, ClientError>) {
+        let latency = match res {
+            Err(ClientError::Deadline | ClientError::Aborted) => return,
+            Err(_) => f64::INFINITY,
+            Ok(r) => r.duration(self.latency_includes).as_secs_f64(),
+        };
+        self.window.lock().unwrap().push(latency);
+    }
+
+    /// Adjust the rate by the results of the window which ended `elapsed` after the start
+    pub fn adjust(&self, elapsed: Duration) -> Adjustment {
+        let mut latencies = std::mem::take(&mut *self.window.lock().unwrap());
+        let setpoint = self.qps();
+        let mut adjustments = self.adjustments.lock().unwrap();
+        let start = adjustments.last().map_or(0.0, |a| a.end);
+        let end = elapsed.as_secs_f64();
+        let achieved = latencies.len() as f64 / (end - start);
+        let p99 = p99(&mut latencies);
+        // No response at all is over any target
+        let (action, qps) = if p99.is_nan() || p99 > self.target.as_secs_f64() {
+            (Action::Decrease, (setpoint * DECREASE).max(MIN_QPS))
+        } else if achieved < setpoint * ACHIEVED_RATIO {
+            (Action::Hold, setpoint)
+        } else {
+            (Action::Increase, setpoint + self.increase)
+        };
+        self.qps.store(qps.to_bits(), Ordering::Relaxed);
+        let adjustment = Adjustment {
+            start,
+            end,
+            setpoint,
+            achieved,
+            p99,
+            action,
+        };
+        adjustments.push(adjustment.clone());
+        adjustment
+    }
+
+    /// The windows adjusted so far
+    pub fn adjustments(&self) -> Vec<Adjustment> {
+        self.adjustments.lock().unwrap().clone()
+    }
+
+    /// Adjust the rate at each interval from `start`
+    pub async fn run(self: std::sync::Arc<Self>, start: Instant) {
+        let mut interval = tokio::time::interval_at((start + INTERVAL).into(), INTERVAL);
+        loop {
+            let at = interval.tick().await;
+            self.adjust(at.into_std() - start);
+        }
+    }
+}
+
+/// The highest rate which kept the p99 under the target: the average setpoint of the windows under
+/// the target in the second half of the run. None when there was none.
+pub fn stable_rate(adjustments: &[Adjustment]) -> Option<f64> {
+    let stable = stable_windows(adjustments);
+    (!stable.is_empty())
+        .then(|| stable.iter().map(|a| a.setpoint).sum::<f64>() / stable.len() as f64)
+}
+
+/// The windows under the target in the second half of the run, after the controller converged
+pub fn stable_windows(adjustments: &[Adjustment]) -> Vec<&Adjustment> {
+    let half = adjustments.last().map_or(0.0, |a| a.end) / 2.0;
+    adjustments
+        .iter()
+        .filter(|a| a.start >= half && a.met())
+        .collect()
+}
+
+/// The p99 of `latencies` like the percentiles of the summary
+fn p99(latencies: &mut [f64]) -> f64 {
+    float_ord::sort(latencies);
+    let i = (0.99 * latencies.len() as f64) as usize;
+    latencies
+        .get(i.min(latencies.len().saturating_sub(1)))
+        .copied()
+        .unwrap_or(f64::NAN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(target: &TargetP99, latencies: impl IntoIterator<Item = f64>) {
+        target.window.lock().unwrap().extend(latencies);
+    }
+
+    #[test]
+    fn test_adjust() {
+        let target = TargetP99::new(Duration::from_millis(100), 50.0, LatencyIncludes::All);
+        let secs = Duration::from_secs;
+
+        // Under the target at the full rate
+        feed(&target, vec![0.01; 100]);
+        let adjustment = target.adjust(secs(2));
+        assert_eq!(adjustment.action, Action::Increase);
+        assert_eq!(adjustment.achieved, 50.0);
+        assert_eq!(target.qps(), 55.0);
+
+        // A failure in the top 1% is over the target
+        feed(&target, vec![0.01; 109]);
+        feed(&target, [f64::INFINITY, f64::INFINITY]);
+        let adjustment = target.adjust(secs(4));
+        assert_eq!(adjustment.action, Action::Decrease);
+        assert!((target.qps() - 38.5).abs() < 1e-9);
+
+        // The workers can't keep up
+        feed(&target, vec![0.01; 10]);
+        assert_eq!(target.adjust(secs(6)).action, Action::Hold);
+        assert!((target.qps() - 38.5).abs() < 1e-9);
+
+        // No response at all
+        let adjustment = target.adjust(secs(8));
+        assert_eq!(adjustment.action, Action::Decrease);
+        assert!(adjustment.p99.is_nan());
+
+        let adjustments = target.adjustments();
+        assert_eq!(adjustments.len(), 4);
+        assert_eq!(adjustments[1].start, 2.0);
+        // Only the hold at 4 to 6 secs is in the second half and under the target
+        let stable_rate = stable_rate(&adjustments).unwrap();
+        assert!((stable_rate - 38.5).abs() < 1e-9, "{stable_rate}");
+    }
+
+    #[test]
+    fn test_min_qps() {
+        let target = TargetP99::new(Duration::from_millis(100), 1.2, LatencyIncludes::All);
+        target.adjust(Duration::from_secs(2));
+        assert_eq!(target.qps(), MIN_QPS);
+        assert_eq!(stable_rate(&target.adjustments()), None);
+    }
+}
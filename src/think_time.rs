@@ -0,0 +1,98 @@
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+
+/// The pause of each worker between a response and its next request for `--think-time`
+pub struct ThinkTime {
+    duration: Duration,
+    /// `--think-time-jitter` as a fraction
+    jitter: f64,
+    /// Total time the workers have paused
+    paused_nanos: AtomicU64,
+    pauses: AtomicUsize,
+}
+
+impl ThinkTime {
+    pub fn new(duration: Duration, jitter_percent: f64) -> Self {
+        Self {
+            duration,
+            jitter: jitter_percent / 100.0,
+            paused_nanos: AtomicU64::new(0),
+            pauses: AtomicUsize::new(0),
+        }
+    }
+
+    /// The think time after a response, scaled by a uniform random factor within the jitter
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Duration {
+        if self.jitter == 0.0 {
+            self.duration
+        } else {
+            self.duration
+                .mul_f64(rng.gen_range(1.0 - self.jitter..=1.0 + self.jitter))
+        }
+    }
+
+    /// Sleep until `until`, or `dead_line` if it comes first
+    pub async fn pause(&self, until: Instant, dead_line: Option<Instant>) {
+        let until = dead_line.map_or(until, |dead_line| until.min(dead_line));
+        let start = Instant::now();
+        if until <= start {
+            return;
+        }
+        tokio::time::sleep_until(until.into()).await;
+        self.pauses.fetch_add(1, Ordering::Relaxed);
+        self.paused_nanos
+            .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// The total time of the pauses so far
+    pub fn paused(&self) -> Duration {
+        Duration::from_nanos(self.paused_nanos.load(Ordering::Relaxed))
+    }
+
+    pub fn pauses(&self) -> usize {
+        self.pauses.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_sample() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let fixed = ThinkTime::new(Duration::from_millis(100), 0.0);
+        assert_eq!(fixed.sample(&mut rng), Duration::from_millis(100));
+
+        let jittered = ThinkTime::new(Duration::from_millis(100), 20.0);
+        let samples: Vec<Duration> = (0..100).map(|_| jittered.sample(&mut rng)).collect();
+        assert!(samples
+            .iter()
+            .all(|d| (Duration::from_millis(80)..=Duration::from_millis(120)).contains(d)));
+        assert!(samples.iter().any(|d| *d != samples[0]));
+    }
+
+    #[tokio::test]
+    async fn test_pause() {
+        let think_time = ThinkTime::new(Duration::from_millis(50), 0.0);
+        think_time
+            .pause(Instant::now() + Duration::from_millis(50), None)
+            .await;
+        // Cut by the dead line
+        let dead_line = Instant::now() + Duration::from_millis(10);
+        think_time
+            .pause(Instant::now() + Duration::from_secs(10), Some(dead_line))
+            .await;
+        // Already over
+        think_time.pause(Instant::now(), None).await;
+
+        assert_eq!(think_time.pauses(), 2);
+        assert!(think_time.paused() >= Duration::from_millis(60));
+        assert!(think_time.paused() < Duration::from_secs(1));
+    }
+}
@@ -2,6 +2,8 @@ use std::{fmt, time::Duration};
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum TimeScale {
+    /// Makes bursts visible
+    HundredMillis,
     Second,
     TenSeconds,
     Minute,
@@ -18,6 +20,7 @@ pub struct TimeLabel {
 impl fmt::Display for TimeScale {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            TimeScale::HundredMillis => write!(f, "100ms"),
             TimeScale::Second => write!(f, "second"),
             TimeScale::TenSeconds => write!(f, "10 seconds"),
             TimeScale::Minute => write!(f, "minute"),
@@ -30,6 +33,10 @@ impl fmt::Display for TimeScale {
 impl fmt::Display for TimeLabel {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            TimeLabel {
+                x,
+                timescale: TimeScale::HundredMillis,
+            } => write!(f, "{}ms", 100 * x),
             TimeLabel {
                 x,
                 timescale: TimeScale::Second,
@@ -57,6 +64,7 @@ impl fmt::Display for TimeLabel {
 impl TimeScale {
     pub fn as_secs_f64(&self) -> f64 {
         match self {
+            TimeScale::HundredMillis => 0.1,
             TimeScale::Second => 1.0,
             TimeScale::TenSeconds => 10.0,
             TimeScale::Minute => 60.0,
@@ -83,6 +91,7 @@ impl TimeScale {
 
     pub fn inc(&self) -> Self {
         match self {
+            TimeScale::HundredMillis => TimeScale::Second,
             TimeScale::Second => TimeScale::TenSeconds,
             TimeScale::TenSeconds => TimeScale::Minute,
             TimeScale::Minute => TimeScale::TenMinutes,
@@ -93,7 +102,8 @@ impl TimeScale {
 
     pub fn dec(&self) -> Self {
         match self {
-            TimeScale::Second => TimeScale::Second,
+            TimeScale::HundredMillis => TimeScale::HundredMillis,
+            TimeScale::Second => TimeScale::HundredMillis,
             TimeScale::TenSeconds => TimeScale::Second,
             TimeScale::Minute => TimeScale::TenSeconds,
             TimeScale::TenMinutes => TimeScale::Minute,
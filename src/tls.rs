@@ -0,0 +1,415 @@
+//! The TLS backends. The `tls-rustls` feature (the default) selects rustls and `tls-native`
+//! selects the platform's TLS library through native-tls. rustls wins when both are enabled.
+
+use std::future::Future;
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use url::Url;
+
+use crate::{client::ClientError, url_generator::Authority};
+
+/// The TLS options of the command line, which every backend supports
+#[cfg_attr(not(any(feature = "native-tls", feature = "rustls")), allow(dead_code))]
+pub struct TlsOptions {
+    /// Accept any server certificate for `--insecure`
+    pub insecure: bool,
+    /// Offer h2 by ALPN
+    pub http2: bool,
+    /// The PEM certificates of `--cacert`, trusted instead of the platform's roots
+    pub ca_certs: Option<Vec<u8>>,
+    /// The PEM certificate chain of `--cert` and the PEM PKCS#8 key of `--key`
+    pub client_cert: Option<(Vec<u8>, Vec<u8>)>,
+    /// `--sni`
+    pub sni: Option<Sni>,
+    /// Let new connections resume the sessions of earlier ones. Only rustls resumes them.
+    #[cfg(feature = "rustls")]
+    pub session_resumption: bool,
+}
+
+/// The name sent in SNI and verified in the certificate instead of the host of the URL, on the
+/// connections to the URL of the run only
+#[cfg_attr(not(any(feature = "native-tls", feature = "rustls")), allow(dead_code))]
+pub struct Sni {
+    pub origin: Authority,
+    /// An IPv6 address is without brackets
+    pub name: String,
+}
+
+/// Makes the TLS connections of the client
+pub trait TlsConnector: Sized + Send + Sync {
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    /// The name of the backend in the summary
+    const NAME: &'static str;
+
+    /// Fails on unreadable certificates or keys.
+    fn new(options: TlsOptions) -> anyhow::Result<Self>;
+
+    /// Do the handshake on `stream`. Returns whether the session was resumed, None when the
+    /// backend can't tell.
+    fn connect(
+        &self,
+        stream: TcpStream,
+        url: &Url,
+    ) -> impl Future<Output = Result<(Self::Stream, Option<bool>), ClientError>> + Send;
+}
+
+#[cfg(feature = "rustls")]
+pub type Connector = rustls_backend::RustlsConnector;
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+pub type Connector = native_backend::NativeTlsConnector;
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+pub type Connector = NoTls;
+
+/// The name of the backend the client uses
+pub const BACKEND: &str = <Connector as TlsConnector>::NAME;
+
+/// The host name for TLS SNI and certificate verification. IPv6 addresses are without brackets.
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+fn tls_host(url: &Url, sni: Option<&Sni>) -> Result<String, ClientError> {
+    if let Some(sni) = sni.filter(|sni| sni.origin.matches(url)) {
+        return Ok(sni.name.clone());
+    }
+    match url.host().ok_or(ClientError::HostNotFound)? {
+        url::Host::Ipv6(addr) => Ok(addr.to_string()),
+        host => Ok(host.to_string()),
+    }
+}
+
+#[cfg(feature = "rustls")]
+mod rustls_backend {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use anyhow::Context;
+    use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+    use tokio::net::TcpStream;
+    use url::Url;
+
+    use super::{tls_host, Sni, TlsConnector, TlsOptions};
+    use crate::client::ClientError;
+
+    pub struct RustlsConnector {
+        insecure: bool,
+        sni: Option<Sni>,
+        http2: bool,
+        session_resumption: bool,
+        /// None trusts the platform's roots
+        ca_certs: Option<rustls::RootCertStore>,
+        client_cert: Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>,
+        /// Built on the first connection and shared by all of them, with the session cache
+        config: tokio::sync::OnceCell<RustlsConfig>,
+    }
+
+    /// The TLS configuration of rustls without the per-connection verifier
+    struct RustlsConfig {
+        config: rustls::ClientConfig,
+        verifier: Arc<dyn rustls::client::danger::ServerCertVerifier>,
+    }
+
+    impl RustlsConnector {
+        fn build_config(&self) -> Result<RustlsConfig, ClientError> {
+            let verifier: Arc<dyn rustls::client::danger::ServerCertVerifier> = if self.insecure {
+                Arc::new(AcceptAnyServerCert)
+            } else {
+                let root_cert_store = match &self.ca_certs {
+                    Some(ca_certs) => ca_certs.clone(),
+                    None => {
+                        let mut root_cert_store = rustls::RootCertStore::empty();
+                        for cert in rustls_native_certs::load_native_certs()? {
+                            root_cert_store.add(cert).ok(); // ignore error
+                        }
+                        root_cert_store
+                    }
+                };
+                rustls::client::WebPkiServerVerifier::builder(Arc::new(root_cert_store)).build()?
+            };
+            let builder = rustls::ClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(verifier.clone());
+            let mut config = match &self.client_cert {
+                Some((certs, key)) => {
+                    builder.with_client_auth_cert(certs.clone(), key.clone_key())?
+                }
+                None => builder.with_no_client_auth(),
+            };
+            if !self.session_resumption {
+                config.resumption = rustls::client::Resumption::disabled();
+            }
+            if self.http2 {
+                config.alpn_protocols = vec![b"h2".to_vec()];
+            }
+            Ok(RustlsConfig { config, verifier })
+        }
+    }
+
+    impl TlsConnector for RustlsConnector {
+        type Stream = tokio_rustls::client::TlsStream<TcpStream>;
+
+        const NAME: &'static str = "rustls";
+
+        fn new(options: TlsOptions) -> anyhow::Result<Self> {
+            let ca_certs = match &options.ca_certs {
+                Some(pem) => {
+                    let mut root_cert_store = rustls::RootCertStore::empty();
+                    for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+                        root_cert_store.add(cert.context("--cacert")?)?;
+                    }
+                    anyhow::ensure!(!root_cert_store.is_empty(), "--cacert: no certificate");
+                    Some(root_cert_store)
+                }
+                None => None,
+            };
+            let client_cert = match &options.client_cert {
+                Some((cert, key)) => {
+                    let certs = rustls_pemfile::certs(&mut cert.as_slice())
+                        .collect::<Result<Vec<_>, _>>()
+                        .context("--cert")?;
+                    anyhow::ensure!(!certs.is_empty(), "--cert: no certificate");
+                    let key = rustls_pemfile::pkcs8_private_keys(&mut key.as_slice())
+                        .next()
+                        .context("--key: no PKCS#8 private key")?
+                        .context("--key")?;
+                    let key = PrivateKeyDer::from(key);
+                    rustls::crypto::ring::sign::any_supported_type(&key).context("--key")?;
+                    Some((certs, key))
+                }
+                None => None,
+            };
+            Ok(Self {
+                insecure: options.insecure,
+                sni: options.sni,
+                http2: options.http2,
+                session_resumption: options.session_resumption,
+                ca_certs,
+                client_cert,
+                config: Default::default(),
+            })
+        }
+
+        /// The session was resumed when the server certificate wasn't verified. 0-RTT early
+        /// data is never sent since requests aren't safe to replay.
+        async fn connect(
+            &self,
+            stream: TcpStream,
+            url: &Url,
+        ) -> Result<(Self::Stream, Option<bool>), ClientError> {
+            let tls = self
+                .config
+                .get_or_try_init(|| async { self.build_config() })
+                .await?;
+            let verified = Arc::new(AtomicBool::new(false));
+            // The clone shares the session cache
+            let mut config = tls.config.clone();
+            config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(RecordingVerifier {
+                    inner: tls.verifier.clone(),
+                    verified: verified.clone(),
+                }));
+            let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+            let domain = rustls_pki_types::ServerName::try_from(tls_host(url, self.sni.as_ref())?)?;
+            let stream = connector.connect(domain.to_owned(), stream).await?;
+
+            Ok((stream, Some(!verified.load(Ordering::Relaxed))))
+        }
+    }
+
+    /// A server certificate verifier that accepts any certificate.
+    #[derive(Debug)]
+    struct AcceptAnyServerCert;
+
+    impl rustls::client::danger::ServerCertVerifier for AcceptAnyServerCert {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &rustls_pki_types::CertificateDer<'_>,
+            _intermediates: &[rustls_pki_types::CertificateDer<'_>],
+            _server_name: &rustls_pki_types::ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: rustls_pki_types::UnixTime,
+        ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+            Ok(rustls::client::danger::ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            _message: &[u8],
+            _cert: &rustls_pki_types::CertificateDer<'_>,
+            _dss: &rustls::DigitallySignedStruct,
+        ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+            Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            _message: &[u8],
+            _cert: &rustls_pki_types::CertificateDer<'_>,
+            _dss: &rustls::DigitallySignedStruct,
+        ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+            Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+            rustls::crypto::ring::default_provider()
+                .signature_verification_algorithms
+                .supported_schemes()
+        }
+    }
+
+    /// Records that the server certificate was verified. A resumed handshake skips it.
+    #[derive(Debug)]
+    struct RecordingVerifier {
+        inner: Arc<dyn rustls::client::danger::ServerCertVerifier>,
+        verified: Arc<AtomicBool>,
+    }
+
+    impl rustls::client::danger::ServerCertVerifier for RecordingVerifier {
+        fn verify_server_cert(
+            &self,
+            end_entity: &rustls_pki_types::CertificateDer<'_>,
+            intermediates: &[rustls_pki_types::CertificateDer<'_>],
+            server_name: &rustls_pki_types::ServerName<'_>,
+            ocsp_response: &[u8],
+            now: rustls_pki_types::UnixTime,
+        ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+            self.verified.store(true, Ordering::Relaxed);
+            self.inner.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                ocsp_response,
+                now,
+            )
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &rustls_pki_types::CertificateDer<'_>,
+            dss: &rustls::DigitallySignedStruct,
+        ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+            self.inner.verify_tls12_signature(message, cert, dss)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &rustls_pki_types::CertificateDer<'_>,
+            dss: &rustls::DigitallySignedStruct,
+        ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+            self.inner.verify_tls13_signature(message, cert, dss)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+            self.inner.supported_verify_schemes()
+        }
+    }
+}
+
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+mod native_backend {
+    use anyhow::Context;
+    use tokio::net::TcpStream;
+    use url::Url;
+
+    use super::{tls_host, Sni, TlsConnector, TlsOptions};
+    use crate::client::ClientError;
+
+    /// One connector for all the connections
+    pub struct NativeTlsConnector {
+        connector: tokio_native_tls::TlsConnector,
+        sni: Option<Sni>,
+    }
+
+    impl TlsConnector for NativeTlsConnector {
+        type Stream = tokio_native_tls::TlsStream<TcpStream>;
+
+        const NAME: &'static str = "native-tls";
+
+        fn new(options: TlsOptions) -> anyhow::Result<Self> {
+            let mut builder = native_tls::TlsConnector::builder();
+            if options.insecure {
+                builder
+                    .danger_accept_invalid_certs(true)
+                    .danger_accept_invalid_hostnames(true);
+            }
+            if options.http2 {
+                builder.request_alpns(&["h2"]);
+            }
+            if let Some(pem) = &options.ca_certs {
+                let certs = super::pem_certificates(pem);
+                anyhow::ensure!(!certs.is_empty(), "--cacert: no certificate");
+                builder.disable_built_in_roots(true);
+                for cert in certs {
+                    builder.add_root_certificate(
+                        native_tls::Certificate::from_pem(cert).context("--cacert")?,
+                    );
+                }
+            }
+            if let Some((cert, key)) = &options.client_cert {
+                builder.identity(native_tls::Identity::from_pkcs8(cert, key).context("--cert")?);
+            }
+            Ok(Self {
+                connector: builder.build()?.into(),
+                sni: options.sni,
+            })
+        }
+
+        /// native-tls doesn't tell whether the session was resumed.
+        async fn connect(
+            &self,
+            stream: TcpStream,
+            url: &Url,
+        ) -> Result<(Self::Stream, Option<bool>), ClientError> {
+            let stream = self
+                .connector
+                .connect(&tls_host(url, self.sni.as_ref())?, stream)
+                .await?;
+            Ok((stream, None))
+        }
+    }
+}
+
+/// Split a PEM file into its certificates, since native-tls reads them one by one.
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+fn pem_certificates(pem: &[u8]) -> Vec<&[u8]> {
+    const END: &[u8] = b"-----END CERTIFICATE-----";
+    let mut certs = Vec::new();
+    let mut rest = pem;
+    while let Some(end) = rest.windows(END.len()).position(|w| w == END) {
+        certs.push(&rest[..end + END.len()]);
+        rest = &rest[end + END.len()..];
+    }
+    certs
+}
+
+/// A build without TLS fails every HTTPS connection.
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+pub struct NoTls;
+
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+impl TlsConnector for NoTls {
+    type Stream = TcpStream;
+
+    const NAME: &'static str = "none";
+
+    fn new(_options: TlsOptions) -> anyhow::Result<Self> {
+        Ok(Self)
+    }
+
+    async fn connect(
+        &self,
+        _stream: TcpStream,
+        _url: &Url,
+    ) -> Result<(Self::Stream, Option<bool>), ClientError> {
+        Err(ClientError::IoError(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "oha was built without TLS support",
+        )))
+    }
+}
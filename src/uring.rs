@@ -0,0 +1,547 @@
+use std::{
+    cell::{OnceCell, RefCell},
+    future::Future,
+    io::{self, IoSlice},
+    net::SocketAddr,
+    os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd},
+    pin::Pin,
+    task::{ready, Context, Poll, Waker},
+};
+
+use io_uring::{opcode, squeue, types, IoUring};
+use tokio::io::{unix::AsyncFd, AsyncRead, AsyncWrite, Interest, ReadBuf};
+
+/// Entries of the submission queue of each thread. The kernel makes the completion queue twice
+/// as large.
+const ENTRIES: u32 = 4096;
+/// Bytes received by one operation
+const READ_SIZE: usize = 16 * 1024;
+/// Bytes sent by one operation at most
+const WRITE_SIZE: usize = 64 * 1024;
+
+/// What the kernel reads from or writes to until the operation completes
+enum OpData {
+    None,
+    Buf(Vec<u8>),
+    /// Only kept alive
+    #[allow(dead_code)]
+    Addr(Box<socket2::SockAddr>),
+}
+
+enum Lifecycle {
+    Submitted,
+    Waiting(Waker),
+    Completed(i32),
+    /// Its stream is gone, so it's freed on completion
+    Orphaned,
+}
+
+struct Op {
+    lifecycle: Lifecycle,
+    data: OpData,
+}
+
+/// The operations in flight, by user_data
+#[derive(Default)]
+struct Ops {
+    entries: Vec<Option<Op>>,
+    free: Vec<usize>,
+}
+
+impl Ops {
+    fn insert(&mut self, op: Op) -> usize {
+        match self.free.pop() {
+            Some(id) => {
+                self.entries[id] = Some(op);
+                id
+            }
+            None => {
+                self.entries.push(Some(op));
+                self.entries.len() - 1
+            }
+        }
+    }
+
+    fn remove(&mut self, id: usize) -> Op {
+        self.free.push(id);
+        self.entries[id].take().unwrap()
+    }
+
+    fn get(&mut self, id: usize) -> &mut Op {
+        self.entries[id].as_mut().unwrap()
+    }
+}
+
+/// An io_uring of one thread. The submission queue is flushed with one syscall each time the
+/// runtime parks, and the completions are reaped when the ring's fd is readable.
+struct Ring {
+    ring: RefCell<IoUring>,
+    ops: RefCell<Ops>,
+}
+
+impl Ring {
+    fn new(entries: u32) -> io::Result<Self> {
+        Ok(Self {
+            ring: RefCell::new(IoUring::new(entries)?),
+            ops: Default::default(),
+        })
+    }
+
+    /// Queue an operation, which keeps `data` until it completes
+    ///
+    /// SAFETY: the buffers and addresses `entry` points to are owned by `data`, or outlive
+    /// the operation
+    unsafe fn push(&self, entry: squeue::Entry, data: OpData) -> io::Result<usize> {
+        let mut ring = self.ring.borrow_mut();
+        if ring.submission().is_full() {
+            submit(&ring)?;
+            if ring.submission().is_full() {
+                return Err(io::Error::from(io::ErrorKind::WouldBlock));
+            }
+        }
+
+        let mut ops = self.ops.borrow_mut();
+        let id = ops.insert(Op {
+            lifecycle: Lifecycle::Submitted,
+            data,
+        });
+        // SAFETY: as above, `data` is kept in `ops` until the completion
+        if unsafe { ring.submission().push(&entry.user_data(id as u64)) }.is_err() {
+            ops.remove(id);
+            return Err(io::Error::from(io::ErrorKind::WouldBlock));
+        }
+        Ok(id)
+    }
+
+    /// Record the completions and wake their tasks
+    fn reap(&self) {
+        let mut wakers = Vec::new();
+        {
+            let mut ring = self.ring.borrow_mut();
+            let mut ops = self.ops.borrow_mut();
+            for cqe in ring.completion() {
+                let id = cqe.user_data() as usize;
+                let op = ops.get(id);
+                match std::mem::replace(&mut op.lifecycle, Lifecycle::Completed(cqe.result())) {
+                    Lifecycle::Waiting(waker) => wakers.push(waker),
+                    Lifecycle::Orphaned => {
+                        ops.remove(id);
+                    }
+                    Lifecycle::Submitted | Lifecycle::Completed(_) => {}
+                }
+            }
+        }
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// The result of a completed operation with its data back
+    fn poll_op(&self, id: usize, cx: &mut Context<'_>) -> Poll<(i32, OpData)> {
+        let mut ops = self.ops.borrow_mut();
+        let op = ops.get(id);
+        match op.lifecycle {
+            Lifecycle::Completed(res) => Poll::Ready((res, ops.remove(id).data)),
+            _ => {
+                op.lifecycle = Lifecycle::Waiting(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Give up on an operation. Its data is kept until the kernel is done with it.
+    fn forget(&self, id: usize) {
+        let mut ops = self.ops.borrow_mut();
+        let op = ops.get(id);
+        match op.lifecycle {
+            Lifecycle::Completed(_) => {
+                ops.remove(id);
+            }
+            _ => op.lifecycle = Lifecycle::Orphaned,
+        }
+    }
+
+    /// Called each time the runtime parks
+    fn park(&self) {
+        let _ = submit(&self.ring.borrow());
+        self.reap();
+    }
+}
+
+/// Hand the queued operations to the kernel
+fn submit(ring: &IoUring) -> io::Result<()> {
+    match ring.submit() {
+        Ok(_) => Ok(()),
+        // Tried again on the next park
+        Err(err)
+            if matches!(
+                err.raw_os_error(),
+                Some(libc::EINTR | libc::EAGAIN | libc::EBUSY)
+            ) =>
+        {
+            Ok(())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        // The kernel may still write to the data of the operations in flight until the fd is
+        // closed, so it's leaked rather than freed
+        for op in self.ops.get_mut().entries.drain(..).flatten() {
+            if !matches!(op.lifecycle, Lifecycle::Completed(_)) {
+                std::mem::forget(op.data);
+            }
+        }
+    }
+}
+
+thread_local! {
+    static RING: OnceCell<Ring> = const { OnceCell::new() };
+}
+
+fn with_ring<T>(f: impl FnOnce(&Ring) -> T) -> io::Result<T> {
+    RING.with(|ring| match ring.get() {
+        Some(ring) => Ok(f(ring)),
+        None => Err(io::Error::other(
+            "io_uring is only used on the threads of --io-backend uring",
+        )),
+    })
+}
+
+/// Check that io_uring can be used, which a seccomp filter or `kernel.io_uring_disabled` may
+/// prevent
+pub fn probe() -> io::Result<()> {
+    Ring::new(2).map(drop)
+}
+
+/// Run `future` on a current-thread runtime with an io_uring for this thread
+pub fn block_on<F: Future>(future: F) -> io::Result<F::Output> {
+    let ring = Ring::new(ENTRIES)?;
+    RING.with(|cell| {
+        if cell.get().is_some() {
+            return Err(io::Error::other("this thread already has an io_uring"));
+        }
+        let _ = cell.set(ring);
+        Ok(())
+    })?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .on_thread_park(|| {
+            let _ = with_ring(Ring::park);
+        })
+        .build()?;
+    Ok(runtime.block_on(async {
+        let driver = tokio::spawn(drive());
+        let output = future.await;
+        driver.abort();
+        output
+    }))
+}
+
+/// Wake the tasks whose operations completed while the runtime wasn't parked
+async fn drive() -> io::Result<()> {
+    let fd = AsyncFd::with_interest(
+        with_ring(|ring| ring.ring.borrow().as_raw_fd())?,
+        Interest::READABLE,
+    )?;
+    loop {
+        let mut guard = fd.readable().await?;
+        with_ring(Ring::reap)?;
+        guard.clear_ready();
+    }
+}
+
+/// A TCP connection whose receives, sends and close go through the io_uring of its thread
+pub struct UringStream {
+    fd: Option<OwnedFd>,
+    /// Received but not read yet: `read_buf[read_pos..read_len]`
+    read_buf: Vec<u8>,
+    read_pos: usize,
+    read_len: usize,
+    read_op: Option<usize>,
+    write_buf: Vec<u8>,
+    /// A connect or a send
+    write_op: Option<usize>,
+}
+
+impl UringStream {
+    /// Connect to `addr` only, without falling back to other addresses
+    pub async fn connect(addr: SocketAddr) -> io::Result<Self> {
+        let domain = if addr.is_ipv4() {
+            libc::AF_INET
+        } else {
+            libc::AF_INET6
+        };
+        // SAFETY: no pointers
+        let fd = unsafe { libc::socket(domain, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut stream = Self {
+            // SAFETY: a new fd that's only owned here
+            fd: Some(unsafe { OwnedFd::from_raw_fd(fd) }),
+            read_buf: Vec::new(),
+            read_pos: 0,
+            read_len: 0,
+            read_op: None,
+            write_buf: Vec::new(),
+            write_op: None,
+        };
+
+        let addr = Box::new(socket2::SockAddr::from(addr));
+        let entry = opcode::Connect::new(types::Fd(fd), addr.as_ptr(), addr.len()).build();
+        // SAFETY: the address is boxed in the data of the operation
+        stream.write_op = Some(with_ring(|ring| unsafe {
+            ring.push(entry, OpData::Addr(addr))
+        })??);
+        let res = std::future::poll_fn(|cx| {
+            let id = stream.write_op.unwrap();
+            let (res, _) = ready!(with_ring(|ring| ring.poll_op(id, cx))?);
+            stream.write_op = None;
+            Poll::Ready(Ok::<_, io::Error>(res))
+        })
+        .await?;
+        if res < 0 {
+            return Err(io::Error::from_raw_os_error(-res));
+        }
+        socket2::SockRef::from(stream.fd.as_ref().unwrap()).set_nodelay(true)?;
+        Ok(stream)
+    }
+
+    fn raw_fd(&self) -> RawFd {
+        self.fd.as_ref().unwrap().as_raw_fd()
+    }
+
+    pub fn socket(&self) -> socket2::SockRef<'_> {
+        socket2::SockRef::from(self.fd.as_ref().unwrap())
+    }
+
+    /// The peer closed the connection, or it failed, while it was idle
+    pub fn is_closed(&self) -> bool {
+        if self.read_pos < self.read_len || self.read_op.is_some() {
+            return false;
+        }
+        let mut byte = 0u8;
+        // SAFETY: one byte is written at most
+        let n = unsafe {
+            libc::recv(
+                self.raw_fd(),
+                (&mut byte as *mut u8).cast(),
+                1,
+                libc::MSG_PEEK | libc::MSG_DONTWAIT,
+            )
+        };
+        n == 0 || (n < 0 && io::Error::last_os_error().kind() != io::ErrorKind::WouldBlock)
+    }
+
+    fn poll_write_op(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        let id = self.write_op.unwrap();
+        let (res, data) = ready!(with_ring(|ring| ring.poll_op(id, cx))?);
+        self.write_op = None;
+        if let OpData::Buf(buf) = data {
+            self.write_buf = buf;
+        }
+        if res < 0 {
+            return Poll::Ready(Err(io::Error::from_raw_os_error(-res)));
+        }
+        Poll::Ready(Ok(res as usize))
+    }
+
+    /// Copy as much of `bufs` as fits into one send
+    fn start_write(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<()> {
+        let mut buf = std::mem::take(&mut self.write_buf);
+        buf.clear();
+        for slice in bufs {
+            let n = slice.len().min(WRITE_SIZE - buf.len());
+            buf.extend_from_slice(&slice[..n]);
+            if buf.len() == WRITE_SIZE {
+                break;
+            }
+        }
+        let entry = opcode::Send::new(types::Fd(self.raw_fd()), buf.as_ptr(), buf.len() as u32)
+            .flags(libc::MSG_NOSIGNAL)
+            .build();
+        // SAFETY: the buffer is the data of the operation, whose heap allocation doesn't move
+        self.write_op = Some(with_ring(|ring| unsafe {
+            ring.push(entry, OpData::Buf(buf))
+        })??);
+        Ok(())
+    }
+}
+
+impl AsyncRead for UringStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        out: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.read_pos < this.read_len {
+                let n = out.remaining().min(this.read_len - this.read_pos);
+                out.put_slice(&this.read_buf[this.read_pos..this.read_pos + n]);
+                this.read_pos += n;
+                return Poll::Ready(Ok(()));
+            }
+            match this.read_op {
+                Some(id) => {
+                    let (res, data) = ready!(with_ring(|ring| ring.poll_op(id, cx))?);
+                    this.read_op = None;
+                    if let OpData::Buf(buf) = data {
+                        this.read_buf = buf;
+                    }
+                    if res < 0 {
+                        return Poll::Ready(Err(io::Error::from_raw_os_error(-res)));
+                    }
+                    this.read_pos = 0;
+                    this.read_len = res as usize;
+                    if res == 0 {
+                        // EOF
+                        return Poll::Ready(Ok(()));
+                    }
+                }
+                None => {
+                    if out.remaining() == 0 {
+                        return Poll::Ready(Ok(()));
+                    }
+                    let mut buf = std::mem::take(&mut this.read_buf);
+                    buf.resize(READ_SIZE, 0);
+                    let entry = opcode::Recv::new(
+                        types::Fd(this.raw_fd()),
+                        buf.as_mut_ptr(),
+                        buf.len() as u32,
+                    )
+                    .build();
+                    // SAFETY: the buffer is the data of the operation, whose heap allocation
+                    // doesn't move
+                    this.read_op = Some(with_ring(|ring| unsafe {
+                        ring.push(entry, OpData::Buf(buf))
+                    })??);
+                }
+            }
+        }
+    }
+}
+
+impl AsyncWrite for UringStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_vectored(cx, &[IoSlice::new(buf)])
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.write_op.is_none() {
+            if bufs.iter().all(|buf| buf.is_empty()) {
+                return Poll::Ready(Ok(0));
+            }
+            this.start_write(bufs)?;
+        }
+        // A write returns once its bytes are sent, so that the caller moves on with the rest
+        this.poll_write_op(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // SAFETY: no pointers
+        if unsafe { libc::shutdown(self.raw_fd(), libc::SHUT_WR) } != 0 {
+            return Poll::Ready(Err(io::Error::last_os_error()));
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for UringStream {
+    fn drop(&mut self) {
+        let Some(fd) = self.fd.take() else {
+            return;
+        };
+        let ops = [self.read_op, self.write_op];
+        let _ = with_ring(move |ring| {
+            if ops.iter().any(Option::is_some) {
+                // Ends the operations in flight early
+                // SAFETY: no pointers
+                unsafe { libc::shutdown(fd.as_raw_fd(), libc::SHUT_RDWR) };
+            }
+            for id in ops.into_iter().flatten() {
+                ring.forget(id);
+            }
+            // Closed with the next submission instead of a syscall of its own
+            let raw = fd.into_raw_fd();
+            let close = opcode::Close::new(types::Fd(raw)).build();
+            // SAFETY: no pointers
+            match unsafe { ring.push(close, OpData::None) } {
+                Ok(id) => ring.forget(id),
+                Err(_) => {
+                    // SAFETY: the fd isn't owned by anything else
+                    drop(unsafe { OwnedFd::from_raw_fd(raw) });
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_echo() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            std::io::copy(&mut socket.try_clone().unwrap(), &mut socket).unwrap();
+        });
+
+        let received = std::thread::spawn(move || {
+            block_on(async move {
+                let mut stream = UringStream::connect(addr).await.unwrap();
+                assert!(!stream.is_closed());
+                let data = vec![7u8; 3 * WRITE_SIZE];
+                stream.write_all(&data).await.unwrap();
+                stream.shutdown().await.unwrap();
+                let mut received = Vec::new();
+                stream.read_to_end(&mut received).await.unwrap();
+                assert!(stream.is_closed());
+                received
+            })
+            .unwrap()
+        })
+        .join()
+        .unwrap();
+        server.join().unwrap();
+        assert_eq!(received, vec![7u8; 3 * WRITE_SIZE]);
+    }
+
+    #[test]
+    fn test_without_ring() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let err = runtime
+            .block_on(UringStream::connect("127.0.0.1:1".parse().unwrap()))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("--io-backend uring"));
+    }
+}
@@ -0,0 +1,337 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use url::Url;
+
+use crate::url_generator;
+
+#[derive(Error, Debug)]
+pub enum UrlListError {
+    #[error("failed to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("the list has no URL")]
+    Empty,
+    #[error("line {0}: invalid URL {1:?}: {2}")]
+    Url(usize, String, url::ParseError),
+    #[error("line {0}: {1} isn't on {2}. The URLs must have the scheme, host and port of the URL")]
+    OtherOrigin(usize, String, String),
+    #[error("line {0}: invalid field {1:?}. Expected weight=N or max_concurrent=N with N > 0")]
+    Field(usize, String),
+}
+
+/// A URL of `--urls-from-file` and how it has been requested
+pub struct UrlEntry {
+    pub url: Url,
+    /// Relative chance of the URL to be picked
+    pub weight: u32,
+    pub max_concurrent: Option<usize>,
+    semaphore: Option<Arc<Semaphore>>,
+    in_flight: AtomicUsize,
+    peak: AtomicUsize,
+    requests: AtomicU64,
+    /// Times the URL was picked while it was full
+    blocked: AtomicU64,
+    /// Total time of its requests, in nanoseconds
+    busy: AtomicU64,
+}
+
+impl UrlEntry {
+    fn has_room(&self) -> bool {
+        self.semaphore
+            .as_ref()
+            .is_none_or(|semaphore| semaphore.available_permits() > 0)
+    }
+
+    /// Requests sent to the URL
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Times the URL was picked while it was full, so another URL was requested instead
+    pub fn blocked(&self) -> u64 {
+        self.blocked.load(Ordering::Relaxed)
+    }
+
+    /// The most requests in flight to the URL at once
+    pub fn peak_concurrency(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    /// The average number of requests in flight to the URL over `duration`
+    pub fn average_concurrency(&self, duration: Duration) -> f64 {
+        Duration::from_nanos(self.busy.load(Ordering::Relaxed)).as_secs_f64()
+            / duration.as_secs_f64()
+    }
+}
+
+/// The URLs of `--urls-from-file`, picked by weight for each request. A URL with
+/// `max_concurrent` has a semaphore of that many permits, and a request holds one of them until
+/// it's done.
+pub struct UrlList {
+    entries: Vec<UrlEntry>,
+}
+
+/// A slot of a URL for a request. The URL has one less request in flight once it's dropped.
+pub struct UrlPermit<'a> {
+    entry: &'a UrlEntry,
+    _permit: Option<OwnedSemaphorePermit>,
+    start: Instant,
+}
+
+impl<'a> UrlPermit<'a> {
+    fn new(entry: &'a UrlEntry, permit: Option<OwnedSemaphorePermit>) -> Self {
+        let in_flight = entry.in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+        entry.peak.fetch_max(in_flight, Ordering::Relaxed);
+        entry.requests.fetch_add(1, Ordering::Relaxed);
+        Self {
+            entry,
+            _permit: permit,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn url(&self) -> &'a Url {
+        &self.entry.url
+    }
+}
+
+impl Drop for UrlPermit<'_> {
+    fn drop(&mut self) {
+        self.entry.in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.entry
+            .busy
+            .fetch_add(self.start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+impl UrlList {
+    pub fn load(path: &Path, base: &Url) -> Result<Self, UrlListError> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| UrlListError::Io(path.to_path_buf(), e))?;
+        Self::parse(&text, base)
+    }
+
+    /// Parse a URL on each line, relative to `base`, optionally followed by `weight=N` and
+    /// `max_concurrent=N`. Empty lines and lines starting with `#` are skipped.
+    pub fn parse(text: &str, base: &Url) -> Result<Self, UrlListError> {
+        let mut entries = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let n = i + 1;
+            let mut fields = line.split_whitespace();
+            let Some(url) = fields.next().filter(|url| !url.starts_with('#')) else {
+                continue;
+            };
+            let mut url = base
+                .join(url)
+                .map_err(|e| UrlListError::Url(n, url.to_string(), e))?;
+            url_generator::normalize(&mut url);
+            if url.origin() != base.origin() {
+                return Err(UrlListError::OtherOrigin(
+                    n,
+                    url.to_string(),
+                    base.origin().ascii_serialization(),
+                ));
+            }
+
+            let mut weight = 1;
+            let mut max_concurrent = None;
+            for field in fields {
+                let invalid = || UrlListError::Field(n, field.to_string());
+                match field.split_once('=') {
+                    Some(("weight", w)) => {
+                        weight = w.parse().ok().filter(|&w| w > 0).ok_or_else(invalid)?
+                    }
+                    Some(("max_concurrent", m)) => {
+                        max_concurrent =
+                            Some(m.parse().ok().filter(|&m| m > 0).ok_or_else(invalid)?)
+                    }
+                    _ => return Err(invalid()),
+                }
+            }
+            entries.push(UrlEntry {
+                url,
+                weight,
+                max_concurrent,
+                semaphore: max_concurrent.map(|m| Arc::new(Semaphore::new(m))),
+                in_flight: AtomicUsize::new(0),
+                peak: AtomicUsize::new(0),
+                requests: AtomicU64::new(0),
+                blocked: AtomicU64::new(0),
+                busy: AtomicU64::new(0),
+            });
+        }
+        if entries.is_empty() {
+            return Err(UrlListError::Empty);
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> &[UrlEntry] {
+        &self.entries
+    }
+
+    /// Pick a URL by weight among those which `eligible` accepts
+    fn pick<R: Rng>(&self, rng: &mut R, eligible: impl Fn(&UrlEntry) -> bool) -> Option<usize> {
+        let total: u64 = self
+            .entries
+            .iter()
+            .filter(|e| eligible(e))
+            .map(|e| u64::from(e.weight))
+            .sum();
+        if total == 0 {
+            return None;
+        }
+        let mut r = rng.gen_range(0..total);
+        self.entries.iter().position(|e| {
+            if !eligible(e) {
+                return false;
+            }
+            match r.checked_sub(u64::from(e.weight)) {
+                Some(rest) => {
+                    r = rest;
+                    false
+                }
+                None => true,
+            }
+        })
+    }
+
+    fn try_acquire(&self, i: usize) -> Option<UrlPermit<'_>> {
+        let entry = &self.entries[i];
+        match &entry.semaphore {
+            Some(semaphore) => semaphore
+                .clone()
+                .try_acquire_owned()
+                .ok()
+                .map(|permit| UrlPermit::new(entry, Some(permit))),
+            None => Some(UrlPermit::new(entry, None)),
+        }
+    }
+
+    /// Pick a URL by weight. When it's full, the request goes to another URL with room instead,
+    /// and waits for the first free slot when all of them are full.
+    pub async fn acquire<R: Rng>(&self, rng: &mut R) -> UrlPermit<'_> {
+        let first = self.pick(rng, |_| true).expect("the list isn't empty");
+        if let Some(permit) = self.try_acquire(first) {
+            return permit;
+        }
+        self.entries[first].blocked.fetch_add(1, Ordering::Relaxed);
+
+        while let Some(i) = self.pick(rng, UrlEntry::has_room) {
+            if let Some(permit) = self.try_acquire(i) {
+                return permit;
+            }
+        }
+
+        // Every URL has max_concurrent requests in flight
+        let waits = self.entries.iter().filter_map(|entry| {
+            let semaphore = entry.semaphore.clone()?;
+            Some(Box::pin(async move {
+                let permit = semaphore.acquire_owned().await.expect("never closed");
+                UrlPermit::new(entry, Some(permit))
+            }))
+        });
+        futures::future::select_all(waits).await.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn base() -> Url {
+        Url::parse("http://example.com/api/").unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let list = UrlList::parse(
+            "# browse and checkout\n/browse weight=9\n\ncheckout?x=1 max_concurrent=5 weight=1\nhttp://example.com/home\n",
+            &base(),
+        )
+        .unwrap();
+        let entries = list.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].url.as_str(), "http://example.com/browse");
+        assert_eq!(entries[0].weight, 9);
+        assert_eq!(entries[0].max_concurrent, None);
+        assert_eq!(
+            entries[1].url.as_str(),
+            "http://example.com/api/checkout?x=1"
+        );
+        assert_eq!(entries[1].max_concurrent, Some(5));
+        assert_eq!(entries[2].weight, 1);
+
+        assert!(matches!(
+            UrlList::parse("http://other.com/", &base()),
+            Err(UrlListError::OtherOrigin(1, _, _))
+        ));
+        assert!(matches!(
+            UrlList::parse("/a\n/b max_concurrent=0", &base()),
+            Err(UrlListError::Field(2, _))
+        ));
+        assert!(matches!(
+            UrlList::parse("/a limit=3", &base()),
+            Err(UrlListError::Field(1, _))
+        ));
+        assert!(matches!(
+            UrlList::parse("# nothing\n", &base()),
+            Err(UrlListError::Empty)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_acquire_redirects() {
+        let list = UrlList::parse("/slow weight=100 max_concurrent=2\n/fast", &base()).unwrap();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut permits = Vec::new();
+        for _ in 0..10 {
+            permits.push(list.acquire(&mut rng).await);
+        }
+        let slow = &list.entries()[0];
+        let fast = &list.entries()[1];
+        // Picked almost always, but never more than 2 at once
+        assert_eq!(slow.requests(), 2);
+        assert_eq!(slow.peak_concurrency(), 2);
+        assert_eq!(fast.requests(), 8);
+        assert!(slow.blocked() >= 7, "{}", slow.blocked());
+
+        drop(permits);
+        assert_eq!(slow.in_flight.load(Ordering::Relaxed), 0);
+        assert!(list.acquire(&mut rng).await.url().path().ends_with("/slow"));
+    }
+
+    #[tokio::test]
+    async fn test_acquire_waits_when_all_full() {
+        let list = UrlList::parse("/a max_concurrent=1\n/b max_concurrent=1", &base()).unwrap();
+        let list = Arc::new(list);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let a = list.acquire(&mut rng).await;
+        let b = list.acquire(&mut rng).await;
+        assert_ne!(a.url(), b.url());
+        let freed = a.url().clone();
+
+        let waiting = tokio::spawn({
+            let list = list.clone();
+            async move {
+                let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+                list.acquire(&mut rng).await.url().clone()
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+        drop(a);
+        assert_eq!(waiting.await.unwrap(), freed);
+        drop(b);
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use futures::{SinkExt, StreamExt};
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+use thiserror::Error;
+use tokio_tungstenite::{
+    tungstenite::{protocol::Role, Message},
+    WebSocketStream,
+};
+
+/// Options of the WebSocket mode, enabled by `ws://` and `wss://` URLs or `--ws`.
+pub struct WebSocketOptions {
+    /// The message sent on each round trip. Sent as a text frame if it's UTF-8.
+    pub payload: bytes::Bytes,
+    /// Reconnect after this many messages
+    pub messages_per_connection: Option<usize>,
+    /// `--ws-ping-interval`
+    pub ping_interval: Option<Duration>,
+}
+
+/// Events on WebSocket connections which aren't recorded in the results
+#[derive(Default)]
+pub struct WebSocketCounters {
+    pub handshakes: AtomicUsize,
+    pub pings: AtomicUsize,
+}
+
+#[derive(Error, Debug)]
+pub enum WebSocketError {
+    #[error("WebSocket handshake failed: {0}")]
+    Handshake(String),
+    #[error("WebSocket message timeout")]
+    Timeout,
+    #[error("WebSocket closed by the server")]
+    Closed,
+    #[error(transparent)]
+    // Use Box here because tungstenite::Error is big.
+    Protocol(Box<tokio_tungstenite::tungstenite::Error>),
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for WebSocketError {
+    fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
+        Self::Protocol(Box::new(err))
+    }
+}
+
+type Stream = WebSocketStream<TokioIo<Upgraded>>;
+
+/// A WebSocket connection after the handshake
+pub struct WebSocketConnection {
+    stream: Arc<tokio::sync::Mutex<Stream>>,
+    /// Messages sent on this connection
+    pub messages: usize,
+}
+
+impl WebSocketConnection {
+    pub async fn new(
+        upgraded: Upgraded,
+        options: &WebSocketOptions,
+        counters: &Arc<WebSocketCounters>,
+    ) -> Self {
+        counters.handshakes.fetch_add(1, Ordering::Relaxed);
+        let stream =
+            WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Client, None).await;
+        let stream = Arc::new(tokio::sync::Mutex::new(stream));
+        if let Some(interval) = options.ping_interval {
+            tokio::spawn(ping(Arc::downgrade(&stream), interval, counters.clone()));
+        }
+        Self {
+            stream,
+            messages: 0,
+        }
+    }
+
+    /// Send the payload and wait for the first data frame from the server.
+    /// Returns when it arrived and its length.
+    pub async fn round_trip(&mut self, payload: &[u8]) -> Result<(Instant, usize), WebSocketError> {
+        let message = match std::str::from_utf8(payload) {
+            Ok(text) => Message::Text(text.to_string()),
+            Err(_) => Message::Binary(payload.to_vec()),
+        };
+        let mut stream = self.stream.lock().await;
+        self.messages += 1;
+        stream.send(message).await?;
+        loop {
+            match stream.next().await.ok_or(WebSocketError::Closed)?? {
+                Message::Text(text) => return Ok((Instant::now(), text.len())),
+                Message::Binary(data) => return Ok((Instant::now(), data.len())),
+                Message::Close(_) => return Err(WebSocketError::Closed),
+                // Pings are answered by tungstenite and pongs are for `ping`
+                Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {}
+            }
+        }
+    }
+
+    /// Send a close frame, and wait for the close frame from the server for a while
+    /// so that the server doesn't see an abnormal closure.
+    pub async fn close(self) {
+        let mut stream = self.stream.lock().await;
+        let _ = tokio::time::timeout(Duration::from_secs(1), async {
+            stream.close(None).await?;
+            while stream.next().await.transpose()?.is_some() {}
+            Ok::<_, tokio_tungstenite::tungstenite::Error>(())
+        })
+        .await;
+    }
+}
+
+/// Send a ping every `interval` while the connection is idle. Stops when the connection is dropped.
+async fn ping(
+    stream: Weak<tokio::sync::Mutex<Stream>>,
+    interval: Duration,
+    counters: Arc<WebSocketCounters>,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let Some(stream) = stream.upgrade() else {
+            return;
+        };
+        // A connection in a round trip isn't idle
+        let Ok(mut stream) = stream.try_lock() else {
+            continue;
+        };
+        if stream.send(Message::Ping(Vec::new())).await.is_err() {
+            return;
+        }
+        counters.pings.fetch_add(1, Ordering::Relaxed);
+    }
+}
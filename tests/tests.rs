@@ -34,6 +34,46 @@ async fn bind_port_ipv6() -> (tokio::net::TcpListener, u16) {
     (tokio::net::TcpListener::bind(addr).await.unwrap(), port)
 }
 
+/// Serve the app on a port of its own
+async fn serve(app: Router) -> u16 {
+    let (listener, port) = bind_port().await;
+    tokio::spawn(async { axum::serve(listener, app).await });
+    port
+}
+
+/// Run oha with `--no-tui --json`, the arguments and the URL on a blocking thread, so that the
+/// server of the test keeps serving
+async fn run_oha(args: &[&str], url: String) -> std::process::Output {
+    let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+    tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["--no-tui", "--json"])
+            .args(args)
+            .arg(url)
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap()
+}
+
+/// The JSON summary of a successful run
+fn summary(output: &std::process::Output) -> serde_json::Value {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+/// Run oha against the app at / and return the JSON summary of the run, which must succeed
+async fn run_json(app: Router, args: &[&str]) -> serde_json::Value {
+    let port = serve(app).await;
+    summary(&run_oha(args, format!("http://127.0.0.1:{port}/")).await)
+}
+
 async fn get_header_body(args: &[&str]) -> (HeaderMap, bytes::Bytes) {
     let (tx, rx) = flume::unbounded();
 
@@ -52,8 +92,67 @@ async fn get_header_body(args: &[&str]) -> (HeaderMap, bytes::Bytes) {
         ),
     );
 
-    let (listener, port) = bind_port().await;
-    tokio::spawn(async { axum::serve(listener, app).await });
+    let port = serve(app).await;
+
+    let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+    tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui"])
+            .args(args)
+            .arg(format!("http://127.0.0.1:{port}"))
+            .assert()
+            .success();
+    })
+    .await
+    .unwrap();
+
+    rx.try_recv().unwrap()
+}
+
+async fn get_header_with_userinfo(userinfo: &'static str, args: &[&str]) -> HeaderMap {
+    let (tx, rx) = flume::unbounded();
+
+    let app = Router::new().route(
+        "/",
+        get(|header: HeaderMap| async move {
+            tx.send(header).unwrap();
+            "Hello World"
+        }),
+    );
+
+    let port = serve(app).await;
+
+    let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+    tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui"])
+            .args(args)
+            .arg(format!("http://{userinfo}@127.0.0.1:{port}/"))
+            .assert()
+            .success();
+    })
+    .await
+    .unwrap();
+
+    rx.try_recv().unwrap()
+}
+
+async fn get_method_header_body(args: &[&str]) -> (http::method::Method, HeaderMap, Bytes) {
+    let (tx, rx) = flume::unbounded();
+
+    let app = Router::new().route(
+        "/",
+        any(
+            |method: http::method::Method, header: HeaderMap, body: Bytes| async move {
+                tx.send((method, header, body)).unwrap();
+                "Hello World"
+            },
+        ),
+    );
+
+    let port = serve(app).await;
 
     let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
     tokio::task::spawn_blocking(move || {
@@ -81,8 +180,7 @@ async fn get_method(args: &[&str]) -> http::method::Method {
         }),
     );
 
-    let (listener, port) = bind_port().await;
-    tokio::spawn(async { axum::serve(listener, app).await });
+    let port = serve(app).await;
 
     let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
 
@@ -112,8 +210,7 @@ async fn get_query(p: &'static str, args: &[&str]) -> String {
         }),
     );
 
-    let (listener, port) = bind_port().await;
-    tokio::spawn(async { axum::serve(listener, app).await });
+    let port = serve(app).await;
 
     let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
 
@@ -174,7 +271,7 @@ async fn get_path_rand_regex(p: &'static str, args: &[&str]) -> String {
     rx.try_recv().unwrap()
 }
 
-async fn redirect(n: usize, is_relative: bool, limit: usize) -> bool {
+async fn redirect(n: usize, is_relative: bool, limit: usize, args: &[&str]) -> bool {
     let (tx, rx) = flume::unbounded();
 
     let (listener, port) = bind_port().await;
@@ -195,11 +292,13 @@ async fn redirect(n: usize, is_relative: bool, limit: usize) -> bool {
 
     tokio::spawn(async { axum::serve(listener, app).await });
 
+    let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
     tokio::task::spawn_blocking(move || {
         Command::cargo_bin("oha")
             .unwrap()
             .args(["-n", "1", "--no-tui", "--redirect"])
             .arg(limit.to_string())
+            .args(args)
             .arg(format!("http://127.0.0.1:{port}/0"))
             .assert()
             .success();
@@ -222,8 +321,7 @@ async fn get_host_with_connect_to(host: &'static str) -> String {
         }),
     );
 
-    let (listener, port) = bind_port().await;
-    tokio::spawn(async { axum::serve(listener, app).await });
+    let port = serve(app).await;
 
     tokio::task::spawn_blocking(move || {
         Command::cargo_bin("oha")
@@ -282,8 +380,7 @@ async fn get_host_with_connect_to_ipv6_requested() -> String {
         }),
     );
 
-    let (listener, port) = bind_port().await;
-    tokio::spawn(async { axum::serve(listener, app).await });
+    let port = serve(app).await;
 
     tokio::task::spawn_blocking(move || {
         Command::cargo_bin("oha")
@@ -301,6 +398,37 @@ async fn get_host_with_connect_to_ipv6_requested() -> String {
     rx.try_recv().unwrap()
 }
 
+/// Send a request to an IPv6 server and return the Host header and the port.
+/// `url` and `args` take the port.
+async fn get_host_ipv6(url: fn(u16) -> String, args: fn(u16) -> Vec<String>) -> (String, u16) {
+    let (tx, rx) = flume::unbounded();
+    let app = Router::new().route(
+        "/",
+        get(|header: HeaderMap| async move {
+            tx.send(header.get("host").unwrap().to_str().unwrap().to_string())
+                .unwrap();
+            "Hello World"
+        }),
+    );
+
+    let (listener, port) = bind_port_ipv6().await;
+    tokio::spawn(async { axum::serve(listener, app).await });
+
+    tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui"])
+            .args(args(port))
+            .arg(url(port))
+            .assert()
+            .success();
+    })
+    .await
+    .unwrap();
+
+    (rx.try_recv().unwrap(), port)
+}
+
 async fn get_host_with_connect_to_redirect(host: &'static str) -> String {
     let (tx, rx) = flume::unbounded();
 
@@ -317,8 +445,7 @@ async fn get_host_with_connect_to_redirect(host: &'static str) -> String {
             }),
         );
 
-    let (listener, port) = bind_port().await;
-    tokio::spawn(async { axum::serve(listener, app).await });
+    let port = serve(app).await;
 
     tokio::task::spawn_blocking(move || {
         Command::cargo_bin("oha")
@@ -347,8 +474,7 @@ async fn burst_10_req_delay_2s_rate_4(iteration: u8, args: &[&str]) -> usize {
         }),
     );
 
-    let (listener, port) = bind_port().await;
-    tokio::spawn(async { axum::serve(listener, app).await });
+    let port = serve(app).await;
 
     let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
     tokio::task::spawn_blocking(move || {
@@ -391,6 +517,8 @@ async fn test_enable_compression_default() {
 
     assert!(accept_encoding.contains(&"gzip"));
     assert!(accept_encoding.contains(&"br"));
+    // oha can't decode it
+    assert!(!accept_encoding.contains(&"compress"));
 
     let header = get_header_body(&["--http2"]).await.0;
     let accept_encoding: Vec<&str> = header
@@ -416,8 +544,7 @@ async fn get_http_version(args: &[&str]) -> http::Version {
         }),
     );
 
-    let (listener, port) = bind_port().await;
-    tokio::spawn(async { axum::serve(listener, app).await });
+    let port = serve(app).await;
 
     let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
     tokio::task::spawn_blocking(move || {
@@ -448,6 +575,203 @@ async fn test_setting_custom_header() {
     assert_eq!(header.get("foo").unwrap().to_str().unwrap(), "bar");
     let header = get_header_body(&["--http2", "-H", "foo:bar", "--"]).await.0;
     assert_eq!(header.get("foo").unwrap().to_str().unwrap(), "bar");
+
+    let header = get_header_body(&["-H", " X-Foo : bar ", "--"]).await.0;
+    assert_eq!(header.get("x-foo").unwrap().to_str().unwrap(), "bar");
+}
+
+/// The head of the one request `oha -n 1 <args> <url>` sends, as it's serialized on the wire
+async fn serialized_request(args: &[&str]) -> String {
+    serialized_request_to(args, "/").await
+}
+
+/// `serialized_request` with the path and query of the URL
+async fn serialized_request_to(args: &[&str], path: &str) -> String {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    let (listener, port) = bind_port().await;
+    let server = tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut tcp = tokio::io::BufReader::new(tcp);
+        let mut head = String::new();
+        while !head.ends_with("\r\n\r\n") {
+            if tcp.read_line(&mut head).await.unwrap() == 0 {
+                break;
+            }
+        }
+        tcp.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .await
+            .unwrap();
+        head
+    });
+
+    let mut command = Command::cargo_bin("oha").unwrap();
+    command
+        .args(["-n", "1", "-c", "1", "--no-tui"])
+        .args(args)
+        .arg(format!("http://127.0.0.1:{port}{path}"));
+    tokio::task::spawn_blocking(move || command.assert().success())
+        .await
+        .unwrap();
+    server.await.unwrap()
+}
+
+#[tokio::test]
+async fn test_url_encoding() {
+    let urls = tempfile::NamedTempFile::new().unwrap();
+    for (path, target) in [
+        ("/search?q=hello world", "/search?q=hello%20world"),
+        ("/caf\u{e9}?q=\u{e9}", "/caf%C3%A9?q=%C3%A9"),
+        ("/a%20b/100%?x=%2F", "/a%20b/100%25?x=%2F"),
+        ("/a|b?q={x}", "/a%7Cb?q=%7Bx%7D"),
+        ("/a?b#frag", "/a?b"),
+    ] {
+        // The URL of the command line, sent without hyper and with it
+        for args in [&["--prebuilt-requests"][..], &[]] {
+            let head = serialized_request_to(args, path).await;
+            assert_eq!(
+                head.lines().next().unwrap(),
+                format!("GET {target} HTTP/1.1"),
+                "{path:?} {args:?}"
+            );
+        }
+        // A URL of --urls-from-file, where whitespace separates the fields
+        if path.contains(' ') {
+            continue;
+        }
+        std::fs::write(urls.path(), path).unwrap();
+        let head =
+            serialized_request_to(&["--urls-from-file", urls.path().to_str().unwrap()], "/").await;
+        assert_eq!(
+            head.lines().next().unwrap(),
+            format!("GET {target} HTTP/1.1"),
+            "{path:?}"
+        );
+    }
+}
+
+/// The values of the header `name` in a serialized request
+fn header_lines<'a>(head: &'a str, name: &str) -> Vec<&'a str> {
+    head.lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_header_precedence() {
+    // Prebuilt requests and requests built by hyper
+    for extra in [&["--prebuilt-requests"][..], &[]] {
+        let cases: &[(&[&str], &str, &str, &str)] = &[
+            (&[], "{authority}", "*/*", ""),
+            (
+                &[
+                    "-H",
+                    "Host: h.example",
+                    "-H",
+                    "Accept: a/b",
+                    "-H",
+                    "Content-Type: c/d",
+                ],
+                "h.example",
+                "a/b",
+                "c/d",
+            ),
+            (
+                &["--host", "o.example", "-A", "x/y", "-T", "z/w"],
+                "o.example",
+                "x/y",
+                "z/w",
+            ),
+            (
+                &[
+                    "-H",
+                    "host: h.example",
+                    "--host",
+                    "o.example",
+                    "-H",
+                    "accept: a/b",
+                    "-A",
+                    "x/y",
+                    "-H",
+                    "content-type: c/d",
+                    "-T",
+                    "z/w",
+                ],
+                "o.example",
+                "x/y",
+                "z/w",
+            ),
+            (
+                &["-F", "a=1", "-H", "Content-Type: c/d"],
+                "{authority}",
+                "*/*",
+                "c/d",
+            ),
+        ];
+        for (args, host, accept, content_type) in cases {
+            let args = [*args, extra].concat();
+            let head = serialized_request(&args).await;
+            let hosts = header_lines(&head, "host");
+            assert_eq!(hosts.len(), 1, "{head}");
+            if *host == "{authority}" {
+                assert!(hosts[0].starts_with("127.0.0.1:"), "{head}");
+            } else {
+                assert_eq!(hosts[0], *host, "{head}");
+            }
+            assert_eq!(header_lines(&head, "accept"), [*accept], "{head}");
+            let content_types = header_lines(&head, "content-type");
+            if content_type.is_empty() {
+                assert!(content_types.is_empty(), "{head}");
+            } else {
+                assert_eq!(content_types, [*content_type], "{head}");
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_header_file() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), "# sent by each request\nfoo: 1\n\nbar : 2\n").unwrap();
+    let path = file.path().to_str().unwrap();
+    let header = get_header_body(&["--header-file", path, "-H", "bar: 3"])
+        .await
+        .0;
+    assert_eq!(header.get("foo").unwrap().to_str().unwrap(), "1");
+    assert_eq!(header.get("bar").unwrap().to_str().unwrap(), "3");
+}
+
+#[test]
+fn test_invalid_header() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), "foo: 1\n: 2\n").unwrap();
+    for (args, message) in [
+        (
+            &["-H", "foo: 1", "-H", " : bar"][..],
+            "-H #2 \" : bar\": empty header name",
+        ),
+        (
+            &["-H", "X Foo: bar"],
+            "-H #1 \"X Foo: bar\": invalid character ' ' in the header name at byte 1",
+        ),
+        (
+            &["--header-file", file.path().to_str().unwrap()],
+            "line 2 \": 2\": empty header name",
+        ),
+    ] {
+        let output = Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui"])
+            .args(args)
+            .arg("http://127.0.0.1:1/")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(message), "{stderr}");
+    }
 }
 
 #[tokio::test]
@@ -481,249 +805,4728 @@ async fn test_setting_body() {
 }
 
 #[tokio::test]
-async fn test_setting_content_type_header() {
-    let header = get_header_body(&["-T", "text/html"]).await.0;
-    assert_eq!(
-        header.get("content-type").unwrap().to_str().unwrap(),
-        "text/html"
-    );
-    let header = get_header_body(&["-H", "content-type:text/html"]).await.0;
+async fn test_setting_form() {
+    let (method, header, body) = get_method_header_body(&["-F", "a=b c", "-F", "q=1+1&x=é"]).await;
+    assert_eq!(method, http::method::Method::POST);
     assert_eq!(
         header.get("content-type").unwrap().to_str().unwrap(),
-        "text/html"
+        "application/x-www-form-urlencoded"
     );
+    assert_eq!(body.as_ref(), &b"a=b+c&q=1%2B1%26x%3D%C3%A9"[..]);
 
-    let header = get_header_body(&["--http2", "-T", "text/html"]).await.0;
-    assert_eq!(
-        header.get("content-type").unwrap().to_str().unwrap(),
-        "text/html"
-    );
-    let header = get_header_body(&["--http2", "-H", "content-type:text/html"])
-        .await
-        .0;
+    let (method, header, _) =
+        get_method_header_body(&["-F", "a=b", "-m", "PUT", "-T", "text/plain"]).await;
+    assert_eq!(method, http::method::Method::PUT);
     assert_eq!(
         header.get("content-type").unwrap().to_str().unwrap(),
-        "text/html"
+        "text/plain"
     );
+
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), "from file").unwrap();
+    let (_, _, body) =
+        get_method_header_body(&["-F", &format!("f=@{}", file.path().display())]).await;
+    assert_eq!(body.as_ref(), &b"f=from+file"[..]);
+
+    // A file needn't be UTF-8
+    std::fs::write(file.path(), b"\x00\xff a").unwrap();
+    let (_, _, body) =
+        get_method_header_body(&["-F", &format!("f=@{}", file.path().display())]).await;
+    assert_eq!(body.as_ref(), &b"f=%00%FF+a"[..]);
+
+    Command::cargo_bin("oha")
+        .unwrap()
+        .args(["-n", "1", "--no-tui", "-F", "a=b", "-d", "c"])
+        .arg("http://127.0.0.1:1")
+        .assert()
+        .failure();
 }
 
-#[tokio::test]
-async fn test_setting_basic_auth() {
-    let header = get_header_body(&["-a", "hatoo:pass"]).await.0;
-    assert_eq!(
-        header.get("authorization").unwrap().to_str().unwrap(),
-        "Basic aGF0b286cGFzcw=="
-    );
+async fn get_multipart(
+    args: &[&str],
+) -> Vec<(Option<String>, Option<String>, Option<String>, Bytes)> {
+    let (tx, rx) = flume::unbounded();
 
-    let header = get_header_body(&["--http2", "-a", "hatoo:pass"]).await.0;
-    assert_eq!(
-        header.get("authorization").unwrap().to_str().unwrap(),
-        "Basic aGF0b286cGFzcw=="
+    let app = Router::new().route(
+        "/",
+        any(|mut multipart: axum::extract::Multipart| async move {
+            let mut parts = Vec::new();
+            while let Some(field) = multipart.next_field().await.unwrap() {
+                let name = field.name().map(|s| s.to_string());
+                let file_name = field.file_name().map(|s| s.to_string());
+                let content_type = field.content_type().map(|s| s.to_string());
+                parts.push((name, file_name, content_type, field.bytes().await.unwrap()));
+            }
+            tx.send(parts).unwrap();
+            "Hello World"
+        }),
     );
+
+    let port = serve(app).await;
+
+    let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+    tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui"])
+            .args(args)
+            .arg(format!("http://127.0.0.1:{port}"))
+            .assert()
+            .success();
+    })
+    .await
+    .unwrap();
+
+    rx.try_recv().unwrap()
 }
 
 #[tokio::test]
-async fn test_setting_host() {
-    let header = get_header_body(&["--host", "hatoo.io"]).await.0;
-    assert_eq!(header.get("host").unwrap().to_str().unwrap(), "hatoo.io");
+async fn test_setting_multipart() {
+    let binary: Vec<u8> = (0..=255u8).chain(b"\r\n--\r\n".iter().copied()).collect();
+    let file = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
+    std::fs::write(file.path(), &binary).unwrap();
+    let file_name = file
+        .path()
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
 
-    let header = get_header_body(&["-H", "host:hatoo.io"]).await.0;
-    assert_eq!(header.get("host").unwrap().to_str().unwrap(), "hatoo.io");
+    let parts = get_multipart(&[
+        "--multipart",
+        "te\"xt=hello world",
+        "--multipart",
+        &format!("file=@{}", file.path().display()),
+        "--multipart",
+        &format!("typed=@{};type=text/plain", file.path().display()),
+    ])
+    .await;
 
-    // You shouldn't set host header when using HTTP/2
-    // Use --connect-to instead
+    assert_eq!(parts.len(), 3);
+    assert_eq!(parts[0].0.as_deref(), Some("te%22xt"));
+    assert_eq!(parts[0].1, None);
+    assert_eq!(parts[0].3.as_ref(), b"hello world");
+    assert_eq!(parts[1].0.as_deref(), Some("file"));
+    assert_eq!(parts[1].1.as_deref(), Some(file_name.as_str()));
+    assert_eq!(parts[1].2.as_deref(), Some("image/png"));
+    assert_eq!(parts[1].3.as_ref(), binary.as_slice());
+    assert_eq!(parts[2].2.as_deref(), Some("text/plain"));
+    assert_eq!(parts[2].3.as_ref(), binary.as_slice());
 }
 
 #[tokio::test]
-async fn test_setting_method() {
-    assert_eq!(get_method(&[]).await, http::method::Method::GET);
-    assert_eq!(get_method(&["-m", "GET"]).await, http::method::Method::GET);
-    assert_eq!(
-        get_method(&["-m", "POST"]).await,
-        http::method::Method::POST
-    );
-    assert_eq!(
-        get_method(&["-m", "CONNECT"]).await,
-        http::method::Method::CONNECT
-    );
+async fn test_setting_chunked_body() {
+    let payload = "0123456789".repeat(1000);
+
+    let (header, body) = get_header_body(&["-d", &payload, "--chunked=1000"]).await;
     assert_eq!(
-        get_method(&["-m", "DELETE"]).await,
-        http::method::Method::DELETE
+        header.get("transfer-encoding").unwrap().to_str().unwrap(),
+        "chunked"
     );
+    assert!(header.get("content-length").is_none());
+    assert_eq!(body.as_ref(), payload.as_bytes());
+
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), &payload).unwrap();
+    let path = file.path().to_str().unwrap();
+
+    let (header, body) = get_header_body(&["-D", path, "--chunked"]).await;
     assert_eq!(
-        get_method(&["-m", "HEAD"]).await,
-        http::method::Method::HEAD
+        header.get("transfer-encoding").unwrap().to_str().unwrap(),
+        "chunked"
     );
+    assert_eq!(body.as_ref(), payload.as_bytes());
+
+    let (_, body) = get_header_body(&["--http2", "-D", path, "--chunked=1KiB"]).await;
+    assert_eq!(body.as_ref(), payload.as_bytes());
+
+    // A chunk of 0 bytes would never send the body
+    let output = Command::cargo_bin("oha")
+        .unwrap()
+        .args(["-n", "1", "--no-tui", "--chunked=0", "-d", "abc"])
+        .arg("http://127.0.0.1:1")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("must be at least 1 byte"));
+}
+
+#[tokio::test]
+async fn test_setting_body_encoding() {
+    use std::io::Read;
+
+    let payload = "hello body ".repeat(100);
+
+    let (header, body) = get_header_body(&["-d", &payload, "--body-encoding", "gzip"]).await;
     assert_eq!(
-        get_method(&["-m", "OPTIONS"]).await,
-        http::method::Method::OPTIONS
+        header.get("content-encoding").unwrap().to_str().unwrap(),
+        "gzip"
     );
     assert_eq!(
-        get_method(&["-m", "PATCH"]).await,
-        http::method::Method::PATCH
+        header.get("content-length").unwrap().to_str().unwrap(),
+        body.len().to_string()
     );
-    assert_eq!(get_method(&["-m", "PUT"]).await, http::method::Method::PUT);
+    let mut decoded = String::new();
+    flate2::read::GzDecoder::new(body.as_ref())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, payload);
+
+    let (header, body) = get_header_body(&["-d", &payload, "--body-encoding", "deflate"]).await;
     assert_eq!(
-        get_method(&["-m", "TRACE"]).await,
-        http::method::Method::TRACE
+        header.get("content-encoding").unwrap().to_str().unwrap(),
+        "deflate"
     );
+    let mut decoded = String::new();
+    flate2::read::ZlibDecoder::new(body.as_ref())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, payload);
 
-    assert_eq!(get_method(&["--http2"]).await, http::method::Method::GET);
+    let (header, body) = get_header_body(&["-d", &payload, "--body-encoding", "br"]).await;
     assert_eq!(
-        get_method(&["--http2", "-m", "GET"]).await,
-        http::method::Method::GET
+        header.get("content-encoding").unwrap().to_str().unwrap(),
+        "br"
     );
-    assert_eq!(
-        get_method(&["--http2", "-m", "POST"]).await,
-        http::method::Method::POST
-    );
-    assert_eq!(
-        get_method(&["--http2", "-m", "DELETE"]).await,
-        http::method::Method::DELETE
+    let mut decoded = String::new();
+    brotli::Decompressor::new(body.as_ref(), 4096)
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, payload);
+}
+
+async fn body_dir_json(dir: &std::path::Path, args: &[&str]) -> (serde_json::Value, Vec<String>) {
+    let (tx, rx) = flume::unbounded();
+    let app = Router::new().route(
+        "/",
+        any(move |header: HeaderMap, body: Bytes| async move {
+            assert_eq!(
+                header.get("content-length").unwrap().to_str().unwrap(),
+                body.len().to_string()
+            );
+            tx.send(String::from_utf8(body.to_vec()).unwrap()).unwrap();
+        }),
     );
+    let dir = dir.to_str().unwrap();
+    let args = [&["-c", "1", "-m", "POST", "--body-dir", dir], args].concat();
+    (run_json(app, &args).await, rx.drain().collect())
+}
+
+#[tokio::test]
+async fn test_body_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("1.json"), r#"{"id":1}"#).unwrap();
+    std::fs::write(dir.path().join("2.json"), r#"{"id":22}"#).unwrap();
+    std::fs::write(dir.path().join("3.json"), r#"{"id":333}"#).unwrap();
+
+    let (json, bodies) = body_dir_json(dir.path(), &["-n", "4"]).await;
     assert_eq!(
-        get_method(&["--http2", "-m", "HEAD"]).await,
-        http::method::Method::HEAD
+        bodies,
+        [
+            r#"{"id":1}"#,
+            r#"{"id":22}"#,
+            r#"{"id":333}"#,
+            r#"{"id":1}"#
+        ]
     );
+    assert_eq!(json["summary"]["bodies"]["used"], 3);
+    assert_eq!(json["summary"]["bodies"]["total"], 3);
+
+    let (_, first) = body_dir_json(
+        dir.path(),
+        &["-n", "8", "--body-dir-order", "random", "--rand-seed", "0"],
+    )
+    .await;
+    let (json, second) = body_dir_json(
+        dir.path(),
+        &["-n", "8", "--body-dir-order", "random", "--rand-seed", "0"],
+    )
+    .await;
+    assert_eq!(first, second);
     assert_eq!(
-        get_method(&["--http2", "-m", "OPTIONS"]).await,
-        http::method::Method::OPTIONS
+        json["summary"]["bodies"]["used"],
+        second
+            .iter()
+            .collect::<std::collections::HashSet<_>>()
+            .len()
     );
-    assert_eq!(
-        get_method(&["--http2", "-m", "PATCH"]).await,
-        http::method::Method::PATCH
+
+    let empty = tempfile::tempdir().unwrap();
+    let output = Command::cargo_bin("oha")
+        .unwrap()
+        .args(["--no-tui", "--body-dir", empty.path().to_str().unwrap()])
+        .arg("http://127.0.0.1:1/")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no files in the directory"));
+}
+
+#[tokio::test]
+async fn test_body_reload_interval() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("body");
+    std::fs::write(&path, "one").unwrap();
+
+    let (tx, rx) = flume::unbounded();
+    let app = Router::new().route(
+        "/",
+        any({
+            let path = path.clone();
+            move |body: Bytes| async move {
+                // Replace the file once the first body has arrived. The rename is atomic, so
+                // that a reload never reads a file half written.
+                let new = path.with_extension("new");
+                std::fs::write(&new, "two").unwrap();
+                std::fs::rename(&new, &path).unwrap();
+                tx.send(String::from_utf8(body.to_vec()).unwrap()).unwrap();
+            }
+        }),
     );
-    assert_eq!(
-        get_method(&["--http2", "-m", "PUT"]).await,
-        http::method::Method::PUT
+
+    let port = serve(app).await;
+
+    let path = path.to_str().unwrap().to_string();
+    let output = tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-c", "1", "-q", "20", "-n", "40", "-m", "POST", "--no-tui"])
+            .args(["-D", &path, "--body-reload-interval", "50ms"])
+            .arg(format!("http://127.0.0.1:{port}/"))
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap();
+    assert!(output.status.success());
+
+    // The body changes once, from the first request on, however long the reload takes
+    let bodies: Vec<String> = rx.drain().collect();
+    assert_eq!(bodies.len(), 40);
+    assert_eq!(bodies[0], "one");
+    let changed = bodies.iter().position(|body| body == "two").unwrap();
+    assert!(
+        bodies[..changed].iter().all(|body| body == "one"),
+        "{bodies:?}"
     );
-    assert_eq!(
-        get_method(&["--http2", "-m", "TRACE"]).await,
-        http::method::Method::TRACE
+    assert!(
+        bodies[changed..].iter().all(|body| body == "two"),
+        "{bodies:?}"
     );
 }
 
+async fn expect_continue_json(path: &'static str, args: &[&str]) -> serde_json::Value {
+    let app = Router::new()
+        .route("/", any(|body: Bytes| async move { body }))
+        // Reply without reading the body, so that hyper doesn't send 100 Continue
+        .route(
+            "/reject",
+            any(|| async { http::StatusCode::PAYLOAD_TOO_LARGE }),
+        );
+    let port = serve(app).await;
+    let args = [&["-n", "2", "-c", "1", "--expect-continue"], args].concat();
+    summary(&run_oha(&args, format!("http://127.0.0.1:{port}{path}")).await)
+}
+
+async fn assert_body_json(args: &[&str]) -> serde_json::Value {
+    let app = Router::new().route("/", get(|| async { r#"{"ok": true, "id": 42}"# }));
+    run_json(app, &[&["-n", "4"], args].concat()).await
+}
+
 #[tokio::test]
-async fn test_query() {
+async fn test_assert_body() {
+    let json = assert_body_json(&[
+        "--assert-body-contains",
+        r#""ok": true"#,
+        "--assert-body-regex",
+        r#""id": \d+"#,
+    ])
+    .await;
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert_eq!(json["summary"]["assertionFailures"], 0);
+    assert_eq!(json["summary"]["backpressure"], 0);
+
+    let json = assert_body_json(&["--assert-body-contains", r#""ok": false"#]).await;
+    assert_eq!(json["summary"]["successRate"], 0.0);
+    assert_eq!(json["summary"]["assertionFailures"], 4);
     assert_eq!(
-        get_query("/index?a=b&c=d", &[]).await,
-        "/index?a=b&c=d".to_string()
+        json["errorDistribution"]["assertion failed: body doesn't contain the expected string"],
+        4
     );
 
-    assert_eq!(
-        get_query("/index?a=b&c=d", &["--http2"])
-            .await
-            .split('/')
-            .last()
-            .unwrap(),
-        "index?a=b&c=d".to_string()
+    // The body is checked only up to the cap
+    let json =
+        assert_body_json(&["--assert-body-regex", "42", "--max-assert-body-bytes", "8"]).await;
+    assert_eq!(json["summary"]["assertionFailures"], 4);
+
+    let json = assert_body_json(&[]).await;
+    assert!(json["summary"].get("assertionFailures").is_none());
+}
+
+async fn expect_status_json(expect_status: &str) -> serde_json::Value {
+    let app = Router::new().route(
+        "/",
+        get(|| async { http::StatusCode::INTERNAL_SERVER_ERROR }),
     );
+    run_json(app, &["-n", "4", "--expect-status", expect_status]).await
 }
 
 #[tokio::test]
-async fn test_query_rand_regex() {
-    let query = get_path_rand_regex("/[a-z][0-9][a-z]", &[]).await;
-    let chars = query.trim_start_matches('/').chars().collect::<Vec<char>>();
-    assert_eq!(chars.len(), 3);
-    assert!(chars[0].is_ascii_lowercase());
-    assert!(chars[1].is_ascii_digit());
-    assert!(chars[2].is_ascii_lowercase());
+async fn test_expect_status() {
+    let json = expect_status_json("2xx,404").await;
+    assert_eq!(json["summary"]["successRate"], 0.0);
+    assert_eq!(json["statusCodeDistribution"]["500"], 4);
+    assert_eq!(
+        json["errorDistribution"]["unexpected status 500 Internal Server Error"],
+        4
+    );
+    assert!(json["latencyPercentilesUnexpectedStatus"]["p50"].is_number());
 
-    let query = get_path_rand_regex("/[a-z][0-9][a-z]", &["--http2"]).await;
-    let chars = query
-        .split('/')
-        .last()
-        .unwrap()
-        .chars()
-        .collect::<Vec<char>>();
-    assert_eq!(chars.len(), 3);
-    assert!(chars[0].is_ascii_lowercase());
-    assert!(chars[1].is_ascii_digit());
-    assert!(chars[2].is_ascii_lowercase());
+    let json = expect_status_json("2xx,5xx").await;
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert!(json["errorDistribution"].as_object().unwrap().is_empty());
+}
+
+async fn max_body_bytes_json(args: &[&str]) -> serde_json::Value {
+    let app = Router::new().route("/", get(|| async { vec![b'a'; 1024 * 1024] }));
+    let args = [&["-n", "4", "-c", "1", "--max-body-bytes", "16KiB"], args].concat();
+    run_json(app, &args).await
 }
 
 #[tokio::test]
-async fn test_redirect() {
-    for n in 1..=5 {
-        assert!(redirect(n, true, 10).await);
-        assert!(redirect(n, false, 10).await);
-    }
-    for n in 11..=15 {
-        assert!(!redirect(n, true, 10).await);
-        assert!(!redirect(n, false, 10).await);
+async fn test_max_body_bytes() {
+    for args in [&[][..], &["--http2"][..], &["--prebuilt-requests"][..]] {
+        let json = max_body_bytes_json(args).await;
+        assert_eq!(json["summary"]["successRate"], 1.0);
+        assert_eq!(json["summary"]["truncatedBodies"], 4, "{args:?}");
+        let size = json["summary"]["sizePerRequest"].as_u64().unwrap();
+        assert!((16 * 1024..1024 * 1024).contains(&size), "{size}");
     }
 }
 
 #[tokio::test]
-async fn test_connect_to() {
+async fn test_save_responses() {
+    let app = Router::new()
+        .route("/", get(|| async { "Hello World" }))
+        .route(
+            "/error",
+            get(|| async { (http::StatusCode::INTERNAL_SERVER_ERROR, "oops") }),
+        );
+
+    let port = serve(app).await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let save = |name: &str, path: &'static str, args: &'static [&'static str]| {
+        let dir = dir.path().join(name);
+        async move {
+            let out = dir.clone();
+            tokio::task::spawn_blocking(move || {
+                Command::cargo_bin("oha")
+                    .unwrap()
+                    .args(["-n", "5", "--no-tui", "--save-responses"])
+                    .arg(&out)
+                    .args(args)
+                    .arg(format!("http://127.0.0.1:{port}{path}"))
+                    .assert()
+                    .success();
+            })
+            .await
+            .unwrap();
+            let mut names: Vec<String> = std::fs::read_dir(&dir)
+                .unwrap()
+                .map(|e| e.unwrap().file_name().into_string().unwrap())
+                .collect();
+            names.sort();
+            (dir, names)
+        }
+    };
+
+    let (out, names) = save("all", "/", &["--save-count", "2"]).await;
     assert_eq!(
-        get_host_with_connect_to("invalid.example.org").await,
-        "invalid.example.org"
-    )
+        names,
+        [
+            "000001.body",
+            "000001.headers",
+            "000002.body",
+            "000002.headers"
+        ]
+    );
+    assert_eq!(
+        std::fs::read_to_string(out.join("000001.body")).unwrap(),
+        "Hello World"
+    );
+    assert!(std::fs::read_to_string(out.join("000001.headers"))
+        .unwrap()
+        .starts_with("200 OK\n"));
+
+    let (_, names) = save("ok", "/", &["--save-errors-only"]).await;
+    assert!(names.is_empty());
+
+    let (out, names) = save("error", "/error", &["--save-errors-only"]).await;
+    assert_eq!(names.len(), 10);
+    assert_eq!(
+        std::fs::read_to_string(out.join("000005.body")).unwrap(),
+        "oops"
+    );
+}
+
+async fn prewarm_json(args: &[&str]) -> (serde_json::Value, Vec<http::Method>) {
+    let (tx, rx) = flume::unbounded();
+    let app = Router::new().route(
+        "/",
+        any(move |method: http::Method| async move {
+            tx.send(method).unwrap();
+            "Hello World"
+        }),
+    );
+    let args = [
+        &[
+            "-n",
+            "4",
+            "-c",
+            "2",
+            "--prewarm-connections",
+            "--prewarm-method",
+            "HEAD",
+        ],
+        args,
+    ]
+    .concat();
+    (run_json(app, &args).await, rx.drain().collect())
 }
 
 #[tokio::test]
-async fn test_connect_to_ipv6_target() {
-    assert_eq!(
-        get_host_with_connect_to_ipv6_target("invalid.example.org").await,
-        "invalid.example.org"
-    )
+async fn test_prewarm_connections() {
+    for args in [&[][..], &["--http2"][..]] {
+        let (json, methods) = prewarm_json(args).await;
+        assert_eq!(json["summary"]["prewarm"]["connections"], 2);
+        assert!(json["summary"]["prewarm"]["duration"].is_number());
+        assert_eq!(json["summary"]["successRate"], 1.0);
+        assert_eq!(
+            methods.iter().filter(|&m| m == http::Method::HEAD).count(),
+            2
+        );
+        assert_eq!(
+            methods.iter().filter(|&m| m == http::Method::GET).count(),
+            4
+        );
+        // No request pays for connecting
+        assert_eq!(json["details"]["DNSDialup"]["average"], 0.0);
+    }
+}
+
+async fn keepalive_json(args: &[&str]) -> serde_json::Value {
+    let app = Router::new().route("/", get(|| async { "Hello World" }));
+    run_json(app, &[&["-n", "6"], args].concat()).await
 }
 
 #[tokio::test]
-async fn test_connect_to_ipv6_requested() {
-    assert_eq!(get_host_with_connect_to_ipv6_requested().await, "[::1]")
+async fn test_head_content_length() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // The Content-Length of HEAD responses is the size of the body a GET would get
+    let app = Router::new().route(
+        "/",
+        axum::routing::head(|| async { [(http::header::CONTENT_LENGTH, "1000")] }),
+    );
+    let port = serve(app).await;
+
+    let mut tcp = tokio::net::TcpStream::connect(("127.0.0.1", port))
+        .await
+        .unwrap();
+    tcp.write_all(b"HEAD / HTTP/1.1\r\nHost: a\r\n\r\n")
+        .await
+        .unwrap();
+    let mut head = vec![0; 1024];
+    let n = tcp.read(&mut head).await.unwrap();
+    assert!(String::from_utf8_lossy(&head[..n]).contains("content-length: 1000\r\n"));
+
+    for args in [&[][..], &["--prebuilt-requests"], &["--http2"]] {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let output = tokio::task::spawn_blocking(move || {
+            Command::cargo_bin("oha")
+                .unwrap()
+                .args(["-n", "50", "-c", "2", "-m", "HEAD", "-t", "2s"])
+                .args(["--no-tui", "--json"])
+                .args(&args)
+                .arg(format!("http://127.0.0.1:{port}/"))
+                .output()
+                .unwrap()
+        })
+        .await
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json["summary"]["successRate"], 1.0);
+        assert_eq!(json["statusCodeDistribution"]["200"], 50);
+        assert_eq!(json["errorDistribution"], serde_json::json!({}));
+        assert_eq!(json["summary"]["sizePerRequest"], 0);
+        // The connections are reused after the headers. None on HTTP/2.
+        if let Some(new) = json["details"]["connections"]["new"].as_u64() {
+            assert!(new <= 2);
+        }
+    }
 }
 
 #[tokio::test]
-async fn test_connect_to_redirect() {
-    assert_eq!(
-        get_host_with_connect_to_redirect("invalid.example.org").await,
-        "invalid.example.org"
-    )
+async fn test_disable_keepalive() {
+    let json = keepalive_json(&["-c", "2"]).await;
+    let new = json["details"]["connections"]["new"].as_u64().unwrap();
+    let reused = json["details"]["connections"]["reused"].as_u64().unwrap();
+    // A worker which doesn't get any request doesn't connect
+    assert!((1..=2).contains(&new));
+    assert_eq!(new + reused, 6);
+    // No TLS
+    assert!(json["details"].get("TLSHandshake").is_none());
+
+    let json = keepalive_json(&["-c", "2", "--disable-keepalive"]).await;
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert_eq!(json["details"]["connections"]["new"], 6);
+    assert_eq!(json["details"]["connections"]["reused"], 0);
+
+    let output = Command::cargo_bin("oha")
+        .unwrap()
+        .args(["--no-tui", "--http2", "--disable-keepalive"])
+        .arg("http://127.0.0.1:1/")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("--disable-keepalive is not supported on HTTP/2"));
 }
 
 #[tokio::test]
-async fn test_ipv6() {
-    let (tx, rx) = flume::unbounded();
+async fn test_requests_per_connection() {
+    let json = keepalive_json(&["-c", "1", "--requests-per-connection", "3"]).await;
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert_eq!(json["details"]["connections"]["new"], 2);
+    assert_eq!(json["details"]["connections"]["reused"], 4);
+    let ratio = json["details"]["connections"]["ratio"].as_f64().unwrap();
+    assert!((ratio - 2.0 / 3.0).abs() < 1e-9, "{ratio}");
+
+    // Same as --disable-keepalive
+    let json = keepalive_json(&["-c", "1", "--requests-per-connection", "1"]).await;
+    assert_eq!(json["details"]["connections"]["new"], 6);
+    assert_eq!(json["details"]["connections"]["ratio"], 0.0);
 
+    // Unlimited
+    let json = keepalive_json(&["-c", "1", "--requests-per-connection", "0"]).await;
+    assert_eq!(json["details"]["connections"]["new"], 1);
+
+    let output = Command::cargo_bin("oha")
+        .unwrap()
+        .args(["--no-tui", "--http2", "--requests-per-connection", "3"])
+        .arg("http://127.0.0.1:1/")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("--requests-per-connection is only supported on HTTP/1.x"));
+}
+
+#[tokio::test]
+async fn test_latency_includes() {
+    // The server takes 200ms before the response header
     let app = Router::new().route(
         "/",
-        get(|| async move {
-            tx.send(()).unwrap();
+        get(|| async {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
             "Hello World"
         }),
     );
+    let port = serve(app).await;
 
-    let (listener, port) = bind_port_ipv6().await;
-    tokio::spawn(async { axum::serve(listener, app).await });
+    let run = |args: &'static [&'static str]| {
+        tokio::task::spawn_blocking(move || {
+            Command::cargo_bin("oha")
+                .unwrap()
+                .args(["-n", "2", "-c", "1", "--no-tui"])
+                .args(args)
+                .arg(format!("http://127.0.0.1:{port}/"))
+                .output()
+                .unwrap()
+        })
+    };
 
-    tokio::task::spawn_blocking(move || {
+    let output = run(&["--json"]).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["summary"]["latencyIncludes"], "all");
+    assert!(json["summary"]["fastest"].as_f64().unwrap() >= 0.2);
+
+    let output = run(&["--json", "--latency-includes", "response-only"])
+        .await
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["summary"]["latencyIncludes"], "response-only");
+    assert!(
+        json["summary"]["slowest"].as_f64().unwrap()
+            < json["details"]["latency"]["all"]["fastest"]
+                .as_f64()
+                .unwrap()
+    );
+    assert_eq!(
+        json["summary"]["slowest"],
+        json["details"]["latency"]["responseOnly"]["slowest"]
+    );
+    // The other definitions are still reported
+    assert!(
+        json["details"]["latency"]["all"]["fastest"]
+            .as_f64()
+            .unwrap()
+            >= 0.2
+    );
+    assert!(
+        json["details"]["latency"]["noDns"]["fastest"]
+            .as_f64()
+            .unwrap()
+            >= 0.2
+    );
+
+    let output = run(&["--latency-includes", "no-dns"]).await.unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout)
+        .contains("Latency:\trequest start to last byte, excluding DNS lookup"));
+}
+
+#[tokio::test]
+async fn test_idle_workers() {
+    for latency_correction in [&[][..], &["--latency-correction"]] {
+        // The requests go to the workers in turn, each on its connection
+        let json = keepalive_json(&[&["-c", "3", "-q", "10"], latency_correction].concat()).await;
+        assert_eq!(json["summary"]["successRate"], 1.0);
+        assert_eq!(json["details"]["connections"]["new"], 3);
+
+        // One worker is enough for the rate
+        let json = keepalive_json(
+            &[
+                &["-c", "3", "-q", "10", "--idle-workers", "park"],
+                latency_correction,
+            ]
+            .concat(),
+        )
+        .await;
+        assert_eq!(json["summary"]["successRate"], 1.0);
+        assert_eq!(json["details"]["connections"]["new"], 1);
+    }
+
+    // Three bursts of two requests. The parked workers close their connection between them.
+    let burst = ["-c", "2", "--burst-delay", "1500ms", "--burst-rate", "2"];
+    let json = keepalive_json(&burst).await;
+    assert_eq!(json["details"]["connections"]["new"], 2);
+    let json = keepalive_json(&[&burst[..], &["--idle-workers", "park"]].concat()).await;
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert_eq!(json["details"]["connections"]["new"], 6);
+
+    let output = Command::cargo_bin("oha")
+        .unwrap()
+        .args(["--no-tui", "--http2", "--idle-workers", "park"])
+        .arg("http://127.0.0.1:1/")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("--idle-workers park is only supported on HTTP/1.x"));
+}
+
+#[tokio::test]
+async fn test_keepalive_idle_timeout() {
+    let json = keepalive_json(&["-c", "1", "-q", "10", "--keepalive-idle-timeout", "50ms"]).await;
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert_eq!(json["summary"]["keepAlive"]["idleCloses"], 5);
+    assert_eq!(json["details"]["connections"]["new"], 6);
+    assert_eq!(json["details"]["connections"]["reused"], 0);
+
+    let json = keepalive_json(&["-c", "1", "-q", "10", "--keepalive-idle-timeout", "10s"]).await;
+    assert!(json["summary"].get("keepAlive").is_none());
+    assert_eq!(json["details"]["connections"]["new"], 1);
+}
+
+#[tokio::test]
+async fn test_stale_connection_retry() {
+    // The server closes each connection after the response without telling the client
+    let (json, accepted) = raw_http1_json(
+        &["-q", "20"],
+        |_| "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello",
+        true,
+    )
+    .await;
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert_eq!(json["errorDistribution"], serde_json::json!({}));
+    // Every request but the first of each worker finds its connection closed
+    let stale_retries = json["summary"]["keepAlive"]["staleRetries"]
+        .as_u64()
+        .unwrap();
+    assert!((18..=19).contains(&stale_retries));
+    assert_eq!(accepted, 20);
+}
+
+/// Run `oha -n 30 -c 1` against a server which closes each connection after 3 responses,
+/// as soon as the next request arrives
+async fn closing_server_json(args: &[&str]) -> serde_json::Value {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    let (listener, port) = bind_port().await;
+    tokio::spawn(async move {
+        loop {
+            let (tcp, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut tcp = tokio::io::BufReader::new(tcp);
+                let mut line = String::new();
+                let mut responses = 0;
+                loop {
+                    line.clear();
+                    if tcp.read_line(&mut line).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    if line == "\r\n" {
+                        if responses == 3 {
+                            return;
+                        }
+                        tcp.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello")
+                            .await
+                            .unwrap();
+                        responses += 1;
+                    }
+                }
+            });
+        }
+    });
+    let output = run_oha(
+        &[&["-n", "30", "-c", "1"], args].concat(),
+        format!("http://127.0.0.1:{port}/"),
+    )
+    .await;
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[tokio::test]
+async fn test_stale_connection_retry_after_write() {
+    // Hyper and the prebuilt request
+    for args in [&[][..], &["--prebuilt-requests"]] {
+        let json = closing_server_json(args).await;
+        assert_eq!(json["summary"]["successRate"], 1.0, "{args:?}");
+        assert_eq!(json["errorDistribution"], serde_json::json!({}), "{args:?}");
+        assert_eq!(json["summary"]["keepAlive"]["staleRetries"], 9, "{args:?}");
+        assert_eq!(json["details"]["connections"]["new"], 10, "{args:?}");
+    }
+
+    // A POST may have been processed once it's written, so it isn't sent again
+    let json = closing_server_json(&["-m", "POST", "--prebuilt-requests"]).await;
+    assert!(json["summary"]["successRate"].as_f64().unwrap() < 1.0);
+    assert!(json["summary"].get("keepAlive").is_none());
+}
+
+#[tokio::test]
+async fn test_tcp_keepalive() {
+    let app = Router::new().route("/", get(|| async { "Hello World" }));
+
+    let port = serve(app).await;
+
+    let output = tokio::task::spawn_blocking(move || {
         Command::cargo_bin("oha")
             .unwrap()
-            .args(["-n", "1", "--no-tui"])
-            .arg(format!("http://[::1]:{port}/"))
-            .assert()
-            .success();
+            .args(["-n", "2", "--no-tui", "--json", "--tcp-keepalive", "30s"])
+            .arg(format!("http://127.0.0.1:{port}/"))
+            .output()
+            .unwrap()
     })
     .await
     .unwrap();
+    assert!(output.status.success());
 
-    rx.try_recv().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert_eq!(json["details"]["tcpKeepalive"], 30.0);
+}
+
+async fn http2_json(args: &[&str]) -> Option<serde_json::Value> {
+    let app = Router::new().route("/", get(|| async move { "Hello World" }));
+    let port = serve(app).await;
+    let output = run_oha(
+        &[&["-n", "60"], args].concat(),
+        format!("http://127.0.0.1:{port}/"),
+    )
+    .await;
+    output.status.success().then(|| summary(&output))
 }
 
 #[tokio::test]
-async fn test_query_limit() {
-    assert_eq!(burst_10_req_delay_2s_rate_4(10, &[],).await, 10);
-    assert_eq!(burst_10_req_delay_2s_rate_4(10, &["--http2"],).await, 10);
+async fn test_http2_connections() {
+    let json = http2_json(&[
+        "--http2",
+        "--http2-connections",
+        "2",
+        "--http2-streams-per-connection",
+        "3",
+        "--http2-initial-window-size",
+        "1MiB",
+        "--http2-max-frame-size",
+        "32KiB",
+    ])
+    .await
+    .unwrap();
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert_eq!(json["http2"]["connections"], 2);
+    assert_eq!(json["http2"]["goaways"], 0);
+    assert_eq!(json["http2"]["streamsPerConnection"]["average"], 30.0);
+
+    assert!(http2_json(&["--http2-connections", "2"]).await.is_none());
+    assert!(http2_json(&["--http2", "--http2-max-frame-size", "1KiB"])
+        .await
+        .is_none());
+    assert!(http2_json(&[]).await.unwrap().get("http2").is_none());
+}
+
+/// What the raw HTTP/2 server does with a request
+#[derive(Clone, Copy)]
+enum H2Action {
+    Respond,
+    Reset(h2::Reason),
+    /// Respond and then close the connection gracefully with GOAWAY
+    RespondAndGoAway,
+    /// Send the response header and never the body
+    StallBody,
+}
+
+/// Run `oha` against a raw HTTP/2 server which handles the n-th request by `action(n)`.
+async fn h2_server_json(
+    args: &[&str],
+    action: impl Fn(usize) -> H2Action + Send + Sync + 'static,
+) -> serde_json::Value {
+    let (listener, port) = bind_port().await;
+    let action = std::sync::Arc::new(action);
+    let counter = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    tokio::spawn(async move {
+        loop {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let action = action.clone();
+            let counter = counter.clone();
+            tokio::spawn(async move {
+                let mut conn = h2::server::handshake(tcp).await.unwrap();
+                let mut stalled = Vec::new();
+                while let Some(Ok((_, mut respond))) = conn.accept().await {
+                    match action(counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed)) {
+                        H2Action::Reset(reason) => respond.send_reset(reason),
+                        H2Action::StallBody => {
+                            stalled.push(respond.send_response(Response::new(()), false).unwrap());
+                        }
+                        action => {
+                            let mut send = respond.send_response(Response::new(()), false).unwrap();
+                            send.send_data(Bytes::from_static(b"Hello World"), true)
+                                .unwrap();
+                            if let H2Action::RespondAndGoAway = action {
+                                conn.graceful_shutdown();
+                            }
+                        }
+                    }
+                }
+            });
+        }
+    });
+    let output = run_oha(
+        &[&["--http2"], args].concat(),
+        format!("http://127.0.0.1:{port}/"),
+    )
+    .await;
+    summary(&output)
 }
 
 #[tokio::test]
-async fn test_http2() {
-    assert_eq!(get_http_version(&[]).await, http::Version::HTTP_11);
-    assert_eq!(get_http_version(&["--http2"]).await, http::Version::HTTP_2);
-    assert_eq!(
-        get_http_version(&["--http-version", "2"]).await,
-        http::Version::HTTP_2
-    );
+async fn test_http2_stream_errors() {
+    // Every first attempt is refused
+    let json = h2_server_json(&["-n", "10", "-c", "1"], |n| {
+        if n % 2 == 0 {
+            H2Action::Reset(h2::Reason::REFUSED_STREAM)
+        } else {
+            H2Action::Respond
+        }
+    })
+    .await;
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert_eq!(json["http2"]["refusedStreamRetries"], 10);
+
+    let json = h2_server_json(&["-n", "10", "-c", "1", "--http2-max-retries", "0"], |n| {
+        if n % 2 == 0 {
+            H2Action::Reset(h2::Reason::REFUSED_STREAM)
+        } else {
+            H2Action::Respond
+        }
+    })
+    .await;
+    assert_eq!(json["http2"]["errors"]["RST_STREAM REFUSED_STREAM"], 5);
+    assert_eq!(json["http2"]["refusedStreamRetries"], 0);
+
+    let json = h2_server_json(&["-n", "10", "-c", "1"], |_| {
+        H2Action::Reset(h2::Reason::ENHANCE_YOUR_CALM)
+    })
+    .await;
+    assert_eq!(json["http2"]["errors"]["RST_STREAM ENHANCE_YOUR_CALM"], 10);
+    assert_eq!(
+        json["errorDistribution"]["HTTP/2 stream reset by the server: ENHANCE_YOUR_CALM"],
+        10
+    );
+}
+
+#[tokio::test]
+async fn test_http2_goaway() {
+    let json = h2_server_json(&["-n", "40", "-c", "1", "-p", "4"], |n| {
+        if n % 10 == 9 {
+            H2Action::RespondAndGoAway
+        } else {
+            H2Action::Respond
+        }
+    })
+    .await;
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert!(json["http2"]["goaways"].as_u64().unwrap() >= 1);
+    assert!(json["http2"]["connections"].as_u64().unwrap() >= 2);
+}
+
+#[tokio::test]
+async fn test_http2_read_timeout() {
+    let json = h2_server_json(&["-n", "10", "-c", "1", "--read-timeout", "100ms"], |n| {
+        if n % 2 == 0 {
+            H2Action::StallBody
+        } else {
+            H2Action::Respond
+        }
+    })
+    .await;
+    assert_eq!(json["errorDistribution"]["read timeout"], 5);
+    assert_eq!(json["statusCodeDistribution"]["200"], 5);
+    // Each response after a read timeout is on a new connection
+    assert_eq!(json["http2"]["connections"], 5);
+}
+
+/// Run `oha` against a raw HTTP/1.1 server which sends two `103 Early Hints` before each response.
+async fn early_hints_json(args: &[&str]) -> serde_json::Value {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    let (listener, port) = bind_port().await;
+    tokio::spawn(async move {
+        loop {
+            let (tcp, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut tcp = tokio::io::BufReader::new(tcp);
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    if tcp.read_line(&mut line).await.unwrap() == 0 {
+                        return;
+                    }
+                    // The end of the request header. GET has no body.
+                    if line == "\r\n" {
+                        let early_hints =
+                            "HTTP/1.1 103 Early Hints\r\nLink: </style.css>; rel=preload\r\n\r\n";
+                        tcp.write_all(early_hints.as_bytes()).await.unwrap();
+                        tcp.write_all(early_hints.as_bytes()).await.unwrap();
+                        tcp.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello")
+                            .await
+                            .unwrap();
+                    }
+                }
+            });
+        }
+    });
+    let output = run_oha(
+        &[&["-n", "10", "-c", "2"], args].concat(),
+        format!("http://127.0.0.1:{port}/"),
+    )
+    .await;
+    summary(&output)
+}
+
+#[tokio::test]
+async fn test_early_hints() {
+    let json = early_hints_json(&[]).await;
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert_eq!(json["statusCodeDistribution"]["200"], 10);
+    assert_eq!(json["statusCodeDistribution"].as_object().unwrap().len(), 1);
+    assert_eq!(json["summary"]["totalData"], 50);
+    assert!(json.get("informational").is_none());
+
+    let json = early_hints_json(&["--informational-stats"]).await;
+    assert_eq!(json["statusCodeDistribution"]["200"], 10);
+    assert_eq!(json["informational"]["requests"], 10);
+    assert_eq!(json["informational"]["earlyHints"], 20);
+    assert!(json["informational"]["first"]["average"].is_number());
+}
+
+/// Run `oha` against a raw HTTP/1.1 server which answers each request with `response(method)`.
+/// The server closes the connection after a response when `close` is true.
+/// Returns the JSON and how many connections the server accepted.
+async fn raw_http1_json(
+    args: &[&str],
+    response: fn(&str) -> &'static str,
+    close: bool,
+) -> (serde_json::Value, usize) {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    let (listener, port) = bind_port().await;
+    let accepted = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let server_accepted = accepted.clone();
+    tokio::spawn(async move {
+        loop {
+            let (tcp, _) = listener.accept().await.unwrap();
+            server_accepted.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            tokio::spawn(async move {
+                let mut tcp = tokio::io::BufReader::new(tcp);
+                let mut line = String::new();
+                let mut method = String::new();
+                loop {
+                    line.clear();
+                    if tcp.read_line(&mut line).await.unwrap() == 0 {
+                        return;
+                    }
+                    if method.is_empty() {
+                        method = line.split(' ').next().unwrap().to_string();
+                    } else if line == "\r\n" {
+                        tcp.write_all(response(&method).as_bytes()).await.unwrap();
+                        if close {
+                            return;
+                        }
+                        method.clear();
+                    }
+                }
+            });
+        }
+    });
+    let output = run_oha(
+        &[&["-n", "20", "-c", "2", "-t", "5s"], args].concat(),
+        format!("http://127.0.0.1:{port}/"),
+    )
+    .await;
+    (
+        summary(&output),
+        accepted.load(std::sync::atomic::Ordering::Relaxed),
+    )
+}
+
+/// Run `oha -n 4 -c 1` against a server which answers the first request with
+/// `429 Too Many Requests` and `Retry-After: 1`
+async fn retry_after_json(args: &[&str]) -> serde_json::Value {
+    let count = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let app = Router::new().route(
+        "/",
+        get(move || async move {
+            if count.fetch_add(1, std::sync::atomic::Ordering::Relaxed) == 0 {
+                (http::StatusCode::TOO_MANY_REQUESTS, [("retry-after", "1")])
+            } else {
+                (http::StatusCode::OK, [("retry-after", "1")])
+            }
+        }),
+    );
+    run_json(app, &[&["-n", "4", "-c", "1"], args].concat()).await
+}
+
+#[tokio::test]
+async fn test_respect_retry_after() {
+    let json = retry_after_json(&[]).await;
+    assert!(json["summary"].get("retryAfter").is_none());
+
+    let json = retry_after_json(&["--respect-retry-after"]).await;
+    assert_eq!(json["statusCodeDistribution"]["429"], 1);
+    assert_eq!(json["summary"]["retryAfter"]["responses"], 1);
+    assert!(json["summary"]["retryAfter"]["paused"].as_f64().unwrap() >= 0.9);
+    // The pause is in the total time but not in the latency
+    assert!(json["summary"]["total"].as_f64().unwrap() >= 1.0);
+    assert!(json["summary"]["slowest"].as_f64().unwrap() < 1.0);
+}
+
+#[cfg(feature = "rustls")]
+async fn tls_session_resumption_json(args: &[&str]) -> serde_json::Value {
+    let cert = rustls_pki_types::CertificateDer::from(include_bytes!("tls/cert.der").to_vec());
+    let key = rustls_pki_types::PrivatePkcs8KeyDer::from(include_bytes!("tls/key.der").to_vec());
+    let config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key.into())
+        .unwrap();
+    let acceptor = tokio_rustls::TlsAcceptor::from(std::sync::Arc::new(config));
+    let (listener, port) = bind_port().await;
+    tokio::spawn(async move {
+        loop {
+            let (socket, _remote_addr) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(stream) = acceptor.accept(socket).await else {
+                    return;
+                };
+                let service = service_fn(|_req| async {
+                    Ok::<_, Infallible>(Response::new("Hello World".to_string()))
+                });
+                let _ = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+    let args = [
+        &["-n", "5", "-c", "1", "--insecure", "--disable-keepalive"],
+        args,
+    ]
+    .concat();
+    summary(&run_oha(&args, format!("https://localhost:{port}/")).await)
+}
+
+#[cfg(feature = "rustls")]
+#[tokio::test]
+async fn test_tls_session_resumption() {
+    let json = tls_session_resumption_json(&[]).await;
+    assert_eq!(json["statusCodeDistribution"]["200"], 5);
+    let tls = &json["details"]["TLSHandshake"];
+    assert_eq!(tls["full"]["count"], 1);
+    assert_eq!(tls["resumed"]["count"], 4);
+
+    let json = tls_session_resumption_json(&["--tls-session-resumption", "off"]).await;
+    assert_eq!(json["statusCodeDistribution"]["200"], 5);
+    let tls = &json["details"]["TLSHandshake"];
+    assert_eq!(tls["full"]["count"], 5);
+    assert_eq!(tls["resumed"]["count"], 0);
+}
+
+/// The TLS backend oha is built with, as in the summary
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+const TLS_BACKEND: &str = if cfg!(feature = "rustls") {
+    "rustls"
+} else {
+    "native-tls"
+};
+
+/// Run oha against an HTTPS server with the certificate of localhost signed by tests/tls/ca.pem.
+/// The server requires a client certificate signed by the same CA with `client_auth`, and
+/// answers 505 to other versions than `version`. HTTP/2 must be negotiated by ALPN.
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+async fn tls_json(client_auth: bool, version: http::Version, args: &[&str]) -> serde_json::Value {
+    let pem = |bytes: &[u8]| {
+        rustls_pemfile::certs(&mut &bytes[..])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    };
+    let ca = pem(include_bytes!("tls/ca.pem"));
+    let certs = pem(include_bytes!("tls/server.pem"));
+    let key = rustls_pemfile::private_key(&mut &include_bytes!("tls/server.key")[..])
+        .unwrap()
+        .unwrap();
+    let builder = rustls::ServerConfig::builder();
+    let builder = if client_auth {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(ca[0].clone()).unwrap();
+        let verifier = rustls::server::WebPkiClientVerifier::builder(std::sync::Arc::new(roots))
+            .build()
+            .unwrap();
+        builder.with_client_cert_verifier(verifier)
+    } else {
+        builder.with_no_client_auth()
+    };
+    let mut config = builder.with_single_cert(certs, key).unwrap();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    let acceptor = tokio_rustls::TlsAcceptor::from(std::sync::Arc::new(config));
+    let (listener, port) = bind_port().await;
+    tokio::spawn(async move {
+        loop {
+            let (socket, _remote_addr) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(stream) = acceptor.accept(socket).await else {
+                    return;
+                };
+                // HTTP/2 must be negotiated by ALPN
+                let alpn = stream.get_ref().1.alpn_protocol();
+                if version == http::Version::HTTP_2 && alpn != Some(b"h2") {
+                    return;
+                }
+                let service = service_fn(move |req: Request<hyper::body::Incoming>| async move {
+                    let mut res = Response::new("Hello World".to_string());
+                    if req.version() != version {
+                        *res.status_mut() = http::StatusCode::HTTP_VERSION_NOT_SUPPORTED;
+                    }
+                    Ok::<_, Infallible>(res)
+                });
+                let _ = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+    let args = [&["-n", "3", "-c", "1"], args].concat();
+    summary(&run_oha(&args, format!("https://localhost:{port}/")).await)
+}
+
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+#[tokio::test]
+async fn test_tls_cacert() {
+    let json = tls_json(
+        false,
+        http::Version::HTTP_11,
+        &["--cacert", "tests/tls/ca.pem"],
+    )
+    .await;
+    assert_eq!(json["statusCodeDistribution"]["200"], 3);
+    assert_eq!(json["details"]["TLSHandshake"]["backend"], TLS_BACKEND);
+
+    // The CA isn't one of the platform's roots
+    let json = tls_json(false, http::Version::HTTP_11, &[]).await;
+    assert_eq!(json["summary"]["successRate"], 0.0);
+    assert!(json["statusCodeDistribution"].get("200").is_none());
+
+    let json = tls_json(false, http::Version::HTTP_11, &["--insecure"]).await;
+    assert_eq!(json["statusCodeDistribution"]["200"], 3);
+}
+
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+#[tokio::test]
+async fn test_tls_connect_host() {
+    // The certificate is verified against the host of the URL, localhost, rather than
+    // --host, which the certificate doesn't cover
+    let json = tls_json(
+        false,
+        http::Version::HTTP_11,
+        &[
+            "--cacert",
+            "tests/tls/ca.pem",
+            "--connect-host",
+            "127.0.0.1",
+            "--host",
+            "other.example",
+        ],
+    )
+    .await;
+    assert_eq!(json["statusCodeDistribution"]["200"], 3);
+}
+
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+#[tokio::test]
+async fn test_tls_sni() {
+    let ca = "tests/tls/ca.pem";
+    // The certificate covers localhost and 127.0.0.1, the host of the URL is localhost
+    let json = tls_json(
+        false,
+        http::Version::HTTP_11,
+        &["--cacert", ca, "--sni", "127.0.0.1"],
+    )
+    .await;
+    assert_eq!(json["statusCodeDistribution"]["200"], 3);
+
+    let json = tls_json(
+        false,
+        http::Version::HTTP_11,
+        &["--cacert", ca, "--sni", "other.example"],
+    )
+    .await;
+    assert_eq!(json["summary"]["successRate"], 0.0);
+
+    // --connect-host doesn't change the name
+    let json = tls_json(
+        false,
+        http::Version::HTTP_11,
+        &[
+            "--cacert",
+            ca,
+            "--connect-host",
+            "127.0.0.1",
+            "--sni",
+            "other.example",
+        ],
+    )
+    .await;
+    assert_eq!(json["summary"]["successRate"], 0.0);
+
+    let output = Command::cargo_bin("oha")
+        .unwrap()
+        .args(["-n", "1", "--no-tui", "--sni", "localhost"])
+        .arg("http://127.0.0.1:1/")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--sni requires an https"));
+}
+
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+#[tokio::test]
+async fn test_tls_client_cert() {
+    let json = tls_json(
+        true,
+        http::Version::HTTP_11,
+        &[
+            "--cacert",
+            "tests/tls/ca.pem",
+            "--cert",
+            "tests/tls/client.pem",
+            "--key",
+            "tests/tls/client.key",
+        ],
+    )
+    .await;
+    assert_eq!(json["statusCodeDistribution"]["200"], 3);
+
+    let json = tls_json(
+        true,
+        http::Version::HTTP_11,
+        &["--cacert", "tests/tls/ca.pem"],
+    )
+    .await;
+    assert_eq!(json["summary"]["successRate"], 0.0);
+    assert!(json["statusCodeDistribution"].get("200").is_none());
+}
+
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+#[tokio::test]
+async fn test_tls_http2_alpn() {
+    let json = tls_json(
+        false,
+        http::Version::HTTP_2,
+        &["--http2", "--cacert", "tests/tls/ca.pem"],
+    )
+    .await;
+    assert_eq!(json["statusCodeDistribution"]["200"], 3);
+}
+
+#[tokio::test]
+async fn test_read_timeout() {
+    // The server stops in the middle of the body
+    let (json, accepted) = raw_http1_json(
+        &["--read-timeout", "100ms"],
+        |_| "HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\nabc",
+        false,
+    )
+    .await;
+    assert_eq!(json["errorDistribution"]["read timeout"], 20);
+    // A connection is never reused after a read timeout
+    assert_eq!(accepted, 20);
+
+    // The server never sends the response header
+    let (json, accepted) = raw_http1_json(&["--read-timeout", "100ms"], |_| "", false).await;
+    assert_eq!(json["errorDistribution"]["read timeout"], 20);
+    assert_eq!(accepted, 20);
+}
+
+/// Run `oha -n 4 -c 1 -t 300ms` against a server which stalls for a second in the middle of the
+/// body of its first response, then sends the rest of it.
+/// Returns the JSON and how many connections the server accepted.
+async fn slow_body_json(args: &[&str]) -> (serde_json::Value, usize) {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    let (listener, port) = bind_port().await;
+    let accepted = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let requests = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let server_accepted = accepted.clone();
+    tokio::spawn(async move {
+        loop {
+            let (tcp, _) = listener.accept().await.unwrap();
+            server_accepted.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let requests = requests.clone();
+            tokio::spawn(async move {
+                let mut tcp = tokio::io::BufReader::new(tcp);
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    if tcp.read_line(&mut line).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    if line != "\r\n" {
+                        continue;
+                    }
+                    let first = requests.fetch_add(1, std::sync::atomic::Ordering::Relaxed) == 0;
+                    let head = "HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\nabcde";
+                    if tcp.write_all(head.as_bytes()).await.is_err() {
+                        return;
+                    }
+                    if first {
+                        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                    }
+                    // A reused connection would read this as the next response
+                    if tcp.write_all(b"fghij").await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    let output = run_oha(
+        &[&["-n", "4", "-c", "1", "-t", "300ms"], args].concat(),
+        format!("http://127.0.0.1:{port}/"),
+    )
+    .await;
+    (
+        summary(&output),
+        accepted.load(std::sync::atomic::Ordering::Relaxed),
+    )
+}
+
+#[tokio::test]
+async fn test_timeout() {
+    // Read without hyper, and with hyper
+    for args in [&["--prebuilt-requests"][..], &[]] {
+        let (json, accepted) = slow_body_json(args).await;
+        assert_eq!(json["summary"]["timeouts"], 1, "{args:?}");
+        assert_eq!(
+            json["errorDistribution"],
+            serde_json::json!({"timeout": 1}),
+            "{args:?}"
+        );
+        assert_eq!(json["statusCodeDistribution"]["200"], 3, "{args:?}");
+        // The connection with the half-read response is dropped
+        assert_eq!(accepted, 2, "{args:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_bodyless_responses() {
+    // Content-Length of HEAD is the size of the body GET would return
+    let (json, accepted) = raw_http1_json(
+        &["-m", "HEAD"],
+        |method| {
+            assert_eq!(method, "HEAD");
+            "HTTP/1.1 200 OK\r\ncontent-length: 100\r\n\r\n"
+        },
+        false,
+    )
+    .await;
+    assert_eq!(json["statusCodeDistribution"]["200"], 20);
+    assert_eq!(json["summary"]["totalData"], 0);
+    // No reconnection. A worker which doesn't get any request doesn't connect.
+    assert!(accepted <= 2);
+
+    let (json, accepted) = raw_http1_json(
+        &[],
+        |_| "HTTP/1.1 204 No Content\r\nconnection: keep-alive\r\n\r\n",
+        false,
+    )
+    .await;
+    assert_eq!(json["statusCodeDistribution"]["204"], 20);
+    assert_eq!(json["summary"]["totalData"], 0);
+    assert!(accepted <= 2);
+
+    // 304 may have the Content-Length of the cached representation
+    let (json, accepted) = raw_http1_json(
+        &["-H", "If-None-Match: \"a\""],
+        |_| "HTTP/1.1 304 Not Modified\r\netag: \"a\"\r\ncontent-length: 100\r\n\r\n",
+        false,
+    )
+    .await;
+    assert_eq!(json["statusCodeDistribution"]["304"], 20);
+    assert_eq!(json["summary"]["totalData"], 0);
+    assert_eq!(json["errorDistribution"], serde_json::json!({}));
+    assert!(accepted <= 2);
+}
+
+/// `close` tells whether the server closes the connection after `response`.
+async fn check_keep_alive(response: fn(&str) -> &'static str, close: bool) {
+    let (json, accepted) = raw_http1_json(&[], response, close).await;
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert_eq!(json["errorDistribution"], serde_json::json!({}));
+    assert_eq!(json["summary"]["totalData"], 100);
+    assert_eq!(accepted, if close { 20 } else { 2 });
+}
+
+#[tokio::test]
+async fn test_keep_alive_semantics() {
+    // HTTP/1.0 closes by default and the body ends at EOF
+    check_keep_alive(|_| "HTTP/1.0 200 OK\r\n\r\nhello", true).await;
+    check_keep_alive(
+        |_| "HTTP/1.0 200 OK\r\nconnection: keep-alive\r\ncontent-length: 5\r\n\r\nhello",
+        false,
+    )
+    .await;
+    check_keep_alive(
+        |_| "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello",
+        false,
+    )
+    .await;
+    check_keep_alive(
+        |_| "HTTP/1.1 200 OK\r\nconnection: close\r\n\r\nhello",
+        true,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_truncated_response() {
+    let (json, accepted) = raw_http1_json(
+        &[],
+        |_| "HTTP/1.1 200 OK\r\ncontent-length: 100\r\n\r\nonly 20 bytes here..",
+        true,
+    )
+    .await;
+    assert_eq!(json["summary"]["successRate"], 0.0);
+    assert_eq!(
+        json["errorDistribution"]["truncated response: received 20 of 100 bytes"],
+        20
+    );
+    assert_eq!(json["statusCodeDistribution"], serde_json::json!({}));
+    // The connection is not reused
+    assert_eq!(accepted, 20);
+
+    let (json, _) = raw_http1_json(
+        &[],
+        |_| "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n5\r\nhello\r\n",
+        true,
+    )
+    .await;
+    assert_eq!(
+        json["errorDistribution"]["truncated response: connection closed in the chunked body"],
+        20
+    );
+}
+
+/// A server of a gzip body, with the lengths of the body and of the text in it
+fn gzip_app() -> (Router, usize, usize) {
+    use std::io::Write;
+
+    let text = "hello world ".repeat(1000);
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(text.as_bytes()).unwrap();
+    let gzip = encoder.finish().unwrap();
+    let gzip_len = gzip.len();
+
+    let app = Router::new().route(
+        "/",
+        get(move || async move { ([(http::header::CONTENT_ENCODING, "gzip")], gzip.clone()) }),
+    );
+    (app, gzip_len, text.len())
+}
+
+async fn gzip_json(args: &[&str]) -> (serde_json::Value, usize, usize) {
+    let (app, gzip_len, text_len) = gzip_app();
+    (
+        run_json(app, &[&["-n", "10"], args].concat()).await,
+        gzip_len,
+        text_len,
+    )
+}
+
+#[tokio::test]
+async fn test_decompressed_size() {
+    let (json, gzip_len, _) = gzip_json(&[]).await;
+    assert_eq!(json["summary"]["totalData"], 10 * gzip_len);
+    assert!(json["summary"].get("totalDecompressedData").is_none());
+
+    for args in [&[][..], &["--http2"][..], &["--prebuilt-requests"][..]] {
+        let args = [args, &["--decompressed-size"]].concat();
+        let (json, gzip_len, text_len) = gzip_json(&args).await;
+        assert_eq!(json["summary"]["totalData"], 10 * gzip_len);
+        assert_eq!(json["summary"]["sizePerRequest"], gzip_len);
+        assert_eq!(json["summary"]["totalDecompressedData"], 10 * text_len);
+        assert_eq!(json["summary"]["decompressedSizePerRequest"], text_len);
+    }
+
+    let (app, gzip_len, _) = gzip_app();
+    let port = serve(app).await;
+    let output = tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "10", "--no-tui", "--decompressed-size"])
+            .arg(format!("http://127.0.0.1:{port}/"))
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains(&format!("Size/request:\t{gzip_len} B (wire bytes)")),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("Size/request:\t11.72 KiB (decompressed)"),
+        "{stdout}"
+    );
+}
+
+/// Run oha against a WebSocket server. `/echo` echoes messages and `/silent` never replies.
+/// Returns the JSON output and the number of close frames the server received.
+async fn websocket_json(path: &'static str, args: &[&str]) -> (serde_json::Value, usize) {
+    use axum::extract::ws::{Message, WebSocketUpgrade};
+
+    let (tx, rx) = flume::unbounded();
+    let echo = move |ws: WebSocketUpgrade| async move {
+        ws.on_upgrade(|mut socket| async move {
+            while let Some(Ok(message)) = socket.recv().await {
+                match message {
+                    Message::Text(_) | Message::Binary(_) => socket.send(message).await.unwrap(),
+                    Message::Close(_) => {
+                        tx.send(()).unwrap();
+                        return;
+                    }
+                    _ => {}
+                }
+            }
+        })
+    };
+    let silent = |ws: WebSocketUpgrade| async move {
+        ws.on_upgrade(|mut socket| async move { while socket.recv().await.is_some() {} })
+    };
+    let app = Router::new()
+        .route("/echo", get(echo))
+        .route("/silent", get(silent));
+    let port = serve(app).await;
+    let output = run_oha(args, format!("ws://127.0.0.1:{port}{path}")).await;
+    (summary(&output), rx.drain().count())
+}
+
+#[tokio::test]
+async fn test_websocket() {
+    let (json, closes) = websocket_json(
+        "/echo",
+        &[
+            "-n",
+            "10",
+            "-c",
+            "2",
+            "-d",
+            "hello",
+            "--ws-messages-per-connection",
+            "3",
+        ],
+    )
+    .await;
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert_eq!(json["summary"]["totalData"], 50);
+    assert_eq!(json["statusCodeDistribution"]["101"], 10);
+    // Each worker reconnects after 3 messages, whichever of them sends more
+    assert_eq!(json["websocket"]["handshakes"], 4);
+    // Every connection is closed with a close frame
+    assert_eq!(closes, 4);
+
+    // Idle connections between rate limited messages are pinged
+    let (json, _) = websocket_json(
+        "/echo",
+        &[
+            "-n",
+            "3",
+            "-c",
+            "1",
+            "-q",
+            "2",
+            "--ws-ping-interval",
+            "100ms",
+        ],
+    )
+    .await;
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert!(json["websocket"]["pings"].as_u64().unwrap() >= 2);
+
+    let (json, _) = websocket_json("/not-found", &["-n", "2", "-c", "1"]).await;
+    assert_eq!(
+        json["errorDistribution"]["WebSocket handshake failed: unexpected status 404 Not Found"],
+        2
+    );
+
+    let (json, _) = websocket_json("/silent", &["-n", "2", "-c", "1", "-t", "200ms"]).await;
+    assert_eq!(json["errorDistribution"]["WebSocket message timeout"], 2);
+    assert_eq!(json["websocket"]["handshakes"], 2);
+}
+
+#[tokio::test]
+async fn test_expect_continue() {
+    let json = expect_continue_json("/", &["-d", "hello"]).await;
+    assert_eq!(json["expectContinue"]["received"], 2);
+    assert_eq!(json["statusCodeDistribution"]["200"], 2);
+    assert_eq!(json["summary"]["sizePerRequest"], 5);
+
+    let json = expect_continue_json("/reject", &["-d", "hello"]).await;
+    assert_eq!(json["expectContinue"]["bodyNotSent"], 2);
+    assert_eq!(json["statusCodeDistribution"]["413"], 2);
+
+    let json = expect_continue_json("/", &[]).await;
+    assert!(json.get("expectContinue").is_none());
+}
+
+#[tokio::test]
+async fn test_setting_content_type_header() {
+    let header = get_header_body(&["-T", "text/html"]).await.0;
+    assert_eq!(
+        header.get("content-type").unwrap().to_str().unwrap(),
+        "text/html"
+    );
+    let header = get_header_body(&["-H", "content-type:text/html"]).await.0;
+    assert_eq!(
+        header.get("content-type").unwrap().to_str().unwrap(),
+        "text/html"
+    );
+
+    let header = get_header_body(&["--http2", "-T", "text/html"]).await.0;
+    assert_eq!(
+        header.get("content-type").unwrap().to_str().unwrap(),
+        "text/html"
+    );
+    let header = get_header_body(&["--http2", "-H", "content-type:text/html"])
+        .await
+        .0;
+    assert_eq!(
+        header.get("content-type").unwrap().to_str().unwrap(),
+        "text/html"
+    );
+}
+
+#[tokio::test]
+async fn test_setting_basic_auth() {
+    let header = get_header_body(&["-a", "hatoo:pass"]).await.0;
+    assert_eq!(
+        header.get("authorization").unwrap().to_str().unwrap(),
+        "Basic aGF0b286cGFzcw=="
+    );
+
+    let header = get_header_body(&["--http2", "-a", "hatoo:pass"]).await.0;
+    assert_eq!(
+        header.get("authorization").unwrap().to_str().unwrap(),
+        "Basic aGF0b286cGFzcw=="
+    );
+
+    // As curl sends them
+    for (credentials, expected) in [
+        ("hatoo:pa:ss", "Basic aGF0b286cGE6c3M="),
+        ("hatoo:", "Basic aGF0b286"),
+        (":pass", "Basic OnBhc3M="),
+    ] {
+        let header = get_header_body(&["-a", credentials]).await.0;
+        assert_eq!(
+            header.get("authorization").unwrap().to_str().unwrap(),
+            expected
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_basic_auth_from_url() {
+    for args in [&[][..], &["--http2"]] {
+        let header = get_header_with_userinfo("hatoo:pass", args).await;
+        assert_eq!(
+            header.get("authorization").unwrap().to_str().unwrap(),
+            "Basic aGF0b286cGFzcw=="
+        );
+        // HTTP/2 sends the authority as a pseudo-header instead
+        assert!(header
+            .get("host")
+            .is_none_or(|host| !host.to_str().unwrap().contains('@')));
+    }
+
+    // Percent-encoded characters are decoded
+    let header = get_header_with_userinfo("ha%40too:p%3Ass", &[]).await;
+    assert_eq!(
+        header.get("authorization").unwrap().to_str().unwrap(),
+        "Basic aGFAdG9vOnA6c3M="
+    );
+    let header = get_header_with_userinfo(":pass", &[]).await;
+    assert_eq!(
+        header.get("authorization").unwrap().to_str().unwrap(),
+        "Basic OnBhc3M="
+    );
+
+    // `-a` wins over the URL
+    let header = get_header_with_userinfo("user:secret", &["-a", "hatoo:pass"]).await;
+    assert_eq!(
+        header.get("authorization").unwrap().to_str().unwrap(),
+        "Basic aGF0b286cGFzcw=="
+    );
+}
+
+#[tokio::test]
+async fn test_setting_host() {
+    let header = get_header_body(&["--host", "hatoo.io"]).await.0;
+    assert_eq!(header.get("host").unwrap().to_str().unwrap(), "hatoo.io");
+
+    let header = get_header_body(&["-H", "host:hatoo.io"]).await.0;
+    assert_eq!(header.get("host").unwrap().to_str().unwrap(), "hatoo.io");
+
+    // You shouldn't set host header when using HTTP/2
+    // Use --connect-to instead
+}
+
+#[tokio::test]
+async fn test_setting_method() {
+    assert_eq!(get_method(&[]).await, http::method::Method::GET);
+    assert_eq!(get_method(&["-m", "GET"]).await, http::method::Method::GET);
+    assert_eq!(
+        get_method(&["-m", "POST"]).await,
+        http::method::Method::POST
+    );
+    assert_eq!(
+        get_method(&["-m", "CONNECT"]).await,
+        http::method::Method::CONNECT
+    );
+    assert_eq!(
+        get_method(&["-m", "DELETE"]).await,
+        http::method::Method::DELETE
+    );
+    assert_eq!(
+        get_method(&["-m", "HEAD"]).await,
+        http::method::Method::HEAD
+    );
+    assert_eq!(
+        get_method(&["-m", "OPTIONS"]).await,
+        http::method::Method::OPTIONS
+    );
+    assert_eq!(
+        get_method(&["-m", "PATCH"]).await,
+        http::method::Method::PATCH
+    );
+    assert_eq!(get_method(&["-m", "PUT"]).await, http::method::Method::PUT);
+    assert_eq!(
+        get_method(&["-m", "TRACE"]).await,
+        http::method::Method::TRACE
+    );
+
+    assert_eq!(get_method(&["--http2"]).await, http::method::Method::GET);
+    assert_eq!(
+        get_method(&["--http2", "-m", "GET"]).await,
+        http::method::Method::GET
+    );
+    assert_eq!(
+        get_method(&["--http2", "-m", "POST"]).await,
+        http::method::Method::POST
+    );
+    assert_eq!(
+        get_method(&["--http2", "-m", "DELETE"]).await,
+        http::method::Method::DELETE
+    );
+    assert_eq!(
+        get_method(&["--http2", "-m", "HEAD"]).await,
+        http::method::Method::HEAD
+    );
+    assert_eq!(
+        get_method(&["--http2", "-m", "OPTIONS"]).await,
+        http::method::Method::OPTIONS
+    );
+    assert_eq!(
+        get_method(&["--http2", "-m", "PATCH"]).await,
+        http::method::Method::PATCH
+    );
+    assert_eq!(
+        get_method(&["--http2", "-m", "PUT"]).await,
+        http::method::Method::PUT
+    );
+    assert_eq!(
+        get_method(&["--http2", "-m", "TRACE"]).await,
+        http::method::Method::TRACE
+    );
+}
+
+#[tokio::test]
+async fn test_method_mix() {
+    let (tx, rx) = flume::unbounded();
+    let app = Router::new().route(
+        "/",
+        any(
+            |method: http::Method, header: HeaderMap, body: Bytes| async move {
+                tx.send((method, header, body)).unwrap();
+                "Hello World"
+            },
+        ),
+    );
+
+    let port = serve(app).await;
+
+    let output = tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "40", "--no-tui", "--json", "--per-method-stats"])
+            .args([
+                "--method-mix",
+                "GET:1,POST:1",
+                "-d",
+                "hello",
+                "-T",
+                "text/plain",
+            ])
+            .arg(format!("http://127.0.0.1:{port}"))
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap();
+    assert!(output.status.success());
+
+    let mut counts: std::collections::HashMap<http::Method, u64> = Default::default();
+    for (method, header, body) in rx.drain() {
+        if method == http::Method::GET {
+            assert!(body.is_empty());
+            assert!(header.get("content-type").is_none());
+        } else {
+            assert_eq!(method, http::Method::POST);
+            assert_eq!(body.as_ref(), b"hello");
+            assert_eq!(header.get("content-type").unwrap(), "text/plain");
+        }
+        *counts.entry(method).or_default() += 1;
+    }
+    assert_eq!(counts.values().sum::<u64>(), 40);
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    for (method, count) in counts {
+        assert_eq!(json["perMethod"][method.as_str()]["count"], count);
+    }
+
+    Command::cargo_bin("oha")
+        .unwrap()
+        .args([
+            "-n",
+            "1",
+            "--no-tui",
+            "--method-mix",
+            "GET:1,HEAD:1",
+            "-d",
+            "x",
+        ])
+        .arg(format!("http://127.0.0.1:{port}"))
+        .assert()
+        .failure();
+}
+
+async fn scenario_json(scenario: &str) -> serde_json::Value {
+    let app = Router::new()
+        .route(
+            "/login",
+            any(|body: Bytes| async move {
+                assert_eq!(body.as_ref(), br#"{"user":"oha"}"#);
+                axum::Json(serde_json::json!({"token": "abc", "id": 7}))
+            }),
+        )
+        .route(
+            "/items/:id",
+            get(|Path(id): Path<u32>, header: HeaderMap| async move {
+                if id == 7 && header.get("authorization").unwrap() == "Bearer abc" {
+                    http::StatusCode::OK
+                } else {
+                    http::StatusCode::UNAUTHORIZED
+                }
+            }),
+        );
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), scenario).unwrap();
+    let scenario = file.path().to_str().unwrap();
+    run_json(app, &["-n", "10", "-c", "1", "--scenario", scenario]).await
+}
+
+#[tokio::test]
+async fn test_scenario() {
+    let json = scenario_json(
+        r#"
+        [[step]]
+        name = "login"
+        method = "POST"
+        path = "/login"
+        body = '{"user":"oha"}'
+        extract = { token = { json = "/token" }, id = { json = "/id" } }
+
+        [[step]]
+        name = "item"
+        path = "/items/{{id}}"
+        headers = { authorization = "Bearer {{token}}" }
+        "#,
+    )
+    .await;
+    assert_eq!(json["perStep"]["login"]["count"], 5);
+    assert_eq!(json["perStep"]["item"]["count"], 5);
+    assert_eq!(json["perStep"]["item"]["statusCodeDistribution"]["200"], 5);
+    assert_eq!(json["errorDistribution"], serde_json::json!({}));
+
+    // Extraction fails, so the second step is never reached
+    let json = scenario_json(
+        r#"
+        [[step]]
+        name = "login"
+        method = "POST"
+        path = "/login"
+        body = '{"user":"oha"}'
+        extract = { token = { json = "/missing" } }
+
+        [[step]]
+        name = "item"
+        path = "/items/7"
+        headers = { authorization = "Bearer {{token}}" }
+        "#,
+    )
+    .await;
+    assert!(json["perStep"].get("item").is_none());
+    assert_eq!(
+        json["errorDistribution"]["scenario step login failed: /missing is not found for token"],
+        10
+    );
+}
+
+#[tokio::test]
+async fn test_har() {
+    let (tx, rx) = flume::unbounded();
+    let app = Router::new().route(
+        "/*path",
+        any(
+            |method: http::Method, req_uri: http::Uri, header: HeaderMap, body: Bytes| async move {
+                tx.send((method, req_uri.to_string(), header, body))
+                    .unwrap();
+                "Hello World"
+            },
+        ),
+    );
+
+    let port = serve(app).await;
+
+    let har = r#"{"log": {"entries": [
+        {"request": {"method": "GET", "url": "https://example.com/a?x=1",
+            "headers": [{"name": "Cookie", "value": "s=1"}, {"name": "X-Test", "value": "a"}]}},
+        {"request": {"method": "POST", "url": "https://example.com/b", "headers": [],
+            "postData": {"mimeType": "text/plain", "text": "hello"}}}
+    ]}}"#;
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), har).unwrap();
+    let path = file.path().to_str().unwrap().to_string();
+
+    let url = format!("http://127.0.0.1:{port}/");
+    let output = {
+        let (path, url) = (path.clone(), url.clone());
+        tokio::task::spawn_blocking(move || {
+            Command::cargo_bin("oha")
+                .unwrap()
+                .args([
+                    "-n",
+                    "4",
+                    "-c",
+                    "1",
+                    "--no-tui",
+                    "--json",
+                    "--per-url-stats",
+                ])
+                .args([
+                    "--har",
+                    &path,
+                    "--har-any-origin",
+                    "--har-strip-cookies",
+                    &url,
+                ])
+                .output()
+                .unwrap()
+        })
+        .await
+        .unwrap()
+    };
+    assert!(output.status.success());
+
+    let requests: Vec<_> = rx.drain().collect();
+    assert_eq!(requests.len(), 4);
+    for (i, (method, uri, header, body)) in requests.into_iter().enumerate() {
+        if i % 2 == 0 {
+            assert_eq!(method, http::Method::GET);
+            assert_eq!(uri, "/a?x=1");
+            assert_eq!(header.get("x-test").unwrap(), "a");
+            assert!(header.get("cookie").is_none());
+        } else {
+            assert_eq!(method, http::Method::POST);
+            assert_eq!(uri, "/b");
+            assert_eq!(body.as_ref(), b"hello");
+        }
+    }
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["perUrl"]["/a?x=1"]["count"], 2);
+    assert_eq!(json["perUrl"]["/b"]["count"], 2);
+
+    tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui", "--har", &path, &url])
+            .assert()
+            .failure();
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_from_curl() {
+    let (method, header, body) = get_method_header_body(&[
+        "--from-curl",
+        "curl -X PUT -H 'X-Test: a b' -d 'k=v' -u user:pass http://example.com/",
+    ])
+    .await;
+    assert_eq!(method, http::Method::PUT);
+    assert_eq!(header.get("x-test").unwrap(), "a b");
+    assert_eq!(
+        header.get("content-type").unwrap(),
+        "application/x-www-form-urlencoded"
+    );
+    assert_eq!(header.get("authorization").unwrap(), "Basic dXNlcjpwYXNz");
+    assert_eq!(body.as_ref(), b"k=v");
+
+    // Explicit options override the curl command
+    let (method, header, body) = get_method_header_body(&[
+        "--from-curl",
+        "curl -H 'X-Test: a' -d 'k=v' http://example.com/",
+        "-m",
+        "PATCH",
+        "-H",
+        "X-Test: b",
+        "-d",
+        "override",
+    ])
+    .await;
+    assert_eq!(method, http::Method::PATCH);
+    assert_eq!(header.get("x-test").unwrap(), "b");
+    assert_eq!(body.as_ref(), b"override");
+}
+
+#[tokio::test]
+async fn test_query() {
+    assert_eq!(
+        get_query("/index?a=b&c=d", &[]).await,
+        "/index?a=b&c=d".to_string()
+    );
+
+    assert_eq!(
+        get_query("/index?a=b&c=d", &["--http2"])
+            .await
+            .split('/')
+            .next_back()
+            .unwrap(),
+        "index?a=b&c=d".to_string()
+    );
+}
+
+#[tokio::test]
+async fn test_cache_bust() {
+    let query = get_query("/index", &["--cache-bust"]).await;
+    assert!(query.starts_with("/index?_oha="), "{query}");
+
+    let query = get_query("/index?a=b", &["--cache-bust=x y"]).await;
+    assert!(query.starts_with("/index?a=b&x+y="), "{query}");
+
+    let query = get_path_rand_regex("/[a-z]{3}", &["--cache-bust=c"]).await;
+    assert!(query.contains("?c="), "{query}");
+
+    // The values come from --rand-seed
+    let seeded = &["--cache-bust", "--rand-seed", "42"];
+    assert_eq!(
+        get_query("/index", seeded).await,
+        get_query("/index", seeded).await
+    );
+    assert_ne!(
+        get_query("/index", &["--cache-bust"]).await,
+        get_query("/index", &["--cache-bust"]).await
+    );
+
+    // Unique within a run, however few URLs the regex gives
+    let (requests, _) = seeded_json(&["--cache-bust"]).await;
+    let unique: std::collections::HashSet<_> = requests
+        .iter()
+        .map(|request| request.split_once("?_oha=").unwrap().1)
+        .collect();
+    assert_eq!(unique.len(), requests.len());
+}
+
+#[tokio::test]
+async fn test_query_rand_regex() {
+    let query = get_path_rand_regex("/[a-z][0-9][a-z]", &[]).await;
+    let chars = query.trim_start_matches('/').chars().collect::<Vec<char>>();
+    assert_eq!(chars.len(), 3);
+    assert!(chars[0].is_ascii_lowercase());
+    assert!(chars[1].is_ascii_digit());
+    assert!(chars[2].is_ascii_lowercase());
+
+    let query = get_path_rand_regex("/[a-z][0-9][a-z]", &["--http2"]).await;
+    let chars = query
+        .split('/')
+        .next_back()
+        .unwrap()
+        .chars()
+        .collect::<Vec<char>>();
+    assert_eq!(chars.len(), 3);
+    assert!(chars[0].is_ascii_lowercase());
+    assert!(chars[1].is_ascii_digit());
+    assert!(chars[2].is_ascii_lowercase());
+}
+
+#[tokio::test]
+async fn test_query_rand_regex_seed() {
+    let a = get_path_rand_regex("/[a-z]{16}", &["--rand-seed", "42"]).await;
+    let b = get_path_rand_regex("/[a-z]{16}", &["--rand-seed", "42"]).await;
+    let c = get_path_rand_regex("/[a-z]{16}", &["--rand-seed", "43"]).await;
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+}
+
+#[test]
+fn test_query_rand_regex_varying_authority() {
+    let assert = Command::cargo_bin("oha")
+        .unwrap()
+        .args(["-n", "1", "--no-tui", "--rand-regex-url"])
+        .arg("http://127.0.0.[1-9]/")
+        .assert()
+        .failure();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("must be literal"), "{stderr}");
+}
+
+#[tokio::test]
+async fn test_redirect() {
+    for args in [&[][..], &["--prebuilt-requests"]] {
+        for n in 1..=5 {
+            assert!(redirect(n, true, 10, args).await, "{args:?}");
+            assert!(redirect(n, false, 10, args).await, "{args:?}");
+        }
+        for n in 11..=15 {
+            assert!(!redirect(n, true, 10, args).await, "{args:?}");
+            assert!(!redirect(n, false, 10, args).await, "{args:?}");
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_connect_to() {
+    assert_eq!(
+        get_host_with_connect_to("invalid.example.org").await,
+        "invalid.example.org"
+    )
+}
+
+#[tokio::test]
+async fn test_connect_to_idn() {
+    assert_eq!(
+        get_host_with_connect_to("bücher.example").await,
+        "xn--bcher-kva.example"
+    );
+    assert_eq!(
+        get_host_with_connect_to("xn--bcher-kva.example").await,
+        "xn--bcher-kva.example"
+    );
+}
+
+/// The Host header that a server on `[::1]` or `127.0.0.1` receives from `url`. `{port}` in
+/// `url` and `args` is replaced with the port of the server, and back in the result.
+async fn get_host_with_connect_host(ipv6: bool, url: &str, args: &[&str]) -> String {
+    let (tx, rx) = flume::unbounded();
+    let app = Router::new().route(
+        "/",
+        get(|header: HeaderMap| async move {
+            tx.send(header.get("host").unwrap().to_str().unwrap().to_string())
+                .unwrap();
+            "Hello World"
+        }),
+    );
+
+    let (listener, port) = if ipv6 {
+        bind_port_ipv6().await
+    } else {
+        bind_port().await
+    };
+    tokio::spawn(async { axum::serve(listener, app).await });
+
+    let port = port.to_string();
+    let url = url.replace("{port}", &port);
+    let args: Vec<String> = args.iter().map(|s| s.replace("{port}", &port)).collect();
+    tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui"])
+            .args(args)
+            .arg(url)
+            .assert()
+            .success();
+    })
+    .await
+    .unwrap();
+
+    rx.try_recv().unwrap().replace(&port, "{port}")
+}
+
+#[tokio::test]
+async fn test_connect_host() {
+    // The URL still gives the Host header
+    assert_eq!(
+        get_host_with_connect_host(
+            false,
+            "http://invalid.example.org/",
+            &["--connect-host", "127.0.0.1:{port}"]
+        )
+        .await,
+        "invalid.example.org"
+    );
+    assert_eq!(
+        get_host_with_connect_host(
+            false,
+            "http://bücher.example/",
+            &["--connect-host", "localhost:{port}"]
+        )
+        .await,
+        "xn--bcher-kva.example"
+    );
+    // Without a port, the one of the URL
+    assert_eq!(
+        get_host_with_connect_host(
+            false,
+            "http://invalid.example.org:{port}/",
+            &["--connect-host", "localhost"]
+        )
+        .await,
+        "invalid.example.org:{port}"
+    );
+}
+
+#[tokio::test]
+async fn test_connect_host_ipv6() {
+    assert_eq!(
+        get_host_with_connect_host(
+            true,
+            "http://invalid.example.org/",
+            &["--connect-host", "[::1]:{port}"]
+        )
+        .await,
+        "invalid.example.org"
+    );
+    assert_eq!(
+        get_host_with_connect_host(
+            true,
+            "http://invalid.example.org:{port}/",
+            &["--connect-host", "::1"]
+        )
+        .await,
+        "invalid.example.org:{port}"
+    );
+}
+
+#[tokio::test]
+async fn test_connect_host_with_host() {
+    // --host changes only the header
+    assert_eq!(
+        get_host_with_connect_host(
+            false,
+            "http://invalid.example.org/",
+            &[
+                "--connect-host",
+                "127.0.0.1:{port}",
+                "--host",
+                "other.example"
+            ]
+        )
+        .await,
+        "other.example"
+    );
+}
+
+#[tokio::test]
+async fn test_connect_host_with_connect_to() {
+    // A matching --connect-to wins over --connect-host, which points at a closed port here
+    assert_eq!(
+        get_host_with_connect_host(
+            false,
+            "http://invalid.example.org/",
+            &[
+                "--connect-host",
+                "127.0.0.1:1",
+                "--connect-to",
+                "invalid.example.org:80:localhost:{port}"
+            ]
+        )
+        .await,
+        "invalid.example.org"
+    );
+    // --connect-host applies to the hosts that no --connect-to matches
+    assert_eq!(
+        get_host_with_connect_host(
+            false,
+            "http://invalid.example.org/",
+            &[
+                "--connect-host",
+                "127.0.0.1:{port}",
+                "--connect-to",
+                "other.example:80:127.0.0.1:1"
+            ]
+        )
+        .await,
+        "invalid.example.org"
+    );
+}
+
+#[tokio::test]
+async fn test_connect_host_redirect() {
+    let (tx, rx) = flume::unbounded();
+    let destination = Router::new().route(
+        "/destination",
+        get(move || async move {
+            tx.send(()).unwrap();
+            "Hello World"
+        }),
+    );
+    let (listener, destination_port) = bind_port().await;
+    tokio::spawn(async { axum::serve(listener, destination).await });
+
+    let source = Router::new().route(
+        "/source",
+        get(move || async move {
+            Redirect::permanent(&format!("http://localhost:{destination_port}/destination"))
+        }),
+    );
+    let (listener, port) = bind_port().await;
+    tokio::spawn(async { axum::serve(listener, source).await });
+
+    // The redirect to another host doesn't go to --connect-host
+    tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui"])
+            .arg("http://invalid.example.org/source")
+            .arg("--connect-host")
+            .arg(format!("127.0.0.1:{port}"))
+            .assert()
+            .success();
+    })
+    .await
+    .unwrap();
+
+    assert!(rx.try_recv().is_ok());
+}
+
+#[tokio::test]
+async fn test_idn_summary() {
+    let (listener, port) = bind_port().await;
+    tokio::spawn(async {
+        axum::serve(listener, Router::new().route("/", get(|| async { "" }))).await
+    });
+
+    let output = tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui", "--json"])
+            .arg("http://bücher-книги.example/")
+            .arg("--connect-to")
+            .arg(format!(
+                "xn--bcher--3ya307djbbt0b.example:80:localhost:{port}"
+            ))
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert_eq!(
+        json["summary"]["host"],
+        "bücher-книги.example (xn--bcher--3ya307djbbt0b.example)"
+    );
+}
+
+#[tokio::test]
+async fn test_connect_to_ipv6_target() {
+    assert_eq!(
+        get_host_with_connect_to_ipv6_target("invalid.example.org").await,
+        "invalid.example.org"
+    )
+}
+
+#[tokio::test]
+async fn test_connect_to_ipv6_requested() {
+    assert_eq!(get_host_with_connect_to_ipv6_requested().await, "[::1]")
+}
+
+#[tokio::test]
+async fn test_connect_to_redirect() {
+    assert_eq!(
+        get_host_with_connect_to_redirect("invalid.example.org").await,
+        "invalid.example.org"
+    )
+}
+
+#[tokio::test]
+async fn test_ipv6() {
+    let (tx, rx) = flume::unbounded();
+
+    let app = Router::new().route(
+        "/",
+        get(|| async move {
+            tx.send(()).unwrap();
+            "Hello World"
+        }),
+    );
+
+    let (listener, port) = bind_port_ipv6().await;
+    tokio::spawn(async { axum::serve(listener, app).await });
+
+    tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui"])
+            .arg(format!("http://[::1]:{port}/"))
+            .assert()
+            .success();
+    })
+    .await
+    .unwrap();
+
+    rx.try_recv().unwrap();
+}
+
+#[tokio::test]
+async fn test_ipv6_literal() {
+    // `--ipv4` doesn't apply to an address which needs no lookup
+    let (host, port) = get_host_ipv6(
+        |port| format!("http://[::1]:{port}/"),
+        |_| vec!["--ipv4".to_string()],
+    )
+    .await;
+    assert_eq!(host, format!("[::1]:{port}"));
+
+    // Without a port. The requested address of `--connect-to` is compared after normalization.
+    let (host, _) = get_host_ipv6(
+        |_| "http://[0:0::1]/".to_string(),
+        |port| {
+            vec![
+                "--ipv4".to_string(),
+                "--connect-to".to_string(),
+                format!("[::0:1]:80:[::1]:{port}"),
+            ]
+        },
+    )
+    .await;
+    assert_eq!(host, "[::1]");
+}
+
+#[tokio::test]
+async fn test_default_scheme() {
+    let (listener, port) = bind_port().await;
+    tokio::spawn(async {
+        axum::serve(
+            listener,
+            Router::new().route("/health", get(|| async { "" })),
+        )
+        .await
+    });
+    let output = tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "2", "-c", "1", "--no-tui", "--json"])
+            .arg(format!("localhost:{port}/health"))
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["statusCodeDistribution"]["200"], 2);
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("note: the URL has no scheme, so http:// is assumed"));
+
+    for (url, error) in [
+        ("", "the URL is empty"),
+        ("/health", "the URL \"/health\" has no host"),
+        ("http://", "the URL \"http://\" has no host"),
+        ("ftp://localhost/", "unsupported scheme \"ftp\""),
+    ] {
+        let output = Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui", url])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(error), "{url:?}: {stderr}");
+    }
+}
+
+#[tokio::test]
+async fn test_host_header_default_port() {
+    let (host, _) = get_host_ipv6(
+        |_| "http://example.com:80/".to_string(),
+        |port| {
+            vec![
+                "--connect-to".to_string(),
+                format!("example.com:80:[::1]:{port}"),
+            ]
+        },
+    )
+    .await;
+    assert_eq!(host, "example.com");
+
+    let (host, _) = get_host_ipv6(
+        |_| "HTTP://Example.COM:80/".to_string(),
+        |port| {
+            vec![
+                "--connect-to".to_string(),
+                format!("example.com:80:[::1]:{port}"),
+            ]
+        },
+    )
+    .await;
+    assert_eq!(host, "example.com");
+
+    let (host, _) = get_host_ipv6(
+        |_| "http://example.com:443/".to_string(),
+        |port| {
+            vec![
+                "--connect-to".to_string(),
+                format!("example.com:443:[::1]:{port}"),
+            ]
+        },
+    )
+    .await;
+    assert_eq!(host, "example.com:443");
+
+    let (host, _) = get_host_ipv6(
+        |_| "http://[::1]:80/".to_string(),
+        |port| vec!["--connect-to".to_string(), format!("[::1]:80:[::1]:{port}")],
+    )
+    .await;
+    assert_eq!(host, "[::1]");
+
+    // An explicit Host header is sent as is
+    let (host, _) = get_host_ipv6(
+        |_| "http://example.com/".to_string(),
+        |port| {
+            vec![
+                "--connect-to".to_string(),
+                format!("example.com:80:[::1]:{port}"),
+                "-H".to_string(),
+                "Host: example.com:80".to_string(),
+            ]
+        },
+    )
+    .await;
+    assert_eq!(host, "example.com:80");
+}
+
+/// Request `http://example.com/a?b=1` through `--connect-to` and return the request line
+/// and the Host header the server received.
+async fn get_request_line(args: &[&str]) -> (String, String) {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    let (listener, port) = bind_port().await;
+    let server = tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut tcp = tokio::io::BufReader::new(tcp);
+        let mut request_line = String::new();
+        tcp.read_line(&mut request_line).await.unwrap();
+        let mut host = String::new();
+        loop {
+            let mut line = String::new();
+            tcp.read_line(&mut line).await.unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            if let Some(value) = line.to_ascii_lowercase().strip_prefix("host:") {
+                host = value.trim().to_string();
+            }
+        }
+        tcp.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .await
+            .unwrap();
+        (request_line.trim_end().to_string(), host)
+    });
+
+    let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+    tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui", "--connect-to"])
+            .arg(format!("example.com:80:127.0.0.1:{port}"))
+            .args(args)
+            .arg("http://example.com/a?b=1")
+            .assert()
+            .success();
+    })
+    .await
+    .unwrap();
+
+    server.await.unwrap()
+}
+
+/// The bytes of the first request oha sends with `args`
+async fn get_request_bytes(args: &[&str]) -> Vec<u8> {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+
+    let (listener, port) = bind_port().await;
+    let server = tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut tcp = tokio::io::BufReader::new(tcp);
+        let mut request = Vec::new();
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            tcp.read_line(&mut line).await.unwrap();
+            request.extend_from_slice(line.as_bytes());
+            if line == "\r\n" {
+                break;
+            }
+            if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                content_length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; content_length];
+        tcp.read_exact(&mut body).await.unwrap();
+        request.extend_from_slice(&body);
+        tcp.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .await
+            .unwrap();
+        request
+    });
+
+    let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+    tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui", "--connect-to"])
+            .arg(format!("example.com:80:127.0.0.1:{port}"))
+            .args(args)
+            .arg("http://example.com/a?b=1")
+            .assert()
+            .success();
+    })
+    .await
+    .unwrap();
+
+    server.await.unwrap()
+}
+
+#[tokio::test]
+async fn test_prebuilt_request() {
+    // With --prebuilt-requests, the same request is encoded once and sent without hyper. Both
+    // send the same bytes.
+    for args in [
+        &[][..],
+        &[
+            "-m",
+            "POST",
+            "-d",
+            "hello",
+            "-H",
+            "x-a: 1",
+            "-T",
+            "text/plain",
+        ][..],
+        &["-m", "PUT", "-d", ""][..],
+    ] {
+        let prebuilt = get_request_bytes(&[args, &["--prebuilt-requests"]].concat()).await;
+        let hyper = get_request_bytes(args).await;
+        assert_eq!(
+            String::from_utf8_lossy(&prebuilt),
+            String::from_utf8_lossy(&hyper)
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_request_target() {
+    assert_eq!(
+        get_request_line(&[]).await,
+        ("GET /a?b=1 HTTP/1.1".to_string(), "example.com".to_string())
+    );
+    assert_eq!(
+        get_request_line(&["--request-target", "absolute"]).await,
+        (
+            "GET http://example.com/a?b=1 HTTP/1.1".to_string(),
+            "example.com".to_string()
+        )
+    );
+
+    let output = Command::cargo_bin("oha")
+        .unwrap()
+        .args(["--no-tui", "--http2", "--request-target", "absolute"])
+        .arg("http://127.0.0.1:1/")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("--request-target absolute is only supported on HTTP/1.x"));
+}
+
+/// Run `--raw-request` with the template against a server which answers each request with
+/// `201 Created`. Returns the requests as received and the JSON output.
+async fn raw_request_json(template: &str, args: &[&str]) -> (Vec<String>, serde_json::Value) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let (listener, port) = bind_port().await;
+    let server = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        while let Ok((mut tcp, _)) = listener.accept().await {
+            let mut buf = Vec::new();
+            let mut chunk = [0; 1024];
+            loop {
+                // The test templates end with a 2 byte body
+                while let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                    if buf.len() < end + 6 {
+                        break;
+                    }
+                    let request: Vec<u8> = buf.drain(..end + 6).collect();
+                    requests.push(String::from_utf8(request).unwrap());
+                    tcp.write_all(b"HTTP/1.1 201 Created\r\ncontent-length: 3\r\n\r\nabc")
+                        .await
+                        .unwrap();
+                }
+                match tcp.read(&mut chunk).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                }
+            }
+            if requests.len() >= 3 {
+                return requests;
+            }
+        }
+        requests
+    });
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), template).unwrap();
+    let connect_to = format!("example.com:80:127.0.0.1:{port}");
+    let args = [
+        &["-n", "3", "-c", "1", "--connect-to", &connect_to][..],
+        &["--raw-request", file.path().to_str().unwrap()],
+        args,
+    ]
+    .concat();
+    let output = run_oha(&args, "http://example.com/".to_string()).await;
+    let json = summary(&output);
+    (server.await.unwrap(), json)
+}
+
+#[tokio::test]
+async fn test_raw_request() {
+    let (requests, json) = raw_request_json(
+        "POST /{seq} HTTP/1.1\nHost: {host}\nContent-Length: 2\n\nhi",
+        &[],
+    )
+    .await;
+    assert_eq!(
+        requests,
+        (0..3)
+            .map(|seq| format!(
+                "POST /{seq} HTTP/1.1\r\nHost: example.com\r\nContent-Length: 2\r\n\r\nhi"
+            ))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(json["statusCodeDistribution"]["201"], 3);
+    assert_eq!(json["summary"]["sizePerRequest"], 3);
+    assert_eq!(json["details"]["connections"]["new"], 1);
+
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), "GET / HTTP/1.1\nHost: {host}\n").unwrap();
+    let output = Command::cargo_bin("oha")
+        .unwrap()
+        .args(["--no-tui", "--raw-request"])
+        .arg(file.path())
+        .arg("http://127.0.0.1:1/")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("the headers must end with an empty line")
+    );
+}
+
+#[test]
+fn test_ipv6_zone_identifier() {
+    for args in [
+        &["http://[fe80::1%25eth0]:3000/"][..],
+        &[
+            "http://[::1]/",
+            "--connect-to",
+            "[::1]:80:[fe80::1%eth0]:3000",
+        ],
+    ] {
+        let output = Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains("IPv6 zone identifiers are not supported"));
+    }
+}
+
+#[test]
+fn test_dns_failure() {
+    // .invalid never resolves (RFC 6761)
+    let output = Command::cargo_bin("oha")
+        .unwrap()
+        .args(["-n", "100", "--no-tui", "--dns-retries", "1"])
+        .args(["--dns-retry-interval", "10ms", "http://oha-test.invalid/"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("failed to resolve the host before the run"));
+    assert!(stderr.contains("DNS lookup failed"));
+    // The run is aborted without any result
+    assert!(output.stdout.is_empty());
+}
+
+#[tokio::test]
+async fn test_happy_eyeballs() {
+    // `localhost` resolves to both ::1 and 127.0.0.1, and IPv6 is tried first
+    let (listener, port) = bind_port().await;
+    tokio::spawn(async {
+        axum::serve(listener, Router::new().route("/", get(|| async { "" }))).await
+    });
+
+    // An IPv6 listener with the full backlog drops SYNs, so connections to it hang
+    let black_hole =
+        socket2::Socket::new(socket2::Domain::IPV6, socket2::Type::STREAM, None).unwrap();
+    black_hole
+        .bind(&SocketAddr::new(Ipv6Addr::LOCALHOST.into(), port).into())
+        .unwrap();
+    black_hole.listen(0).unwrap();
+    let fillers: Vec<_> = (0..3)
+        .map(|_| {
+            let socket =
+                socket2::Socket::new(socket2::Domain::IPV6, socket2::Type::STREAM, None).unwrap();
+            socket.set_nonblocking(true).unwrap();
+            let _ = socket.connect(&SocketAddr::new(Ipv6Addr::LOCALHOST.into(), port).into());
+            socket
+        })
+        .collect();
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let output = tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "2", "--no-tui", "--json", "--disable-keepalive"])
+            .args(["--ip-version", "dual-prefer-6"])
+            .arg(format!("http://localhost:{port}/"))
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap();
+    drop(fillers);
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    // Without the fallback, each connection would time out after 5 seconds
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert!(json["summary"]["slowest"].as_f64().unwrap() < 4.0);
+    assert_eq!(json["details"]["ipFamilies"]["ipv4"], 2);
+    assert_eq!(json["details"]["ipFamilies"]["ipv6"], 0);
+    assert_eq!(json["details"]["ipFamilies"]["preferred"], "ipv6");
+
+    // A family forced by `--ipv4` is reported too
+    let (listener, port) = bind_port().await;
+    tokio::spawn(async {
+        axum::serve(listener, Router::new().route("/", get(|| async { "" }))).await
+    });
+    let output = tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui", "--json", "--ipv4"])
+            .arg(format!("http://localhost:{port}/"))
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert_eq!(json["details"]["ipFamilies"]["ipv4"], 1);
+    assert_eq!(json["details"]["ipFamilies"]["preferred"], "ipv4");
+}
+
+#[tokio::test]
+async fn test_ip_version() {
+    // `localhost` resolves to both ::1 and 127.0.0.1, with a server on each
+    let (listener, port) = bind_port().await;
+    tokio::spawn(async {
+        axum::serve(listener, Router::new().route("/", get(|| async { "" }))).await
+    });
+    let listener = tokio::net::TcpListener::bind((Ipv6Addr::LOCALHOST, port))
+        .await
+        .unwrap();
+    tokio::spawn(async {
+        axum::serve(listener, Router::new().route("/", get(|| async { "" }))).await
+    });
+
+    for (args, ipv6, ipv4, preferred) in [
+        (&["--ip-version", "4"][..], 0, 2, "ipv4"),
+        (&["--ip-version", "6"], 2, 0, "ipv6"),
+        (&["--ip-version", "dual-prefer-4"], 0, 2, "ipv4"),
+        (&["--ip-version", "dual-prefer-6"], 2, 0, "ipv6"),
+        (&["--ipv4"], 0, 2, "ipv4"),
+        (&["--ipv6"], 2, 0, "ipv6"),
+        (&["--ipv4", "--ipv6"], 2, 0, "ipv6"),
+    ] {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let output = tokio::task::spawn_blocking(move || {
+            Command::cargo_bin("oha")
+                .unwrap()
+                .args(["-n", "2", "--no-tui", "--json", "--disable-keepalive"])
+                .args(&args)
+                .arg(format!("http://localhost:{port}/"))
+                .output()
+                .unwrap()
+        })
+        .await
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let families = &json["details"]["ipFamilies"];
+        assert_eq!(json["summary"]["successRate"], 1.0);
+        assert_eq!(
+            (&families["ipv6"], &families["ipv4"], &families["preferred"]),
+            (
+                &serde_json::json!(ipv6),
+                &serde_json::json!(ipv4),
+                &serde_json::json!(preferred)
+            ),
+            "{json}"
+        );
+    }
+
+    let output = Command::cargo_bin("oha")
+        .unwrap()
+        .args(["--no-tui", "--ipv4", "--ip-version", "6"])
+        .arg("http://127.0.0.1:1/")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("--ipv4 and --ipv6 can't be used with --ip-version"));
+}
+
+#[tokio::test]
+async fn test_query_limit() {
+    assert_eq!(burst_10_req_delay_2s_rate_4(10, &[],).await, 10);
+    assert_eq!(burst_10_req_delay_2s_rate_4(10, &["--http2"],).await, 10);
+}
+
+async fn burst_json(args: &[&str]) -> serde_json::Value {
+    let app = Router::new().route(
+        "/",
+        get(|| async {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            "Success"
+        }),
+    );
+    let args = [
+        &[
+            "-n",
+            "8",
+            "-c",
+            "4",
+            "--burst-delay",
+            "100ms",
+            "--burst-rate",
+            "4",
+        ],
+        args,
+    ]
+    .concat();
+    run_json(app, &args).await
+}
+
+#[tokio::test]
+async fn test_burst_completion() {
+    for args in [&[][..], &["--latency-correction"]] {
+        let json = burst_json(args).await;
+        let bursts = &json["summary"]["bursts"];
+        assert_eq!(bursts["count"], 2);
+        assert!(bursts["fastest"].as_f64().unwrap() >= 0.05);
+        assert!(bursts["slowest"].as_f64().unwrap() < 1.0);
+    }
+}
+
+#[tokio::test]
+async fn test_qps_ramp() {
+    let app = Router::new().route("/", get(|| async { "Hello World" }));
+    let port = serve(app).await;
+
+    let output = tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-z", "2s", "-c", "4", "--no-tui", "--json"])
+            .args(["--qps-ramp", "10:30"])
+            .arg(format!("http://127.0.0.1:{port}/"))
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    // 15 and 25 requests in average
+    let seconds = json["qpsRamp"].as_array().unwrap();
+    assert_eq!(seconds.len(), 2);
+    assert_eq!(seconds[0]["target"], 15.0);
+    assert_eq!(seconds[1]["target"], 25.0);
+    let achieved = |s: &serde_json::Value| s["achieved"].as_u64().unwrap();
+    assert!((10..=20).contains(&achieved(&seconds[0])));
+    assert!((18..=30).contains(&achieved(&seconds[1])));
+    assert!(achieved(&seconds[0]) < achieved(&seconds[1]));
+    assert!(seconds[0]["p99"].as_f64().unwrap() < 1.0);
+
+    for args in [
+        &["-n", "10", "--qps-ramp", "1:2"][..],
+        &["-z", "1s", "--qps-ramp", "5"],
+    ] {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["--no-tui"])
+            .args(args)
+            .arg("http://127.0.0.1/")
+            .assert()
+            .failure();
+    }
+}
+
+async fn load_profile_json(args: &[&str]) -> serde_json::Value {
+    let app = Router::new().route(
+        "/",
+        get(|| async {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            "Success"
+        }),
+    );
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), "# 10 requests\n1s 10\n500ms max\n").unwrap();
+    let profile = file.path().to_str().unwrap();
+    // The profile overrides -z
+    let args = [&["-z", "10s", "-c", "2", "--load-profile", profile], args].concat();
+    run_json(app, &args).await
+}
+
+#[tokio::test]
+async fn test_load_profile() {
+    for args in [&[][..], &["--latency-correction"], &["--http2"]] {
+        let json = load_profile_json(args).await;
+        let total = json["summary"]["total"].as_f64().unwrap();
+        assert!((1.5..8.0).contains(&total), "{total}");
+
+        let per_load_step = json["perLoadStep"].as_object().unwrap();
+        assert_eq!(per_load_step.len(), 2);
+        let count = |step: &str| per_load_step[step]["count"].as_u64().unwrap();
+        assert!((8..=12).contains(&count("#1 1s at 10 QPS")));
+        // 2 workers for 500ms at 10ms per request, as fast as they can
+        assert!(count("#2 500ms at max") > count("#1 1s at 10 QPS"));
+    }
+
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), "1s 10\n1s fast\n").unwrap();
+    let output = Command::cargo_bin("oha")
+        .unwrap()
+        .args(["--no-tui", "--load-profile"])
+        .arg(file.path())
+        .arg("http://127.0.0.1/")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("line 2: invalid rate \"fast\""));
+}
+
+async fn arrival_rate_json(args: &[&str]) -> serde_json::Value {
+    let app = Router::new().route(
+        "/",
+        get(|| async {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            "Success"
+        }),
+    );
+    let args = [&["-n", "10", "-c", "1", "--arrival-rate", "20"], args].concat();
+    run_json(app, &args).await
+}
+
+#[tokio::test]
+async fn test_arrival_rate() {
+    for args in [&[][..], &["--http2"]] {
+        // A worker would take 2 seconds for 10 requests of 200ms
+        let json = arrival_rate_json(args).await;
+        assert_eq!(json["summary"]["successRate"], 1.0);
+        assert_eq!(json["summary"]["requestedQps"], 20.0);
+        assert_eq!(json["summary"]["lateArrivals"], 0);
+        let total = json["summary"]["total"].as_f64().unwrap();
+        assert!(total < 1.6, "{total}");
+    }
+
+    // The 3rd request waits for the 1st one and so on
+    let json = arrival_rate_json(&["--max-in-flight", "2"]).await;
+    assert_eq!(json["summary"]["successRate"], 1.0);
+    assert!(json["summary"]["lateArrivals"].as_u64().unwrap() >= 5);
+    // Latency includes the wait for a slot
+    assert!(json["summary"]["slowest"].as_f64().unwrap() > 0.4);
+
+    Command::cargo_bin("oha")
+        .unwrap()
+        .args(["--no-tui", "--max-in-flight", "2", "http://127.0.0.1/"])
+        .assert()
+        .failure();
+}
+
+async fn think_time_json(args: &[&str]) -> serde_json::Value {
+    let app = Router::new().route("/", get(|| async { "Success" }));
+    run_json(app, &[&["-c", "2", "--think-time", "300ms"], args].concat()).await
+}
+
+#[tokio::test]
+async fn test_think_time() {
+    for args in [
+        &["-n", "6"][..],
+        &["-n", "6", "--http2"],
+        &["-n", "6", "--think-time-jitter", "50", "--rand-seed", "0"],
+    ] {
+        // 2 pauses of each worker between its 3 requests
+        let json = think_time_json(args).await;
+        let summary = &json["summary"];
+        assert_eq!(summary["successRate"], 1.0);
+        assert_eq!(summary["thinkTime"]["pauses"], 4);
+        let paused = summary["thinkTime"]["paused"].as_f64().unwrap();
+        assert!((0.5..3.0).contains(&paused), "{paused}");
+        assert!(summary["total"].as_f64().unwrap() >= 0.3);
+        // The pauses aren't latency
+        assert!(summary["slowest"].as_f64().unwrap() < 0.3);
+    }
+
+    // -q is slower than the think time
+    let json = think_time_json(&["-n", "6", "-q", "5"]).await;
+    assert!(json["summary"]["total"].as_f64().unwrap() >= 1.0);
+
+    // At most 4 requests of each worker start in a second with 3 pauses between them
+    let json = think_time_json(&["-z", "1s"]).await;
+    let count: u64 = json["statusCodeDistribution"]["200"].as_u64().unwrap();
+    assert!((2..=8).contains(&count), "{count}");
+}
+
+async fn abort_json(args: &[&str]) -> (Option<i32>, serde_json::Value) {
+    let app = Router::new().route(
+        "/",
+        get(|| async { (http::StatusCode::NOT_FOUND, "Not Found") }),
+    );
+    let port = serve(app).await;
+    let output = run_oha(
+        &[&["-c", "2"], args].concat(),
+        format!("http://127.0.0.1:{port}/"),
+    )
+    .await;
+    (
+        output.status.code(),
+        serde_json::from_slice(&output.stdout).unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn test_abort_on_error_rate() {
+    for args in [
+        &["-n", "1000000"][..],
+        &["-z", "60s"],
+        &["-z", "60s", "-q", "1000"],
+        &["-n", "1000000", "--http2"],
+    ] {
+        let (code, json) = abort_json(&[args, &["--abort-on-error-rate", "50"]].concat()).await;
+        assert_eq!(code, Some(3));
+        assert_eq!(json["summary"]["aborted"], "error rate exceeded");
+        assert!(json["summary"]["total"].as_f64().unwrap() < 30.0);
+        let not_found = json["statusCodeDistribution"]["404"].as_u64().unwrap();
+        assert!((20..1000000).contains(&not_found), "{not_found}");
+    }
+
+    // 404 is expected
+    let (code, json) = abort_json(&[
+        "-n",
+        "100",
+        "--expect-status",
+        "404",
+        "--abort-on-error-rate",
+        "50",
+    ])
+    .await;
+    assert_eq!(code, Some(0));
+    assert!(json["summary"].get("aborted").is_none());
+    assert_eq!(json["statusCodeDistribution"]["404"], 100);
+}
+
+#[tokio::test]
+async fn test_abort_on_first_error() {
+    for args in [&["-n", "1000000"][..], &["-z", "60s", "--http2"]] {
+        let (code, json) = abort_json(
+            &[
+                args,
+                &[
+                    "--abort-on-first-error",
+                    "--expect-status",
+                    "2xx",
+                    "--think-time",
+                    "10s",
+                ],
+            ]
+            .concat(),
+        )
+        .await;
+        assert_eq!(code, Some(3));
+        assert_eq!(json["summary"]["aborted"], "first error");
+        assert_eq!(
+            json["summary"]["firstError"],
+            "unexpected status 404 Not Found"
+        );
+        // The think time of the workers is cut by the abort
+        assert!(json["summary"]["total"].as_f64().unwrap() < 5.0);
+        let not_found = json["statusCodeDistribution"]["404"].as_u64().unwrap();
+        assert!((1..=2).contains(&not_found), "{not_found}");
+    }
+
+    // 404 isn't a failure without --expect-status
+    let (code, json) = abort_json(&["-n", "10", "--abort-on-first-error"]).await;
+    assert_eq!(code, Some(0));
+    assert!(json["summary"].get("aborted").is_none());
+    assert_eq!(json["statusCodeDistribution"]["404"], 10);
+}
+
+async fn duty_cycle_json(args: &[&str]) -> (serde_json::Value, Vec<std::time::Duration>) {
+    let arrivals = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let app = Router::new().route(
+        "/",
+        get({
+            let arrivals = arrivals.clone();
+            move || async move {
+                arrivals.lock().unwrap().push(std::time::Instant::now());
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                "Hello World"
+            }
+        }),
+    );
+    let port = serve(app).await;
+    let output = run_oha(
+        &[&["-c", "2"], args].concat(),
+        format!("http://127.0.0.1:{port}/"),
+    )
+    .await;
+    let arrivals = arrivals.lock().unwrap();
+    let first = arrivals[0];
+    (
+        serde_json::from_slice(&output.stdout).unwrap(),
+        arrivals.iter().map(|t| *t - first).collect(),
+    )
+}
+
+#[tokio::test]
+async fn test_duty_cycle() {
+    for args in [&["-z", "2s"][..], &["-z", "2s", "--http2"]] {
+        let (json, arrivals) =
+            duty_cycle_json(&[args, &["--on", "500ms", "--off", "500ms"]].concat()).await;
+        // No request starts in the off periods
+        assert!(
+            arrivals
+                .iter()
+                .all(|t| !(700..900).contains(&(t.as_millis() % 1000))),
+            "{arrivals:?}"
+        );
+        assert!(arrivals.iter().any(|t| t.as_millis() >= 1000));
+
+        let load_time = json["summary"]["loadTime"].as_f64().unwrap();
+        assert!((0.8..=1.3).contains(&load_time), "{load_time}");
+        let count = json["statusCodeDistribution"]["200"].as_u64().unwrap();
+        let rps = json["summary"]["requestsPerSec"].as_f64().unwrap();
+        assert!(rps > count as f64 / 1.2, "{rps} {count}");
+
+        let phases = json["perDutyCyclePhase"].as_object().unwrap();
+        assert!(phases["on"]["count"].as_u64().unwrap() > 0);
+        assert!(
+            phases["on, first second after resume"]["count"]
+                .as_u64()
+                .unwrap()
+                > 0
+        );
+    }
+
+    let (json, _) = duty_cycle_json(&["-n", "10"]).await;
+    assert!(json.get("perDutyCyclePhase").is_none());
+    assert!(json["summary"].get("loadTime").is_none());
+}
+
+async fn max_bytes_json(args: &[&str]) -> (Option<i32>, serde_json::Value) {
+    let app = Router::new().route("/", get(|| async { "a".repeat(1000) }));
+    let port = serve(app).await;
+    let output = run_oha(
+        &[&["-c", "2"], args].concat(),
+        format!("http://127.0.0.1:{port}/"),
+    )
+    .await;
+    (
+        output.status.code(),
+        serde_json::from_slice(&output.stdout).unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn test_max_bytes() {
+    for args in [
+        &["--forever"][..],
+        &["-z", "60s"],
+        &["--forever", "--http2"],
+    ] {
+        let (code, json) = max_bytes_json(&[args, &["--max-bytes", "100KB"]].concat()).await;
+        assert_eq!(code, Some(0));
+        assert_eq!(json["summary"]["stoppedBy"], "maxBytes");
+        assert!(json["summary"].get("aborted").is_none());
+        let count = json["statusCodeDistribution"]["200"].as_u64().unwrap();
+        assert!((100..=200).contains(&count), "{count}");
+    }
+
+    let (code, json) = max_bytes_json(&["-n", "10", "--max-bytes", "1MiB"]).await;
+    assert_eq!(code, Some(0));
+    assert_eq!(json["summary"]["stoppedBy"], "requests");
+    assert_eq!(json["statusCodeDistribution"]["200"], 10);
+
+    let (_, json) = max_bytes_json(&["-z", "1s", "-q", "10", "--max-bytes", "1GB"]).await;
+    assert_eq!(json["summary"]["stoppedBy"], "duration");
+
+    // Not shown without --max-bytes
+    let (_, json) = max_bytes_json(&["-n", "10"]).await;
+    assert!(json["summary"].get("stoppedBy").is_none());
+}
+
+#[cfg(unix)]
+async fn interrupted_json(args: &[&str]) -> (Option<i32>, serde_json::Value) {
+    let app = Router::new().route("/", get(|| async { "Hello World" }));
+    let port = serve(app).await;
+
+    let child = std::process::Command::new(assert_cmd::cargo::cargo_bin("oha"))
+        .args(["-c", "2", "-q", "100", "--no-tui", "--json"])
+        .args(args)
+        .arg(format!("http://127.0.0.1:{port}/"))
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGINT);
+    }
+    let output = tokio::task::spawn_blocking(move || child.wait_with_output().unwrap())
+        .await
+        .unwrap();
+
+    (
+        output.status.code(),
+        serde_json::from_slice(&output.stdout).unwrap(),
+    )
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_forever() {
+    for args in [&["--forever"][..], &["-n", "0"]] {
+        let (code, json) = interrupted_json(args).await;
+        assert_eq!(code, Some(0));
+        let count = json["statusCodeDistribution"]["200"].as_u64().unwrap();
+        assert!((20..=500).contains(&count), "{count}");
+        assert!(json["summary"]["total"].as_f64().unwrap() >= 0.5);
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_shutdown_grace() {
+    let app = Router::new().route(
+        "/",
+        get(|| async {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            "Hello World"
+        }),
+    );
+    let port = serve(app).await;
+
+    // The second ctrl-c quits without waiting for the requests in flight
+    for signals in [1, 2] {
+        let t0 = std::time::Instant::now();
+        let child = std::process::Command::new(assert_cmd::cargo::cargo_bin("oha"))
+            .args([
+                "-c",
+                "3",
+                "-z",
+                "30s",
+                "--shutdown-grace",
+                "10s",
+                "--no-tui",
+                "--json",
+            ])
+            .arg(format!("http://127.0.0.1:{port}/"))
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        for _ in 0..signals {
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            unsafe {
+                libc::kill(child.id() as libc::pid_t, libc::SIGINT);
+            }
+        }
+        let output = tokio::task::spawn_blocking(move || child.wait_with_output().unwrap())
+            .await
+            .unwrap();
+        assert!(t0.elapsed() < std::time::Duration::from_secs(5));
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        if signals == 1 {
+            // The requests in flight finished and no new one started
+            assert_eq!(output.status.code(), Some(0));
+            assert_eq!(json["statusCodeDistribution"]["200"], 3);
+            assert_eq!(json["errorDistribution"], serde_json::json!({}));
+            assert!(json["summary"].get("aborted").is_none());
+        } else {
+            assert_eq!(output.status.code(), Some(130));
+            assert_eq!(json["statusCodeDistribution"], serde_json::json!({}));
+            assert_eq!(json["summary"]["aborted"], "forced quit");
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_n_requests_suffix() {
+    let app = Router::new().route("/", get(|| async { "Hello World" }));
+    let port = serve(app).await;
+
+    for (n, count) in [("1_5", 15), ("1k", 1000)] {
+        let output = tokio::task::spawn_blocking(move || {
+            Command::cargo_bin("oha")
+                .unwrap()
+                .args(["-n", n, "--no-tui", "--json"])
+                .arg(format!("http://127.0.0.1:{port}/"))
+                .assert()
+                .success()
+                .get_output()
+                .stdout
+                .clone()
+        })
+        .await
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json["statusCodeDistribution"]["200"], count);
+    }
+
+    // Over u64::MAX, with and without a suffix, and malformed counts
+    for n in ["18446744073709551616", "18446745T", "_10", "10x", "k"] {
+        let output = Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", n, "--no-tui", "http://127.0.0.1:1/"])
+            .output()
+            .unwrap();
+        assert!(!output.status.success(), "{n}");
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("invalid value"),
+            "{n}"
+        );
+    }
+}
+
+async fn ramp_up_json(args: &[&str]) -> serde_json::Value {
+    let app = Router::new().route(
+        "/",
+        get(|| async {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            "Success"
+        }),
+    );
+    run_json(app, &[&["-c", "4"], args].concat()).await
+}
+
+#[tokio::test]
+async fn test_ramp_up() {
+    for http2 in [&[][..], &["--http2"]] {
+        let json = ramp_up_json(&[&["-z", "2s", "--ramp-up", "1s"], http2].concat()).await;
+        let ramp_up = &json["summary"]["rampUp"];
+        assert_eq!(ramp_up["duration"], 1.0);
+        assert!(ramp_up["requests"].as_u64().unwrap() > 0);
+        // Only the steady part
+        let total = json["summary"]["total"].as_f64().unwrap();
+        assert!((0.9..1.9).contains(&total), "{total}");
+    }
+
+    // The waiting workers don't start once the work is done, and a run which ends in the
+    // ramp-up is reported as a whole
+    let json = ramp_up_json(&["-n", "5", "--ramp-up", "10s"]).await;
+    assert_eq!(json["statusCodeDistribution"]["200"], 5);
+    assert!(json["summary"]["total"].as_f64().unwrap() < 5.0);
+    assert!(json["summary"].get("rampUp").is_none());
+}
+
+#[tokio::test]
+async fn test_stagger() {
+    for http2 in [&[][..], &["--http2"]] {
+        // Included in the statistics by default
+        let json = ramp_up_json(&[&["-z", "600ms", "--stagger", "300ms"], http2].concat()).await;
+        assert!(json["summary"].get("stagger").is_none());
+        assert!(json["summary"].get("rampUp").is_none());
+        let total = json["summary"]["total"].as_f64().unwrap();
+        assert!(total >= 0.5, "{total}");
+
+        let json = ramp_up_json(
+            &[
+                &["-z", "600ms", "--stagger", "300ms", "--stagger-exclude"],
+                http2,
+            ]
+            .concat(),
+        )
+        .await;
+        let stagger = &json["summary"]["stagger"];
+        assert_eq!(stagger["duration"], 0.3);
+        assert!(stagger["requests"].as_u64().unwrap() > 0);
+        assert!(json["summary"].get("rampUp").is_none());
+        let total = json["summary"]["total"].as_f64().unwrap();
+        assert!((0.2..0.6).contains(&total), "{total}");
+    }
+}
+
+async fn fractional_qps_json(qps: &str) -> serde_json::Value {
+    let app = Router::new().route("/", get(|| async { "Hello World" }));
+    run_json(app, &["-n", "4", "-c", "1", "-q", qps]).await
+}
+
+#[tokio::test]
+async fn test_fractional_qps() {
+    // Sent at 0, 0.4, 0.8 and 1.2 secs
+    let json = fractional_qps_json("2.5").await;
+    assert_eq!(json["summary"]["requestedQps"], 2.5);
+    let total = json["summary"]["total"].as_f64().unwrap();
+    assert!((1.2..2.0).contains(&total), "{total}");
+
+    for qps in ["0", "-1", "inf"] {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui", "-q", qps, "http://127.0.0.1/"])
+            .assert()
+            .failure();
+    }
+}
+
+#[tokio::test]
+async fn test_http2() {
+    assert_eq!(get_http_version(&[]).await, http::Version::HTTP_11);
+    assert_eq!(get_http_version(&["--http2"]).await, http::Version::HTTP_2);
+    assert_eq!(
+        get_http_version(&["--http-version", "2"]).await,
+        http::Version::HTTP_2
+    );
+}
+
+/// The coefficient of variation of the gaps between the arrivals at the server, and the summary
+async fn arrival_distribution_json(args: &[&str]) -> (f64, serde_json::Value) {
+    let arrivals = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let app = Router::new().route(
+        "/",
+        get({
+            let arrivals = arrivals.clone();
+            move || async move {
+                arrivals.lock().unwrap().push(std::time::Instant::now());
+                "Hello World"
+            }
+        }),
+    );
+    let port = serve(app).await;
+    let output = run_oha(
+        &[&["-c", "10", "-z", "3s", "--rand-seed", "0"], args].concat(),
+        format!("http://127.0.0.1:{port}/"),
+    )
+    .await;
+    let arrivals = arrivals.lock().unwrap();
+    let gaps: Vec<f64> = arrivals
+        .windows(2)
+        .map(|w| (w[1] - w[0]).as_secs_f64())
+        .collect();
+    let mean = gaps.iter().sum::<f64>() / gaps.len() as f64;
+    let variance = gaps.iter().map(|g| (g - mean).powi(2)).sum::<f64>() / gaps.len() as f64;
+    (
+        variance.sqrt() / mean,
+        serde_json::from_slice(&output.stdout).unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn test_arrival_distribution() {
+    for args in [&["-q", "100"][..], &["--arrival-rate", "100"]] {
+        let (uniform, json) =
+            arrival_distribution_json(&[args, &["--arrival-distribution", "uniform"]].concat())
+                .await;
+        assert!(uniform < 0.5, "{uniform}");
+        let arrivals = &json["summary"]["arrivalsPerSecond"];
+        assert_eq!(arrivals["distribution"], "uniform");
+        let mean = arrivals["mean"].as_f64().unwrap();
+        assert!((80.0..=110.0).contains(&mean), "{mean}");
+
+        let (poisson, json) =
+            arrival_distribution_json(&[args, &["--arrival-distribution", "poisson"]].concat())
+                .await;
+        // The gaps of a Poisson process are exponential, whose standard deviation is the mean
+        assert!((0.7..1.4).contains(&poisson), "{poisson}");
+        let arrivals = &json["summary"]["arrivalsPerSecond"];
+        assert_eq!(arrivals["distribution"], "poisson");
+        let mean = arrivals["mean"].as_f64().unwrap();
+        assert!((60.0..=140.0).contains(&mean), "{mean}");
+    }
+}
+
+/// The method and URI of each request in the order they arrived, and the summary
+async fn seeded_json(args: &[&str]) -> (Vec<String>, serde_json::Value) {
+    let requests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let app = Router::new().fallback({
+        let requests = requests.clone();
+        move |method: http::Method, uri: http::Uri| async move {
+            requests.lock().unwrap().push(format!("{method} {uri}"));
+            "Hello World"
+        }
+    });
+    let port = serve(app).await;
+    let args = [
+        &["-n", "20", "-c", "1", "--rand-regex-url"][..],
+        &["--method-mix", "GET:1,POST:1"],
+        args,
+    ]
+    .concat();
+    let output = run_oha(&args, format!("http://127.0.0.1:{port}/[a-z]{{8}}")).await;
+    let requests = requests.lock().unwrap().clone();
+    (requests, serde_json::from_slice(&output.stdout).unwrap())
+}
+
+#[tokio::test]
+async fn test_rand_seed() {
+    let (first, json) = seeded_json(&["--rand-seed", "7"]).await;
+    assert_eq!(json["summary"]["randSeed"], 7);
+    let (second, _) = seeded_json(&["--rand-seed", "7"]).await;
+    assert_eq!(first.len(), 20);
+    assert_eq!(first, second);
+    let (other, _) = seeded_json(&["--rand-seed", "8"]).await;
+    assert_ne!(first, other);
+
+    // The generated seed repeats the run
+    let (first, json) = seeded_json(&[]).await;
+    let seed = json["summary"]["randSeed"].as_u64().unwrap().to_string();
+    let (second, _) = seeded_json(&["--rand-seed", &seed]).await;
+    assert_eq!(first, second);
+}
+
+async fn runtime_threads_output(args: &[&str]) -> std::process::Output {
+    let app = Router::new().route("/", get(|| async { "Hello World" }));
+    let port = serve(app).await;
+
+    let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+    tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "20", "--no-tui", "--json"])
+            .args(args)
+            .arg(format!("http://127.0.0.1:{port}/"))
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_runtime_threads() {
+    for threads in ["1", "2"] {
+        let output = runtime_threads_output(&["-c", "2", "--runtime-threads", threads]).await;
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json["statusCodeDistribution"]["200"], 20);
+        assert_eq!(json["summary"]["runtimeThreads"].to_string(), threads);
+        assert!(output.stderr.is_empty());
+    }
+
+    // More workers than a thread can drive. -z, as -n 20 would lower -c.
+    let output =
+        runtime_threads_output(&["-c", "1001", "-z", "1s", "--runtime-threads", "1"]).await;
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(json["statusCodeDistribution"]["200"].as_u64().unwrap() > 0);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("1001 workers are more than 1000 for each runtime thread"));
+}
+
+#[tokio::test]
+async fn test_fewer_requests_than_connections() {
+    // The prewarmed connections tell how many workers there are
+    for (args, connections, note) in [
+        (&["-n", "3", "-c", "10"][..], 3, true),
+        (&["-n", "10", "-c", "10"], 10, false),
+        (&["-n", "7", "-c", "3"], 3, false),
+        // Two streams on each HTTP/2 connection
+        (&["-n", "3", "-c", "10", "--http2", "-p", "2"], 2, true),
+        // -n 0 runs until stopped, here by --max-bytes
+        (&["-n", "0", "-c", "5", "--max-bytes", "1KB"], 5, false),
+    ] {
+        let app = Router::new().route("/", get(|| async { "Hello World" }));
+        let port = serve(app).await;
+        let command_args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let output = tokio::task::spawn_blocking(move || {
+            Command::cargo_bin("oha")
+                .unwrap()
+                .args(["--no-tui", "--json", "--prewarm-connections"])
+                .args(command_args)
+                .arg(format!("http://127.0.0.1:{port}/"))
+                .output()
+                .unwrap()
+        })
+        .await
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(
+            json["summary"]["prewarm"]["connections"], connections,
+            "{args:?}"
+        );
+        if let Some(n) = args[1].parse::<u64>().ok().filter(|&n| n > 0) {
+            // No request is lost when -n isn't a multiple of -c
+            assert_eq!(json["statusCodeDistribution"]["200"], n, "{args:?}");
+        }
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert_eq!(
+            stderr.contains(&format!("note: -c {} is lowered to {connections}", args[3])),
+            note,
+            "{args:?}: {stderr}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_fps_without_tui() {
+    let output = runtime_threads_output(&["--fps", "30"]).await;
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["statusCodeDistribution"]["200"], 20);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    // Accepted by any build, so that scripts work with both
+    assert_eq!(
+        stderr.contains("--fps is ignored"),
+        !cfg!(feature = "tui"),
+        "{stderr}"
+    );
+}
+
+#[tokio::test]
+async fn test_connect_rate() {
+    let output = runtime_threads_output(&["-c", "5", "--connect-rate", "10"]).await;
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["statusCodeDistribution"]["200"], 20);
+    let startup = &json["summary"]["startupConnects"];
+    assert_eq!(startup["connections"], 5);
+    assert_eq!(startup["connectRate"], 10.0);
+    // A token at once, then one every 100ms
+    assert!(startup["duration"].as_f64().unwrap() >= 0.4);
+
+    // Without a rate the startup connects are still reported
+    let output = runtime_threads_output(&["-c", "5"]).await;
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let startup = &json["summary"]["startupConnects"];
+    assert_eq!(startup["connections"], 5);
+    assert!(startup.get("connectRate").is_none());
+    assert!(startup["duration"].as_f64().unwrap() < 0.4);
+
+    // Prewarmed connections are reported as such
+    let output = runtime_threads_output(&["-c", "5", "--prewarm-connections"]).await;
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(json["summary"].get("startupConnects").is_none());
+    assert_eq!(json["summary"]["prewarm"]["connections"], 5);
+}
+
+#[tokio::test]
+async fn test_sample_rate() {
+    // Every third response fails --expect-status
+    let counter = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let app = Router::new().route(
+        "/",
+        get(move || {
+            let n = counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            async move {
+                if n % 3 == 2 {
+                    (http::StatusCode::INTERNAL_SERVER_ERROR, "Hello World")
+                } else {
+                    (http::StatusCode::OK, "Hello World")
+                }
+            }
+        }),
+    );
+    let port = serve(app).await;
+
+    let output = tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "999", "-c", "4", "--no-tui", "--json"])
+            .args(["--sample-rate", "1/10", "--expect-status", "200"])
+            .arg(format!("http://127.0.0.1:{port}/"))
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    // The totals are exact
+    assert_eq!(json["summary"]["successRate"], 666.0 / 999.0);
+    assert_eq!(json["summary"]["totalData"], 999 * 11);
+    // The rest comes from 1 in 10 results, weighed as 10 each
+    let sampling = &json["summary"]["sampling"];
+    assert_eq!(sampling["every"], 10);
+    assert_eq!(sampling["sampledResults"], 100);
+    let status = &json["statusCodeDistribution"];
+    assert_eq!(
+        status["200"].as_u64().unwrap() + status["500"].as_u64().unwrap(),
+        1000
+    );
+    assert_eq!(status["200"].as_u64().unwrap() % 10, 0);
+
+    for (args, error) in [
+        (
+            ["--sample-rate", "1/10", "--per-url-stats"],
+            "--sample-rate can't be combined",
+        ),
+        (["--sample-rate", "10", "--json"], "must be 1/N"),
+    ] {
+        let output = Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui"])
+            .args(args)
+            .arg("http://127.0.0.1:1/")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains(error));
+    }
+}
+
+async fn send_delay_json(args: &[&str]) -> serde_json::Value {
+    let app = Router::new().route(
+        "/",
+        get(|| async {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            "Hello World"
+        }),
+    );
+    let port = serve(app).await;
+    let output = run_oha(
+        &[&["-q", "100"], args].concat(),
+        format!("http://127.0.0.1:{port}/"),
+    )
+    .await;
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[tokio::test]
+async fn test_send_delay() {
+    for args in [
+        &["-z", "1s"][..],
+        &["-n", "50"],
+        &["-z", "1s", "--latency-correction"],
+    ] {
+        // Enough workers for the rate
+        let json = send_delay_json(&[args, &["-c", "20"]].concat()).await;
+        let send_delay = &json["summary"]["sendDelay"];
+        assert!(send_delay["max"].as_f64().unwrap() < 0.05, "{send_delay}");
+        assert_eq!(send_delay["behind"], false);
+
+        // One worker can send 20 requests/sec at most
+        let json = send_delay_json(&[args, &["-c", "1"]].concat()).await;
+        let send_delay = &json["summary"]["sendDelay"];
+        assert!(send_delay["max"].as_f64().unwrap() > 0.1, "{send_delay}");
+        assert!(send_delay["late"].as_u64().unwrap() > 0, "{send_delay}");
+        assert_eq!(send_delay["behind"], true);
+    }
+}
+
+async fn rate_output(args: &[&str]) -> std::process::Output {
+    let app = Router::new().route("/", get(|| async { "Hello World" }));
+    let port = serve(app).await;
+
+    let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+    tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["--no-tui"])
+            .args(args)
+            .arg(format!("http://127.0.0.1:{port}/"))
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_rate_units() {
+    // Exactly as many requests as are due before the end of -z
+    for (rate, expected) in [("20", 20), ("20/s", 20), ("600/m", 10), ("7200/h", 2)] {
+        for extra in [&[][..], &["--latency-correction"]] {
+            let output =
+                rate_output(&[&["-q", rate, "-z", "1s", "--json"][..], extra].concat()).await;
+            let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+            assert_eq!(json["statusCodeDistribution"]["200"], expected, "{rate}");
+            assert!(
+                json["errorDistribution"].as_object().unwrap().is_empty(),
+                "{rate}"
+            );
+        }
+    }
+
+    let output = rate_output(&["-q", "1/m", "-n", "1", "--disable-color"]).await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Requested QPS:\t0.0167 (1.00/m, "),
+        "{stdout}"
+    );
+
+    let output = rate_output(&["-q", "1/d", "-n", "1"]).await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("the unit must be /s, /m or /h"), "{stderr}");
+}
+
+/// The number of requests the server received in each 100ms from the first one
+async fn qps_buckets(args: &[&str]) -> Vec<usize> {
+    let arrivals = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let app = Router::new().route(
+        "/",
+        get({
+            let arrivals = arrivals.clone();
+            move || async move {
+                arrivals.lock().unwrap().push(std::time::Instant::now());
+                "Hello World"
+            }
+        }),
+    );
+    let port = serve(app).await;
+
+    let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+    tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-q", "500", "-z", "2s", "-c", "50", "--no-tui"])
+            .args(args)
+            .arg(format!("http://127.0.0.1:{port}/"))
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap();
+
+    let arrivals = arrivals.lock().unwrap();
+    let mut buckets = vec![0; 20];
+    for t in arrivals.iter() {
+        let bucket = (*t - arrivals[0]).as_millis() as usize / 100;
+        buckets[bucket.min(19)] += 1;
+    }
+    buckets
+}
+
+#[tokio::test]
+async fn test_qps_pacing() {
+    // A request every 2ms, so about 50 in each 100ms
+    let buckets = qps_buckets(&[]).await;
+    assert!(buckets.iter().all(|n| (30..=70).contains(n)), "{buckets:?}");
+
+    // The 500 requests of each second at its start
+    let buckets = qps_buckets(&["--qps-burst"]).await;
+    assert!(buckets[0] >= 450, "{buckets:?}");
+    // The first connects may delay the first burst a little
+    assert!(buckets[9] + buckets[10] >= 450, "{buckets:?}");
+    assert!(buckets[1..9].iter().sum::<usize>() <= 50, "{buckets:?}");
+}
+
+/// Pause the run with SIGUSR1 from 1s to 2s, returning the summary, stderr and the times of the
+/// requests from the pause
+#[cfg(unix)]
+async fn paused_json(
+    args: &[&str],
+    resume: libc::c_int,
+) -> (serde_json::Value, String, Vec<std::time::Duration>) {
+    let arrivals = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let app = Router::new().route(
+        "/",
+        get({
+            let arrivals = arrivals.clone();
+            move || async move {
+                arrivals.lock().unwrap().push(std::time::Instant::now());
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                "Hello World"
+            }
+        }),
+    );
+    let port = serve(app).await;
+
+    let child = std::process::Command::new(assert_cmd::cargo::cargo_bin("oha"))
+        .args(["-c", "2", "-z", "3s", "--no-tui", "--json"])
+        .args(args)
+        .arg(format!("http://127.0.0.1:{port}/"))
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    let paused = std::time::Instant::now();
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGUSR1);
+    }
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, resume);
+    }
+    let output = tokio::task::spawn_blocking(move || child.wait_with_output().unwrap())
+        .await
+        .unwrap();
+
+    let arrivals = arrivals.lock().unwrap();
+    (
+        serde_json::from_slice(&output.stdout).unwrap(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+        arrivals
+            .iter()
+            .filter(|t| **t > paused)
+            .map(|t| *t - paused)
+            .collect(),
+    )
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_pause_signal() {
+    for (args, resume) in [
+        (&[][..], libc::SIGUSR1),
+        (&["-q", "50"], libc::SIGUSR2),
+        (&["-q", "50", "--latency-correction"], libc::SIGUSR1),
+    ] {
+        let (json, stderr, arrivals) = paused_json(args, resume).await;
+        // Only the requests in flight when the signal came
+        let in_pause = arrivals
+            .iter()
+            .filter(|t| {
+                (std::time::Duration::from_millis(50)..std::time::Duration::from_millis(950))
+                    .contains(t)
+            })
+            .count();
+        assert_eq!(in_pause, 0, "{arrivals:?}");
+        assert!(
+            arrivals.iter().any(|t| t.as_secs_f64() > 1.1),
+            "{arrivals:?}"
+        );
+
+        assert!(stderr.contains(" Paused by SIGUSR1 at "), "{stderr}");
+        assert!(stderr.contains(" Resumed by SIGUSR"), "{stderr}");
+        let pauses = json["summary"]["pauses"].as_array().unwrap();
+        assert_eq!(pauses.len(), 1);
+        let paused = pauses[0]["end"].as_f64().unwrap() - pauses[0]["start"].as_f64().unwrap();
+        assert!((0.9..1.1).contains(&paused), "{paused}");
+        let load_time = json["summary"]["loadTime"].as_f64().unwrap();
+        assert!((1.8..2.2).contains(&load_time), "{load_time}");
+        if args.contains(&"-q") {
+            // The schedule moves by the pause instead of catching up
+            let count = json["statusCodeDistribution"]["200"].as_u64().unwrap();
+            assert!((80..=110).contains(&count), "{count}");
+        }
+    }
+}
+
+async fn drain_json(args: &[&str]) -> serde_json::Value {
+    let app = Router::new().route(
+        "/",
+        get(|| async {
+            tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+            "Hello World"
+        }),
+    );
+    run_json(app, &[&["-c", "2", "-z", "1s"], args].concat()).await
+}
+
+#[tokio::test]
+async fn test_wait_ongoing_requests_after_deadline() {
+    // The requests in flight at the deadline are cut
+    let json = drain_json(&[]).await;
+    assert_eq!(json["errorDistribution"]["aborted due to deadline"], 2);
+    assert!(json["summary"]["testDuration"].is_null());
+
+    for args in [&[][..], &["-q", "5"], &["-q", "5", "--latency-correction"]] {
+        let json = drain_json(&[args, &["--wait-ongoing-requests-after-deadline"]].concat()).await;
+        assert_eq!(json["errorDistribution"], serde_json::json!({}), "{args:?}");
+        let count = json["statusCodeDistribution"]["200"].as_u64().unwrap();
+        if args.is_empty() {
+            // 0.0, 0.4 and 0.8 secs on each connection
+            assert_eq!(count, 6);
+        } else {
+            assert!((4..=6).contains(&count), "{count}");
+        }
+        let summary = &json["summary"];
+        let test_duration = summary["testDuration"].as_f64().unwrap();
+        assert!((0.99..1.1).contains(&test_duration), "{test_duration}");
+        let drain = summary["drain"].as_f64().unwrap();
+        assert!((0.1..0.4).contains(&drain), "{drain}");
+        let total = summary["total"].as_f64().unwrap();
+        assert!((test_duration + drain - total).abs() < 1e-6, "{total}");
+    }
+
+    let output = Command::cargo_bin("oha")
+        .unwrap()
+        .args(["--no-tui", "--wait-ongoing-requests-after-deadline"])
+        .arg("http://127.0.0.1:1/")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("--wait-ongoing-requests-after-deadline requires -z or --load-profile"));
+}
+
+/// A server which slows down when it gets more than `capacity` requests a second
+async fn target_p99_json(capacity: usize, args: &[&str]) -> serde_json::Value {
+    let arrivals = std::sync::Arc::new(std::sync::Mutex::new(std::collections::VecDeque::new()));
+    let app = Router::new().route(
+        "/",
+        get(move || async move {
+            let overloaded = {
+                let mut arrivals = arrivals.lock().unwrap();
+                let now = std::time::Instant::now();
+                arrivals.push_back(now);
+                while arrivals
+                    .front()
+                    .is_some_and(|t| now - *t > std::time::Duration::from_secs(1))
+                {
+                    arrivals.pop_front();
+                }
+                arrivals.len() > capacity
+            };
+            let latency = if overloaded { 200 } else { 5 };
+            tokio::time::sleep(std::time::Duration::from_millis(latency)).await;
+            "Hello World"
+        }),
+    );
+    run_json(app, &[&["--target-p99", "100ms"], args].concat()).await
+}
+
+#[tokio::test]
+async fn test_target_p99() {
+    let json = target_p99_json(36, &["-z", "10s", "-q", "30"]).await;
+    let target_p99 = &json["targetP99"];
+    assert_eq!(target_p99["target"], 0.1);
+    let windows = target_p99["windows"].as_array().unwrap();
+    assert!((4..=5).contains(&windows.len()), "{windows:?}");
+    assert_eq!(windows[0]["setpoint"], 30.0);
+    assert_eq!(windows[0]["action"], "increase");
+    // Up while the server keeps up, then down once it's overloaded
+    assert!(
+        windows.iter().any(|w| w["action"] == "decrease"),
+        "{windows:?}"
+    );
+    assert!(windows
+        .iter()
+        .all(|w| w["setpoint"].as_f64().unwrap() < 45.0));
+    let stable_rate = target_p99["stableRate"].as_f64().unwrap();
+    assert!((10.0..40.0).contains(&stable_rate), "{windows:?}");
+    let p99 = target_p99["stableLatencyPercentiles"]["p99"]
+        .as_f64()
+        .unwrap();
+    assert!(p99 < 0.1, "{p99}");
+    // The changing rate isn't a requested one
+    assert!(json["summary"]["requestedQps"].is_null());
+}
+
+#[tokio::test]
+async fn test_start_at() {
+    let arrivals = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let app = Router::new().route(
+        "/",
+        get({
+            let arrivals = arrivals.clone();
+            move || async move {
+                arrivals.lock().unwrap().push(std::time::SystemTime::now());
+                "Hello World"
+            }
+        }),
+    );
+    let port = serve(app).await;
+
+    let start_at = std::time::SystemTime::now() + std::time::Duration::from_secs(2);
+    let requested = humantime::format_rfc3339_millis(start_at).to_string();
+    let output = tokio::task::spawn_blocking({
+        let requested = requested.clone();
+        move || {
+            Command::cargo_bin("oha")
+                .unwrap()
+                .args(["-n", "10", "--no-tui", "--json", "--prewarm-connections"])
+                .args(["--start-at", &requested])
+                .arg(format!("http://127.0.0.1:{port}/"))
+                .output()
+                .unwrap()
+        }
+    })
+    .await
+    .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("Starting at {requested} in ")),
+        "{stderr}"
+    );
+
+    let first = *arrivals.lock().unwrap().iter().min().unwrap();
+    let start_at = humantime::parse_rfc3339(&requested).unwrap();
+    assert!(first >= start_at);
+    assert!(first.duration_since(start_at).unwrap() < std::time::Duration::from_secs(1));
+
+    let summary = &json["summary"];
+    assert!(summary["prewarm"].is_object());
+    assert!(summary["startAt"]["requested"]
+        .as_str()
+        .unwrap()
+        .starts_with(requested.trim_end_matches('Z')));
+    assert!(summary["startAt"]["actual"].is_string());
+    let late = summary["startAt"]["late"].as_f64().unwrap();
+    assert!((0.0..0.5).contains(&late), "{late}");
+    // The wait isn't a part of the run
+    assert!(summary["total"].as_f64().unwrap() < 1.0);
+
+    let output = Command::cargo_bin("oha")
+        .unwrap()
+        .args(["-n", "1", "--no-tui", "--start-at", "2020-01-01T00:00:00Z"])
+        .arg("http://127.0.0.1:1/")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("secs in the past"));
+}
+
+#[tokio::test]
+async fn test_workers() {
+    // The body of -D is read by the controller and sent to the agents
+    let app = Router::new().route(
+        "/",
+        any(|body: Bytes| async move {
+            if body == "from the controller" {
+                http::StatusCode::OK
+            } else {
+                http::StatusCode::BAD_REQUEST
+            }
+        }),
+    );
+    let port = serve(app).await;
+    let dir = tempfile::tempdir().unwrap();
+    let body = dir.path().join("body");
+    std::fs::write(&body, "from the controller").unwrap();
+
+    let mut agents = Vec::new();
+    let mut workers = Vec::new();
+    // The last one doesn't share the secret of the controller
+    for secret in ["s3cret", "s3cret", "other"] {
+        let (listener, agent_port) = bind_port().await;
+        drop(listener);
+        let child = std::process::Command::new(assert_cmd::cargo::cargo_bin("oha"))
+            .args(["agent", "--listen", &format!("127.0.0.1:{agent_port}")])
+            .env("OHA_AGENT_SECRET", secret)
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        agents.push(child);
+        let address = format!("127.0.0.1:{agent_port}");
+        while tokio::net::TcpStream::connect(&address).await.is_err() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        workers.push(address);
+    }
+    // Nothing listens on this one
+    let (listener, dead_port) = bind_port().await;
+    drop(listener);
+    let dead = format!("127.0.0.1:{dead_port}");
+    workers.push(dead.clone());
+
+    let output = tokio::task::spawn_blocking({
+        let workers = workers.join(",");
+        let body = body.clone();
+        move || {
+            Command::cargo_bin("oha")
+                .unwrap()
+                .args(["-n", "20", "--no-tui", "--json", "--workers", &workers])
+                .args(["--workers-secret", "s3cret", "-m", "POST", "-D"])
+                .arg(&body)
+                .arg(format!("http://127.0.0.1:{port}/"))
+                .output()
+                .unwrap()
+        }
+    })
+    .await
+    .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    // -n applies to each agent
+    assert_eq!(json["statusCodeDistribution"]["200"], 40);
+    assert!(json["statusCodeDistribution"]["400"].is_null());
+    let per_agent = json["perAgent"].as_object().unwrap();
+    assert_eq!(per_agent.len(), 4);
+    for worker in &workers[..2] {
+        assert_eq!(per_agent[worker]["status"], "done");
+        assert_eq!(per_agent[worker]["count"], 20);
+        assert_eq!(per_agent[worker]["statusCodeDistribution"]["200"], 20);
+        assert!(per_agent[worker]["latencyPercentiles"]["p50"].is_f64());
+    }
+    // The failed agents don't stop the others
+    assert_eq!(per_agent[&workers[2]]["status"], "failed");
+    assert!(per_agent[&workers[2]]["error"]
+        .as_str()
+        .unwrap()
+        .contains("isn't signed with the secret"));
+    assert_eq!(per_agent[&dead]["status"], "failed");
+    assert!(per_agent[&dead]["error"]
+        .as_str()
+        .unwrap()
+        .contains("failed to connect"));
+
+    // The options which read files on the agents aren't sent
+    let output = tokio::task::spawn_blocking({
+        let workers = workers[0].clone();
+        move || {
+            Command::cargo_bin("oha")
+                .unwrap()
+                .args(["-n", "1", "--no-tui", "--workers", &workers])
+                .args(["--workers-secret", "s3cret", "--raw-request"])
+                .arg(&body)
+                .arg(format!("http://127.0.0.1:{port}/"))
+                .output()
+                .unwrap()
+        }
+    })
+    .await
+    .unwrap();
+    for mut agent in agents {
+        agent.kill().unwrap();
+    }
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("--raw-request can't be used with --workers"));
+}
+
+#[tokio::test]
+async fn test_urls_from_file() {
+    let in_flight = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let peak = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let app = Router::new()
+        .route(
+            "/slow",
+            get({
+                let in_flight = in_flight.clone();
+                let peak = peak.clone();
+                move || async move {
+                    let n = in_flight.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                    peak.fetch_max(n, std::sync::atomic::Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                    in_flight.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                    "slow"
+                }
+            }),
+        )
+        .route("/fast", get(|| async { "fast" }));
+    let port = serve(app).await;
+
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let list = "# mostly slow\n/slow weight=9 max_concurrent=2\n/fast\n";
+    std::fs::write(file.path(), list).unwrap();
+    let path = file.path().to_owned();
+    let output = tokio::task::spawn_blocking(move || {
+        Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "40", "-c", "4", "--no-tui", "--json"])
+            .arg("--urls-from-file")
+            .arg(path)
+            .arg(format!("http://127.0.0.1:{port}/"))
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone()
+    })
+    .await
+    .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+    // Requests which pick the full /slow go to /fast instead of being dropped
+    assert_eq!(json["statusCodeDistribution"]["200"], 40);
+    assert!(peak.load(std::sync::atomic::Ordering::SeqCst) <= 2);
+    let urls = json["urlList"].as_array().unwrap();
+    assert_eq!(urls[0]["url"], "/slow");
+    assert_eq!(urls[0]["maxConcurrent"], 2);
+    assert!(urls[0]["peakConcurrency"].as_u64().unwrap() <= 2);
+    assert!(urls[0]["blocked"].as_u64().unwrap() > 0);
+    let average = urls[0]["averageConcurrency"].as_f64().unwrap();
+    assert!(average > 0.5 && average <= 2.0, "{average}");
+    assert_eq!(urls[1]["url"], "/fast");
+    assert!(urls[1]["maxConcurrent"].is_null());
+    assert_eq!(
+        urls[0]["requests"].as_u64().unwrap() + urls[1]["requests"].as_u64().unwrap(),
+        40
+    );
+    assert!(urls[1]["requests"].as_u64().unwrap() > 4);
+
+    // The URLs must be on the origin of the URL
+    std::fs::write(file.path(), format!("{list}http://example.com/\n")).unwrap();
+    let output = Command::cargo_bin("oha")
+        .unwrap()
+        .args(["-n", "1", "--no-tui", "--urls-from-file"])
+        .arg(file.path())
+        .arg(format!("http://127.0.0.1:{port}/"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("line 4"));
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_generator_usage() {
+    let output = runtime_threads_output(&["-z", "1500ms", "-q", "20"]).await;
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let generator = &json["summary"]["generator"];
+    let cpu = &generator["cpu"];
+    assert!(cpu["min"].as_f64().unwrap() <= cpu["max"].as_f64().unwrap());
+    assert!(generator["rss"]["mean"].as_f64().unwrap() > 0.0);
+    assert!(generator["openFiles"]["max"].as_f64().unwrap() >= 1.0);
+    assert_eq!(generator["cpuBound"], false);
+    assert_eq!(generator["nearOpenFilesLimit"], false);
+}
+
+#[tokio::test]
+async fn test_streamed_file_body() {
+    // Above the size which is loaded in memory
+    let payload: Vec<u8> = (0..2 * 1024 * 1024 + 7).map(|i| (i % 251) as u8).collect();
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), &payload).unwrap();
+    let path = file.path().to_str().unwrap();
+
+    for args in [
+        &["-D", path][..],
+        &["-D", path, "--http2"],
+        &["-D", path, "--informational-stats"],
+        &["-D", path, "--prebuilt-requests"],
+    ] {
+        let (header, body) = get_header_body(args).await;
+        assert_eq!(
+            header.get("content-length").unwrap().to_str().unwrap(),
+            payload.len().to_string()
+        );
+        assert!(header.get("transfer-encoding").is_none());
+        assert_eq!(body.as_ref(), payload.as_slice());
+    }
+
+    // A small file streamed from a lower threshold
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), "hello").unwrap();
+    let path = file.path().to_str().unwrap();
+    let (header, body) = get_header_body(&["-D", path, "--body-stream-threshold", "0"]).await;
+    assert_eq!(header.get("content-length").unwrap(), "5");
+    assert_eq!(body.as_ref(), b"hello");
+}
+
+#[cfg(all(feature = "uring", target_os = "linux"))]
+#[tokio::test]
+async fn test_io_backend_uring() {
+    for args in [
+        &["--io-backend", "uring", "-c", "3"][..],
+        &["--io-backend", "uring", "-c", "3", "--disable-keepalive"],
+        &["--io-backend", "uring", "-c", "1", "--runtime-threads", "2"],
+    ] {
+        let output = runtime_threads_output(args).await;
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json["statusCodeDistribution"]["200"], 20);
+    }
+
+    // A streamed body is sent by the ring too
+    let payload: Vec<u8> = (0..2 * 1024 * 1024 + 7).map(|i| (i % 251) as u8).collect();
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), &payload).unwrap();
+    let (_, body) =
+        get_header_body(&["--io-backend", "uring", "-D", file.path().to_str().unwrap()]).await;
+    assert_eq!(body.as_ref(), payload.as_slice());
+}
+
+#[test]
+fn test_io_backend_uring_unsupported() {
+    let unsupported = if cfg!(all(feature = "uring", target_os = "linux")) {
+        &[
+            &["https://example.com/"][..],
+            &["--http2", "http://127.0.0.1:1/"],
+            &["-q", "10", "http://127.0.0.1:1/"],
+        ][..]
+    } else {
+        &[&["http://127.0.0.1:1/"][..]][..]
+    };
+    for args in unsupported {
+        let output = Command::cargo_bin("oha")
+            .unwrap()
+            .args(["-n", "1", "--no-tui", "--io-backend", "uring"])
+            .args(*args)
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("--io-backend uring"), "{stderr}");
+    }
+}
+
+#[tokio::test]
+async fn test_duration_forms() {
+    // A bare number is seconds, with a note
+    let output = runtime_threads_output(&["-z", "1", "-c", "1"]).await;
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(json["summary"]["total"].as_f64().unwrap() >= 1.0);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("note: the duration 1 without a unit is read as 1s"));
+
+    for args in [
+        &["-z", "10sec"][..],
+        &["-t", "1h 30m"],
+        &["--think-time", "1.5s"],
+    ] {
+        let output = Command::cargo_bin("oha")
+            .unwrap()
+            .args(["--no-tui"])
+            .args(args)
+            .arg("http://127.0.0.1:1/")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains("expected a number with a unit of ms, s, m or h"),
+            "{stderr}"
+        );
+    }
+}
+
+/// Run oha with the open files limit set to `soft` and `hard`
+#[cfg(unix)]
+async fn nofile_limit_output(soft: u64, hard: u64, args: &[&str]) -> std::process::Output {
+    use std::os::unix::process::CommandExt;
+
+    let app = Router::new().route("/", get(|| async { "Hello World" }));
+    let port = serve(app).await;
+
+    let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+    tokio::task::spawn_blocking(move || {
+        let mut command = std::process::Command::new(assert_cmd::cargo::cargo_bin("oha"));
+        command
+            .args(["--no-tui", "--json"])
+            .args(&args)
+            .arg(format!("http://127.0.0.1:{port}/"));
+        unsafe {
+            command.pre_exec(move || {
+                let limit = libc::rlimit {
+                    rlim_cur: soft as libc::rlim_t,
+                    rlim_max: hard as libc::rlim_t,
+                };
+                if libc::setrlimit(libc::RLIMIT_NOFILE, &limit) == 0 {
+                    Ok(())
+                } else {
+                    Err(std::io::Error::last_os_error())
+                }
+            });
+        }
+        command.output().unwrap()
+    })
+    .await
+    .unwrap()
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_open_files_limit() {
+    // -c doesn't fit in the hard limit
+    let output = nofile_limit_output(100, 100, &["-n", "200", "-c", "200"]).await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(
+            "-c 200 needs about 264 open files, but the limit is 100. Raise it with ulimit -n 264, or lower -c"
+        ),
+        "{stderr}"
+    );
+
+    // The soft limit is raised to the hard one
+    let output = nofile_limit_output(100, 1000, &["-n", "200", "-c", "200"]).await;
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["summary"]["successRate"], 1.0);
+
+    // Unless --no-rlimit-bump
+    let output =
+        nofile_limit_output(100, 1000, &["-n", "200", "-c", "200", "--no-rlimit-bump"]).await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("but the limit is 100"), "{stderr}");
+
+    // A few connections fit
+    let output = nofile_limit_output(100, 100, &["-n", "20", "-c", "10"]).await;
+    assert!(output.status.success());
 }